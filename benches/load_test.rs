@@ -15,7 +15,9 @@ use operator::test_lib::*;
 use std::collections::HashMap;
 use std::env;
 use std::ffi::OsStr;
+use std::fs;
 use std::net::SocketAddr;
+use std::os::unix::fs::PermissionsExt;
 use std::process::{Child, Command, Stdio};
 use std::str;
 use std::thread;
@@ -23,6 +25,27 @@ use std::time;
 
 const CONCURRENT_REQUESTS_PER_ROUTE: u8 = 10;
 
+/// Settings for comparing memory-mapped static files against reading them.
+const MMAP_CONFIG: &str = "[content]\nmmap-max-bytes = 65536\n";
+
+/// How large the file and executable output streamed by the streaming
+/// benchmarks are.
+const STREAMED_BYTES: usize = 8 * 1024 * 1024;
+
+/// Chunk sizes to compare when streaming. The small executable chunk size is
+/// the buffer size that was hardcoded before it could be configured.
+const STREAMING_CHUNK_SIZES: [(&str, &[&str]); 3] = [
+    ("default", &[]),
+    (
+        "small",
+        &["--file-chunk-size=4k", "--executable-chunk-size=32"],
+    ),
+    (
+        "large",
+        &["--file-chunk-size=1m", "--executable-chunk-size=64k"],
+    ),
+];
+
 lazy_static! {
     static ref BENCHMARKED_SAMPLES: HashMap<&'static str, ContentDirectory> = [
         "empty",
@@ -44,16 +67,76 @@ fn benchmark_all_samples() {
         .configure_from_args();
     let mut runtime = actix_rt::System::new("load_test");
 
+    let config_directory = tempfile::tempdir().expect("Failed to create temporary directory");
+    let mmap_config_path = config_directory.path().join("mmap.toml");
+    fs::write(&mmap_config_path, MMAP_CONFIG).expect("Failed to write config file");
+    let mmap_config_arg = format!(
+        "--config={}",
+        mmap_config_path
+            .to_str()
+            .expect("Config file path was not UTF-8")
+    );
+
     for (sample_name, content_directory) in BENCHMARKED_SAMPLES.iter() {
-        let server = RunningServer::start(&content_directory).expect("Server failed to start");
-        criterion.bench_with_input(
-            BenchmarkId::new("load-test", sample_name),
-            &content_directory,
-            |bencher, content_directory| {
-                benchmark_load_test(bencher, &mut runtime, content_directory, &server)
-            },
-        );
+        for (benchmark_name, extra_args) in [
+            ("load-test", Vec::new()),
+            ("load-test-mmap", vec![mmap_config_arg.as_str()]),
+        ] {
+            let server = RunningServer::start(content_directory, &extra_args)
+                .expect("Server failed to start");
+            criterion.bench_with_input(
+                BenchmarkId::new(benchmark_name, sample_name),
+                &content_directory,
+                |bencher, content_directory| {
+                    benchmark_load_test(bencher, &mut runtime, content_directory, &server)
+                },
+            );
+        }
     }
+
+    benchmark_streaming(&mut criterion, &mut runtime);
+}
+
+/// Measures how quickly a large static file and a large amount of executable
+/// output can be streamed with different chunk sizes.
+fn benchmark_streaming(criterion: &mut Criterion, runtime: &mut SystemRunner) {
+    let root = tempfile::tempdir().expect("Failed to create temporary directory");
+    fs::write(root.path().join("large.bin"), vec![0; STREAMED_BYTES])
+        .expect("Failed to write content file");
+    let executable_path = root.path().join("large-output.bin.sh");
+    fs::write(
+        &executable_path,
+        format!("#!/bin/sh\nhead -c {} /dev/zero\n", STREAMED_BYTES),
+    )
+    .expect("Failed to write content file");
+    fs::set_permissions(&executable_path, fs::Permissions::from_mode(0o755))
+        .expect("Failed to make content file executable");
+    let content_directory =
+        ContentDirectory::from_root(&root.path()).expect("Failed to create content directory");
+
+    for (chunk_sizes_name, extra_args) in STREAMING_CHUNK_SIZES {
+        let server =
+            RunningServer::start(&content_directory, extra_args).expect("Server failed to start");
+        for route in ["/large", "/large-output"] {
+            let route = route.parse::<Route>().expect("Route was invalid");
+            criterion.bench_with_input(
+                BenchmarkId::new(format!("streaming{}", route), chunk_sizes_name),
+                &route,
+                |bencher, route| {
+                    bencher.iter(|| {
+                        runtime.block_on(stream_via_http_request(*server.address(), route.clone()))
+                    })
+                },
+            );
+        }
+    }
+}
+
+async fn stream_via_http_request(server_address: SocketAddr, route: Route) {
+    let (status, body) =
+        render_via_http_request(&server_address, &route, "application/octet-stream").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body.expect("Payload error").len(), STREAMED_BYTES);
 }
 
 fn benchmark_load_test(
@@ -83,7 +166,7 @@ async fn load_test(content_directory: &'static ContentDirectory, server_address:
                 render_via_http_request(
                     &server_address,
                     &content_file.route,
-                    &target_media_type.to_string(),
+                    target_media_type.as_ref(),
                 )
                 .map(|result| result.1.expect("Payload error"))
                 .await
@@ -126,10 +209,10 @@ struct RunningServer {
 }
 
 impl RunningServer {
-    fn start(content_directory: &ContentDirectory) -> Result<Self, String> {
+    fn start(content_directory: &ContentDirectory, extra_args: &[&str]) -> Result<Self, String> {
         let address = unused_addr();
 
-        let mut command = operator_command(&[
+        let mut command = operator_command([
             "serve",
             "--quiet",
             &format!(
@@ -142,6 +225,7 @@ impl RunningServer {
             &format!("--bind-to={}", address),
         ]);
         command
+            .args(extra_args)
             .stdin(Stdio::null())
             .stdout(Stdio::null())
             .stderr(Stdio::inherit());
@@ -10,7 +10,8 @@ render_data_without_socket_address=$(
 if [ "${render_data_without_socket_address#*'"is-operator-snapshot-test":"true"'}" != "$render_data_without_socket_address" ]
 then
     echo "$render_data_without_socket_address" \
-        | sed -E 's/"request-headers":\{[^}]*\}/"request-headers":\{\}/'
+        | sed -E 's/"request-headers":\{[^}]*\}/"request-headers":\{\}/' \
+        | sed -E -e ':mask' -e 's/("http":\{[^}]*":)"[^"]*"/\1null/' -e 't mask'
 else
     echo "$render_data_without_socket_address"
 fi
\ No newline at end of file
@@ -0,0 +1,169 @@
+//! Limits on how many requests to part of the site can be handled at once, so
+//! that slow content in one place cannot starve everything else.
+
+use crate::content::Route;
+use actix_rt::time;
+use futures::channel::oneshot;
+use futures::future::{self, Either};
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+/// The bulkheads for every concurrency-limited route, created the first time
+/// each is needed. Clones share the same bulkheads.
+#[derive(Clone, Default)]
+pub(crate) struct Bulkheads(Arc<Mutex<HashMap<Route, Arc<Bulkhead>>>>);
+
+impl Bulkheads {
+    /// The bulkhead for requests within `route`, which allows `limit`
+    /// requests at once.
+    pub fn get(&self, route: &Route, limit: usize) -> Arc<Bulkhead> {
+        let mut bulkheads = self
+            .0
+            .lock()
+            .expect("Mutex for bulkheads has been poisoned");
+        bulkheads
+            .entry(route.clone())
+            .or_insert_with(|| Arc::new(Bulkhead::new(limit)))
+            .clone()
+    }
+}
+
+pub(crate) struct Bulkhead {
+    limit: usize,
+    state: Mutex<BulkheadState>,
+}
+
+#[derive(Default)]
+struct BulkheadState {
+    active: usize,
+    /// Requests waiting for a slot, in the order they arrived. Slots are
+    /// handed over as permits, so a slot that is handed to a request which
+    /// is no longer waiting is given up again when its permit is dropped.
+    waiting: VecDeque<oneshot::Sender<BulkheadPermit>>,
+}
+
+impl Bulkhead {
+    fn new(limit: usize) -> Self {
+        Bulkhead {
+            limit,
+            state: Mutex::new(BulkheadState::default()),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, BulkheadState> {
+        self.state
+            .lock()
+            .expect("Mutex for bulkhead has been poisoned")
+    }
+
+    /// Waits for up to `queue_timeout` for a slot. The slot is held until the
+    /// returned permit is dropped. `None` means that the bulkhead stayed full.
+    pub async fn enter(self: Arc<Self>, queue_timeout: Duration) -> Option<BulkheadPermit> {
+        let slot = {
+            let mut state = self.lock();
+            if state.active < self.limit {
+                state.active += 1;
+                None
+            } else {
+                let (sender, receiver) = oneshot::channel();
+                state.waiting.push_back(sender);
+                Some(receiver)
+            }
+        };
+        let mut slot = match slot {
+            None => return Some(BulkheadPermit(self)),
+            Some(slot) => slot,
+        };
+
+        match future::select(&mut slot, Box::pin(time::delay_for(queue_timeout))).await {
+            Either::Left((Ok(permit), _)) => Some(permit),
+            Either::Left((Err(_), _)) => None,
+            Either::Right(_) => {
+                // A slot may have been handed over just as time ran out.
+                slot.close();
+                slot.try_recv().ok().flatten()
+            }
+        }
+    }
+
+    /// Hands a slot over to the next waiting request, or frees it if there
+    /// are none.
+    fn leave(self: &Arc<Self>) {
+        let waiting = {
+            let mut state = self.lock();
+            loop {
+                match state.waiting.pop_front() {
+                    // Requests which gave up waiting can't take the slot.
+                    Some(waiting) if waiting.is_canceled() => {}
+                    Some(waiting) => break waiting,
+                    None => {
+                        state.active -= 1;
+                        return;
+                    }
+                }
+            }
+        };
+        // If the request gives up before it gets the permit, dropping the
+        // permit calls this again.
+        let _ = waiting.send(BulkheadPermit(self.clone()));
+    }
+}
+
+/// A slot in a [`Bulkhead`], which is given up when this is dropped.
+pub(crate) struct BulkheadPermit(Arc<Bulkhead>);
+
+impl Drop for BulkheadPermit {
+    fn drop(&mut self) {
+        self.0.leave();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_rt::System;
+
+    #[test]
+    fn requests_over_the_limit_wait_for_a_slot() {
+        System::new("test").block_on(async {
+            let bulkhead = Bulkheads::default().get(&"/slow".parse().unwrap(), 1);
+            let timeout = Duration::from_millis(50);
+
+            let first = bulkhead.clone().enter(timeout).await;
+            assert!(first.is_some());
+            assert!(bulkhead.clone().enter(timeout).await.is_none());
+
+            let second = bulkhead.clone().enter(Duration::from_secs(10));
+            let release = async {
+                time::delay_for(Duration::from_millis(10)).await;
+                drop(first);
+            };
+            let (second, ()) = future::join(second, release).await;
+            assert!(second.is_some());
+            drop(second);
+
+            assert_eq!(bulkhead.lock().active, 0);
+        });
+    }
+
+    #[test]
+    fn slots_handed_to_requests_which_stop_waiting_are_freed() {
+        System::new("test").block_on(async {
+            let bulkhead = Bulkheads::default().get(&"/slow".parse().unwrap(), 1);
+
+            let first = bulkhead.clone().enter(Duration::ZERO).await;
+            assert!(first.is_some());
+            let mut second = Box::pin(bulkhead.clone().enter(Duration::from_secs(10)));
+            assert!(futures::poll!(&mut second).is_pending());
+
+            // The slot is handed over, but the second request is dropped
+            // before it notices.
+            drop(first);
+            drop(second);
+
+            assert_eq!(bulkhead.lock().active, 0);
+            assert!(bulkhead.clone().enter(Duration::ZERO).await.is_some());
+        });
+    }
+}
@@ -1,11 +1,25 @@
+use crate::config::Config;
 use crate::content::*;
 use crate::http::QueryString;
+use crate::render::{self, RenderOutcome, RenderRequest, Rendered};
 use crate::*;
+use actix_rt::System;
+use actix_web::client::Client as HttpClient;
 use futures::executor;
+use futures::future;
 use futures::stream::TryStreamExt;
+use globset::Glob;
+use mime_guess::MimeGuess;
+use serde::Serialize;
+use std::cell::Cell;
 use std::collections::HashMap;
+use std::fmt;
+use std::fs;
 use std::io;
-use std::net::ToSocketAddrs;
+use std::net::{SocketAddr, TcpListener, ToSocketAddrs};
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -22,7 +36,7 @@ pub enum RenderCommandError {
     #[error("Unable to load content.")]
     ContentLoadingError {
         #[from]
-        source: ContentLoadingError,
+        source: ContentLoadingErrors,
     },
 
     #[error("Unable to parse template from input.")]
@@ -58,12 +72,15 @@ pub enum GetCommandError {
     #[error("Unable to load content.")]
     ContentLoadingError {
         #[from]
-        source: ContentLoadingError,
+        source: ContentLoadingErrors,
     },
 
     #[error("Content not found at route '{}'.", .route)]
     ContentNotFound { route: Route },
 
+    #[error("Route '{}' redirects to '{}'.", .route, .location)]
+    Redirect { route: Route, location: String },
+
     #[error("Unable to render content.")]
     RenderError {
         #[from]
@@ -76,8 +93,17 @@ pub enum GetCommandError {
         source: StreamError,
     },
 
+    #[error("Content at route '{}' was rendered with error status {}.", .route, .status)]
+    ErrorStatus { route: Route, status: u16 },
+
     #[error("Failed to write output.")]
     WriteError { source: io::Error },
+
+    #[error("Unable to get content at route '{}'.", .route)]
+    RouteError {
+        route: Route,
+        source: Box<GetCommandError>,
+    },
 }
 
 #[derive(Error, Debug)]
@@ -91,7 +117,7 @@ pub enum ServeCommandError {
     #[error("Unable to load content.")]
     ContentLoadingError {
         #[from]
-        source: ContentLoadingError,
+        source: ContentLoadingErrors,
     },
 
     #[error("Index route does not exist.")]
@@ -100,20 +126,333 @@ pub enum ServeCommandError {
     #[error("Error handler route does not exist.")]
     ErrorHandlerRouteMissing,
 
+    #[error("Unable to prerender content at route '{}'.", .route)]
+    PrerenderError {
+        route: Route,
+        source: GetCommandError,
+    },
+
+    #[error("Task route '{}' does not exist.", .route)]
+    TaskRouteMissing { route: Route },
+
+    #[error("Failed to bind server.")]
+    BindError { source: io::Error },
+
+    #[error("Failed to run server.")]
+    ServerError { source: io::Error },
+}
+
+#[cfg(feature = "lambda")]
+#[derive(Error, Debug)]
+pub enum LambdaCommandError {
+    #[error("Unable to collect server info.")]
+    ServerInfoError {
+        #[from]
+        source: ServerInfoError,
+    },
+
+    #[error("Unable to load content.")]
+    ContentLoadingError {
+        #[from]
+        source: ContentLoadingErrors,
+    },
+
+    #[error("Index route does not exist.")]
+    IndexRouteMissing,
+
+    #[error("AWS_LAMBDA_RUNTIME_API is not set. Is this running in AWS Lambda?")]
+    RuntimeApiMissing,
+
+    #[error("Failed to handle Lambda invocations.")]
+    LambdaError {
+        #[from]
+        source: lambda::LambdaError,
+    },
+}
+
+#[derive(Error, Debug)]
+pub enum BenchCommandError {
+    #[error("Unable to collect server info.")]
+    ServerInfoError {
+        #[from]
+        source: ServerInfoError,
+    },
+
+    #[error("Unable to load content.")]
+    ContentLoadingError {
+        #[from]
+        source: ContentLoadingErrors,
+    },
+
+    #[error("Content not found at route '{}'.", .route)]
+    ContentNotFound { route: Route },
+
     #[error("Failed to run server.")]
     ServerError { source: io::Error },
+
+    #[error("Failed to write output.")]
+    WriteError { source: io::Error },
+}
+
+#[derive(Error, Debug)]
+pub enum RenderAllCommandError {
+    #[error("Unable to collect server info.")]
+    ServerInfoError {
+        #[from]
+        source: ServerInfoError,
+    },
+
+    #[error("Unable to load content.")]
+    ContentLoadingError {
+        #[from]
+        source: ContentLoadingErrors,
+    },
+
+    #[error("Unable to render content at route '{}' as {}.", .route, .media_type)]
+    RenderError {
+        route: Route,
+        media_type: MediaType,
+        source: Box<GetCommandError>,
+    },
+
+    #[error("Failed to write output.")]
+    WriteError { source: io::Error },
+}
+
+/// How `render-all` writes its output.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum RenderAllFormat {
+    /// A sequence of records, each made of the route, the media type, and the
+    /// length of the rendered content in bytes (all terminated by NUL
+    /// characters) followed by the content itself.
+    #[default]
+    Nul,
+
+    /// A tar archive with one file per rendered representation, named after
+    /// the source content file (e.g. `about.html`).
+    Tar,
+}
+
+#[derive(Error, Debug)]
+pub enum RoutesCommandError {
+    #[error("Unable to collect server info.")]
+    ServerInfoError {
+        #[from]
+        source: ServerInfoError,
+    },
+
+    #[error("Unable to load content.")]
+    ContentLoadingError {
+        #[from]
+        source: ContentLoadingErrors,
+    },
+
+    #[error("Failed to write output.")]
+    WriteError { source: io::Error },
+}
+
+#[derive(Error, Debug)]
+pub enum CheckCommandError {
+    #[error("Unable to collect server info.")]
+    ServerInfoError {
+        #[from]
+        source: ServerInfoError,
+    },
+
+    #[error(
+        "Found {} problem{} in the content directory.",
+        .problem_count,
+        if *.problem_count == 1 { "" } else { "s" },
+    )]
+    InvalidContent {
+        problem_count: usize,
+        problems: Vec<ContentLoadingErrors>,
+    },
+
+    #[error("Failed to write output.")]
+    WriteError { source: io::Error },
+}
+
+/// How errors are written to stderr.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum ErrorFormat {
+    /// Log messages meant for people.
+    #[default]
+    Text,
+
+    /// One JSON object per line for each problem (see [`ErrorReport`]), meant
+    /// for editors and CI systems.
+    Json,
+}
+
+/// A machine-readable description of a single problem.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct ErrorReport {
+    /// What sort of problem this is: `config`, `content-directory`,
+    /// `content-loading`, `template`, `not-found`, `render`, `stream`, `io`,
+    /// or `other`.
+    pub kind: &'static str,
+
+    /// The route that the problem occurred at, if it is tied to one.
+    pub route: Option<Route>,
+
+    /// The content file that caused the problem, relative to the content
+    /// directory.
+    pub file: Option<String>,
+
+    pub message: String,
+
+    /// Messages from the underlying causes of the problem, from outermost to
+    /// innermost.
+    pub sources: Vec<String>,
+}
+
+impl ErrorReport {
+    /// Describes `error`. Content loading errors are split up so that each
+    /// problem in the content directory gets its own report.
+    pub fn all_from(error: &(dyn std::error::Error + 'static)) -> Vec<Self> {
+        for cause in error_chain(error) {
+            if let Some(errors) = cause.downcast_ref::<ContentLoadingErrors>() {
+                return Self::from_content_loading_errors(errors).collect();
+            }
+            if let Some(CheckCommandError::InvalidContent { problems, .. }) = cause.downcast_ref() {
+                return problems
+                    .iter()
+                    .flat_map(Self::from_content_loading_errors)
+                    .collect();
+            }
+        }
+
+        let mut messages = error_chain(error).map(ToString::to_string);
+        vec![ErrorReport {
+            kind: error_chain(error).find_map(kind_of).unwrap_or("other"),
+            route: error_chain(error).find_map(route_of),
+            file: error_chain(error).find_map(file_of),
+            message: messages.next().unwrap_or_default(),
+            sources: messages.collect(),
+        }]
+    }
+
+    fn from_content_loading_errors(
+        errors: &ContentLoadingErrors,
+    ) -> impl Iterator<Item = Self> + '_ {
+        errors.iter().map(|(location, error)| {
+            // Problems found by running content are paired with its route
+            // rather than a file path.
+            let (route, file) = match location.parse::<Route>() {
+                Ok(route) if location.starts_with('/') => (Some(route), None),
+                _ => (None, Some(location.clone())),
+            };
+            ErrorReport {
+                kind: "content-loading",
+                route,
+                file,
+                message: error.to_string(),
+                sources: error_chain(error)
+                    .skip(1)
+                    .map(ToString::to_string)
+                    .collect(),
+            }
+        })
+    }
+}
+
+fn error_chain<'a>(
+    error: &'a (dyn std::error::Error + 'static),
+) -> impl Iterator<Item = &'a (dyn std::error::Error + 'static)> {
+    std::iter::successors(Some(error), |error| error.source())
+}
+
+fn kind_of(error: &(dyn std::error::Error + 'static)) -> Option<&'static str> {
+    if error.is::<config::ConfigError>() {
+        Some("config")
+    } else if error.is::<ContentDirectoryFromRootError>() {
+        Some("content-directory")
+    } else if error.is::<TemplateError>() {
+        Some("template")
+    } else if error.is::<RenderError>()
+        || matches!(
+            error.downcast_ref(),
+            Some(GetCommandError::ErrorStatus { .. })
+        )
+    {
+        Some("render")
+    } else if error.is::<StreamError>() {
+        Some("stream")
+    } else if error.is::<io::Error>() {
+        Some("io")
+    } else if matches!(
+        error.downcast_ref(),
+        Some(GetCommandError::ContentNotFound { .. })
+    ) || matches!(
+        error.downcast_ref(),
+        Some(BenchCommandError::ContentNotFound { .. })
+    ) || matches!(
+        error.downcast_ref(),
+        Some(
+            ServeCommandError::IndexRouteMissing
+                | ServeCommandError::ErrorHandlerRouteMissing
+                | ServeCommandError::TaskRouteMissing { .. }
+        )
+    ) {
+        Some("not-found")
+    } else {
+        None
+    }
+}
+
+fn route_of(error: &(dyn std::error::Error + 'static)) -> Option<Route> {
+    if let Some(
+        GetCommandError::ContentNotFound { route }
+        | GetCommandError::Redirect { route, .. }
+        | GetCommandError::ErrorStatus { route, .. },
+    ) = error.downcast_ref()
+    {
+        Some(route.clone())
+    } else if let Some(BenchCommandError::ContentNotFound { route }) = error.downcast_ref() {
+        Some(route.clone())
+    } else if let Some(GetCommandError::RouteError { route, .. }) = error.downcast_ref() {
+        Some(route.clone())
+    } else if let Some(RenderAllCommandError::RenderError { route, .. }) = error.downcast_ref() {
+        Some(route.clone())
+    } else if let Some(
+        ServeCommandError::PrerenderError { route, .. }
+        | ServeCommandError::TaskRouteMissing { route },
+    ) = error.downcast_ref()
+    {
+        Some(route.clone())
+    } else {
+        None
+    }
+}
+
+fn file_of(error: &(dyn std::error::Error + 'static)) -> Option<String> {
+    error
+        .downcast_ref::<TemplateError>()
+        .and_then(TemplateError::name)
+        .map(String::from)
 }
 
 /// Reads a template from `input`, renders it, and writes it to `output`.
+///
+/// The template is rendered as the media type given by `accept` if it is a
+/// specific one, and as `application/octet-stream` otherwise. Each entry in
+/// `data` is added to the `data` in the render context as a string.
+#[allow(clippy::too_many_arguments)]
 pub fn eval<I: io::Read, O: io::Write>(
     content_directory: ContentDirectory,
     query_string: Option<QueryString>,
+    request_headers: HashMap<String, String>,
+    accept: Option<MediaRange>,
+    data: Vec<(String, String)>,
+    config: Config,
     input: &mut I,
     output: &mut O,
 ) -> Result<(), RenderCommandError> {
-    let shared_content_engine = FilesystemBasedContentEngine::from_content_directory(
+    let shared_content_engine = FilesystemBasedContentEngine::from_content_directory_with_config(
         content_directory,
-        ServerInfo::without_socket_address()?,
+        ServerInfo::without_socket_address()?.for_config(&config),
+        config,
     )?;
     let content_engine = shared_content_engine
         .read()
@@ -124,17 +463,21 @@ pub fn eval<I: io::Read, O: io::Write>(
         .read_to_string(&mut template)
         .map_err(|source| RenderCommandError::ReadError { source })?;
 
-    let content_item =
-        content_engine.new_template(&template, MediaType::APPLICATION_OCTET_STREAM)?;
+    let accept = accept.unwrap_or(mime::STAR_STAR);
+    let media_type =
+        MediaType::from_media_range(accept.clone()).unwrap_or(MediaType::APPLICATION_OCTET_STREAM);
+    let content_item = content_engine.new_template(&template, media_type)?;
 
     let query_parameters = query_string.unwrap_or_default();
 
-    // Request headers cannot be specified on the CLI (yet).
-    let request_headers = HashMap::new();
-
-    let render_context =
-        content_engine.render_context(None, query_parameters.into(), request_headers);
-    let media = content_item.render(render_context, &[mime::STAR_STAR])?;
+    let render_context = content_engine
+        .render_context(None, query_parameters.into(), request_headers)
+        .with_data(
+            data.into_iter()
+                .map(|(key, value)| (key, serde_json::Value::String(value)))
+                .collect(),
+        );
+    let media = content_item.render(render_context, &[accept])?;
 
     executor::block_on(media.content.try_for_each(|bytes| {
         let result = output.write_all(&bytes).map_err(StreamError::from);
@@ -147,91 +490,890 @@ pub fn eval<I: io::Read, O: io::Write>(
 }
 
 /// Renders an item from the content directory and writes it to `output`.
+///
+/// Content is rendered just like the server would render it, including any
+/// fallbacks. If the server would respond with an error status (from the
+/// route's settings or the content's front matter) the output is still
+/// written, but this returns an error.
 pub fn get<O: io::Write>(
     content_directory: ContentDirectory,
     route: &Route,
     query_string: Option<QueryString>,
+    request_headers: HashMap<String, String>,
     accept: Option<MediaRange>,
+    config: Config,
     output: &mut O,
 ) -> Result<(), GetCommandError> {
-    let shared_content_engine = FilesystemBasedContentEngine::from_content_directory(
+    let shared_content_engine = FilesystemBasedContentEngine::from_content_directory_with_config(
         content_directory,
-        ServerInfo::without_socket_address()?,
+        ServerInfo::without_socket_address()?.for_config(&config),
+        config,
     )?;
     let content_engine = shared_content_engine
         .read()
         .expect("RwLock for ContentEngine has been poisoned");
 
-    let content_item =
-        content_engine
-            .get(route)
-            .ok_or_else(|| GetCommandError::ContentNotFound {
-                route: route.clone(),
-            })?;
+    let (_, status) = render_route(
+        &*content_engine,
+        route,
+        query_string,
+        request_headers,
+        accept,
+        output,
+    )?;
 
-    let query_parameters = query_string.unwrap_or_default();
+    output
+        .flush()
+        .map_err(|source| GetCommandError::WriteError { source })?;
+
+    if status >= 400 {
+        Err(GetCommandError::ErrorStatus {
+            route: route.clone(),
+            status,
+        })
+    } else {
+        Ok(())
+    }
+}
+
+/// Renders content at each of the `routes` and writes it to a file in
+/// `output_directory` named after the route, with the filename extension of
+/// the media type it was rendered as (e.g. `/blog/post` rendered from
+/// `blog/post.html.hbs` is written to `blog/post.html`).
+pub fn get_to_directory(
+    content_directory: ContentDirectory,
+    routes: &[Route],
+    query_string: Option<QueryString>,
+    request_headers: HashMap<String, String>,
+    accept: Option<MediaRange>,
+    config: Config,
+    output_directory: &Path,
+) -> Result<(), GetCommandError> {
+    let shared_content_engine = FilesystemBasedContentEngine::from_content_directory_with_config(
+        content_directory,
+        ServerInfo::without_socket_address()?.for_config(&config),
+        config,
+    )?;
+    let content_engine = shared_content_engine
+        .read()
+        .expect("RwLock for ContentEngine has been poisoned");
 
-    // Request headers cannot be specified on the CLI (yet).
-    let request_headers = HashMap::new();
+    for route in routes {
+        get_route_to_directory(
+            &*content_engine,
+            route,
+            query_string.clone(),
+            request_headers.clone(),
+            accept.clone(),
+            output_directory,
+        )
+        .map_err(|source| GetCommandError::RouteError {
+            route: route.clone(),
+            source: Box::new(source),
+        })?;
+    }
+    Ok(())
+}
 
-    let render_context = content_engine.render_context(
-        Some(route.clone()),
-        query_parameters.into(),
+fn get_route_to_directory<Engine>(
+    content_engine: &Engine,
+    route: &Route,
+    query_string: Option<QueryString>,
+    request_headers: HashMap<String, String>,
+    accept: Option<MediaRange>,
+    output_directory: &Path,
+) -> Result<(), GetCommandError>
+where
+    Engine: ContentEngine<ServerInfo> + InternalContentEngine,
+{
+    let mut rendered = Vec::new();
+    let (media_type, _) = render_route(
+        content_engine,
+        route,
+        query_string,
         request_headers,
-    );
-    let media = content_item.render(render_context, &[accept.unwrap_or(mime::STAR_STAR)])?;
+        accept,
+        &mut rendered,
+    )?;
 
-    executor::block_on(media.content.try_for_each(|bytes| {
-        let result = output.write_all(&bytes).map_err(StreamError::from);
-        async { result }
-    }))?;
+    let extension = content_engine
+        .url_extension(route, &media_type)
+        .map(String::from)
+        .or_else(|| {
+            mime_guess::get_mime_extensions(&media_type.clone().into_media_range())
+                .and_then(|extensions| extensions.first())
+                .map(|extension| String::from(*extension))
+        });
+    let name = match route.as_ref().trim_start_matches('/') {
+        "" => "index",
+        name => name,
+    };
+    let path = match extension {
+        Some(extension) => output_directory.join(format!("{}.{}", name, extension)),
+        None => output_directory.join(name),
+    };
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|source| GetCommandError::WriteError { source })?;
+    }
+    fs::write(&path, rendered).map_err(|source| GetCommandError::WriteError { source })
+}
+
+/// Renders content at `route` to `output` the same way the server would,
+/// returning the media type that it was rendered as and the status that the
+/// server would respond with.
+fn render_route<Engine, O>(
+    content_engine: &Engine,
+    route: &Route,
+    query_string: Option<QueryString>,
+    request_headers: HashMap<String, String>,
+    accept: Option<MediaRange>,
+    output: &mut O,
+) -> Result<(MediaType, u16), GetCommandError>
+where
+    Engine: ContentEngine<ServerInfo> + InternalContentEngine,
+    O: io::Write,
+{
+    let request = RenderRequest::new(route.clone(), vec![accept.unwrap_or(mime::STAR_STAR)])
+        .with_query_parameters(query_string.unwrap_or_default().into())
+        .with_request_headers(request_headers);
+
+    match render::render_route(content_engine, content_engine.config(), &request) {
+        RenderOutcome::Redirect(redirect) => Err(GetCommandError::Redirect {
+            route: route.clone(),
+            location: String::from(redirect.location()),
+        }),
+        RenderOutcome::NotFound => Err(GetCommandError::ContentNotFound {
+            route: route.clone(),
+        }),
+        RenderOutcome::NotAcceptable(error) | RenderOutcome::Failed(error) => Err(error.into()),
+        RenderOutcome::Rendered(Rendered {
+            response: Response { media, status, .. },
+            ..
+        }) => {
+            executor::block_on(media.content.try_for_each(|bytes| {
+                let result = output.write_all(&bytes).map_err(StreamError::from);
+                async { result }
+            }))?;
+            Ok((media.media_type, status))
+        }
+    }
+}
+
+/// Renders every item in the content directory (or those whose routes match
+/// `route_pattern` and whose media types are within `accept`) and writes them
+/// all to `output` in the given format.
+pub fn render_all<O: io::Write>(
+    content_directory: ContentDirectory,
+    route_pattern: Option<Glob>,
+    accept: Option<MediaRange>,
+    format: RenderAllFormat,
+    config: Config,
+    output: &mut O,
+) -> Result<(), RenderAllCommandError> {
+    let route_matcher = route_pattern.map(|pattern| pattern.compile_matcher());
+    let accept = accept.unwrap_or(mime::STAR_STAR);
 
+    // Each content file is rendered as the media type of its first extension,
+    // so that alternative representations are all included.
+    let mut targets = (&content_directory)
+        .into_iter()
+        .filter(|content_file| {
+            route_matcher
+                .as_ref()
+                .is_none_or(|matcher| matcher.is_match(content_file.route.as_ref()))
+        })
+        .filter_map(|content_file| {
+            let extension = content_file.extensions.first()?;
+            let media_type = MimeGuess::from_ext(extension)
+                .first()
+                .and_then(MediaType::from_media_range)?;
+            if media_type.is_within_media_range(&accept) {
+                Some((content_file.route.clone(), extension.clone(), media_type))
+            } else {
+                None
+            }
+        })
+        .collect::<Vec<_>>();
+    targets.sort_by(|(a, ..), (b, ..)| a.cmp(b));
+
+    let shared_content_engine = FilesystemBasedContentEngine::from_content_directory_with_config(
+        content_directory,
+        ServerInfo::without_socket_address()?.for_config(&config),
+        config,
+    )?;
+    let content_engine = shared_content_engine
+        .read()
+        .expect("RwLock for ContentEngine has been poisoned");
+
+    let mut writer = match format {
+        RenderAllFormat::Nul => RenderAllWriter::Nul(output),
+        RenderAllFormat::Tar => RenderAllWriter::Tar(tar::Builder::new(output)),
+    };
+    for (route, extension, media_type) in targets {
+        if content_engine.get(&route).is_none() {
+            // Internal content cannot be rendered on its own, and drafts are
+            // left out.
+            continue;
+        }
+
+        let mut rendered = Vec::new();
+        render_route(
+            &*content_engine,
+            &route,
+            None,
+            HashMap::new(),
+            Some(media_type.clone().into_media_range()),
+            &mut rendered,
+        )
+        .map_err(|source| RenderAllCommandError::RenderError {
+            route: route.clone(),
+            media_type: media_type.clone(),
+            source: Box::new(source),
+        })?;
+
+        writer
+            .write(&route, &extension, &media_type, &rendered)
+            .map_err(|source| RenderAllCommandError::WriteError { source })?;
+    }
+    writer
+        .finish()
+        .map_err(|source| RenderAllCommandError::WriteError { source })
+}
+
+enum RenderAllWriter<'o, O: io::Write> {
+    Nul(&'o mut O),
+    Tar(tar::Builder<&'o mut O>),
+}
+impl<O: io::Write> RenderAllWriter<'_, O> {
+    fn write(
+        &mut self,
+        route: &Route,
+        extension: &str,
+        media_type: &MediaType,
+        rendered: &[u8],
+    ) -> Result<(), io::Error> {
+        match self {
+            RenderAllWriter::Nul(output) => {
+                write!(output, "{}\0{}\0{}\0", route, media_type, rendered.len())?;
+                output.write_all(rendered)
+            }
+            RenderAllWriter::Tar(archive) => {
+                let mut header = tar::Header::new_gnu();
+                header.set_size(rendered.len() as u64);
+                header.set_mode(0o644);
+                header.set_cksum();
+                let path = format!("{}.{}", route.as_ref().trim_start_matches('/'), extension);
+                archive.append_data(&mut header, path, rendered)
+            }
+        }
+    }
+
+    fn finish(self) -> Result<(), io::Error> {
+        let output = match self {
+            RenderAllWriter::Nul(output) => output,
+            RenderAllWriter::Tar(archive) => archive.into_inner()?,
+        };
+        output.flush()
+    }
+}
+
+/// Optional checks which run content to find problems that loading it
+/// cannot.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ContentChecks {
+    /// Run every executable in validation mode (see
+    /// [`FilesystemBasedContentEngine::probe_executables`]).
+    pub probe_executables: bool,
+
+    /// Render every route (see
+    /// [`FilesystemBasedContentEngine::render_check`]).
+    pub render: bool,
+}
+
+impl ContentChecks {
+    fn run(
+        self,
+        content_engine: &FilesystemBasedContentEngine<ServerInfo>,
+    ) -> Vec<ContentLoadingErrors> {
+        let mut all_errors = Vec::new();
+        if self.probe_executables {
+            log::info!("Probing executables");
+            all_errors.extend(content_engine.probe_executables().err());
+        }
+        if self.render {
+            log::info!("Checking that all content renders");
+            all_errors.extend(content_engine.render_check().err());
+        }
+        all_errors
+    }
+}
+
+/// Writes every route in the content directory to `output`, one per line
+/// followed by the media types it is available as. With `graph`, writes which
+/// routes include which others instead, in Graphviz DOT format. Only
+/// dependencies that can be found without rendering are included (partials
+/// and literal routes passed to helpers like `get`).
+pub fn routes<O: io::Write>(
+    content_directory: ContentDirectory,
+    graph: bool,
+    config: Config,
+    output: &mut O,
+) -> Result<(), RoutesCommandError> {
+    let mut routes = (&content_directory)
+        .into_iter()
+        .map(|content_file| content_file.route.clone())
+        .collect::<Vec<_>>();
+    routes.sort();
+    routes.dedup();
+
+    let shared_content_engine = FilesystemBasedContentEngine::from_content_directory_with_config(
+        content_directory,
+        ServerInfo::without_socket_address()?.for_config(&config),
+        config,
+    )?;
+    let content_engine = shared_content_engine
+        .read()
+        .expect("RwLock for ContentEngine has been poisoned");
+
+    let write = |output: &mut O, text: &str| {
+        output
+            .write_all(text.as_bytes())
+            .map_err(|source| RoutesCommandError::WriteError { source })
+    };
+    if graph {
+        write(output, &content_engine.dependency_graph().to_dot())?;
+    } else {
+        for route in routes {
+            // Internal content and drafts are left out.
+            if let Some(representations) = content_engine.get(&route) {
+                let mut media_types = representations
+                    .keys()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>();
+                media_types.sort();
+                write(output, &format!("{} {}\n", route, media_types.join(" ")))?;
+            }
+        }
+    }
     output
         .flush()
-        .map_err(|source| GetCommandError::WriteError { source })
+        .map_err(|source| RoutesCommandError::WriteError { source })
+}
+
+/// Loads the content directory and writes any problems with it to `output`,
+/// one per line. The `checks` look for more problems by running content.
+pub fn check<O: io::Write>(
+    content_directory: ContentDirectory,
+    checks: ContentChecks,
+    config: Config,
+    output: &mut O,
+) -> Result<(), CheckCommandError> {
+    let all_errors = match FilesystemBasedContentEngine::from_content_directory_with_config(
+        content_directory,
+        ServerInfo::without_socket_address()?.for_config(&config),
+        config,
+    ) {
+        Ok(shared_content_engine) => checks.run(
+            &shared_content_engine
+                .read()
+                .expect("RwLock for ContentEngine has been poisoned"),
+        ),
+        Err(errors) => vec![errors],
+    };
+    if all_errors.is_empty() {
+        return Ok(());
+    }
+
+    for message in all_errors.iter().flat_map(ContentLoadingErrors::messages) {
+        writeln!(output, "{}", message)
+            .map_err(|source| CheckCommandError::WriteError { source })?;
+    }
+    output
+        .flush()
+        .map_err(|source| CheckCommandError::WriteError { source })?;
+
+    Err(CheckCommandError::InvalidContent {
+        problem_count: all_errors.iter().map(ContentLoadingErrors::len).sum(),
+        problems: all_errors,
+    })
 }
 
 /// Starts an HTTP server for the given content directory.
+///
+/// Requests with a `Host` header matching one of the `virtual_hosts` are
+/// served from that host's content directory instead. The index and error
+/// handler routes must exist in every content directory.
+///
+/// Content at the `prerender` routes (and templates with `prerender: true`
+/// in their front matter) is rendered before the server starts to fill the
+/// output caches of executables (see `cache-ttl`), as they would be for a
+/// request without query parameters. The rendered output is discarded, so
+/// routes which cannot fill any cache are skipped with a warning. The
+/// `prerender` routes only apply to the default content directory. The
+/// `checks` run against all content directories before the server starts,
+/// and it does not start if any of them fail.
+///
+/// Tasks (from the config or template front matter) are run on their
+/// schedules until the server stops.
+///
+/// All content directories are loaded again when the process receives
+/// `SIGHUP` or an authorized request to the configured reload route. If that
+/// fails the previous content continues to be served.
+#[allow(clippy::too_many_arguments)]
 pub fn serve<A: 'static + ToSocketAddrs>(
     content_directory: ContentDirectory,
+    virtual_hosts: Vec<(String, ContentDirectory)>,
     index_route: Option<Route>,
     error_handler_route: Option<Route>,
+    prerender: Vec<Route>,
+    checks: ContentChecks,
     bind_to: A,
+    config: Config,
 ) -> Result<(), ServeCommandError> {
-    let shared_content_engine = FilesystemBasedContentEngine::from_content_directory(
+    let server_info = ServerInfo::with_socket_address(&bind_to)?.for_config(&config);
+    log_startup_summary(
+        &content_directory,
+        &virtual_hosts,
+        &index_route,
+        &error_handler_route,
+        &bind_to,
+        &config,
+    );
+    if config.dev {
+        log::warn!(
+            "Development mode is on, so failed renders show their details (including render data) to clients"
+        );
+    }
+    let shared_content_engine = FilesystemBasedContentEngine::from_content_directory_with_config(
         content_directory,
-        ServerInfo::with_socket_address(&bind_to)?,
+        server_info.clone(),
+        config.clone(),
+    )?;
+    prepare_to_serve(
+        &shared_content_engine,
+        &index_route,
+        &error_handler_route,
+        prerender,
+        checks,
     )?;
 
-    // If index or error handler are set, validate that they refer to an
-    // existing route.
-    if index_route.is_some() || error_handler_route.is_some() {
+    let mut virtual_host_content_engines = HashMap::new();
+    for (host_name, content_directory) in virtual_hosts {
+        log::info!("Loading content for virtual host {}", host_name);
+        let shared_content_engine =
+            FilesystemBasedContentEngine::from_content_directory_with_config(
+                content_directory,
+                server_info.clone(),
+                config.clone(),
+            )?;
+        prepare_to_serve(
+            &shared_content_engine,
+            &index_route,
+            &error_handler_route,
+            Vec::new(),
+            checks,
+        )?;
+        virtual_host_content_engines.insert(host_name.to_ascii_lowercase(), shared_content_engine);
+    }
+
+    let tasks = {
         let content_engine = shared_content_engine
             .read()
             .expect("RwLock for ContentEngine has been poisoned");
-
-        if let Some(specified_index_route) = &index_route {
-            let index = content_engine.get(specified_index_route);
-            if index.is_none() {
-                return Err(ServeCommandError::IndexRouteMissing);
-            }
+        if let Some(route) = config
+            .tasks
+            .keys()
+            .find(|route| content_engine.get_internal(route).is_none())
+        {
+            return Err(ServeCommandError::TaskRouteMissing {
+                route: route.clone(),
+            });
         }
+        content_engine.tasks()
+    };
+    let scheduler = scheduler::start(shared_content_engine.clone(), tasks);
 
-        if let Some(specified_error_handler_route) = &error_handler_route {
-            let error_handler = content_engine.get(specified_error_handler_route);
-            if error_handler.is_none() {
-                return Err(ServeCommandError::ErrorHandlerRouteMissing);
+    let reloader: http::Reloader = {
+        let shared_content_engines = virtual_host_content_engines
+            .values()
+            .chain([&shared_content_engine])
+            .cloned()
+            .collect::<Vec<_>>();
+        Arc::new(move |changed_paths| {
+            for shared_content_engine in &shared_content_engines {
+                match FilesystemBasedContentEngine::reload_changed(
+                    shared_content_engine,
+                    changed_paths,
+                )? {
+                    ReloadedContent::Nothing => {}
+                    ReloadedContent::Routes(routes) => log::info!(
+                        "Reloaded {}",
+                        routes
+                            .iter()
+                            .map(ToString::to_string)
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    ),
+                    ReloadedContent::Everything => log::info!("Reloaded all content"),
+                }
             }
-        }
-    }
+            Ok(())
+        })
+    };
 
-    http::run_server(
+    let result = http::run_server(
         shared_content_engine,
+        virtual_host_content_engines,
         index_route,
         error_handler_route,
         bind_to,
+        config,
+        Some(reloader),
     )
-    .map_err(|source| ServeCommandError::ServerError { source })
+    .map_err(|error| match error {
+        http::RunServerError::BindError { source } => ServeCommandError::BindError { source },
+        http::RunServerError::RunError { source } => ServeCommandError::ServerError { source },
+    });
+    if let Some(scheduler) = scheduler {
+        scheduler.stop();
+    }
+    result
+}
+
+/// Logs what the server is about to do, so that deployments can check that
+/// it loaded the settings they expected.
+fn log_startup_summary<A: ToSocketAddrs>(
+    content_directory: &ContentDirectory,
+    virtual_hosts: &[(String, ContentDirectory)],
+    index_route: &Option<Route>,
+    error_handler_route: &Option<Route>,
+    bind_to: &A,
+    config: &Config,
+) {
+    if !log::log_enabled!(log::Level::Info) {
+        return;
+    }
+    let optional_route = |route: &Option<Route>| match route {
+        Some(route) => route.to_string(),
+        None => String::from("none"),
+    };
+    let bind_addresses = match bind_to.to_socket_addrs() {
+        Ok(socket_addresses) => socket_addresses
+            .map(|socket_address| socket_address.to_string())
+            .collect::<Vec<_>>()
+            .join(", "),
+        Err(error) => format!("unresolved ({})", error),
+    };
+    let features = [
+        ("executables", cfg!(feature = "executables")),
+        ("lambda", cfg!(feature = "lambda")),
+    ]
+    .iter()
+    .map(|(feature, enabled)| format!("{}={}", feature, if *enabled { "on" } else { "off" }))
+    .collect::<Vec<_>>()
+    .join(", ");
+
+    log::info!("Operator {} starting", VERSION.0);
+    log::info!("Binding to {}", bind_addresses);
+    log::info!("Content directory: {}", content_directory.root().display());
+    for (host_name, content_directory) in virtual_hosts {
+        log::info!(
+            "Virtual host {}: {}",
+            host_name,
+            content_directory.root().display()
+        );
+    }
+    log::info!(
+        "Index route: {}, error handler route: {}",
+        optional_route(index_route),
+        optional_route(error_handler_route),
+    );
+    log::info!("Features: {}", features);
+    match config.to_toml() {
+        Ok(config) => log::info!("Effective configuration:\n{}", config.trim_end()),
+        Err(error) => log::warn!("Unable to show the effective configuration: {}", error),
+    }
+}
+
+/// Validates that the index and error handler routes (if set) exist and
+/// runs `checks`, then prerenders content.
+fn prepare_to_serve(
+    shared_content_engine: &RwLock<FilesystemBasedContentEngine<ServerInfo>>,
+    index_route: &Option<Route>,
+    error_handler_route: &Option<Route>,
+    prerender: Vec<Route>,
+    checks: ContentChecks,
+) -> Result<(), ServeCommandError> {
+    let content_engine = shared_content_engine
+        .read()
+        .expect("RwLock for ContentEngine has been poisoned");
+
+    if let Some(specified_index_route) = index_route {
+        let index = content_engine.get(specified_index_route);
+        if index.is_none() {
+            return Err(ServeCommandError::IndexRouteMissing);
+        }
+    }
+
+    if let Some(specified_error_handler_route) = error_handler_route {
+        let error_handler = content_engine.get(specified_error_handler_route);
+        if error_handler.is_none() {
+            return Err(ServeCommandError::ErrorHandlerRouteMissing);
+        }
+    }
+
+    if let Some(errors) = checks.run(&content_engine).into_iter().next() {
+        return Err(errors.into());
+    }
+
+    let mut routes = prerender;
+    for route in content_engine.prerender_routes() {
+        if !routes.contains(&route) {
+            routes.push(route);
+        }
+    }
+    for route in routes {
+        let warms_caches = content_engine
+            .get_internal(&route)
+            .is_none_or(|representations| {
+                representations
+                    .values()
+                    .any(RegisteredContent::warms_caches)
+            });
+        if !warms_caches {
+            log::warn!(
+                "Not prerendering {} because its output is not cached (see `cache-ttl`)",
+                route
+            );
+            continue;
+        }
+        log::info!("Prerendering {}", route);
+        render_route(
+            &*content_engine,
+            &route,
+            None,
+            HashMap::new(),
+            None,
+            &mut io::sink(),
+        )
+        .map_err(|source| ServeCommandError::PrerenderError { route, source })?;
+    }
+    Ok(())
+}
+
+/// Handles invocations from the AWS Lambda runtime API by rendering content
+/// from the content directory.
+#[cfg(feature = "lambda")]
+pub fn lambda(
+    content_directory: ContentDirectory,
+    index_route: Option<Route>,
+    config: Config,
+) -> Result<(), LambdaCommandError> {
+    let runtime_api = std::env::var("AWS_LAMBDA_RUNTIME_API")
+        .map_err(|_| LambdaCommandError::RuntimeApiMissing)?;
+    let shared_content_engine = FilesystemBasedContentEngine::from_content_directory_with_config(
+        content_directory,
+        ServerInfo::without_socket_address()?.for_config(&config),
+        config.clone(),
+    )?;
+
+    let mut handler = lambda::LambdaHandler::new(shared_content_engine.clone(), config);
+    if let Some(index_route) = index_route {
+        let content_engine = shared_content_engine
+            .read()
+            .expect("RwLock for ContentEngine has been poisoned");
+        if content_engine.get(&index_route).is_none() {
+            return Err(LambdaCommandError::IndexRouteMissing);
+        }
+        handler = handler.with_index_route(index_route);
+    }
+
+    handler.run(&runtime_api)?;
+    Ok(())
+}
+
+/// Serves the content directory on a local port and sends it `requests`
+/// requests for each route (`concurrency` at a time), then writes latency
+/// and throughput measurements to `output`. If `routes` is empty then every
+/// route in the content directory is tested.
+pub fn bench<O: io::Write>(
+    content_directory: ContentDirectory,
+    routes: Vec<Route>,
+    requests: usize,
+    concurrency: usize,
+    config: Config,
+    output: &mut O,
+) -> Result<(), BenchCommandError> {
+    let listener = TcpListener::bind(("127.0.0.1", 0))
+        .map_err(|source| BenchCommandError::ServerError { source })?;
+    let address = listener
+        .local_addr()
+        .map_err(|source| BenchCommandError::ServerError { source })?;
+
+    let all_routes = (&content_directory)
+        .into_iter()
+        .map(|content_file| content_file.route.clone())
+        .collect::<Vec<_>>();
+    let shared_content_engine = FilesystemBasedContentEngine::from_content_directory_with_config(
+        content_directory,
+        ServerInfo::with_socket_address(&address)?.for_config(&config),
+        config.clone(),
+    )?;
+
+    let routes = {
+        let content_engine = shared_content_engine
+            .read()
+            .expect("RwLock for ContentEngine has been poisoned");
+        if routes.is_empty() {
+            let mut routes = all_routes
+                .into_iter()
+                .filter(|route| content_engine.get(route).is_some())
+                .collect::<Vec<_>>();
+            routes.sort();
+            routes.dedup();
+            routes
+        } else {
+            if let Some(missing_route) = routes
+                .iter()
+                .find(|route| content_engine.get(route).is_none())
+            {
+                return Err(BenchCommandError::ContentNotFound {
+                    route: missing_route.clone(),
+                });
+            }
+            routes
+        }
+    };
+
+    let mut system = System::new("bench");
+    let all_results = system
+        .block_on(async move {
+            let server = http::OperatorServer::builder(shared_content_engine)
+                .with_config(config)
+                .listen(listener)?;
+            let mut all_results = Vec::with_capacity(routes.len());
+            for route in routes {
+                all_results.push(load_test(address, route, requests, concurrency).await);
+            }
+            server.handle().stop(true).await;
+            Ok(all_results)
+        })
+        .map_err(|error| match error {
+            http::RunServerError::BindError { source }
+            | http::RunServerError::RunError { source } => {
+                BenchCommandError::ServerError { source }
+            }
+        })?;
+
+    for results in all_results {
+        writeln!(output, "{}", results)
+            .map_err(|source| BenchCommandError::WriteError { source })?;
+    }
+    output
+        .flush()
+        .map_err(|source| BenchCommandError::WriteError { source })
+}
+
+async fn load_test(
+    address: SocketAddr,
+    route: Route,
+    requests: usize,
+    concurrency: usize,
+) -> LoadTestResults {
+    let client = HttpClient::new();
+    let url = format!("http://{}{}", address, route);
+    let remaining_requests = Cell::new(requests);
+
+    let start = Instant::now();
+    let workers = (0..concurrency.max(1)).map(|_| async {
+        let mut latencies = Vec::new();
+        let mut failures = 0;
+        while remaining_requests.get() > 0 {
+            remaining_requests.set(remaining_requests.get() - 1);
+            let request_start = Instant::now();
+            let succeeded = match client
+                .get(&url)
+                .header("Accept", "*/*")
+                .timeout(Duration::from_secs(60))
+                .send()
+                .await
+            {
+                Err(error) => {
+                    log::warn!("Request for {} failed: {}", url, error);
+                    false
+                }
+                Ok(mut response) => {
+                    let body = response.body().limit(usize::MAX).await;
+                    response.status().is_success() && body.is_ok()
+                }
+            };
+            latencies.push(request_start.elapsed());
+            if !succeeded {
+                failures += 1;
+            }
+        }
+        (latencies, failures)
+    });
+    let worker_results = future::join_all(workers).await;
+    let elapsed = start.elapsed();
+
+    let mut latencies = Vec::with_capacity(requests);
+    let mut failures = 0;
+    for (worker_latencies, worker_failures) in worker_results {
+        latencies.extend(worker_latencies);
+        failures += worker_failures;
+    }
+    LoadTestResults::new(route, latencies, failures, elapsed)
+}
+
+/// Measurements from sending many requests for one route.
+struct LoadTestResults {
+    route: Route,
+    /// Sorted from fastest to slowest.
+    latencies: Vec<Duration>,
+    failures: usize,
+    elapsed: Duration,
+}
+impl LoadTestResults {
+    fn new(route: Route, mut latencies: Vec<Duration>, failures: usize, elapsed: Duration) -> Self {
+        latencies.sort();
+        LoadTestResults {
+            route,
+            latencies,
+            failures,
+            elapsed,
+        }
+    }
+
+    /// The latency which `percent`% of requests were at least as fast as.
+    fn percentile(&self, percent: usize) -> Duration {
+        let rank = (self.latencies.len() * percent).div_ceil(100);
+        self.latencies
+            .get(rank.saturating_sub(1))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    fn requests_per_second(&self) -> f64 {
+        self.latencies.len() as f64 / self.elapsed.as_secs_f64()
+    }
+}
+impl fmt::Display for LoadTestResults {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            formatter,
+            "{}: {} requests ({} failed), {:.1} requests/second, latency p50 {:.2?} p90 {:.2?} p99 {:.2?} max {:.2?}",
+            self.route,
+            self.latencies.len(),
+            self.failures,
+            self.requests_per_second(),
+            self.percentile(50),
+            self.percentile(90),
+            self.percentile(99),
+            self.percentile(100),
+        )
+    }
 }
 
 #[cfg(test)]
@@ -247,7 +1389,16 @@ mod tests {
             let mut input = template.as_bytes();
             let mut output = Vec::new();
             let directory = arbitrary_content_directory_with_valid_content();
-            let result = eval(directory, None, &mut input, &mut output);
+            let result = eval(
+                directory,
+                None,
+                HashMap::new(),
+                None,
+                Vec::new(),
+                Config::default(),
+                &mut input,
+                &mut output,
+            );
 
             assert!(
                 result.is_ok(),
@@ -273,7 +1424,16 @@ mod tests {
             let mut input = template.as_bytes();
             let mut output = Vec::new();
             let directory = arbitrary_content_directory_with_valid_content();
-            let result = eval(directory, None, &mut input, &mut output);
+            let result = eval(
+                directory,
+                None,
+                HashMap::new(),
+                None,
+                Vec::new(),
+                Config::default(),
+                &mut input,
+                &mut output,
+            );
 
             assert!(
                 result.is_err(),
@@ -294,7 +1454,16 @@ mod tests {
         let mut input = template.as_bytes();
         let mut output = Vec::new();
         let directory = arbitrary_content_directory_with_valid_content();
-        let result = eval(directory, Some(query), &mut input, &mut output);
+        let result = eval(
+            directory,
+            Some(query),
+            HashMap::new(),
+            None,
+            Vec::new(),
+            Config::default(),
+            &mut input,
+            &mut output,
+        );
 
         assert!(
             result.is_ok(),
@@ -312,6 +1481,39 @@ mod tests {
             );
     }
 
+    #[test]
+    fn eval_can_render_as_a_media_type_with_extra_data() {
+        let mut input = "<p>{{ data.greeting }}</p>".as_bytes();
+        let mut output = Vec::new();
+        let result = eval(
+            arbitrary_content_directory_with_valid_content(),
+            None,
+            HashMap::new(),
+            Some(mime::TEXT_HTML),
+            vec![(String::from("greeting"), String::from("<hi>"))],
+            Config::default(),
+            &mut input,
+            &mut output,
+        );
+        assert!(result.is_ok(), "Eval failed: {}", result.unwrap_err());
+        assert_eq!(str::from_utf8(&output).unwrap(), "<p>&lt;hi&gt;</p>");
+
+        let mut input = "<p>hi</p>".as_bytes();
+        let mut output = Vec::new();
+        let result = eval(
+            arbitrary_content_directory_with_valid_content(),
+            None,
+            HashMap::new(),
+            Some(mime::TEXT_PLAIN),
+            Vec::new(),
+            Config::default(),
+            &mut input,
+            &mut output,
+        );
+        assert!(result.is_ok(), "Eval failed: {}", result.unwrap_err());
+        assert_eq!(str::from_utf8(&output).unwrap(), "<p>hi</p>");
+    }
+
     #[test]
     fn content_can_be_retrieved_from_content_directory() {
         let mut output = Vec::new();
@@ -319,7 +1521,15 @@ mod tests {
         let expected_output = "hello world";
 
         let directory = arbitrary_content_directory_with_valid_content();
-        let result = get(directory, &route, None, Some(mime::TEXT_PLAIN), &mut output);
+        let result = get(
+            directory,
+            &route,
+            None,
+            HashMap::new(),
+            Some(mime::TEXT_PLAIN),
+            Config::default(),
+            &mut output,
+        );
 
         assert!(
             result.is_ok(),
@@ -345,7 +1555,15 @@ mod tests {
         let expected_output = "hello world";
 
         let directory = arbitrary_content_directory_with_valid_content();
-        let result = get(directory, &route, None, None, &mut output);
+        let result = get(
+            directory,
+            &route,
+            None,
+            HashMap::new(),
+            None,
+            Config::default(),
+            &mut output,
+        );
 
         assert!(
             result.is_ok(),
@@ -370,7 +1588,15 @@ mod tests {
         let route = route("/this-route-does-not-refer-to-any-content");
 
         let directory = arbitrary_content_directory_with_valid_content();
-        let result = get(directory, &route, None, Some(mime::TEXT_HTML), &mut output);
+        let result = get(
+            directory,
+            &route,
+            None,
+            HashMap::new(),
+            Some(mime::TEXT_HTML),
+            Config::default(),
+            &mut output,
+        );
 
         match result {
             Ok(_) => panic!(
@@ -388,6 +1614,68 @@ mod tests {
     }
 
     #[test]
+    fn request_headers_can_be_provided_when_evaluating_templates() {
+        let mut input = "{{request.request-headers.user-agent}}".as_bytes();
+        let mut output = Vec::new();
+        let result = eval(
+            arbitrary_content_directory_with_valid_content(),
+            None,
+            HashMap::from([(String::from("user-agent"), String::from("test"))]),
+            None,
+            Vec::new(),
+            Config::default(),
+            &mut input,
+            &mut output,
+        );
+        assert!(result.is_ok(), "Eval failed: {}", result.unwrap_err());
+        assert_eq!(str::from_utf8(&output).unwrap(), "test");
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "templating"), ignore = "uses templates")]
+    fn get_renders_like_the_server() {
+        let root = tempfile::tempdir().expect("Failed to create temporary directory");
+        fs::write(root.path().join("broken.txt.hbs"), "{{no-such-variable}}").unwrap();
+        fs::write(root.path().join("not-found.txt"), "not found").unwrap();
+        let config = toml::from_str::<Config>(
+            r#"
+            routes."/broken" = { fallback = "/not-found" }
+            routes."/not-found" = { status = 404 }
+            "#,
+        )
+        .unwrap();
+
+        let mut output = Vec::new();
+        let result = get(
+            ContentDirectory::from_root(&root.path()).unwrap(),
+            &route("/broken"),
+            None,
+            HashMap::new(),
+            None,
+            config.clone(),
+            &mut output,
+        );
+        assert!(result.is_ok(), "Get failed: {}", result.unwrap_err());
+        assert_eq!(str::from_utf8(&output).unwrap(), "not found");
+
+        let mut output = Vec::new();
+        match get(
+            ContentDirectory::from_root(&root.path()).unwrap(),
+            &route("/not-found"),
+            None,
+            HashMap::new(),
+            None,
+            config,
+            &mut output,
+        ) {
+            Err(GetCommandError::ErrorStatus { status: 404, .. }) => {}
+            result => panic!("Get did not fail as expected: {:?}", result),
+        }
+        assert_eq!(str::from_utf8(&output).unwrap(), "not found");
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "templating"), ignore = "uses templates")]
     fn query_string_can_be_provided_when_retrieving_content() {
         let mut output = Vec::new();
         let route = route("/query-string");
@@ -396,7 +1684,15 @@ mod tests {
             .expect("Test query string was invalid");
 
         let directory = sample_content_directory("render-context");
-        let result = get(directory, &route, Some(query), None, &mut output);
+        let result = get(
+            directory,
+            &route,
+            Some(query),
+            HashMap::new(),
+            None,
+            Config::default(),
+            &mut output,
+        );
 
         assert!(
             result.is_ok(),
@@ -412,4 +1708,443 @@ mod tests {
             output_as_str
         );
     }
+
+    #[test]
+    fn checking_valid_content_succeeds_without_output() {
+        let mut output = Vec::new();
+        let result = check(
+            arbitrary_content_directory_with_valid_content(),
+            ContentChecks::default(),
+            Config::default(),
+            &mut output,
+        );
+        assert!(result.is_ok(), "Check failed: {}", result.unwrap_err());
+        assert!(output.is_empty());
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "executables"), ignore = "uses executables")]
+    fn checking_can_probe_executables() {
+        use std::os::unix::fs::PermissionsExt;
+        let root = tempfile::tempdir().expect("Failed to create temporary directory");
+        let path = root.path().join("broken.txt.sh");
+        std::fs::write(&path, "#!/no/such/interpreter\n").unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let mut output = Vec::new();
+        let directory = ContentDirectory::from_root(&root.path()).unwrap();
+        assert!(check(
+            directory,
+            ContentChecks::default(),
+            Config::default(),
+            &mut output
+        )
+        .is_ok());
+
+        let directory = ContentDirectory::from_root(&root.path()).unwrap();
+        let checks = ContentChecks {
+            probe_executables: true,
+            ..ContentChecks::default()
+        };
+        match check(directory, checks, Config::default(), &mut output) {
+            Err(CheckCommandError::InvalidContent {
+                problem_count: 1, ..
+            }) => {}
+            result => panic!("Check did not fail as expected: {:?}", result),
+        }
+        let output = String::from_utf8(output).unwrap();
+        assert!(
+            output.starts_with("/broken: Executable is not runnable:"),
+            "Unexpected output: {}",
+            output,
+        );
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "executables"), ignore = "uses executables")]
+    fn prerendering_fills_executable_output_caches() {
+        use std::os::unix::fs::PermissionsExt;
+        let root = tempfile::tempdir().expect("Failed to create temporary directory");
+        let log = tempfile::tempdir().expect("Failed to create temporary directory");
+        let log_path = log.path().join("runs");
+        for name in ["cached", "uncached"] {
+            let path = root.path().join(format!("{}.txt.sh", name));
+            fs::write(
+                &path,
+                format!(
+                    "#!/bin/sh\necho {0} >> '{1}'\nwc -l < '{1}' | tr -d ' \\n'\n",
+                    name,
+                    log_path.display(),
+                ),
+            )
+            .unwrap();
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+        }
+        let config = toml::from_str::<Config>(r#"executables.cache-ttl."/cached" = 600"#).unwrap();
+        let shared_content_engine =
+            FilesystemBasedContentEngine::from_content_directory_with_config(
+                ContentDirectory::from_root(&root.path()).unwrap(),
+                ServerInfo::without_socket_address().unwrap(),
+                config,
+            )
+            .unwrap();
+
+        let result = prepare_to_serve(
+            &shared_content_engine,
+            &None,
+            &None,
+            vec![route("/cached"), route("/uncached")],
+            ContentChecks::default(),
+        );
+        assert!(
+            result.is_ok(),
+            "Prerendering failed: {}",
+            result.unwrap_err()
+        );
+        assert_eq!(fs::read_to_string(&log_path).unwrap(), "cached\n");
+
+        // Requests get the prerendered output (from the first run) without
+        // running the executable again.
+        let content_engine = shared_content_engine.read().unwrap();
+        for _ in 0..2 {
+            let mut output = Vec::new();
+            render_route(
+                &*content_engine,
+                &route("/cached"),
+                None,
+                HashMap::new(),
+                None,
+                &mut output,
+            )
+            .unwrap();
+            assert_eq!(String::from_utf8(output).unwrap(), "1");
+        }
+        assert_eq!(fs::read_to_string(&log_path).unwrap(), "cached\n");
+    }
+
+    #[test]
+    #[cfg_attr(
+        not(all(feature = "executables", feature = "templating")),
+        ignore = "uses executables and templates"
+    )]
+    fn deterministic_renders_do_not_depend_on_the_machine() {
+        use std::os::unix::fs::PermissionsExt;
+        let root = tempfile::tempdir().expect("Failed to create temporary directory");
+        std::fs::write(
+            root.path().join("page.txt.hbs"),
+            "{{server-info.version}} {{server-info.operator-path}} {{get \"/env\"}}",
+        )
+        .unwrap();
+        let path = root.path().join("env.txt.sh");
+        std::fs::write(
+            &path,
+            "#!/bin/sh\nprintf '%s' \"$OPERATOR_DETERMINISTIC\"\n",
+        )
+        .unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let config = Config {
+            deterministic: true,
+            ..Config::default()
+        };
+
+        let mut output = Vec::new();
+        let result = get(
+            ContentDirectory::from_root(&root.path()).unwrap(),
+            &route("/page"),
+            None,
+            HashMap::new(),
+            None,
+            config,
+            &mut output,
+        );
+        assert!(result.is_ok(), "Get failed: {}", result.unwrap_err());
+        assert_eq!(String::from_utf8(output).unwrap(), "0.0.0 operator 1");
+    }
+
+    #[test]
+    fn checking_can_render_everything() {
+        let checks = ContentChecks {
+            render: true,
+            ..ContentChecks::default()
+        };
+        let mut output = Vec::new();
+        let result = check(
+            sample_content_directory("hello-world"),
+            checks,
+            Config::default(),
+            &mut output,
+        );
+        assert!(result.is_ok(), "Check failed: {}", result.unwrap_err());
+
+        let mut output = Vec::new();
+        match check(
+            sample_content_directory("error-handling"),
+            checks,
+            Config::default(),
+            &mut output,
+        ) {
+            Err(CheckCommandError::InvalidContent { problem_count, .. }) => {
+                assert!(problem_count > 0);
+                assert!(!output.is_empty());
+            }
+            result => panic!("Check did not fail as expected: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn checking_invalid_content_lists_problems() {
+        for directory in sample_content_directories_with_invalid_contents() {
+            let root = directory.root().to_path_buf();
+            let mut output = Vec::new();
+            match check(
+                directory,
+                ContentChecks::default(),
+                Config::default(),
+                &mut output,
+            ) {
+                Err(CheckCommandError::InvalidContent { problem_count, .. }) => {
+                    assert!(problem_count > 0);
+                    assert!(
+                        !output.is_empty(),
+                        "Check did not describe problems in '{}'",
+                        root.display(),
+                    );
+                }
+                Ok(_) => panic!("Check succeeded for '{}'", root.display()),
+                Err(error) => panic!("Wrong type of error was produced: {}", error),
+            }
+        }
+    }
+
+    #[test]
+    fn content_problems_are_reported_individually() {
+        for directory in sample_content_directories_with_invalid_contents() {
+            let root = directory.root().to_path_buf();
+            let mut output = Vec::new();
+            let error = check(
+                directory,
+                ContentChecks::default(),
+                Config::default(),
+                &mut output,
+            )
+            .expect_err("Check succeeded for invalid content");
+            let problem_count = match &error {
+                CheckCommandError::InvalidContent { problem_count, .. } => *problem_count,
+                error => panic!("Wrong type of error was produced: {}", error),
+            };
+            let reports = ErrorReport::all_from(&error);
+            assert_eq!(
+                reports.len(),
+                problem_count,
+                "Wrong number of reports for '{}'",
+                root.display(),
+            );
+            for report in reports {
+                assert_eq!(report.kind, "content-loading");
+                assert!(report.file.is_some(), "Report had no file: {:?}", report);
+                assert!(report.route.is_none(), "Report had a route: {:?}", report);
+            }
+        }
+    }
+
+    #[test]
+    fn errors_are_reported_with_their_causes() {
+        let mut output = Vec::new();
+        let error = get(
+            arbitrary_content_directory_with_valid_content(),
+            &route("/no/such/route"),
+            None,
+            HashMap::new(),
+            None,
+            Config::default(),
+            &mut output,
+        )
+        .expect_err("Getting a missing route succeeded");
+        assert_eq!(
+            ErrorReport::all_from(&error),
+            vec![ErrorReport {
+                kind: "not-found",
+                route: Some(route("/no/such/route")),
+                file: None,
+                message: String::from("Content not found at route '/no/such/route'."),
+                sources: Vec::new(),
+            }],
+        );
+
+        let error = RenderAllCommandError::RenderError {
+            route: route("/hello"),
+            media_type: MediaType::from_media_range(mime::TEXT_HTML).unwrap(),
+            source: Box::new(GetCommandError::StreamError {
+                source: StreamError::Canceled,
+            }),
+        };
+        let report = ErrorReport::all_from(&error).remove(0);
+        assert_eq!(report.kind, "stream");
+        assert_eq!(report.route, Some(route("/hello")));
+        assert_eq!(
+            report.sources,
+            vec!["Unable to emit rendered content.", "Stream was cancelled"],
+        );
+    }
+
+    #[test]
+    fn load_test_percentiles_are_nearest_rank() {
+        let results = LoadTestResults::new(
+            route("/"),
+            (1..=10).rev().map(Duration::from_millis).collect(),
+            0,
+            Duration::from_secs(2),
+        );
+        assert_eq!(results.percentile(50), Duration::from_millis(5));
+        assert_eq!(results.percentile(90), Duration::from_millis(9));
+        assert_eq!(results.percentile(99), Duration::from_millis(10));
+        assert_eq!(results.percentile(100), Duration::from_millis(10));
+        assert_eq!(results.requests_per_second(), 5.0);
+    }
+
+    #[test]
+    fn content_can_be_benchmarked() {
+        let mut output = Vec::new();
+        let result = bench(
+            arbitrary_content_directory_with_valid_content(),
+            vec![route("/hello")],
+            4,
+            2,
+            Config::default(),
+            &mut output,
+        );
+        assert!(result.is_ok(), "Bench failed: {}", result.unwrap_err());
+        let output_as_str = str::from_utf8(output.as_slice()).expect("Output was not UTF-8");
+        assert!(
+            output_as_str.starts_with("/hello: 4 requests (0 failed)"),
+            "Unexpected output: {}",
+            output_as_str,
+        );
+    }
+
+    #[test]
+    fn everything_can_be_rendered_as_nul_delimited_records() {
+        let mut output = Vec::new();
+        let result = render_all(
+            sample_content_directory("hello-world"),
+            None,
+            None,
+            RenderAllFormat::Nul,
+            Config::default(),
+            &mut output,
+        );
+        assert!(result.is_ok(), "Render all failed: {}", result.unwrap_err());
+        assert_eq!(output, b"/hello\x00text/plain\x0011\x00hello world");
+    }
+
+    #[test]
+    #[cfg_attr(
+        not(all(feature = "executables", feature = "templating")),
+        ignore = "uses executables and templates"
+    )]
+    fn everything_can_be_rendered_into_a_tar_archive() {
+        let mut output = Vec::new();
+        let result = render_all(
+            sample_content_directory("alternative-representations"),
+            Some(Glob::new("/foo").unwrap()),
+            Some("text/*".parse().unwrap()),
+            RenderAllFormat::Tar,
+            Config::default(),
+            &mut output,
+        );
+        assert!(result.is_ok(), "Render all failed: {}", result.unwrap_err());
+
+        let mut archive = tar::Archive::new(output.as_slice());
+        let mut file_names = archive
+            .entries()
+            .expect("Output was not a tar archive")
+            .map(|entry| {
+                entry
+                    .unwrap()
+                    .path()
+                    .unwrap()
+                    .to_string_lossy()
+                    .into_owned()
+            })
+            .collect::<Vec<_>>();
+        file_names.sort();
+        assert_eq!(
+            file_names,
+            vec!["foo.html", "foo.md", "foo.toml", "foo.txt", "foo.xml"]
+        );
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "templating"), ignore = "uses templates")]
+    fn multiple_routes_can_be_written_to_a_directory() {
+        let output_directory = tempfile::tempdir().expect("Failed to create temporary directory");
+        let result = get_to_directory(
+            sample_content_directory("media-types"),
+            &[route("/html-variation"), route("/nesting/just-html")],
+            None,
+            HashMap::new(),
+            None,
+            Config::default(),
+            output_directory.path(),
+        );
+        assert!(result.is_ok(), "Get failed: {}", result.unwrap_err());
+        assert_eq!(
+            fs::read_to_string(output_directory.path().join("html-variation.htm")).unwrap(),
+            fs::read_to_string(sample_path("media-types/html-variation.htm")).unwrap(),
+        );
+        assert_eq!(
+            fs::read_to_string(output_directory.path().join("nesting/just-html.html")).unwrap(),
+            fs::read_to_string(sample_path("media-types/nesting/just-html.html")).unwrap(),
+        );
+
+        let result = get_to_directory(
+            sample_content_directory("media-types"),
+            &[route("/html-variation"), route("/no/such/route")],
+            None,
+            HashMap::new(),
+            None,
+            Config::default(),
+            output_directory.path(),
+        );
+        match result {
+            Err(GetCommandError::RouteError { route: failed, .. }) => {
+                assert_eq!(failed, route("/no/such/route"))
+            }
+            result => panic!("Get did not fail as expected: {:?}", result),
+        }
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "templating"), ignore = "uses templates")]
+    fn routes_can_be_listed_with_their_dependencies() {
+        let directory = ContentDirectory::from_root(&sample_path("layouts")).unwrap();
+        let mut output = Vec::new();
+        routes(directory, false, Config::default(), &mut output).expect("Routes failed");
+        let output = String::from_utf8(output).expect("Output was not UTF-8");
+        assert!(
+            output.contains("/index text/html\n"),
+            "Unexpected output: {}",
+            output
+        );
+        assert!(
+            !output.contains("/_layouts"),
+            "Unexpected output: {}",
+            output
+        );
+
+        let directory = ContentDirectory::from_root(&sample_path("layouts")).unwrap();
+        let mut output = Vec::new();
+        routes(directory, true, Config::default(), &mut output).expect("Routes failed");
+        let output = String::from_utf8(output).expect("Output was not UTF-8");
+        assert!(
+            output.starts_with("digraph routes {\n"),
+            "Unexpected output: {}",
+            output
+        );
+        assert!(
+            output.contains("  \"/index\" -> \"/_layouts/base\";\n"),
+            "Unexpected output: {}",
+            output
+        );
+    }
 }
@@ -0,0 +1,318 @@
+//! The current time and time zone as seen by templates (see
+//! [`Config::time`](crate::config::Config::time)).
+
+use crate::config::Config;
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, FixedOffset, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt::{self, Write as _};
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+use thiserror::Error;
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum ClockError {
+    #[error(
+        "Clock '{}' must be \"system\" or a timestamp like \"2024-05-01T09:30:00Z\".",
+        .clock,
+    )]
+    InvalidClock { clock: String },
+
+    #[error(
+        "Time zone '{}' must be \"UTC\", an offset like \"+09:00\", or a name from the IANA \
+        time zone database like \"Asia/Tokyo\".",
+        .timezone,
+    )]
+    InvalidTimeZone { timezone: String },
+}
+
+#[derive(Error, Debug, PartialEq, Eq)]
+#[error("Date format '{}' is invalid (see the specifiers of `chrono::format::strftime`).", .format)]
+pub struct DateFormatError {
+    pub format: String,
+}
+
+/// Where the current time comes from.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum Clock {
+    /// The operating system's clock.
+    #[default]
+    System,
+
+    /// Always the same time, which is useful for tests.
+    Fixed(SystemTime),
+}
+
+impl Clock {
+    /// The time that a [fixed](Clock::Fixed) clock is stopped at in
+    /// [deterministic](crate::config::Config::deterministic) mode: the Unix
+    /// epoch.
+    pub const DETERMINISTIC_TIME: SystemTime = UNIX_EPOCH;
+
+    /// The clock that `config` asks for, which is never the system clock
+    /// in [deterministic](crate::config::Config::deterministic) mode.
+    pub fn for_config(config: &Config) -> Self {
+        match config.time.clock {
+            Clock::System if config.deterministic => Clock::Fixed(Self::DETERMINISTIC_TIME),
+            clock => clock,
+        }
+    }
+
+    pub fn now(&self) -> SystemTime {
+        match self {
+            Clock::System => SystemTime::now(),
+            Clock::Fixed(time) => *time,
+        }
+    }
+}
+
+impl FromStr for Clock {
+    type Err = ClockError;
+    fn from_str(clock: &str) -> Result<Self, Self::Err> {
+        if clock.eq_ignore_ascii_case("system") {
+            Ok(Clock::System)
+        } else {
+            humantime::parse_rfc3339(clock)
+                .map(Clock::Fixed)
+                .map_err(|_| ClockError::InvalidClock {
+                    clock: String::from(clock),
+                })
+        }
+    }
+}
+
+impl TryFrom<String> for Clock {
+    type Error = ClockError;
+    fn try_from(clock: String) -> Result<Self, Self::Error> {
+        clock.parse()
+    }
+}
+
+impl From<Clock> for String {
+    fn from(clock: Clock) -> Self {
+        clock.to_string()
+    }
+}
+
+impl fmt::Display for Clock {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Clock::System => write!(formatter, "system"),
+            Clock::Fixed(time) => write!(formatter, "{}", humantime::format_rfc3339(*time)),
+        }
+    }
+}
+
+/// A time zone: either a fixed offset from UTC or a named zone (which
+/// follows its daylight saving rules).
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum TimeZone {
+    Offset(FixedOffset),
+    Named(chrono_tz::Tz),
+}
+
+impl Default for TimeZone {
+    fn default() -> Self {
+        TimeZone::UTC
+    }
+}
+
+impl TimeZone {
+    pub const UTC: TimeZone = TimeZone::Named(chrono_tz::UTC);
+
+    /// How dates are formatted when no format is given: RFC 3339, like
+    /// `2024-05-01T09:30:00+09:00`.
+    pub const DEFAULT_FORMAT: &'static str = "%Y-%m-%dT%H:%M:%S%:z";
+
+    /// Formats `time` in this time zone. `format` uses `strftime`-style
+    /// specifiers (see `chrono::format::strftime`), like `%Y-%m-%d` for
+    /// `2024-05-01` or `%-d %B %Y` for `1 May 2024`.
+    pub fn format(&self, time: SystemTime, format: &str) -> Result<String, DateFormatError> {
+        let items = StrftimeItems::new(format).collect::<Vec<_>>();
+        if items.contains(&Item::Error) {
+            return Err(DateFormatError {
+                format: String::from(format),
+            });
+        }
+        let time = DateTime::<Utc>::from(time);
+        let mut formatted = String::new();
+        let result = match self {
+            TimeZone::Offset(offset) => write!(
+                formatted,
+                "{}",
+                time.with_timezone(offset).format_with_items(items.iter())
+            ),
+            TimeZone::Named(timezone) => write!(
+                formatted,
+                "{}",
+                time.with_timezone(timezone).format_with_items(items.iter())
+            ),
+        };
+        // Formatting can still fail for specifiers which need information
+        // that the time does not have.
+        result.map(|()| formatted).map_err(|_| DateFormatError {
+            format: String::from(format),
+        })
+    }
+}
+
+impl FromStr for TimeZone {
+    type Err = ClockError;
+    fn from_str(timezone: &str) -> Result<Self, Self::Err> {
+        let invalid = || ClockError::InvalidTimeZone {
+            timezone: String::from(timezone),
+        };
+        if timezone.eq_ignore_ascii_case("utc") || timezone == "Z" {
+            return Ok(TimeZone::UTC);
+        }
+        let (sign, offset) = match timezone.split_at_checked(1) {
+            Some(("+", offset)) => (1, offset),
+            Some(("-", offset)) => (-1, offset),
+            _ => return timezone.parse().map(TimeZone::Named).map_err(|_| invalid()),
+        };
+        // Minutes are optional, and so is the colon before them.
+        let (hours, minutes) = match offset.split_once(':') {
+            Some(parts) => parts,
+            None if offset.len() == 4 => offset.split_at_checked(2).ok_or_else(invalid)?,
+            None => (offset, "00"),
+        };
+        if hours.len() != 2 || minutes.len() != 2 {
+            return Err(invalid());
+        }
+        match (hours.parse::<i32>(), minutes.parse::<i32>()) {
+            (Ok(hours @ 0..=23), Ok(minutes @ 0..=59)) => {
+                FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60))
+                    .map(TimeZone::Offset)
+                    .ok_or_else(invalid)
+            }
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl TryFrom<String> for TimeZone {
+    type Error = ClockError;
+    fn try_from(timezone: String) -> Result<Self, Self::Error> {
+        timezone.parse()
+    }
+}
+
+impl From<TimeZone> for String {
+    fn from(timezone: TimeZone) -> Self {
+        timezone.to_string()
+    }
+}
+
+impl fmt::Display for TimeZone {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TimeZone::Offset(offset) if offset.local_minus_utc() == 0 => {
+                write!(formatter, "UTC")
+            }
+            TimeZone::Offset(offset) => write!(formatter, "{}", offset),
+            TimeZone::Named(timezone) => write!(formatter, "{}", timezone.name()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use test_log::test;
+
+    #[test]
+    fn clocks_can_be_parsed() {
+        assert_eq!("system".parse(), Ok(Clock::System));
+        assert_eq!(
+            "2024-05-01T09:30:00Z".parse(),
+            Ok(Clock::Fixed(UNIX_EPOCH + Duration::from_secs(1714555800)))
+        );
+        assert!("yesterday".parse::<Clock>().is_err());
+
+        let deterministic_config = Config {
+            deterministic: true,
+            ..Config::default()
+        };
+        assert_eq!(
+            Clock::for_config(&deterministic_config).now(),
+            Clock::DETERMINISTIC_TIME
+        );
+    }
+
+    #[test]
+    fn time_zones_can_be_parsed() {
+        assert_eq!("UTC".parse(), Ok(TimeZone::UTC));
+        assert_eq!(
+            "+09:00".parse(),
+            Ok(TimeZone::Offset(FixedOffset::east_opt(9 * 3600).unwrap()))
+        );
+        assert_eq!(
+            "-0330".parse(),
+            Ok(TimeZone::Offset(
+                FixedOffset::west_opt(3 * 3600 + 30 * 60).unwrap()
+            ))
+        );
+        assert_eq!(
+            "America/New_York".parse(),
+            Ok(TimeZone::Named(chrono_tz::America::New_York))
+        );
+        for invalid in ["Mars/Olympus_Mons", "+9", "+24:00", "09:00", ""] {
+            assert!(
+                invalid.parse::<TimeZone>().is_err(),
+                "Time zone '{}' was parsed, but it should have been invalid",
+                invalid
+            );
+        }
+        assert_eq!("-03:30".parse::<TimeZone>().unwrap().to_string(), "-03:30");
+        assert_eq!(
+            "Asia/Tokyo".parse::<TimeZone>().unwrap().to_string(),
+            "Asia/Tokyo"
+        );
+    }
+
+    #[test]
+    fn times_can_be_formatted() {
+        // 2024-05-01T09:30:00Z, a Wednesday.
+        let time = UNIX_EPOCH + Duration::from_secs(1714555800);
+        let tokyo = "+09:00".parse::<TimeZone>().unwrap();
+        assert_eq!(
+            TimeZone::UTC.format(time, TimeZone::DEFAULT_FORMAT),
+            Ok(String::from("2024-05-01T09:30:00+00:00"))
+        );
+        assert_eq!(
+            tokyo.format(time, TimeZone::DEFAULT_FORMAT),
+            Ok(String::from("2024-05-01T18:30:00+09:00"))
+        );
+        assert_eq!(
+            tokyo.format(time, "%a %-e %b %y, %I:%M %p (%z) %%"),
+            Ok(String::from("Wed 1 May 24, 06:30 PM (+0900) %"))
+        );
+        assert_eq!(
+            TimeZone::UTC.format(time, "%A %d %B %F %T %s"),
+            Ok(String::from(
+                "Wednesday 01 May 2024-05-01 09:30:00 1714555800"
+            ))
+        );
+        assert_eq!(
+            TimeZone::UTC.format(time, "%Q"),
+            Err(DateFormatError {
+                format: String::from("%Q"),
+            })
+        );
+
+        // Named time zones follow daylight saving time.
+        let new_york = "America/New_York".parse::<TimeZone>().unwrap();
+        assert_eq!(
+            new_york.format(time, "%F %T %Z"),
+            Ok(String::from("2024-05-01 05:30:00 EDT"))
+        );
+        let january = UNIX_EPOCH + Duration::from_secs(1704101400);
+        assert_eq!(
+            new_york.format(january, TimeZone::DEFAULT_FORMAT),
+            Ok(String::from("2024-01-01T04:30:00-05:00"))
+        );
+    }
+}
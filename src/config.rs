@@ -0,0 +1,1689 @@
+//! Settings which can be loaded from a TOML file via the `--config` option.
+//!
+//! Every setting has a default, so an empty file (or no file at all) is a
+//! valid configuration. Property names are kebab-case. String values can
+//! refer to environment variables as `${env:NAME}` (write `$${env:` for a
+//! literal `${env:`), and it is an error if any of them are not set. For
+//! example:
+//!
+//! ```toml
+//! base-url = "https://example.com"
+//! path-prefix = "/app"
+//! trusted-proxies = ["127.0.0.1"]
+//!
+//! [content]
+//! invalid-symlinks = "skip"
+//! exclude = ["**/*.swp", "drafts/**"]
+//! mmap-max-bytes = 65536
+//! preload-max-bytes = 8192
+//! file-chunk-size = 131072
+//!
+//! [executables]
+//! clear-env = true
+//! pass-env = ["PATH"]
+//! uid = 1000
+//! gid = 1000
+//! output-limit = 10485760
+//! chunk-size = 4096
+//! max-chunk-size = 262144
+//! output-buffer-bytes = 1048576
+//! output-spill-bytes = 104857600
+//! sendfile = ["/downloads"]
+//! sendfile-paths = ["/srv/downloads"]
+//!
+//! [executables.interpreters]
+//! py = "python3"
+//!
+//! [executables.arguments]
+//! "/weather" = ["--city={query:city}"]
+//!
+//! [executables.cache-ttl]
+//! "/weather" = 300
+//!
+//! [executables.stale-while-revalidate]
+//! "/weather" = 3600
+//!
+//! [executables.cache-vary]
+//! "/weather" = ["accept-language"]
+//!
+//! [executables.output-limits]
+//! "/weather" = 65536
+//!
+//! [executables.env]
+//! API_URL = "https://api.example.com"
+//!
+//! [streaming]
+//! buffer-threshold = 65536
+//!
+//! [streaming.buffer-thresholds]
+//! "text/html" = 1048576
+//!
+//! [timeouts]
+//! client-request = 5
+//! request = 30
+//!
+//! [search]
+//! index-route = "/search-index"
+//! query-route = "/_search"
+//!
+//! [reload]
+//! route = "/_reload"
+//! secret = "${env:RELOAD_SECRET}"
+//!
+//! [status]
+//! route = "/_status"
+//!
+//! [time]
+//! timezone = "Asia/Tokyo"
+//!
+//! [site]
+//! title = "My Website"
+//!
+//! [tasks."/_tasks/fetch-feed"]
+//! schedule = "*/15 * * * *"
+//! output = "/srv/feeds/latest.xml"
+//!
+//! [routes."/news"]
+//! fallback = "/news-unavailable"
+//! cache-control = "no-cache"
+//! etag = "strong"
+//!
+//! [routes."/api".cors]
+//! allowed-origins = ["https://app.example.com"]
+//! allowed-headers = ["Authorization"]
+//! max-age = 600
+//!
+//! [routes."/assets"]
+//! cache-control = "public, max-age=31536000, immutable"
+//! surrogate-control = "max-age=86400"
+//!
+//! [routes."/api/health"]
+//! log-level = "error"
+//!
+//! [routes."/api/reports"]
+//! max-concurrent-requests = 4
+//! queue-timeout-ms = 250
+//! retry-after = 5
+//!
+//! [routes."/api/search"]
+//! log-level = "trace"
+//! body-trace-interval = 100
+//!
+//! [routes."/news".site]
+//! section = "News"
+//!
+//! [profiles.dev]
+//! render-trace = true
+//! dev = true
+//! content = { include-drafts = true }
+//! routes."/" = { cache-control = "no-store" }
+//!
+//! [profiles.test]
+//! deterministic = true
+//! executables = { fixtures = { mode = "replay", directory = "tests/fixtures" } }
+//! ```
+
+use crate::clock::{Clock, TimeZone};
+use crate::content::{fill_missing, MediaType, Route};
+use crate::scheduler::Schedule;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::fs;
+use std::io;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum ConfigError {
+    #[error("Unable to read config file '{}'.", .path.display())]
+    ReadError { path: PathBuf, source: io::Error },
+
+    #[error("Config file '{}' is invalid: {}", .path.display(), .source)]
+    ParseError {
+        path: PathBuf,
+        source: toml::de::Error,
+    },
+
+    #[error(
+        "Config file '{}' has no profile named '{}'.",
+        .path.display(),
+        .profile,
+    )]
+    UnknownProfile { path: PathBuf, profile: String },
+
+    #[error("Config file '{}' is invalid: {}", .path.display(), .source)]
+    EnvironmentVariable {
+        path: PathBuf,
+        source: EnvironmentVariableError,
+    },
+}
+
+#[derive(Error, Debug)]
+pub enum EnvironmentVariableError {
+    #[error("Environment variable `{}` is not set.", .name)]
+    Missing { name: String },
+
+    #[error(
+        "Environment variable `{}` cannot be used here (only those named like `{}*` can).",
+        .name,
+        .allowed_prefix
+    )]
+    NotAllowed {
+        name: String,
+        allowed_prefix: &'static str,
+    },
+}
+
+impl EnvironmentVariableError {
+    pub fn name(&self) -> &str {
+        match self {
+            EnvironmentVariableError::Missing { name }
+            | EnvironmentVariableError::NotAllowed { name, .. } => name,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Config {
+    /// The absolute URL where the site is hosted (e.g.
+    /// `https://example.com`). Renders need this to produce absolute links,
+    /// since the server cannot know how it is reached from the outside (the
+    /// `Host` header is chosen by clients, so it is only used for virtual
+    /// hosts and when forwarded by `trusted-proxies`).
+    #[serde(deserialize_with = "deserialize_base_url")]
+    pub base_url: Option<String>,
+
+    /// The URL path that the site is served under when it is behind a
+    /// reverse proxy (e.g. `/app`). Request paths have it removed before
+    /// routing, and renders can find it in `request.http.path-prefix` to build
+    /// links.
+    pub path_prefix: Option<String>,
+
+    /// Addresses of reverse proxies which are trusted to describe the
+    /// original request via `X-Forwarded-*` headers. Those headers are
+    /// ignored when requests come from anywhere else. When proxies are
+    /// chained, list each of them so that values they append can be told
+    /// apart from values sent by clients.
+    pub trusted_proxies: Vec<IpAddr>,
+
+    /// How the content directory is loaded.
+    pub content: ContentConfig,
+
+    /// How executables from the content directory are run.
+    pub executables: ExecutablesConfig,
+
+    /// How response bodies are delivered to HTTP clients.
+    pub streaming: StreamingConfig,
+
+    /// Limits on how long clients and responses can take.
+    pub timeouts: TimeoutsConfig,
+
+    /// Full-text search over the site's content.
+    pub search: SearchConfig,
+
+    /// Reloading content while the server is running.
+    pub reload: ReloadConfig,
+
+    /// Reporting on the server's content while it is running.
+    pub status: StatusConfig,
+
+    /// The clock and time zone used by date helpers in templates.
+    pub time: TimeConfig,
+
+    /// Whether to append a tree of the routes that each HTML page included
+    /// via the `get` helper (with how long each took to render) to the page
+    /// as an HTML comment. This is meant for finding slow content during
+    /// development. The same tree is always logged at the `debug` level.
+    pub render_trace: bool,
+
+    /// Whether to render the same way on every machine and every run, so
+    /// that output can be compared against snapshots. Server info in render
+    /// data gets placeholder values and executables find
+    /// `OPERATOR_DETERMINISTIC=1` in their environment (so they can avoid
+    /// things like timestamps).
+    pub deterministic: bool,
+
+    /// Whether failed renders respond with a page describing what went wrong
+    /// (the chain of errors, the template or executable that failed, and the
+    /// render data, with anything that looks secret redacted) instead of the
+    /// usual error response. This is meant for authoring content, and should
+    /// never be enabled for a public site.
+    pub dev: bool,
+
+    /// Arbitrary data which is available to all content as the `site`
+    /// property of its render data. Routes can add to or override it via
+    /// their own `site` settings.
+    pub site: serde_json::Map<String, serde_json::Value>,
+
+    /// Content to render periodically while serving, keyed by route (which
+    /// may be internal, like `/_tasks/fetch-feed`). This is useful for
+    /// executables that fetch data or warm caches without an external cron.
+    /// Each run of an executable with a `cache-ttl` replaces its cached
+    /// output. Templates can also be scheduled with `task` in their front
+    /// matter. Tasks only run for the default content directory, not virtual
+    /// hosts.
+    pub tasks: BTreeMap<Route, TaskConfig>,
+
+    /// Settings for specific parts of the site. Each key is a route, and its
+    /// settings also apply to all routes beneath it. When multiple entries
+    /// apply to a route the most specific one wins.
+    pub routes: BTreeMap<Route, RouteConfig>,
+
+    /// Named sets of settings (like `dev` or `production`) which override
+    /// the rest of the file when selected with `--profile` or
+    /// `OPERATOR_PROFILE`. Tables are merged, so a profile only needs the
+    /// settings that differ.
+    #[serde(skip_serializing)]
+    pub profiles: BTreeMap<String, toml::Table>,
+}
+
+impl Config {
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, ConfigError> {
+        Config::from_file_with_profile(path, None)
+    }
+
+    /// Reads a config file with the settings of `profile` (one of its
+    /// `[profiles]`) applied atop the rest.
+    pub fn from_file_with_profile<P: AsRef<Path>>(
+        path: P,
+        profile: Option<&str>,
+    ) -> Result<Self, ConfigError> {
+        let path = path.as_ref();
+        let source = fs::read_to_string(path).map_err(|source| ConfigError::ReadError {
+            path: PathBuf::from(path),
+            source,
+        })?;
+        let parse_error = |source| ConfigError::ParseError {
+            path: PathBuf::from(path),
+            source,
+        };
+        // Parsing directly keeps line numbers in error messages.
+        if profile.is_none() && !source.contains(ENVIRONMENT_VARIABLE_START) {
+            return toml::from_str(&source).map_err(parse_error);
+        }
+
+        let mut settings = toml::from_str::<toml::Table>(&source).map_err(parse_error)?;
+        if let Some(profile) = profile {
+            let overrides = settings
+                .get("profiles")
+                .and_then(|profiles| profiles.get(profile))
+                .and_then(toml::Value::as_table)
+                .cloned()
+                .ok_or_else(|| ConfigError::UnknownProfile {
+                    path: PathBuf::from(path),
+                    profile: String::from(profile),
+                })?;
+            merge_tables(&mut settings, overrides);
+        }
+        for (_, value) in settings.iter_mut() {
+            interpolate_toml_value(value).map_err(|source| ConfigError::EnvironmentVariable {
+                path: PathBuf::from(path),
+                source,
+            })?;
+        }
+        settings.try_into().map_err(parse_error)
+    }
+
+    /// These settings in the format of a config file, with secrets
+    /// redacted. This includes defaults, so it shows exactly what is in
+    /// effect.
+    pub fn to_toml(&self) -> Result<String, toml::ser::Error> {
+        toml::to_string_pretty(self)
+    }
+
+    /// The most verbose `log-level` of any route.
+    pub fn max_route_log_level(&self) -> log::LevelFilter {
+        self.routes
+            .values()
+            .filter_map(|route_config| route_config.log_level)
+            .max()
+            .unwrap_or(log::LevelFilter::Off)
+    }
+
+    /// Gets the effective settings for `route` by merging all applicable
+    /// `[routes]` entries.
+    pub fn route_config(&self, route: &Route) -> RouteConfig {
+        // Keys are sorted, and a route always sorts before its descendants,
+        // so more specific settings are merged last.
+        self.routes
+            .iter()
+            .filter(|(prefix, _)| route.is_within(prefix))
+            .fold(RouteConfig::default(), |merged, (_, route_config)| {
+                merged.merge(route_config)
+            })
+    }
+
+    /// The most specific `[routes]` entry which limits concurrent requests to
+    /// `route`. Requests to all routes within it share that limit.
+    pub fn concurrency_limited_route(&self, route: &Route) -> Option<&Route> {
+        // Descendants sort after their ancestors, so search from the end.
+        self.routes
+            .iter()
+            .rev()
+            .find(|(prefix, route_config)| {
+                route.is_within(prefix) && route_config.max_concurrent_requests.is_some()
+            })
+            .map(|(prefix, _)| prefix)
+    }
+
+    /// The `site` render data for requests to `route` (or for renders which
+    /// are not associated with a route).
+    pub fn site_data(&self, route: Option<&Route>) -> serde_json::Map<String, serde_json::Value> {
+        match route {
+            Some(route) => merge_site_data(self.route_config(route).site, &self.site),
+            None => self.site.clone(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct RouteConfig {
+    /// Content to render instead if rendering this route fails. Fallbacks can
+    /// have their own fallbacks. Templates can override this with `fallback`
+    /// in their front matter.
+    pub fallback: Option<Route>,
+
+    /// Added to the top-level `site` data for this part of the site. Nested
+    /// tables are merged rather than replaced.
+    pub site: serde_json::Map<String, serde_json::Value>,
+
+    /// The `Cache-Control` header of successful responses. Templates can
+    /// override this with `cache-control` in their front matter.
+    pub cache_control: Option<String>,
+
+    /// Successful responses get an `Expires` header this many seconds in the
+    /// future. Templates can override this with `expires` in their front
+    /// matter.
+    pub expires: Option<u64>,
+
+    /// The `Surrogate-Control` header of successful responses (used by some
+    /// CDNs). Templates can override this with `surrogate-control` in their
+    /// front matter.
+    pub surrogate_control: Option<String>,
+
+    /// Buffer entire responses to compute an `ETag` header, so that clients
+    /// which already have the content get a `304 Not Modified` instead of the
+    /// body. Templates can override this with `etag` in their front matter.
+    pub etag: Option<EntityTagStrength>,
+
+    /// The HTTP status code of successful responses, instead of `200` (e.g.
+    /// `410` for content that explains why something is gone). Templates can
+    /// override this with `status` in their front matter.
+    #[serde(deserialize_with = "deserialize_status")]
+    pub status: Option<u16>,
+
+    /// Allows scripts on other origins to request content (see
+    /// [CORS](https://developer.mozilla.org/en-US/docs/Web/HTTP/CORS)). More
+    /// specific routes replace these settings entirely rather than merging
+    /// with them.
+    pub cors: Option<CorsConfig>,
+
+    /// How verbose logging is for requests to this part of the site, which
+    /// can be more or less verbose than the `--verbose` level (e.g. `"debug"`
+    /// to investigate one endpoint, or `"error"` to quiet a noisy one).
+    pub log_level: Option<log::LevelFilter>,
+
+    /// When response bodies are logged at the trace level, only log those of
+    /// one in this many responses.
+    pub body_trace_interval: Option<u64>,
+
+    /// How many requests to this part of the site can be handled at once.
+    /// All routes beneath it share the limit, so a slow executable here
+    /// cannot tie up the server for the rest of the site. Requests over the
+    /// limit wait for up to `queue-timeout-ms` and then get a `503`.
+    pub max_concurrent_requests: Option<usize>,
+
+    /// How long requests over `max-concurrent-requests` wait for another
+    /// request to finish, in milliseconds. Defaults to 500.
+    pub queue_timeout_ms: Option<u64>,
+
+    /// The `Retry-After` header (in seconds) of responses to requests which
+    /// waited too long for `max-concurrent-requests`. Defaults to 1.
+    pub retry_after: Option<u64>,
+}
+
+impl RouteConfig {
+    /// Combines two sets of settings, preferring those from `overrides`.
+    fn merge(self, overrides: &RouteConfig) -> RouteConfig {
+        RouteConfig {
+            fallback: overrides.fallback.clone().or(self.fallback),
+            site: merge_site_data(overrides.site.clone(), &self.site),
+            cache_control: overrides.cache_control.clone().or(self.cache_control),
+            expires: overrides.expires.or(self.expires),
+            surrogate_control: overrides
+                .surrogate_control
+                .clone()
+                .or(self.surrogate_control),
+            etag: overrides.etag.or(self.etag),
+            status: overrides.status.or(self.status),
+            cors: overrides.cors.clone().or(self.cors),
+            log_level: overrides.log_level.or(self.log_level),
+            body_trace_interval: overrides.body_trace_interval.or(self.body_trace_interval),
+            max_concurrent_requests: overrides
+                .max_concurrent_requests
+                .or(self.max_concurrent_requests),
+            queue_timeout_ms: overrides.queue_timeout_ms.or(self.queue_timeout_ms),
+            retry_after: overrides.retry_after.or(self.retry_after),
+        }
+    }
+}
+
+/// Stands in for secrets when settings are serialized (e.g. to show the
+/// effective config).
+pub(crate) const REDACTED: &str = "<redacted>";
+
+fn serialize_redacted<S: Serializer>(
+    secret: &Option<String>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    secret.as_ref().map(|_| REDACTED).serialize(serializer)
+}
+
+fn serialize_redacted_values<S: Serializer>(
+    map: &BTreeMap<String, String>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_map(map.keys().map(|key| (key, REDACTED)))
+}
+
+/// Accepts status codes from `100` to `599` (the classes defined by RFC 9110),
+/// so that typos are reported when settings are loaded rather than when a
+/// response is sent.
+pub(crate) fn deserialize_status<'de, D>(deserializer: D) -> Result<Option<u16>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let status = u64::deserialize(deserializer)?;
+    match u16::try_from(status) {
+        Ok(status @ 100..=599) => Ok(Some(status)),
+        _ => Err(serde::de::Error::custom(format!(
+            "`status` must be an HTTP status code from 100 to 599, but it was `{}`",
+            status,
+        ))),
+    }
+}
+
+/// How references to environment variables begin (they end with `}`).
+const ENVIRONMENT_VARIABLE_START: &str = "${env:";
+
+/// Only environment variables with names starting with this can be used in
+/// front matter, since front matter ends up in render data (and so possibly
+/// in rendered output).
+pub(crate) const PUBLIC_ENVIRONMENT_VARIABLE_PREFIX: &str = "OPERATOR_PUBLIC_";
+
+/// Replaces `${env:NAME}` in `input` with the value of the environment
+/// variable `NAME`, so that secrets and other per-environment values can
+/// stay out of files. `$${env:` is a literal `${env:`, and anything else
+/// (including `${NAME}`) is left alone.
+pub(crate) fn interpolate_environment_variables(
+    input: &str,
+) -> Result<String, EnvironmentVariableError> {
+    interpolate_variables(input, |name| {
+        env::var(name).map_err(|_| EnvironmentVariableError::Missing {
+            name: String::from(name),
+        })
+    })
+}
+
+/// Like [`interpolate_environment_variables`], but only allows variables
+/// named with [`PUBLIC_ENVIRONMENT_VARIABLE_PREFIX`].
+pub(crate) fn interpolate_public_environment_variables(
+    input: &str,
+) -> Result<String, EnvironmentVariableError> {
+    interpolate_variables(input, |name| {
+        if !name.starts_with(PUBLIC_ENVIRONMENT_VARIABLE_PREFIX) {
+            return Err(EnvironmentVariableError::NotAllowed {
+                name: String::from(name),
+                allowed_prefix: PUBLIC_ENVIRONMENT_VARIABLE_PREFIX,
+            });
+        }
+        env::var(name).map_err(|_| EnvironmentVariableError::Missing {
+            name: String::from(name),
+        })
+    })
+}
+
+fn interpolate_variables(
+    input: &str,
+    lookup: impl Fn(&str) -> Result<String, EnvironmentVariableError>,
+) -> Result<String, EnvironmentVariableError> {
+    let mut output = String::with_capacity(input.len());
+    let mut rest = input;
+    while let Some(start) = rest.find(ENVIRONMENT_VARIABLE_START) {
+        if rest[..start].ends_with('$') {
+            output.push_str(&rest[..start - 1]);
+            output.push_str(ENVIRONMENT_VARIABLE_START);
+            rest = &rest[start + ENVIRONMENT_VARIABLE_START.len()..];
+            continue;
+        }
+        output.push_str(&rest[..start]);
+        let after_start = &rest[start + ENVIRONMENT_VARIABLE_START.len()..];
+        let name = after_start
+            .find('}')
+            .map(|end| &after_start[..end])
+            .filter(|name| is_variable_name(name));
+        match name {
+            Some(name) => {
+                output.push_str(&lookup(name)?);
+                rest = &after_start[name.len() + 1..];
+            }
+            // Anything else is left alone.
+            None => {
+                output.push_str(ENVIRONMENT_VARIABLE_START);
+                rest = after_start;
+            }
+        }
+    }
+    output.push_str(rest);
+    Ok(output)
+}
+
+fn is_variable_name(name: &str) -> bool {
+    name.chars()
+        .next()
+        .is_some_and(|first| first.is_ascii_alphabetic() || first == '_')
+        && name
+            .chars()
+            .all(|character| character.is_ascii_alphanumeric() || character == '_')
+}
+
+fn interpolate_toml_value(value: &mut toml::Value) -> Result<(), EnvironmentVariableError> {
+    match value {
+        toml::Value::String(string) => *string = interpolate_environment_variables(string)?,
+        toml::Value::Array(values) => {
+            for value in values {
+                interpolate_toml_value(value)?;
+            }
+        }
+        toml::Value::Table(table) => {
+            for (_, value) in table.iter_mut() {
+                interpolate_toml_value(value)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Deeply merges TOML `overrides` into `base`.
+fn merge_tables(base: &mut toml::Table, overrides: toml::Table) {
+    for (key, value) in overrides {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(base)), toml::Value::Table(overrides)) => {
+                merge_tables(base, overrides)
+            }
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Deeply merges `overrides` atop `base`.
+fn merge_site_data(
+    overrides: serde_json::Map<String, serde_json::Value>,
+    base: &serde_json::Map<String, serde_json::Value>,
+) -> serde_json::Map<String, serde_json::Value> {
+    let mut merged = overrides;
+    for (key, base_value) in base {
+        match merged.get_mut(key) {
+            Some(existing) => fill_missing(existing, base_value),
+            None => {
+                merged.insert(key.clone(), base_value.clone());
+            }
+        }
+    }
+    merged
+}
+
+/// Checks that `{query:name}` placeholders in executable arguments cannot
+/// become options, because they are either the value of an option (like
+/// `--city={query:city}`) or come after a `--` argument.
+fn check_argument_placeholders(arguments: &[String]) -> Result<(), String> {
+    let mut after_separator = false;
+    for argument in arguments {
+        if let Some(placeholder_start) = argument.find("{query:") {
+            let is_option_value = argument.starts_with("--")
+                && argument
+                    .find('=')
+                    .is_some_and(|equals| equals < placeholder_start);
+            if !after_separator && !is_option_value {
+                return Err(format!(
+                    "executable argument `{}` has a query parameter placeholder, which must be \
+                    the value of an option (like `--name={{query:name}}`) or come after a `--` \
+                    argument",
+                    argument,
+                ));
+            }
+        }
+        after_separator = after_separator || argument == "--";
+    }
+    Ok(())
+}
+
+fn deserialize_arguments<'de, D>(deserializer: D) -> Result<BTreeMap<Route, Vec<String>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let arguments = BTreeMap::<Route, Vec<String>>::deserialize(deserializer)?;
+    for route_arguments in arguments.values() {
+        check_argument_placeholders(route_arguments).map_err(serde::de::Error::custom)?;
+    }
+    Ok(arguments)
+}
+
+pub(crate) fn deserialize_front_matter_arguments<'de, D>(
+    deserializer: D,
+) -> Result<Option<Vec<String>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let arguments = Vec::<String>::deserialize(deserializer)?;
+    check_argument_placeholders(&arguments).map_err(serde::de::Error::custom)?;
+    Ok(Some(arguments))
+}
+
+/// Checks that `base_url` is an absolute `http` or `https` URL, which can
+/// have a path but not a query string or fragment (since paths are appended
+/// to it).
+pub fn parse_base_url(base_url: &str) -> Result<String, String> {
+    let invalid = || {
+        format!(
+            "`base-url` must be an absolute http or https URL like `https://example.com`, but it \
+            was `{}`",
+            base_url,
+        )
+    };
+    let (scheme, rest) = base_url.split_once("://").ok_or_else(invalid)?;
+    let authority = rest.split('/').next().unwrap_or_default();
+    if !(scheme.eq_ignore_ascii_case("http") || scheme.eq_ignore_ascii_case("https"))
+        || authority.is_empty()
+        || authority.contains('@')
+        || rest.contains(|character: char| {
+            character.is_whitespace() || character.is_control() || "?#\\".contains(character)
+        })
+    {
+        return Err(invalid());
+    }
+    Ok(String::from(base_url))
+}
+
+fn deserialize_base_url<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let base_url = String::deserialize(deserializer)?;
+    parse_base_url(&base_url)
+        .map(Some)
+        .map_err(serde::de::Error::custom)
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct ContentConfig {
+    /// Files and directories whose names start with this are skipped entirely
+    /// when loading the content directory. An empty string disables this.
+    pub hidden_prefix: String,
+
+    /// Routes with a path component starting with this can be used by other
+    /// content (e.g. as partials or via the `get` helper) but are not
+    /// accessible to HTTP clients. An empty string disables this.
+    pub internal_prefix: String,
+
+    /// Glob patterns (relative to the content directory) for files to load.
+    /// If empty, all files are loaded.
+    pub include: Vec<String>,
+
+    /// Glob patterns (relative to the content directory) for files and
+    /// directories to skip. These take precedence over `include`.
+    pub exclude: Vec<String>,
+
+    /// What to do with symlinks that resolve outside of the content directory
+    /// or form cycles.
+    pub invalid_symlinks: InvalidSymlinkAction,
+
+    /// Skip (and log) files with unknown media types or unsupported names
+    /// instead of refusing to load the content directory.
+    pub lenient: bool,
+
+    /// Serve templates whose front matter marks them as drafts (`draft:
+    /// true`) or whose `publish-date` is in the future. Otherwise they are
+    /// treated as if they did not exist.
+    pub include_drafts: bool,
+
+    /// Whether rendering a handlebars template fails when it references
+    /// missing data. Templates can override this with `strict` in their front
+    /// matter.
+    pub strict_templates: bool,
+
+    /// Values to use for render data which would otherwise be missing in
+    /// handlebars templates. Templates can add their own with `defaults` in
+    /// their front matter.
+    pub template_defaults: serde_json::Map<String, serde_json::Value>,
+
+    /// Static files up to this many bytes are memory-mapped when they are
+    /// loaded, so serving them does not need to read the file. This suits
+    /// small files which are requested often. Mapped files must not be
+    /// truncated or rewritten in place while they are served (replace them
+    /// by renaming instead). Zero disables this.
+    pub mmap_max_bytes: u64,
+
+    /// Static files up to this many bytes are read into memory when they are
+    /// loaded and served from there, trading memory for latency (e.g. for
+    /// sites with many icons and stylesheets). These take precedence over
+    /// `mmap-max-bytes`. Zero disables this.
+    pub preload_max_bytes: u64,
+
+    /// Static files which are not kept in memory are read and sent in chunks
+    /// of this many bytes. Larger chunks mean fewer reads for big files, but
+    /// more memory for each response in flight.
+    pub file_chunk_size: u64,
+}
+
+impl Default for ContentConfig {
+    fn default() -> Self {
+        ContentConfig {
+            hidden_prefix: String::from("."),
+            internal_prefix: String::from("_"),
+            include: Vec::new(),
+            exclude: Vec::new(),
+            invalid_symlinks: InvalidSymlinkAction::default(),
+            lenient: false,
+            include_drafts: false,
+            strict_templates: true,
+            template_defaults: serde_json::Map::new(),
+            mmap_max_bytes: 0,
+            preload_max_bytes: 0,
+            file_chunk_size: 65536,
+        }
+    }
+}
+
+/// Content is always reloaded when the server receives `SIGHUP`. These
+/// settings also allow reloading it remotely (e.g. from a deploy hook).
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct ReloadConfig {
+    /// Where `POST` requests reload content. Other methods are handled as
+    /// usual, so this can be an internal route to keep it out of the way.
+    pub route: Option<Route>,
+
+    /// Reload requests must have an `Authorization: Bearer <secret>` header.
+    /// The reload route is disabled unless this is set.
+    #[serde(serialize_with = "serialize_redacted")]
+    pub secret: Option<String>,
+}
+
+impl ReloadConfig {
+    /// The reload route, unless remote reloading is disabled.
+    pub fn enabled_route(&self) -> Option<&Route> {
+        match &self.secret {
+            Some(secret) if !secret.is_empty() => self.route.as_ref(),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct StatusConfig {
+    /// Where to serve a JSON report on the loaded content: how many files of
+    /// each kind there are, their total size, how long loading took, and how
+    /// many files are held open (compared to the process's limit). This can
+    /// be an internal route to keep it out of the way.
+    pub route: Option<Route>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct TimeConfig {
+    /// Where `{{now}}` gets the current time: `"system"`, or a timestamp like
+    /// `"2024-05-01T09:30:00Z"` to stop the clock there (e.g. for tests). In
+    /// [deterministic](Config::deterministic) mode the system clock is
+    /// replaced by one stopped at the Unix epoch.
+    pub clock: Clock,
+
+    /// The time zone that date helpers show times in: `"UTC"`, an offset from
+    /// UTC like `"+09:00"`, or a name from the IANA time zone database like
+    /// `"Asia/Tokyo"` (which follows daylight saving time).
+    pub timezone: TimeZone,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct TaskConfig {
+    /// When to run the task, as a cron expression in UTC (e.g. `"0 * * * *"`
+    /// for the start of every hour). Shortcuts like `"@daily"` also work.
+    pub schedule: Schedule,
+
+    /// A file to also write the task's rendered output to. It is replaced
+    /// atomically, so readers never see partial output.
+    #[serde(default)]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct SearchConfig {
+    /// Where to serve a JSON search index for client-side search. The index
+    /// covers the HTML and plain text representations of all public
+    /// templates and static files, and is built when the content directory
+    /// is loaded. Executables are not indexed themselves, but templates are
+    /// rendered to index them, so any executables they `get` are run then
+    /// (as if for a request without query parameters or headers).
+    pub index_route: Option<Route>,
+
+    /// Where to serve search results. Requests to this route search for the
+    /// words in the `q` query parameter and get a JSON object with a
+    /// `results` list (each result has a `route`, `title`, and `score`).
+    pub query_route: Option<Route>,
+
+    /// The most results `query-route` responds with.
+    pub max_results: usize,
+}
+
+impl SearchConfig {
+    /// Whether a search index needs to be built.
+    pub fn is_enabled(&self) -> bool {
+        self.index_route.is_some() || self.query_route.is_some()
+    }
+}
+
+impl Default for SearchConfig {
+    fn default() -> Self {
+        SearchConfig {
+            index_route: None,
+            query_route: None,
+            max_results: 20,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct ExecutablesConfig {
+    /// Start executables with an empty environment instead of inheriting
+    /// Operator's. Variables named in `pass-env` are still inherited.
+    pub clear_env: bool,
+
+    /// Environment variables to inherit when `clear-env` is set.
+    pub pass_env: Vec<String>,
+
+    /// Environment variables to set for executables. These take precedence
+    /// over inherited variables.
+    #[serde(serialize_with = "serialize_redacted_values")]
+    pub env: BTreeMap<String, String>,
+
+    /// Interpreters for content files which are not executable, keyed by
+    /// their final extension. For example, with `py = "python3"` a file named
+    /// `page.html.py` is run as `python3 page.html.py` even if it does not
+    /// have the executable bit set. Interpreters can include arguments
+    /// (separated by whitespace).
+    pub interpreters: BTreeMap<String, String>,
+
+    /// Command-line arguments for executables, keyed by their route.
+    /// Arguments can contain placeholders which are filled in from the
+    /// request: `{route}` is the request route and `{query:name}` is the
+    /// value of the `name` query parameter (both are empty if missing).
+    /// Query parameters are chosen by clients, so `{query:name}` may only be
+    /// used as the value of an option (like `--city={query:city}`) or after a
+    /// `--` argument; otherwise a client could pass options of its own.
+    /// Executables can override this with `arguments` in their front matter
+    /// (see [`ExecutableFrontMatter`](crate::content::ExecutableFrontMatter)).
+    #[serde(deserialize_with = "deserialize_arguments")]
+    pub arguments: BTreeMap<Route, Vec<String>>,
+
+    /// How many seconds to cache the output of executables for, keyed by
+    /// their route. Within this time, requests with the same query parameters
+    /// (and `cache-vary` headers) get the previous output instead of running
+    /// the executable again. Only output from successful runs is cached, and
+    /// output for requests with an `Authorization` or `Cookie` header is not
+    /// cached unless that header is listed in `cache-vary`.
+    pub cache_ttl: BTreeMap<Route, u64>,
+
+    /// Names of request headers that the output of cached executables depends
+    /// on, keyed by route. Output is cached separately for each combination
+    /// of their values.
+    pub cache_vary: BTreeMap<Route, Vec<String>>,
+
+    /// How many seconds past its `cache-ttl` cached output can still be
+    /// served, keyed by route. Stale output is served immediately while the
+    /// executable is run again in the background to refresh it.
+    pub stale_while_revalidate: BTreeMap<Route, u64>,
+
+    /// The most outputs to cache for each executable (one per distinct set
+    /// of query parameters and `cache-vary` header values). Once there are
+    /// this many the oldest output is dropped to make room for new ones.
+    pub cache_max_entries: usize,
+
+    /// The most bytes that any executable may write to standard output.
+    /// Executables which write more are killed and their render fails, so a
+    /// buggy script cannot produce an unbounded response (or exhaust memory
+    /// when its output is included in another template).
+    pub output_limit: Option<u64>,
+
+    /// Overrides `output-limit` for specific executables, keyed by route.
+    pub output_limits: BTreeMap<Route, u64>,
+
+    /// Routes of executables which may ask for a file to be sent as their
+    /// response, instead of copying it to standard output themselves. To do
+    /// that the first line of their output must be `X-Sendfile: <path>`; the
+    /// rest of their output is discarded. Relative paths are resolved against
+    /// the content directory, and the file must be within it or one of
+    /// `sendfile-paths`.
+    pub sendfile: Vec<Route>,
+
+    /// Directories outside of the content directory which executables listed
+    /// in `sendfile` may send files from.
+    pub sendfile_paths: Vec<PathBuf>,
+
+    /// Run executables as this user ID instead of Operator's (unix only).
+    /// Operator must have permission to switch users, e.g. by running as root.
+    pub uid: Option<u32>,
+
+    /// Run executables as this group ID instead of Operator's (unix only).
+    pub gid: Option<u32>,
+
+    /// A command to run executables within, such as a sandboxing tool. The
+    /// path of the executable is appended to it, and `{working-directory}` in
+    /// any argument is replaced with the executable's working directory. For
+    /// example:
+    ///
+    /// ```toml
+    /// sandbox = ["bwrap", "--ro-bind", "/", "/", "--chdir", "{working-directory}", "--"]
+    /// ```
+    pub sandbox: Vec<String>,
+
+    /// How many bytes of standard output are read at a time to begin with.
+    /// Executables which keep filling the buffer get a bigger one, so this
+    /// mostly matters for those which write a little at a time.
+    pub chunk_size: usize,
+
+    /// The largest that the buffer for reading standard output can grow.
+    pub max_chunk_size: usize,
+
+    /// Standard output is read ahead of the client, keeping up to this many
+    /// bytes per response in memory, so that slow clients do not leave
+    /// executables blocked (and holding on to resources) until they catch
+    /// up. Zero disables this, so executables wait whenever the client does.
+    pub output_buffer_bytes: usize,
+
+    /// Once `output-buffer-bytes` is full, up to this many more bytes of
+    /// output per response are written to a temporary file. Zero disables
+    /// this.
+    pub output_spill_bytes: u64,
+
+    /// Records the output of executables to a directory, or replays it from
+    /// there instead of running them. This makes tests of content
+    /// repeatable, even on machines which cannot run the executables.
+    pub fixtures: Option<FixturesConfig>,
+}
+
+impl Default for ExecutablesConfig {
+    fn default() -> Self {
+        ExecutablesConfig {
+            clear_env: false,
+            pass_env: Vec::new(),
+            env: BTreeMap::new(),
+            interpreters: BTreeMap::new(),
+            arguments: BTreeMap::new(),
+            cache_ttl: BTreeMap::new(),
+            cache_vary: BTreeMap::new(),
+            stale_while_revalidate: BTreeMap::new(),
+            cache_max_entries: 1000,
+            output_limit: None,
+            output_limits: BTreeMap::new(),
+            sendfile: Vec::new(),
+            sendfile_paths: Vec::new(),
+            uid: None,
+            gid: None,
+            sandbox: Vec::new(),
+            chunk_size: 4096,
+            max_chunk_size: 65536,
+            output_buffer_bytes: 0,
+            output_spill_bytes: 0,
+            fixtures: None,
+        }
+    }
+}
+
+/// Where executable output is recorded to or replayed from.
+///
+/// Each recording is keyed by the executable's route, its arguments, and its
+/// render data, so replaying only works for renders that match one which was
+/// recorded. Render data includes server info, which differs between
+/// machines unless rendering is [deterministic](Config::deterministic). Only
+/// output from successful runs is recorded.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct FixturesConfig {
+    pub mode: FixturesMode,
+
+    /// The directory that recordings are kept in. It is created if needed.
+    pub directory: PathBuf,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FixturesMode {
+    /// Run executables as usual and save their output.
+    Record,
+
+    /// Use saved output instead of running executables. Renders for which
+    /// nothing was recorded fail.
+    Replay,
+}
+
+/// What to do when loading the content directory encounters a symlink that
+/// cannot be safely followed.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum InvalidSymlinkAction {
+    /// Fail to load the content directory.
+    #[default]
+    Reject,
+
+    /// Log a warning and leave the symlink out of the content directory.
+    Skip,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct CorsConfig {
+    /// Origins (like `https://example.com`) which may request content. `*`
+    /// allows any origin.
+    pub allowed_origins: Vec<String>,
+
+    /// Request methods which other origins may use.
+    pub allowed_methods: Vec<String>,
+
+    /// Request headers which other origins may send (beyond those that
+    /// browsers always allow).
+    pub allowed_headers: Vec<String>,
+
+    /// How many seconds browsers may cache the response to a preflight
+    /// request.
+    pub max_age: Option<u64>,
+}
+
+impl CorsConfig {
+    pub fn allows_any_origin(&self) -> bool {
+        self.allowed_origins.iter().any(|allowed| allowed == "*")
+    }
+
+    pub fn allows_origin(&self, origin: &str) -> bool {
+        self.allows_any_origin() || self.allowed_origins.iter().any(|allowed| allowed == origin)
+    }
+
+    pub fn allows_method(&self, method: &str) -> bool {
+        self.allowed_methods
+            .iter()
+            .any(|allowed| allowed.eq_ignore_ascii_case(method))
+    }
+
+    pub fn allows_header(&self, header_name: &str) -> bool {
+        self.allowed_headers
+            .iter()
+            .any(|allowed| allowed.eq_ignore_ascii_case(header_name))
+    }
+}
+
+impl Default for CorsConfig {
+    fn default() -> Self {
+        CorsConfig {
+            allowed_origins: Vec::new(),
+            allowed_methods: vec![String::from("GET")],
+            allowed_headers: Vec::new(),
+            max_age: None,
+        }
+    }
+}
+
+/// See [IETF RFC 7232](https://tools.ietf.org/html/rfc7232#section-2.1).
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum EntityTagStrength {
+    /// The `ETag` changes whenever any byte of the response changes.
+    Strong,
+
+    /// The same hash as `strong`, but marked as weak (`W/"..."`) so that
+    /// intermediaries which transform responses may keep it.
+    Weak,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct StreamingConfig {
+    /// Response bodies are buffered in memory up to this many bytes before
+    /// anything is sent to the client. If rendering fails before the body
+    /// exceeds this size the client receives a proper error response instead
+    /// of a truncated `200`. Zero disables buffering.
+    pub buffer_threshold: usize,
+
+    /// Overrides `buffer-threshold` for specific media types.
+    pub buffer_thresholds: HashMap<MediaType, usize>,
+
+    /// The level at which streaming failures are logged. When streaming
+    /// fails after the response has started, the connection is dropped
+    /// without completing the response, so that clients can tell that the
+    /// body is incomplete.
+    pub stream_error_log_level: log::Level,
+}
+
+impl StreamingConfig {
+    pub fn buffer_threshold_for(&self, media_type: &MediaType) -> usize {
+        self.buffer_thresholds
+            .get(media_type)
+            .copied()
+            .unwrap_or(self.buffer_threshold)
+    }
+}
+
+/// Timeouts are in seconds, and zero disables them.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct TimeoutsConfig {
+    /// How long a client has to send its request headers after connecting.
+    /// Slower clients are disconnected, so they cannot tie up connections by
+    /// trickling requests in.
+    pub client_request: u64,
+
+    /// How long a client has to acknowledge the connection being closed.
+    pub client_shutdown: u64,
+
+    /// How long a response can take from start to finish, including running
+    /// executables and streaming the body. Responses which are not ready in
+    /// time get a `503`; streams that are still going are cut off by dropping
+    /// the connection.
+    pub request: u64,
+}
+
+impl TimeoutsConfig {
+    pub fn request_timeout(&self) -> Option<Duration> {
+        if self.request == 0 {
+            None
+        } else {
+            Some(Duration::from_secs(self.request))
+        }
+    }
+}
+
+impl Default for TimeoutsConfig {
+    fn default() -> Self {
+        TimeoutsConfig {
+            client_request: 5,
+            client_shutdown: 5,
+            request: 0,
+        }
+    }
+}
+
+impl Default for StreamingConfig {
+    fn default() -> Self {
+        StreamingConfig {
+            buffer_threshold: 0,
+            buffer_thresholds: HashMap::new(),
+            stream_error_log_level: log::Level::Error,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_log::test;
+
+    #[test]
+    fn empty_config_is_valid() {
+        let config = toml::from_str::<Config>("").expect("Empty config was invalid");
+        assert_eq!(config.streaming.buffer_threshold, 0);
+    }
+
+    #[test]
+    fn buffer_thresholds_can_be_set_per_media_type() {
+        let config = toml::from_str::<Config>(
+            r#"
+            [streaming]
+            buffer-threshold = 10
+            stream-error-log-level = "warn"
+
+            [streaming.buffer-thresholds]
+            "text/html" = 20
+            "#,
+        )
+        .expect("Config was invalid");
+
+        let text_html = MediaType::from_media_range(mime::TEXT_HTML).unwrap();
+        let text_plain = MediaType::from_media_range(mime::TEXT_PLAIN).unwrap();
+        assert_eq!(config.streaming.buffer_threshold_for(&text_html), 20);
+        assert_eq!(config.streaming.buffer_threshold_for(&text_plain), 10);
+        assert_eq!(config.streaming.stream_error_log_level, log::Level::Warn);
+    }
+
+    #[test]
+    fn log_levels_can_be_set_per_route() {
+        let config = toml::from_str::<Config>(
+            r#"
+            routes."/api" = { log-level = "debug", body-trace-interval = 10 }
+            routes."/api/health" = { log-level = "off" }
+            routes."/other" = { log-level = "WARN" }
+            "#,
+        )
+        .expect("Config was invalid");
+
+        let api = config.route_config(&"/api/search".parse().unwrap());
+        assert_eq!(api.log_level, Some(log::LevelFilter::Debug));
+        assert_eq!(api.body_trace_interval, Some(10));
+        let health = config.route_config(&"/api/health".parse().unwrap());
+        assert_eq!(health.log_level, Some(log::LevelFilter::Off));
+        assert_eq!(health.body_trace_interval, Some(10));
+        assert_eq!(config.max_route_log_level(), log::LevelFilter::Debug);
+        assert_eq!(
+            Config::default().max_route_log_level(),
+            log::LevelFilter::Off
+        );
+    }
+
+    #[test]
+    fn concurrency_limits_are_shared_beneath_their_route() {
+        let config = toml::from_str::<Config>(
+            r#"
+            routes."/api" = { max-concurrent-requests = 4 }
+            routes."/api/slow" = { max-concurrent-requests = 1, queue-timeout-ms = 50 }
+            routes."/api/slow/cached" = { cache-control = "max-age=60" }
+            "#,
+        )
+        .expect("Config was invalid");
+
+        let limited_route = |route: &str| {
+            config
+                .concurrency_limited_route(&route.parse().unwrap())
+                .map(Route::to_string)
+        };
+        assert_eq!(limited_route("/api/search"), Some(String::from("/api")));
+        assert_eq!(
+            limited_route("/api/slow/cached"),
+            Some(String::from("/api/slow"))
+        );
+        assert_eq!(limited_route("/other"), None);
+
+        let slow = config.route_config(&"/api/slow".parse().unwrap());
+        assert_eq!(slow.max_concurrent_requests, Some(1));
+        assert_eq!(slow.queue_timeout_ms, Some(50));
+        assert_eq!(slow.retry_after, None);
+    }
+
+    #[test]
+    fn configs_can_be_written_back_out_without_secrets() {
+        let config = toml::from_str::<Config>(
+            r#"
+            base-url = "https://example.com"
+            streaming = { buffer-thresholds = { "text/html" = 20 } }
+            reload = { route = "/_reload", secret = "hunter2" }
+            executables = { env = { API_KEY = "hunter2" } }
+            tasks."/_tasks/fetch" = { schedule = "*/15 * * * *" }
+            routes."/news" = { etag = "weak", log-level = "debug", site = { section = "News" } }
+            "#,
+        )
+        .expect("Config was invalid");
+
+        let written = config.to_toml().expect("Config could not be written");
+        assert!(!written.contains("hunter2"));
+        let reread = toml::from_str::<Config>(&written).expect("Written config was invalid");
+        assert_eq!(reread.base_url.as_deref(), Some("https://example.com"));
+        assert_eq!(
+            reread
+                .streaming
+                .buffer_threshold_for(&MediaType::from_media_range(mime::TEXT_HTML).unwrap()),
+            20
+        );
+        assert_eq!(reread.reload.secret.as_deref(), Some(REDACTED));
+        assert_eq!(reread.executables.env["API_KEY"], REDACTED);
+        assert_eq!(
+            reread.tasks[&"/_tasks/fetch".parse::<Route>().unwrap()]
+                .schedule
+                .to_string(),
+            "*/15 * * * *"
+        );
+        let news = reread.route_config(&"/news".parse().unwrap());
+        assert_eq!(news.etag, Some(EntityTagStrength::Weak));
+        assert_eq!(news.log_level, Some(log::LevelFilter::Debug));
+        assert_eq!(
+            reread.timeouts.client_request,
+            TimeoutsConfig::default().client_request
+        );
+    }
+
+    #[test]
+    fn profiles_override_the_rest_of_the_file() {
+        let directory = tempfile::tempdir().expect("Failed to create temporary directory");
+        let path = directory.path().join("operator.toml");
+        fs::write(
+            &path,
+            r#"
+            base-url = "https://example.com"
+            content = { include-drafts = false, lenient = true }
+            routes."/" = { cache-control = "max-age=60" }
+
+            [profiles.dev]
+            base-url = "http://localhost:8080"
+            content = { include-drafts = true }
+            routes."/" = { cache-control = "no-store" }
+            "#,
+        )
+        .unwrap();
+
+        let base = Config::from_file(&path).expect("Config was invalid");
+        assert_eq!(base.base_url.as_deref(), Some("https://example.com"));
+        assert!(!base.content.include_drafts);
+
+        let dev = Config::from_file_with_profile(&path, Some("dev")).expect("Config was invalid");
+        assert_eq!(dev.base_url.as_deref(), Some("http://localhost:8080"));
+        assert!(dev.content.include_drafts);
+        assert!(dev.content.lenient);
+        assert_eq!(
+            dev.route_config(&"/".parse().unwrap())
+                .cache_control
+                .as_deref(),
+            Some("no-store")
+        );
+
+        assert!(matches!(
+            Config::from_file_with_profile(&path, Some("production")),
+            Err(ConfigError::UnknownProfile { .. })
+        ));
+    }
+
+    #[test]
+    fn environment_variables_can_be_interpolated() {
+        let lookup = |name: &str| match name {
+            "HOST" => Ok(String::from("example.com")),
+            "EMPTY" => Ok(String::new()),
+            _ => Err(EnvironmentVariableError::Missing {
+                name: String::from(name),
+            }),
+        };
+        let interpolate = |input: &str| {
+            interpolate_variables(input, lookup).map_err(|error| String::from(error.name()))
+        };
+
+        assert_eq!(
+            interpolate("https://${env:HOST}/${env:EMPTY}path"),
+            Ok(String::from("https://example.com/path"))
+        );
+        assert_eq!(
+            interpolate("$${env:HOST} ${env:HOST"),
+            Ok(String::from("${env:HOST} ${env:HOST"))
+        );
+        assert_eq!(
+            interpolate("${HOST} $${HOST} ${env:not a name} $5"),
+            Ok(String::from("${HOST} $${HOST} ${env:not a name} $5"))
+        );
+        assert_eq!(
+            interpolate("${env:HOST}${env:MISSING}"),
+            Err(String::from("MISSING"))
+        );
+    }
+
+    #[test]
+    fn only_public_environment_variables_can_be_interpolated_into_front_matter() {
+        env::set_var("OPERATOR_TEST_PRIVATE", "hunter2");
+        env::set_var("OPERATOR_PUBLIC_TEST_HOST", "example.com");
+        assert!(matches!(
+            interpolate_public_environment_variables("${env:OPERATOR_TEST_PRIVATE}"),
+            Err(EnvironmentVariableError::NotAllowed { name, .. })
+                if name == "OPERATOR_TEST_PRIVATE"
+        ));
+        assert_eq!(
+            interpolate_public_environment_variables("${env:OPERATOR_PUBLIC_TEST_HOST}")
+                .expect("Interpolation failed"),
+            "example.com"
+        );
+    }
+
+    #[test]
+    fn config_files_can_refer_to_environment_variables() {
+        let directory = tempfile::tempdir().expect("Failed to create temporary directory");
+        let path = directory.path().join("operator.toml");
+        fs::write(
+            &path,
+            r#"
+            base-url = "https://${env:OPERATOR_TEST_CONFIG_HOST}"
+            path-prefix = "/${OPERATOR_TEST_CONFIG_HOST}"
+            reload = { secret = "${env:OPERATOR_TEST_CONFIG_MISSING}" }
+            "#,
+        )
+        .unwrap();
+        env::set_var("OPERATOR_TEST_CONFIG_HOST", "example.com");
+
+        assert!(matches!(
+            Config::from_file(&path),
+            Err(ConfigError::EnvironmentVariable { source, .. })
+                if source.name() == "OPERATOR_TEST_CONFIG_MISSING"
+        ));
+
+        env::set_var("OPERATOR_TEST_CONFIG_MISSING", "hunter2");
+        let config = Config::from_file(&path).expect("Config was invalid");
+        assert_eq!(config.base_url.as_deref(), Some("https://example.com"));
+        assert_eq!(
+            config.path_prefix.as_deref(),
+            Some("/${OPERATOR_TEST_CONFIG_HOST}")
+        );
+        assert_eq!(config.reload.secret.as_deref(), Some("hunter2"));
+    }
+
+    #[test]
+    fn unknown_properties_are_rejected() {
+        assert!(toml::from_str::<Config>("not-a-real-setting = true").is_err());
+        assert!(toml::from_str::<Config>("[streaming]\nbogus = 1").is_err());
+    }
+
+    #[test]
+    fn base_urls_must_be_absolute_http_urls() {
+        for base_url in [
+            "https://example.com",
+            "http://localhost:8080/",
+            "HTTPS://example.com/blog",
+        ] {
+            let config = toml::from_str::<Config>(&format!("base-url = \"{}\"", base_url))
+                .expect("Config was invalid");
+            assert_eq!(config.base_url.as_deref(), Some(base_url));
+        }
+        for base_url in [
+            "example.com",
+            "/blog",
+            "ftp://example.com",
+            "https://",
+            "https:///blog",
+            "https://user@example.com",
+            "https://example.com/?page=1",
+            "https://example.com/#top",
+            "https://example.com/a b",
+        ] {
+            assert!(
+                toml::from_str::<Config>(&format!("base-url = \"{}\"", base_url)).is_err(),
+                "Base URL `{}` was accepted",
+                base_url,
+            );
+        }
+    }
+
+    #[test]
+    fn route_status_codes_are_validated() {
+        let config = toml::from_str::<Config>("[routes.\"/gone\"]\nstatus = 410")
+            .expect("Config was invalid");
+        assert_eq!(
+            config.route_config(&"/gone".parse().unwrap()).status,
+            Some(410)
+        );
+        for status in ["99", "600", "2000", "70000", "-1"] {
+            assert!(
+                toml::from_str::<Config>(&format!("[routes.\"/\"]\nstatus = {}", status)).is_err(),
+                "Status {} was accepted",
+                status,
+            );
+        }
+    }
+
+    #[test]
+    fn route_config_is_inherited_by_descendants() {
+        let config = toml::from_str::<Config>(
+            r#"
+            [routes."/"]
+            fallback = "/default-fallback"
+
+            [routes."/news"]
+            fallback = "/news-fallback"
+
+            [routes."/news/today"]
+            "#,
+        )
+        .expect("Config was invalid");
+
+        let fallback_for = |route: &str| config.route_config(&route.parse().unwrap()).fallback;
+        assert_eq!(
+            fallback_for("/news"),
+            Some("/news-fallback".parse().unwrap())
+        );
+        assert_eq!(
+            fallback_for("/news/today"),
+            Some("/news-fallback".parse().unwrap())
+        );
+        assert_eq!(
+            fallback_for("/newsletter"),
+            Some("/default-fallback".parse().unwrap())
+        );
+    }
+
+    #[test]
+    fn site_data_is_merged_for_each_route() {
+        let config = toml::from_str::<Config>(
+            r#"
+            [site]
+            title = "Example"
+            nav = { home = "/", news = "/news" }
+
+            [routes."/news".site]
+            section = "News"
+            nav = { archive = "/news/archive" }
+
+            [routes."/news/today".site]
+            section = "Today's News"
+            "#,
+        )
+        .expect("Config was invalid");
+
+        let site_data_for = |route: Option<&str>| {
+            serde_json::Value::Object(
+                config.site_data(route.map(|route| route.parse().unwrap()).as_ref()),
+            )
+        };
+        assert_eq!(
+            site_data_for(None),
+            serde_json::json!({
+                "title": "Example",
+                "nav": { "home": "/", "news": "/news" },
+            })
+        );
+        assert_eq!(
+            site_data_for(Some("/news/today")),
+            serde_json::json!({
+                "title": "Example",
+                "section": "Today's News",
+                "nav": { "home": "/", "news": "/news", "archive": "/news/archive" },
+            })
+        );
+    }
+
+    #[test]
+    fn invalid_symlinks_are_rejected_by_default() {
+        let config = toml::from_str::<Config>("").expect("Empty config was invalid");
+        assert_eq!(
+            config.content.invalid_symlinks,
+            InvalidSymlinkAction::Reject
+        );
+
+        let config = toml::from_str::<Config>("[content]\ninvalid-symlinks = \"skip\"")
+            .expect("Config was invalid");
+        assert_eq!(config.content.invalid_symlinks, InvalidSymlinkAction::Skip);
+    }
+
+    #[test]
+    fn tasks_need_a_valid_schedule() {
+        let config = toml::from_str::<Config>(
+            r#"
+            [tasks."/_tasks/hourly"]
+            schedule = "@hourly"
+            output = "hourly.json"
+
+            [tasks."/_tasks/often"]
+            schedule = "*/5 * * * *"
+            "#,
+        )
+        .expect("Config was invalid");
+        let hourly = &config.tasks[&"/_tasks/hourly".parse().unwrap()];
+        assert_eq!(hourly.schedule.to_string(), "@hourly");
+        assert_eq!(hourly.output, Some(PathBuf::from("hourly.json")));
+        assert_eq!(config.tasks[&"/_tasks/often".parse().unwrap()].output, None);
+
+        assert!(toml::from_str::<Config>("[tasks.\"/a\"]\noutput = \"a\"").is_err());
+        assert!(toml::from_str::<Config>("[tasks.\"/a\"]\nschedule = \"daily\"").is_err());
+    }
+
+    #[test]
+    fn time_zones_can_be_offsets_or_names() {
+        let config =
+            toml::from_str::<Config>("[time]\ntimezone = \"-05:00\"").expect("Config was invalid");
+        assert_eq!(config.time.timezone.to_string(), "-05:00");
+        assert_eq!(config.time.clock, Clock::System);
+
+        let config = toml::from_str::<Config>("[time]\ntimezone = \"America/New_York\"")
+            .expect("Config was invalid");
+        assert_eq!(config.time.timezone.to_string(), "America/New_York");
+
+        assert!(toml::from_str::<Config>("[time]\ntimezone = \"Mars/Olympus_Mons\"").is_err());
+        assert!(toml::from_str::<Config>("[time]\nclock = \"tomorrow\"").is_err());
+    }
+
+    #[test]
+    fn invalid_media_types_are_rejected() {
+        assert!(toml::from_str::<Config>("[streaming.buffer-thresholds]\n\"text/*\" = 1").is_err());
+    }
+
+    #[test]
+    fn query_placeholders_in_arguments_cannot_become_options() {
+        for arguments in [
+            r#"["--city={query:city}"]"#,
+            r#"["--city", "--", "{query:city}"]"#,
+            r#"["{route}", "--", "-x", "{query:city}"]"#,
+        ] {
+            toml::from_str::<Config>(&format!(
+                "[executables.arguments]\n\"/weather\" = {}",
+                arguments
+            ))
+            .expect("Config was invalid");
+        }
+        for arguments in [
+            r#"["{query:city}"]"#,
+            r#"["--city", "{query:city}"]"#,
+            r#"["--{query:option}=1"]"#,
+            r#"["-c{query:city}"]"#,
+            r#"["{query:city}", "--"]"#,
+        ] {
+            assert!(
+                toml::from_str::<Config>(&format!(
+                    "[executables.arguments]\n\"/weather\" = {}",
+                    arguments
+                ))
+                .is_err(),
+                "Arguments `{}` were accepted",
+                arguments,
+            );
+        }
+    }
+}
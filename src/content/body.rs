@@ -2,30 +2,92 @@
 //! (reading from files, capturing stdout of a process, etc). All of these
 //! types have an impl for Stream<Item=Result<Bytes, StreamError>>.
 
+#[cfg(feature = "executables")]
+mod process;
+#[cfg(feature = "executables")]
+mod spill;
+
+#[cfg(feature = "executables")]
+pub use process::*;
+#[cfg(feature = "executables")]
+pub use spill::OutputBufferMetrics;
+
 use super::StreamError;
 use crate::bug_message;
 use bytes::Bytes;
+use futures::channel::oneshot;
 use futures::future::{Future, FutureExt, LocalBoxFuture};
 use futures::Stream;
+use memmap2::Mmap;
 use std::cmp;
+use std::collections::VecDeque;
 use std::fs::File;
-use std::io::ErrorKind::Interrupted;
 use std::io::{self, Read, Seek};
 use std::mem;
+use std::num::NonZeroUsize;
+use std::panic::{self, AssertUnwindSafe};
 use std::pin::Pin;
-use std::process::Child;
+use std::sync::{mpsc, Arc, Mutex, OnceLock};
 use std::task::{Context, Poll};
+use std::thread;
 
-// FIXME: Should not depend on actix from inside the content module.
-use actix_web::error::BlockingError;
-use actix_web::web;
+type ChunkOperation<'a, T> = LocalBoxFuture<'a, Result<T, StreamError>>;
 
-type ChunkOperation<'a, T> = LocalBoxFuture<'a, Result<T, BlockingError<StreamError>>>;
+type BlockingJob = Box<dyn FnOnce() + Send>;
 
-fn handle_error(error: BlockingError<StreamError>) -> StreamError {
-    match error {
-        BlockingError::Error(error) => error,
-        BlockingError::Canceled => StreamError::Canceled,
+/// How many threads are used for blocking reads. A thread is tied up for as
+/// long as a read from a slow process takes, so there are several per CPU.
+fn blocking_thread_count() -> usize {
+    thread::available_parallelism().map_or(1, NonZeroUsize::get) * 5
+}
+
+/// Runs `operation` on a shared pool of threads, so that bodies can read from
+/// files and processes without blocking whatever executor is polling them.
+/// The stream is [`Canceled`](StreamError::Canceled) if the operation panics.
+fn block<F, T>(operation: F) -> impl Future<Output = Result<T, StreamError>>
+where
+    F: FnOnce() -> Result<T, StreamError> + Send + 'static,
+    T: Send + 'static,
+{
+    static JOBS: OnceLock<Option<mpsc::Sender<BlockingJob>>> = OnceLock::new();
+    let jobs = JOBS.get_or_init(|| {
+        let (sender, receiver) = mpsc::channel::<BlockingJob>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        let mut started = false;
+        for index in 0..blocking_thread_count() {
+            let receiver = receiver.clone();
+            let spawn_result = thread::Builder::new()
+                .name(format!("operator-blocking-{}", index))
+                .spawn(move || loop {
+                    let job = match receiver.lock() {
+                        Ok(receiver) => receiver.recv(),
+                        Err(_) => return,
+                    };
+                    match job {
+                        // Panics only cancel the job, not the thread.
+                        Ok(job) => drop(panic::catch_unwind(AssertUnwindSafe(job))),
+                        Err(_) => return,
+                    }
+                });
+            match spawn_result {
+                Ok(_) => started = true,
+                Err(error) => log::error!("Failed to start a thread for blocking reads: {}", error),
+            }
+        }
+        Some(sender).filter(|_| started)
+    });
+
+    let (result_sender, result_receiver) = oneshot::channel();
+    let job: BlockingJob = Box::new(move || {
+        // The receiver is gone if the body was dropped while this ran.
+        let _ = result_sender.send(operation());
+    });
+    let sent = jobs.as_ref().is_some_and(|jobs| jobs.send(job).is_ok());
+    async move {
+        if !sent {
+            return Err(StreamError::Canceled);
+        }
+        result_receiver.await.unwrap_or(Err(StreamError::Canceled))
     }
 }
 
@@ -47,6 +109,73 @@ impl Stream for InMemoryBody {
     }
 }
 
+/// HTTP response body populated by a memory-mapped file. Like
+/// [`InMemoryBody`] everything is produced on the first poll, without any
+/// system calls or trips to the blocking thread pool (as long as the pages
+/// are resident), so this is meant for small files.
+pub struct MappedBody(Option<Arc<Mmap>>);
+impl MappedBody {
+    pub fn new(map: Arc<Mmap>) -> Self {
+        MappedBody(Some(map))
+    }
+}
+impl Stream for MappedBody {
+    type Item = Result<Bytes, StreamError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, _: &mut Context) -> Poll<Option<Self::Item>> {
+        Poll::Ready(
+            self.0
+                .take()
+                .filter(|map| !map.is_empty())
+                .map(|map| Ok(Bytes::copy_from_slice(&map))),
+        )
+    }
+}
+
+/// A `multipart/byteranges` HTTP response body. Each part has its own headers
+/// followed by a segment of a file, and parts are streamed one after another.
+pub struct ByteRangesBody {
+    parts: VecDeque<(Bytes, FileBody)>,
+    current_part: Option<FileBody>,
+    closing_delimiter: Option<Bytes>,
+}
+impl ByteRangesBody {
+    /// `parts` are pairs of headers (without the trailing blank line) and
+    /// file segments.
+    pub fn new(boundary: &str, parts: Vec<(String, FileBody)>) -> Self {
+        ByteRangesBody {
+            parts: parts
+                .into_iter()
+                .map(|(headers, segment)| {
+                    let delimiter = format!("\r\n--{}\r\n{}\r\n\r\n", boundary, headers);
+                    (Bytes::from(delimiter), segment)
+                })
+                .collect(),
+            current_part: None,
+            closing_delimiter: Some(Bytes::from(format!("\r\n--{}--\r\n", boundary))),
+        }
+    }
+}
+impl Stream for ByteRangesBody {
+    type Item = Result<Bytes, StreamError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, context: &mut Context) -> Poll<Option<Self::Item>> {
+        if let Some(current_part) = &mut self.current_part {
+            match Pin::new(current_part).poll_next(context) {
+                Poll::Ready(None) => self.current_part = None,
+                poll => return poll,
+            }
+        }
+        match self.parts.pop_front() {
+            Some((delimiter, segment)) => {
+                self.current_part = Some(segment);
+                Poll::Ready(Some(Ok(delimiter)))
+            }
+            None => Poll::Ready(self.closing_delimiter.take().map(Ok)),
+        }
+    }
+}
+
 /// HTTP response body populated by a local file. This was yoinked [from
 /// actix-files's `ChunkedReadFile`](https://github.com/actix/actix-web/blob/web-v3.0.0-beta.3/actix-files/src/lib.rs#L58-L117)
 /// and only lightly modified.
@@ -56,8 +185,13 @@ pub struct FileBody {
     file: Option<File>,
     next: Option<ChunkOperation<'static, (File, Bytes)>>,
     counter: u64,
+    chunk_size: u64,
 }
 impl FileBody {
+    /// Files are read in chunks of this many bytes unless another size is
+    /// set via [`with_chunk_size`](Self::with_chunk_size).
+    pub const DEFAULT_CHUNK_SIZE: u64 = 65536;
+
     pub fn try_from_file(file: File) -> Result<Self, io::Error> {
         Ok(Self {
             size: file.metadata()?.len(),
@@ -65,8 +199,32 @@ impl FileBody {
             file: Some(file),
             next: None,
             counter: 0,
+            chunk_size: Self::DEFAULT_CHUNK_SIZE,
         })
     }
+
+    /// Like `try_from_file`, but only `length` bytes of the file starting at
+    /// `offset` are streamed.
+    pub fn try_from_file_range(file: File, offset: u64, length: u64) -> Result<Self, io::Error> {
+        let remaining = file.metadata()?.len().saturating_sub(offset);
+        Ok(Self {
+            size: cmp::min(length, remaining),
+            offset,
+            file: Some(file),
+            next: None,
+            counter: 0,
+            chunk_size: Self::DEFAULT_CHUNK_SIZE,
+        })
+    }
+
+    /// Reads the file in chunks of (at most) `chunk_size` bytes. Larger
+    /// chunks mean fewer reads, but more memory per response.
+    pub fn with_chunk_size(self, chunk_size: u64) -> Self {
+        FileBody {
+            chunk_size: chunk_size.max(1),
+            ..self
+        }
+    }
 }
 impl Stream for FileBody {
     type Item = Result<Bytes, StreamError>;
@@ -81,7 +239,7 @@ impl Stream for FileBody {
                     self.counter += bytes.len() as u64;
                     Poll::Ready(Some(Ok(bytes)))
                 }
-                Poll::Ready(Err(error)) => Poll::Ready(Some(Err(handle_error(error)))),
+                Poll::Ready(Err(error)) => Poll::Ready(Some(Err(error))),
                 Poll::Pending => Poll::Pending,
             };
         }
@@ -89,6 +247,7 @@ impl Stream for FileBody {
         let size = self.size;
         let offset = self.offset;
         let counter = self.counter;
+        let chunk_size = self.chunk_size;
 
         if size == counter {
             Poll::Ready(None)
@@ -97,8 +256,8 @@ impl Stream for FileBody {
                 "Stream for FileBody is in an inconsistent state (attempted to use the file after it was released).",
             ));
             self.next = Some(
-                web::block(move || {
-                    let max_bytes = cmp::min(size.saturating_sub(counter), 65536);
+                block(move || {
+                    let max_bytes = cmp::min(size.saturating_sub(counter), chunk_size);
                     let mut buffer = Vec::with_capacity(max_bytes as usize);
                     file.seek(io::SeekFrom::Start(offset))?;
                     file.by_ref().take(max_bytes).read_to_end(&mut buffer)?;
@@ -110,97 +269,3 @@ impl Stream for FileBody {
         }
     }
 }
-
-/// HTTP response body populated from the stdout of a running process.
-pub struct ProcessBody {
-    process: Option<Child>,
-    next: Option<ChunkOperation<'static, (Option<Child>, Bytes)>>,
-}
-impl ProcessBody {
-    pub fn new(process: Child) -> Self {
-        ProcessBody {
-            process: Some(process),
-            next: None,
-        }
-    }
-}
-impl Stream for ProcessBody {
-    type Item = Result<Bytes, StreamError>;
-
-    fn poll_next(mut self: Pin<&mut Self>, context: &mut Context) -> Poll<Option<Self::Item>> {
-        if let Some(ref mut future) = self.next {
-            return match Pin::new(future).poll(context) {
-                Poll::Ready(Ok((process, bytes))) => {
-                    self.next.take();
-                    self.process = process;
-                    Poll::Ready(Some(Ok(bytes)))
-                }
-                Poll::Ready(Err(e)) => {
-                    self.process = None; // Give up on the process after hitting an error.
-                    Poll::Ready(Some(Err(handle_error(e))))
-                }
-                Poll::Pending => Poll::Pending,
-            };
-        }
-
-        let mut process = match self.process.take() {
-            // None means the process has terminated; we're all done!
-            None => return Poll::Ready(None),
-            Some(process) => process,
-        };
-
-        let pid = process.id();
-        let next = web::block(move || {
-            let mut buffer = [0; 32]; // FIXME: 32 bytes is totally arbitrary.
-            match process.stdout {
-                None => Err(StreamError::ExecutableOutputCouldNotBeCaptured { pid }),
-                Some(ref mut stdout) => {
-                    match stdout.read(&mut buffer) {
-                        Err(error) if error.kind() == Interrupted => {
-                            // If the read was interrupted then it can be tried
-                            // again on the next poll. Just emit an empty chunk.
-                            Ok((Some(process), Bytes::new()))
-                        }
-                        Err(fatal_error) => Err(StreamError::from(fatal_error)),
-                        Ok(0) => {
-                            match process.try_wait()? {
-                                None => {
-                                    // The process is still running, there was
-                                    // just no new output.
-                                    Ok((Some(process), Bytes::new()))
-                                }
-                                Some(exit_status) => {
-                                    if !exit_status.success() {
-                                        let stderr_contents = {
-                                            process.stderr.and_then(|mut stderr| {
-                                                let mut error_message = String::new();
-                                                match stderr.read_to_string(&mut error_message) {
-                                                    Err(_) | Ok(0) => None,
-                                                    Ok(_) => Some(error_message),
-                                                }
-                                            })
-                                        };
-
-                                        Err(StreamError::ExecutableExitedWithNonzero {
-                                            pid,
-                                            stderr_contents,
-                                            exit_code: exit_status.code(),
-                                        })
-                                    } else {
-                                        // Successful completion.
-                                        Ok((None, Bytes::new()))
-                                    }
-                                }
-                            }
-                        }
-                        Ok(size) => Ok((Some(process), Bytes::copy_from_slice(&buffer[..size]))),
-                    }
-                }
-            }
-        })
-        .boxed_local();
-
-        self.next = Some(next);
-        self.poll_next(context)
-    }
-}
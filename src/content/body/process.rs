@@ -0,0 +1,474 @@
+//! Bodies for executables (see the `executables` cargo feature).
+
+use super::spill::spill;
+use super::{block, ChunkOperation, FileBody, InMemoryBody};
+use crate::content::process_group::ProcessGroup;
+use crate::content::{Cancellation, StreamError};
+use bytes::{Bytes, BytesMut};
+use futures::future::{Future, FutureExt};
+use futures::Stream;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::ErrorKind::Interrupted;
+use std::io::{BufRead, BufReader, Read};
+use std::mem;
+use std::pin::Pin;
+use std::process::{Child, ChildStderr};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::task::{Context, Poll};
+use std::thread::{self, JoinHandle};
+
+/// At most this many bytes from the end of a process's stderr are kept for
+/// error messages.
+const STDERR_TAIL_LENGTH: usize = 4096;
+
+/// A child process along with the thread that is reading its stderr.
+struct RunningProcess {
+    pid: u32,
+    group: Arc<Mutex<ProcessGroup>>,
+    stdout: Option<Box<dyn Read + Send>>,
+    stderr: Option<JoinHandle<String>>,
+}
+
+/// HTTP response body populated from the stdout of a running process.
+///
+/// The process's stderr is read concurrently (so it can never fill up and
+/// block the process) and each line is logged. `name` identifies the process
+/// in those logs.
+///
+/// Stdout is read into a buffer which starts small (so that output from
+/// processes which trickle it out is not held up or over-allocated for) and
+/// doubles each time it is filled twice in a row, up to a maximum size.
+///
+/// If the body is dropped before the process exits (e.g. because the client
+/// went away) or the render it belongs to is cancelled, the process and
+/// anything it started are killed.
+pub struct ProcessBody {
+    group: Arc<Mutex<ProcessGroup>>,
+    cancellation: Cancellation,
+    process: Option<RunningProcess>,
+    next: Option<ChunkOperation<'static, (Option<RunningProcess>, Bytes)>>,
+    output_limit: Option<u64>,
+    output_length: u64,
+    chunk_size: usize,
+    max_chunk_size: usize,
+    filled_previous_chunk: bool,
+}
+impl ProcessBody {
+    /// Reads from stdout start with a buffer of this many bytes unless other
+    /// sizes are set via [`with_chunk_sizes`](Self::with_chunk_sizes).
+    pub const DEFAULT_CHUNK_SIZE: usize = 4096;
+
+    /// The read buffer grows until it is this large.
+    pub const DEFAULT_MAX_CHUNK_SIZE: usize = 65536;
+
+    pub fn new(mut process: Child, name: &str) -> Self {
+        let stderr = process
+            .stderr
+            .take()
+            .map(|stderr| drain_stderr(stderr, format!("{} (pid {})", name, process.id())));
+        let stdout = process
+            .stdout
+            .take()
+            .map(|stdout| Box::new(stdout) as Box<dyn Read + Send>);
+        let process_id = process.id();
+        let group = Arc::new(Mutex::new(ProcessGroup::new(process)));
+        ProcessBody {
+            group: group.clone(),
+            cancellation: Cancellation::current(),
+            process: Some(RunningProcess {
+                pid: process_id,
+                group,
+                stdout,
+                stderr,
+            }),
+            next: None,
+            output_limit: None,
+            output_length: 0,
+            chunk_size: Self::DEFAULT_CHUNK_SIZE,
+            max_chunk_size: Self::DEFAULT_MAX_CHUNK_SIZE,
+            filled_previous_chunk: false,
+        }
+    }
+
+    /// Reads stdout into a buffer of `chunk_size` bytes, which can grow up to
+    /// `max_chunk_size` bytes.
+    pub fn with_chunk_sizes(mut self, chunk_size: usize, max_chunk_size: usize) -> Self {
+        self.chunk_size = chunk_size.max(1);
+        self.max_chunk_size = max_chunk_size.max(self.chunk_size);
+        self
+    }
+
+    /// Reads stdout ahead of the client, keeping up to `memory_bytes` of
+    /// output in memory and then up to `file_bytes` more in a temporary file,
+    /// so that the process can finish without waiting for a slow client. If
+    /// both are zero the process waits whenever the client does.
+    pub fn with_spill_buffer(mut self, memory_bytes: usize, file_bytes: u64) -> Self {
+        if memory_bytes == 0 && file_bytes == 0 {
+            return self;
+        }
+        if let Some(process) = &mut self.process {
+            process.stdout = process.stdout.take().map(|stdout| {
+                Box::new(spill(stdout, memory_bytes, file_bytes)) as Box<dyn Read + Send>
+            });
+        }
+        self
+    }
+
+    /// Grows the buffer if the last two reads both filled it, since the
+    /// process is producing output faster than it is being read.
+    fn adapt_chunk_size(&mut self, bytes_read: usize) {
+        let filled_chunk = bytes_read >= self.chunk_size;
+        if filled_chunk && self.filled_previous_chunk {
+            self.chunk_size = (self.chunk_size * 2).min(self.max_chunk_size);
+            self.filled_previous_chunk = false;
+        } else {
+            self.filled_previous_chunk = filled_chunk;
+        }
+    }
+
+    /// Kills the process (and fails the stream) if it writes more than
+    /// `output_limit` bytes.
+    pub fn with_output_limit(mut self, output_limit: Option<u64>) -> Self {
+        self.output_limit = output_limit;
+        self
+    }
+}
+impl Stream for ProcessBody {
+    type Item = Result<Bytes, StreamError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, context: &mut Context) -> Poll<Option<Self::Item>> {
+        if let Some(ref mut future) = self.next {
+            return match Pin::new(future).poll(context) {
+                Poll::Ready(Ok((process, bytes))) => {
+                    self.next.take();
+                    self.process = process;
+                    self.output_length += bytes.len() as u64;
+                    if !bytes.is_empty() {
+                        self.adapt_chunk_size(bytes.len());
+                    }
+                    Poll::Ready(Some(Ok(bytes)))
+                }
+                Poll::Ready(Err(e)) => {
+                    self.process = None; // Give up on the process after hitting an error.
+                    Poll::Ready(Some(Err(e)))
+                }
+                Poll::Pending => Poll::Pending,
+            };
+        }
+
+        let mut process = match self.process.take() {
+            // None means the process has terminated; we're all done!
+            None => return Poll::Ready(None),
+            Some(process) => process,
+        };
+        if self.cancellation.is_cancelled() {
+            lock(&process.group).kill();
+            return Poll::Ready(Some(Err(StreamError::Canceled)));
+        }
+
+        let pid = process.pid;
+        let output_limit = self.output_limit;
+        let remaining_output = output_limit.map(|limit| limit.saturating_sub(self.output_length));
+        let chunk_size = self.chunk_size;
+        let next = block(move || {
+            let mut buffer = vec![0; chunk_size];
+            match process.stdout {
+                None => Err(StreamError::ExecutableOutputCouldNotBeCaptured { pid }),
+                Some(ref mut stdout) => {
+                    match stdout.read(&mut buffer) {
+                        Err(error) if error.kind() == Interrupted => {
+                            // If the read was interrupted then it can be tried
+                            // again on the next poll. Just emit an empty chunk.
+                            Ok((Some(process), Bytes::new()))
+                        }
+                        Err(fatal_error) => Err(StreamError::from(fatal_error)),
+                        Ok(0) => {
+                            let exit_status = lock(&process.group).try_wait()?;
+                            match exit_status {
+                                None => {
+                                    // The process is still running, there was
+                                    // just no new output.
+                                    Ok((Some(process), Bytes::new()))
+                                }
+                                Some(exit_status) => {
+                                    if !exit_status.success() {
+                                        let stderr_contents = process
+                                            .stderr
+                                            .and_then(|stderr| stderr.join().ok())
+                                            .filter(|stderr_tail| !stderr_tail.is_empty());
+
+                                        Err(StreamError::ExecutableExitedWithNonzero {
+                                            pid,
+                                            stderr_contents,
+                                            exit_code: exit_status.code(),
+                                        })
+                                    } else {
+                                        // Successful completion.
+                                        Ok((None, Bytes::new()))
+                                    }
+                                }
+                            }
+                        }
+                        Ok(size)
+                            if remaining_output
+                                .is_some_and(|remaining| size as u64 > remaining) =>
+                        {
+                            lock(&process.group).kill();
+                            Err(StreamError::ExecutableOutputTooLarge {
+                                pid,
+                                limit: output_limit.unwrap_or_default(),
+                            })
+                        }
+                        Ok(size) => {
+                            buffer.truncate(size);
+                            Ok((Some(process), Bytes::from(buffer)))
+                        }
+                    }
+                }
+            }
+        })
+        .boxed_local();
+
+        self.next = Some(next);
+        self.poll_next(context)
+    }
+}
+
+impl Drop for ProcessBody {
+    fn drop(&mut self) {
+        // A read may still be in progress on another thread, which this
+        // unblocks by closing the process's stdout.
+        if let Ok(mut group) = self.group.lock() {
+            group.kill();
+        }
+    }
+}
+
+fn lock(group: &Mutex<ProcessGroup>) -> MutexGuard<'_, ProcessGroup> {
+    group
+        .lock()
+        .expect("Mutex for process group has been poisoned")
+}
+
+/// Logs each line that a process writes to stderr (prefixed with `name`) on
+/// another thread, which finishes with the last [`STDERR_TAIL_LENGTH`] bytes
+/// once stderr is closed.
+fn drain_stderr(stderr: ChildStderr, name: String) -> JoinHandle<String> {
+    thread::spawn(move || {
+        let mut reader = BufReader::new(stderr);
+        let mut tail = VecDeque::with_capacity(STDERR_TAIL_LENGTH);
+        let mut line = Vec::new();
+        loop {
+            line.clear();
+            match reader.read_until(b'\n', &mut line) {
+                Ok(0) => break,
+                Ok(_) => {
+                    log::warn!(
+                        "{} wrote to stderr: {}",
+                        name,
+                        String::from_utf8_lossy(&line).trim_end()
+                    );
+                    tail.extend(&line);
+                    let excess = tail.len().saturating_sub(STDERR_TAIL_LENGTH);
+                    tail.drain(..excess);
+                }
+                Err(error) if error.kind() == Interrupted => continue,
+                Err(error) => {
+                    log::warn!("Failed to read stderr of {}: {}", name, error);
+                    break;
+                }
+            }
+        }
+        String::from_utf8_lossy(tail.make_contiguous()).into_owned()
+    })
+}
+
+/// Passes through another body unchanged while keeping a copy of everything
+/// it produces. If the inner body ends without any errors then `on_complete`
+/// is called with all of its bytes.
+pub struct RecordingBody<B> {
+    body: B,
+    recorded: BytesMut,
+    on_complete: Option<Box<dyn FnOnce(Bytes)>>,
+}
+impl<B> RecordingBody<B> {
+    pub fn new<F: FnOnce(Bytes) + 'static>(body: B, on_complete: F) -> Self {
+        RecordingBody {
+            body,
+            recorded: BytesMut::new(),
+            on_complete: Some(Box::new(on_complete)),
+        }
+    }
+}
+impl<B> Stream for RecordingBody<B>
+where
+    B: Stream<Item = Result<Bytes, StreamError>> + Unpin,
+{
+    type Item = Result<Bytes, StreamError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, context: &mut Context) -> Poll<Option<Self::Item>> {
+        let next = Pin::new(&mut self.body).poll_next(context);
+        match &next {
+            Poll::Ready(Some(Ok(bytes))) => self.recorded.extend_from_slice(bytes),
+            Poll::Ready(Some(Err(_))) => {
+                // Never record partial output.
+                self.on_complete = None;
+            }
+            Poll::Ready(None) => {
+                if let Some(on_complete) = self.on_complete.take() {
+                    on_complete(mem::take(&mut self.recorded).freeze());
+                }
+            }
+            Poll::Pending => {}
+        }
+        next
+    }
+}
+
+/// HTTP response body for an executable, which is either the output of a
+/// running process or output that was cached from an earlier run.
+pub enum ExecutableBody {
+    Cached(InMemoryBody),
+    Running(ProcessBody),
+    Caching(RecordingBody<ProcessBody>),
+    Sendfile(Box<SendfileBody<ExecutableBody>>),
+}
+impl Stream for ExecutableBody {
+    type Item = Result<Bytes, StreamError>;
+
+    fn poll_next(self: Pin<&mut Self>, context: &mut Context) -> Poll<Option<Self::Item>> {
+        match self.get_mut() {
+            ExecutableBody::Cached(body) => Pin::new(body).poll_next(context),
+            ExecutableBody::Running(body) => Pin::new(body).poll_next(context),
+            ExecutableBody::Caching(body) => Pin::new(body).poll_next(context),
+            ExecutableBody::Sendfile(body) => Pin::new(body.as_mut()).poll_next(context),
+        }
+    }
+}
+
+/// Output which starts with this (followed by a path and a newline) is
+/// replaced by the contents of the file at that path.
+const SENDFILE_PREFIX: &[u8] = b"X-Sendfile:";
+
+/// The first line of output must be shorter than this to be an `X-Sendfile`
+/// line.
+const SENDFILE_LINE_LIMIT: usize = 4096;
+
+/// Opens the file for a path from an `X-Sendfile` line, if it's allowed.
+pub type SendfileResolver = Box<dyn Fn(&str) -> Result<File, StreamError>>;
+
+/// Passes through another body unless its first line is `X-Sendfile: <path>`,
+/// in which case the rest of it is discarded and the file at that path is
+/// streamed instead.
+pub struct SendfileBody<Inner: Stream<Item = Result<Bytes, StreamError>> + Unpin> {
+    inner: Inner,
+    is_inner_finished: bool,
+    state: SendfileState,
+    resolve: SendfileResolver,
+}
+enum SendfileState {
+    /// Buffering output until the end of the first line.
+    Peeking(BytesMut),
+    /// The first line was not an `X-Sendfile` line.
+    PassingThrough,
+    /// Waiting for the inner body to finish before sending the file.
+    Discarding(String),
+    Sending(FileBody),
+}
+impl<Inner: Stream<Item = Result<Bytes, StreamError>> + Unpin> SendfileBody<Inner> {
+    pub fn new(inner: Inner, resolve: SendfileResolver) -> Self {
+        SendfileBody {
+            inner,
+            is_inner_finished: false,
+            state: SendfileState::Peeking(BytesMut::new()),
+            resolve,
+        }
+    }
+
+    /// Decides what to do once the first line (or as much of it as there
+    /// will ever be) has been buffered. Returns output to pass through.
+    fn finish_peeking(&mut self, buffer: BytesMut) -> Option<Bytes> {
+        let sendfile_path = buffer
+            .strip_prefix(SENDFILE_PREFIX)
+            .and_then(|rest| rest.split(|byte| *byte == b'\n').next())
+            .and_then(|path| std::str::from_utf8(path).ok())
+            .map(|path| String::from(path.trim()))
+            .filter(|path| !path.is_empty());
+        match sendfile_path {
+            Some(path) => {
+                self.state = SendfileState::Discarding(path);
+                None
+            }
+            None => {
+                self.state = SendfileState::PassingThrough;
+                if buffer.is_empty() {
+                    None
+                } else {
+                    Some(buffer.freeze())
+                }
+            }
+        }
+    }
+}
+impl<Inner: Stream<Item = Result<Bytes, StreamError>> + Unpin> Stream for SendfileBody<Inner> {
+    type Item = Result<Bytes, StreamError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, context: &mut Context) -> Poll<Option<Self::Item>> {
+        loop {
+            let this = &mut *self;
+            if let SendfileState::Sending(file) = &mut this.state {
+                return Pin::new(file).poll_next(context);
+            }
+
+            let next = if this.is_inner_finished {
+                None
+            } else {
+                match Pin::new(&mut this.inner).poll_next(context) {
+                    Poll::Pending => return Poll::Pending,
+                    Poll::Ready(Some(Err(error))) => return Poll::Ready(Some(Err(error))),
+                    Poll::Ready(Some(Ok(bytes))) => Some(bytes),
+                    Poll::Ready(None) => {
+                        this.is_inner_finished = true;
+                        None
+                    }
+                }
+            };
+
+            match (&mut this.state, next) {
+                (SendfileState::PassingThrough, next) => return Poll::Ready(next.map(Ok)),
+                (SendfileState::Peeking(buffer), next) => {
+                    if let Some(bytes) = next {
+                        buffer.extend_from_slice(&bytes);
+                    }
+                    let prefix_length = buffer.len().min(SENDFILE_PREFIX.len());
+                    let may_be_sendfile_line = buffer[..prefix_length]
+                        == SENDFILE_PREFIX[..prefix_length]
+                        && !buffer.contains(&b'\n')
+                        && buffer.len() < SENDFILE_LINE_LIMIT;
+                    if may_be_sendfile_line && !this.is_inner_finished {
+                        continue;
+                    }
+                    let buffer = mem::take(buffer);
+                    if let Some(bytes) = this.finish_peeking(buffer) {
+                        return Poll::Ready(Some(Ok(bytes)));
+                    }
+                }
+                (SendfileState::Discarding(path), _) if this.is_inner_finished => {
+                    let path = mem::take(path);
+                    let file = (this.resolve)(&path).and_then(|file| {
+                        FileBody::try_from_file(file).map_err(|error| StreamError::SendfileFailed {
+                            path,
+                            message: error.to_string(),
+                        })
+                    });
+                    match file {
+                        Ok(file) => this.state = SendfileState::Sending(file),
+                        Err(error) => return Poll::Ready(Some(Err(error))),
+                    }
+                }
+                (SendfileState::Discarding(_), _) | (SendfileState::Sending(_), _) => {}
+            }
+        }
+    }
+}
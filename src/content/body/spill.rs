@@ -0,0 +1,339 @@
+//! Buffering for process output which has not been read yet, so that a slow
+//! client does not leave the process blocked on a full pipe.
+
+use crate::bug_message;
+use bytes::Bytes;
+use serde::Serialize;
+use std::cmp;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::ErrorKind::Interrupted;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread;
+
+/// The most bytes that are read from the source at a time.
+const PUMP_CHUNK_SIZE: usize = 65536;
+
+static BUFFERED_BYTES: AtomicU64 = AtomicU64::new(0);
+static SPILLED_BYTES: AtomicU64 = AtomicU64::new(0);
+static FULL_BUFFERS: AtomicU64 = AtomicU64::new(0);
+
+/// Totals across every spill buffer in the process.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct OutputBufferMetrics {
+    /// Bytes of output held in memory which clients have not read yet.
+    pub buffered_bytes: u64,
+
+    /// Bytes of output in temporary files which clients have not read yet.
+    pub spilled_bytes: u64,
+
+    /// How many times a buffer has filled up, leaving its process waiting
+    /// for a slow client.
+    pub full_buffers: u64,
+}
+impl OutputBufferMetrics {
+    pub fn current() -> Self {
+        OutputBufferMetrics {
+            buffered_bytes: BUFFERED_BYTES.load(Ordering::Relaxed),
+            spilled_bytes: SPILLED_BYTES.load(Ordering::Relaxed),
+            full_buffers: FULL_BUFFERS.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Reads everything from `source` on another thread, keeping up to
+/// `memory_limit` bytes which have not been read from the returned reader in
+/// memory and up to `file_limit` more in a temporary file. Once both are full
+/// the source is not read until there is room again.
+pub(super) fn spill<R: Read + Send + 'static>(
+    source: R,
+    memory_limit: usize,
+    file_limit: u64,
+) -> SpillReader {
+    let shared = Arc::new(Shared {
+        state: Mutex::new(SpillState::default()),
+        changed: Condvar::new(),
+    });
+    let pump_shared = shared.clone();
+    thread::spawn(move || pump(source, &pump_shared, memory_limit, file_limit));
+    SpillReader { shared }
+}
+
+struct Shared {
+    state: Mutex<SpillState>,
+    changed: Condvar,
+}
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, SpillState> {
+        self.state
+            .lock()
+            .expect("Mutex for spill buffer has been poisoned")
+    }
+}
+
+/// Unread output is in `memory` followed by the part of `file` between the
+/// read and write positions. New output only goes into memory while the file
+/// has nothing unread, which keeps it all in order.
+#[derive(Default)]
+struct SpillState {
+    memory: VecDeque<Bytes>,
+    memory_bytes: usize,
+    file: Option<File>,
+    file_unavailable: bool,
+    file_read_position: u64,
+    file_write_position: u64,
+    error: Option<io::Error>,
+    finished: bool,
+    closed: bool,
+}
+impl SpillState {
+    fn is_spilling(&self) -> bool {
+        self.file_read_position < self.file_write_position
+    }
+
+    /// How many more bytes can be buffered. The temporary file is created the
+    /// first time it is needed.
+    fn make_room(&mut self, memory_limit: usize, file_limit: u64) -> usize {
+        let memory_room = if self.is_spilling() {
+            0
+        } else {
+            memory_limit.saturating_sub(self.memory_bytes)
+        };
+        if memory_room == 0 && file_limit > 0 && self.file.is_none() && !self.file_unavailable {
+            match tempfile::tempfile() {
+                Ok(file) => self.file = Some(file),
+                Err(error) => {
+                    log::warn!(
+                        "Unable to create a temporary file for executable output, so it will \
+                        only be buffered in memory: {}",
+                        error
+                    );
+                    self.file_unavailable = true;
+                }
+            }
+        }
+        let file_room = if self.file.is_some() {
+            file_limit.saturating_sub(self.file_write_position)
+        } else {
+            0
+        };
+        usize::try_from(memory_room as u64 + file_room).unwrap_or(usize::MAX)
+    }
+
+    /// Buffers `bytes`, which must fit within the room that was made.
+    fn push(&mut self, bytes: &[u8], memory_limit: usize) -> Result<(), io::Error> {
+        if self.closed {
+            return Ok(());
+        }
+        let in_memory = if self.is_spilling() {
+            0
+        } else {
+            cmp::min(bytes.len(), memory_limit.saturating_sub(self.memory_bytes))
+        };
+        if in_memory > 0 {
+            self.memory
+                .push_back(Bytes::copy_from_slice(&bytes[..in_memory]));
+            self.memory_bytes += in_memory;
+            BUFFERED_BYTES.fetch_add(in_memory as u64, Ordering::Relaxed);
+        }
+
+        let rest = &bytes[in_memory..];
+        if !rest.is_empty() {
+            let file = self.file.as_mut().expect(bug_message!(
+                "Output was spilled to a file before there was one.",
+            ));
+            file.seek(SeekFrom::Start(self.file_write_position))?;
+            file.write_all(rest)?;
+            self.file_write_position += rest.len() as u64;
+            SPILLED_BYTES.fetch_add(rest.len() as u64, Ordering::Relaxed);
+        }
+        Ok(())
+    }
+
+    /// Moves buffered output into `buffer`, returning how many bytes were
+    /// moved (zero if nothing is buffered).
+    fn pop(&mut self, buffer: &mut [u8]) -> Result<usize, io::Error> {
+        if let Some(mut front) = self.memory.pop_front() {
+            let size = cmp::min(buffer.len(), front.len());
+            buffer[..size].copy_from_slice(&front.split_to(size));
+            if !front.is_empty() {
+                self.memory.push_front(front);
+            }
+            self.memory_bytes -= size;
+            BUFFERED_BYTES.fetch_sub(size as u64, Ordering::Relaxed);
+            return Ok(size);
+        }
+
+        if self.is_spilling() {
+            let unread = self.file_write_position - self.file_read_position;
+            let size = usize::try_from(unread)
+                .map_or(buffer.len(), |unread| cmp::min(buffer.len(), unread));
+            let file = self.file.as_mut().expect(bug_message!(
+                "Spilled output was read before there was a file.",
+            ));
+            file.seek(SeekFrom::Start(self.file_read_position))?;
+            file.read_exact(&mut buffer[..size])?;
+            self.file_read_position += size as u64;
+            SPILLED_BYTES.fetch_sub(size as u64, Ordering::Relaxed);
+            if self.file_read_position == self.file_write_position {
+                // Everything in the file has been read, so it can start over.
+                self.file_read_position = 0;
+                self.file_write_position = 0;
+                file.set_len(0)?;
+            }
+            return Ok(size);
+        }
+
+        Ok(0)
+    }
+}
+
+/// Reads from `source` into the buffer for as long as there is room and a
+/// reader to read it.
+fn pump<R: Read>(mut source: R, shared: &Shared, memory_limit: usize, file_limit: u64) {
+    let mut buffer = vec![0; PUMP_CHUNK_SIZE];
+    loop {
+        let room = {
+            let mut state = shared.lock();
+            let mut was_full = false;
+            loop {
+                if state.closed {
+                    return;
+                }
+                let room = state.make_room(memory_limit, file_limit);
+                if room > 0 {
+                    break room;
+                }
+                if !was_full {
+                    FULL_BUFFERS.fetch_add(1, Ordering::Relaxed);
+                    was_full = true;
+                }
+                state = shared
+                    .changed
+                    .wait(state)
+                    .expect("Mutex for spill buffer has been poisoned");
+            }
+        };
+
+        let size = cmp::min(room, buffer.len());
+        let result = match source.read(&mut buffer[..size]) {
+            Err(error) if error.kind() == Interrupted => continue,
+            result => result,
+        };
+        let mut state = shared.lock();
+        match result.and_then(|size| state.push(&buffer[..size], memory_limit).map(|()| size)) {
+            Ok(0) => state.finished = true,
+            Ok(_) => {}
+            Err(error) => state.error = Some(error),
+        }
+        let done = state.finished || state.error.is_some();
+        shared.changed.notify_all();
+        if done {
+            return;
+        }
+    }
+}
+
+/// The reading end of a spill buffer. Reads block until there is buffered
+/// output or the source has ended.
+pub(super) struct SpillReader {
+    shared: Arc<Shared>,
+}
+impl Read for SpillReader {
+    fn read(&mut self, buffer: &mut [u8]) -> Result<usize, io::Error> {
+        if buffer.is_empty() {
+            return Ok(0);
+        }
+        let mut state = self.shared.lock();
+        loop {
+            let size = state.pop(buffer)?;
+            if size > 0 {
+                self.shared.changed.notify_all();
+                return Ok(size);
+            }
+            if let Some(error) = state.error.take() {
+                state.finished = true;
+                return Err(error);
+            }
+            if state.finished {
+                return Ok(0);
+            }
+            state = self
+                .shared
+                .changed
+                .wait(state)
+                .expect("Mutex for spill buffer has been poisoned");
+        }
+    }
+}
+impl Drop for SpillReader {
+    fn drop(&mut self) {
+        if let Ok(mut state) = self.shared.state.lock() {
+            state.closed = true;
+            BUFFERED_BYTES.fetch_sub(state.memory_bytes as u64, Ordering::Relaxed);
+            SPILLED_BYTES.fetch_sub(
+                state.file_write_position - state.file_read_position,
+                Ordering::Relaxed,
+            );
+            state.memory.clear();
+            state.memory_bytes = 0;
+            state.file = None;
+            state.file_read_position = 0;
+            state.file_write_position = 0;
+        }
+        self.shared.changed.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn output_is_unchanged_by_spilling() {
+        let source = (0..200_000).map(|index| index as u8).collect::<Vec<_>>();
+        for (memory_limit, file_limit) in [(1000, 0), (1000, 10_000), (0, 10_000), (1_000_000, 0)] {
+            let mut reader = spill(io::Cursor::new(source.clone()), memory_limit, file_limit);
+            let mut output = Vec::new();
+            let mut buffer = [0; 777];
+            loop {
+                match reader.read(&mut buffer).expect("Read failed") {
+                    0 => break,
+                    size => output.extend_from_slice(&buffer[..size]),
+                }
+            }
+            assert_eq!(output, source);
+        }
+    }
+
+    #[test]
+    fn sources_are_not_read_past_the_limits() {
+        let reader = spill(io::repeat(1), 100, 1000);
+        let started_at = Instant::now();
+        loop {
+            let (memory_bytes, file_bytes) = {
+                let state = reader.shared.lock();
+                (
+                    state.memory_bytes,
+                    state.file_write_position - state.file_read_position,
+                )
+            };
+            assert!(memory_bytes <= 100 && file_bytes <= 1000);
+            if memory_bytes == 100
+                && file_bytes == 1000
+                && OutputBufferMetrics::current().full_buffers > 0
+            {
+                break;
+            }
+            assert!(
+                started_at.elapsed() < Duration::from_secs(10),
+                "The buffer never filled up"
+            );
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+}
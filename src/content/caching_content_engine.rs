@@ -0,0 +1,480 @@
+use super::body::InMemoryBody;
+use super::*;
+use bytes::Bytes;
+use futures::executor;
+use futures::stream::{self, StreamExt};
+use handlebars::Handlebars;
+use std::collections::{HashMap, HashSet};
+use std::hash::Hash;
+use std::iter;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// How long and how much a [`CachingContentEngine`] remembers.
+#[derive(Clone, Debug)]
+pub struct CachePolicy {
+    /// How long rendered output and missing routes are remembered.
+    pub time_to_live: Duration,
+
+    /// The most rendered outputs (and separately, missing routes) to keep.
+    pub max_entries: usize,
+
+    /// Rendered output larger than this many bytes is not cached.
+    pub max_entry_size: usize,
+}
+
+impl Default for CachePolicy {
+    fn default() -> Self {
+        CachePolicy {
+            time_to_live: Duration::from_secs(60),
+            max_entries: 1024,
+            max_entry_size: 1024 * 1024,
+        }
+    }
+}
+
+/// A [`ContentEngine`] which wraps another engine and remembers rendered
+/// output and routes that have no content, according to its
+/// [`CachePolicy`].
+///
+/// Output is cached per content item, acceptable media ranges, and
+/// [`RequestData`], so requests which differ in any way that templates can
+/// see are rendered separately. Output is buffered in memory while it is
+/// first rendered, so content which streams slowly (like some executables)
+/// should not be cached.
+pub struct CachingContentEngine<Engine> {
+    engine: Engine,
+    policy: CachePolicy,
+    rendered: Mutex<HashMap<RenderKey, (Instant, RenderedOutput)>>,
+    missing: Mutex<HashMap<Route, (Instant, ())>>,
+}
+
+type RenderedOutput = (MediaType, Bytes);
+
+#[derive(Clone, Eq, Hash, PartialEq)]
+struct RenderKey {
+    /// The requested route, so output can be invalidated by route.
+    route: Option<Route>,
+
+    /// The address of the content which was rendered. Content lives as long
+    /// as the engine does, and the cache is cleared whenever the engine could
+    /// be changed.
+    content: usize,
+    acceptable_media_ranges: Vec<String>,
+    request: String,
+    error_code: Option<u16>,
+}
+
+impl<Engine> CachingContentEngine<Engine> {
+    pub fn new(engine: Engine) -> Self {
+        CachingContentEngine {
+            engine,
+            policy: CachePolicy::default(),
+            rendered: Mutex::new(HashMap::new()),
+            missing: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn with_policy(self, policy: CachePolicy) -> Self {
+        CachingContentEngine { policy, ..self }
+    }
+
+    /// The wrapped engine.
+    pub fn engine(&self) -> &Engine {
+        &self.engine
+    }
+
+    /// The wrapped engine, which can be changed (e.g. reloaded). This clears
+    /// the cache.
+    pub fn engine_mut(&mut self) -> &mut Engine {
+        self.clear();
+        &mut self.engine
+    }
+
+    /// Forgets everything that has been cached.
+    pub fn clear(&self) {
+        self.rendered
+            .lock()
+            .expect("Mutex for rendered output cache has been poisoned")
+            .clear();
+        self.missing
+            .lock()
+            .expect("Mutex for missing route cache has been poisoned")
+            .clear();
+    }
+
+    /// Forgets output rendered for requests to any of `routes` (e.g. the
+    /// [affected routes](DependencyGraph::affected_routes) of a change), and
+    /// whether they were missing.
+    pub fn invalidate<'a>(&self, routes: impl IntoIterator<Item = &'a Route>) {
+        let routes = routes.into_iter().collect::<HashSet<_>>();
+        self.rendered
+            .lock()
+            .expect("Mutex for rendered output cache has been poisoned")
+            .retain(|key, _| {
+                key.route
+                    .as_ref()
+                    .is_none_or(|route| !routes.contains(route))
+            });
+        self.missing
+            .lock()
+            .expect("Mutex for missing route cache has been poisoned")
+            .retain(|route, _| !routes.contains(route));
+    }
+
+    fn is_fresh(&self, cached_at: Instant) -> bool {
+        cached_at.elapsed() < self.policy.time_to_live
+    }
+
+    /// Inserts into `cache`, making room by dropping stale entries. If there
+    /// is still no room, nothing is inserted.
+    fn insert<Key, Value>(&self, cache: &mut HashMap<Key, (Instant, Value)>, key: Key, value: Value)
+    where
+        Key: Eq + Hash,
+    {
+        if cache.len() >= self.policy.max_entries {
+            cache.retain(|_, (cached_at, _)| self.is_fresh(*cached_at));
+        }
+        if cache.len() < self.policy.max_entries {
+            cache.insert(key, (Instant::now(), value));
+        }
+    }
+}
+
+impl<ServerInfo, Engine> ContentEngine<ServerInfo> for CachingContentEngine<Engine>
+where
+    ServerInfo: Clone + Serialize,
+    Engine: ContentEngine<ServerInfo>,
+{
+    fn render_data(
+        &self,
+        request_route: Option<Route>,
+        query_parameters: QueryParameters,
+        request_headers: HashMap<String, String>,
+    ) -> RenderData<ServerInfo> {
+        self.engine
+            .render_data(request_route, query_parameters, request_headers)
+    }
+
+    fn render_content(
+        &self,
+        content: &ContentRepresentations,
+        render_data: RenderData<ServerInfo>,
+        acceptable_media_ranges: &[MediaRange],
+    ) -> Result<Media<Box<dyn ByteStream>>, RenderError> {
+        let key = match serde_json::to_string(&render_data.request) {
+            Ok(request) => RenderKey {
+                route: render_data.request.route.clone(),
+                content: content as *const ContentRepresentations as usize,
+                acceptable_media_ranges: acceptable_media_ranges
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect(),
+                request,
+                error_code: render_data.error_code,
+            },
+            Err(error) => {
+                log::warn!("Rendering without the cache: {}", error);
+                return self
+                    .engine
+                    .render_content(content, render_data, acceptable_media_ranges);
+            }
+        };
+
+        if let Some((cached_at, (media_type, bytes))) = self
+            .rendered
+            .lock()
+            .expect("Mutex for rendered output cache has been poisoned")
+            .get(&key)
+        {
+            if self.is_fresh(*cached_at) {
+                return Ok(Media::new(
+                    media_type.clone(),
+                    Box::new(InMemoryBody(bytes.clone())),
+                ));
+            }
+        }
+
+        let Media {
+            media_type,
+            mut content,
+        } = self
+            .engine
+            .render_content(content, render_data, acceptable_media_ranges)?;
+
+        // Buffer the output until it ends or gets too large to cache.
+        let mut chunks = Vec::new();
+        let mut size = 0;
+        while size <= self.policy.max_entry_size {
+            match executor::block_on(content.next()) {
+                Some(Ok(chunk)) => {
+                    size += chunk.len();
+                    chunks.push(chunk);
+                }
+                Some(Err(error)) => {
+                    let remainder =
+                        stream::iter(chunks.into_iter().map(Ok).chain(iter::once(Err(error))))
+                            .chain(content);
+                    return Ok(Media::new(media_type, Box::new(remainder)));
+                }
+                None => {
+                    let bytes = Bytes::from(chunks.concat());
+                    let mut rendered = self
+                        .rendered
+                        .lock()
+                        .expect("Mutex for rendered output cache has been poisoned");
+                    self.insert(&mut rendered, key, (media_type.clone(), bytes.clone()));
+                    return Ok(Media::new(media_type, Box::new(InMemoryBody(bytes))));
+                }
+            }
+        }
+        let remainder = stream::iter(chunks.into_iter().map(Ok)).chain(content);
+        Ok(Media::new(media_type, Box::new(remainder)))
+    }
+
+    fn new_template(
+        &self,
+        template_source: &str,
+        media_type: MediaType,
+    ) -> Result<UnregisteredTemplate, TemplateError> {
+        self.engine.new_template(template_source, media_type)
+    }
+
+    fn get(&self, route: &Route) -> Option<&ContentRepresentations> {
+        let mut missing = self
+            .missing
+            .lock()
+            .expect("Mutex for missing route cache has been poisoned");
+        if let Some((cached_at, ())) = missing.get(route) {
+            if self.is_fresh(*cached_at) {
+                return None;
+            }
+        }
+        let content = self.engine.get(route);
+        if content.is_none() {
+            self.insert(&mut missing, route.clone(), ());
+        } else {
+            missing.remove(route);
+        }
+        content
+    }
+
+    fn handlebars_registry(&self) -> &Handlebars<'_> {
+        self.engine.handlebars_registry()
+    }
+
+    fn search_index(&self) -> Option<&SearchIndex> {
+        self.engine.search_index()
+    }
+
+    fn stats(&self) -> Option<&ContentStats> {
+        self.engine.stats()
+    }
+
+    fn redirect(&self, route: &Route) -> Option<&Redirect> {
+        self.engine.redirect(route)
+    }
+
+    fn template_engine(&self, language: TemplateLanguage, strict: bool) -> &dyn TemplateEngine {
+        self.engine.template_engine(language, strict)
+    }
+
+    fn template_source(&self, template_name: &str) -> Option<String> {
+        self.engine.template_source(template_name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::test_lib::*;
+    use super::*;
+    use crate::test_lib::*;
+    use ::mime;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use test_log::test;
+
+    type TestContentEngine<'a> = FilesystemBasedContentEngine<'a, ()>;
+
+    /// Counts how often the wrapped engine is asked for content.
+    struct CountingContentEngine<'a> {
+        engine: &'a TestContentEngine<'a>,
+        gets: AtomicUsize,
+        renders: AtomicUsize,
+    }
+    impl<'a> CountingContentEngine<'a> {
+        fn new(engine: &'a TestContentEngine<'a>) -> Self {
+            CountingContentEngine {
+                engine,
+                gets: AtomicUsize::new(0),
+                renders: AtomicUsize::new(0),
+            }
+        }
+    }
+    impl ContentEngine<()> for CountingContentEngine<'_> {
+        fn render_data(
+            &self,
+            request_route: Option<Route>,
+            query_parameters: QueryParameters,
+            request_headers: HashMap<String, String>,
+        ) -> RenderData<()> {
+            self.engine
+                .render_data(request_route, query_parameters, request_headers)
+        }
+        fn render_content(
+            &self,
+            content: &ContentRepresentations,
+            render_data: RenderData<()>,
+            acceptable_media_ranges: &[MediaRange],
+        ) -> Result<Media<Box<dyn ByteStream>>, RenderError> {
+            self.renders.fetch_add(1, Ordering::SeqCst);
+            self.engine
+                .render_content(content, render_data, acceptable_media_ranges)
+        }
+        fn new_template(
+            &self,
+            template_source: &str,
+            media_type: MediaType,
+        ) -> Result<UnregisteredTemplate, TemplateError> {
+            self.engine.new_template(template_source, media_type)
+        }
+        fn get(&self, route: &Route) -> Option<&ContentRepresentations> {
+            self.gets.fetch_add(1, Ordering::SeqCst);
+            self.engine.get(route)
+        }
+        fn handlebars_registry(&self) -> &Handlebars<'_> {
+            self.engine.handlebars_registry()
+        }
+        fn search_index(&self) -> Option<&SearchIndex> {
+            self.engine.search_index()
+        }
+        fn stats(&self) -> Option<&ContentStats> {
+            self.engine.stats()
+        }
+        fn redirect(&self, route: &Route) -> Option<&Redirect> {
+            self.engine.redirect(route)
+        }
+        fn template_engine(&self, language: TemplateLanguage, strict: bool) -> &dyn TemplateEngine {
+            self.engine.template_engine(language, strict)
+        }
+    }
+
+    fn render(
+        content_engine: &impl ContentEngine<()>,
+        route: &Route,
+        query_parameters: QueryParameters,
+    ) -> String {
+        let content = content_engine
+            .get(route)
+            .expect("Content could not be found");
+        let render_data =
+            content_engine.render_data(Some(route.clone()), query_parameters, HashMap::new());
+        let media = content_engine
+            .render_content(content, render_data, &[mime::TEXT_PLAIN])
+            .expect("Content could not be rendered");
+        media_to_string(media)
+    }
+
+    fn content_directory() -> (tempfile::TempDir, ContentDirectory) {
+        let root = tempfile::tempdir().expect("Failed to create temporary directory");
+        std::fs::write(
+            root.path().join("hello.txt.hbs"),
+            "hello {{request.query-parameters.name}}",
+        )
+        .unwrap();
+        let directory = ContentDirectory::from_root(&root.path()).unwrap();
+        (root, directory)
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "templating"), ignore = "uses templates")]
+    fn rendered_output_is_reused_for_identical_requests() {
+        let (_root, directory) = content_directory();
+        let shared_content_engine = TestContentEngine::from_content_directory(directory, ())
+            .expect("Content engine could not be created");
+        let inner_engine = shared_content_engine.read().unwrap();
+        let content_engine = CachingContentEngine::new(CountingContentEngine::new(&inner_engine));
+
+        let world = QueryParameters::new(vec![(String::from("name"), String::from("world"))]);
+        let moon = QueryParameters::new(vec![(String::from("name"), String::from("moon"))]);
+
+        assert_eq!(
+            render(&content_engine, &route("/hello"), world.clone()),
+            "hello world"
+        );
+        assert_eq!(
+            render(&content_engine, &route("/hello"), world),
+            "hello world"
+        );
+        assert_eq!(content_engine.engine().renders.load(Ordering::SeqCst), 1);
+
+        assert_eq!(
+            render(&content_engine, &route("/hello"), moon.clone()),
+            "hello moon"
+        );
+        assert_eq!(content_engine.engine().renders.load(Ordering::SeqCst), 2);
+
+        content_engine.invalidate(&[route("/goodbye")]);
+        render(&content_engine, &route("/hello"), moon.clone());
+        assert_eq!(content_engine.engine().renders.load(Ordering::SeqCst), 2);
+
+        content_engine.invalidate(&[route("/hello")]);
+        render(&content_engine, &route("/hello"), moon);
+        assert_eq!(content_engine.engine().renders.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "templating"), ignore = "uses templates")]
+    fn output_is_rendered_again_once_it_is_stale_or_too_large() {
+        let (_root, directory) = content_directory();
+        let shared_content_engine = TestContentEngine::from_content_directory(directory, ())
+            .expect("Content engine could not be created");
+        let inner_engine = shared_content_engine.read().unwrap();
+        let world = QueryParameters::new(vec![(String::from("name"), String::from("world"))]);
+
+        for policy in [
+            CachePolicy {
+                time_to_live: Duration::ZERO,
+                ..CachePolicy::default()
+            },
+            CachePolicy {
+                max_entry_size: 1,
+                ..CachePolicy::default()
+            },
+        ] {
+            let content_engine =
+                CachingContentEngine::new(CountingContentEngine::new(&inner_engine))
+                    .with_policy(policy.clone());
+            for _ in 0..2 {
+                assert_eq!(
+                    render(&content_engine, &route("/hello"), world.clone()),
+                    "hello world"
+                );
+            }
+            assert_eq!(
+                content_engine.engine().renders.load(Ordering::SeqCst),
+                2,
+                "Output was reused with {:?}",
+                policy
+            );
+        }
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "templating"), ignore = "uses templates")]
+    fn missing_routes_are_remembered() {
+        let (_root, directory) = content_directory();
+        let shared_content_engine = TestContentEngine::from_content_directory(directory, ())
+            .expect("Content engine could not be created");
+        let inner_engine = shared_content_engine.read().unwrap();
+        let mut content_engine =
+            CachingContentEngine::new(CountingContentEngine::new(&inner_engine));
+
+        assert!(content_engine.get(&route("/nope")).is_none());
+        assert!(content_engine.get(&route("/nope")).is_none());
+        assert_eq!(content_engine.engine().gets.load(Ordering::SeqCst), 1);
+
+        content_engine.engine_mut();
+        assert!(content_engine.get(&route("/nope")).is_none());
+        assert_eq!(content_engine.engine().gets.load(Ordering::SeqCst), 2);
+    }
+}
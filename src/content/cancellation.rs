@@ -0,0 +1,114 @@
+use std::cell::RefCell;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+
+static CANCELLED_RENDERS: AtomicU64 = AtomicU64::new(0);
+
+thread_local! {
+    /// The cancellation for the render in progress on this thread. Nested
+    /// renders happen synchronously, so they share it.
+    static CURRENT: RefCell<Option<Cancellation>> = const { RefCell::new(None) };
+}
+
+/// Lets a render find out that nobody is waiting for it anymore (e.g. because
+/// the client disconnected), so that it can stop early instead of finishing
+/// uselessly. Checking is cooperative: included content is not rendered and
+/// executables are killed once a render has been cancelled.
+#[derive(Clone, Debug, Default)]
+pub struct Cancellation(Arc<AtomicBool>);
+
+impl Cancellation {
+    pub fn new() -> Self {
+        Cancellation::default()
+    }
+
+    pub fn cancel(&self) {
+        if !self.0.swap(true, Ordering::Relaxed) {
+            CANCELLED_RENDERS.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Returns a guard which cancels this when it is dropped, unless it is
+    /// [disarmed](CancelOnDrop::disarm) first.
+    pub fn cancel_on_drop(&self) -> CancelOnDrop {
+        CancelOnDrop(Some(self.clone()))
+    }
+
+    /// How many renders have been cancelled since the process started.
+    pub fn cancelled_render_count() -> u64 {
+        CANCELLED_RENDERS.load(Ordering::Relaxed)
+    }
+
+    /// The cancellation for the render in progress on this thread, or one
+    /// which is never cancelled if there is no render in progress.
+    pub(crate) fn current() -> Self {
+        CURRENT.with(|current| current.borrow().clone().unwrap_or_default())
+    }
+
+    /// Runs `render` with this as the [`current`](Self::current)
+    /// cancellation.
+    pub(crate) fn scope<Output>(&self, render: impl FnOnce() -> Output) -> Output {
+        let previous = CURRENT.with(|current| current.replace(Some(self.clone())));
+        let _restore_guard = RestoreGuard(previous);
+        render()
+    }
+}
+
+/// Cancels a render when it is dropped, unless it was disarmed.
+pub struct CancelOnDrop(Option<Cancellation>);
+
+impl CancelOnDrop {
+    /// Stops this from cancelling anything, e.g. because the render finished.
+    pub fn disarm(mut self) {
+        self.0.take();
+    }
+}
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        if let Some(cancellation) = self.0.take() {
+            cancellation.cancel();
+        }
+    }
+}
+
+/// Puts back the previous current cancellation, even if rendering panics.
+struct RestoreGuard(Option<Cancellation>);
+impl Drop for RestoreGuard {
+    fn drop(&mut self) {
+        CURRENT.with(|current| *current.borrow_mut() = self.0.take());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cancellations_apply_within_their_scope() {
+        let cancellation = Cancellation::new();
+        assert!(!Cancellation::current().is_cancelled());
+        cancellation.scope(|| {
+            assert!(!Cancellation::current().is_cancelled());
+            cancellation.cancel();
+            assert!(Cancellation::current().is_cancelled());
+        });
+        assert!(!Cancellation::current().is_cancelled());
+    }
+
+    #[test]
+    fn dropping_a_guard_cancels_unless_it_was_disarmed() {
+        let cancellation = Cancellation::new();
+        cancellation.cancel_on_drop().disarm();
+        assert!(!cancellation.is_cancelled());
+
+        let cancelled_renders = Cancellation::cancelled_render_count();
+        drop(cancellation.cancel_on_drop());
+        assert!(cancellation.is_cancelled());
+        assert!(Cancellation::cancelled_render_count() > cancelled_renders);
+    }
+}
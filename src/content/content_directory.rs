@@ -1,6 +1,12 @@
 use super::Route;
 use crate::bug_message;
+use crate::config::{ContentConfig, InvalidSymlinkAction};
+use crate::file_limit;
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use ignore::gitignore::Gitignore;
+use std::collections::HashMap;
 use std::env;
+use std::fs;
 use std::fs::File;
 use std::os::unix::fs::PermissionsExt;
 use std::path;
@@ -19,6 +25,42 @@ pub enum ContentDirectoryFromRootError {
         source: walkdir::Error,
     },
 
+    #[error(
+        "Symlink '{}' resolves to '{}', which is outside of the content directory '{}'.",
+        .path.display(),
+        .target.display(),
+        .root.display()
+    )]
+    SymlinkEscapesRoot {
+        root: PathBuf,
+        path: PathBuf,
+        target: PathBuf,
+    },
+
+    #[error(
+        "Symlink '{}' in content directory '{}' forms a cycle with its ancestor '{}'.",
+        .path.display(),
+        .root.display(),
+        .ancestor.display()
+    )]
+    SymlinkCycle {
+        root: PathBuf,
+        path: PathBuf,
+        ancestor: PathBuf,
+    },
+
+    #[error("Invalid glob pattern '{}': {}", .pattern, .source)]
+    InvalidGlob {
+        pattern: String,
+        source: globset::Error,
+    },
+
+    #[error("Invalid ignore file '{}': {}", .path.display(), .source)]
+    InvalidIgnoreFile {
+        path: PathBuf,
+        source: ignore::Error,
+    },
+
     #[error(transparent)]
     DirectoryEntryError(#[from] ContentFileError),
 }
@@ -28,14 +70,87 @@ pub enum ContentDirectoryFromRootError {
 pub struct ContentFileError(String);
 
 /// A filesystem directory containing content.
+///
+/// Files can be left out by listing them in `.operatorignore` files, which use
+/// the same syntax as `.gitignore`. Patterns apply to the directory that the
+/// ignore file is in and all of its descendants, and ignore files in nested
+/// directories take precedence.
+///
+/// Other content directories can be mounted beneath a route prefix (see
+/// [`ContentDirectory::mount`]), so one engine can serve several of them.
 pub struct ContentDirectory {
     files: Vec<ContentFile>,
     root: PathBuf,
+
+    /// The root as it was given, which may be a symlink to `root`.
+    requested_root: PathBuf,
+
+    config: ContentConfig,
+    mounts: Vec<Route>,
+
+    /// Where mounted directories came from, so they can be reloaded.
+    mounted_sources: Vec<(Route, ContentDirectorySource)>,
+}
+
+/// Everything needed to load a [`ContentDirectory`] from the filesystem
+/// again (e.g. after its files change).
+#[derive(Clone, Debug)]
+pub struct ContentDirectorySource {
+    root: PathBuf,
+    config: ContentConfig,
+    mounted_sources: Vec<(Route, ContentDirectorySource)>,
+}
+
+impl ContentDirectorySource {
+    /// Reads the directory (and any mounted directories) again.
+    pub fn load(&self) -> Result<ContentDirectory, ContentDirectoryFromRootError> {
+        let mut content_directory =
+            ContentDirectory::from_root_with_config(&self.root, &self.config)?;
+        for (prefix, mounted_source) in &self.mounted_sources {
+            content_directory.mount(prefix.clone(), mounted_source.load()?)?;
+        }
+        Ok(content_directory)
+    }
+
+    /// Where the content file at `relative_path` (which is prefixed like its
+    /// route if it is from a mounted directory) is, if it still exists.
+    pub fn absolute_path(&self, relative_path: &str) -> Option<PathBuf> {
+        for (prefix, mounted_source) in self.mounted_sources.iter().rev() {
+            let relative_prefix = prefix.as_ref().trim_start_matches('/');
+            let relative_path = if relative_prefix.is_empty() {
+                Some(relative_path)
+            } else {
+                relative_path
+                    .strip_prefix(relative_prefix)
+                    .and_then(|rest| rest.strip_prefix(ContentFile::PATH_SEPARATOR))
+            };
+            if let Some(path) = relative_path.and_then(|path| mounted_source.absolute_path(path)) {
+                return Some(path);
+            }
+        }
+        let path = relative_path
+            .split(ContentFile::PATH_SEPARATOR)
+            .fold(self.root.clone(), |path, component| path.join(component));
+        if path.is_file() {
+            Some(path)
+        } else {
+            None
+        }
+    }
 }
 
 impl ContentDirectory {
+    pub const IGNORE_FILE_NAME: &'static str = ".operatorignore";
+
     pub fn from_root<P: AsRef<Path>>(
         absolute_root: &P,
+    ) -> Result<Self, ContentDirectoryFromRootError> {
+        Self::from_root_with_config(absolute_root, &ContentConfig::default())
+    }
+
+    pub fn from_root_with_config<P: AsRef<Path>>(
+        absolute_root: &P,
+        config: &ContentConfig,
     ) -> Result<Self, ContentDirectoryFromRootError> {
         let absolute_root_path = absolute_root.as_ref();
         if !absolute_root_path.is_absolute() {
@@ -45,47 +160,258 @@ impl ContentDirectory {
             });
         }
 
-        let mut files = Vec::new();
-        let walker = WalkDir::new(absolute_root_path)
+        // Symlink targets are compared against this to make sure they don't
+        // expose files from elsewhere on the filesystem.
+        let canonical_root = fs::canonicalize(absolute_root_path).map_err(|io_error| {
+            ContentDirectoryFromRootError::InvalidRootPath {
+                message: io_error.to_string(),
+                root: PathBuf::from(absolute_root_path),
+            }
+        })?;
+
+        // Files are loaded from the resolved root, so if the root is a symlink
+        // (e.g. to the current release of a site) that deploy tooling
+        // repoints, everything comes from one release. The symlink itself is
+        // kept as the source, so that reloading resolves it again.
+        let requested_root = PathBuf::from(absolute_root_path);
+        let absolute_root_path = canonical_root.as_path();
+
+        let includes = build_glob_set(&config.include)?;
+        let excludes = build_glob_set(&config.exclude)?;
+        let relative_path =
+            |path: &Path| PathBuf::from(path.strip_prefix(absolute_root_path).unwrap_or(path));
+
+        let mut ignore_files = IgnoreFiles::new();
+        ignore_files.load(absolute_root_path)?;
+
+        let mut paths = Vec::new();
+        let mut walker = WalkDir::new(absolute_root_path)
+            // Sorting makes loading (and which file gets blamed for problems
+            // like duplicate routes) deterministic across platforms.
+            .sort_by_file_name()
             .follow_links(true)
             .min_depth(1)
             .into_iter()
             .filter_entry(|entry| {
                 // Skip hidden files/directories.
-                let is_hidden = entry
-                    .file_name()
-                    .to_str()
-                    .map(|name| name.starts_with('.'))
-                    .unwrap_or(false);
-                !is_hidden
+                let is_hidden = !config.hidden_prefix.is_empty()
+                    && entry
+                        .file_name()
+                        .to_str()
+                        .map(|name| name.starts_with(&config.hidden_prefix))
+                        .unwrap_or(false);
+                let is_ignore_file = entry.file_name() == Self::IGNORE_FILE_NAME;
+                !is_hidden && !is_ignore_file && !excludes.is_match(relative_path(entry.path()))
             });
-        for dir_entry_result in walker {
-            let dir_entry = dir_entry_result.map_err(|walkdir_error| {
-                ContentDirectoryFromRootError::WalkDirError {
-                    source: walkdir_error,
-                    root: PathBuf::from(absolute_root_path),
+        while let Some(dir_entry_result) = walker.next() {
+            let dir_entry = match dir_entry_result {
+                Ok(dir_entry) => dir_entry,
+                Err(walkdir_error) => {
+                    let error = match (walkdir_error.path(), walkdir_error.loop_ancestor()) {
+                        (Some(path), Some(ancestor)) => {
+                            ContentDirectoryFromRootError::SymlinkCycle {
+                                root: PathBuf::from(absolute_root_path),
+                                path: PathBuf::from(path),
+                                ancestor: PathBuf::from(ancestor),
+                            }
+                        }
+                        _ => {
+                            return Err(ContentDirectoryFromRootError::WalkDirError {
+                                source: walkdir_error,
+                                root: PathBuf::from(absolute_root_path),
+                            })
+                        }
+                    };
+                    handle_invalid_symlink(error, config.invalid_symlinks)?;
+                    continue;
                 }
-            })?;
-            {
-                let entry_path = dir_entry.path().to_path_buf();
-                if dir_entry.file_type().is_file() {
-                    let content_file =
-                        ContentFile::from_root_and_path(absolute_root_path, entry_path)
-                            .map_err(ContentDirectoryFromRootError::from)?;
-                    files.push(content_file);
+            };
+
+            let is_dir = dir_entry.file_type().is_dir();
+            if ignore_files.is_ignored(dir_entry.path(), is_dir) {
+                if is_dir {
+                    walker.skip_current_dir();
+                }
+                continue;
+            }
+
+            if dir_entry.path_is_symlink() {
+                // Broken symlinks are left for ContentFile to report.
+                if let Ok(target) = fs::canonicalize(dir_entry.path()) {
+                    if !target.starts_with(&canonical_root) {
+                        if is_dir {
+                            walker.skip_current_dir();
+                        }
+                        handle_invalid_symlink(
+                            ContentDirectoryFromRootError::SymlinkEscapesRoot {
+                                root: PathBuf::from(absolute_root_path),
+                                path: dir_entry.into_path(),
+                                target,
+                            },
+                            config.invalid_symlinks,
+                        )?;
+                        continue;
+                    }
                 }
             }
+
+            if is_dir {
+                ignore_files.load(dir_entry.path())?;
+            }
+
+            let entry_path = dir_entry.path().to_path_buf();
+            let is_included =
+                config.include.is_empty() || includes.is_match(relative_path(&entry_path));
+            if dir_entry.file_type().is_file() && is_included {
+                paths.push(entry_path);
+            }
         }
 
+        // Every file is held open, so warn if there are not enough descriptors
+        // before opening them.
+        file_limit::warn_if_insufficient(paths.len());
+        let files = paths
+            .into_iter()
+            .map(|path| ContentFile::from_root_and_path(absolute_root_path, path))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(ContentDirectoryFromRootError::from)?;
+
         Ok(ContentDirectory {
             files,
             root: PathBuf::from(absolute_root_path),
+            requested_root,
+            config: config.clone(),
+            mounts: Vec::new(),
+            mounted_sources: Vec::new(),
         })
     }
 
     pub fn root(&self) -> &Path {
         &self.root
     }
+
+    /// The settings this directory was loaded with.
+    pub fn config(&self) -> &ContentConfig {
+        &self.config
+    }
+
+    /// Where this directory came from, for reloading it later.
+    pub fn source(&self) -> ContentDirectorySource {
+        ContentDirectorySource {
+            root: self.requested_root.clone(),
+            config: self.config.clone(),
+            mounted_sources: self.mounted_sources.clone(),
+        }
+    }
+
+    /// Adds the files from `other` to this directory with their routes
+    /// beneath `prefix`, so `/guide` in `other` becomes `/docs/guide` when
+    /// mounted at `/docs`.
+    ///
+    /// Relative paths get the same prefix (without the leading `/`), which
+    /// means that templates in `other` must include its partials by their
+    /// prefixed names (like `docs/_header.html.hbs`).
+    pub fn mount(
+        &mut self,
+        prefix: Route,
+        other: ContentDirectory,
+    ) -> Result<(), ContentDirectoryFromRootError> {
+        let prefixed = |route: &Route| {
+            prefix
+                .join(route.as_ref().trim_start_matches('/'))
+                .map_err(|error| ContentFileError(error.to_string()))
+        };
+        let relative_prefix = prefix.as_ref().trim_start_matches('/');
+        let other_source = other.source();
+        for mut file in other.files {
+            file.route = prefixed(&file.route)?;
+            if !relative_prefix.is_empty() {
+                file.relative_path = format!(
+                    "{}{}{}",
+                    relative_prefix,
+                    ContentFile::PATH_SEPARATOR,
+                    file.relative_path
+                );
+            }
+            self.files.push(file);
+        }
+        for nested_mount in &other.mounts {
+            self.mounts.push(prefixed(nested_mount)?);
+        }
+        self.mounts.push(prefix.clone());
+        self.mounted_sources.push((prefix, other_source));
+        Ok(())
+    }
+
+    /// Route prefixes that other content directories are mounted at.
+    pub fn mounts(&self) -> &[Route] {
+        &self.mounts
+    }
+}
+
+/// Matchers for all `.operatorignore` files found so far, keyed by the
+/// directory they are in.
+struct IgnoreFiles(HashMap<PathBuf, Gitignore>);
+
+impl IgnoreFiles {
+    fn new() -> Self {
+        IgnoreFiles(HashMap::new())
+    }
+
+    fn load(&mut self, directory: &Path) -> Result<(), ContentDirectoryFromRootError> {
+        let path = directory.join(ContentDirectory::IGNORE_FILE_NAME);
+        if path.is_file() {
+            let (matcher, error) = Gitignore::new(&path);
+            if let Some(source) = error {
+                return Err(ContentDirectoryFromRootError::InvalidIgnoreFile { path, source });
+            }
+            self.0.insert(PathBuf::from(directory), matcher);
+        }
+        Ok(())
+    }
+
+    fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        // The nearest ignore file with an opinion wins, so a nested ignore
+        // file can re-include something with a `!pattern`.
+        path.ancestors()
+            .skip(1)
+            .filter_map(|directory| self.0.get(directory))
+            .map(|matcher| matcher.matched(path, is_dir))
+            .find(|matched| !matched.is_none())
+            .map(|matched| matched.is_ignore())
+            .unwrap_or(false)
+    }
+}
+
+fn build_glob_set(patterns: &[String]) -> Result<GlobSet, ContentDirectoryFromRootError> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        let glob =
+            Glob::new(pattern).map_err(|source| ContentDirectoryFromRootError::InvalidGlob {
+                pattern: pattern.clone(),
+                source,
+            })?;
+        builder.add(glob);
+    }
+    builder
+        .build()
+        .map_err(|source| ContentDirectoryFromRootError::InvalidGlob {
+            pattern: patterns.join(", "),
+            source,
+        })
+}
+
+fn handle_invalid_symlink(
+    error: ContentDirectoryFromRootError,
+    action: InvalidSymlinkAction,
+) -> Result<(), ContentDirectoryFromRootError> {
+    match action {
+        InvalidSymlinkAction::Reject => Err(error),
+        InvalidSymlinkAction::Skip => {
+            log::warn!("{} It will be skipped.", error);
+            Ok(())
+        }
+    }
 }
 
 pub struct ContentFile {
@@ -211,7 +537,7 @@ impl ContentFile {
             route_string.push(Self::PATH_SEPARATOR);
             route_string.push_str(relative_path_without_extensions);
 
-            route_string.parse::<Route>().map_err(|error| {
+            Route::from_decoded(&route_string).map_err(|error| {
                 ContentFileError(format!(
                     bug_message!("This should never happen: Could not create route from path: {}"),
                     error,
@@ -256,7 +582,7 @@ impl PartialEq for ContentDirectory {
 mod tests {
     use super::*;
     use crate::test_lib::*;
-    use std::fs;
+    use std::os::unix::fs::symlink;
     use test_log::test;
 
     #[test]
@@ -267,7 +593,7 @@ mod tests {
             result.is_ok(),
             "Unable to use directory at '{}': {}",
             path.display(),
-            result.err().unwrap().to_string()
+            result.err().unwrap()
         );
     }
 
@@ -290,4 +616,259 @@ mod tests {
             non_absolute_path,
         );
     }
+
+    #[test]
+    fn symlinks_outside_of_root_are_rejected() {
+        let outside = tempfile::tempdir().expect("Failed to create temporary directory");
+        let root = tempfile::tempdir().expect("Failed to create temporary directory");
+        fs::write(outside.path().join("secret.txt"), "secret").unwrap();
+        fs::write(root.path().join("public.txt"), "public").unwrap();
+        symlink(
+            outside.path().join("secret.txt"),
+            root.path().join("file.txt"),
+        )
+        .unwrap();
+        symlink(outside.path(), root.path().join("directory")).unwrap();
+
+        let result = ContentDirectory::from_root(&root.path());
+        assert!(
+            matches!(
+                result,
+                Err(ContentDirectoryFromRootError::SymlinkEscapesRoot { .. })
+            ),
+            "Symlink to outside of the content directory was not rejected",
+        );
+
+        let config = ContentConfig {
+            invalid_symlinks: InvalidSymlinkAction::Skip,
+            ..ContentConfig::default()
+        };
+        let directory = ContentDirectory::from_root_with_config(&root.path(), &config)
+            .expect("Symlinks were not skipped");
+        let routes = directory
+            .into_iter()
+            .map(|file| file.route.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(routes, vec!["/public"]);
+    }
+
+    #[test]
+    fn symlinks_within_root_are_allowed() {
+        let root = tempfile::tempdir().expect("Failed to create temporary directory");
+        fs::create_dir(root.path().join("real")).unwrap();
+        fs::write(root.path().join("real/file.txt"), "hello").unwrap();
+        symlink("real", root.path().join("alias")).unwrap();
+
+        let directory = ContentDirectory::from_root(&root.path()).expect("Symlink was rejected");
+        let mut routes = directory
+            .into_iter()
+            .map(|file| file.route.to_string())
+            .collect::<Vec<_>>();
+        routes.sort();
+        assert_eq!(routes, vec!["/alias/file", "/real/file"]);
+    }
+
+    #[test]
+    fn symlink_cycles_are_reported() {
+        let root = tempfile::tempdir().expect("Failed to create temporary directory");
+        fs::create_dir(root.path().join("directory")).unwrap();
+        fs::write(root.path().join("directory/file.txt"), "hello").unwrap();
+        symlink("..", root.path().join("directory/loop")).unwrap();
+
+        let result = ContentDirectory::from_root(&root.path());
+        assert!(
+            matches!(
+                result,
+                Err(ContentDirectoryFromRootError::SymlinkCycle { .. })
+            ),
+            "Symlink cycle was not reported",
+        );
+
+        let config = ContentConfig {
+            invalid_symlinks: InvalidSymlinkAction::Skip,
+            ..ContentConfig::default()
+        };
+        let directory = ContentDirectory::from_root_with_config(&root.path(), &config)
+            .expect("Symlink cycle was not skipped");
+        let routes = directory
+            .into_iter()
+            .map(|file| file.route.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(routes, vec!["/directory/file"]);
+    }
+
+    fn routes_for_config(root: &Path, config: &ContentConfig) -> Vec<String> {
+        let directory = ContentDirectory::from_root_with_config(&root, config)
+            .expect("Failed to load content directory");
+        let mut routes = directory
+            .into_iter()
+            .map(|file| file.route.to_string())
+            .collect::<Vec<_>>();
+        routes.sort();
+        routes
+    }
+
+    #[test]
+    fn hidden_prefix_is_configurable() {
+        let root = tempfile::tempdir().expect("Failed to create temporary directory");
+        fs::write(root.path().join(".dotfile.txt"), "").unwrap();
+        fs::write(root.path().join("~tilde.txt"), "").unwrap();
+
+        assert_eq!(
+            routes_for_config(root.path(), &ContentConfig::default()),
+            vec!["/~tilde"]
+        );
+        assert_eq!(
+            routes_for_config(
+                root.path(),
+                &ContentConfig {
+                    hidden_prefix: String::from("~"),
+                    ..ContentConfig::default()
+                }
+            ),
+            vec!["/.dotfile"]
+        );
+        assert_eq!(
+            routes_for_config(
+                root.path(),
+                &ContentConfig {
+                    hidden_prefix: String::new(),
+                    ..ContentConfig::default()
+                }
+            ),
+            vec!["/.dotfile", "/~tilde"]
+        );
+    }
+
+    #[test]
+    fn files_can_be_included_and_excluded_with_globs() {
+        let root = tempfile::tempdir().expect("Failed to create temporary directory");
+        fs::create_dir_all(root.path().join("a/b")).unwrap();
+        fs::create_dir_all(root.path().join("drafts")).unwrap();
+        fs::write(root.path().join("a/one.html"), "").unwrap();
+        fs::write(root.path().join("a/two.txt"), "").unwrap();
+        fs::write(root.path().join("a/b/three.html"), "").unwrap();
+        fs::write(root.path().join("drafts/four.html"), "").unwrap();
+
+        let config = ContentConfig {
+            include: vec![String::from("**/*.html")],
+            exclude: vec![String::from("drafts"), String::from("a/b/*")],
+            ..ContentConfig::default()
+        };
+        assert_eq!(routes_for_config(root.path(), &config), vec!["/a/one"]);
+    }
+
+    #[test]
+    fn invalid_globs_are_rejected() {
+        let config = ContentConfig {
+            exclude: vec![String::from("[")],
+            ..ContentConfig::default()
+        };
+        let result = ContentDirectory::from_root_with_config(&sample_path("empty"), &config);
+        assert!(
+            matches!(
+                result,
+                Err(ContentDirectoryFromRootError::InvalidGlob { .. })
+            ),
+            "Invalid glob was not rejected",
+        );
+    }
+
+    #[test]
+    fn files_can_be_ignored_with_ignore_files() {
+        let root = tempfile::tempdir().expect("Failed to create temporary directory");
+        fs::create_dir_all(root.path().join("build")).unwrap();
+        fs::create_dir_all(root.path().join("nested")).unwrap();
+        fs::write(root.path().join("build/artifact.txt"), "").unwrap();
+        fs::write(root.path().join("page.html"), "").unwrap();
+        fs::write(root.path().join("page.html.swp"), "").unwrap();
+        fs::write(root.path().join("nested/keep.swp"), "").unwrap();
+        fs::write(root.path().join("nested/skip.txt"), "").unwrap();
+        fs::write(root.path().join(".operatorignore"), "build/\n*.swp\n").unwrap();
+        fs::write(
+            root.path().join("nested/.operatorignore"),
+            "!keep.swp\nskip.*\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            routes_for_config(root.path(), &ContentConfig::default()),
+            vec!["/nested/keep", "/page"]
+        );
+
+        // Ignore files are never content themselves, even if not hidden.
+        let config = ContentConfig {
+            hidden_prefix: String::new(),
+            ..ContentConfig::default()
+        };
+        assert_eq!(
+            routes_for_config(root.path(), &config),
+            vec!["/nested/keep", "/page"]
+        );
+    }
+
+    #[test]
+    fn directories_can_be_mounted_beneath_a_prefix() {
+        let main_root = tempfile::tempdir().expect("Failed to create temporary directory");
+        let docs_root = tempfile::tempdir().expect("Failed to create temporary directory");
+        fs::create_dir_all(docs_root.path().join("guide")).unwrap();
+        fs::write(main_root.path().join("home.html"), "").unwrap();
+        fs::write(docs_root.path().join("index.html"), "").unwrap();
+        fs::write(docs_root.path().join("guide/intro.md"), "").unwrap();
+
+        let mut directory = ContentDirectory::from_root(&main_root.path()).unwrap();
+        directory
+            .mount(
+                route("/docs"),
+                ContentDirectory::from_root(&docs_root.path()).unwrap(),
+            )
+            .expect("Mounting failed");
+
+        assert_eq!(directory.mounts(), &[route("/docs")]);
+        let mut files = directory
+            .into_iter()
+            .map(|file| (file.route.to_string(), file.relative_path))
+            .collect::<Vec<_>>();
+        files.sort();
+        assert_eq!(
+            files,
+            vec![
+                (
+                    String::from("/docs/guide/intro"),
+                    String::from("docs/guide/intro.md")
+                ),
+                (String::from("/docs/index"), String::from("docs/index.html")),
+                (String::from("/home"), String::from("home.html")),
+            ]
+        );
+    }
+    #[test]
+    fn directories_can_be_loaded_again_from_their_source() {
+        let main_root = tempfile::tempdir().expect("Failed to create temporary directory");
+        let docs_root = tempfile::tempdir().expect("Failed to create temporary directory");
+        fs::write(main_root.path().join("home.html"), "").unwrap();
+        fs::write(docs_root.path().join("index.html"), "").unwrap();
+
+        let mut directory = ContentDirectory::from_root(&main_root.path()).unwrap();
+        directory
+            .mount(
+                route("/docs"),
+                ContentDirectory::from_root(&docs_root.path()).unwrap(),
+            )
+            .expect("Mounting failed");
+        let source = directory.source();
+
+        fs::write(main_root.path().join("about.html"), "").unwrap();
+        fs::write(docs_root.path().join("faq.html"), "").unwrap();
+        let reloaded = source.load().expect("Reloading failed");
+
+        assert_eq!(reloaded.root(), main_root.path());
+        assert_eq!(reloaded.mounts(), &[route("/docs")]);
+        let mut routes = reloaded
+            .into_iter()
+            .map(|file| file.route.to_string())
+            .collect::<Vec<_>>();
+        routes.sort();
+        assert_eq!(routes, vec!["/about", "/docs/faq", "/docs/index", "/home"]);
+    }
 }
@@ -1,27 +1,199 @@
-use super::content_directory::{ContentDirectory, ContentFile};
+use super::content_directory::{ContentDirectory, ContentDirectorySource, ContentFile};
 use super::content_index::*;
 use super::content_item::*;
 use super::content_registry::*;
+#[cfg(feature = "executables")]
+use super::executable::Executable;
 use super::handlebars_helpers::*;
 use super::*;
 use crate::bug_message;
+use crate::clock::Clock;
+use crate::config::{Config, ContentConfig, ExecutablesConfig, TaskConfig};
+use crate::file_limit;
+use futures::executor;
+use futures::TryStreamExt;
 use handlebars::{self, Handlebars};
 use mime_guess::MimeGuess;
 use std::collections::hash_map::Entry;
-use std::collections::HashMap;
-use std::path::Path;
-use std::sync::{Arc, RwLock};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io::Read;
+#[cfg(feature = "executables")]
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, PoisonError, RwLock};
+#[cfg(feature = "executables")]
+use std::time::Duration;
+use std::time::{Instant, SystemTime};
 use thiserror::Error;
 
-/// Indicates that a handlebars template could not be registered.
+/// Indicates that a template could not be parsed or registered.
+///
+/// When the problem is at a known location, the error's message includes an
+/// annotated excerpt of the template source, like this:
+///
+/// ```text
+/// Invalid handlebars template 'example.html.hbs' (line 2, column 4): invalid handlebars syntax: ...
+///  --> example.html.hbs:2:4
+///   |
+/// 1 | <p>
+/// 2 | {{#if}}
+///   |    ^
+/// ```
 #[derive(Error, Debug)]
-#[error(
-  "Failed to register handlebars template{}.",
-  .source.name().map(|known_name| format!(" '{}'", known_name)).unwrap_or_default(),
-)]
 pub struct TemplateError {
-    #[from]
-    source: Box<handlebars::TemplateError>,
+    language: TemplateLanguage,
+    name: Option<String>,
+    position: Option<(usize, usize)>,
+    reason: String,
+    excerpt: Option<String>,
+}
+
+impl TemplateError {
+    /// How many lines before the problematic one to include in excerpts.
+    const EXCERPT_CONTEXT_LINES: usize = 2;
+
+    /// `line_offset` is the number of lines in `template_source` which come
+    /// before the part that handlebars parsed (e.g. front matter).
+    pub(super) fn new(
+        error: handlebars::TemplateError,
+        name: Option<String>,
+        template_source: &str,
+        line_offset: usize,
+    ) -> Self {
+        Self::in_language(
+            TemplateLanguage::Handlebars,
+            name,
+            error.pos(),
+            error.reason().to_string(),
+            template_source,
+            line_offset,
+        )
+    }
+
+    /// Like `new`, for templates in any language. `position` is the one-based
+    /// line and column within the parsed part of `template_source`.
+    pub(super) fn in_language(
+        language: TemplateLanguage,
+        name: Option<String>,
+        position: Option<(usize, usize)>,
+        reason: String,
+        template_source: &str,
+        line_offset: usize,
+    ) -> Self {
+        let position = position.map(|(line, column)| (line + line_offset, column));
+        let excerpt = position
+            .map(|(line, column)| Self::excerpt_of(name.as_deref(), template_source, line, column));
+        TemplateError {
+            language,
+            name,
+            position,
+            reason,
+            excerpt,
+        }
+    }
+
+    #[cfg(feature = "templating")]
+    fn unreadable(language: TemplateLanguage, name: String, error: std::io::Error) -> Self {
+        TemplateError {
+            language,
+            name: Some(name),
+            position: None,
+            reason: format!("Unable to read template: {}", error),
+            excerpt: None,
+        }
+    }
+
+    pub fn language(&self) -> TemplateLanguage {
+        self.language
+    }
+
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// The one-based line number where the problem was found, if known.
+    pub fn line(&self) -> Option<usize> {
+        self.position.map(|(line, _)| line)
+    }
+
+    /// The one-based column number where the problem was found, if known.
+    pub fn column(&self) -> Option<usize> {
+        self.position.map(|(_, column)| column)
+    }
+
+    pub fn reason(&self) -> &str {
+        &self.reason
+    }
+
+    /// The relevant lines of template source with the problematic position
+    /// marked, if it is known.
+    pub fn excerpt(&self) -> Option<&str> {
+        self.excerpt.as_deref()
+    }
+
+    fn excerpt_of(name: Option<&str>, template_source: &str, line: usize, column: usize) -> String {
+        let lines = template_source.lines().collect::<Vec<_>>();
+        let first_line = line.saturating_sub(Self::EXCERPT_CONTEXT_LINES).max(1);
+        let gutter_width = line.to_string().len();
+
+        let mut excerpt = format!(
+            "{:width$}--> {}:{}:{}\n{:width$} |",
+            "",
+            name.unwrap_or("<template>"),
+            line,
+            column,
+            "",
+            width = gutter_width,
+        );
+        for line_number in first_line..=line {
+            // Parse errors at the end of the file can point one line past the
+            // last one.
+            let line_source = lines.get(line_number - 1).copied().unwrap_or_default();
+            excerpt.push_str(&format!(
+                "\n{:>width$} | {}",
+                line_number,
+                line_source,
+                width = gutter_width,
+            ));
+        }
+
+        // Keep tabs so the marker lines up with the source above it.
+        let marker_indentation = lines
+            .get(line - 1)
+            .copied()
+            .unwrap_or_default()
+            .chars()
+            .chain(std::iter::repeat(' '))
+            .take(column.saturating_sub(1))
+            .map(|character| if character == '\t' { '\t' } else { ' ' })
+            .collect::<String>();
+        excerpt.push_str(&format!(
+            "\n{:width$} | {}^",
+            "",
+            marker_indentation,
+            width = gutter_width,
+        ));
+        excerpt
+    }
+}
+
+impl fmt::Display for TemplateError {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(formatter, "Invalid {} template", self.language)?;
+        if let Some(name) = &self.name {
+            write!(formatter, " '{}'", name)?;
+        }
+        if let Some((line, column)) = self.position {
+            write!(formatter, " (line {}, column {})", line, column)?;
+        }
+        write!(formatter, ": {}", self.reason)?;
+        if let Some(excerpt) = &self.excerpt {
+            write!(formatter, "\n{}", excerpt)?;
+        }
+        Ok(())
+    }
 }
 
 /// Indicates that there was a problem loading content from the filesystem.
@@ -36,9 +208,48 @@ pub enum ContentLoadingError {
     #[error("There are multiple content files for route {} with the same media type ({}).", .route, .media_type)]
     DuplicateContent { route: Route, media_type: MediaType },
 
+    #[error(transparent)]
+    FrontMatterError(#[from] FrontMatterError),
+
     #[error("Content file has an unknown media type: {}", .0)]
     UnknownFileType(String),
 
+    #[error("Data file is invalid: {}", .0)]
+    InvalidDataFile(String),
+
+    #[error("Redirect file is invalid: {}", .0)]
+    InvalidRedirect(String),
+
+    #[error("Route {} has both a redirect and other content.", .route)]
+    ConflictingRedirect { route: Route },
+
+    #[error("Executable is not runnable: {}", .0)]
+    ExecutableProbeFailed(RenderingFailedError),
+
+    #[error(
+        "The content file '{}' is an executable, but this build does not support executables.",
+        .0
+    )]
+    ExecutablesNotSupported(String),
+
+    #[error(
+        "The content file '{}' is a template, but this build does not support templates.",
+        .0
+    )]
+    TemplatesNotSupported(String),
+
+    #[error("Rendering as {} failed.", .media_type)]
+    RenderCheckFailed {
+        media_type: MediaType,
+        source: Box<RenderError>,
+    },
+
+    #[error("Rendering as {} failed partway through.", .media_type)]
+    RenderCheckStreamFailed {
+        media_type: MediaType,
+        source: Box<StreamError>,
+    },
+
     #[error("Failed to create route index while loading content directory.")]
     ContentIndexError {
         #[from]
@@ -49,17 +260,159 @@ pub enum ContentLoadingError {
     Bug(String),
 }
 
+impl ContentLoadingError {
+    /// Whether this error is caused by a file that Operator doesn't know how
+    /// to serve (as opposed to a problem with otherwise-valid content). These
+    /// files can be skipped in lenient mode.
+    pub fn is_unsupported_file(&self) -> bool {
+        matches!(
+            self,
+            ContentLoadingError::ContentFileNameError(_) | ContentLoadingError::UnknownFileType(_)
+        )
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum ContentReloadError {
+    #[error("Unable to read content directory.")]
+    ContentDirectoryError {
+        #[from]
+        source: ContentDirectoryFromRootError,
+    },
+
+    #[error("Unable to load content.")]
+    ContentLoadingError {
+        #[from]
+        source: ContentLoadingErrors,
+    },
+}
+
+/// What [`FilesystemBasedContentEngine::reload_changed`] loaded again.
+#[derive(Debug, PartialEq, Eq)]
+pub enum ReloadedContent {
+    /// None of the changed files are part of the content directory.
+    Nothing,
+
+    /// Only the changed files were loaded again. These are the routes whose
+    /// rendered output may be different now: the routes of the changed files
+    /// and every route which includes them (see [`DependencyGraph`]).
+    Routes(BTreeSet<Route>),
+
+    /// The whole content directory was loaded again.
+    Everything,
+}
+
+/// Content files which were loaded again on their own, before they replace
+/// what the engine has.
+struct ContentUpdate<'engine> {
+    handlebars_registry: Handlebars<'engine>,
+    minijinja_engine: MiniJinjaEngine,
+    contents: Vec<(Route, MediaType, RegisteredContent)>,
+}
+
+/// All of the problems found while loading a content directory, each paired
+/// with the path of the content file (relative to the content directory) that
+/// caused it. Problems found by running content after it is loaded are paired
+/// with its route instead.
+#[derive(Error, Debug)]
+pub struct ContentLoadingErrors(Vec<(String, ContentLoadingError)>);
+
+impl ContentLoadingErrors {
+    pub fn iter(&self) -> impl Iterator<Item = &(String, ContentLoadingError)> {
+        self.0.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Describes each problem on a single line, including its underlying
+    /// causes.
+    pub fn messages(&self) -> impl Iterator<Item = String> + '_ {
+        self.0.iter().map(|(relative_path, error)| {
+            let mut message = format!("{}: {}", relative_path, error);
+            let mut source = error.source();
+            while let Some(cause) = source {
+                message.push_str(&format!(": {}", cause));
+                source = cause.source();
+            }
+            message
+        })
+    }
+}
+
+impl fmt::Display for ContentLoadingErrors {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            formatter,
+            "Found {} problem{} in the content directory:",
+            self.0.len(),
+            if self.0.len() == 1 { "" } else { "s" },
+        )?;
+        for message in self.messages() {
+            write!(formatter, "\n  {}", message.replace('\n', "\n    "))?;
+        }
+        Ok(())
+    }
+}
+
+/// Everything rendering needs to know about a collection of content.
+///
+/// This trait is object safe, so engines can be layered at runtime (e.g. a
+/// `Box<dyn ContentEngine<ServerInfo> + Send + Sync>` wrapping another
+/// engine). Boxed and `Arc`ed engines are themselves engines.
 pub trait ContentEngine<ServerInfo>
 where
-    Self: Sized,
     ServerInfo: Clone + Serialize,
 {
+    /// The data that content is rendered with for a request.
+    fn render_data(
+        &self,
+        request_route: Option<Route>,
+        query_parameters: QueryParameters,
+        request_headers: HashMap<String, String>,
+    ) -> RenderData<ServerInfo>;
+
+    /// A context for rendering content from this engine, using
+    /// [`render_data`](#tymethod.render_data).
     fn render_context(
         &self,
         request_route: Option<Route>,
-        query_parameters: HashMap<String, String>,
+        query_parameters: QueryParameters,
         request_headers: HashMap<String, String>,
-    ) -> RenderContext<ServerInfo, Self>;
+    ) -> RenderContext<'_, ServerInfo, Self>
+    where
+        Self: Sized,
+    {
+        RenderContext {
+            content_engine: self,
+            handlebars_render_context: None,
+            refresh_caches: false,
+            data: self.render_data(request_route, query_parameters, request_headers),
+        }
+    }
+
+    /// Renders `content` (from [`get`](#tymethod.get)) with `render_data`.
+    /// Engines which wrap other engines can override this to change how
+    /// content is rendered (e.g. to reuse earlier output).
+    fn render_content(
+        &self,
+        content: &ContentRepresentations,
+        render_data: RenderData<ServerInfo>,
+        acceptable_media_ranges: &[MediaRange],
+    ) -> Result<Media<Box<dyn ByteStream>>, RenderError> {
+        let render_context = RenderContext {
+            content_engine: self,
+            handlebars_render_context: None,
+            refresh_caches: false,
+            data: render_data,
+        };
+        content.render(render_context, acceptable_media_ranges)
+    }
 
     fn new_template(
         &self,
@@ -69,10 +422,113 @@ where
 
     fn get(&self, route: &Route) -> Option<&ContentRepresentations>;
 
-    fn handlebars_registry(&self) -> &Handlebars;
+    fn handlebars_registry(&self) -> &Handlebars<'_>;
+
+    /// The full-text index of the content, if search is enabled.
+    fn search_index(&self) -> Option<&SearchIndex>;
+
+    /// What the content looked like when it was loaded, if this engine keeps
+    /// track.
+    fn stats(&self) -> Option<&ContentStats>;
+
+    /// Where requests for `route` should be sent instead, if it is a
+    /// redirect.
+    fn redirect(&self, route: &Route) -> Option<&Redirect>;
+
+    /// The engine for templates in `language`, with the given strict mode
+    /// (templates can override the default strict mode).
+    fn template_engine(&self, language: TemplateLanguage, strict: bool) -> &dyn TemplateEngine;
+
+    /// The current source (including front matter) of the template named
+    /// `template_name`, if this engine can find it. This is used to show
+    /// where renders failed, so it may be slow.
+    fn template_source(&self, _template_name: &str) -> Option<String> {
+        None
+    }
+}
+macro_rules! forward_content_engine {
+    ($($pointer:ident),*) => {$(
+        impl<ServerInfo, Engine> ContentEngine<ServerInfo> for $pointer<Engine>
+        where
+            ServerInfo: Clone + Serialize,
+            Engine: ContentEngine<ServerInfo> + ?Sized,
+        {
+            fn render_data(
+                &self,
+                request_route: Option<Route>,
+                query_parameters: QueryParameters,
+                request_headers: HashMap<String, String>,
+            ) -> RenderData<ServerInfo> {
+                (**self).render_data(request_route, query_parameters, request_headers)
+            }
+
+            fn render_content(
+                &self,
+                content: &ContentRepresentations,
+                render_data: RenderData<ServerInfo>,
+                acceptable_media_ranges: &[MediaRange],
+            ) -> Result<Media<Box<dyn ByteStream>>, RenderError> {
+                (**self).render_content(content, render_data, acceptable_media_ranges)
+            }
+
+            fn new_template(
+                &self,
+                template_source: &str,
+                media_type: MediaType,
+            ) -> Result<UnregisteredTemplate, TemplateError> {
+                (**self).new_template(template_source, media_type)
+            }
+
+            fn get(&self, route: &Route) -> Option<&ContentRepresentations> {
+                (**self).get(route)
+            }
+
+            fn handlebars_registry(&self) -> &Handlebars<'_> {
+                (**self).handlebars_registry()
+            }
+
+            fn search_index(&self) -> Option<&SearchIndex> {
+                (**self).search_index()
+            }
+
+            fn stats(&self) -> Option<&ContentStats> {
+                (**self).stats()
+            }
+
+            fn redirect(&self, route: &Route) -> Option<&Redirect> {
+                (**self).redirect(route)
+            }
+
+            fn template_engine(
+                &self,
+                language: TemplateLanguage,
+                strict: bool,
+            ) -> &dyn TemplateEngine {
+                (**self).template_engine(language, strict)
+            }
+
+            fn template_source(&self, template_name: &str) -> Option<String> {
+                (**self).template_source(template_name)
+            }
+        }
+    )*};
 }
+forward_content_engine!(Box, Arc);
+
 pub trait InternalContentEngine {
     fn get_internal(&self, route: &Route) -> Option<&ContentRepresentations>;
+
+    /// The filename extension of the content file which provides `route` in
+    /// `media_type` (e.g. `md` for `foo.md.hbs`).
+    fn url_extension(&self, route: &Route, media_type: &MediaType) -> Option<&str>;
+
+    fn index(&self) -> &ContentIndex;
+
+    /// The settings that the engine renders with.
+    fn config(&self) -> &Config;
+
+    /// Records that rendering `route` included `dependency`.
+    fn record_dependency(&self, route: Route, dependency: Route);
 }
 
 /// A [`ContentEngine`](trait.ContentEngine.html) that serves files from a
@@ -82,133 +538,1181 @@ where
     ServerInfo: Clone + Serialize,
 {
     server_info: ServerInfo,
+    config: Config,
     index: ContentIndex,
     content_registry: ContentRegistry,
     handlebars_registry: Handlebars<'engine>,
+
+    /// A copy of `handlebars_registry` with the opposite strict mode. This
+    /// only exists if some template needs it.
+    alternate_handlebars_registry: Option<Handlebars<'engine>>,
+
+    minijinja_engine: MiniJinjaEngine,
+
+    /// Like `alternate_handlebars_registry`, for `minijinja_engine`.
+    alternate_minijinja_engine: Option<MiniJinjaEngine>,
+
+    /// Parsed files from the data directory.
+    data: SiteData,
+
+    /// Only built if the config asks for search.
+    search_index: Option<SearchIndex>,
+
+    /// Route prefixes that other content directories are mounted at.
+    mounts: Vec<Route>,
+
+    /// Which routes include which others. Rendering adds to this.
+    dependency_graph: Mutex<DependencyGraph>,
+
+    /// Where each content file came from, relative to the content directory.
+    relative_paths: BTreeSet<String>,
+
+    /// What the content directory looked like when it was loaded.
+    stats: ContentStats,
+
+    /// Where the content came from, for reloading.
+    source: ContentDirectorySource,
+
+    /// Held while reloading so that reloads happen one at a time (otherwise
+    /// content loaded by a slow reload could replace newer content). This
+    /// is carried over to each engine which replaces this one.
+    reload_lock: Arc<Mutex<()>>,
 }
 
+/// The contents of the data directory, as exposed to renders.
+type SiteData = serde_json::Map<String, serde_json::Value>;
+
 impl<'engine, ServerInfo> FilesystemBasedContentEngine<'engine, ServerInfo>
 where
     ServerInfo: 'static + Clone + Serialize + Send + Sync,
 {
-    const HANDLEBARS_FILE_EXTENSION: &'static str = "hbs";
+    /// Files within this route are data rather than content.
+    pub const DATA_DIRECTORY_ROUTE: &'static str = "/_data";
+
+    /// Files with this extension are redirects rather than content.
+    pub const REDIRECT_EXTENSION: &'static str = "redirect";
+
+    /// Executables find this set to `1` in their environment when rendering
+    /// is [deterministic](Config::deterministic).
+    pub const DETERMINISTIC_ENVIRONMENT_VARIABLE: &'static str = "OPERATOR_DETERMINISTIC";
 
     pub fn from_content_directory(
         content_directory: ContentDirectory,
         server_info: ServerInfo,
-    ) -> Result<Arc<RwLock<Self>>, ContentLoadingError> {
-        let (index_entries, content_registry, handlebars_registry) =
-            Self::set_up_registries(content_directory)?;
-
-        let content_engine = FilesystemBasedContentEngine {
-            server_info,
-            index: ContentIndex::Directory(index_entries),
-            content_registry,
-            handlebars_registry,
-        };
+    ) -> Result<Arc<RwLock<Self>>, ContentLoadingErrors> {
+        Self::from_content_directory_with_config(content_directory, server_info, Config::default())
+    }
 
+    /// Like `from_content_directory`, but with settings which apply while
+    /// rendering. Content settings always come from the `ContentDirectory`.
+    pub fn from_content_directory_with_config(
+        content_directory: ContentDirectory,
+        server_info: ServerInfo,
+        config: Config,
+    ) -> Result<Arc<RwLock<Self>>, ContentLoadingErrors> {
+        let content_engine = Self::load(content_directory, server_info, config)?;
         let shared_content_engine = Arc::new(RwLock::new(content_engine));
-
-        let get_helper = GetHelper::new(shared_content_engine.clone());
         shared_content_engine
             .write()
             .expect("RwLock for ContentEngine has been poisoned")
-            .handlebars_registry
-            .register_helper("get", Box::new(get_helper));
-
+            .register_helpers(&shared_content_engine);
+        Self::build_search_index_if_enabled(&shared_content_engine);
         Ok(shared_content_engine)
     }
 
-    fn set_up_registries<'a, E: IntoIterator<Item = ContentFile>>(
-        content_item_entries: E,
-    ) -> Result<(ContentIndexEntries, ContentRegistry, Handlebars<'a>), ContentLoadingError> {
-        let mut index = ContentIndexEntries::new();
-        let mut handlebars_registry = Handlebars::new();
-        let mut content_registry = ContentRegistry::new();
-        handlebars_registry.set_strict_mode(true);
-        for entry in content_item_entries {
-            let extensions = entry.extensions.to_owned();
-            match extensions.as_slice() {
-                [single_extension] => Self::register_content_file_with_one_extension(
-                    entry,
-                    single_extension,
-                    &mut index,
+    /// Loads the content directory again and replaces the content of
+    /// `shared_content_engine` with it. Renders that are already in progress
+    /// finish with the old content. If loading fails nothing changes.
+    pub fn reload(shared_content_engine: &Arc<RwLock<Self>>) -> Result<(), ContentReloadError> {
+        let reload_lock = Self::reload_lock(shared_content_engine);
+        let _reloading = reload_lock.lock().unwrap_or_else(PoisonError::into_inner);
+        Self::reload_everything(shared_content_engine)
+    }
+
+    fn reload_lock(shared_content_engine: &Arc<RwLock<Self>>) -> Arc<Mutex<()>> {
+        shared_content_engine
+            .read()
+            .expect("RwLock for ContentEngine has been poisoned")
+            .reload_lock
+            .clone()
+    }
+
+    /// Like [`reload`](Self::reload), for callers which hold the reload
+    /// lock.
+    fn reload_everything(
+        shared_content_engine: &Arc<RwLock<Self>>,
+    ) -> Result<(), ContentReloadError> {
+        let (source, server_info, config) = {
+            let content_engine = shared_content_engine
+                .read()
+                .expect("RwLock for ContentEngine has been poisoned");
+            (
+                content_engine.source.clone(),
+                content_engine.server_info.clone(),
+                content_engine.config.clone(),
+            )
+        };
+
+        let mut content_engine = Self::load(source.load()?, server_info, config)?;
+        content_engine.register_helpers(shared_content_engine);
+        {
+            let mut current_content_engine = shared_content_engine
+                .write()
+                .expect("RwLock for ContentEngine has been poisoned");
+            // Keep answering searches from the old index until the new one
+            // is built.
+            content_engine.search_index = current_content_engine.search_index.take();
+            content_engine.reload_lock = current_content_engine.reload_lock.clone();
+            *current_content_engine = content_engine;
+        }
+        Self::build_search_index_if_enabled(shared_content_engine);
+        Ok(())
+    }
+
+    /// Loads content again after the files at `changed_paths` (relative to
+    /// the content directory, with files from mounted directories prefixed
+    /// like their routes) changed.
+    ///
+    /// Templates and static files which were modified in place are loaded
+    /// again on their own. Anything else (like files which were added,
+    /// removed, or renamed, data files, redirects, executables, and ignore
+    /// files) loads everything again, as does an empty `changed_paths`. Like
+    /// [`reload`](Self::reload), nothing changes if loading fails, and this
+    /// waits for any other reload to finish first.
+    pub fn reload_changed<P: AsRef<str>>(
+        shared_content_engine: &Arc<RwLock<Self>>,
+        changed_paths: &[P],
+    ) -> Result<ReloadedContent, ContentReloadError> {
+        let reload_lock = Self::reload_lock(shared_content_engine);
+        let _reloading = reload_lock.lock().unwrap_or_else(PoisonError::into_inner);
+        if changed_paths.is_empty() {
+            Self::reload_everything(shared_content_engine)?;
+            return Ok(ReloadedContent::Everything);
+        }
+        let source = shared_content_engine
+            .read()
+            .expect("RwLock for ContentEngine has been poisoned")
+            .source
+            .clone();
+        let content_directory = source.load()?;
+
+        let update = {
+            let content_engine = shared_content_engine
+                .read()
+                .expect("RwLock for ContentEngine has been poisoned");
+            match content_engine.modified_files(content_directory, changed_paths) {
+                None => None,
+                Some(modified_files) if modified_files.is_empty() => {
+                    return Ok(ReloadedContent::Nothing)
+                }
+                Some(modified_files) => content_engine.load_modified_files(modified_files)?,
+            }
+        };
+        match update {
+            None => {
+                log::info!("Changes cannot be loaded on their own, so reloading everything");
+                Self::reload_everything(shared_content_engine)?;
+                Ok(ReloadedContent::Everything)
+            }
+            Some(update) => {
+                let affected_routes = shared_content_engine
+                    .write()
+                    .expect("RwLock for ContentEngine has been poisoned")
+                    .apply_update(update);
+                Self::build_search_index_if_enabled(shared_content_engine);
+                Ok(ReloadedContent::Routes(affected_routes))
+            }
+        }
+    }
+
+    /// The files at `changed_paths` in `content_directory`, if they were all
+    /// modified in place (or are not content at all). Otherwise the content
+    /// directory's structure changed and `None` is returned.
+    fn modified_files<P: AsRef<str>>(
+        &self,
+        content_directory: ContentDirectory,
+        changed_paths: &[P],
+    ) -> Option<Vec<ContentFile>> {
+        let mut content_files = content_directory
+            .into_iter()
+            .map(|content_file| (content_file.relative_path.clone(), content_file))
+            .collect::<HashMap<_, _>>();
+        let mut modified_files = Vec::new();
+        for changed_path in changed_paths {
+            let changed_path = changed_path.as_ref();
+            let is_ignore_file = changed_path
+                .rsplit(ContentFile::PATH_SEPARATOR)
+                .next()
+                .is_some_and(|file_name| file_name == ContentDirectory::IGNORE_FILE_NAME);
+            match (
+                self.relative_paths.contains(changed_path),
+                content_files.remove(changed_path),
+            ) {
+                (true, Some(content_file)) => modified_files.push(content_file),
+                (false, None) if !is_ignore_file => {}
+                _ => return None,
+            }
+        }
+        Some(modified_files)
+    }
+
+    /// Registers `modified_files` again without changing anything yet. This
+    /// is `None` if some of them cannot be registered on their own.
+    fn load_modified_files(
+        &self,
+        modified_files: Vec<ContentFile>,
+    ) -> Result<Option<ContentUpdate<'engine>>, ContentLoadingErrors> {
+        let data_directory_route = Self::DATA_DIRECTORY_ROUTE
+            .parse::<Route>()
+            .expect(bug_message!("The data directory route was invalid."));
+        let mut handlebars_registry = self.handlebars_registry.clone();
+        let mut minijinja_engine = self.minijinja_engine.clone();
+        let mut content_registry =
+            ContentRegistry::new(self.config.content.internal_prefix.clone());
+        let mut errors = Vec::new();
+        let mut contents = Vec::new();
+        for content_file in modified_files {
+            let media_type = match content_file.extensions.first().and_then(|extension| {
+                MimeGuess::from_ext(extension)
+                    .first()
+                    .and_then(MediaType::from_media_range)
+            }) {
+                Some(media_type) => media_type,
+                None => return Ok(None),
+            };
+            let existing_content = self
+                .content_registry
+                .get_internal(&content_file.route)
+                .and_then(|representations| representations.get(&media_type));
+            let is_loadable_on_its_own = !content_file.is_executable
+                && !content_file.route.is_within(&data_directory_route)
+                && match (content_file.extensions.as_slice(), existing_content) {
+                    ([extension], Some(content @ RegisteredContent::StaticContentItem(_))) => {
+                        extension != Self::REDIRECT_EXTENSION
+                            && content.precompressed_codings().is_empty()
+                    }
+                    (
+                        [_, second_extension],
+                        Some(RegisteredContent::RegisteredTemplate(template)),
+                    ) => {
+                        TemplateLanguage::from_file_extension(second_extension).is_some()
+                            && template.name_in_registry() == content_file.relative_path
+                    }
+                    _ => false,
+                };
+            if !is_loadable_on_its_own {
+                return Ok(None);
+            }
+
+            handlebars_registry.unregister_template(&content_file.relative_path);
+            minijinja_engine.remove_template(&content_file.relative_path);
+            let route = content_file.route.clone();
+            let relative_path = content_file.relative_path.clone();
+            let extensions = content_file.extensions.clone();
+            let result = match extensions.as_slice() {
+                [extension] => Self::register_content_file_with_one_extension(
+                    content_file,
+                    extension,
+                    &mut ContentIndexEntries::new(),
                     &mut content_registry,
-                )?,
+                    &self.config.content,
+                ),
                 [first_extension, second_extension] => {
                     Self::register_content_file_with_two_extensions(
-                        entry,
+                        content_file,
                         first_extension,
                         second_extension,
-                        &mut index,
+                        &mut ContentIndexEntries::new(),
                         &mut content_registry,
                         &mut handlebars_registry,
-                    )?
-                }
-                [_, _, _, ..] => {
-                    return Err(ContentLoadingError::ContentFileNameError(format!(
-                        "Content file name '{}' has too many extensions.",
-                        entry.relative_path
-                    )))
-                }
-                [] => {
-                    return Err(ContentLoadingError::ContentFileNameError(format!(
-                        "Content file names must have extensions, but '{}' does not.",
-                        entry.relative_path
-                    )))
+                        &mut minijinja_engine,
+                        &self.config.content,
+                        &Self::executables_config(&self.config),
+                    )
                 }
+                _ => return Ok(None),
+            };
+            match result.and_then(|()| {
+                content_registry
+                    .get_internal_mut(&route)
+                    .and_then(|representations| representations.remove(&media_type))
+                    .ok_or_else(|| {
+                        ContentLoadingError::Bug(String::from(
+                            "Content file was registered but its content was not found.",
+                        ))
+                    })
+            }) {
+                Ok(content) => contents.push((route, media_type, content)),
+                Err(error) => errors.push((relative_path, error)),
             }
         }
 
-        Ok((index, content_registry, handlebars_registry))
+        if errors.is_empty() {
+            Ok(Some(ContentUpdate {
+                handlebars_registry,
+                minijinja_engine,
+                contents,
+            }))
+        } else {
+            errors.sort_by(|(a, _), (b, _)| a.cmp(b));
+            Err(ContentLoadingErrors(errors))
+        }
     }
 
-    /// Content files with one extension indicate static content (e.g. an image
-    /// or plain text file). They must not have the executable bit set.
-    fn register_content_file_with_one_extension(
-        content: ContentFile,
-        extension: &str,
-        index: &mut ContentIndexEntries,
-        content_registry: &mut ContentRegistry,
-    ) -> Result<(), ContentLoadingError> {
-        if content.is_executable {
-            return Err(ContentLoadingError::ContentFileNameError(format!(
-                "The content file '{}' is executable, but only has one extension ('{}'). \
-                    Executables must have two extensions: \
-                    the first indicates the media type of its output, and the second is arbitrary \
-                    but can be used to indicate the executable type ('.sh', '.exe', '.py', etc).",
-                content.relative_path, extension,
-            )));
+    /// Replaces content with what was loaded again, returning the routes whose
+    /// rendered output may be different now.
+    fn apply_update(&mut self, update: ContentUpdate<'engine>) -> BTreeSet<Route> {
+        self.handlebars_registry = update.handlebars_registry;
+        self.minijinja_engine = update.minijinja_engine;
+        let mut changed_routes = BTreeSet::new();
+        for (route, media_type, content) in update.contents {
+            if let Some(representations) = self.content_registry.get_internal_mut(&route) {
+                representations.insert(media_type, content);
+            }
+            changed_routes.insert(route);
         }
 
-        let mime =
-            MimeGuess::from_ext(extension)
-                .first()
-                .ok_or_else(|| ContentLoadingError::UnknownFileType(
-                    format!(
-                        "The filename extension for the file at '{}' ('{}') does not map to any known media type.",
-                        content.relative_path,
-                        extension,
-                    ),
-                ))?;
-        let media_type = MediaType::from_media_range(mime).ok_or_else(|| {
-            ContentLoadingError::Bug(String::from("Mime guess was not a concrete media type!"))
-        })?;
+        // Strict mode may have changed along with front matter.
+        self.alternate_handlebars_registry = if Self::needs_alternate_template_engine(
+            &self.content_registry,
+            &self.config,
+            TemplateLanguage::Handlebars,
+        ) {
+            let mut alternate_handlebars_registry = self.handlebars_registry.clone();
+            alternate_handlebars_registry.set_strict_mode(!self.config.content.strict_templates);
+            Some(alternate_handlebars_registry)
+        } else {
+            None
+        };
+        self.alternate_minijinja_engine = if Self::needs_alternate_template_engine(
+            &self.content_registry,
+            &self.config,
+            TemplateLanguage::MiniJinja,
+        ) {
+            let mut alternate_minijinja_engine = self.minijinja_engine.clone();
+            alternate_minijinja_engine.set_strict_mode(!self.config.content.strict_templates);
+            Some(alternate_minijinja_engine)
+        } else {
+            None
+        };
 
-        let file = content.file;
-        Self::register_content(
-            content_registry,
-            index,
-            content.route,
-            media_type.clone(),
-            || RegisteredContent::StaticContentItem(StaticContentItem::new(file, media_type)),
-        )
+        // Dependencies found while rendering unchanged routes still apply.
+        let previous_dependency_graph = self.dependency_graph();
+        let mut dependency_graph =
+            DependencyGraph::from_templates(&self.content_registry, &self.handlebars_registry);
+        for (route, dependencies) in previous_dependency_graph.iter() {
+            if !changed_routes.contains(route) {
+                for dependency in dependencies {
+                    dependency_graph.add(route.clone(), dependency.clone());
+                }
+            }
+        }
+        let mut affected_routes = previous_dependency_graph.affected_routes(&changed_routes);
+        affected_routes.extend(dependency_graph.affected_routes(&changed_routes));
+        self.dependency_graph = Mutex::new(dependency_graph);
+        affected_routes
+    }
+
+    fn load(
+        content_directory: ContentDirectory,
+        server_info: ServerInfo,
+        config: Config,
+    ) -> Result<Self, ContentLoadingErrors> {
+        let config = Config {
+            content: content_directory.config().clone(),
+            ..config
+        };
+        let started_at = Instant::now();
+        let source = content_directory.source();
+        let mounts = content_directory.mounts().to_vec();
+        let mut relative_paths = BTreeSet::new();
+        let mut stats = ContentStats::default();
+        for content_file in &content_directory {
+            relative_paths.insert(content_file.relative_path.clone());
+            stats.files += 1;
+            stats.bytes += content_file
+                .file
+                .metadata()
+                .map(|metadata| metadata.len())
+                .unwrap_or_default();
+            *stats
+                .kinds
+                .entry(Self::content_kind(content_file))
+                .or_default() += 1;
+        }
+        let (index_entries, content_registry, handlebars_registry, minijinja_engine, data) =
+            Self::set_up_registries(
+                content_directory,
+                &config.content,
+                &Self::executables_config(&config),
+            )?;
+
+        let dependency_graph =
+            DependencyGraph::from_templates(&content_registry, &handlebars_registry);
+
+        stats.open_files = content_registry
+            .iter()
+            .flat_map(|(_, representations)| representations.values())
+            .map(|content| match content {
+                RegisteredContent::StaticContentItem(_) => {
+                    1 + content.precompressed_codings().len()
+                }
+                _ => 0,
+            })
+            .sum();
+        stats.open_file_limit = file_limit::soft_limit();
+        stats.load_time = started_at.elapsed();
+        stats.log();
+
+        let alternate_minijinja_engine = if Self::needs_alternate_template_engine(
+            &content_registry,
+            &config,
+            TemplateLanguage::MiniJinja,
+        ) {
+            let mut alternate_minijinja_engine = minijinja_engine.clone();
+            alternate_minijinja_engine.set_strict_mode(!config.content.strict_templates);
+            Some(alternate_minijinja_engine)
+        } else {
+            None
+        };
+
+        Ok(FilesystemBasedContentEngine {
+            server_info,
+            config,
+            index: ContentIndex::Directory(index_entries),
+            content_registry,
+            handlebars_registry,
+            alternate_handlebars_registry: None,
+            minijinja_engine,
+            alternate_minijinja_engine,
+            data,
+            search_index: None,
+            mounts,
+            dependency_graph: Mutex::new(dependency_graph),
+            relative_paths,
+            stats,
+            source,
+            reload_lock: Arc::default(),
+        })
+    }
+
+    /// The settings for executables, with `OPERATOR_DETERMINISTIC=1` added
+    /// to their environment in [deterministic](Config::deterministic) mode.
+    fn executables_config(config: &Config) -> Arc<ExecutablesConfig> {
+        let mut executables_config = config.executables.clone();
+        if config.deterministic {
+            executables_config.env.insert(
+                String::from(Self::DETERMINISTIC_ENVIRONMENT_VARIABLE),
+                String::from("1"),
+            );
+        }
+        Arc::new(executables_config)
+    }
+
+    /// What kind of file `content_file` is, going by its location and name.
+    fn content_kind(content_file: &ContentFile) -> ContentKind {
+        let data_directory_route = Self::DATA_DIRECTORY_ROUTE
+            .parse::<Route>()
+            .expect(bug_message!("The data directory route was invalid."));
+        match content_file.extensions.as_slice() {
+            _ if content_file.route.is_within(&data_directory_route) => ContentKind::Data,
+            _ if content_file.is_executable => ContentKind::Executable,
+            [extension] if extension == Self::REDIRECT_EXTENSION => ContentKind::Redirect,
+            [_, second_extension]
+                if ContentCoding::from_file_extension(second_extension).is_some() =>
+            {
+                ContentKind::Precompressed
+            }
+            [_, second_extension]
+                if TemplateLanguage::from_file_extension(second_extension).is_some() =>
+            {
+                ContentKind::Template
+            }
+            _ => ContentKind::Static,
+        }
+    }
+
+    /// Whether some template in `language` has a different strict mode than
+    /// the default.
+    fn needs_alternate_template_engine(
+        content_registry: &ContentRegistry,
+        config: &Config,
+        language: TemplateLanguage,
+    ) -> bool {
+        content_registry
+            .iter()
+            .flat_map(|(_, representations)| representations.values())
+            .any(|content| match content {
+                RegisteredContent::RegisteredTemplate(template) => {
+                    template.language() == language
+                        && template.is_strict() != config.content.strict_templates
+                }
+                _ => false,
+            })
+    }
+
+    /// Registers handlebars helpers, some of which render other content via
+    /// `shared_content_engine` (which is expected to end up holding `self`).
+    fn register_helpers(&mut self, shared_content_engine: &Arc<RwLock<Self>>) {
+        let get_helper = GetHelper::new(shared_content_engine.clone());
+        let layout_helper = LayoutHelper::new(GetHelper::new(shared_content_engine.clone()));
+        let representations_helper = RepresentationsHelper::new(shared_content_engine.clone());
+        let breadcrumbs_helper = BreadcrumbsHelper::new(shared_content_engine.clone());
+        let navigation_helper = NavigationHelper::new(shared_content_engine.clone());
+        let file_info_helper =
+            FileInfoHelper::new(shared_content_engine.clone(), self.config.deterministic);
+        let integrity_helper = IntegrityHelper::new(shared_content_engine.clone());
+        let clock = Clock::for_config(&self.config);
+        let timezone = self.config.time.timezone;
+        self.handlebars_registry
+            .register_helper("get", Box::new(get_helper));
+        self.handlebars_registry
+            .register_helper("layout", Box::new(layout_helper));
+        self.handlebars_registry
+            .register_helper("representations", Box::new(representations_helper));
+        self.handlebars_registry
+            .register_helper("absolute-url", Box::new(AbsoluteUrlHelper));
+        self.handlebars_registry
+            .register_helper("route-join", Box::new(RouteJoinHelper));
+        self.handlebars_registry
+            .register_helper("url-path", Box::new(UrlPathHelper));
+        self.handlebars_registry
+            .register_helper("paginate", Box::new(PaginateHelper));
+        self.handlebars_registry
+            .register_helper("breadcrumbs", Box::new(breadcrumbs_helper));
+        self.handlebars_registry
+            .register_helper("navigation", Box::new(navigation_helper));
+        self.handlebars_registry
+            .register_helper("now", Box::new(NowHelper::new(clock, timezone)));
+        self.handlebars_registry
+            .register_helper("format-date", Box::new(FormatDateHelper::new(timezone)));
+        self.handlebars_registry
+            .register_helper("file-info", Box::new(file_info_helper));
+        self.handlebars_registry
+            .register_helper("integrity", Box::new(integrity_helper));
+        self.handlebars_registry
+            .register_helper("highlight", Box::new(HighlightHelper));
+        self.handlebars_registry
+            .register_helper("toc", Box::new(TocHelper));
+        self.handlebars_registry
+            .register_helper("og-meta", Box::new(OgMetaHelper));
+        self.handlebars_registry
+            .register_helper("sanitize-html", Box::new(SanitizeHtmlHelper));
+        self.handlebars_registry
+            .register_helper("json-escape", Box::new(JsonEscapeHelper));
+        self.handlebars_registry
+            .register_helper("attr-escape", Box::new(AttrEscapeHelper));
+        self.handlebars_registry
+            .register_helper("html-escape", Box::new(HtmlEscapeHelper));
+        self.handlebars_registry
+            .register_helper("xml-escape", Box::new(XmlEscapeHelper));
+        self.handlebars_registry
+            .register_helper("cdata", Box::new(CdataHelper));
+        self.handlebars_registry
+            .register_helper("to-json", Box::new(ToJsonHelper));
+        self.handlebars_registry
+            .register_helper("to-csv", Box::new(ToCsvHelper));
+        if Self::needs_alternate_template_engine(
+            &self.content_registry,
+            &self.config,
+            TemplateLanguage::Handlebars,
+        ) {
+            let mut alternate_handlebars_registry = self.handlebars_registry.clone();
+            alternate_handlebars_registry.set_strict_mode(!self.config.content.strict_templates);
+            self.alternate_handlebars_registry = Some(alternate_handlebars_registry);
+        }
+    }
+
+    /// Which routes include which others, as found in templates and while
+    /// rendering so far.
+    pub fn dependency_graph(&self) -> DependencyGraph {
+        self.dependency_graph
+            .lock()
+            .expect("Mutex for DependencyGraph has been poisoned")
+            .clone()
+    }
+
+    /// Content can only be rendered for the search index once helpers are
+    /// registered.
+    fn build_search_index_if_enabled(shared_content_engine: &RwLock<Self>) {
+        let search_index = {
+            let content_engine = shared_content_engine
+                .read()
+                .expect("RwLock for ContentEngine has been poisoned");
+            if content_engine.config.search.is_enabled() {
+                Some(content_engine.build_search_index())
+            } else {
+                None
+            }
+        };
+        shared_content_engine
+            .write()
+            .expect("RwLock for ContentEngine has been poisoned")
+            .search_index = search_index;
+    }
+
+    /// Runs every executable in validation mode (see [`Executable::probe`])
+    /// to find any that cannot be run. Problems are paired with the route of
+    /// the executable.
+    #[cfg(feature = "executables")]
+    pub fn probe_executables(&self) -> Result<(), ContentLoadingErrors> {
+        let mut executables = self
+            .content_registry
+            .iter()
+            .flat_map(|(route, representations)| {
+                representations
+                    .values()
+                    .filter_map(move |content| match content {
+                        RegisteredContent::Executable(executable) => Some((route, executable)),
+                        _ => None,
+                    })
+            })
+            .collect::<Vec<_>>();
+        executables.sort_by_key(|(route, _)| *route);
+
+        let errors = executables
+            .into_iter()
+            .filter_map(|(route, executable)| {
+                log::debug!("Probing executable at {}", route);
+                let context = self.render_context(
+                    Some(route.clone()),
+                    QueryParameters::default(),
+                    HashMap::new(),
+                );
+                executable.probe(context.data).err().map(|error| {
+                    (
+                        route.to_string(),
+                        ContentLoadingError::ExecutableProbeFailed(error),
+                    )
+                })
+            })
+            .collect::<Vec<_>>();
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(ContentLoadingErrors(errors))
+        }
+    }
+
+    /// Without the `executables` cargo feature there are no executables, so
+    /// none can fail.
+    #[cfg(not(feature = "executables"))]
+    pub fn probe_executables(&self) -> Result<(), ContentLoadingErrors> {
+        Ok(())
+    }
+
+    /// Renders every public route as each of its media types (with no query
+    /// parameters) to find content that fails at render time, like templates
+    /// with runtime errors or executables which exit unsuccessfully. Problems
+    /// are paired with the route that failed.
+    pub fn render_check(&self) -> Result<(), ContentLoadingErrors> {
+        let mut routes = self
+            .content_registry
+            .iter()
+            .map(|(route, _)| route)
+            .collect::<Vec<_>>();
+        routes.sort();
+
+        let mut errors = Vec::new();
+        for route in routes {
+            let representations = match self.get(route) {
+                Some(representations) => representations,
+                // Internal content and drafts are not rendered on their own.
+                None => continue,
+            };
+            for media_type in representations.keys() {
+                log::debug!("Checking that {} renders as {}", route, media_type);
+                let context = self.render_context(
+                    Some(route.clone()),
+                    QueryParameters::default(),
+                    HashMap::new(),
+                );
+                let result = representations
+                    .render(context, &[media_type.clone().into_media_range()])
+                    .map_err(|source| ContentLoadingError::RenderCheckFailed {
+                        media_type: media_type.clone(),
+                        source: Box::new(source),
+                    })
+                    .and_then(|media| {
+                        executor::block_on(media.content.try_for_each(|_| async { Ok(()) }))
+                            .map_err(|source| ContentLoadingError::RenderCheckStreamFailed {
+                                media_type: media_type.clone(),
+                                source: Box::new(source),
+                            })
+                    });
+                if let Err(error) = result {
+                    errors.push((route.to_string(), error));
+                }
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(ContentLoadingErrors(errors))
+        }
+    }
+
+    /// Routes of templates which asked to be rendered at startup via their
+    /// front matter.
+    pub fn prerender_routes(&self) -> Vec<Route> {
+        let mut routes = self
+            .content_registry
+            .iter()
+            .filter(|(route, representations)| {
+                self.get(route).is_some()
+                    && representations.values().any(|content| match content {
+                        RegisteredContent::RegisteredTemplate(template) => {
+                            template.front_matter().settings().prerender
+                        }
+                        _ => false,
+                    })
+            })
+            .map(|(route, _)| route.clone())
+            .collect::<Vec<_>>();
+        routes.sort();
+        routes
+    }
+
+    /// Tasks from the config, along with templates which have a `task` in
+    /// their front matter. The config wins if both have a task for a route.
+    pub fn tasks(&self) -> BTreeMap<Route, TaskConfig> {
+        let mut tasks = self
+            .content_registry
+            .iter()
+            .flat_map(|(route, representations)| {
+                representations
+                    .values()
+                    .filter_map(move |content| match content {
+                        RegisteredContent::RegisteredTemplate(template) => template
+                            .front_matter()
+                            .settings()
+                            .task
+                            .clone()
+                            .map(|task| (route.clone(), task)),
+                        _ => None,
+                    })
+            })
+            .collect::<BTreeMap<_, _>>();
+        tasks.extend(self.config.tasks.clone());
+        tasks
+    }
+
+    /// Renders the HTML (or else plain text) representation of every public
+    /// template and static file into a search index. Content which fails to
+    /// render is logged and left out. Executables are skipped, but this is
+    /// not free of side effects: templates which `get` executables run them
+    /// (and may fill their output caches).
+    fn build_search_index(&self) -> SearchIndex {
+        let searchable_media_types = [::mime::TEXT_HTML, ::mime::TEXT_PLAIN]
+            .into_iter()
+            .filter_map(MediaType::from_media_range)
+            .collect::<Vec<_>>();
+
+        let mut routes = self
+            .content_registry
+            .iter()
+            .map(|(route, _)| route.clone())
+            .filter(|route| self.get(route).is_some())
+            .collect::<Vec<_>>();
+        routes.sort();
+
+        let mut search_index = SearchIndex::new();
+        for route in routes {
+            let representations = match self.get(&route) {
+                Some(representations) => representations,
+                None => continue,
+            };
+            let searchable_content = searchable_media_types.iter().find_map(|media_type| {
+                match representations.get(media_type) {
+                    #[cfg(feature = "executables")]
+                    Some(RegisteredContent::Executable(_)) => None,
+                    None => None,
+                    Some(content) => Some((media_type, content)),
+                }
+            });
+            let (media_type, content) = match searchable_content {
+                Some(searchable_content) => searchable_content,
+                None => continue,
+            };
+            let title = match content {
+                RegisteredContent::RegisteredTemplate(template) => template
+                    .front_matter()
+                    .data()
+                    .get("title")
+                    .and_then(|title| title.as_str())
+                    .map(String::from),
+                _ => None,
+            };
+
+            let context = self.render_context(
+                Some(route.clone()),
+                QueryParameters::default(),
+                HashMap::new(),
+            );
+            let rendered = representations
+                .render(context, &[media_type.clone().into_media_range()])
+                .map_err(|error| error.to_string())
+                .and_then(|media| {
+                    executor::block_on(media.content.try_fold(Vec::new(), |mut bytes, chunk| {
+                        bytes.extend_from_slice(&chunk);
+                        async { Ok(bytes) }
+                    }))
+                    .map_err(|error| error.to_string())
+                })
+                .and_then(|bytes| String::from_utf8(bytes).map_err(|error| error.to_string()));
+            match rendered {
+                Ok(text) => search_index.add(route, media_type, &text, title),
+                Err(error) => log::warn!("Could not index {} for search: {}", route, error),
+            }
+        }
+        search_index
+    }
+
+    /// Registers every file in `content_item_entries`. Problems are collected
+    /// rather than returned immediately, so that all of them can be reported
+    /// at once.
+    fn set_up_registries<'a, E: IntoIterator<Item = ContentFile>>(
+        content_item_entries: E,
+        config: &ContentConfig,
+        executables_config: &Arc<ExecutablesConfig>,
+    ) -> Result<
+        (
+            ContentIndexEntries,
+            ContentRegistry,
+            Handlebars<'a>,
+            MiniJinjaEngine,
+            SiteData,
+        ),
+        ContentLoadingErrors,
+    > {
+        let mut index = ContentIndexEntries::new();
+        let mut handlebars_registry = Handlebars::new();
+        let mut minijinja_engine = MiniJinjaEngine::new(config.strict_templates);
+        let mut content_registry = ContentRegistry::new(config.internal_prefix.clone());
+        let mut data = serde_json::Map::new();
+        let mut errors = Vec::new();
+        let mut precompressed_files = Vec::new();
+        handlebars_registry.set_strict_mode(config.strict_templates);
+        handlebars_registry.register_escape_fn(Escaping::escape_current);
+        let data_directory_route = Self::DATA_DIRECTORY_ROUTE
+            .parse::<Route>()
+            .expect(bug_message!("The data directory route was invalid."));
+        for entry in content_item_entries {
+            let relative_path = entry.relative_path.clone();
+            let extensions = entry.extensions.to_owned();
+            let result = match extensions.as_slice() {
+                _ if entry.route.is_within(&data_directory_route) => {
+                    Self::load_data_file(entry, &data_directory_route, &mut data)
+                }
+                [_, second_extension]
+                    if !entry.is_executable
+                        && ContentCoding::from_file_extension(second_extension).is_some() =>
+                {
+                    // These are attached to their uncompressed counterparts
+                    // once everything else is registered.
+                    precompressed_files.push(entry);
+                    Ok(())
+                }
+                [single_extension] => Self::register_content_file_with_one_extension(
+                    entry,
+                    single_extension,
+                    &mut index,
+                    &mut content_registry,
+                    config,
+                ),
+                [first_extension, second_extension] => {
+                    Self::register_content_file_with_two_extensions(
+                        entry,
+                        first_extension,
+                        second_extension,
+                        &mut index,
+                        &mut content_registry,
+                        &mut handlebars_registry,
+                        &mut minijinja_engine,
+                        config,
+                        executables_config,
+                    )
+                }
+                [_, _, _, ..] => Err(ContentLoadingError::ContentFileNameError(format!(
+                    "Content file name '{}' has too many extensions.",
+                    entry.relative_path
+                ))),
+                [] => Err(ContentLoadingError::ContentFileNameError(format!(
+                    "Content file names must have extensions, but '{}' does not.",
+                    entry.relative_path
+                ))),
+            };
+            match result {
+                Err(error) if config.lenient && error.is_unsupported_file() => {
+                    log::warn!("Skipping content file '{}': {}", relative_path, error);
+                }
+                Err(error) => errors.push((relative_path, error)),
+                Ok(()) => {}
+            }
+        }
+        for entry in precompressed_files {
+            let relative_path = entry.relative_path.clone();
+            match Self::register_precompressed_file(entry, &mut content_registry) {
+                Err(error) if config.lenient && error.is_unsupported_file() => {
+                    log::warn!("Skipping content file '{}': {}", relative_path, error);
+                }
+                Err(error) => errors.push((relative_path, error)),
+                Ok(()) => {}
+            }
+        }
+
+        if errors.is_empty() {
+            Ok((
+                index,
+                content_registry,
+                handlebars_registry,
+                minijinja_engine,
+                data,
+            ))
+        } else {
+            // Directory traversal order is platform-dependent.
+            errors.sort_by(|(a, _), (b, _)| a.cmp(b));
+            Err(ContentLoadingErrors(errors))
+        }
+    }
+
+    /// Files in the data directory are parsed and made available to all
+    /// renders (nested under their directory names and extensionless file
+    /// names) instead of being served.
+    fn load_data_file(
+        content: ContentFile,
+        data_directory_route: &Route,
+        data: &mut SiteData,
+    ) -> Result<(), ContentLoadingError> {
+        let mut source = String::new();
+        (&content.file)
+            .read_to_string(&mut source)
+            .map_err(|error| {
+                ContentLoadingError::InvalidDataFile(format!(
+                    "'{}' could not be read: {}",
+                    content.relative_path, error
+                ))
+            })?;
+        let parse_error = |error: &dyn fmt::Display| {
+            ContentLoadingError::InvalidDataFile(format!(
+                "'{}' could not be parsed: {}",
+                content.relative_path, error
+            ))
+        };
+        let value = match content.extensions.as_slice() {
+            [extension] if extension == "yaml" || extension == "yml" => {
+                serde_yaml::from_str::<serde_json::Value>(&source)
+                    .map_err(|error| parse_error(&error))?
+            }
+            [extension] if extension == "json" => {
+                serde_json::from_str::<serde_json::Value>(&source)
+                    .map_err(|error| parse_error(&error))?
+            }
+            [extension] if extension == "toml" => {
+                toml::from_str::<serde_json::Value>(&source).map_err(|error| parse_error(&error))?
+            }
+            _ => {
+                return Err(ContentLoadingError::InvalidDataFile(format!(
+                    "'{}' must have a single extension of 'yaml', 'yml', 'json', or 'toml'.",
+                    content.relative_path
+                )))
+            }
+        };
+
+        let mut path = content.route.as_ref()[data_directory_route.as_ref().len()..]
+            .split('/')
+            .filter(|component| !component.is_empty())
+            .collect::<Vec<_>>();
+        let name = path.pop().ok_or_else(|| {
+            ContentLoadingError::Bug(format!(
+                "The data file '{}' has an empty name.",
+                content.relative_path
+            ))
+        })?;
+        let mut parent = data;
+        for component in path {
+            let child = parent
+                .entry(component)
+                .or_insert_with(|| serde_json::Value::Object(serde_json::Map::new()));
+            parent = match child {
+                serde_json::Value::Object(child) => child,
+                _ => {
+                    return Err(ContentLoadingError::InvalidDataFile(format!(
+                        "'{}' conflicts with another data file named '{}'.",
+                        content.relative_path, component
+                    )))
+                }
+            };
+        }
+        match parent.get_mut(name) {
+            None => {
+                parent.insert(String::from(name), value);
+                Ok(())
+            }
+            Some(serde_json::Value::Object(existing)) => match value {
+                // A directory with the same name was loaded first.
+                serde_json::Value::Object(value) => {
+                    for (key, value) in value {
+                        existing.entry(key).or_insert(value);
+                    }
+                    Ok(())
+                }
+                _ => Err(ContentLoadingError::InvalidDataFile(format!(
+                    "'{}' conflicts with a data directory of the same name.",
+                    content.relative_path
+                ))),
+            },
+            Some(_) => Err(ContentLoadingError::InvalidDataFile(format!(
+                "'{}' conflicts with another data file of the same name.",
+                content.relative_path
+            ))),
+        }
+    }
+
+    /// Content files with one extension indicate static content (e.g. an image
+    /// or plain text file). They must not have the executable bit set.
+    fn register_content_file_with_one_extension(
+        content: ContentFile,
+        extension: &str,
+        index: &mut ContentIndexEntries,
+        content_registry: &mut ContentRegistry,
+        config: &ContentConfig,
+    ) -> Result<(), ContentLoadingError> {
+        if content.is_executable {
+            return Err(ContentLoadingError::ContentFileNameError(format!(
+                "The content file '{}' is executable, but only has one extension ('{}'). \
+                    Executables must have two extensions: \
+                    the first indicates the media type of its output, and the second is arbitrary \
+                    but can be used to indicate the executable type ('.sh', '.exe', '.py', etc).",
+                content.relative_path, extension,
+            )));
+        }
+
+        if extension == Self::REDIRECT_EXTENSION {
+            return Self::register_redirect(content, content_registry);
+        }
+
+        let mime =
+            MimeGuess::from_ext(extension)
+                .first()
+                .ok_or_else(|| ContentLoadingError::UnknownFileType(
+                    format!(
+                        "The filename extension for the file at '{}' ('{}') does not map to any known media type.",
+                        content.relative_path,
+                        extension,
+                    ),
+                ))?;
+        let media_type = MediaType::from_media_range(mime).ok_or_else(|| {
+            ContentLoadingError::Bug(String::from("Mime guess was not a concrete media type!"))
+        })?;
+
+        let file = content.file;
+        Self::register_content(
+            content_registry,
+            index,
+            content.route,
+            media_type.clone(),
+            extension,
+            || {
+                let mut item = StaticContentItem::new(file, media_type)
+                    .with_chunk_size(config.file_chunk_size);
+                if config.preload_max_bytes > 0 {
+                    item.preload_if_small(config.preload_max_bytes);
+                }
+                if config.mmap_max_bytes > 0 {
+                    item.map_if_small(config.mmap_max_bytes);
+                }
+                item.compute_integrity_if_script_or_style();
+                RegisteredContent::StaticContentItem(item)
+            },
+        )
+    }
+
+    /// Content files named like foo.html.gz are precompressed copies of a
+    /// static file with the same name minus the final extension, which are
+    /// served instead of it to clients that accept the compression. They
+    /// can't be served on their own.
+    fn register_precompressed_file(
+        content: ContentFile,
+        content_registry: &mut ContentRegistry,
+    ) -> Result<(), ContentLoadingError> {
+        let (first_extension, second_extension) = match content.extensions.as_slice() {
+            [first_extension, second_extension] => (first_extension, second_extension),
+            _ => {
+                return Err(ContentLoadingError::Bug(format!(
+                    "Precompressed file '{}' did not have two extensions.",
+                    content.relative_path,
+                )))
+            }
+        };
+        let content_coding =
+            ContentCoding::from_file_extension(second_extension).ok_or_else(|| {
+                ContentLoadingError::Bug(format!(
+                    "Precompressed file '{}' had an unknown compression extension.",
+                    content.relative_path,
+                ))
+            })?;
+        let uncompressed_path = content
+            .relative_path
+            .trim_end_matches(second_extension)
+            .trim_end_matches('.');
+        let missing_uncompressed_file = || {
+            ContentLoadingError::ContentFileNameError(format!(
+                "'{}' appears to be a precompressed file (because it ends in '.{}'), but there is \
+                no uncompressed '{}' for it.",
+                content.relative_path, second_extension, uncompressed_path,
+            ))
+        };
+
+        let media_type = MimeGuess::from_ext(first_extension)
+            .first()
+            .and_then(MediaType::from_media_range)
+            .ok_or_else(missing_uncompressed_file)?;
+        match content_registry
+            .get_internal_mut(&content.route)
+            .and_then(|representations| representations.get_mut(&media_type))
+        {
+            Some(RegisteredContent::StaticContentItem(item)) => {
+                item.add_precompressed(content_coding, content.file);
+                Ok(())
+            }
+            _ => Err(missing_uncompressed_file()),
+        }
+    }
+
+    fn register_redirect(
+        content: ContentFile,
+        content_registry: &mut ContentRegistry,
+    ) -> Result<(), ContentLoadingError> {
+        let mut source = String::new();
+        (&content.file)
+            .read_to_string(&mut source)
+            .map_err(|error| {
+                ContentLoadingError::InvalidRedirect(format!(
+                    "'{}' could not be read: {}",
+                    content.relative_path, error
+                ))
+            })?;
+        let redirect = Redirect::parse(&source).map_err(|error| {
+            ContentLoadingError::InvalidRedirect(format!("'{}': {}", content.relative_path, error))
+        })?;
+        if content_registry.get_internal(&content.route).is_some()
+            || content_registry.redirect_internal(&content.route).is_some()
+        {
+            return Err(ContentLoadingError::ConflictingRedirect {
+                route: content.route,
+            });
+        }
+        content_registry.insert_redirect(content.route, redirect);
+        Ok(())
     }
 
     /// Content files with two extensions are either templates or executables
     /// (depending on the final extension and whether the executable bit is
     /// set). In both cases the first extension indicates the media type that
     /// will be produced when the content is rendered.
+    #[allow(clippy::too_many_arguments)]
     fn register_content_file_with_two_extensions(
         content: ContentFile,
         first_extension: &str,
@@ -216,127 +1720,53 @@ where
         index: &mut ContentIndexEntries,
         content_registry: &mut ContentRegistry,
         handlebars_registry: &mut Handlebars,
+        minijinja_engine: &mut MiniJinjaEngine,
+        config: &ContentConfig,
+        executables_config: &Arc<ExecutablesConfig>,
     ) -> Result<(), ContentLoadingError> {
         match [first_extension, second_extension] {
-            // Handlebars templates are named like foo.html.hbs and do not
-            // have the executable bit set. They are evaluated when rendered.
-            [first_extension, Self::HANDLEBARS_FILE_EXTENSION] => {
-                if content.is_executable {
-                    return Err(ContentLoadingError::ContentFileNameError(
-                        format!(
-                            "The content file '{}' appears to be a handlebars file (because it ends in '.{}'), \
-                            but it is also executable. It must be one or the other.",
-                            content.relative_path,
-                            Self::HANDLEBARS_FILE_EXTENSION,
-                        ),
-                    ));
-                }
-
-                let mime = MimeGuess::from_ext(first_extension)
-                    .first()
-                    .ok_or_else(|| ContentLoadingError::UnknownFileType(
-                        format!(
-                            "The first filename extension for the handlebars template at '{}' ('{}') \
-                            does not map to any known media type.",
-                            content.relative_path,
-                            first_extension,
-                        ),
-                    ))?;
-                let media_type = MediaType::from_media_range(mime).ok_or_else(|| {
-                    ContentLoadingError::Bug(String::from(
-                        "Mime guess was not a concrete media type!",
-                    ))
-                })?;
-
-                // Note that templates are keyed by relative path + extensions
-                // in the handlebars registry, not the extensionless routes
-                // used elsewhere. This is necessary to allow alternative
-                // representations for templates (foo.html.hbs and foo.md.hbs
-                // need to both live in the handlebars registry under distinct
-                // names).
-                let template_name = content.relative_path;
-                if handlebars_registry.has_template(&template_name) {
-                    return Err(ContentLoadingError::Bug(format!(
-                        "More than one handlebars template has the name '{}'.",
-                        template_name,
-                    )));
-                }
-                handlebars_registry
-                    .register_template_file(&template_name, content.absolute_path)
-                    .map_err(Box::new)
-                    .map_err(TemplateError::from)
-                    .map_err(ContentLoadingError::TemplateRegistrationError)?;
-
-                Self::register_content(
-                    content_registry,
+            // Templates are named like foo.html.hbs or foo.html.jinja and do
+            // not have the executable bit set. They are evaluated when
+            // rendered.
+            [first_extension, second_extension]
+                if TemplateLanguage::from_file_extension(second_extension).is_some() =>
+            {
+                Self::register_template(
+                    content,
+                    first_extension,
+                    second_extension,
                     index,
-                    content.route,
-                    media_type.clone(),
-                    || {
-                        RegisteredContent::RegisteredTemplate(RegisteredTemplate::new(
-                            template_name,
-                            media_type,
-                        ))
-                    },
+                    content_registry,
+                    handlebars_registry,
+                    minijinja_engine,
+                    config,
                 )
             }
 
-            // Executable programs are named like foo.html.py and must have the
-            // executable bit set in their file permissions. When rendered they
-            // will executed by the OS in a separate process.
-            [first_extension, _arbitrary_second_extension] if content.is_executable => {
-                let mime =
-                    MimeGuess::from_ext(first_extension)
-                        .first()
-                        .ok_or_else(|| ContentLoadingError::UnknownFileType(
-                            format!(
-                                "The first filename extension for the executable at '{}' ('{}') does not map to any known media type.",
-                                content.relative_path,
-                                first_extension,
-                            ),
-                        ))?;
-                let media_type = MediaType::from_media_range(mime).ok_or_else(|| {
-                    ContentLoadingError::Bug(String::from(
-                        "Mime guess was not a concrete media type!",
-                    ))
-                })?;
-
-                let absolute_path = content.absolute_path;
-
-                // The working directory for the executable is the immediate
-                // parent directory it resides in (which may be a child of the
-                // content directory).
-                let working_directory = Path::new(&absolute_path).parent().ok_or_else(|| {
-                    // This indicates a bug because it can only occur if
-                    // the absolute path is the filesystem root, but we
-                    // should have already verified that `entry` is a file
-                    // (not a directory). If it's the filesystem root then
-                    // it is a directory.
-                    ContentLoadingError::Bug(format!(
-                        "Failed to get a parent directory for the executable at '{}'.",
-                        absolute_path,
-                    ))
-                })?;
-
-                Self::register_content(
-                    content_registry,
+            // Executable programs are named like foo.html.py and must either
+            // have the executable bit set in their file permissions or have
+            // an interpreter configured for their second extension. When
+            // rendered they will executed by the OS in a separate process.
+            [first_extension, second_extension]
+                if content.is_executable
+                    || executables_config
+                        .interpreters
+                        .contains_key(second_extension) =>
+            {
+                Self::register_executable(
+                    content,
+                    first_extension,
+                    second_extension,
                     index,
-                    content.route,
-                    media_type.clone(),
-                    || {
-                        RegisteredContent::Executable(Executable::new(
-                            &absolute_path,
-                            working_directory,
-                            media_type,
-                        ))
-                    },
+                    content_registry,
+                    executables_config,
                 )
             }
 
             [first_unsupported_extension, second_unsupported_extension] => {
                 Err(ContentLoadingError::ContentFileNameError(format!(
                     "The content file '{}' has two extensions ('{}.{}'), but is \
-                        neither a handlebars template nor an executable.",
+                        neither a template nor an executable.",
                     content.relative_path,
                     first_unsupported_extension,
                     second_unsupported_extension
@@ -345,57 +1775,311 @@ where
         }
     }
 
-    fn register_content<F>(
+    /// Registers a content file which is a template, to be evaluated when it
+    /// is rendered.
+    #[cfg(feature = "templating")]
+    #[allow(clippy::too_many_arguments)]
+    fn register_template(
+        content: ContentFile,
+        first_extension: &str,
+        second_extension: &str,
+        index: &mut ContentIndexEntries,
         content_registry: &mut ContentRegistry,
-        content_index: &mut ContentIndexEntries,
-        route: Route,
-        media_type: MediaType,
-        create_content: F,
-    ) -> Result<(), ContentLoadingError>
-    where
-        F: FnOnce() -> RegisteredContent,
-    {
-        content_index.try_add(route.clone())?;
-        let representations = content_registry.entry_or_insert_default(route.clone());
+        handlebars_registry: &mut Handlebars,
+        minijinja_engine: &mut MiniJinjaEngine,
+        config: &ContentConfig,
+    ) -> Result<(), ContentLoadingError> {
+        let language =
+            TemplateLanguage::from_file_extension(second_extension).ok_or_else(|| {
+                ContentLoadingError::Bug(String::from(
+                    "Template language could not be determined from its extension!",
+                ))
+            })?;
+        if content.is_executable {
+            return Err(ContentLoadingError::ContentFileNameError(format!(
+                "The content file '{}' appears to be a {} file (because it ends in '.{}'), \
+                    but it is also executable. It must be one or the other.",
+                content.relative_path, language, second_extension,
+            )));
+        }
 
-        match representations.entry(media_type) {
-            Entry::Occupied(entry) => {
-                let (media_type, _) = entry.remove_entry();
-                Err(ContentLoadingError::DuplicateContent { route, media_type })
-            }
-            Entry::Vacant(entry) => {
-                entry.insert(create_content());
-                Ok(())
-            }
+        let mime = MimeGuess::from_ext(first_extension)
+            .first()
+            .ok_or_else(|| {
+                ContentLoadingError::UnknownFileType(format!(
+                    "The first filename extension for the {} template at '{}' ('{}') \
+                    does not map to any known media type.",
+                    language, content.relative_path, first_extension,
+                ))
+            })?;
+        let media_type = MediaType::from_media_range(mime).ok_or_else(|| {
+            ContentLoadingError::Bug(String::from("Mime guess was not a concrete media type!"))
+        })?;
+
+        let template_engine: &mut dyn TemplateEngine = match language {
+            TemplateLanguage::Handlebars => handlebars_registry,
+            TemplateLanguage::MiniJinja => minijinja_engine,
+        };
+
+        // Note that templates are keyed by relative path + extensions
+        // in the template engine, not the extensionless routes used
+        // elsewhere. This is necessary to allow alternative
+        // representations for templates (foo.html.hbs and foo.md.hbs
+        // need to both live in the handlebars registry under distinct
+        // names).
+        let template_name = content.relative_path;
+        if template_engine.has_template(&template_name) {
+            return Err(ContentLoadingError::Bug(format!(
+                "More than one {} template has the name '{}'.",
+                language, template_name,
+            )));
         }
+        let mut template_source = String::new();
+        (&content.file)
+            .read_to_string(&mut template_source)
+            .map_err(|error| TemplateError::unreadable(language, template_name.clone(), error))?;
+        let (front_matter, template_body, body_line_offset) = FrontMatter::split(&template_source)?;
+        template_engine.register_template(
+            &template_name,
+            template_body,
+            &template_source,
+            body_line_offset,
+        )?;
+
+        Self::register_content(
+            content_registry,
+            index,
+            content.route,
+            media_type.clone(),
+            first_extension,
+            || {
+                RegisteredContent::RegisteredTemplate(
+                    RegisteredTemplate::new(template_name, media_type)
+                        .with_language(language)
+                        .with_front_matter(front_matter, config),
+                )
+            },
+        )
     }
-}
 
-impl<'engine, ServerInfo> ContentEngine<ServerInfo>
-    for FilesystemBasedContentEngine<'engine, ServerInfo>
-where
-    ServerInfo: Clone + Serialize,
-{
-    fn render_context(
+    /// Without the `templating` cargo feature, templates cannot be loaded.
+    #[cfg(not(feature = "templating"))]
+    #[allow(clippy::too_many_arguments)]
+    fn register_template(
+        content: ContentFile,
+        _first_extension: &str,
+        _second_extension: &str,
+        _index: &mut ContentIndexEntries,
+        _content_registry: &mut ContentRegistry,
+        _handlebars_registry: &mut Handlebars,
+        _minijinja_engine: &mut MiniJinjaEngine,
+        _config: &ContentConfig,
+    ) -> Result<(), ContentLoadingError> {
+        Err(ContentLoadingError::TemplatesNotSupported(
+            content.relative_path,
+        ))
+    }
+
+    /// Registers a content file which is run by the OS in a separate process
+    /// when it is rendered.
+    #[cfg(feature = "executables")]
+    fn register_executable(
+        content: ContentFile,
+        first_extension: &str,
+        second_extension: &str,
+        index: &mut ContentIndexEntries,
+        content_registry: &mut ContentRegistry,
+        executables_config: &Arc<ExecutablesConfig>,
+    ) -> Result<(), ContentLoadingError> {
+        let interpreter = if content.is_executable {
+            None
+        } else {
+            executables_config
+                .interpreters
+                .get(second_extension)
+                .cloned()
+        };
+
+        let mime =
+                MimeGuess::from_ext(first_extension)
+                    .first()
+                    .ok_or_else(|| ContentLoadingError::UnknownFileType(
+                        format!(
+                            "The first filename extension for the executable at '{}' ('{}') does not map to any known media type.",
+                            content.relative_path,
+                            first_extension,
+                        ),
+                    ))?;
+        let media_type = MediaType::from_media_range(mime).ok_or_else(|| {
+            ContentLoadingError::Bug(String::from("Mime guess was not a concrete media type!"))
+        })?;
+
+        let front_matter = ExecutableFrontMatter::read(&content.file)?;
+        let absolute_path = content.absolute_path;
+        let arguments = front_matter
+            .arguments
+            .or_else(|| executables_config.arguments.get(&content.route).cloned())
+            .unwrap_or_default();
+        let cache_ttl = front_matter
+            .cache_ttl
+            .or_else(|| executables_config.cache_ttl.get(&content.route).copied())
+            .map(Duration::from_secs);
+        let stale_while_revalidate = front_matter
+            .stale_while_revalidate
+            .or_else(|| {
+                executables_config
+                    .stale_while_revalidate
+                    .get(&content.route)
+                    .copied()
+            })
+            .map(Duration::from_secs)
+            .unwrap_or_default();
+        let cache_vary = executables_config
+            .cache_vary
+            .get(&content.route)
+            .cloned()
+            .unwrap_or_default();
+        let route = content.route.clone();
+        let output_limit = executables_config
+            .output_limits
+            .get(&content.route)
+            .copied()
+            .or(executables_config.output_limit);
+        let sendfile_roots = if executables_config.sendfile.contains(&content.route) {
+            // Relative paths are resolved against the content
+            // directory (or the executable's own directory if it was
+            // mounted from elsewhere).
+            let content_directory_root = absolute_path
+                .strip_suffix(&content.relative_path)
+                .map(PathBuf::from)
+                .or_else(|| Path::new(&absolute_path).parent().map(PathBuf::from));
+            Some(
+                content_directory_root
+                    .into_iter()
+                    .chain(executables_config.sendfile_paths.iter().cloned())
+                    .collect::<Vec<_>>(),
+            )
+        } else {
+            None
+        };
+
+        // The working directory for the executable is the immediate
+        // parent directory it resides in (which may be a child of the
+        // content directory).
+        let working_directory = Path::new(&absolute_path).parent().ok_or_else(|| {
+            // This indicates a bug because it can only occur if
+            // the absolute path is the filesystem root, but we
+            // should have already verified that `entry` is a file
+            // (not a directory). If it's the filesystem root then
+            // it is a directory.
+            ContentLoadingError::Bug(format!(
+                "Failed to get a parent directory for the executable at '{}'.",
+                absolute_path,
+            ))
+        })?;
+
+        Self::register_content(
+            content_registry,
+            index,
+            content.route,
+            media_type.clone(),
+            first_extension,
+            || {
+                RegisteredContent::Executable(
+                    Executable::new(&absolute_path, working_directory, media_type)
+                        .with_interpreter(interpreter)
+                        .with_arguments(arguments)
+                        .with_cache(
+                            cache_ttl,
+                            stale_while_revalidate,
+                            cache_vary,
+                            executables_config.cache_max_entries,
+                        )
+                        .with_output_limit(output_limit)
+                        .with_sendfile_roots(sendfile_roots.clone())
+                        .with_route(route.clone())
+                        .with_config(executables_config.clone()),
+                )
+            },
+        )
+    }
+
+    /// Without the `executables` cargo feature, executables cannot be loaded.
+    #[cfg(not(feature = "executables"))]
+    fn register_executable(
+        content: ContentFile,
+        _first_extension: &str,
+        _second_extension: &str,
+        _index: &mut ContentIndexEntries,
+        _content_registry: &mut ContentRegistry,
+        _executables_config: &Arc<ExecutablesConfig>,
+    ) -> Result<(), ContentLoadingError> {
+        Err(ContentLoadingError::ExecutablesNotSupported(
+            content.relative_path,
+        ))
+    }
+
+    fn register_content<F>(
+        content_registry: &mut ContentRegistry,
+        content_index: &mut ContentIndexEntries,
+        route: Route,
+        media_type: MediaType,
+        extension: &str,
+        create_content: F,
+    ) -> Result<(), ContentLoadingError>
+    where
+        F: FnOnce() -> RegisteredContent,
+    {
+        if content_registry.redirect_internal(&route).is_some() {
+            return Err(ContentLoadingError::ConflictingRedirect { route });
+        }
+        content_index.try_add(route.clone())?;
+        content_registry.set_url_extension(route.clone(), media_type.clone(), extension);
+        let representations = content_registry.entry_or_insert_default(route.clone());
+
+        match representations.entry(media_type) {
+            Entry::Occupied(entry) => {
+                let (media_type, _) = entry.remove_entry();
+                Err(ContentLoadingError::DuplicateContent { route, media_type })
+            }
+            Entry::Vacant(entry) => {
+                entry.insert(create_content());
+                Ok(())
+            }
+        }
+    }
+}
+
+impl<'engine, ServerInfo> ContentEngine<ServerInfo>
+    for FilesystemBasedContentEngine<'engine, ServerInfo>
+where
+    ServerInfo: Clone + Serialize,
+{
+    fn render_data(
         &self,
         route: Option<Route>,
-        query_parameters: HashMap<String, String>,
+        query_parameters: QueryParameters,
         request_headers: HashMap<String, String>,
-    ) -> RenderContext<ServerInfo, Self> {
-        RenderContext {
-            content_engine: self,
-            handlebars_render_context: None,
-            data: RenderData {
-                server_info: self.server_info.clone(),
-                index: self.index.clone(),
-                target_media_type: None,
-                error_code: None,
-                request: RequestData {
-                    route,
-                    query_parameters,
-                    request_headers,
-                },
-            },
+    ) -> RenderData<ServerInfo> {
+        // Mounts can be nested, so the longest matching prefix wins.
+        let mount_prefix = route.as_ref().and_then(|route| {
+            self.mounts
+                .iter()
+                .filter(|mount| route.is_within(mount))
+                .max_by_key(|mount| mount.as_ref().len())
+                .cloned()
+        });
+        RenderData {
+            server_info: self.server_info.clone(),
+            index: self.index.clone(),
+            target_media_type: None,
+            site: self.config.site_data(route.as_ref()),
+            data: self.data.clone(),
+            front_matter: None,
+            error_code: None,
+            request: RequestData::new(route, query_parameters, request_headers)
+                .with_base_url(self.config.base_url.as_deref())
+                .with_mount_prefix(mount_prefix),
         }
     }
 
@@ -408,12 +2092,65 @@ where
     }
 
     fn get(&self, route: &Route) -> Option<&ContentRepresentations> {
-        self.content_registry.get(route)
+        self.content_registry.get(route).filter(|representations| {
+            self.config.content.include_drafts || is_published(representations, SystemTime::now())
+        })
     }
 
-    fn handlebars_registry(&self) -> &Handlebars {
+    fn handlebars_registry(&self) -> &Handlebars<'_> {
         &self.handlebars_registry
     }
+
+    fn search_index(&self) -> Option<&SearchIndex> {
+        self.search_index.as_ref()
+    }
+
+    fn stats(&self) -> Option<&ContentStats> {
+        Some(&self.stats)
+    }
+
+    fn redirect(&self, route: &Route) -> Option<&Redirect> {
+        self.content_registry.redirect(route)
+    }
+
+    fn template_engine(&self, language: TemplateLanguage, strict: bool) -> &dyn TemplateEngine {
+        match language {
+            TemplateLanguage::Handlebars => match &self.alternate_handlebars_registry {
+                Some(alternate_handlebars_registry)
+                    if self.handlebars_registry.strict_mode() != strict =>
+                {
+                    alternate_handlebars_registry
+                }
+                _ => &self.handlebars_registry,
+            },
+            TemplateLanguage::MiniJinja => match &self.alternate_minijinja_engine {
+                Some(alternate_minijinja_engine)
+                    if self.minijinja_engine.strict_mode() != strict =>
+                {
+                    alternate_minijinja_engine
+                }
+                _ => &self.minijinja_engine,
+            },
+        }
+    }
+
+    fn template_source(&self, template_name: &str) -> Option<String> {
+        if !self.relative_paths.contains(template_name) {
+            return None;
+        }
+        let path = self.source.absolute_path(template_name)?;
+        fs::read_to_string(path).ok()
+    }
+}
+
+/// Routes are unpublished if any of their templates are.
+fn is_published(representations: &ContentRepresentations, now: SystemTime) -> bool {
+    representations.values().all(|content| match content {
+        RegisteredContent::RegisteredTemplate(template) => {
+            template.front_matter().settings().is_published(now)
+        }
+        _ => true,
+    })
 }
 
 impl<'engine, ServerInfo> InternalContentEngine
@@ -424,6 +2161,25 @@ where
     fn get_internal(&self, route: &Route) -> Option<&ContentRepresentations> {
         self.content_registry.get_internal(route)
     }
+
+    fn url_extension(&self, route: &Route, media_type: &MediaType) -> Option<&str> {
+        self.content_registry.url_extension(route, media_type)
+    }
+
+    fn index(&self) -> &ContentIndex {
+        &self.index
+    }
+
+    fn config(&self) -> &Config {
+        &self.config
+    }
+
+    fn record_dependency(&self, route: Route, dependency: Route) {
+        self.dependency_graph
+            .lock()
+            .expect("Mutex for DependencyGraph has been poisoned")
+            .add(route, dependency);
+    }
 }
 
 #[cfg(test)]
@@ -436,11 +2192,16 @@ mod tests {
     use test_log::test;
 
     type TestContentEngine<'a, ServerInfo = ()> = FilesystemBasedContentEngine<'a, ServerInfo>;
+    type SharedTestContentEngine = Arc<RwLock<TestContentEngine<'static>>>;
 
     // FIXME: It's not ideal to rely on specific sample directories in these
     // tests. It would be better to mock out contents in each of the tests.
 
     #[test]
+    #[cfg_attr(
+        not(all(feature = "executables", feature = "templating")),
+        ignore = "uses executables and templates"
+    )]
     fn content_engine_can_be_created_from_valid_content_directory() {
         for directory in sample_content_directories_with_valid_contents() {
             if let Err(error) = TestContentEngine::from_content_directory(directory, ()) {
@@ -460,195 +2221,2108 @@ mod tests {
     }
 
     #[test]
-    fn new_templates_can_be_rendered() {
-        let shared_content_engine = TestContentEngine::from_content_directory(
-            arbitrary_content_directory_with_valid_content(),
-            (),
-        )
-        .expect("Content engine could not be created");
+    fn all_loading_errors_are_reported() {
+        let errors = content_engine_in_temporary_directory(
+            &[
+                ("no-extension", ""),
+                ("too.many.extensions.here", ""),
+                ("broken.html.hbs", "{{"),
+                ("fine.html", ""),
+            ],
+            Config::default(),
+        )
+        .err()
+        .expect("Content engine was successfully created, but this should have failed");
+
+        let failed_paths = errors
+            .iter()
+            .map(|(relative_path, _)| relative_path.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            failed_paths,
+            vec![
+                "broken.html.hbs",
+                "no-extension",
+                "too.many.extensions.here"
+            ]
+        );
+    }
+
+    #[test]
+    fn unsupported_files_can_be_skipped_in_lenient_mode() {
+        let config = Config {
+            content: ContentConfig {
+                lenient: true,
+                ..ContentConfig::default()
+            },
+            ..Config::default()
+        };
+        let (_root, shared_content_engine) = content_engine_in_temporary_directory(
+            &[
+                ("no-extension", ""),
+                ("unknown.not-a-real-extension", ""),
+                ("fine.html", "hello"),
+            ],
+            config,
+        )
+        .expect("Content engine could not be created");
+
+        let content_engine = shared_content_engine.read().unwrap();
+        assert!(content_engine.get(&route("/fine")).is_some());
+        assert!(content_engine.get(&route("/unknown")).is_none());
+    }
+
+    #[test]
+    fn lenient_mode_does_not_skip_invalid_templates() {
+        let config = ContentConfig {
+            lenient: true,
+            ..ContentConfig::default()
+        };
+        let directory =
+            ContentDirectory::from_root_with_config(&sample_path("invalid-templates"), &config)
+                .unwrap();
+        assert!(
+            TestContentEngine::from_content_directory(directory, ()).is_err(),
+            "Content engine was successfully created, but this should have failed",
+        );
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "templating"), ignore = "uses templates")]
+    fn template_errors_describe_where_the_problem_is() {
+        let directory = ContentDirectory::from_root(&sample_path("invalid-templates")).unwrap();
+        let errors = TestContentEngine::from_content_directory(directory, ())
+            .err()
+            .expect("Content engine was successfully created, but this should have failed");
+        let template_error = match errors.iter().next() {
+            Some((_, ContentLoadingError::TemplateRegistrationError(template_error))) => {
+                template_error
+            }
+            _ => panic!("Wrong type of error was produced: {}", errors),
+        };
+
+        assert_eq!(template_error.name(), Some("borked.html.hbs"));
+        assert_eq!(template_error.line(), Some(3));
+        assert_eq!(template_error.column(), Some(1));
+        assert_eq!(
+            template_error.excerpt(),
+            Some(
+                " --> borked.html.hbs:3:1\n  |\n1 | {{!-- this is not valid --}}\n2 | {{\n3 | \n  | ^"
+            ),
+        );
+    }
+
+    #[test]
+    fn template_error_excerpts_point_at_the_problem() {
+        let content_engine = MockContentEngine::new();
+        let error = content_engine
+            .new_template(
+                "one\ntwo\n\tthree {{if !}} x\nfour",
+                MediaType::from_media_range(mime::TEXT_HTML).unwrap(),
+            )
+            .err()
+            .expect("Template was successfully parsed, but this should have failed");
+        assert_eq!(error.name(), None);
+        assert_eq!(error.line(), Some(3));
+        assert_eq!(error.column(), Some(13));
+        assert_eq!(
+            error.excerpt(),
+            Some(" --> <template>:3:13\n  |\n1 | one\n2 | two\n3 | \tthree {{if !}} x\n  | \t           ^"),
+        );
+    }
+
+    /// Writes `files` (paths relative to the directory paired with their
+    /// contents) to a new temporary directory, creating subdirectories as
+    /// needed. The directory is deleted when the returned value is dropped.
+    fn temporary_directory_with_files<C: AsRef<[u8]>>(files: &[(&str, C)]) -> tempfile::TempDir {
+        let root = tempfile::tempdir().expect("Failed to create temporary directory");
+        for (relative_path, contents) in files {
+            let path = root.path().join(relative_path);
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent).unwrap();
+            }
+            std::fs::write(path, contents).unwrap();
+        }
+        root
+    }
+
+    /// Loads a content engine from the content directory at `root`. Content
+    /// settings come from `config` too.
+    fn content_engine_from_root(
+        root: &std::path::Path,
+        config: Config,
+    ) -> Result<SharedTestContentEngine, ContentLoadingErrors> {
+        let directory = ContentDirectory::from_root_with_config(&root, &config.content).unwrap();
+        TestContentEngine::from_content_directory_with_config(directory, (), config)
+    }
+
+    /// Loads a content engine from a temporary content directory containing
+    /// `files`. Keep the returned directory around for as long as the engine
+    /// is used.
+    fn content_engine_in_temporary_directory<C: AsRef<[u8]>>(
+        files: &[(&str, C)],
+        config: Config,
+    ) -> Result<(tempfile::TempDir, SharedTestContentEngine), ContentLoadingErrors> {
+        let root = temporary_directory_with_files(files);
+        let shared_content_engine = content_engine_from_root(root.path(), config)?;
+        Ok((root, shared_content_engine))
+    }
+
+    fn render_template_in_temporary_directory(
+        template: &str,
+        config: &ContentConfig,
+    ) -> Result<String, RenderError> {
+        let config = Config {
+            content: config.clone(),
+            ..Config::default()
+        };
+        let (_root, shared_content_engine) =
+            content_engine_in_temporary_directory(&[("template.html.hbs", template)], config)
+                .expect("Content engine could not be created");
+        let content_engine = shared_content_engine.read().unwrap();
+        let context =
+            content_engine.render_context(None, QueryParameters::default(), HashMap::new());
+        content_engine
+            .get(&route("/template"))
+            .expect("Template was not registered")
+            .render(context, &[mime::TEXT_HTML])
+            .map(media_to_string)
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "templating"), ignore = "uses templates")]
+    fn templates_can_use_their_front_matter() {
+        let output = render_template_in_temporary_directory(
+            "---\ntitle: Hello\n---\n<h1>{{front-matter.title}}</h1>",
+            &ContentConfig::default(),
+        )
+        .expect("Rendering failed");
+        assert_eq!(output, "<h1>Hello</h1>");
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "templating"), ignore = "uses templates")]
+    fn templates_starting_with_dashes_are_rendered_whole() {
+        let template = "---\n<p>Not front matter.</p>\n---\n<p>{{#if true}}Rendered.{{/if}}</p>";
+        let output = render_template_in_temporary_directory(template, &ContentConfig::default())
+            .expect("Rendering failed");
+        assert_eq!(
+            output,
+            "---\n<p>Not front matter.</p>\n---\n<p>Rendered.</p>"
+        );
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "templating"), ignore = "uses templates")]
+    fn search_index_covers_public_text_content() {
+        let root = temporary_directory_with_files(&[
+            (
+                "about.html.hbs",
+                "---\ntitle: About Us\n---\n<p>We like {{#if true}}penguins{{/if}}.</p>",
+            ),
+            ("notes.txt", "Penguins are birds."),
+            ("data.json", r#"{ "penguins": 1 }"#),
+            ("_secret.html", "penguins"),
+        ]);
+
+        let shared_content_engine = content_engine_from_root(root.path(), Config::default())
+            .expect("Content engine could not be created");
+        assert!(
+            shared_content_engine
+                .read()
+                .unwrap()
+                .search_index()
+                .is_none(),
+            "Search index was built without being enabled",
+        );
+
+        let mut config = Config::default();
+        config.search.query_route = Some(route("/search"));
+        let shared_content_engine = content_engine_from_root(root.path(), config)
+            .expect("Content engine could not be created");
+
+        let content_engine = shared_content_engine.read().unwrap();
+        let results = content_engine
+            .search_index()
+            .expect("Search index was not built")
+            .search("penguins", 10)
+            .into_iter()
+            .map(|result| (result.route, result.title))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            results,
+            vec![
+                (route("/about"), String::from("About Us")),
+                (route("/notes"), String::from("notes")),
+            ]
+        );
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "templating"), ignore = "uses templates")]
+    fn drafts_are_hidden_unless_included() {
+        let root = temporary_directory_with_files(&[
+            ("draft.html.hbs", "---\ndraft: true\n---\n"),
+            ("future.html.hbs", "---\npublish-date: 9999-01-01\n---\n"),
+            ("past.html.hbs", "---\npublish-date: 2000-01-01\n---\n"),
+        ]);
+
+        for include_drafts in [false, true] {
+            let mut config = Config::default();
+            config.content.include_drafts = include_drafts;
+            let shared_content_engine = content_engine_from_root(root.path(), config)
+                .expect("Content engine could not be created");
+
+            let content_engine = shared_content_engine.read().unwrap();
+            assert_eq!(
+                content_engine.get(&route("/draft")).is_some(),
+                include_drafts
+            );
+            assert_eq!(
+                content_engine.get(&route("/future")).is_some(),
+                include_drafts
+            );
+            assert!(content_engine.get(&route("/past")).is_some());
+        }
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "templating"), ignore = "uses templates")]
+    fn mounted_directories_know_their_prefix() {
+        let main_root =
+            temporary_directory_with_files(&[("home.html.hbs", "[{{request.mount-prefix}}]")]);
+        let docs_root = temporary_directory_with_files(&[
+            ("_link.html.hbs", "{{request.mount-prefix}}/other"),
+            ("page.html.hbs", "{{> docs/_link.html.hbs}}"),
+        ]);
+
+        let mut directory = ContentDirectory::from_root(&main_root.path()).unwrap();
+        directory
+            .mount(
+                route("/docs"),
+                ContentDirectory::from_root(&docs_root.path()).unwrap(),
+            )
+            .unwrap();
+        let shared_content_engine = TestContentEngine::from_content_directory(directory, ())
+            .expect("Content engine could not be created");
+        let content_engine = shared_content_engine.read().unwrap();
+
+        let render = |route: Route| {
+            let context = content_engine.render_context(
+                Some(route.clone()),
+                QueryParameters::default(),
+                HashMap::new(),
+            );
+            content_engine
+                .get(&route)
+                .expect("Template was not registered")
+                .render(context, &[mime::TEXT_HTML])
+                .map(media_to_string)
+                .expect("Rendering failed")
+        };
+        assert_eq!(render(route("/home")), "[]");
+        assert_eq!(render(route("/docs/page")), "/docs/other");
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "templating"), ignore = "uses templates")]
+    fn templates_can_ask_to_be_prerendered() {
+        let (_root, shared_content_engine) = content_engine_in_temporary_directory(
+            &[
+                ("b.html.hbs", "---\nprerender: true\n---\nb"),
+                ("a.html.hbs", "---\nprerender: true\n---\na"),
+                ("c.html.hbs", "---\nprerender: false\n---\nc"),
+                ("d.html.hbs", "d"),
+            ],
+            Config::default(),
+        )
+        .expect("Content engine could not be created");
+
+        let content_engine = shared_content_engine.read().unwrap();
+        assert_eq!(
+            content_engine.prerender_routes(),
+            vec![route("/a"), route("/b")]
+        );
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "templating"), ignore = "uses templates")]
+    fn templates_can_be_scheduled_as_tasks() {
+        let config = toml::from_str::<Config>(
+            r#"
+            tasks."/daily" = { schedule = "0 6 * * *" }
+            "#,
+        )
+        .unwrap();
+        let (_root, shared_content_engine) = content_engine_in_temporary_directory(
+            &[
+                (
+                    "hourly.txt.hbs",
+                    "---\ntask:\n  schedule: '@hourly'\n---\nhourly",
+                ),
+                (
+                    "daily.txt.hbs",
+                    "---\ntask:\n  schedule: '@daily'\n---\ndaily",
+                ),
+                ("never.txt.hbs", "never"),
+            ],
+            config,
+        )
+        .expect("Content engine could not be created");
+
+        let content_engine = shared_content_engine.read().unwrap();
+
+        let tasks = content_engine.tasks();
+        assert_eq!(
+            tasks.keys().collect::<Vec<_>>(),
+            vec![&route("/daily"), &route("/hourly")]
+        );
+        assert_eq!(tasks[&route("/daily")].schedule.to_string(), "0 6 * * *");
+        assert_eq!(tasks[&route("/hourly")].schedule.to_string(), "@hourly");
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "templating"), ignore = "uses templates")]
+    fn data_files_are_available_to_templates() {
+        let (_root, shared_content_engine) = content_engine_in_temporary_directory(
+            &[
+                ("_data/site.yaml", "title: Example"),
+                ("_data/authors/alice.json", r#"{ "name": "Alice" }"#),
+                ("_data/nav.toml", "home = \"/\""),
+                (
+                    "page.html.hbs",
+                    "{{data.site.title}} {{data.authors.alice.name}} {{data.nav.home}}",
+                ),
+            ],
+            Config::default(),
+        )
+        .expect("Content engine could not be created");
+        let content_engine = shared_content_engine.read().unwrap();
+
+        let context =
+            content_engine.render_context(None, QueryParameters::default(), HashMap::new());
+        let output = content_engine
+            .get(&route("/page"))
+            .expect("Template was not registered")
+            .render(context, &[mime::TEXT_HTML])
+            .map(media_to_string)
+            .expect("Rendering failed");
+        assert_eq!(output, "Example Alice /");
+        assert!(
+            content_engine.get_internal(&route("/_data/site")).is_none(),
+            "Data file was registered as content",
+        );
+    }
+
+    #[test]
+    fn invalid_data_files_are_errors() {
+        for (name, contents) in [
+            ("broken.json", "{"),
+            ("unsupported.txt", "hello"),
+            ("template.json.hbs", "{}"),
+        ] {
+            let path = format!("_data/{}", name);
+            assert!(
+                content_engine_in_temporary_directory(&[(&path, contents)], Config::default())
+                    .is_err(),
+                "Content engine was created with data file `{}`, but it should have failed",
+                name,
+            );
+        }
+    }
+
+    #[test]
+    fn redirects_are_registered() {
+        let (_root, shared_content_engine) = content_engine_in_temporary_directory(
+            &[("old.redirect", "/new 302\n"), ("_hidden.redirect", "/new")],
+            Config::default(),
+        )
+        .expect("Content engine could not be created");
+        let content_engine = shared_content_engine.read().unwrap();
+
+        let redirect = content_engine
+            .redirect(&route("/old"))
+            .expect("Redirect was not registered");
+        assert_eq!(redirect.location(), "/new");
+        assert_eq!(redirect.status_code(), 302);
+        assert!(content_engine.get(&route("/old")).is_none());
+        assert!(content_engine.redirect(&route("/_hidden")).is_none());
+    }
+
+    #[test]
+    fn invalid_redirects_are_errors() {
+        for files in [
+            vec![("old.redirect", "not a route")],
+            vec![("old.redirect", "/new 200")],
+            vec![("old.redirect", "/new"), ("old.html", "")],
+            vec![("old.txt", ""), ("old.redirect", "/new")],
+        ] {
+            assert!(
+                content_engine_in_temporary_directory(&files, Config::default()).is_err(),
+                "Content engine was created with {:?}, but it should have failed",
+                files,
+            );
+        }
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "templating"), ignore = "uses templates")]
+    fn site_data_from_config_is_available_to_templates() {
+        let config = toml::from_str::<Config>(
+            "[site]\ntitle = \"Example\"\n[routes.\"/news\".site]\nsection = \"News\"",
+        )
+        .expect("Config was invalid");
+        let (_root, shared_content_engine) = content_engine_in_temporary_directory(
+            &[("news/template.html.hbs", "{{site.title}}: {{site.section}}")],
+            config,
+        )
+        .expect("Content engine could not be created");
+
+        let content_engine = shared_content_engine.read().unwrap();
+
+        let route = route("/news/template");
+        let context = content_engine.render_context(
+            Some(route.clone()),
+            QueryParameters::default(),
+            HashMap::new(),
+        );
+        let output = content_engine
+            .get(&route)
+            .expect("Template was not registered")
+            .render(context, &[mime::TEXT_HTML])
+            .map(media_to_string)
+            .expect("Rendering failed");
+        assert_eq!(output, "Example: News");
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "templating"), ignore = "uses templates")]
+    fn escaping_helpers_make_untrusted_values_safe_to_include() {
+        let (_root, shared_content_engine) = content_engine_in_temporary_directory(
+            &[
+                (
+                    "comment.html",
+                    "<p onclick=\"steal()\">Nice <a href=\"javascript:steal()\">post</a>!\
+                    <script>steal()</script>",
+                ),
+                (
+                    "page.html.hbs",
+                    "---\ntitle: \"</script> & 'quotes'\"\n---\n\
+                    {{#sanitize-html}}{{{get \"/comment\"}}}{{/sanitize-html}}\n\
+                    <script>const title = {{json-escape front-matter.title}};</script>\n\
+                    <div data-title={{attr-escape front-matter.title}}></div>",
+                ),
+            ],
+            Config::default(),
+        )
+        .expect("Content engine could not be created");
+
+        let content_engine = shared_content_engine.read().unwrap();
+        let context = content_engine.render_context(
+            Some(route("/page")),
+            QueryParameters::default(),
+            HashMap::new(),
+        );
+        let rendered = content_engine
+            .get(&route("/page"))
+            .expect("Template was not registered")
+            .render(context, &[mime::TEXT_HTML])
+            .map(media_to_string)
+            .expect("Rendering failed");
+
+        assert_eq!(
+            rendered,
+            "<p>Nice <a rel=\"noopener noreferrer\">post</a>!</p>\n\
+            <script>const title = \"\\u003c/script\\u003e \\u0026 'quotes'\";</script>\n\
+            <div data-title=&#x3C;&#x2F;script&#x3E;&#x20;&#x26;&#x20;&#x27;quotes&#x27;></div>",
+        );
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "templating"), ignore = "uses templates")]
+    fn values_are_escaped_according_to_the_rendered_media_type() {
+        let front_matter = "---\ntitle: \"Tom & \\\"Jerry\\\" <3\"\n---\n";
+        let templates = [
+            ("page.html.hbs", "<h1>{{front-matter.title}}</h1>"),
+            ("page.json.hbs", "{\"title\": \"{{front-matter.title}}\"}"),
+            (
+                "page.txt.hbs",
+                "{{front-matter.title}} / {{html-escape front-matter.title}}",
+            ),
+            ("other.json.jinja", "{\"title\": {{ front_matter.title }}}"),
+            ("other.txt.jinja", "{{ front_matter.title }}"),
+        ];
+        let (_root, shared_content_engine) = content_engine_in_temporary_directory(
+            &templates.map(|(name, body)| (name, format!("{}{}", front_matter, body))),
+            Config::default(),
+        )
+        .expect("Content engine could not be created");
+
+        let content_engine = shared_content_engine.read().unwrap();
+        let render = |route_to_render: &str, media_range: MediaRange| {
+            let context = content_engine.render_context(
+                Some(route(route_to_render)),
+                QueryParameters::default(),
+                HashMap::new(),
+            );
+            content_engine
+                .get(&route(route_to_render))
+                .expect("Template was not registered")
+                .render(context, &[media_range])
+                .map(media_to_string)
+                .expect("Rendering failed")
+        };
+
+        assert_eq!(
+            render("/page", mime::TEXT_HTML),
+            "<h1>Tom &amp; &quot;Jerry&quot; &lt;3</h1>",
+        );
+        assert_eq!(
+            render("/page", mime::APPLICATION_JSON),
+            "{\"title\": \"Tom & \\\"Jerry\\\" <3\"}",
+        );
+        assert_eq!(
+            render("/page", mime::TEXT_PLAIN),
+            "Tom & \"Jerry\" <3 / Tom &amp; &quot;Jerry&quot; &lt;3",
+        );
+        assert_eq!(
+            render("/other", mime::APPLICATION_JSON),
+            "{\"title\": \"Tom & \\\"Jerry\\\" <3\"}",
+        );
+        assert_eq!(render("/other", mime::TEXT_PLAIN), "Tom & \"Jerry\" <3");
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "templating"), ignore = "uses templates")]
+    fn serialization_helpers_write_json_and_csv() {
+        let (_root, shared_content_engine) = content_engine_in_temporary_directory(
+            &[
+                (
+                    "_data/people.json",
+                    r#"[{"name": "Ann", "email": "ann@example.com", "bio": "Likes \"quotes\", commas"}]"#,
+                ),
+                (
+                    "people.json.hbs",
+                    "{{to-json data.people}}\n{{to-json data.people.[0].name pretty=true}}",
+                ),
+                (
+                    "people.csv.hbs",
+                    "---\ncolumns: [email, name]\n---\n\
+                    {{to-csv data.people}}{{to-csv data.people headers=front-matter.columns}}",
+                ),
+            ],
+            Config::default(),
+        )
+        .expect("Content engine could not be created");
+
+        let content_engine = shared_content_engine.read().unwrap();
+        let render = |media_range: MediaRange| {
+            let context = content_engine.render_context(
+                Some(route("/people")),
+                QueryParameters::default(),
+                HashMap::new(),
+            );
+            content_engine
+                .get(&route("/people"))
+                .expect("Template was not registered")
+                .render(context, &[media_range])
+                .map(media_to_string)
+                .expect("Rendering failed")
+        };
+
+        assert_eq!(
+            render(mime::APPLICATION_JSON),
+            "[{\"bio\":\"Likes \\\"quotes\\\", commas\",\"email\":\"ann@example.com\",\"name\":\"Ann\"}]\n\
+            \"Ann\"",
+        );
+        assert_eq!(
+            render(mime::TEXT_CSV),
+            "bio,email,name\r\n\
+            \"Likes \"\"quotes\"\", commas\",ann@example.com,Ann\r\n\
+            email,name\r\n\
+            ann@example.com,Ann\r\n",
+        );
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "templating"), ignore = "uses templates")]
+    fn xml_templates_are_escaped_and_negotiated_as_xml() {
+        let (_root, shared_content_engine) = content_engine_in_temporary_directory(
+            &[
+                (
+                    "feed.atom.hbs",
+                    "---\ntitle: \"Tom's \\u0007feed\"\nbody: \"<p>a ]]> b</p>\"\n---\n\
+                    <title>{{front-matter.title}}</title>\
+                    <content type=\"html\">{{#cdata}}{{{front-matter.body}}}{{/cdata}}</content>",
+                ),
+                ("sitemap.xml.hbs", "xml"),
+                ("sitemap.rss.hbs", "rss"),
+            ],
+            Config::default(),
+        )
+        .expect("Content engine could not be created");
+
+        let content_engine = shared_content_engine.read().unwrap();
+        let render = |route_to_render: &str, media_range: &str| {
+            let context = content_engine.render_context(
+                Some(route(route_to_render)),
+                QueryParameters::default(),
+                HashMap::new(),
+            );
+            content_engine
+                .get(&route(route_to_render))
+                .expect("Template was not registered")
+                .render(context, &[media_range.parse::<MediaRange>().unwrap()])
+                .map(|media| (media.media_type.to_string(), media_to_string(media)))
+                .expect("Rendering failed")
+        };
+
+        assert_eq!(
+            render("/feed", "application/xml"),
+            (
+                String::from("application/atom+xml"),
+                String::from(
+                    "<title>Tom&apos;s feed</title>\
+                    <content type=\"html\"><![CDATA[<p>a ]]]]><![CDATA[> b</p>]]></content>"
+                ),
+            ),
+        );
+        assert_eq!(
+            render("/sitemap", "application/xml"),
+            (String::from("text/xml"), String::from("xml")),
+        );
+        assert_eq!(
+            render("/sitemap", "application/rss+xml"),
+            (String::from("application/rss+xml"), String::from("rss")),
+        );
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "templating"), ignore = "uses templates")]
+    fn og_meta_helper_uses_front_matter_and_site_data() {
+        let config = toml::from_str::<Config>(
+            r#"
+            base-url = "https://example.com"
+            [site]
+            title = "Example"
+            description = "An example site"
+            image = "/images/card.png"
+            "#,
+        )
+        .expect("Config was invalid");
+        let (_root, shared_content_engine) = content_engine_in_temporary_directory(
+            &[
+                (
+                    "posts/hello.html.hbs",
+                    "---\ntitle: Hello & welcome\nog-type: article\n---\n{{og-meta}}",
+                ),
+                ("about.html.hbs", "{{og-meta}}"),
+            ],
+            config,
+        )
+        .expect("Content engine could not be created");
+        let content_engine = shared_content_engine.read().unwrap();
+        let render = |route_to_render: &str| {
+            let context = content_engine.render_context(
+                Some(route(route_to_render)),
+                QueryParameters::default(),
+                HashMap::new(),
+            );
+            content_engine
+                .get(&route(route_to_render))
+                .expect("Template was not registered")
+                .render(context, &[mime::TEXT_HTML])
+                .map(media_to_string)
+                .expect("Rendering failed")
+        };
+
+        assert_eq!(
+            render("/posts/hello"),
+            "<meta property=\"og:type\" content=\"article\">\n\
+            <meta property=\"og:title\" content=\"Hello &amp; welcome\">\n\
+            <meta property=\"og:description\" content=\"An example site\">\n\
+            <meta property=\"og:url\" content=\"https://example.com/posts/hello\">\n\
+            <meta property=\"og:image\" content=\"https://example.com/images/card.png\">\n\
+            <meta property=\"og:site_name\" content=\"Example\">\n\
+            <meta name=\"twitter:card\" content=\"summary_large_image\">\n\
+            <meta name=\"twitter:title\" content=\"Hello &amp; welcome\">\n\
+            <meta name=\"twitter:description\" content=\"An example site\">\n\
+            <meta name=\"twitter:image\" content=\"https://example.com/images/card.png\">",
+        );
+        assert!(render("/about").starts_with(
+            "<meta property=\"og:type\" content=\"website\">\n\
+                <meta property=\"og:title\" content=\"Example\">"
+        ));
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "executables"), ignore = "uses executables")]
+    fn non_executable_scripts_can_be_run_by_configured_interpreters() {
+        let root = temporary_directory_with_files(&[("script.txt.sh", "echo \"hello from $0\"")]);
+
+        assert!(
+            content_engine_from_root(root.path(), Config::default()).is_err(),
+            "Content engine was created, but the script should not have been supported",
+        );
+
+        let config = toml::from_str::<Config>("[executables.interpreters]\nsh = \"sh -e\"")
+            .expect("Config was invalid");
+        let shared_content_engine = content_engine_from_root(root.path(), config)
+            .expect("Content engine could not be created");
+
+        let content_engine = shared_content_engine.read().unwrap();
+        let context =
+            content_engine.render_context(None, QueryParameters::default(), HashMap::new());
+        let output = content_engine
+            .get(&route("/script"))
+            .expect("Script was not registered")
+            .render(context, &[mime::TEXT_PLAIN])
+            .map(media_to_string)
+            .expect("Rendering failed");
+        assert_eq!(
+            output,
+            format!(
+                "hello from {}\n",
+                root.path().join("script.txt.sh").display()
+            ),
+        );
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "executables"), ignore = "uses executables")]
+    fn executable_arguments_can_come_from_front_matter() {
+        let root = temporary_directory_with_files(&[
+            (
+                "from-front-matter.txt.sh",
+                "# ---operator\n# arguments: [\"--\", \"{query:name}\", \"{route}\"]\n\
+                # cache-ttl: 600\n# ---\necho \"$2 $3\"",
+            ),
+            ("from-config.txt.sh", "echo \"$1\""),
+        ]);
+
+        let config = toml::from_str::<Config>(
+            r#"
+            [executables.interpreters]
+            sh = "sh -e"
+
+            [executables.arguments]
+            "/from-front-matter" = ["ignored"]
+            "/from-config" = ["config"]
+            "#,
+        )
+        .expect("Config was invalid");
+        let shared_content_engine = content_engine_from_root(root.path(), config)
+            .expect("Content engine could not be created");
+        let content_engine = shared_content_engine.read().unwrap();
+        let render = |route_path: &str| {
+            let query_parameters =
+                QueryParameters::new(vec![(String::from("name"), String::from("world"))]);
+            let context = content_engine.render_context(
+                Some(route(route_path)),
+                query_parameters,
+                HashMap::new(),
+            );
+            content_engine
+                .get(&route(route_path))
+                .expect("Script was not registered")
+                .render(context, &[mime::TEXT_PLAIN])
+                .map(media_to_string)
+                .expect("Rendering failed")
+        };
+        assert_eq!(render("/from-front-matter"), "world /from-front-matter\n");
+        assert_eq!(render("/from-config"), "config\n");
+        for (route_path, caches_output) in [("/from-front-matter", true), ("/from-config", false)] {
+            assert_eq!(
+                content_engine
+                    .get(&route(route_path))
+                    .expect("Script was not registered")
+                    .values()
+                    .any(RegisteredContent::warms_caches),
+                caches_output,
+                "Wrong caching for {}",
+                route_path,
+            );
+        }
+
+        std::fs::write(
+            root.path().join("unterminated.txt.sh"),
+            "# ---operator\n# arguments: []\necho",
+        )
+        .unwrap();
+        assert!(
+            content_engine_from_root(
+                root.path(),
+                toml::from_str::<Config>("[executables.interpreters]\nsh = \"sh -e\"").unwrap(),
+            )
+            .is_err(),
+            "Content engine was created, but the front matter was unterminated",
+        );
+    }
+
+    #[test]
+    #[cfg(not(feature = "executables"))]
+    fn executables_are_not_supported_without_the_feature() {
+        let config = toml::from_str::<Config>("[executables.interpreters]\nsh = \"sh -e\"")
+            .expect("Config was invalid");
+        match content_engine_in_temporary_directory(&[("script.txt.sh", "echo hello")], config) {
+            Err(ContentLoadingErrors(errors)) => assert!(
+                matches!(
+                    errors.as_slice(),
+                    [(_, ContentLoadingError::ExecutablesNotSupported(path))] if path == "script.txt.sh"
+                ),
+                "Unexpected errors: {:?}",
+                errors
+            ),
+            Ok(_) => panic!("Content engine was created, but executables should not be supported"),
+        }
+    }
+
+    #[test]
+    #[cfg(not(feature = "templating"))]
+    fn templates_are_not_supported_without_the_feature() {
+        match content_engine_in_temporary_directory(
+            &[("page.html.hbs", "hello")],
+            Config::default(),
+        ) {
+            Err(ContentLoadingErrors(errors)) => assert!(
+                matches!(
+                    errors.as_slice(),
+                    [(_, ContentLoadingError::TemplatesNotSupported(path))] if path == "page.html.hbs"
+                ),
+                "Unexpected errors: {:?}",
+                errors
+            ),
+            Ok(_) => panic!("Content engine was created, but templates should not be supported"),
+        }
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "templating"), ignore = "uses templates")]
+    fn strict_mode_can_be_disabled() {
+        let template = "a{{front-matter.missing}}b";
+
+        assert!(
+            render_template_in_temporary_directory(template, &ContentConfig::default()).is_err(),
+            "Rendering succeeded in strict mode, but it should have failed",
+        );
+
+        let output = render_template_in_temporary_directory(
+            template,
+            &ContentConfig {
+                strict_templates: false,
+                ..ContentConfig::default()
+            },
+        )
+        .expect("Rendering failed");
+        assert_eq!(output, "ab");
+
+        let output = render_template_in_temporary_directory(
+            &format!("---\nstrict: false\n---\n{}", template),
+            &ContentConfig::default(),
+        )
+        .expect("Rendering failed");
+        assert_eq!(output, "ab");
+
+        assert!(
+            render_template_in_temporary_directory(
+                &format!("---\nstrict: true\n---\n{}", template),
+                &ContentConfig {
+                    strict_templates: false,
+                    ..ContentConfig::default()
+                },
+            )
+            .is_err(),
+            "Rendering succeeded in strict mode, but it should have failed",
+        );
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "templating"), ignore = "uses templates")]
+    fn defaults_can_be_provided_for_missing_data() {
+        let config = ContentConfig {
+            template_defaults:
+                serde_json::json!({ "site-name": "Site", "front-matter": { "subtitle": "None" } })
+                    .as_object()
+                    .unwrap()
+                    .clone(),
+            ..ContentConfig::default()
+        };
+        let output = render_template_in_temporary_directory(
+            "---\ntitle: Hi\ndefaults:\n  front-matter:\n    title: Untitled\n    author: Anonymous\n---\n\
+            {{site-name}}/{{front-matter.title}}/{{front-matter.author}}/{{front-matter.subtitle}}",
+            &config,
+        )
+        .expect("Rendering failed");
+        assert_eq!(output, "Site/Hi/Anonymous/None");
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "templating"), ignore = "uses templates")]
+    fn template_error_locations_account_for_front_matter() {
+        let errors = content_engine_in_temporary_directory(
+            &[("template.html.hbs", "---\ntitle: Hi\n---\nfine\n{{if !}}")],
+            Config::default(),
+        )
+        .err()
+        .expect("Content engine was successfully created, but this should have failed");
+        match errors.iter().next() {
+            Some((_, ContentLoadingError::TemplateRegistrationError(template_error))) => {
+                assert_eq!(template_error.line(), Some(5));
+            }
+            _ => panic!("Wrong type of error was produced: {}", errors),
+        };
+    }
+
+    #[test]
+    fn new_templates_can_be_rendered() {
+        let shared_content_engine = TestContentEngine::from_content_directory(
+            arbitrary_content_directory_with_valid_content(),
+            (),
+        )
+        .expect("Content engine could not be created");
+        let content_engine = shared_content_engine.read().unwrap();
+
+        for &(template, expected_output) in &VALID_TEMPLATES {
+            let renderable = content_engine
+                .new_template(
+                    template,
+                    MediaType::from_media_range(mime::TEXT_HTML).unwrap(),
+                )
+                .expect("Template could not be parsed");
+            let rendered = renderable
+                .render(
+                    content_engine.render_context(None, QueryParameters::default(), HashMap::new()),
+                    &[mime::TEXT_HTML],
+                )
+                .unwrap_or_else(|_| panic!("Template rendering failed for `{}`", template));
+            let actual_output = media_to_string(rendered);
+
+            assert_eq!(
+                actual_output,
+                expected_output,
+                "Template rendering for `{}` did not produce the expected output (\"{}\"), instead got \"{}\"",
+                template,
+                expected_output,
+                actual_output,
+            );
+        }
+    }
+
+    #[test]
+    fn new_template_fails_for_invalid_templates() {
+        let shared_content_engine = TestContentEngine::from_content_directory(
+            arbitrary_content_directory_with_valid_content(),
+            (),
+        )
+        .expect("Content engine could not be created");
+        let content_engine = shared_content_engine.read().unwrap();
+
+        for &template in &INVALID_TEMPLATES {
+            let result = content_engine.new_template(
+                template,
+                MediaType::from_media_range(mime::TEXT_HTML).unwrap(),
+            );
+
+            assert!(
+                result.is_err(),
+                "Content was successfully created for invalid template `{}`, but it should have failed",
+                template,
+            );
+        }
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "templating"), ignore = "uses templates")]
+    fn new_templates_can_reference_partials_from_content_directory() {
+        let directory = ContentDirectory::from_root(&sample_path("partials")).unwrap();
+        let shared_content_engine = TestContentEngine::from_content_directory(directory, ())
+            .expect("Content engine could not be created");
+        let content_engine = shared_content_engine.read().unwrap();
+
+        let template = "this is partial: {{> abc.html.hbs}}";
+        let expected_output =
+            "this is partial: a\nb\nc\n\nsubdirectory entries:\n/subdirectory/c\n";
+
+        let renderable = content_engine
+            .new_template(
+                template,
+                MediaType::from_media_range(mime::TEXT_HTML).unwrap(),
+            )
+            .expect("Template could not be parsed");
+        let rendered = renderable
+            .render(
+                content_engine.render_context(None, QueryParameters::default(), HashMap::new()),
+                &[mime::TEXT_HTML],
+            )
+            .unwrap_or_else(|_| panic!("Template rendering failed for `{}`", template));
+        let actual_output = media_to_string(rendered);
+
+        assert_eq!(
+            actual_output,
+            expected_output,
+            "Template rendering for `{}` did not produce the expected output (\"{}\"), instead got \"{}\"",
+            template,
+            expected_output,
+            actual_output,
+        );
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "templating"), ignore = "uses templates")]
+    fn content_can_be_retrieved() {
+        let directory = ContentDirectory::from_root(&sample_path("partials")).unwrap();
+        let shared_content_engine = TestContentEngine::from_content_directory(directory, ())
+            .expect("Content engine could not be created");
+        let content_engine = shared_content_engine.read().unwrap();
+
+        let route = route("/abc");
+        let expected_output = "a\nb\nc\n\nsubdirectory entries:\n/subdirectory/c\n";
+
+        let content = content_engine
+            .get(&route)
+            .expect("Content could not be found");
+        let rendered = content
+            .render(
+                content_engine.render_context(None, QueryParameters::default(), HashMap::new()),
+                &[mime::TEXT_HTML],
+            )
+            .unwrap_or_else(|_| panic!("Template rendering failed for content at '{}'", route));
+        let actual_output = media_to_string(rendered);
+
+        assert_eq!(
+            actual_output,
+            expected_output,
+            "Rendering content at '{}' did not produce the expected output (\"{}\"), instead got \"{}\"",
+            route,
+            expected_output,
+            actual_output,
+        );
+    }
+
+    #[test]
+    fn content_may_not_exist_at_route() {
+        let directory = ContentDirectory::from_root(&sample_path("hello-world")).unwrap();
+        let shared_content_engine = TestContentEngine::from_content_directory(directory, ())
+            .expect("Content engine could not be created");
+        let content_engine = shared_content_engine.read().unwrap();
+
+        let route = route("/this-route-does-not-refer-to-any-content");
+
+        assert!(
+            content_engine.get(&route).is_none(),
+            "Content was found at '{}', but it was not expected to be",
+            route
+        );
+    }
+
+    #[test]
+    fn handlebars_extension_agrees_with_mime_guess() {
+        let mime_guess_handlebars_extension =
+            mime_guess::get_extensions("text", "x-handlebars-template")
+                .unwrap()
+                .first()
+                .unwrap();
+        let content_engine_handlebars_extension = TemplateLanguage::Handlebars.file_extension();
+
+        assert_eq!(
+            mime_guess_handlebars_extension,
+            &content_engine_handlebars_extension,
+        );
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "templating"), ignore = "uses templates")]
+    fn get_helper_is_available() {
+        let directory = ContentDirectory::from_root(&sample_path("partials")).unwrap();
+        let shared_content_engine = TestContentEngine::from_content_directory(directory, ())
+            .expect("Content engine could not be created");
+        let content_engine = shared_content_engine.read().unwrap();
+
+        let template = "i got stuff: {{get [/].b}}";
+        let expected_output = "i got stuff: b\n";
+
+        let renderable = content_engine
+            .new_template(
+                template,
+                MediaType::from_media_range(mime::TEXT_HTML).unwrap(),
+            )
+            .expect("Template could not be parsed");
+        let rendered = renderable
+            .render(
+                content_engine.render_context(None, QueryParameters::default(), HashMap::new()),
+                &[mime::TEXT_HTML],
+            )
+            .unwrap_or_else(|_| panic!("Template rendering failed for `{}`", template));
+        let actual_output = media_to_string(rendered);
+
+        assert_eq!(
+            actual_output,
+            expected_output,
+            "Template rendering for `{}` did not produce the expected output (\"{}\"), instead got \"{}\"",
+            template,
+            expected_output,
+            actual_output,
+        );
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "templating"), ignore = "uses templates")]
+    fn layout_helper_wraps_content_and_fills_named_blocks() {
+        let directory = ContentDirectory::from_root(&sample_path("layouts")).unwrap();
+        let shared_content_engine = TestContentEngine::from_content_directory(directory, ())
+            .expect("Content engine could not be created");
+        let content_engine = shared_content_engine.read().unwrap();
+
+        let render = |route_to_render: &str| {
+            let context =
+                content_engine.render_context(None, QueryParameters::default(), HashMap::new());
+            content_engine
+                .get(&route(route_to_render))
+                .expect("Content was not registered")
+                .render(context, &[mime::TEXT_HTML])
+                .map(media_to_string)
+                .unwrap_or_else(|error| panic!("Rendering {} failed: {}", route_to_render, error))
+        };
+
+        let index = render("/index");
+        assert!(index.starts_with("<!doctype html>"), "Got \"{}\"", index);
+        assert!(index.contains("<title>Home</title>"), "Got \"{}\"", index);
+        assert!(index.contains("<h1>Welcome</h1>"), "Got \"{}\"", index);
+
+        let untitled = render("/untitled");
+        assert!(
+            untitled.contains("<title>Untitled</title>"),
+            "Got \"{}\"",
+            untitled
+        );
+        assert!(
+            untitled.contains("<p>This page uses the layout's default title.</p>"),
+            "Got \"{}\"",
+            untitled,
+        );
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "templating"), ignore = "uses templates")]
+    fn layouts_cannot_be_nested() {
+        let directory = ContentDirectory::from_root(&sample_path("layouts")).unwrap();
+        let shared_content_engine = TestContentEngine::from_content_directory(directory, ())
+            .expect("Content engine could not be created");
+        let content_engine = shared_content_engine.read().unwrap();
+
+        let template =
+            "{{#layout \"/_layouts/base\"}}{{#layout \"/_layouts/base\"}}x{{/layout}}{{/layout}}";
+        let renderable = content_engine
+            .new_template(
+                template,
+                MediaType::from_media_range(mime::TEXT_HTML).unwrap(),
+            )
+            .expect("Template could not be parsed");
+        let result = renderable.render(
+            content_engine.render_context(None, QueryParameters::default(), HashMap::new()),
+            &[mime::TEXT_HTML],
+        );
+
+        assert!(
+            result.is_err(),
+            "Nested layouts were rendered, but this should have failed",
+        );
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "templating"), ignore = "uses templates")]
+    fn layouts_do_not_leak_partials() {
+        let directory = ContentDirectory::from_root(&sample_path("layouts")).unwrap();
+        let shared_content_engine = TestContentEngine::from_content_directory(directory, ())
+            .expect("Content engine could not be created");
+        let content_engine = shared_content_engine.read().unwrap();
+        let render = |template: &str| {
+            content_engine
+                .new_template(
+                    template,
+                    MediaType::from_media_range(mime::TEXT_HTML).unwrap(),
+                )
+                .expect("Template could not be parsed")
+                .render(
+                    content_engine.render_context(None, QueryParameters::default(), HashMap::new()),
+                    &[mime::TEXT_HTML],
+                )
+                .map(media_to_string)
+        };
+
+        let siblings = render(
+            "{{#*inline \"content\"}}mine{{/inline}}\
+            {{#layout \"/_layouts/base\"}}{{#*inline \"title\"}}One{{/inline}}first{{/layout}}\
+            {{#layout \"/_layouts/base\"}}second{{/layout}}\
+            {{> content}}",
+        )
+        .expect("Rendering sibling layouts failed");
+        assert!(
+            siblings.contains("<title>One</title>"),
+            "Got \"{}\"",
+            siblings
+        );
+        assert!(siblings.contains("first"), "Got \"{}\"", siblings);
+        // The second layout does not get the first one's title.
+        assert!(
+            siblings.contains("<title>Untitled</title>"),
+            "Got \"{}\"",
+            siblings
+        );
+        assert!(siblings.contains("second"), "Got \"{}\"", siblings);
+        assert!(siblings.ends_with("mine"), "Got \"{}\"", siblings);
+
+        assert!(
+            render("{{#layout \"/_layouts/base\"}}x{{/layout}}{{> title}}").is_err(),
+            "A partial from the layout's block was visible after it",
+        );
+    }
+
+    #[test]
+    #[cfg_attr(
+        not(all(feature = "executables", feature = "templating")),
+        ignore = "uses executables and templates"
+    )]
+    fn representations_helper_lists_alternate_representations() {
+        let directory =
+            ContentDirectory::from_root(&sample_path("alternative-representations")).unwrap();
+        let shared_content_engine = TestContentEngine::from_content_directory(directory, ())
+            .expect("Content engine could not be created");
+        let content_engine = shared_content_engine.read().unwrap();
+
+        let template = "{{#each (representations \"/foo\")}}{{media-type}} {{url}}\n{{/each}}";
+        let renderable = content_engine
+            .new_template(
+                template,
+                MediaType::from_media_range(mime::TEXT_HTML).unwrap(),
+            )
+            .expect("Template could not be parsed");
+
+        let render = |request_route| {
+            let rendered = renderable
+                .render(
+                    content_engine.render_context(
+                        request_route,
+                        QueryParameters::default(),
+                        HashMap::new(),
+                    ),
+                    &[mime::TEXT_HTML],
+                )
+                .unwrap_or_else(|_| panic!("Template rendering failed for `{}`", template));
+            media_to_string(rendered)
+        };
+
+        assert_eq!(
+            render(None),
+            "application/json /foo.json\n\
+            text/html /foo.html\n\
+            text/markdown /foo.md\n\
+            text/plain /foo.txt\n\
+            text/x-toml /foo.toml\n\
+            text/xml /foo.xml\n",
+        );
+        assert_eq!(
+            render(Some(route("/foo"))),
+            "application/json /foo.json\n\
+            text/markdown /foo.md\n\
+            text/plain /foo.txt\n\
+            text/x-toml /foo.toml\n\
+            text/xml /foo.xml\n",
+        );
+    }
+
+    #[test]
+    fn absolute_url_helper_uses_base_url() {
+        let render = |config: Config, template: &str| {
+            let directory = ContentDirectory::from_root(&sample_path("empty")).unwrap();
+            let shared_content_engine =
+                TestContentEngine::from_content_directory_with_config(directory, (), config)
+                    .expect("Content engine could not be created");
+            let content_engine = shared_content_engine.read().unwrap();
+            let renderable = content_engine
+                .new_template(
+                    template,
+                    MediaType::from_media_range(mime::TEXT_HTML).unwrap(),
+                )
+                .expect("Template could not be parsed");
+            renderable
+                .render(
+                    content_engine.render_context(None, QueryParameters::default(), HashMap::new()),
+                    &[mime::TEXT_HTML],
+                )
+                .map(media_to_string)
+        };
+
+        let config = Config {
+            base_url: Some(String::from("https://example.com/")),
+            ..Config::default()
+        };
+        assert_eq!(
+            render(
+                config,
+                "{{request.base-url}} {{absolute-url \"/feed.xml\"}}"
+            )
+            .expect("Rendering failed"),
+            "https://example.com https://example.com/feed.xml",
+        );
+
+        assert!(
+            render(Config::default(), "{{absolute-url \"/feed.xml\"}}").is_err(),
+            "Rendering an absolute URL without a base URL succeeded, but it should have failed",
+        );
+    }
+
+    #[test]
+    fn date_helpers_use_the_configured_clock_and_time_zone() {
+        let render = |config: Config, template: &str| {
+            let directory = ContentDirectory::from_root(&sample_path("empty")).unwrap();
+            let shared_content_engine =
+                TestContentEngine::from_content_directory_with_config(directory, (), config)
+                    .expect("Content engine could not be created");
+            let content_engine = shared_content_engine.read().unwrap();
+            let renderable = content_engine
+                .new_template(
+                    template,
+                    MediaType::from_media_range(mime::TEXT_HTML).unwrap(),
+                )
+                .expect("Template could not be parsed");
+            renderable
+                .render(
+                    content_engine.render_context(None, QueryParameters::default(), HashMap::new()),
+                    &[mime::TEXT_HTML],
+                )
+                .map(media_to_string)
+        };
+
+        let config = toml::from_str::<Config>(
+            r#"
+            [time]
+            clock = "2024-05-01T09:30:00Z"
+            timezone = "+09:00"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            render(
+                config.clone(),
+                "{{now}} | {{now format=\"%Y\"}} | {{now timezone=\"UTC\" format=\"%H:%M\"}}"
+            )
+            .expect("Rendering failed"),
+            "2024-05-01T18:30:00+09:00 | 2024 | 09:30",
+        );
+        assert_eq!(
+            render(
+                config.clone(),
+                "{{now timezone=\"Asia/Tokyo\"}} | {{now timezone=\"America/New_York\"}}"
+            )
+            .expect("Rendering failed"),
+            "2024-05-01T18:30:00+09:00 | 2024-05-01T05:30:00-04:00",
+        );
+        assert_eq!(
+            render(
+                config.clone(),
+                "{{format-date \"2024-02-29\" format=\"%e %B %Y\" timezone=\"UTC\"}} | \
+                {{format-date 0 format=\"%F %T\"}}"
+            )
+            .expect("Rendering failed"),
+            "29 February 2024 | 1970-01-01 09:00:00",
+        );
+
+        let deterministic_config = Config {
+            deterministic: true,
+            ..Config::default()
+        };
+        assert_eq!(
+            render(deterministic_config, "{{now}}").expect("Rendering failed"),
+            "1970-01-01T00:00:00+00:00",
+        );
+
+        for template in [
+            "{{now format=\"%Q\"}}",
+            "{{now timezone=\"Mars/Olympus_Mons\"}}",
+            "{{format-date \"yesterday\"}}",
+            "{{format-date}}",
+        ] {
+            assert!(
+                render(config.clone(), template).is_err(),
+                "Rendering `{}` succeeded, but it should have failed",
+                template,
+            );
+        }
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "templating"), ignore = "uses templates")]
+    fn file_info_helper_describes_static_files() {
+        let root = temporary_directory_with_files(&[
+            ("downloads/notes.txt", ""),
+            (
+                "downloads.html.hbs",
+                "{{#with (file-info \"/downloads/notes.txt\")}}\
+                {{size}} {{human-size}} {{media-type}} {{modified}}\
+                {{/with}}",
+            ),
+            ("template.html.hbs", "{{file-info \"/downloads.html\"}}"),
+        ]);
+        let notes = std::fs::File::options()
+            .write(true)
+            .open(root.path().join("downloads/notes.txt"))
+            .unwrap();
+        notes.set_len(4200).unwrap();
+        // 2024-05-01T09:30:00Z.
+        notes
+            .set_modified(SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1714555800))
+            .unwrap();
+        drop(notes);
+
+        let render = |config: Config, route_to_render: &str| {
+            let shared_content_engine = content_engine_from_root(root.path(), config)
+                .expect("Content engine could not be created");
+
+            let content_engine = shared_content_engine.read().unwrap();
+            let context = content_engine.render_context(
+                Some(route(route_to_render)),
+                QueryParameters::default(),
+                HashMap::new(),
+            );
+            content_engine
+                .get(&route(route_to_render))
+                .expect("Template was not registered")
+                .render(context, &[mime::TEXT_HTML])
+                .map(media_to_string)
+        };
+
+        assert_eq!(
+            render(Config::default(), "/downloads").expect("Rendering failed"),
+            "4200 4.2 kB text/plain 2024-05-01T09:30:00Z",
+        );
+        let deterministic_config = Config {
+            deterministic: true,
+            ..Config::default()
+        };
+        assert_eq!(
+            render(deterministic_config, "/downloads").expect("Rendering failed"),
+            "4200 4.2 kB text/plain 1970-01-01T00:00:00Z",
+        );
+        assert!(
+            render(Config::default(), "/template").is_err(),
+            "Getting file info for a template succeeded, but it should have failed",
+        );
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "templating"), ignore = "uses templates")]
+    fn integrity_helper_hashes_scripts_and_stylesheets() {
+        let (_root, shared_content_engine) = content_engine_in_temporary_directory(
+            &[
+                ("js/app.js", "alert('Hello, world.');"),
+                ("image.png", "not really an image"),
+                (
+                    "page.html.hbs",
+                    "{{{integrity \"/js/app.js\"}}}\n\
+                    {{{integrity \"/js/app\" algo=\"sha256\"}}}\n\
+                    {{{integrity \"/js/app.js\" algo=\"sha512\"}}}",
+                ),
+                ("image.html.hbs", "{{integrity \"/image.png\"}}"),
+                ("md5.html.hbs", "{{integrity \"/js/app.js\" algo=\"md5\"}}"),
+            ],
+            Config::default(),
+        )
+        .expect("Content engine could not be created");
+
+        let content_engine = shared_content_engine.read().unwrap();
+        let render = |route_to_render: &str| {
+            let context = content_engine.render_context(
+                Some(route(route_to_render)),
+                QueryParameters::default(),
+                HashMap::new(),
+            );
+            content_engine
+                .get(&route(route_to_render))
+                .expect("Template was not registered")
+                .render(context, &[mime::TEXT_HTML])
+                .map(media_to_string)
+        };
+
+        assert_eq!(
+            render("/page").expect("Rendering failed"),
+            "sha384-H8BRh8j48O9oYatfu5AZzq6A9RINhZO5H16dQZngK7T62em8MUt1FLm52t+eX6xO\n\
+            sha256-qznLcsROx4GACP2dm0UCKCzCG+HiZ1guq6ZZDob/Tng=\n\
+            sha512-Q2bFTOhEALkN8hOms2FKTDLy7eugP2zFZ1T8LCvX42Fp3WoNr3bjZSAHeOsHrbV1Fu9/A0EzCinRE7Af1ofPrw==",
+        );
+        assert!(
+            render("/image").is_err(),
+            "Getting the integrity of an image succeeded, but it should have failed",
+        );
+        assert!(
+            render("/md5").is_err(),
+            "Getting the integrity with an unsupported algorithm succeeded, but it should have \
+            failed",
+        );
+    }
+
+    #[test]
+    fn highlight_helper_highlights_code() {
+        let directory = ContentDirectory::from_root(&sample_path("empty")).unwrap();
+        let shared_content_engine = TestContentEngine::from_content_directory(directory, ())
+            .expect("Content engine could not be created");
+        let content_engine = shared_content_engine.read().unwrap();
+        let render = |template: &str| {
+            content_engine
+                .new_template(
+                    template,
+                    MediaType::from_media_range(mime::TEXT_HTML).unwrap(),
+                )
+                .expect("Template could not be parsed")
+                .render(
+                    content_engine.render_context(None, QueryParameters::default(), HashMap::new()),
+                    &[mime::TEXT_HTML],
+                )
+                .map(media_to_string)
+        };
+
+        let highlighted = render("{{#highlight lang=\"python\"}}\nreturn \"<b>\"\n{{/highlight}}")
+            .expect("Rendering failed");
+        assert!(
+            highlighted.starts_with(
+                "<pre class=\"highlight\"><code class=\"language-python\">\
+                <span class=\"hl-source hl-python\">\
+                <span class=\"hl-keyword hl-control hl-flow hl-return hl-python\">return</span>"
+            ),
+            "{}",
+            highlighted,
+        );
+        assert!(highlighted.contains("&lt;b&gt;"), "{}", highlighted);
+        assert!(highlighted.ends_with("</code></pre>"), "{}", highlighted);
+        assert_eq!(
+            render("{{highlight \"a < b\"}}").expect("Rendering failed"),
+            "<pre class=\"highlight\"><code>a &lt; b</code></pre>",
+        );
+        assert!(
+            render("{{highlight lang=\"rust\"}}").is_err(),
+            "Highlighting without any code succeeded, but it should have failed",
+        );
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "templating"), ignore = "uses templates")]
+    fn toc_helper_lists_headings_of_rendered_routes() {
+        let (_root, shared_content_engine) = content_engine_in_temporary_directory(
+            &[
+                (
+                    "guide.html.hbs",
+                    "<h1>{{title}}</h1><h2 id=\"install\">Install</h2><h3 id=\"linux\">Linux</h3>\
+                    <h2 id=\"use\">Use</h2>",
+                ),
+                (
+                    "sidebar.html.hbs",
+                    "{{#each (toc (get \"/guide\" title=\"Guide\") min-level=2)}}\
+                    <a href=\"{{url}}\">{{text}}</a>({{#each children}}{{text}}{{/each}})\
+                    {{/each}}",
+                ),
+                ("invalid.html.hbs", "{{toc 42}}"),
+            ],
+            Config::default(),
+        )
+        .expect("Content engine could not be created");
+
+        let content_engine = shared_content_engine.read().unwrap();
+        let render = |route_to_render: &str| {
+            let context = content_engine.render_context(
+                Some(route(route_to_render)),
+                QueryParameters::default(),
+                HashMap::new(),
+            );
+            content_engine
+                .get(&route(route_to_render))
+                .expect("Template was not registered")
+                .render(context, &[mime::TEXT_HTML])
+                .map(media_to_string)
+        };
+
+        assert_eq!(
+            render("/sidebar").expect("Rendering failed"),
+            "<a href=\"#install\">Install</a>(Linux)<a href=\"#use\">Use</a>()",
+        );
+        assert!(
+            render("/invalid").is_err(),
+            "Building a table of contents from a number succeeded, but it should have failed",
+        );
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "templating"), ignore = "uses templates")]
+    fn get_helper_accepts_joined_routes() {
+        let directory = ContentDirectory::from_root(&sample_path("partials")).unwrap();
+        let shared_content_engine = TestContentEngine::from_content_directory(directory, ())
+            .expect("Content engine could not be created");
         let content_engine = shared_content_engine.read().unwrap();
 
-        for &(template, expected_output) in &VALID_TEMPLATES {
-            let renderable = content_engine
+        let render = |template: &str| {
+            content_engine
                 .new_template(
                     template,
                     MediaType::from_media_range(mime::TEXT_HTML).unwrap(),
                 )
-                .expect("Template could not be parsed");
-            let rendered = renderable
+                .expect("Template could not be parsed")
                 .render(
-                    content_engine.render_context(None, HashMap::new(), HashMap::new()),
+                    content_engine.render_context(None, QueryParameters::default(), HashMap::new()),
                     &[mime::TEXT_HTML],
                 )
-                .expect(&format!("Template rendering failed for `{}`", template));
-            let actual_output = media_to_string(rendered);
+                .map(media_to_string)
+        };
 
-            assert_eq!(
-                actual_output,
-                expected_output,
-                "Template rendering for `{}` did not produce the expected output (\"{}\"), instead got \"{}\"",
+        assert_eq!(
+            render("{{get (route-join \"/\" \"b\")}}").expect("Rendering failed"),
+            "b\n",
+        );
+        for template in [
+            "{{get (route-join \"/subdirectory\" \"..\" \"b\")}}",
+            "{{get \"/subdirectory/../b\"}}",
+        ] {
+            assert!(
+                render(template).is_err(),
+                "Rendering `{}` succeeded, but it should have failed",
                 template,
-                expected_output,
-                actual_output,
             );
         }
     }
 
     #[test]
-    fn new_template_fails_for_invalid_templates() {
-        let shared_content_engine = TestContentEngine::from_content_directory(
-            arbitrary_content_directory_with_valid_content(),
-            (),
+    fn precompressed_files_need_an_uncompressed_counterpart() {
+        let root = temporary_directory_with_files(&[
+            ("page.html", "uncompressed"),
+            ("page.html.gz", "gzipped"),
+        ]);
+        assert!(content_engine_from_root(root.path(), Config::default()).is_ok());
+
+        std::fs::write(root.path().join("orphan.html.gz"), "gzipped").unwrap();
+        match content_engine_from_root(root.path(), Config::default()) {
+            Ok(_) => panic!("Content engine was created, but it should have failed"),
+            Err(errors) => {
+                let errors = errors.iter().collect::<Vec<_>>();
+                assert_eq!(errors.len(), 1);
+                assert_eq!(errors[0].0, "orphan.html.gz");
+                assert!(matches!(
+                    errors[0].1,
+                    ContentLoadingError::ContentFileNameError(_)
+                ));
+            }
+        }
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "templating"), ignore = "uses templates")]
+    fn content_can_be_reloaded() {
+        let (root, shared_content_engine) = content_engine_in_temporary_directory(
+            &[
+                ("_name.txt", "old"),
+                ("greeting.txt.hbs", "hello {{get \"/_name\"}}"),
+            ],
+            Config::default(),
         )
         .expect("Content engine could not be created");
-        let content_engine = shared_content_engine.read().unwrap();
 
-        for &template in &INVALID_TEMPLATES {
-            let result = content_engine.new_template(
-                template,
-                MediaType::from_media_range(mime::TEXT_HTML).unwrap(),
+        let render = |route_to_render: &str| {
+            let content_engine = shared_content_engine.read().unwrap();
+            let context = content_engine.render_context(
+                Some(route(route_to_render)),
+                QueryParameters::default(),
+                HashMap::new(),
             );
+            content_engine.get(&route(route_to_render)).map(|content| {
+                content
+                    .render(context, &[mime::TEXT_PLAIN])
+                    .map(media_to_string)
+                    .expect("Rendering failed")
+            })
+        };
+        assert_eq!(render("/greeting").as_deref(), Some("hello old"));
+        assert_eq!(render("/new"), None);
+
+        std::fs::remove_file(root.path().join("_name.txt")).unwrap();
+        std::fs::write(root.path().join("_name.txt"), "new").unwrap();
+        std::fs::write(root.path().join("new.txt"), "new file").unwrap();
+        TestContentEngine::reload(&shared_content_engine).expect("Reloading failed");
+        assert_eq!(render("/greeting").as_deref(), Some("hello new"));
+        assert_eq!(render("/new").as_deref(), Some("new file"));
+
+        // Content stays the same when reloading fails.
+        std::fs::write(root.path().join("broken.txt.hbs"), "{{").unwrap();
+        assert!(matches!(
+            TestContentEngine::reload(&shared_content_engine),
+            Err(ContentReloadError::ContentLoadingError { .. })
+        ));
+        assert_eq!(render("/greeting").as_deref(), Some("hello new"));
+        assert_eq!(render("/broken"), None);
+    }
 
-            assert!(
-                result.is_err(),
-                "Content was successfully created for invalid template `{}`, but it should have failed",
-                template,
+    #[test]
+    #[cfg_attr(not(feature = "templating"), ignore = "uses templates")]
+    fn changed_files_can_be_reloaded_on_their_own() {
+        let (root, shared_content_engine) = content_engine_in_temporary_directory(
+            &[
+                ("_name.txt.hbs", "old"),
+                ("greeting.txt.hbs", "hello {{get \"/_name\"}}"),
+                ("other.txt", "other"),
+            ],
+            Config::default(),
+        )
+        .expect("Content engine could not be created");
+        let render = |route_to_render: &str| {
+            let content_engine = shared_content_engine.read().unwrap();
+            let context = content_engine.render_context(
+                Some(route(route_to_render)),
+                QueryParameters::default(),
+                HashMap::new(),
             );
-        }
+            content_engine.get(&route(route_to_render)).map(|content| {
+                content
+                    .render(context, &[mime::TEXT_PLAIN])
+                    .map(media_to_string)
+                    .expect("Rendering failed")
+            })
+        };
+        assert_eq!(render("/greeting").as_deref(), Some("hello old"));
+
+        std::fs::write(root.path().join("_name.txt.hbs"), "new").unwrap();
+        assert_eq!(
+            TestContentEngine::reload_changed(&shared_content_engine, &["_name.txt.hbs"])
+                .expect("Reloading failed"),
+            ReloadedContent::Routes([route("/_name"), route("/greeting")].into_iter().collect()),
+        );
+        assert_eq!(render("/greeting").as_deref(), Some("hello new"));
+        assert_eq!(render("/other").as_deref(), Some("other"));
+
+        assert_eq!(
+            TestContentEngine::reload_changed(&shared_content_engine, &["not-content.txt"])
+                .expect("Reloading failed"),
+            ReloadedContent::Nothing,
+        );
+
+        // Structural changes load everything again.
+        std::fs::write(root.path().join("new.txt"), "new file").unwrap();
+        assert_eq!(
+            TestContentEngine::reload_changed(&shared_content_engine, &["new.txt"])
+                .expect("Reloading failed"),
+            ReloadedContent::Everything,
+        );
+        assert_eq!(render("/new").as_deref(), Some("new file"));
+
+        // Content stays the same when reloading fails.
+        std::fs::write(root.path().join("_name.txt.hbs"), "{{").unwrap();
+        assert!(matches!(
+            TestContentEngine::reload_changed(&shared_content_engine, &["_name.txt.hbs"]),
+            Err(ContentReloadError::ContentLoadingError { .. })
+        ));
+        assert_eq!(render("/greeting").as_deref(), Some("hello new"));
     }
 
     #[test]
-    fn new_templates_can_reference_partials_from_content_directory() {
-        let directory = ContentDirectory::from_root(&sample_path("partials")).unwrap();
-        let shared_content_engine = TestContentEngine::from_content_directory(directory, ())
-            .expect("Content engine could not be created");
-        let content_engine = shared_content_engine.read().unwrap();
+    #[cfg_attr(not(feature = "templating"), ignore = "uses templates")]
+    fn concurrent_reloads_end_with_the_latest_content() {
+        let (root, shared_content_engine) =
+            content_engine_in_temporary_directory(&[("greeting.txt.hbs", "0")], Config::default())
+                .expect("Content engine could not be created");
+
+        for version in 1..20 {
+            std::fs::write(root.path().join("greeting.txt.hbs"), version.to_string()).unwrap();
+            let threads = [
+                std::thread::spawn({
+                    let shared_content_engine = shared_content_engine.clone();
+                    move || TestContentEngine::reload(&shared_content_engine).map(|_| ())
+                }),
+                std::thread::spawn({
+                    let shared_content_engine = shared_content_engine.clone();
+                    move || {
+                        TestContentEngine::reload_changed(
+                            &shared_content_engine,
+                            &["greeting.txt.hbs"],
+                        )
+                        .map(|_| ())
+                    }
+                }),
+            ];
+            for thread in threads {
+                thread.join().unwrap().expect("Reloading failed");
+            }
 
-        let template = "this is partial: {{> abc.html.hbs}}";
-        let expected_output =
-            "this is partial: a\nb\nc\n\nsubdirectory entries:\n/subdirectory/c\n";
+            let content_engine = shared_content_engine.read().unwrap();
+            let context = content_engine.render_context(
+                Some(route("/greeting")),
+                QueryParameters::default(),
+                HashMap::new(),
+            );
+            let rendered = content_engine
+                .get(&route("/greeting"))
+                .expect("Content was missing")
+                .render(context, &[mime::TEXT_PLAIN])
+                .map(media_to_string)
+                .expect("Rendering failed");
+            assert_eq!(rendered, version.to_string());
+        }
+    }
 
-        let renderable = content_engine
-            .new_template(
-                template,
-                MediaType::from_media_range(mime::TEXT_HTML).unwrap(),
-            )
-            .expect("Template could not be parsed");
-        let rendered = renderable
-            .render(
-                content_engine.render_context(None, HashMap::new(), HashMap::new()),
-                &[mime::TEXT_HTML],
-            )
-            .expect(&format!("Template rendering failed for `{}`", template));
-        let actual_output = media_to_string(rendered);
+    #[test]
+    #[cfg_attr(not(feature = "templating"), ignore = "uses templates")]
+    fn content_is_counted_when_it_is_loaded() {
+        let (_root, shared_content_engine) = content_engine_in_temporary_directory(
+            &[
+                ("page.html", "<p>hi</p>"),
+                ("page.html.gz", "gzipped"),
+                ("greeting.txt.hbs", "hello"),
+                ("old.redirect", "/page"),
+                ("_data/site.json", "{}"),
+            ],
+            Config::default(),
+        )
+        .expect("Content engine could not be created");
 
+        let content_engine = shared_content_engine.read().unwrap();
+        let stats = content_engine.stats().expect("Stats were missing");
+
+        assert_eq!(stats.files, 5);
+        assert_eq!(stats.bytes, 9 + 7 + 5 + 5 + 2);
         assert_eq!(
-            actual_output,
-            expected_output,
-            "Template rendering for `{}` did not produce the expected output (\"{}\"), instead got \"{}\"",
-            template,
-            expected_output,
-            actual_output,
+            stats.kinds,
+            [
+                (ContentKind::Static, 1),
+                (ContentKind::Precompressed, 1),
+                (ContentKind::Template, 1),
+                (ContentKind::Redirect, 1),
+                (ContentKind::Data, 1),
+            ]
+            .into_iter()
+            .collect()
         );
+        assert_eq!(stats.open_files, 2);
     }
 
     #[test]
-    fn content_can_be_retrieved() {
-        let directory = ContentDirectory::from_root(&sample_path("partials")).unwrap();
-        let shared_content_engine = TestContentEngine::from_content_directory(directory, ())
+    fn reloading_follows_a_repointed_root_symlink() {
+        let releases = temporary_directory_with_files(&[
+            ("1/page.txt", "first release"),
+            ("2/page.txt", "second release"),
+        ]);
+        let (first, second) = (releases.path().join("1"), releases.path().join("2"));
+        let current = releases.path().join("current");
+        std::os::unix::fs::symlink(&first, &current).unwrap();
+
+        let shared_content_engine = content_engine_from_root(&current, Config::default())
             .expect("Content engine could not be created");
-        let content_engine = shared_content_engine.read().unwrap();
 
-        let route = route("/abc");
-        let expected_output = "a\nb\nc\n\nsubdirectory entries:\n/subdirectory/c\n";
+        let render_page = || {
+            let content_engine = shared_content_engine.read().unwrap();
+            let context = content_engine.render_context(
+                Some(route("/page")),
+                QueryParameters::default(),
+                HashMap::new(),
+            );
+            content_engine
+                .get(&route("/page"))
+                .expect("Content was not registered")
+                .render(context, &[mime::TEXT_PLAIN])
+                .expect("Rendering failed")
+        };
+        let in_flight = render_page();
+
+        // Deploy tools swap the symlink atomically by renaming a new one over
+        // it.
+        let next = releases.path().join("next");
+        std::os::unix::fs::symlink(&second, &next).unwrap();
+        std::fs::rename(&next, &current).unwrap();
+        std::fs::remove_dir_all(&first).unwrap();
+
+        TestContentEngine::reload(&shared_content_engine).expect("Reloading failed");
+        assert_eq!(media_to_string(render_page()), "second release");
+        assert_eq!(media_to_string(in_flight), "first release");
+    }
 
-        let content = content_engine
-            .get(&route)
-            .expect("Content could not be found");
-        let rendered = content
-            .render(
-                content_engine.render_context(None, HashMap::new(), HashMap::new()),
-                &[mime::TEXT_HTML],
+    #[test]
+    #[cfg_attr(not(feature = "executables"), ignore = "uses executables")]
+    fn executables_can_be_probed() {
+        use std::os::unix::fs::PermissionsExt;
+        let executables = [
+            (
+                "good.txt.sh",
+                "#!/bin/sh\nif [ \"$OPERATOR_VALIDATE\" = 1 ]; then exit 0; fi\nexit 1\n",
+            ),
+            ("_internal.txt.sh", "#!/bin/sh\nexit 3\n"),
+            ("missing-interpreter.txt.sh", "#!/no/such/interpreter\n"),
+        ];
+        let root = temporary_directory_with_files(&executables);
+        for (name, _) in executables {
+            std::fs::set_permissions(
+                root.path().join(name),
+                std::fs::Permissions::from_mode(0o755),
             )
-            .expect(&format!(
-                "Template rendering failed for content at '{}'",
-                route
-            ));
-        let actual_output = media_to_string(rendered);
+            .unwrap();
+        }
+        std::fs::write(root.path().join("static.txt"), "not an executable").unwrap();
+        let shared_content_engine = content_engine_from_root(root.path(), Config::default())
+            .expect("Content engine could not be created");
 
-        assert_eq!(
-            actual_output,
-            expected_output,
-            "Rendering content at '{}' did not produce the expected output (\"{}\"), instead got \"{}\"",
-            route,
-            expected_output,
-            actual_output,
-        );
+        let content_engine = shared_content_engine.read().unwrap();
+
+        let errors = content_engine
+            .probe_executables()
+            .expect_err("Probing succeeded, but it should have failed");
+        let failed_routes = errors
+            .iter()
+            .map(|(failed_route, error)| {
+                assert!(matches!(
+                    error,
+                    ContentLoadingError::ExecutableProbeFailed(_)
+                ));
+                failed_route.as_str()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(failed_routes, vec!["/_internal", "/missing-interpreter"]);
     }
 
     #[test]
-    fn content_may_not_exist_at_route() {
-        let directory = ContentDirectory::from_root(&sample_path("hello-world")).unwrap();
-        let shared_content_engine = TestContentEngine::from_content_directory(directory, ())
+    #[cfg_attr(
+        not(all(feature = "executables", feature = "templating")),
+        ignore = "uses executables and templates"
+    )]
+    fn render_check_finds_content_which_fails_to_render() {
+        use std::os::unix::fs::PermissionsExt;
+        let root = temporary_directory_with_files(&[
+            ("good.html.hbs", "<p>{{request.route}}</p>"),
+            ("good.txt", "static"),
+            ("missing-data.html.hbs", "{{nope.nothing}}"),
+            ("_partial.html.hbs", "{{nope.nothing}}"),
+            ("failing.txt.sh", "#!/bin/sh\necho partial output\nexit 1\n"),
+        ]);
+        std::fs::set_permissions(
+            root.path().join("failing.txt.sh"),
+            std::fs::Permissions::from_mode(0o755),
+        )
+        .unwrap();
+        let shared_content_engine = content_engine_from_root(root.path(), Config::default())
             .expect("Content engine could not be created");
-        let content_engine = shared_content_engine.read().unwrap();
 
-        let route = route("/this-route-does-not-refer-to-any-content");
+        let content_engine = shared_content_engine.read().unwrap();
 
-        assert!(
-            content_engine.get(&route).is_none(),
-            "Content was found at '{}', but it was not expected to be",
-            route
+        let errors = content_engine
+            .render_check()
+            .expect_err("Render check succeeded, but it should have failed");
+        let failures = errors
+            .iter()
+            .map(|(failed_route, error)| match error {
+                ContentLoadingError::RenderCheckFailed { media_type, .. } => {
+                    (failed_route.as_str(), "render", media_type.to_string())
+                }
+                ContentLoadingError::RenderCheckStreamFailed { media_type, .. } => {
+                    (failed_route.as_str(), "stream", media_type.to_string())
+                }
+                error => panic!("Unexpected error: {}", error),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            failures,
+            vec![
+                ("/failing", "stream", String::from("text/plain")),
+                ("/missing-data", "render", String::from("text/html")),
+            ]
         );
     }
 
     #[test]
-    fn handlebars_extension_agrees_with_mime_guess() {
-        let mime_guess_handlebars_extension =
-            mime_guess::get_extensions("text", "x-handlebars-template")
-                .unwrap()
-                .first()
-                .unwrap();
-        let content_engine_handlebars_extension =
-            <TestContentEngine<'static, ()>>::HANDLEBARS_FILE_EXTENSION;
+    #[cfg_attr(not(feature = "templating"), ignore = "uses templates")]
+    fn paginate_helper_slices_collections() {
+        let (_root, shared_content_engine) = content_engine_in_temporary_directory(
+            &[
+                ("_data/posts.json", r#"["a", "b", "c", "d", "e"]"#),
+                (
+                    "posts.html.hbs",
+                    "{{#paginate data.posts per-page=2 page=request.query-parameters.page}}\
+                    {{#each items}}{{this}}{{/each}} {{current-page}}/{{total-pages}} \
+                    {{{previous-url}}} {{{next-url}}}\
+                    {{/paginate}}",
+                ),
+            ],
+            Config::default(),
+        )
+        .expect("Content engine could not be created");
+
+        let content_engine = shared_content_engine.read().unwrap();
+
+        let render = |query_parameters: Vec<(&str, &str)>| {
+            let query_parameters = QueryParameters::new(
+                query_parameters
+                    .into_iter()
+                    .map(|(key, value)| (String::from(key), String::from(value)))
+                    .collect(),
+            );
+            let context = content_engine.render_context(
+                Some(route("/posts")),
+                query_parameters,
+                HashMap::new(),
+            );
+            content_engine
+                .get(&route("/posts"))
+                .expect("Template was not registered")
+                .render(context, &[mime::TEXT_HTML])
+                .map(media_to_string)
+                .expect("Rendering failed")
+        };
 
+        assert_eq!(render(vec![]), "ab 1/3  /posts?page=2");
         assert_eq!(
-            mime_guess_handlebars_extension,
-            &content_engine_handlebars_extension,
+            render(vec![("tag", "x"), ("page", "2")]),
+            "cd 2/3 /posts?tag=x&page=1 /posts?tag=x&page=3"
         );
+        assert_eq!(render(vec![("page", "3")]), "e 3/3 /posts?page=2 ");
+        assert_eq!(render(vec![("page", "99")]), "e 3/3 /posts?page=2 ");
+        assert_eq!(render(vec![("page", "nope")]), "ab 1/3  /posts?page=2");
     }
 
     #[test]
-    fn get_helper_is_available() {
-        let directory = ContentDirectory::from_root(&sample_path("partials")).unwrap();
-        let shared_content_engine = TestContentEngine::from_content_directory(directory, ())
-            .expect("Content engine could not be created");
-        let content_engine = shared_content_engine.read().unwrap();
+    #[cfg_attr(not(feature = "templating"), ignore = "uses templates")]
+    fn breadcrumbs_and_navigation_helpers_use_the_content_index() {
+        let (_root, shared_content_engine) = content_engine_in_temporary_directory(
+            &[
+                ("docs.html.hbs", "---\ntitle: Docs\n---\n"),
+                ("docs/install.html", ""),
+                ("docs/_partial.html", ""),
+                (
+                    "docs/guide/start.html.hbs",
+                    "---\ntitle: Getting Started\n---\n\
+                    {{#*inline \"tree\"}}[{{name}} {{title}}{{#if active}} active{{/if}}\
+                    {{#each children}}{{> tree}}{{/each}}]{{/inline}}\
+                    {{#each (breadcrumbs)}}\
+                    {{title}} ({{route}}{{#if current}}, current{{/if}}) \
+                    {{/each}}\n\
+                    {{#each (navigation \"/\" depth=3)}}{{> tree}}{{/each}}\n\
+                    {{#each (navigation)}}{{name}}{{/each}}",
+                ),
+                ("_private/secret.html", ""),
+            ],
+            Config::default(),
+        )
+        .expect("Content engine could not be created");
 
-        let template = "i got stuff: {{get [/].b}}";
-        let expected_output = "i got stuff: b\n";
+        let content_engine = shared_content_engine.read().unwrap();
 
-        let renderable = content_engine
-            .new_template(
-                template,
-                MediaType::from_media_range(mime::TEXT_HTML).unwrap(),
-            )
-            .expect("Template could not be parsed");
-        let rendered = renderable
-            .render(
-                content_engine.render_context(None, HashMap::new(), HashMap::new()),
-                &[mime::TEXT_HTML],
-            )
-            .expect(&format!("Template rendering failed for `{}`", template));
-        let actual_output = media_to_string(rendered);
+        let request_route = route("/docs/guide/start");
+        let context = content_engine.render_context(
+            Some(request_route.clone()),
+            QueryParameters::default(),
+            HashMap::new(),
+        );
+        let output = content_engine
+            .get(&request_route)
+            .expect("Template was not registered")
+            .render(context, &[mime::TEXT_HTML])
+            .map(media_to_string)
+            .expect("Rendering failed");
 
         assert_eq!(
-            actual_output,
-            expected_output,
-            "Template rendering for `{}` did not produce the expected output (\"{}\"), instead got \"{}\"",
-            template,
-            expected_output,
-            actual_output,
+            output,
+            "Docs (/docs) Getting Started (/docs/guide/start, current) \n\
+            [docs Docs active[guide  active[start Getting Started active]][install install]]\n\
+            start",
         );
     }
 
@@ -677,7 +4351,7 @@ mod tests {
                 )
                 .expect("Template could not be parsed");
             let result = renderable.render(
-                content_engine.render_context(None, HashMap::new(), HashMap::new()),
+                content_engine.render_context(None, QueryParameters::default(), HashMap::new()),
                 &[mime::TEXT_HTML],
             );
             assert!(
@@ -689,6 +4363,7 @@ mod tests {
     }
 
     #[test]
+    #[cfg_attr(not(feature = "templating"), ignore = "uses templates")]
     fn get_helper_accepts_hash_parameters() {
         let directory = ContentDirectory::from_root(&sample_path("partials")).unwrap();
         let shared_content_engine = TestContentEngine::from_content_directory(directory, ())
@@ -706,10 +4381,10 @@ mod tests {
             .expect("Template could not be parsed");
         let rendered = renderable
             .render(
-                content_engine.render_context(None, HashMap::new(), HashMap::new()),
+                content_engine.render_context(None, QueryParameters::default(), HashMap::new()),
                 &[mime::TEXT_HTML],
             )
-            .expect(&format!("Template rendering failed for `{}`", template));
+            .unwrap_or_else(|_| panic!("Template rendering failed for `{}`", template));
         let actual_output = media_to_string(rendered);
 
         assert_eq!(
@@ -723,6 +4398,56 @@ mod tests {
     }
 
     #[test]
+    #[cfg_attr(
+        not(all(feature = "executables", feature = "templating")),
+        ignore = "uses executables and templates"
+    )]
+    fn get_helper_can_override_query_parameters_and_headers() {
+        let directory = ContentDirectory::from_root(&sample_path("executables")).unwrap();
+        let shared_content_engine = TestContentEngine::from_content_directory(directory, ())
+            .expect("Content engine could not be created");
+        let content_engine = shared_content_engine.read().unwrap();
+
+        let template =
+            "{{get \"/render-data\" query-a=\"override\" query-b=null query-c=request.query-parameter-lists.a \
+            header-X-Widget=\"yes\" header-accept=null}}";
+        let renderable = content_engine
+            .new_template(
+                template,
+                MediaType::from_media_range(mime::APPLICATION_JSON).unwrap(),
+            )
+            .expect("Template could not be parsed");
+        let rendered = renderable
+            .render(
+                content_engine.render_context(
+                    None,
+                    QueryParameters::new(vec![
+                        (String::from("a"), String::from("original")),
+                        (String::from("b"), String::from("removed")),
+                        (String::from("d"), String::from("kept")),
+                    ]),
+                    hashmap![String::from("accept") => String::from("*/*")],
+                ),
+                &[mime::APPLICATION_JSON],
+            )
+            .unwrap_or_else(|error| panic!("Template rendering failed: {}", error));
+        let render_data = serde_json::from_str::<serde_json::Value>(&media_to_string(rendered))
+            .expect("Rendered content was not JSON");
+        let request = &render_data["request"];
+
+        assert_eq!(
+            request["query-parameter-lists"],
+            serde_json::json!({ "a": ["override"], "c": ["original"], "d": ["kept"] }),
+        );
+        assert_eq!(
+            request["request-headers"],
+            serde_json::json!({ "x-widget": "yes" }),
+        );
+        assert_eq!(render_data.get("query-a"), None);
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "templating"), ignore = "uses templates")]
     fn get_helper_accepts_custom_context() {
         let directory = ContentDirectory::from_root(&sample_path("partials")).unwrap();
         let shared_content_engine = TestContentEngine::from_content_directory(
@@ -737,7 +4462,7 @@ mod tests {
         let content_engine = shared_content_engine.read().unwrap();
 
         let template = "output:\n\n{{get \"/_iterate-context\" server-info}}";
-        let expected_output = "output:\n\n/: [object]\nerror-code: \nkey1: value1\nkey2: value2\nkey3: value3\nrequest: [object]\nserver-info: [object]\ntarget-media-type: text/html\n";
+        let expected_output = "output:\n\n/: [object]\ndata: [object]\nerror-code: \nfront-matter: [object]\nkey1: value1\nkey2: value2\nkey3: value3\nrequest: [object]\nserver-info: [object]\nsite: [object]\ntarget-media-type: text/html\n";
 
         let renderable = content_engine
             .new_template(
@@ -747,10 +4472,10 @@ mod tests {
             .expect("Template could not be parsed");
         let rendered = renderable
             .render(
-                content_engine.render_context(None, HashMap::new(), HashMap::new()),
+                content_engine.render_context(None, QueryParameters::default(), HashMap::new()),
                 &[mime::TEXT_HTML],
             )
-            .expect(&format!("Template rendering failed for `{}`", template));
+            .unwrap_or_else(|_| panic!("Template rendering failed for `{}`", template));
         let actual_output = media_to_string(rendered);
 
         assert_eq!(
@@ -764,6 +4489,7 @@ mod tests {
     }
 
     #[test]
+    #[cfg_attr(not(feature = "templating"), ignore = "uses templates")]
     fn get_helper_errors_with_non_object_context() {
         let directory = ContentDirectory::from_root(&sample_path("partials")).unwrap();
         let shared_content_engine = TestContentEngine::from_content_directory(directory, ())
@@ -779,7 +4505,7 @@ mod tests {
             )
             .expect("Template could not be parsed");
         let result = renderable.render(
-            content_engine.render_context(None, HashMap::new(), HashMap::new()),
+            content_engine.render_context(None, QueryParameters::default(), HashMap::new()),
             &[mime::TEXT_HTML],
         );
 
@@ -792,6 +4518,7 @@ mod tests {
     }
 
     #[test]
+    #[cfg_attr(not(feature = "templating"), ignore = "uses templates")]
     fn registered_content_cannot_be_rendered_with_unacceptable_target_media_type() {
         let content_directory_path = &sample_path("media-types");
         let directory = ContentDirectory::from_root(content_directory_path).unwrap();
@@ -809,7 +4536,11 @@ mod tests {
                 None => panic!("No content was found at '{}'", route),
                 Some(renderable) => {
                     let result = renderable.render(
-                        content_engine.render_context(None, HashMap::new(), HashMap::new()),
+                        content_engine.render_context(
+                            None,
+                            QueryParameters::default(),
+                            HashMap::new(),
+                        ),
                         &[mime::TEXT_HTML],
                     );
                     assert!(
@@ -839,7 +4570,7 @@ mod tests {
             )
             .expect("Template could not be created");
         let result = template.render(
-            content_engine.render_context(None, HashMap::new(), HashMap::new()),
+            content_engine.render_context(None, QueryParameters::default(), HashMap::new()),
             &[mime::TEXT_PLAIN],
         );
 
@@ -850,6 +4581,7 @@ mod tests {
     }
 
     #[test]
+    #[cfg_attr(not(feature = "templating"), ignore = "uses templates")]
     fn nesting_incompatible_media_types_fails_at_render_time() {
         let content_directory_path = &sample_path("media-types");
         let directory = ContentDirectory::from_root(content_directory_path).unwrap();
@@ -867,7 +4599,11 @@ mod tests {
                 None => panic!("No content was found at '{}'", route),
                 Some(renderable) => {
                     let result = renderable.render(
-                        content_engine.render_context(None, HashMap::new(), HashMap::new()),
+                        content_engine.render_context(
+                            None,
+                            QueryParameters::default(),
+                            HashMap::new(),
+                        ),
                         &[target_media_type],
                     );
                     assert!(
@@ -881,6 +4617,7 @@ mod tests {
     }
 
     #[test]
+    #[cfg_attr(not(feature = "templating"), ignore = "uses templates")]
     fn target_media_type_is_correct_for_templates_rendered_directly() {
         let shared_content_engine = TestContentEngine::from_content_directory(
             ContentDirectory::from_root(&sample_path("media-types")).unwrap(),
@@ -900,7 +4637,11 @@ mod tests {
                         )
                         .expect("Test template was invalid")
                         .render(
-                            content_engine.render_context(None, HashMap::new(), HashMap::new()),
+                            content_engine.render_context(
+                                None,
+                                QueryParameters::default(),
+                                HashMap::new(),
+                            ),
                             &[mime::TEXT_PLAIN],
                         )
                         .expect("Failed to render unregistered template"),
@@ -913,7 +4654,11 @@ mod tests {
                         .get(&route("/echo-target-media-type"))
                         .expect("Test template does not exist")
                         .render(
-                            content_engine.render_context(None, HashMap::new(), HashMap::new()),
+                            content_engine.render_context(
+                                None,
+                                QueryParameters::default(),
+                                HashMap::new(),
+                            ),
                             &[mime::TEXT_HTML],
                         )
                         .expect("Failed to render registered template"),
@@ -932,6 +4677,10 @@ mod tests {
     }
 
     #[test]
+    #[cfg_attr(
+        not(all(feature = "executables", feature = "templating")),
+        ignore = "uses executables and templates"
+    )]
     fn executables_are_given_zero_args() {
         let directory = ContentDirectory::from_root(&sample_path("executables")).unwrap();
         let shared_content_engine = TestContentEngine::from_content_directory(directory, ())
@@ -946,10 +4695,10 @@ mod tests {
             .expect("Content could not be found");
         let rendered = content
             .render(
-                content_engine.render_context(None, HashMap::new(), HashMap::new()),
+                content_engine.render_context(None, QueryParameters::default(), HashMap::new()),
                 &[mime::TEXT_PLAIN],
             )
-            .expect(&format!("Rendering failed for content at '{}'", route));
+            .unwrap_or_else(|_| panic!("Rendering failed for content at '{}'", route));
         let actual_output = media_to_string(rendered);
 
         assert_eq!(
@@ -963,6 +4712,10 @@ mod tests {
     }
 
     #[test]
+    #[cfg_attr(
+        not(all(feature = "executables", feature = "templating")),
+        ignore = "uses executables and templates"
+    )]
     fn executables_are_executed_with_correct_working_directory() {
         let directory = ContentDirectory::from_root(&sample_path("executables")).unwrap();
         let shared_content_engine = TestContentEngine::from_content_directory(directory, ())
@@ -977,10 +4730,10 @@ mod tests {
             .expect("Content could not be found");
         let rendered = content
             .render(
-                content_engine.render_context(None, HashMap::new(), HashMap::new()),
+                content_engine.render_context(None, QueryParameters::default(), HashMap::new()),
                 &[mime::TEXT_PLAIN],
             )
-            .expect(&format!("Rendering failed for content at '{}'", route1));
+            .unwrap_or_else(|_| panic!("Rendering failed for content at '{}'", route1));
         let actual_output = media_to_string(rendered);
 
         assert_eq!(
@@ -1000,10 +4753,10 @@ mod tests {
             .expect("Content could not be found");
         let rendered = content
             .render(
-                content_engine.render_context(None, HashMap::new(), HashMap::new()),
+                content_engine.render_context(None, QueryParameters::default(), HashMap::new()),
                 &[mime::TEXT_PLAIN],
             )
-            .expect(&format!("Rendering failed for content at '{}'", route2));
+            .unwrap_or_else(|_| panic!("Rendering failed for content at '{}'", route2));
         let actual_output = media_to_string(rendered);
 
         assert_eq!(
@@ -1017,6 +4770,10 @@ mod tests {
     }
 
     #[test]
+    #[cfg_attr(
+        not(all(feature = "executables", feature = "templating")),
+        ignore = "uses executables and templates"
+    )]
     fn executables_have_a_media_type() {
         let directory = ContentDirectory::from_root(&sample_path("executables")).unwrap();
         let shared_content_engine = TestContentEngine::from_content_directory(directory, ())
@@ -1029,7 +4786,7 @@ mod tests {
             .expect("Content could not be found");
 
         let result1 = content.render(
-            content_engine.render_context(None, HashMap::new(), HashMap::new()),
+            content_engine.render_context(None, QueryParameters::default(), HashMap::new()),
             &[mime::TEXT_PLAIN], // Not text/html!
         );
         assert!(
@@ -1039,7 +4796,7 @@ mod tests {
         );
 
         let result2 = content.render(
-            content_engine.render_context(None, HashMap::new(), HashMap::new()),
+            content_engine.render_context(None, QueryParameters::default(), HashMap::new()),
             &[mime::TEXT_HTML],
         );
         assert!(
@@ -1050,6 +4807,10 @@ mod tests {
     }
 
     #[test]
+    #[cfg_attr(
+        not(all(feature = "executables", feature = "templating")),
+        ignore = "uses executables and templates"
+    )]
     fn executables_can_output_arbitrary_bytes() {
         let directory = ContentDirectory::from_root(&sample_path("executables")).unwrap();
         let shared_content_engine = TestContentEngine::from_content_directory(directory, ())
@@ -1063,13 +4824,15 @@ mod tests {
 
         let media = content
             .render(
-                content_engine.render_context(None, HashMap::new(), HashMap::new()),
+                content_engine.render_context(None, QueryParameters::default(), HashMap::new()),
                 &[mime::APPLICATION_OCTET_STREAM],
             )
-            .expect(&format!(
-                "Rendering content at '{}' failed when it should have succeeded",
-                route
-            ));
+            .unwrap_or_else(|_| {
+                panic!(
+                    "Rendering content at '{}' failed when it should have succeeded",
+                    route
+                )
+            });
 
         assert!(
             media.media_type
@@ -1079,6 +4842,10 @@ mod tests {
     }
 
     #[test]
+    #[cfg_attr(
+        not(all(feature = "executables", feature = "templating")),
+        ignore = "uses executables and templates"
+    )]
     fn templates_can_get_executable_output() {
         let directory = ContentDirectory::from_root(&sample_path("executables")).unwrap();
         let shared_content_engine = TestContentEngine::from_content_directory(directory, ())
@@ -1096,10 +4863,10 @@ mod tests {
             .expect("Content could not be found");
         let rendered = content
             .render(
-                content_engine.render_context(None, HashMap::new(), HashMap::new()),
+                content_engine.render_context(None, QueryParameters::default(), HashMap::new()),
                 &[mime::TEXT_PLAIN],
             )
-            .expect(&format!("Rendering failed for content at '{}'", route));
+            .unwrap_or_else(|_| panic!("Rendering failed for content at '{}'", route));
         let actual_output = media_to_string(rendered);
 
         assert_eq!(
@@ -1113,6 +4880,7 @@ mod tests {
     }
 
     #[test]
+    #[cfg_attr(not(feature = "templating"), ignore = "uses templates")]
     fn content_can_be_hidden() {
         let directory = ContentDirectory::from_root(&sample_path("hidden-content")).unwrap();
         let shared_content_engine = TestContentEngine::from_content_directory(directory, ())
@@ -1136,13 +4904,41 @@ mod tests {
 
         for route in routes.iter() {
             assert!(
-                content_engine.get(&route).is_none(),
+                content_engine.get(route).is_none(),
                 "Content was successfully retrieved for hidden item `{}`, but `get` should have returned None",
                 route,
             );
         }
     }
 
+    #[test]
+    #[cfg_attr(not(feature = "templating"), ignore = "uses templates")]
+    fn internal_prefix_is_configurable() {
+        let internal_route = route("/_partially-hidden-file");
+
+        let directory = ContentDirectory::from_root(&sample_path("hidden-content")).unwrap();
+        let shared_content_engine = TestContentEngine::from_content_directory(directory, ())
+            .expect("Content engine could not be created");
+        let content_engine = shared_content_engine.read().unwrap();
+        assert!(content_engine.get(&internal_route).is_none());
+        assert!(content_engine.get_internal(&internal_route).is_some());
+
+        let config = ContentConfig {
+            internal_prefix: String::from("non-"),
+            ..ContentConfig::default()
+        };
+        let directory =
+            ContentDirectory::from_root_with_config(&sample_path("hidden-content"), &config)
+                .unwrap();
+        let shared_content_engine = TestContentEngine::from_content_directory(directory, ())
+            .expect("Content engine could not be created");
+        let content_engine = shared_content_engine.read().unwrap();
+        assert!(content_engine.get(&internal_route).is_some());
+        assert!(content_engine
+            .get(&route("/non-hidden-directory/_partially-hidden-file"))
+            .is_none());
+    }
+
     #[test]
     fn templates_receive_query_parameters() {
         let shared_content_engine = TestContentEngine::from_content_directory(
@@ -1171,10 +4967,10 @@ mod tests {
             .expect("Template could not be parsed");
         let rendered = renderable
             .render(
-                content_engine.render_context(None, query_parameters, HashMap::new()),
+                content_engine.render_context(None, query_parameters.into(), HashMap::new()),
                 &[mime::TEXT_PLAIN],
             )
-            .expect(&format!("Template rendering failed for `{}`", template));
+            .unwrap_or_else(|_| panic!("Template rendering failed for `{}`", template));
         let actual_output = media_to_string(rendered);
 
         assert!(
@@ -1214,10 +5010,10 @@ mod tests {
             .expect("Template could not be parsed");
         let rendered = renderable
             .render(
-                content_engine.render_context(None, HashMap::new(), request_headers),
+                content_engine.render_context(None, QueryParameters::default(), request_headers),
                 &[mime::TEXT_PLAIN],
             )
-            .expect(&format!("Template rendering failed for `{}`", template));
+            .unwrap_or_else(|_| panic!("Template rendering failed for `{}`", template));
         let actual_output = media_to_string(rendered);
 
         assert!(
@@ -1228,4 +5024,72 @@ mod tests {
             actual_output,
         );
     }
+
+    #[test]
+    fn content_engines_can_be_layered_as_trait_objects() {
+        /// Adds `data.layer` to whatever the inner engine renders with.
+        struct DataLayer(Box<dyn ContentEngine<()>>);
+        impl ContentEngine<()> for DataLayer {
+            fn render_data(
+                &self,
+                request_route: Option<Route>,
+                query_parameters: QueryParameters,
+                request_headers: HashMap<String, String>,
+            ) -> RenderData<()> {
+                let mut render_data =
+                    self.0
+                        .render_data(request_route, query_parameters, request_headers);
+                render_data
+                    .data
+                    .insert(String::from("layer"), serde_json::json!("outer"));
+                render_data
+            }
+            fn new_template(
+                &self,
+                template_source: &str,
+                media_type: MediaType,
+            ) -> Result<UnregisteredTemplate, TemplateError> {
+                self.0.new_template(template_source, media_type)
+            }
+            fn get(&self, route: &Route) -> Option<&ContentRepresentations> {
+                self.0.get(route)
+            }
+            fn handlebars_registry(&self) -> &Handlebars<'_> {
+                self.0.handlebars_registry()
+            }
+            fn search_index(&self) -> Option<&SearchIndex> {
+                self.0.search_index()
+            }
+            fn stats(&self) -> Option<&ContentStats> {
+                self.0.stats()
+            }
+            fn redirect(&self, route: &Route) -> Option<&Redirect> {
+                self.0.redirect(route)
+            }
+            fn template_engine(
+                &self,
+                language: TemplateLanguage,
+                strict: bool,
+            ) -> &dyn TemplateEngine {
+                self.0.template_engine(language, strict)
+            }
+        }
+
+        let content_engine: Box<dyn ContentEngine<()>> =
+            Box::new(DataLayer(Box::new(MockContentEngine::new())));
+
+        let renderable = content_engine
+            .new_template(
+                "{{data.layer}}",
+                MediaType::from_media_range(mime::TEXT_PLAIN).unwrap(),
+            )
+            .expect("Template could not be parsed");
+        let rendered = renderable
+            .render(
+                content_engine.render_context(None, QueryParameters::default(), HashMap::new()),
+                &[mime::TEXT_PLAIN],
+            )
+            .expect("Template rendering failed");
+        assert_eq!(media_to_string(rendered), "outer");
+    }
 }
@@ -114,6 +114,29 @@ impl ContentIndexEntries {
     }
 }
 
+impl ContentIndexEntries {
+    /// Entries directly within this directory, sorted by name. Names of
+    /// subdirectories end with `/`.
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &ContentIndex)> {
+        self.0.iter()
+    }
+
+    /// The entries beneath `route` (e.g. `/bar/baz` gives the `bar/` →
+    /// `baz/` directory). The root route gives the whole index.
+    pub fn directory(&self, route: &Route) -> Option<&ContentIndexEntries> {
+        route
+            .as_ref()
+            .split('/')
+            .filter(|component| !component.is_empty())
+            .try_fold(self, |node, component| {
+                match node.0.get(&format!("{}/", component)) {
+                    Some(ContentIndex::Directory(entries)) => Some(entries),
+                    _ => None,
+                }
+            })
+    }
+}
+
 impl Default for ContentIndexEntries {
     fn default() -> Self {
         Self::new()
@@ -127,6 +150,29 @@ mod tests {
     use serde_json::json;
     use test_log::test;
 
+    #[test]
+    fn directories_can_be_found_by_route() {
+        let mut index = ContentIndexEntries::new();
+        index.try_add(route("/foo")).unwrap();
+        index.try_add(route("/bar/baz/quux")).unwrap();
+
+        let names = |entries: Option<&ContentIndexEntries>| {
+            entries
+                .map(|entries| {
+                    entries
+                        .iter()
+                        .map(|(name, _)| name.clone())
+                        .collect::<Vec<_>>()
+                })
+                .unwrap_or_default()
+        };
+        assert_eq!(names(index.directory(&route("/"))), vec!["bar/", "foo"]);
+        assert_eq!(names(index.directory(&route("/bar"))), vec!["baz/"]);
+        assert_eq!(names(index.directory(&route("/bar/baz"))), vec!["quux"]);
+        assert!(index.directory(&route("/foo")).is_none());
+        assert!(index.directory(&route("/nope")).is_none());
+    }
+
     #[test]
     fn index_has_the_correct_structure() {
         let mut index = ContentIndexEntries::new();
@@ -1,11 +1,16 @@
+use super::front_matter::{fill_missing, FrontMatter};
 use super::*;
-use body::{FileBody, InMemoryBody, ProcessBody};
+use crate::config::ContentConfig;
+use body::{ByteRangesBody, FileBody, InMemoryBody, MappedBody};
 use handlebars::{self, Handlebars, Renderable as _};
+use memmap2::Mmap;
+use sha2::{Digest, Sha256, Sha384, Sha512};
+use std::collections::BTreeMap;
 use std::fs;
-use std::io;
-use std::mem;
-use std::path::{Path, PathBuf};
-use std::process::{Command, Stdio};
+use std::io::{self, Read, Seek};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::SystemTime;
 use thiserror::Error;
 
 /// Indicates that there was an error during rendering.
@@ -17,6 +22,13 @@ pub enum RenderingFailedError {
         source: handlebars::RenderError,
     },
 
+    #[cfg(feature = "templating")]
+    #[error(transparent)]
+    MiniJinjaRenderError {
+        #[from]
+        source: minijinja::Error,
+    },
+
     #[error(
         "Executable '{}' with working directory '{}' could not be successfully executed: {}",
         .program,
@@ -48,46 +60,429 @@ pub enum RenderingFailedError {
     Bug(String),
 }
 
+impl RenderingFailedError {
+    pub fn location(&self) -> Option<FailureLocation> {
+        match self {
+            RenderingFailedError::TemplateRenderError { source } => source
+                .template_name
+                .clone()
+                .map(|name| FailureLocation::Template {
+                    name,
+                    line: source.line_no,
+                }),
+            #[cfg(feature = "templating")]
+            RenderingFailedError::MiniJinjaRenderError { source } => {
+                source.name().map(|name| FailureLocation::Template {
+                    name: String::from(name),
+                    line: source.line(),
+                })
+            }
+            RenderingFailedError::ExecutableError {
+                program,
+                working_directory,
+                ..
+            } => Some(FailureLocation::Executable {
+                program: program.clone(),
+                working_directory: working_directory.clone(),
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// A `Content-Encoding` that static files can be precompressed with.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum ContentCoding {
+    Brotli,
+    Gzip,
+}
+impl ContentCoding {
+    /// Precompressed files are named like `foo.html.gz` or `foo.html.br`.
+    pub fn from_file_extension(extension: &str) -> Option<Self> {
+        match extension {
+            "br" => Some(ContentCoding::Brotli),
+            "gz" => Some(ContentCoding::Gzip),
+            _ => None,
+        }
+    }
+
+    /// The name of this coding in HTTP headers.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ContentCoding::Brotli => "br",
+            ContentCoding::Gzip => "gzip",
+        }
+    }
+}
+
 /// A static file from the content directory (such as an image or a text file).
 pub struct StaticContentItem {
     contents: fs::File,
     media_type: MediaType,
+    precompressed: BTreeMap<ContentCoding, fs::File>,
+
+    /// The contents, if they are small enough to keep in memory (see
+    /// [`preload_if_small`](Self::preload_if_small) and
+    /// [`map_if_small`](Self::map_if_small)).
+    in_memory: Option<InMemoryContents>,
+
+    /// How many bytes are read at a time when the contents are streamed from
+    /// the file.
+    chunk_size: u64,
+
+    /// Digests of the contents, for scripts and stylesheets (see
+    /// [`compute_integrity_if_script_or_style`](Self::compute_integrity_if_script_or_style)).
+    integrity: Option<Integrity>,
+}
+
+enum InMemoryContents {
+    Preloaded(Bytes),
+    Mapped(Arc<Mmap>),
 }
+
 impl StaticContentItem {
     pub fn new(contents: fs::File, media_type: MediaType) -> Self {
         StaticContentItem {
             contents,
             media_type,
+            precompressed: BTreeMap::new(),
+            in_memory: None,
+            chunk_size: FileBody::DEFAULT_CHUNK_SIZE,
+            integrity: None,
+        }
+    }
+
+    /// Reads the file in chunks of `chunk_size` bytes when rendering.
+    pub fn with_chunk_size(self, chunk_size: u64) -> Self {
+        StaticContentItem { chunk_size, ..self }
+    }
+
+    /// Reads the contents into memory if they are no larger than `max_bytes`,
+    /// so that rendering does not need to read the file. If reading fails the
+    /// file is read during rendering as usual.
+    pub fn preload_if_small(&mut self, max_bytes: u64) {
+        if self.in_memory.is_some() || !self.is_small(max_bytes) {
+            return;
+        }
+        let mut contents = Vec::new();
+        match self.contents.try_clone().and_then(|mut file| {
+            file.seek(io::SeekFrom::Start(0))?;
+            file.read_to_end(&mut contents)
+        }) {
+            Ok(_) => self.in_memory = Some(InMemoryContents::Preloaded(Bytes::from(contents))),
+            Err(error) => log::warn!(
+                "Unable to preload static content, so it will be read later instead: {}",
+                error
+            ),
+        }
+    }
+
+    /// Memory-maps the contents if they are no larger than `max_bytes`, so
+    /// that rendering does not need to read the file. If the file cannot be
+    /// mapped it is read as usual.
+    pub fn map_if_small(&mut self, max_bytes: u64) {
+        if self.in_memory.is_some() || !self.is_small(max_bytes) {
+            return;
+        }
+        // SAFETY: The mapping is only read. If the file is truncated while it
+        // is mapped reads can fault, which is why mapping is opt-in and
+        // documented as being for files which are not modified in place.
+        match unsafe { Mmap::map(&self.contents) } {
+            Ok(map) => self.in_memory = Some(InMemoryContents::Mapped(Arc::new(map))),
+            Err(error) => log::warn!(
+                "Unable to memory-map static content, so it will be read instead: {}",
+                error
+            ),
+        }
+    }
+
+    /// Hashes the contents if this item is JavaScript or CSS, so that pages
+    /// can refer to it with an `integrity` attribute. Other files are left
+    /// alone, since hashing them all would slow down loading for no benefit.
+    pub fn compute_integrity_if_script_or_style(&mut self) {
+        if self.integrity.is_some()
+            || !Integrity::MEDIA_TYPES.contains(&self.media_type.essence_str())
+        {
+            return;
+        }
+        match self.contents.try_clone().and_then(|mut file| {
+            file.seek(io::SeekFrom::Start(0))?;
+            Integrity::from_reader(file)
+        }) {
+            Ok(integrity) => self.integrity = Some(integrity),
+            Err(error) => log::warn!(
+                "Unable to compute the integrity of static content: {}",
+                error
+            ),
+        }
+    }
+
+    /// Digests of the contents, if they were computed when loading.
+    pub fn integrity(&self) -> Option<&Integrity> {
+        self.integrity.as_ref()
+    }
+
+    /// Whether the contents are not empty and no larger than `max_bytes`.
+    fn is_small(&self, max_bytes: u64) -> bool {
+        self.contents
+            .metadata()
+            .is_ok_and(|metadata| metadata.len() > 0 && metadata.len() <= max_bytes)
+    }
+
+    /// Adds a copy of this item's contents which has already been compressed
+    /// with `content_coding`, for clients that accept it.
+    pub fn add_precompressed(&mut self, content_coding: ContentCoding, contents: fs::File) {
+        self.precompressed.insert(content_coding, contents);
+    }
+
+    /// The codings that this item has precompressed copies for.
+    pub fn precompressed_codings(&self) -> impl Iterator<Item = ContentCoding> + '_ {
+        self.precompressed.keys().copied()
+    }
+
+    /// The size of this item's (uncompressed) contents in bytes.
+    pub fn size(&self) -> Result<u64, RenderingFailedError> {
+        Ok(self.contents.metadata()?.len())
+    }
+
+    /// When this item's file was last modified.
+    pub fn modified(&self) -> Result<SystemTime, RenderingFailedError> {
+        Ok(self.contents.metadata()?.modified()?)
+    }
+
+    /// Like rendering, but only the given (inclusive) byte ranges of the
+    /// contents are produced. If there is more than one range they are
+    /// rendered as `multipart/byteranges` separated by `boundary`. Ranges must
+    /// be within the contents.
+    pub fn render_byte_ranges(
+        &self,
+        ranges: &[(u64, u64)],
+        boundary: &str,
+    ) -> Result<Media<Box<dyn ByteStream>>, RenderingFailedError> {
+        let segment = |(first, last): (u64, u64)| {
+            FileBody::try_from_file_range(self.contents.try_clone()?, first, last - first + 1)
+                .map(|body| body.with_chunk_size(self.chunk_size))
+        };
+        match ranges {
+            [range] => Ok(Media::new(
+                self.media_type.clone(),
+                Box::new(segment(*range)?),
+            )),
+            _ => {
+                let size = self.size()?;
+                let parts = ranges
+                    .iter()
+                    .map(|&(first, last)| {
+                        let headers = format!(
+                            "Content-Type: {}\r\nContent-Range: bytes {}-{}/{}",
+                            self.media_type, first, last, size,
+                        );
+                        Ok((headers, segment((first, last))?))
+                    })
+                    .collect::<Result<Vec<_>, io::Error>>()?;
+                let media_type = format!("multipart/byteranges; boundary={}", boundary)
+                    .parse::<MediaType>()
+                    .map_err(|error| RenderingFailedError::Bug(error.to_string()))?;
+                Ok(Media::new(
+                    media_type,
+                    Box::new(ByteRangesBody::new(boundary, parts)),
+                ))
+            }
         }
     }
 
+    /// Like rendering, but produces the copy of the contents compressed with
+    /// `content_coding` (if there is one).
+    pub fn render_precompressed(
+        &self,
+        content_coding: ContentCoding,
+    ) -> Option<Result<Media<FileBody>, RenderingFailedError>> {
+        self.precompressed.get(&content_coding).map(|contents| {
+            let file = contents.try_clone()?;
+            let stream = FileBody::try_from_file(file)?.with_chunk_size(self.chunk_size);
+            Ok(Media::new(self.media_type.clone(), stream))
+        })
+    }
+
     pub(super) fn render_to_native_media_type(
         &self,
-    ) -> Result<Media<FileBody>, RenderingFailedError> {
+    ) -> Result<Media<Box<dyn ByteStream>>, RenderingFailedError> {
+        match &self.in_memory {
+            Some(InMemoryContents::Preloaded(bytes)) => {
+                return Ok(Media::new(
+                    self.media_type.clone(),
+                    Box::new(InMemoryBody(bytes.clone())),
+                ));
+            }
+            Some(InMemoryContents::Mapped(map)) => {
+                return Ok(Media::new(
+                    self.media_type.clone(),
+                    Box::new(MappedBody::new(map.clone())),
+                ));
+            }
+            None => {}
+        }
         // We clone the file handle and operate on that to avoid taking
         // self as mut.
         let file = self.contents.try_clone()?;
-        let stream = FileBody::try_from_file(file)?;
-        Ok(Media::new(self.media_type.clone(), stream))
+        let stream = FileBody::try_from_file(file)?.with_chunk_size(self.chunk_size);
+        Ok(Media::new(self.media_type.clone(), Box::new(stream)))
     }
 }
 
-/// A handlebars template that came from the content directory.
+/// Digests of a file's contents for [Subresource
+/// Integrity](https://www.w3.org/TR/SRI/).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Integrity {
+    sha256: Vec<u8>,
+    sha384: Vec<u8>,
+    sha512: Vec<u8>,
+}
+
+impl Integrity {
+    /// Media types of the files which browsers check integrity for.
+    const MEDIA_TYPES: [&'static str; 3] =
+        ["text/javascript", "application/javascript", "text/css"];
+
+    /// Hashes everything from `reader` with each algorithm in a single pass.
+    fn from_reader(mut reader: impl Read) -> io::Result<Self> {
+        let mut sha256 = Sha256::new();
+        let mut sha384 = Sha384::new();
+        let mut sha512 = Sha512::new();
+        let mut buffer = [0; 8192];
+        loop {
+            let bytes_read = match reader.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(bytes_read) => bytes_read,
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+                Err(error) => return Err(error),
+            };
+            sha256.update(&buffer[..bytes_read]);
+            sha384.update(&buffer[..bytes_read]);
+            sha512.update(&buffer[..bytes_read]);
+        }
+        Ok(Integrity {
+            sha256: sha256.finalize().to_vec(),
+            sha384: sha384.finalize().to_vec(),
+            sha512: sha512.finalize().to_vec(),
+        })
+    }
+
+    /// The integrity metadata for `algorithm`, like `sha384-<base64 digest>`.
+    pub fn metadata(&self, algorithm: IntegrityAlgorithm) -> String {
+        let digest = match algorithm {
+            IntegrityAlgorithm::Sha256 => &self.sha256,
+            IntegrityAlgorithm::Sha384 => &self.sha384,
+            IntegrityAlgorithm::Sha512 => &self.sha512,
+        };
+        format!("{}-{}", algorithm.as_str(), base64::encode(digest))
+    }
+}
+
+/// Hash algorithms which browsers support for Subresource Integrity.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum IntegrityAlgorithm {
+    Sha256,
+    #[default]
+    Sha384,
+    Sha512,
+}
+
+impl IntegrityAlgorithm {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "sha256" => Some(IntegrityAlgorithm::Sha256),
+            "sha384" => Some(IntegrityAlgorithm::Sha384),
+            "sha512" => Some(IntegrityAlgorithm::Sha512),
+            _ => None,
+        }
+    }
+
+    /// The name of this algorithm in integrity metadata.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            IntegrityAlgorithm::Sha256 => "sha256",
+            IntegrityAlgorithm::Sha384 => "sha384",
+            IntegrityAlgorithm::Sha512 => "sha512",
+        }
+    }
+}
+
+/// A template that came from the content directory.
 pub struct RegisteredTemplate {
     name_in_registry: String,
+    language: TemplateLanguage,
     rendered_media_type: MediaType,
+    escaping: Escaping,
+    front_matter: FrontMatter,
+    strict: bool,
+    defaults: Option<serde_json::Value>,
 }
 impl RegisteredTemplate {
     pub fn new<S: AsRef<str>>(name_in_registry: S, rendered_media_type: MediaType) -> Self {
         RegisteredTemplate {
             name_in_registry: String::from(name_in_registry.as_ref()),
+            language: TemplateLanguage::Handlebars,
+            escaping: Escaping::for_media_type(&rendered_media_type),
             rendered_media_type,
+            front_matter: FrontMatter::default(),
+            strict: true,
+            defaults: None,
         }
     }
 
+    /// Templates are handlebars unless otherwise specified.
+    pub fn with_language(self, language: TemplateLanguage) -> Self {
+        RegisteredTemplate { language, ..self }
+    }
+
+    /// Applies settings from the template's front matter, falling back to
+    /// those in `config`.
+    pub fn with_front_matter(self, front_matter: FrontMatter, config: &ContentConfig) -> Self {
+        let strict = front_matter
+            .settings()
+            .strict
+            .unwrap_or(config.strict_templates);
+
+        let mut defaults = front_matter.settings().defaults.clone();
+        if !config.template_defaults.is_empty() {
+            let config_defaults = serde_json::Value::Object(config.template_defaults.clone());
+            match &mut defaults {
+                Some(defaults) => fill_missing(defaults, &config_defaults),
+                None => defaults = Some(config_defaults),
+            }
+        }
+
+        RegisteredTemplate {
+            front_matter,
+            strict,
+            defaults,
+            ..self
+        }
+    }
+
+    /// The name of the template in its template engine (its relative path in
+    /// the content directory).
+    pub fn name_in_registry(&self) -> &str {
+        &self.name_in_registry
+    }
+
+    pub fn language(&self) -> TemplateLanguage {
+        self.language
+    }
+
+    pub fn front_matter(&self) -> &FrontMatter {
+        &self.front_matter
+    }
+
+    /// Whether rendering fails when the template references missing data.
+    pub fn is_strict(&self) -> bool {
+        self.strict
+    }
+
     pub(super) fn render_to_native_media_type<ServerInfo>(
         &self,
-        handlebars_registry: &Handlebars,
+        template_engine: &dyn TemplateEngine,
         render_data: RenderData<ServerInfo>,
         handlebars_render_context: Option<handlebars::RenderContext>,
     ) -> Result<Media<InMemoryBody>, RenderingFailedError>
@@ -96,24 +491,37 @@ impl RegisteredTemplate {
     {
         let render_data = RenderData {
             target_media_type: Some(self.rendered_media_type.clone()),
+            front_matter: Some(self.front_matter.data().clone()),
             ..render_data
         };
-        let rendered_content = match handlebars_render_context {
-            None => handlebars_registry.render(&self.name_in_registry, &render_data)?,
-            Some(mut handlebars_render_context) => handlebars_registry
-                .get_template(&self.name_in_registry)
-                .ok_or_else(|| {
-                    RenderingFailedError::Bug(format!(
-                        "Template '{}' was not found in the registry",
-                        &self.name_in_registry
-                    ))
-                })?
-                .renders(
-                    handlebars_registry,
-                    &handlebars::Context::wraps(&render_data)?,
-                    &mut { handlebars_render_context },
-                )?,
+        let mut render_data = match handlebars_render_context
+            .as_ref()
+            .and_then(|handlebars_render_context| handlebars_render_context.context())
+        {
+            // Content included via the `get` helper renders with its caller's
+            // data, but front matter is always its own.
+            Some(handlebars_context) => {
+                let mut data = handlebars_context.data().clone();
+                if let Some(data) = data.as_object_mut() {
+                    data.insert(
+                        String::from(FRONT_MATTER_PROPERTY_NAME),
+                        serde_json::Value::Object(self.front_matter.data().clone()),
+                    );
+                }
+                data
+            }
+            None => serde_json::to_value(render_data)?,
         };
+        if let Some(defaults) = &self.defaults {
+            fill_missing(&mut render_data, defaults);
+        }
+        let rendered_content = self.escaping.scope(|| {
+            template_engine.render_template(
+                &self.name_in_registry,
+                &render_data,
+                handlebars_render_context,
+            )
+        })?;
 
         Ok(Media::new(
             self.rendered_media_type.clone(),
@@ -126,16 +534,19 @@ impl RegisteredTemplate {
 pub struct UnregisteredTemplate {
     template: handlebars::Template,
     rendered_media_type: MediaType,
+    escaping: Escaping,
 }
 impl UnregisteredTemplate {
     pub fn from_source<S: AsRef<str>>(
         handlebars_source: S,
         rendered_media_type: MediaType,
     ) -> Result<Self, TemplateError> {
-        let template =
-            handlebars::Template::compile(handlebars_source.as_ref()).map_err(Box::new)?;
+        let handlebars_source = handlebars_source.as_ref();
+        let template = handlebars::Template::compile(handlebars_source)
+            .map_err(|error| TemplateError::new(error, None, handlebars_source, 0))?;
         Ok(UnregisteredTemplate {
             template,
+            escaping: Escaping::for_media_type(&rendered_media_type),
             rendered_media_type,
         })
     }
@@ -154,11 +565,13 @@ impl UnregisteredTemplate {
         };
         let handlebars_context = handlebars::Context::wraps(&render_data)?;
         let mut handlebars_render_context = handlebars::RenderContext::new(None);
-        let rendered_content = self.template.renders(
-            handlebars_registry,
-            &handlebars_context,
-            &mut handlebars_render_context,
-        )?;
+        let rendered_content = self.escaping.scope(|| {
+            self.template.renders(
+                handlebars_registry,
+                &handlebars_context,
+                &mut handlebars_render_context,
+            )
+        })?;
         Ok(Media::new(
             self.rendered_media_type.clone(),
             InMemoryBody(rendered_content.bytes().collect()),
@@ -174,7 +587,7 @@ impl Render for UnregisteredTemplate {
     ) -> Result<Media<Self::Output>, RenderError>
     where
         ServerInfo: Clone + Serialize,
-        Engine: ContentEngine<ServerInfo>,
+        Engine: ContentEngine<ServerInfo> + ?Sized,
         Accept: IntoIterator<Item = &'accept MediaRange>,
         Self::Output: ByteStream,
     {
@@ -196,120 +609,18 @@ impl Render for UnregisteredTemplate {
     }
 }
 
-/// A program that can be run by the operating system, e.g. a shell script.
-///
-/// If the executed program terminates with a nonzero exit code, rendering
-/// output is the contents of standard output. Otherwise a rendering failure
-/// occurs.
-///
-/// Render data is available as JSON in the OPERATOR_RENDER_DATA environment
-/// variable.
-pub struct Executable {
-    program: String,
-    working_directory: PathBuf,
-    output_media_type: MediaType,
-}
-impl Executable {
-    pub fn new<P: AsRef<str>, W: AsRef<Path>>(
-        program: P,
-        working_directory: W,
-        output_media_type: MediaType,
-    ) -> Self {
-        Executable {
-            program: String::from(program.as_ref()),
-            working_directory: PathBuf::from(working_directory.as_ref()),
-            output_media_type,
-        }
-    }
-
-    pub(super) fn render_to_native_media_type<ServerInfo>(
-        &self,
-        render_data: RenderData<ServerInfo>,
-        additional_data: Option<serde_json::Value>,
-    ) -> Result<Media<ProcessBody>, RenderingFailedError>
-    where
-        ServerInfo: Clone + Serialize,
-    {
-        let base_render_data = RenderData {
-            target_media_type: Some(self.output_media_type.clone()),
-            ..render_data
-        };
-
-        let render_data_environment_variable_value = match additional_data {
-            None => serde_json::ser::to_string(&base_render_data)?,
-            Some(serde_json::Value::Object(mut additional_data_as_json_map)) => {
-                // merge additional data atop base render data
-                let base_render_data_as_json = serde_json::value::to_value(base_render_data)?;
-                if let serde_json::Value::Object(mut base_render_data_as_json_map) =
-                    base_render_data_as_json
-                {
-                    for (key, value) in additional_data_as_json_map.iter_mut() {
-                        base_render_data_as_json_map.insert(key.to_string(), mem::take(value));
-                    }
-                    serde_json::Value::Object(base_render_data_as_json_map).to_string()
-                } else {
-                    return Err(RenderingFailedError::Bug(format!(
-                        "Render data did not serialize to a JSON object, instead got `{}`.",
-                        base_render_data_as_json
-                    )));
-                }
-            }
-            Some(non_object_additional_data) => non_object_additional_data.to_string(),
-        };
-
-        let mut command = Command::new(self.program.clone());
-        let child = command
-            .current_dir(self.working_directory.clone())
-            .stdin(Stdio::null())
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
-            .env(
-                "OPERATOR_RENDER_DATA",
-                render_data_environment_variable_value,
-            )
-            .spawn()
-            .map_err(|io_error| RenderingFailedError::ExecutableError {
-                message: format!("Unable to execute program: {}", io_error),
-                program: self.program.clone(),
-                working_directory: self.working_directory.clone(),
-            })?;
-
-        Ok(Media::new(
-            self.output_media_type.clone(),
-            ProcessBody::new(child),
-        ))
-    }
-}
-
 #[cfg(test)]
 mod tests {
     use super::super::test_lib::*;
     use super::*;
-    use crate::content::content_index::ContentIndexEntries;
     use crate::test_lib::*;
-    use crate::ServerInfo;
     use ::mime;
     use maplit::hashmap;
-    use std::fs;
     use std::io::Write;
     use std::str;
     use tempfile::tempfile;
     use test_log::test;
 
-    fn test_render_data() -> RenderData<ServerInfo> {
-        RenderData {
-            server_info: ServerInfo::without_socket_address().expect("Unable to create ServerInfo"),
-            index: ContentIndex::Directory(ContentIndexEntries::new()),
-            target_media_type: None,
-            error_code: None,
-            request: RequestData {
-                route: None,
-                query_parameters: hashmap![],
-                request_headers: hashmap![],
-            },
-        }
-    }
-
     #[test]
     fn static_content_can_be_rendered() {
         let mut file = tempfile().expect("Failed to create temporary file");
@@ -317,11 +628,74 @@ mod tests {
         let static_content = StaticContentItem {
             media_type: MediaType::from_media_range(mime::TEXT_PLAIN).unwrap(),
             contents: file,
+            precompressed: BTreeMap::new(),
+            in_memory: None,
+            chunk_size: FileBody::DEFAULT_CHUNK_SIZE,
+            integrity: None,
+        };
+        let output = static_content
+            .render_to_native_media_type()
+            .expect("Render failed");
+
+        assert_eq!(media_to_string(output), String::from("hello world"));
+    }
+
+    #[test]
+    fn static_content_is_read_in_chunks() {
+        let mut file = tempfile().expect("Failed to create temporary file");
+        write!(file, "hello world").expect("Failed to write to temporary file");
+        let static_content =
+            StaticContentItem::new(file, MediaType::from_media_range(mime::TEXT_PLAIN).unwrap())
+                .with_chunk_size(4);
+
+        let render = || {
+            static_content
+                .render_to_native_media_type()
+                .expect("Render failed")
         };
+        assert_eq!(chunk_lengths(render()), vec![4, 4, 3]);
+        assert_eq!(media_to_string(render()), String::from("hello world"));
+    }
+
+    #[test]
+    fn small_static_content_can_be_memory_mapped() {
+        let mut file = tempfile().expect("Failed to create temporary file");
+        write!(file, "hello world").expect("Failed to write to temporary file");
+        let mut static_content =
+            StaticContentItem::new(file, MediaType::from_media_range(mime::TEXT_PLAIN).unwrap());
+
+        static_content.map_if_small(4);
+        assert!(static_content.in_memory.is_none());
+        static_content.map_if_small(11);
+        assert!(matches!(
+            static_content.in_memory,
+            Some(InMemoryContents::Mapped(_))
+        ));
+
         let output = static_content
             .render_to_native_media_type()
             .expect("Render failed");
+        assert_eq!(media_to_string(output), String::from("hello world"));
+    }
 
+    #[test]
+    fn small_static_content_can_be_preloaded() {
+        let mut file = tempfile().expect("Failed to create temporary file");
+        write!(file, "hello world").expect("Failed to write to temporary file");
+        let mut static_content =
+            StaticContentItem::new(file, MediaType::from_media_range(mime::TEXT_PLAIN).unwrap());
+
+        static_content.preload_if_small(4);
+        assert!(static_content.in_memory.is_none());
+        static_content.preload_if_small(64 * 1024);
+        assert!(matches!(
+            static_content.in_memory,
+            Some(InMemoryContents::Preloaded(_))
+        ));
+
+        let output = static_content
+            .render_to_native_media_type()
+            .expect("Render failed");
         assert_eq!(media_to_string(output), String::from("hello world"));
     }
 
@@ -339,6 +713,10 @@ mod tests {
         let static_content = StaticContentItem {
             media_type: MediaType::from_media_range(mime::APPLICATION_OCTET_STREAM).unwrap(),
             contents: file,
+            precompressed: BTreeMap::new(),
+            in_memory: None,
+            chunk_size: FileBody::DEFAULT_CHUNK_SIZE,
+            integrity: None,
         };
         let output = static_content
             .render_to_native_media_type()
@@ -362,7 +740,7 @@ mod tests {
         let rendered = template.render_to_native_media_type(
             content_engine.handlebars_registry(),
             content_engine
-                .render_context(None, hashmap![], hashmap![])
+                .render_context(None, QueryParameters::default(), hashmap![])
                 .data,
         );
 
@@ -384,7 +762,7 @@ mod tests {
         let rendered = template.render_to_native_media_type(
             content_engine.handlebars_registry(),
             content_engine
-                .render_context(Some(route("/test")), hashmap![], hashmap![])
+                .render_context(Some(route("/test")), QueryParameters::default(), hashmap![])
                 .data,
             None,
         );
@@ -413,7 +791,7 @@ mod tests {
         let rendered = template.render_to_native_media_type(
             content_engine.handlebars_registry(),
             content_engine
-                .render_context(Some(route("/test")), hashmap![], hashmap![])
+                .render_context(Some(route("/test")), QueryParameters::default(), hashmap![])
                 .data,
             Some(handlebars_render_context),
         );
@@ -421,97 +799,4 @@ mod tests {
         let template_output = media_to_string(rendered.expect("Rendering failed"));
         assert_eq!(template_output, "pong");
     }
-
-    #[test]
-    fn executables_execute_when_rendered() {
-        let path = format!("{}/src", PROJECT_DIRECTORY);
-        let working_directory =
-            fs::canonicalize(path).expect("Could not canonicalize path for test");
-        let executable = Executable::new(
-            "pwd",
-            working_directory.clone(),
-            MediaType::from_media_range(mime::TEXT_PLAIN).unwrap(),
-        );
-        let output = executable
-            .render_to_native_media_type(test_render_data(), None)
-            .expect("Executable failed but it should have succeeded");
-
-        assert_eq!(
-            media_to_string(output),
-            format!("{}\n", working_directory.display())
-        );
-    }
-
-    #[test]
-    fn executables_require_working_directory_that_exists() {
-        let working_directory = "/hopefully/this/path/does/not/actually/exist/on/your/system";
-        let executable = Executable::new(
-            "pwd",
-            working_directory,
-            MediaType::from_media_range(mime::TEXT_PLAIN).unwrap(),
-        );
-        let result = executable.render_to_native_media_type(test_render_data(), None);
-        assert!(
-            result.is_err(),
-            "Executable succeeded but it should have failed"
-        );
-    }
-
-    #[test]
-    fn executables_emit_stream_error_if_exit_code_is_not_zero() {
-        let path = format!("{}/src", PROJECT_DIRECTORY);
-        let working_directory =
-            fs::canonicalize(path).expect("Could not canonicalize path for test");
-
-        // Exits with 1 and prints nothing to stdout.
-        {
-            let executable = Executable::new(
-                "false",
-                working_directory.clone(),
-                MediaType::from_media_range(mime::TEXT_PLAIN).unwrap(),
-            );
-            let output = executable
-                .render_to_native_media_type(test_render_data(), None)
-                .expect("Executable failed but it should have succeeded");
-
-            match block_on_content(output) {
-                Err(StreamError::ExecutableExitedWithNonzero {
-                    exit_code,
-                    stderr_contents,
-                    ..
-                }) => {
-                    assert_eq!(exit_code, Some(1));
-                    assert_eq!(stderr_contents, None);
-                }
-                Err(_) => panic!("Got a different error than expected"),
-                Ok(_) => panic!("Expected an error"),
-            }
-        }
-
-        // Exits with nonzero and prints a message to stdout.
-        {
-            let executable = Executable::new(
-                "mv",
-                working_directory.clone(),
-                MediaType::from_media_range(mime::TEXT_PLAIN).unwrap(),
-            );
-            let output = executable
-                .render_to_native_media_type(test_render_data(), None)
-                .expect("Executable failed but it should have succeeded");
-
-            match block_on_content(output) {
-                Err(StreamError::ExecutableExitedWithNonzero {
-                    exit_code,
-                    stderr_contents,
-                    ..
-                }) => {
-                    assert!(exit_code.is_some() && exit_code != Some(0));
-                    assert!(stderr_contents.is_some());
-                    assert!(stderr_contents.unwrap().len() > 0);
-                }
-                Err(_) => panic!("Got a different error than expected"),
-                Ok(_) => panic!("Expected an error"),
-            }
-        }
-    }
 }
@@ -1,17 +1,30 @@
 use super::content_item::*;
+#[cfg(feature = "executables")]
+use super::executable::Executable;
 use super::*;
 use std::collections::HashMap;
 
-pub struct ContentRegistry(HashMap<Route, ContentRepresentations>);
+pub struct ContentRegistry {
+    contents: HashMap<Route, ContentRepresentations>,
+    url_extensions: HashMap<(Route, MediaType), String>,
+    redirects: HashMap<Route, Redirect>,
+    internal_prefix: String,
+}
 impl ContentRegistry {
-    pub fn new() -> Self {
-        ContentRegistry(HashMap::new())
+    pub fn new(internal_prefix: String) -> Self {
+        ContentRegistry {
+            contents: HashMap::new(),
+            url_extensions: HashMap::new(),
+            redirects: HashMap::new(),
+            internal_prefix,
+        }
     }
 
-    /// Routes that begin with underscore are ignored for external requests
-    /// (they always 404).
+    /// Routes with a component that begins with the internal prefix (an
+    /// underscore by default) are ignored for external requests (they always
+    /// 404).
     pub fn get(&self, route: &Route) -> Option<&ContentRepresentations> {
-        if route.as_ref().contains("/_") {
+        if self.is_internal(route) {
             None
         } else {
             self.get_internal(route)
@@ -19,11 +32,57 @@ impl ContentRegistry {
     }
 
     pub fn get_internal(&self, route: &Route) -> Option<&ContentRepresentations> {
-        self.0.get(route)
+        self.contents.get(route)
+    }
+
+    pub fn get_internal_mut(&mut self, route: &Route) -> Option<&mut ContentRepresentations> {
+        self.contents.get_mut(route)
+    }
+
+    /// Like `get`, internal redirects are ignored.
+    pub fn redirect(&self, route: &Route) -> Option<&Redirect> {
+        if self.is_internal(route) {
+            None
+        } else {
+            self.redirect_internal(route)
+        }
+    }
+
+    pub fn redirect_internal(&self, route: &Route) -> Option<&Redirect> {
+        self.redirects.get(route)
+    }
+
+    pub fn insert_redirect(&mut self, route: Route, redirect: Redirect) {
+        self.redirects.insert(route, redirect);
+    }
+
+    fn is_internal(&self, route: &Route) -> bool {
+        !self.internal_prefix.is_empty()
+            && route
+                .as_ref()
+                .split('/')
+                .any(|component| component.starts_with(&self.internal_prefix))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&Route, &ContentRepresentations)> {
+        self.contents.iter()
+    }
+
+    /// The filename extension which indicated `media_type` for the content
+    /// at `route`. Requests can use it to choose that representation.
+    pub fn url_extension(&self, route: &Route, media_type: &MediaType) -> Option<&str> {
+        self.url_extensions
+            .get(&(route.clone(), media_type.clone()))
+            .map(String::as_str)
+    }
+
+    pub fn set_url_extension(&mut self, route: Route, media_type: MediaType, extension: &str) {
+        self.url_extensions
+            .insert((route, media_type), String::from(extension));
     }
 
     pub fn entry_or_insert_default(&mut self, key: Route) -> &mut ContentRepresentations {
-        self.0.entry(key).or_default()
+        self.contents.entry(key).or_default()
     }
 }
 
@@ -34,9 +93,47 @@ pub type ContentRepresentations = HashMap<MediaType, RegisteredContent>;
 pub enum RegisteredContent {
     StaticContentItem(StaticContentItem),
     RegisteredTemplate(RegisteredTemplate),
+    #[cfg(feature = "executables")]
     Executable(Executable),
 }
 
+impl RegisteredContent {
+    /// Content encodings which this content can be served with as-is (see
+    /// [`ContentCoding`]). Only static files can be precompressed.
+    pub fn precompressed_codings(&self) -> Vec<ContentCoding> {
+        match self {
+            RegisteredContent::StaticContentItem(item) => item.precompressed_codings().collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Whether rendering this content can fill a cache which later renders
+    /// are served from. Executables with an output cache can, and so can
+    /// templates (which may `get` them). Nothing else is cached.
+    pub fn warms_caches(&self) -> bool {
+        match self {
+            RegisteredContent::StaticContentItem(_) => false,
+            RegisteredContent::RegisteredTemplate(_) => true,
+            #[cfg(feature = "executables")]
+            RegisteredContent::Executable(executable) => executable.caches_output(),
+        }
+    }
+
+    /// The copy of this content which was precompressed with `content_coding`,
+    /// if there is one.
+    pub fn render_precompressed(
+        &self,
+        content_coding: ContentCoding,
+    ) -> Option<Result<Media<Box<dyn ByteStream>>, RenderError>> {
+        match self {
+            RegisteredContent::StaticContentItem(item) => item
+                .render_precompressed(content_coding)
+                .map(|result| result.map(box_media).map_err(RenderError::RenderingFailed)),
+            _ => None,
+        }
+    }
+}
+
 impl Render for ContentRepresentations {
     type Output = Box<dyn ByteStream>;
     fn render<'accept, ServerInfo, Engine, Accept>(
@@ -46,59 +143,79 @@ impl Render for ContentRepresentations {
     ) -> Result<Media<Self::Output>, RenderError>
     where
         ServerInfo: Clone + Serialize,
-        Engine: ContentEngine<ServerInfo>,
+        Engine: ContentEngine<ServerInfo> + ?Sized,
         Accept: IntoIterator<Item = &'accept MediaRange>,
         Self::Output: ByteStream,
     {
         let mut errors = Vec::new();
         for acceptable_media_range in acceptable_media_ranges {
-            for (registered_media_type, content) in self {
-                if registered_media_type.is_within_media_range(acceptable_media_range) {
-                    let render_result = match content {
-                        RegisteredContent::StaticContentItem(renderable) => {
-                            renderable.render_to_native_media_type().map(box_media)
+            let mut candidates = self
+                .iter()
+                .filter(|(registered_media_type, _)| {
+                    registered_media_type.is_within_media_range(acceptable_media_range)
+                })
+                .collect::<Vec<_>>();
+            // Exact matches come first, then generic XML (e.g. `text/xml` for
+            // `application/xml`), then XML-based formats like Atom.
+            candidates.sort_by_key(|(registered_media_type, _)| {
+                (
+                    registered_media_type.is_within_media_range_as_xml(acceptable_media_range),
+                    registered_media_type.essence_str().contains('+'),
+                )
+            });
+            for (registered_media_type, content) in candidates {
+                let render_result = match content {
+                    RegisteredContent::StaticContentItem(renderable) => {
+                        renderable.render_to_native_media_type()
+                    }
+                    RegisteredContent::RegisteredTemplate(renderable) => renderable
+                        .render_to_native_media_type(
+                            context
+                                .content_engine
+                                .template_engine(renderable.language(), renderable.is_strict()),
+                            context.data.clone(),
+                            context.handlebars_render_context.clone(),
+                        )
+                        .map(box_media),
+                    #[cfg(feature = "executables")]
+                    RegisteredContent::Executable(renderable) => {
+                        let additional_data = context.handlebars_render_context.as_ref().and_then(
+                            |handlebars_render_context| {
+                                handlebars_render_context
+                                    .context()
+                                    .map(|context| context.data().clone())
+                            },
+                        );
+                        if context.refresh_caches {
+                            renderable
+                                .render_refreshing_cache(context.data.clone(), additional_data)
+                        } else {
+                            renderable
+                                .render_to_native_media_type(context.data.clone(), additional_data)
                         }
-                        RegisteredContent::RegisteredTemplate(renderable) => renderable
-                            .render_to_native_media_type(
-                                context.content_engine.handlebars_registry(),
-                                context.data.clone(),
-                                context.handlebars_render_context.clone(),
-                            )
-                            .map(box_media),
-                        RegisteredContent::Executable(renderable) => renderable
-                            .render_to_native_media_type(
-                                context.data.clone(),
-                                context.handlebars_render_context.as_ref().and_then(
-                                    |handlebars_render_context| {
-                                        handlebars_render_context
-                                            .context()
-                                            .map(|context| context.data().clone())
-                                    },
-                                ),
-                            )
-                            .map(box_media),
-                    };
+                        .map(box_media)
+                    }
+                };
 
-                    // If rendering succeeded, return immediately. Otherwise
-                    // keep trying.
-                    match render_result {
-                        Ok(rendered) => {
-                            return if &rendered.media_type != registered_media_type {
-                                Err(RenderError::Bug(format!(
-                                    "The actual rendered media type ({}) did not match the \
-                                        media type this content was registered for ({}).",
-                                    rendered.media_type, registered_media_type,
-                                )))
-                            } else {
-                                Ok(rendered)
-                            }
+                // If rendering succeeded, return immediately. Otherwise
+                // keep trying.
+                match render_result {
+                    Ok(rendered) => {
+                        return if &rendered.media_type != registered_media_type {
+                            Err(RenderError::Bug(format!(
+                                "The actual rendered media type ({}) did not match the \
+                                    media type this content was registered for ({}).",
+                                rendered.media_type, registered_media_type,
+                            )))
+                        } else {
+                            Ok(rendered)
                         }
-                        Err(error) => {
-                            log::warn!("Rendering failure: {}", error);
-                            errors.push(error)
-                        }
-                    };
-                }
+                    }
+                    Err(error) => {
+                        log::warn!("Rendering failure: {}", error);
+                        errors.push(error)
+                    }
+                };
             }
         }
 
@@ -128,6 +245,7 @@ fn box_media<'o, O: ByteStream + 'o>(media: Media<O>) -> Media<Box<dyn ByteStrea
 mod tests {
     use super::super::test_lib::*;
     use super::*;
+    #[cfg(feature = "executables")]
     use crate::test_lib::*;
     use maplit::hashmap;
     use tempfile::tempfile;
@@ -143,43 +261,43 @@ mod tests {
             .register_template("registered-template", "")
             .unwrap();
         let empty_file = tempfile().expect("Failed to create temporary file");
-        (
-            content_engine,
-            vec![
-                hashmap![
-                    text_plain.clone() => RegisteredContent::StaticContentItem(StaticContentItem::new(
-                        empty_file.try_clone().unwrap(),
-                        text_plain.clone(),
-                    )),
-                    text_html.clone() => RegisteredContent::StaticContentItem(StaticContentItem::new(
-                        empty_file.try_clone().unwrap(),
-                        text_html.clone(),
-                    )),
-                ],
-                hashmap![
-                    text_plain.clone() => RegisteredContent::Executable(Executable::new(
-                        "true",
-                        PROJECT_DIRECTORY,
-                        text_plain.clone(),
-                    )),
-                    text_html.clone() => RegisteredContent::Executable(Executable::new(
-                        "true",
-                        PROJECT_DIRECTORY,
-                        text_html.clone(),
-                    )),
-                ],
-                hashmap![
-                    text_plain.clone() => RegisteredContent::RegisteredTemplate(RegisteredTemplate::new(
-                        "registered-template",
-                        text_plain.clone(),
-                    )),
-                    text_html.clone() => RegisteredContent::RegisteredTemplate(RegisteredTemplate::new(
-                        "registered-template",
-                        text_html.clone(),
-                    )),
-                ],
+        #[allow(unused_mut)]
+        let mut fixtures = vec![
+            hashmap![
+                text_plain.clone() => RegisteredContent::StaticContentItem(StaticContentItem::new(
+                    empty_file.try_clone().unwrap(),
+                    text_plain.clone(),
+                )),
+                text_html.clone() => RegisteredContent::StaticContentItem(StaticContentItem::new(
+                    empty_file.try_clone().unwrap(),
+                    text_html.clone(),
+                )),
+            ],
+            hashmap![
+                text_plain.clone() => RegisteredContent::RegisteredTemplate(RegisteredTemplate::new(
+                    "registered-template",
+                    text_plain.clone(),
+                )),
+                text_html.clone() => RegisteredContent::RegisteredTemplate(RegisteredTemplate::new(
+                    "registered-template",
+                    text_html.clone(),
+                )),
             ],
-        )
+        ];
+        #[cfg(feature = "executables")]
+        fixtures.push(hashmap![
+            text_plain.clone() => RegisteredContent::Executable(Executable::new(
+                "true",
+                PROJECT_DIRECTORY,
+                text_plain.clone(),
+            )),
+            text_html.clone() => RegisteredContent::Executable(Executable::new(
+                "true",
+                PROJECT_DIRECTORY,
+                text_html.clone(),
+            )),
+        ]);
+        (content_engine, fixtures)
     }
 
     #[test]
@@ -187,7 +305,7 @@ mod tests {
         let (mock_engine, renderables) = fixtures();
         for (index, renderable) in renderables.iter().enumerate() {
             let render_result = renderable.render(
-                mock_engine.render_context(None, hashmap![], hashmap![]),
+                mock_engine.render_context(None, QueryParameters::default(), hashmap![]),
                 &[],
             );
             assert!(
@@ -203,7 +321,7 @@ mod tests {
         let (mock_engine, renderables) = fixtures();
         for (index, renderable) in renderables.iter().enumerate() {
             let render_result = renderable.render(
-                mock_engine.render_context(None, hashmap![], hashmap![]),
+                mock_engine.render_context(None, QueryParameters::default(), hashmap![]),
                 &[::mime::IMAGE_GIF, ::mime::APPLICATION_PDF, ::mime::TEXT_CSS],
             );
             assert!(
@@ -219,7 +337,7 @@ mod tests {
         let (mock_engine, renderables) = fixtures();
         for (index, renderable) in renderables.iter().enumerate() {
             let render_result = renderable.render(
-                mock_engine.render_context(None, hashmap![], hashmap![]),
+                mock_engine.render_context(None, QueryParameters::default(), hashmap![]),
                 &[::mime::IMAGE_STAR],
             );
             assert!(
@@ -235,7 +353,7 @@ mod tests {
         let (mock_engine, renderables) = fixtures();
         for (index, renderable) in renderables.iter().enumerate() {
             let render_result = renderable.render(
-                mock_engine.render_context(None, hashmap![], hashmap![]),
+                mock_engine.render_context(None, QueryParameters::default(), hashmap![]),
                 &[::mime::IMAGE_GIF, ::mime::TEXT_PLAIN, ::mime::TEXT_CSS],
             );
             assert!(
@@ -258,7 +376,7 @@ mod tests {
         let (mock_engine, renderables) = fixtures();
         for (index, renderable) in renderables.iter().enumerate() {
             let render_result = renderable.render(
-                mock_engine.render_context(None, hashmap![], hashmap![]),
+                mock_engine.render_context(None, QueryParameters::default(), hashmap![]),
                 &[::mime::STAR_STAR],
             );
             assert!(
@@ -275,7 +393,7 @@ mod tests {
         let (mock_engine, renderables) = fixtures();
         for (index, renderable) in renderables.iter().enumerate() {
             let render_result = renderable.render(
-                mock_engine.render_context(None, hashmap![], hashmap![]),
+                mock_engine.render_context(None, QueryParameters::default(), hashmap![]),
                 &[::mime::TEXT_STAR],
             );
             assert!(
@@ -300,7 +418,7 @@ mod tests {
         let (mock_engine, renderables) = fixtures();
         for (index, renderable) in renderables.iter().enumerate() {
             let text_plain_result = renderable.render(
-                mock_engine.render_context(None, hashmap![], hashmap![]),
+                mock_engine.render_context(None, QueryParameters::default(), hashmap![]),
                 &[::mime::TEXT_PLAIN],
             );
             assert!(
@@ -316,7 +434,7 @@ mod tests {
             );
 
             let text_html_result = renderable.render(
-                mock_engine.render_context(None, hashmap![], hashmap![]),
+                mock_engine.render_context(None, QueryParameters::default(), hashmap![]),
                 &[::mime::TEXT_HTML],
             );
             assert!(
@@ -0,0 +1,102 @@
+use serde::{Serialize, Serializer};
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+/// What a content directory looked like when it was loaded, to help with
+/// capacity planning.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ContentStats {
+    /// How many files were in the content directory.
+    pub files: usize,
+
+    /// The total size of those files.
+    pub bytes: u64,
+
+    /// How many files there were of each kind.
+    pub kinds: BTreeMap<ContentKind, usize>,
+
+    /// How many files are held open to serve them quickly. Each of these uses
+    /// a file descriptor for as long as the content is loaded.
+    pub open_files: usize,
+
+    /// The most files the process may have open at once, if it is known.
+    pub open_file_limit: Option<u64>,
+
+    /// How long loading took.
+    #[serde(rename = "load-seconds", serialize_with = "serialize_seconds")]
+    pub load_time: Duration,
+}
+
+/// The kinds of files that a content directory can contain.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ContentKind {
+    Static,
+    Precompressed,
+    Template,
+    Executable,
+    Redirect,
+    Data,
+}
+
+impl ContentStats {
+    /// Content holding this fraction of the open file limit gets a warning,
+    /// since the server also needs descriptors for sockets and executables.
+    const OPEN_FILE_WARNING_THRESHOLD: f64 = 0.8;
+
+    /// Whether held-open files use enough of the open file limit that the
+    /// process is at risk of running out.
+    pub fn is_near_open_file_limit(&self) -> bool {
+        match self.open_file_limit {
+            Some(limit) => {
+                self.open_files as f64 >= limit as f64 * Self::OPEN_FILE_WARNING_THRESHOLD
+            }
+            None => false,
+        }
+    }
+
+    pub(crate) fn log(&self) {
+        log::info!(
+            "Loaded {} content files ({} bytes) in {:.3}s: {}",
+            self.files,
+            self.bytes,
+            self.load_time.as_secs_f64(),
+            self.kinds
+                .iter()
+                .map(|(kind, count)| format!("{} {:?}", count, kind).to_lowercase())
+                .collect::<Vec<_>>()
+                .join(", "),
+        );
+        if self.is_near_open_file_limit() {
+            log::warn!(
+                "{} content files are held open, which is close to the limit of {} open files \
+                for this process. Raise the limit (e.g. with `ulimit -n`) to avoid failures \
+                when the server runs out of file descriptors.",
+                self.open_files,
+                self.open_file_limit.unwrap_or_default(),
+            );
+        }
+    }
+}
+
+fn serialize_seconds<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_f64(duration.as_secs_f64())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn content_near_the_open_file_limit_is_noticed() {
+        let stats = |open_files, open_file_limit| ContentStats {
+            open_files,
+            open_file_limit,
+            ..ContentStats::default()
+        };
+        assert!(!stats(100, Some(1024)).is_near_open_file_limit());
+        assert!(stats(900, Some(1024)).is_near_open_file_limit());
+        assert!(!stats(900, None).is_near_open_file_limit());
+    }
+}
@@ -0,0 +1,287 @@
+use super::content_registry::ContentRegistry;
+use super::*;
+use handlebars::template::{HelperTemplate, Parameter, Template, TemplateElement};
+use handlebars::Handlebars;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+/// Which routes include which other routes when they are rendered, via
+/// partials or helpers like `get` and `layout`.
+///
+/// Dependencies are found in two ways: handlebars templates are scanned for
+/// partials and literal routes when content is loaded, and routes which are
+/// included while content is being rendered are added as they are found
+/// (this catches routes which are computed, like `get (route-join ...)`).
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DependencyGraph {
+    dependencies: BTreeMap<Route, BTreeSet<Route>>,
+}
+
+impl DependencyGraph {
+    /// Scans the handlebars templates in `content_registry`.
+    pub(crate) fn from_templates(
+        content_registry: &ContentRegistry,
+        handlebars_registry: &Handlebars,
+    ) -> Self {
+        let templates = content_registry
+            .iter()
+            .flat_map(|(route, representations)| {
+                representations
+                    .values()
+                    .filter_map(move |content| match content {
+                        RegisteredContent::RegisteredTemplate(template)
+                            if template.language() == TemplateLanguage::Handlebars =>
+                        {
+                            Some((template.name_in_registry(), route))
+                        }
+                        _ => None,
+                    })
+            })
+            .collect::<HashMap<_, _>>();
+
+        let mut graph = DependencyGraph::default();
+        for (name, route) in &templates {
+            let template = match handlebars_registry.get_template(name) {
+                Some(template) => template,
+                None => continue,
+            };
+            let mut references = Vec::new();
+            collect_references(template, &mut references);
+            for reference in references {
+                let dependency = match reference {
+                    Reference::Partial(name) => templates.get(name.as_str()).copied().cloned(),
+                    Reference::Route(route) => Route::parse_without_dot_segments(&route).ok(),
+                };
+                if let Some(dependency) = dependency {
+                    graph.add((*route).clone(), dependency);
+                }
+            }
+        }
+        graph
+    }
+
+    /// Records that rendering `route` includes `dependency`.
+    pub fn add(&mut self, route: Route, dependency: Route) {
+        if route != dependency {
+            self.dependencies
+                .entry(route)
+                .or_default()
+                .insert(dependency);
+        }
+    }
+
+    /// Routes which `route` includes directly.
+    pub fn dependencies(&self, route: &Route) -> impl Iterator<Item = &Route> {
+        self.dependencies.get(route).into_iter().flatten()
+    }
+
+    /// Every route and the routes it includes directly, in order.
+    pub fn iter(&self) -> impl Iterator<Item = (&Route, &BTreeSet<Route>)> {
+        self.dependencies.iter()
+    }
+
+    /// `changed_routes` along with every route that includes any of them,
+    /// directly or indirectly. These are the routes whose output could be
+    /// different after the changed routes change.
+    pub fn affected_routes<'a>(
+        &self,
+        changed_routes: impl IntoIterator<Item = &'a Route>,
+    ) -> BTreeSet<Route> {
+        let mut affected = changed_routes.into_iter().cloned().collect::<BTreeSet<_>>();
+        loop {
+            let newly_affected = self
+                .dependencies
+                .iter()
+                .filter(|(route, dependencies)| {
+                    !affected.contains(*route)
+                        && dependencies
+                            .iter()
+                            .any(|dependency| affected.contains(dependency))
+                })
+                .map(|(route, _)| route.clone())
+                .collect::<Vec<_>>();
+            if newly_affected.is_empty() {
+                return affected;
+            }
+            affected.extend(newly_affected);
+        }
+    }
+
+    /// The graph in [Graphviz DOT](https://graphviz.org/doc/info/lang.html)
+    /// format, with an edge from each route to each route it includes.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph routes {\n");
+        for (route, dependencies) in &self.dependencies {
+            for dependency in dependencies {
+                dot.push_str(&format!(
+                    "  {:?} -> {:?};\n",
+                    route.as_ref(),
+                    dependency.as_ref()
+                ));
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+enum Reference {
+    /// A partial, by its name in the handlebars registry.
+    Partial(String),
+    /// A route passed as a literal to a helper which renders other content.
+    Route(String),
+}
+
+/// Helpers whose first parameter is a route that they render.
+const ROUTE_HELPERS: [&str; 2] = ["get", "layout"];
+
+fn collect_references(template: &Template, references: &mut Vec<Reference>) {
+    for element in &template.elements {
+        match element {
+            TemplateElement::Expression(helper)
+            | TemplateElement::HtmlExpression(helper)
+            | TemplateElement::HelperBlock(helper) => collect_helper_references(helper, references),
+            TemplateElement::PartialExpression(partial)
+            | TemplateElement::PartialBlock(partial) => {
+                let name = match &partial.name {
+                    Parameter::Literal(serde_json::Value::String(name)) => Some(name.as_str()),
+                    name => name.as_name(),
+                };
+                references.extend(name.map(|name| Reference::Partial(name.to_string())));
+                if let Some(template) = &partial.template {
+                    collect_references(template, references);
+                }
+            }
+            TemplateElement::DecoratorExpression(decorator)
+            | TemplateElement::DecoratorBlock(decorator) => {
+                if let Some(template) = &decorator.template {
+                    collect_references(template, references);
+                }
+            }
+            TemplateElement::RawString(_) | TemplateElement::Comment(_) => {}
+        }
+    }
+}
+
+fn collect_helper_references(helper: &HelperTemplate, references: &mut Vec<Reference>) {
+    if let Some(name) = helper.name.as_name() {
+        if ROUTE_HELPERS.contains(&name) {
+            if let Some(Parameter::Literal(serde_json::Value::String(route))) =
+                helper.params.first()
+            {
+                references.push(Reference::Route(route.clone()));
+            }
+        }
+    }
+    for parameter in helper.params.iter().chain(helper.hash.values()) {
+        if let Parameter::Subexpression(subexpression) = parameter {
+            if let TemplateElement::Expression(helper) = subexpression.as_element() {
+                collect_helper_references(helper, references);
+            }
+        }
+    }
+    for template in helper.template.iter().chain(&helper.inverse) {
+        collect_references(template, references);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_lib::*;
+    use test_log::test;
+
+    #[test]
+    #[cfg_attr(not(feature = "templating"), ignore = "uses templates")]
+    fn templates_are_scanned_for_dependencies() {
+        let root = tempfile::tempdir().expect("Failed to create temporary directory");
+        std::fs::write(
+            root.path().join("page.html.hbs"),
+            "{{#layout \"/_layout\"}}{{> _widget.html.hbs}}{{#if x}}{{get \"/footer\"}}{{/if}}{{/layout}}",
+        )
+        .unwrap();
+        std::fs::write(root.path().join("_layout.html.hbs"), "{{> content}}").unwrap();
+        std::fs::write(root.path().join("_widget.html.hbs"), "{{get \"/icon\"}}").unwrap();
+        std::fs::write(root.path().join("footer.html"), "footer").unwrap();
+        std::fs::write(root.path().join("icon.html"), "icon").unwrap();
+        let directory = ContentDirectory::from_root(&root.path()).unwrap();
+        let shared_content_engine =
+            FilesystemBasedContentEngine::from_content_directory(directory, ())
+                .expect("Content engine could not be created");
+        let graph = shared_content_engine.read().unwrap().dependency_graph();
+
+        assert_eq!(
+            graph.dependencies(&route("/page")).collect::<Vec<_>>(),
+            vec![&route("/_layout"), &route("/_widget"), &route("/footer")]
+        );
+        assert_eq!(
+            graph.dependencies(&route("/_widget")).collect::<Vec<_>>(),
+            vec![&route("/icon")]
+        );
+        assert_eq!(graph.dependencies(&route("/_layout")).count(), 0);
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "templating"), ignore = "uses templates")]
+    fn computed_routes_are_recorded_while_rendering() {
+        let root = tempfile::tempdir().expect("Failed to create temporary directory");
+        std::fs::write(
+            root.path().join("page.html.hbs"),
+            "{{get (route-join \"/\" \"footer\")}}",
+        )
+        .unwrap();
+        std::fs::write(root.path().join("footer.html"), "footer").unwrap();
+        let directory = ContentDirectory::from_root(&root.path()).unwrap();
+        let shared_content_engine =
+            FilesystemBasedContentEngine::from_content_directory(directory, ())
+                .expect("Content engine could not be created");
+        let content_engine = shared_content_engine.read().unwrap();
+        assert_eq!(
+            content_engine
+                .dependency_graph()
+                .dependencies(&route("/page"))
+                .count(),
+            0
+        );
+
+        let content = content_engine.get(&route("/page")).unwrap();
+        let context = content_engine.render_context(
+            Some(route("/page")),
+            QueryParameters::default(),
+            HashMap::new(),
+        );
+        let (rendered, _) = trace(&route("/page"), || {
+            content.render(context, &[::mime::TEXT_HTML])
+        });
+        assert!(rendered.is_ok());
+
+        assert_eq!(
+            content_engine
+                .dependency_graph()
+                .dependencies(&route("/page"))
+                .collect::<Vec<_>>(),
+            vec![&route("/footer")]
+        );
+    }
+
+    #[test]
+    fn changes_affect_routes_which_include_them() {
+        let mut graph = DependencyGraph::default();
+        graph.add(route("/page"), route("/widget"));
+        graph.add(route("/widget"), route("/icon"));
+        graph.add(route("/other"), route("/footer"));
+
+        assert_eq!(
+            graph.affected_routes(&[route("/icon")]),
+            BTreeSet::from([route("/icon"), route("/widget"), route("/page")])
+        );
+        assert_eq!(
+            graph.affected_routes(&[route("/page")]),
+            BTreeSet::from([route("/page")])
+        );
+        assert_eq!(
+            graph.to_dot(),
+            "digraph routes {\n  \"/other\" -> \"/footer\";\n  \"/page\" -> \"/widget\";\n  \
+            \"/widget\" -> \"/icon\";\n}\n"
+        );
+    }
+}
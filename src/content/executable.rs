@@ -0,0 +1,1536 @@
+//! Content which is rendered by running a program. This module is only built
+//! with the `executables` cargo feature; without it, executable files in a
+//! content directory fail to load.
+
+use super::body::{ExecutableBody, InMemoryBody, ProcessBody, RecordingBody, SendfileBody};
+use super::content_item::RenderingFailedError;
+use super::process_group::{self, ProcessGroup};
+use super::*;
+use crate::config::{ExecutablesConfig, FixturesConfig, FixturesMode};
+use std::env;
+use std::fs;
+use std::io;
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Set for executables which are being run to check that they work (see
+/// [`Executable::probe`]) rather than to render content.
+const VALIDATION_ENVIRONMENT_VARIABLE: &str = "OPERATOR_VALIDATE";
+
+/// How long executables may take to exit in validation mode.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Replaced with an executable's working directory in its sandbox command.
+const WORKING_DIRECTORY_PLACEHOLDER: &str = "{working-directory}";
+
+/// Replaces `{route}` and `{query:name}` in an executable argument with values
+/// from the request. Anything else in braces is left alone.
+fn fill_in_placeholders(argument: &str, request: &RequestData) -> String {
+    let mut filled_in = String::with_capacity(argument.len());
+    let mut remaining = argument;
+    while let Some(start) = remaining.find('{') {
+        filled_in.push_str(&remaining[..start]);
+        let placeholder = &remaining[start..];
+        let end = match placeholder.find('}') {
+            Some(end) => end,
+            None => {
+                remaining = placeholder;
+                break;
+            }
+        };
+        let name = &placeholder[1..end];
+        match name.strip_prefix("query:") {
+            Some(query_parameter) => filled_in.push_str(
+                request
+                    .query_parameters
+                    .get(query_parameter)
+                    .map(String::as_str)
+                    .unwrap_or_default(),
+            ),
+            None if name == "route" => {
+                if let Some(route) = &request.route {
+                    filled_in.push_str(route.as_ref());
+                }
+            }
+            None => filled_in.push_str(&placeholder[..=end]),
+        }
+        remaining = &placeholder[end + 1..];
+    }
+    filled_in.push_str(remaining);
+    filled_in
+}
+
+/// The 64-bit FNV-1a hash. Unlike the standard library's hashers, its output
+/// is the same on every platform and Rust version, so it can name files.
+struct Fnv1a(u64);
+impl Default for Fnv1a {
+    fn default() -> Self {
+        Fnv1a(0xcbf2_9ce4_8422_2325)
+    }
+}
+impl Fnv1a {
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= u64::from(*byte);
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+}
+
+/// Sorted query parameters, followed by the value of each of the cache's
+/// `vary` headers.
+type OutputCacheKey = (Vec<(String, Vec<String>)>, Vec<Option<String>>);
+
+/// Request headers which identify a user. Output for requests with these is
+/// not cached unless they are among the cache's `vary` headers.
+const PERSONALIZING_HEADERS: [&str; 2] = ["authorization", "cookie"];
+
+/// Executable output which is reused until it is older than `ttl`, keyed by
+/// the request's query parameters and `vary` headers. After that it can still
+/// be served for up to `stale_while_revalidate` while fresh output is
+/// rendered in the background. At most `max_entries` outputs are kept; the
+/// oldest is dropped to make room for new ones.
+#[derive(Clone)]
+struct OutputCache {
+    ttl: Duration,
+    stale_while_revalidate: Duration,
+    max_entries: usize,
+    /// Lowercase names of the request headers which the output depends on.
+    vary: Vec<String>,
+    entries: Arc<Mutex<HashMap<OutputCacheKey, CachedOutput>>>,
+}
+
+struct CachedOutput {
+    created: Instant,
+    output: Bytes,
+    refreshing: bool,
+}
+
+enum CacheLookup {
+    Miss,
+    Hit(Bytes),
+    /// The output is stale and the caller is responsible for refreshing it
+    /// (see [`OutputCache::finish_refresh`]).
+    HitNeedingRefresh(Bytes),
+}
+
+impl OutputCache {
+    fn new(
+        ttl: Duration,
+        stale_while_revalidate: Duration,
+        vary: Vec<String>,
+        max_entries: usize,
+    ) -> Self {
+        OutputCache {
+            ttl,
+            stale_while_revalidate,
+            max_entries,
+            vary: vary
+                .into_iter()
+                .map(|header_name| header_name.to_ascii_lowercase())
+                .collect(),
+            entries: Arc::default(),
+        }
+    }
+
+    /// The key for output rendered for `request`, or `None` if the output
+    /// should not be cached because it may be personalized.
+    fn key(&self, request: &RequestData) -> Option<OutputCacheKey> {
+        let header = |name: &str| {
+            request
+                .request_headers
+                .iter()
+                .find(|(header_name, _)| header_name.eq_ignore_ascii_case(name))
+                .map(|(_, value)| value.clone())
+        };
+        let is_personalized = PERSONALIZING_HEADERS
+            .iter()
+            .any(|name| header(name).is_some() && !self.vary.iter().any(|vary| vary == name));
+        if is_personalized {
+            return None;
+        }
+
+        let mut query_parameters = request
+            .query_parameter_lists
+            .iter()
+            .map(|(name, values)| (name.clone(), values.clone()))
+            .collect::<Vec<_>>();
+        query_parameters.sort();
+        let headers = self.vary.iter().map(|name| header(name)).collect();
+        Some((query_parameters, headers))
+    }
+
+    fn is_usable(&self, entry: &CachedOutput) -> bool {
+        entry.created.elapsed() < self.ttl + self.stale_while_revalidate
+    }
+
+    fn lookup(&self, key: &OutputCacheKey) -> CacheLookup {
+        let mut entries = self.lock();
+        match entries.get_mut(key) {
+            Some(entry) if entry.created.elapsed() < self.ttl => {
+                CacheLookup::Hit(entry.output.clone())
+            }
+            Some(entry) if self.is_usable(entry) => {
+                if entry.refreshing {
+                    CacheLookup::Hit(entry.output.clone())
+                } else {
+                    entry.refreshing = true;
+                    CacheLookup::HitNeedingRefresh(entry.output.clone())
+                }
+            }
+            _ => CacheLookup::Miss,
+        }
+    }
+
+    fn insert(&self, key: OutputCacheKey, output: Bytes) {
+        let mut entries = self.lock();
+        // Drop expired entries so that the cache does not grow without bound
+        // as different query parameters come and go.
+        entries.retain(|_, entry| self.is_usable(entry));
+        // Different query parameters can still add entries faster than they
+        // expire, so there is also a cap on how many are kept.
+        while !entries.contains_key(&key) && entries.len() >= self.max_entries {
+            let oldest_key = match entries.iter().min_by_key(|(_, entry)| entry.created) {
+                Some((oldest_key, _)) => oldest_key.clone(),
+                None => return,
+            };
+            entries.remove(&oldest_key);
+        }
+        entries.insert(
+            key,
+            CachedOutput {
+                created: Instant::now(),
+                output,
+                refreshing: false,
+            },
+        );
+    }
+
+    /// Stores refreshed output, or allows another refresh to be attempted if
+    /// this one failed.
+    fn finish_refresh(&self, key: OutputCacheKey, output: Option<Bytes>) {
+        match output {
+            Some(output) => self.insert(key, output),
+            None => {
+                if let Some(entry) = self.lock().get_mut(&key) {
+                    entry.refreshing = false;
+                }
+            }
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<OutputCacheKey, CachedOutput>> {
+        self.entries
+            .lock()
+            .expect(bug_message!("Executable output cache lock was poisoned."))
+    }
+}
+
+/// A program that can be run by the operating system, e.g. a shell script.
+///
+/// If the executed program terminates with a nonzero exit code, rendering
+/// output is the contents of standard output. Otherwise a rendering failure
+/// occurs.
+///
+/// Render data is available as JSON in the OPERATOR_RENDER_DATA environment
+/// variable. Other environment variables are controlled by
+/// [`ExecutablesConfig`].
+///
+/// Output can optionally be cached (see [`Executable::with_cache`]).
+pub struct Executable {
+    program: String,
+    working_directory: PathBuf,
+    output_media_type: MediaType,
+    interpreter: Option<String>,
+    arguments: Vec<String>,
+    config: Arc<ExecutablesConfig>,
+    cache: Option<OutputCache>,
+    output_limit: Option<u64>,
+    route: Option<Route>,
+    sendfile_roots: Option<Vec<PathBuf>>,
+}
+impl Executable {
+    pub fn new<P: AsRef<str>, W: AsRef<Path>>(
+        program: P,
+        working_directory: W,
+        output_media_type: MediaType,
+    ) -> Self {
+        Executable {
+            program: String::from(program.as_ref()),
+            working_directory: PathBuf::from(working_directory.as_ref()),
+            output_media_type,
+            interpreter: None,
+            arguments: Vec::new(),
+            config: Arc::default(),
+            cache: None,
+            output_limit: None,
+            route: None,
+            sendfile_roots: None,
+        }
+    }
+
+    /// The route of the executable, which identifies it in logs.
+    pub fn with_route(self, route: Route) -> Self {
+        Executable {
+            route: Some(route),
+            ..self
+        }
+    }
+
+    /// Passes `arguments` to the program, after filling in placeholders (see
+    /// [`ExecutablesConfig::arguments`]).
+    pub fn with_arguments(self, arguments: Vec<String>) -> Self {
+        Executable { arguments, ..self }
+    }
+
+    /// Runs the program via `interpreter` (a command which is given the path
+    /// of the program as its last argument) instead of executing it directly.
+    pub fn with_interpreter(self, interpreter: Option<String>) -> Self {
+        Executable {
+            interpreter,
+            ..self
+        }
+    }
+
+    pub fn with_config(self, config: Arc<ExecutablesConfig>) -> Self {
+        Executable { config, ..self }
+    }
+
+    /// Reuses successful output for up to `ttl` instead of running the
+    /// program again. Output is cached separately for each distinct set of
+    /// query parameters and values of the `vary` request headers (render
+    /// data is otherwise assumed to not affect it). Output for requests with
+    /// an `Authorization` or `Cookie` header is never cached unless that
+    /// header is in `vary`.
+    ///
+    /// Once output is older than `ttl` it is still used for up to
+    /// `stale_while_revalidate` longer, but the program is also run in the
+    /// background to replace it. No more than `max_entries` outputs are kept
+    /// at once, and the oldest is dropped first.
+    pub fn with_cache(
+        self,
+        ttl: Option<Duration>,
+        stale_while_revalidate: Duration,
+        vary: Vec<String>,
+        max_entries: usize,
+    ) -> Self {
+        Executable {
+            cache: ttl.map(|ttl| OutputCache::new(ttl, stale_while_revalidate, vary, max_entries)),
+            ..self
+        }
+    }
+
+    /// Whether output is cached (see [`Executable::with_cache`]).
+    pub fn caches_output(&self) -> bool {
+        self.cache.is_some()
+    }
+
+    /// Lets the program respond with `X-Sendfile: <path>` to send a file
+    /// instead of its output (see [`ExecutablesConfig::sendfile`]). Files must
+    /// be within one of `roots`, and relative paths are resolved against the
+    /// first of them. `None` disables this.
+    pub fn with_sendfile_roots(self, sendfile_roots: Option<Vec<PathBuf>>) -> Self {
+        Executable {
+            sendfile_roots,
+            ..self
+        }
+    }
+
+    /// Kills the program if it writes more than `output_limit` bytes to
+    /// standard output, which fails the render.
+    pub fn with_output_limit(self, output_limit: Option<u64>) -> Self {
+        Executable {
+            output_limit,
+            ..self
+        }
+    }
+
+    pub(super) fn render_to_native_media_type<ServerInfo>(
+        &self,
+        render_data: RenderData<ServerInfo>,
+        additional_data: Option<serde_json::Value>,
+    ) -> Result<Media<ExecutableBody>, RenderingFailedError>
+    where
+        ServerInfo: Clone + Serialize,
+    {
+        self.render_with_cache(render_data, additional_data, true)
+    }
+
+    /// Runs the program even if there is cached output for this render, and
+    /// caches its new output instead.
+    pub(super) fn render_refreshing_cache<ServerInfo>(
+        &self,
+        render_data: RenderData<ServerInfo>,
+        additional_data: Option<serde_json::Value>,
+    ) -> Result<Media<ExecutableBody>, RenderingFailedError>
+    where
+        ServerInfo: Clone + Serialize,
+    {
+        self.render_with_cache(render_data, additional_data, false)
+    }
+
+    fn render_with_cache<ServerInfo>(
+        &self,
+        render_data: RenderData<ServerInfo>,
+        additional_data: Option<serde_json::Value>,
+        use_cached_output: bool,
+    ) -> Result<Media<ExecutableBody>, RenderingFailedError>
+    where
+        ServerInfo: Clone + Serialize,
+    {
+        // Recordings are made (and replayed) for every render, so they skip
+        // the output cache.
+        let fixture_path = match &self.config.fixtures {
+            None => None,
+            Some(fixtures) => {
+                let fixture_path =
+                    self.fixture_path(fixtures, render_data.clone(), additional_data.clone())?;
+                if fixtures.mode == FixturesMode::Replay {
+                    return self.replay(&fixture_path);
+                }
+                Some(fixture_path)
+            }
+        };
+
+        let cache = match (&self.cache, &fixture_path) {
+            (Some(cache), None) => cache
+                .key(&render_data.request)
+                .map(|cache_key| (cache.clone(), cache_key)),
+            _ => None,
+        };
+        let cached_output = match &cache {
+            Some(_) if !use_cached_output => None,
+            None => None,
+            Some((cache, cache_key)) => match cache.lookup(cache_key) {
+                CacheLookup::Miss => None,
+                CacheLookup::Hit(output) => Some(output),
+                CacheLookup::HitNeedingRefresh(output) => {
+                    self.refresh_in_background(
+                        cache_key.clone(),
+                        render_data.clone(),
+                        additional_data.clone(),
+                    );
+                    Some(output)
+                }
+            },
+        };
+        if let Some(cached_output) = cached_output {
+            return Ok(Media::new(
+                self.output_media_type.clone(),
+                self.with_sendfile_body(ExecutableBody::Cached(InMemoryBody(cached_output))),
+            ));
+        }
+
+        let child = self
+            .command(render_data, additional_data)?
+            .spawn()
+            .map_err(|io_error| RenderingFailedError::ExecutableError {
+                message: format!("Unable to execute program: {}", io_error),
+                program: self.program.clone(),
+                working_directory: self.working_directory.clone(),
+            })?;
+
+        let name = match &self.route {
+            Some(route) => route.to_string(),
+            None => self.program.clone(),
+        };
+        let body = ProcessBody::new(child, &name)
+            .with_output_limit(self.output_limit)
+            .with_chunk_sizes(self.config.chunk_size, self.config.max_chunk_size)
+            .with_spill_buffer(
+                self.config.output_buffer_bytes,
+                self.config.output_spill_bytes,
+            );
+        Ok(Media::new(
+            self.output_media_type.clone(),
+            self.with_sendfile_body(match (cache, fixture_path) {
+                (_, Some(fixture_path)) => {
+                    ExecutableBody::Caching(RecordingBody::new(body, move |output| {
+                        Self::record(&fixture_path, output)
+                    }))
+                }
+                (None, None) => ExecutableBody::Running(body),
+                (Some((cache, cache_key)), None) => {
+                    ExecutableBody::Caching(RecordingBody::new(body, move |output| {
+                        cache.insert(cache_key, output)
+                    }))
+                }
+            }),
+        ))
+    }
+
+    /// Where output for this render is recorded in `fixtures`. The file name
+    /// is a hash of everything about the render which could affect the
+    /// output, in a directory named after the executable's route.
+    fn fixture_path<ServerInfo>(
+        &self,
+        fixtures: &FixturesConfig,
+        render_data: RenderData<ServerInfo>,
+        additional_data: Option<serde_json::Value>,
+    ) -> Result<PathBuf, RenderingFailedError>
+    where
+        ServerInfo: Clone + Serialize,
+    {
+        let request = render_data.request.clone();
+        let name = match &self.route {
+            Some(route) => String::from(route.as_ref().trim_start_matches('/')),
+            None => Path::new(&self.program)
+                .file_name()
+                .map(|file_name| file_name.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+        };
+        let mut hash = Fnv1a::default();
+        hash.write(name.as_bytes());
+        for argument in &self.arguments {
+            hash.write(b"\0");
+            hash.write(fill_in_placeholders(argument, &request).as_bytes());
+        }
+        // Parsing sorts objects by key, so that maps with an unspecified
+        // order (like request headers) always hash the same way.
+        let render_data = serde_json::from_str::<serde_json::Value>(
+            &self.render_data_json(render_data, additional_data)?,
+        )?;
+        hash.write(b"\0");
+        hash.write(render_data.to_string().as_bytes());
+        Ok(name
+            .split('/')
+            .fold(fixtures.directory.clone(), |path, component| {
+                path.join(component)
+            })
+            .join(format!("{:016x}", hash.0)))
+    }
+
+    /// Output which was recorded at `fixture_path`, in place of running the
+    /// program.
+    fn replay(&self, fixture_path: &Path) -> Result<Media<ExecutableBody>, RenderingFailedError> {
+        let output =
+            fs::read(fixture_path).map_err(|error| RenderingFailedError::ExecutableError {
+                message: format!(
+                    "No output was recorded for this render at '{}': {}",
+                    fixture_path.display(),
+                    error
+                ),
+                program: self.program.clone(),
+                working_directory: self.working_directory.clone(),
+            })?;
+        Ok(Media::new(
+            self.output_media_type.clone(),
+            self.with_sendfile_body(ExecutableBody::Cached(InMemoryBody(Bytes::from(output)))),
+        ))
+    }
+
+    fn record(fixture_path: &Path, output: Bytes) {
+        let result = match fixture_path.parent() {
+            Some(directory) => fs::create_dir_all(directory),
+            None => Ok(()),
+        }
+        .and_then(|()| fs::write(fixture_path, output));
+        if let Err(error) = result {
+            log::warn!(
+                "Unable to record executable output to '{}': {}",
+                fixture_path.display(),
+                error
+            );
+        }
+    }
+
+    /// Wraps `body` so that `X-Sendfile` lines are handled, if they are
+    /// allowed for this executable.
+    fn with_sendfile_body(&self, body: ExecutableBody) -> ExecutableBody {
+        let roots = match &self.sendfile_roots {
+            None => return body,
+            Some(roots) => roots.clone(),
+        };
+        ExecutableBody::Sendfile(Box::new(SendfileBody::new(
+            body,
+            Box::new(move |path| {
+                let failed = |message: &str| StreamError::SendfileFailed {
+                    path: String::from(path),
+                    message: String::from(message),
+                };
+                let resolved_path = match roots.first() {
+                    Some(base) => base.join(path),
+                    None => PathBuf::from(path),
+                };
+                let canonical_path = resolved_path
+                    .canonicalize()
+                    .map_err(|error| failed(&error.to_string()))?;
+                let is_allowed = roots
+                    .iter()
+                    .filter_map(|root| root.canonicalize().ok())
+                    .any(|root| canonical_path.starts_with(root));
+                if !is_allowed {
+                    return Err(failed(
+                        "The path is not within the content directory or `sendfile-paths`",
+                    ));
+                }
+                if !canonical_path.is_file() {
+                    return Err(failed("The path is not a file"));
+                }
+                fs::File::open(&canonical_path).map_err(|error| failed(&error.to_string()))
+            }),
+        )))
+    }
+
+    /// Runs the program to completion on another thread and stores its
+    /// output in the cache. Like the foreground path, the program is killed
+    /// as soon as its output exceeds the output limit.
+    fn refresh_in_background<ServerInfo>(
+        &self,
+        cache_key: OutputCacheKey,
+        render_data: RenderData<ServerInfo>,
+        additional_data: Option<serde_json::Value>,
+    ) where
+        ServerInfo: Clone + Serialize,
+    {
+        let cache = match &self.cache {
+            None => return,
+            Some(cache) => cache.clone(),
+        };
+        let program = self.program.clone();
+        let working_directory = self.working_directory.clone();
+        let command = self.command(render_data, additional_data);
+        let output_limit = self.output_limit;
+        thread::spawn(move || {
+            let output = command.and_then(|mut command| {
+                let child =
+                    command
+                        .spawn()
+                        .map_err(|io_error| RenderingFailedError::ExecutableError {
+                            message: format!("Unable to execute program: {}", io_error),
+                            program: program.clone(),
+                            working_directory,
+                        })?;
+                Ok(ProcessGroup::new(child).wait_with_output(output_limit)?)
+            });
+            match output {
+                Ok(None) => {
+                    log::warn!(
+                        "Refreshing cached output of '{}' failed: Output exceeded the limit of {} \
+                        bytes",
+                        program,
+                        output_limit.unwrap_or_default(),
+                    );
+                    cache.finish_refresh(cache_key, None)
+                }
+                Ok(Some(output)) if output.status.success() => {
+                    cache.finish_refresh(cache_key, Some(Bytes::from(output.stdout)))
+                }
+                Ok(Some(output)) => {
+                    log::warn!(
+                        "Refreshing cached output of '{}' failed: {}",
+                        program,
+                        String::from_utf8_lossy(&output.stderr),
+                    );
+                    cache.finish_refresh(cache_key, None)
+                }
+                Err(error) => {
+                    log::warn!(
+                        "Refreshing cached output of '{}' failed: {}",
+                        program,
+                        error
+                    );
+                    cache.finish_refresh(cache_key, None)
+                }
+            }
+        });
+    }
+
+    /// Runs the program once with `OPERATOR_VALIDATE=1` in its environment
+    /// to find out whether it can be executed at all (e.g. that its
+    /// interpreter exists). Programs should check for this variable and exit
+    /// successfully without doing any real work. Programs which are still
+    /// running after [`PROBE_TIMEOUT`] are killed and fail the probe.
+    pub(super) fn probe<ServerInfo>(
+        &self,
+        render_data: RenderData<ServerInfo>,
+    ) -> Result<(), RenderingFailedError>
+    where
+        ServerInfo: Clone + Serialize,
+    {
+        let failed = |message: String| RenderingFailedError::ExecutableError {
+            message,
+            program: self.program.clone(),
+            working_directory: self.working_directory.clone(),
+        };
+
+        let mut command = self.command(render_data, None)?;
+        command
+            .env(VALIDATION_ENVIRONMENT_VARIABLE, "1")
+            .stdout(Stdio::null());
+        let mut group = ProcessGroup::new(
+            command
+                .spawn()
+                .map_err(|io_error| failed(format!("Unable to execute program: {}", io_error)))?,
+        );
+
+        // Read stderr concurrently so that the program cannot block on a
+        // full pipe.
+        let stderr_reader = group.child().stderr.take().map(|mut stderr| {
+            thread::spawn(move || {
+                let mut stderr_contents = Vec::new();
+                let _ = io::Read::read_to_end(&mut stderr, &mut stderr_contents);
+                stderr_contents
+            })
+        });
+
+        let deadline = Instant::now() + PROBE_TIMEOUT;
+        let status = loop {
+            match group.try_wait()? {
+                Some(status) => break status,
+                None if Instant::now() >= deadline => {
+                    group.kill();
+                    return Err(failed(format!(
+                        "Program was still running after {} seconds in validation mode",
+                        PROBE_TIMEOUT.as_secs()
+                    )));
+                }
+                None => thread::sleep(Duration::from_millis(10)),
+            }
+        };
+
+        if status.success() {
+            Ok(())
+        } else {
+            let stderr_contents = stderr_reader
+                .and_then(|stderr_reader| stderr_reader.join().ok())
+                .unwrap_or_default();
+            Err(failed(format!(
+                "Program exited with {} in validation mode: {}",
+                status,
+                String::from_utf8_lossy(&stderr_contents).trim(),
+            )))
+        }
+    }
+
+    fn command<ServerInfo>(
+        &self,
+        render_data: RenderData<ServerInfo>,
+        additional_data: Option<serde_json::Value>,
+    ) -> Result<Command, RenderingFailedError>
+    where
+        ServerInfo: Clone + Serialize,
+    {
+        let request = render_data.request.clone();
+        let render_data_environment_variable_value =
+            self.render_data_json(render_data, additional_data)?;
+
+        let working_directory = self.working_directory.to_string_lossy();
+        let mut command_line = self
+            .config
+            .sandbox
+            .iter()
+            .map(|argument| argument.replace(WORKING_DIRECTORY_PLACEHOLDER, &working_directory))
+            .collect::<Vec<_>>();
+        if let Some(interpreter) = &self.interpreter {
+            command_line.extend(interpreter.split_whitespace().map(String::from));
+        }
+        command_line.push(self.program.clone());
+        command_line.extend(
+            self.arguments
+                .iter()
+                .map(|argument| fill_in_placeholders(argument, &request)),
+        );
+
+        let mut command = Command::new(&command_line[0]);
+        command.args(&command_line[1..]);
+        process_group::isolate(&mut command);
+        self.drop_privileges(&mut command)?;
+        if self.config.clear_env {
+            command.env_clear();
+            for name in &self.config.pass_env {
+                if let Some(value) = env::var_os(name) {
+                    command.env(name, value);
+                }
+            }
+        }
+        command
+            .envs(&self.config.env)
+            .current_dir(self.working_directory.clone())
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .env(
+                "OPERATOR_RENDER_DATA",
+                render_data_environment_variable_value,
+            );
+        Ok(command)
+    }
+
+    /// Render data as JSON, as it is given to the program.
+    fn render_data_json<ServerInfo>(
+        &self,
+        render_data: RenderData<ServerInfo>,
+        additional_data: Option<serde_json::Value>,
+    ) -> Result<String, RenderingFailedError>
+    where
+        ServerInfo: Clone + Serialize,
+    {
+        let base_render_data = RenderData {
+            target_media_type: Some(self.output_media_type.clone()),
+            front_matter: None,
+            ..render_data
+        };
+
+        Ok(match additional_data {
+            None => serde_json::ser::to_string(&base_render_data)?,
+            Some(serde_json::Value::Object(mut additional_data_as_json_map)) => {
+                // merge additional data atop base render data
+                let base_render_data_as_json = serde_json::value::to_value(base_render_data)?;
+                if let serde_json::Value::Object(mut base_render_data_as_json_map) =
+                    base_render_data_as_json
+                {
+                    for (key, value) in additional_data_as_json_map.iter_mut() {
+                        base_render_data_as_json_map.insert(key.to_string(), mem::take(value));
+                    }
+                    serde_json::Value::Object(base_render_data_as_json_map).to_string()
+                } else {
+                    return Err(RenderingFailedError::Bug(format!(
+                        "Render data did not serialize to a JSON object, instead got `{}`.",
+                        base_render_data_as_json
+                    )));
+                }
+            }
+            Some(non_object_additional_data) => non_object_additional_data.to_string(),
+        })
+    }
+
+    #[cfg(unix)]
+    fn drop_privileges(&self, command: &mut Command) -> Result<(), RenderingFailedError> {
+        use std::os::unix::process::CommandExt;
+        if let Some(gid) = self.config.gid {
+            command.gid(gid);
+        }
+        if let Some(uid) = self.config.uid {
+            command.uid(uid);
+        }
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    fn drop_privileges(&self, _: &mut Command) -> Result<(), RenderingFailedError> {
+        if self.config.uid.is_some() || self.config.gid.is_some() {
+            Err(RenderingFailedError::ExecutableError {
+                message: String::from(
+                    "Running executables as another user is only supported on unix.",
+                ),
+                program: self.program.clone(),
+                working_directory: self.working_directory.clone(),
+            })
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_lib::*;
+    use super::*;
+    use crate::content::content_index::ContentIndexEntries;
+    use crate::test_lib::*;
+    use ::mime;
+    use maplit::hashmap;
+    use std::str;
+    use test_log::test;
+
+    fn test_render_data() -> RenderData<crate::ServerInfo> {
+        RenderData {
+            server_info: crate::ServerInfo::without_socket_address()
+                .expect("Unable to create ServerInfo"),
+            index: ContentIndex::Directory(ContentIndexEntries::new()),
+            target_media_type: None,
+            site: serde_json::Map::new(),
+            data: serde_json::Map::new(),
+            front_matter: None,
+            error_code: None,
+            request: RequestData::new(None, QueryParameters::default(), hashmap![]),
+        }
+    }
+
+    #[test]
+    fn executables_execute_when_rendered() {
+        let path = format!("{}/src", PROJECT_DIRECTORY);
+        let working_directory =
+            fs::canonicalize(path).expect("Could not canonicalize path for test");
+        let executable = Executable::new(
+            "pwd",
+            working_directory.clone(),
+            MediaType::from_media_range(mime::TEXT_PLAIN).unwrap(),
+        );
+        let output = executable
+            .render_to_native_media_type(test_render_data(), None)
+            .expect("Executable failed but it should have succeeded");
+
+        assert_eq!(
+            media_to_string(output),
+            format!("{}\n", working_directory.display())
+        );
+    }
+
+    #[test]
+    fn executable_environment_can_be_configured() {
+        std::env::set_var("OPERATOR_TEST_INHERITED", "inherited");
+        std::env::set_var("OPERATOR_TEST_NOT_INHERITED", "not inherited");
+        let executable = Executable::new(
+            "/usr/bin/env",
+            PROJECT_DIRECTORY,
+            MediaType::from_media_range(mime::TEXT_PLAIN).unwrap(),
+        )
+        .with_config(Arc::new(ExecutablesConfig {
+            clear_env: true,
+            pass_env: vec![String::from("OPERATOR_TEST_INHERITED")],
+            env: [(String::from("OPERATOR_TEST_SET"), String::from("set"))]
+                .into_iter()
+                .collect(),
+            ..ExecutablesConfig::default()
+        }));
+        let output = executable
+            .render_to_native_media_type(test_render_data(), None)
+            .expect("Executable failed but it should have succeeded");
+
+        let mut variable_names = media_to_string(output)
+            .lines()
+            .filter_map(|line| line.split_once('=').map(|(name, _)| String::from(name)))
+            .collect::<Vec<_>>();
+        variable_names.sort();
+        assert_eq!(
+            variable_names,
+            vec![
+                "OPERATOR_RENDER_DATA",
+                "OPERATOR_TEST_INHERITED",
+                "OPERATOR_TEST_SET"
+            ],
+        );
+    }
+
+    #[test]
+    fn executables_can_run_within_a_sandbox_command() {
+        let executable = Executable::new(
+            "pwd",
+            PROJECT_DIRECTORY,
+            MediaType::from_media_range(mime::TEXT_PLAIN).unwrap(),
+        )
+        .with_config(Arc::new(ExecutablesConfig {
+            sandbox: vec![
+                String::from("sh"),
+                String::from("-c"),
+                String::from("echo \"$0 in {working-directory}\""),
+            ],
+            ..ExecutablesConfig::default()
+        }));
+        let output = executable
+            .render_to_native_media_type(test_render_data(), None)
+            .expect("Executable failed but it should have succeeded");
+
+        assert_eq!(
+            media_to_string(output),
+            format!("pwd in {}\n", PROJECT_DIRECTORY)
+        );
+    }
+
+    #[test]
+    fn executables_can_be_given_arguments() {
+        let executable = Executable::new(
+            "echo",
+            PROJECT_DIRECTORY,
+            MediaType::from_media_range(mime::TEXT_PLAIN).unwrap(),
+        )
+        .with_arguments(vec![
+            String::from("{route}"),
+            String::from("--city={query:city}"),
+            String::from("--"),
+            String::from("{query:missing}"),
+            String::from("{unknown} {unterminated"),
+        ]);
+        let render_data = RenderData {
+            request: RequestData::new(
+                Some(route("/weather")),
+                QueryParameters::from(hashmap![String::from("city") => String::from("Paris")]),
+                hashmap![],
+            ),
+            ..test_render_data()
+        };
+        let output = executable
+            .render_to_native_media_type(render_data, None)
+            .expect("Executable failed but it should have succeeded");
+
+        assert_eq!(
+            media_to_string(output),
+            "/weather --city=Paris --  {unknown} {unterminated\n"
+        );
+    }
+
+    #[test]
+    fn executable_output_can_be_cached() {
+        let render_with_query = |executable: &Executable, query: &str| {
+            let render_data = RenderData {
+                request: RequestData::new(
+                    Some(route("/now")),
+                    QueryParameters::from(hashmap![String::from("q") => String::from(query)]),
+                    hashmap![],
+                ),
+                ..test_render_data()
+            };
+            media_to_string(
+                executable
+                    .render_to_native_media_type(render_data, None)
+                    .expect("Executable failed but it should have succeeded"),
+            )
+        };
+        let new_executable = || {
+            Executable::new(
+                "date",
+                PROJECT_DIRECTORY,
+                MediaType::from_media_range(mime::TEXT_PLAIN).unwrap(),
+            )
+            .with_arguments(vec![String::from("+%s%N")])
+        };
+
+        let cached = new_executable().with_cache(
+            Some(Duration::from_secs(600)),
+            Duration::ZERO,
+            vec![],
+            100,
+        );
+        let first_output = render_with_query(&cached, "a");
+        assert_eq!(render_with_query(&cached, "a"), first_output);
+        assert_ne!(render_with_query(&cached, "b"), first_output);
+
+        let expired =
+            new_executable().with_cache(Some(Duration::ZERO), Duration::ZERO, vec![], 100);
+        let first_output = render_with_query(&expired, "a");
+        assert_ne!(render_with_query(&expired, "a"), first_output);
+    }
+
+    #[test]
+    fn oldest_cached_executable_output_is_dropped_when_the_cache_is_full() {
+        let cache = OutputCache::new(Duration::from_secs(600), Duration::ZERO, vec![], 2);
+        let key = |value: &str| {
+            (
+                vec![(String::from("q"), vec![String::from(value)])],
+                Vec::new(),
+            )
+        };
+        let is_cached = |value| matches!(cache.lookup(&key(value)), CacheLookup::Hit(_));
+
+        cache.insert(key("a"), Bytes::from("a"));
+        cache.insert(key("b"), Bytes::from("b"));
+        // Replacing an entry does not make room.
+        cache.insert(key("a"), Bytes::from("a"));
+        assert!(is_cached("a") && is_cached("b"));
+
+        cache.insert(key("c"), Bytes::from("c"));
+        assert!(!is_cached("b"), "The oldest output was not dropped");
+        assert!(is_cached("a") && is_cached("c"));
+    }
+
+    #[test]
+    fn cached_executable_output_depends_on_vary_headers() {
+        let render_with_headers = |executable: &Executable, headers: &[(&str, &str)]| {
+            let render_data = RenderData {
+                request: RequestData::new(
+                    Some(route("/now")),
+                    QueryParameters::default(),
+                    headers
+                        .iter()
+                        .map(|(name, value)| (String::from(*name), String::from(*value)))
+                        .collect(),
+                ),
+                ..test_render_data()
+            };
+            media_to_string(
+                executable
+                    .render_to_native_media_type(render_data, None)
+                    .expect("Executable failed but it should have succeeded"),
+            )
+        };
+        let new_executable = |vary: &[&str]| {
+            Executable::new(
+                "date",
+                PROJECT_DIRECTORY,
+                MediaType::from_media_range(mime::TEXT_PLAIN).unwrap(),
+            )
+            .with_arguments(vec![String::from("+%s%N")])
+            .with_cache(
+                Some(Duration::from_secs(600)),
+                Duration::ZERO,
+                vary.iter().map(|name| String::from(*name)).collect(),
+                100,
+            )
+        };
+
+        let cached = new_executable(&["Accept-Language"]);
+        let english = render_with_headers(&cached, &[("accept-language", "en")]);
+        assert_eq!(
+            render_with_headers(&cached, &[("accept-language", "en"), ("dnt", "1")]),
+            english
+        );
+        assert_ne!(
+            render_with_headers(&cached, &[("accept-language", "fr")]),
+            english
+        );
+
+        // Requests which identify a user are not cached.
+        let with_cookie = render_with_headers(&cached, &[("cookie", "session=1")]);
+        assert_ne!(
+            render_with_headers(&cached, &[("cookie", "session=1")]),
+            with_cookie
+        );
+        let authorized = render_with_headers(&cached, &[("authorization", "Bearer a")]);
+        assert_ne!(
+            render_with_headers(&cached, &[("authorization", "Bearer a")]),
+            authorized
+        );
+
+        // Unless the output is cached per user.
+        let cached_per_session = new_executable(&["cookie"]);
+        let session_1 = render_with_headers(&cached_per_session, &[("cookie", "session=1")]);
+        assert_eq!(
+            render_with_headers(&cached_per_session, &[("cookie", "session=1")]),
+            session_1
+        );
+        assert_ne!(
+            render_with_headers(&cached_per_session, &[("cookie", "session=2")]),
+            session_1
+        );
+    }
+
+    #[test]
+    fn executable_output_can_be_recorded_and_replayed() {
+        let fixtures_directory = tempfile::tempdir().expect("Failed to create temporary directory");
+        let new_executable = |mode: FixturesMode| {
+            Executable::new(
+                "date",
+                PROJECT_DIRECTORY,
+                MediaType::from_media_range(mime::TEXT_PLAIN).unwrap(),
+            )
+            .with_route(route("/clock/now"))
+            .with_arguments(vec![String::from("+%s%N")])
+            .with_config(Arc::new(ExecutablesConfig {
+                fixtures: Some(FixturesConfig {
+                    mode,
+                    directory: fixtures_directory.path().to_path_buf(),
+                }),
+                ..ExecutablesConfig::default()
+            }))
+        };
+        let render_with_query = |executable: &Executable, query: &str| {
+            let render_data = RenderData {
+                request: RequestData::new(
+                    Some(route("/clock/now")),
+                    QueryParameters::from(hashmap![String::from("q") => String::from(query)]),
+                    hashmap![],
+                ),
+                ..test_render_data()
+            };
+            executable
+                .render_to_native_media_type(render_data, None)
+                .map(media_to_string)
+        };
+
+        let recorded = render_with_query(&new_executable(FixturesMode::Record), "a")
+            .expect("Executable failed but it should have succeeded");
+        assert!(fixtures_directory.path().join("clock").join("now").is_dir());
+
+        let replaying = new_executable(FixturesMode::Replay);
+        assert_eq!(
+            render_with_query(&replaying, "a").expect("Replaying failed"),
+            recorded
+        );
+        match render_with_query(&replaying, "b") {
+            Err(RenderingFailedError::ExecutableError { message, .. }) => {
+                assert!(message.starts_with("No output was recorded"), "{}", message)
+            }
+            result => panic!(
+                "Replaying did not fail as expected: {:?}",
+                result.map(|_| ())
+            ),
+        }
+    }
+
+    #[test]
+    fn stale_executable_output_is_refreshed_in_the_background() {
+        let executable = Executable::new(
+            "date",
+            PROJECT_DIRECTORY,
+            MediaType::from_media_range(mime::TEXT_PLAIN).unwrap(),
+        )
+        .with_arguments(vec![String::from("+%s%N")])
+        .with_cache(Some(Duration::ZERO), Duration::from_secs(600), vec![], 100);
+        let render = || {
+            media_to_string(
+                executable
+                    .render_to_native_media_type(test_render_data(), None)
+                    .expect("Executable failed but it should have succeeded"),
+            )
+        };
+
+        let first_output = render();
+        // This output is stale, but it is served anyway.
+        assert_eq!(render(), first_output);
+
+        let mut attempts = 0;
+        while render() == first_output {
+            attempts += 1;
+            assert!(attempts < 100, "Stale output was never refreshed");
+            thread::sleep(Duration::from_millis(20));
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn background_refreshes_are_killed_if_their_output_is_too_large() {
+        let directory = tempfile::tempdir().expect("Failed to create temporary directory");
+        // The first run is small enough to be cached, but the refresh writes
+        // too much and then hangs.
+        let script = "if [ -e ran ]; then echo $$ > pid; head -c 2000 /dev/zero; sleep 60; \
+            else touch ran; echo first; fi";
+        let executable = Executable::new(
+            "sh",
+            directory.path(),
+            MediaType::from_media_range(mime::TEXT_PLAIN).unwrap(),
+        )
+        .with_arguments(vec![String::from("-c"), String::from(script)])
+        .with_output_limit(Some(1000))
+        .with_cache(Some(Duration::ZERO), Duration::from_secs(600), vec![], 100);
+        let render = || {
+            media_to_string(
+                executable
+                    .render_to_native_media_type(test_render_data(), None)
+                    .expect("Executable failed but it should have succeeded"),
+            )
+        };
+
+        assert_eq!(render(), "first\n");
+        // This serves the stale output and starts a refresh.
+        assert_eq!(render(), "first\n");
+
+        let pid_path = directory.path().join("pid");
+        let started_at = Instant::now();
+        let is_running = |process_id: &str| {
+            fs::read_to_string(format!("/proc/{}/stat", process_id)).is_ok_and(|stat| {
+                stat.rsplit_once(')')
+                    .is_some_and(|(_, fields)| !fields.trim_start().starts_with('Z'))
+            })
+        };
+        loop {
+            assert!(
+                started_at.elapsed() < Duration::from_secs(10),
+                "The refresh was not killed"
+            );
+            match fs::read_to_string(&pid_path) {
+                Ok(process_id) if process_id.ends_with('\n') && !is_running(process_id.trim()) => {
+                    break
+                }
+                _ => thread::sleep(Duration::from_millis(10)),
+            }
+        }
+        assert_eq!(render(), "first\n");
+    }
+
+    #[test]
+    fn executables_are_killed_if_their_output_is_too_large() {
+        let new_executable = |program| {
+            Executable::new(
+                program,
+                PROJECT_DIRECTORY,
+                MediaType::from_media_range(mime::TEXT_PLAIN).unwrap(),
+            )
+            .with_output_limit(Some(1000))
+        };
+
+        let output = new_executable("yes")
+            .render_to_native_media_type(test_render_data(), None)
+            .expect("Executable failed but it should have succeeded");
+        match block_on_content(output) {
+            Err(StreamError::ExecutableOutputTooLarge { limit, .. }) => assert_eq!(limit, 1000),
+            Err(error) => panic!("Got a different error than expected: {}", error),
+            Ok(_) => panic!("Expected an error"),
+        }
+
+        let output = new_executable("pwd")
+            .render_to_native_media_type(test_render_data(), None)
+            .expect("Executable failed but it should have succeeded");
+        assert!(block_on_content(output).is_ok());
+    }
+
+    #[test]
+    fn executable_output_is_read_in_growing_chunks() {
+        let executable = Executable::new(
+            "sh",
+            PROJECT_DIRECTORY,
+            MediaType::from_media_range(mime::TEXT_PLAIN).unwrap(),
+        )
+        .with_arguments(vec![
+            String::from("-c"),
+            String::from("head -c 100000 /dev/zero"),
+        ])
+        .with_config(Arc::new(ExecutablesConfig {
+            chunk_size: 16,
+            max_chunk_size: 1024,
+            ..ExecutablesConfig::default()
+        }));
+
+        let output = executable
+            .render_to_native_media_type(test_render_data(), None)
+            .expect("Executable failed but it should have succeeded");
+        let chunk_lengths = chunk_lengths(output);
+        assert_eq!(chunk_lengths.iter().sum::<usize>(), 100000);
+        assert!(chunk_lengths.iter().all(|length| *length <= 1024));
+        assert!(
+            chunk_lengths.iter().any(|length| *length > 16),
+            "The read buffer never grew"
+        );
+    }
+
+    #[test]
+    fn executables_can_finish_before_their_output_is_read() {
+        let root = tempfile::tempdir().expect("Failed to create temporary directory");
+        let finished_path = root.path().join("finished");
+        let executable = Executable::new(
+            "sh",
+            root.path(),
+            MediaType::from_media_range(mime::TEXT_PLAIN).unwrap(),
+        )
+        .with_arguments(vec![
+            String::from("-c"),
+            // This is more than fits in a pipe.
+            String::from("head -c 1000000 /dev/zero && touch finished"),
+        ])
+        .with_config(Arc::new(ExecutablesConfig {
+            output_buffer_bytes: 1000,
+            output_spill_bytes: 1_000_000,
+            ..ExecutablesConfig::default()
+        }));
+
+        let output = executable
+            .render_to_native_media_type(test_render_data(), None)
+            .expect("Executable failed but it should have succeeded");
+        let started_at = Instant::now();
+        while !finished_path.exists() {
+            assert!(
+                started_at.elapsed() < Duration::from_secs(10),
+                "The executable was held up by its unread output"
+            );
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(
+            block_on_content(output)
+                .expect("There was an error in the content stream")
+                .len(),
+            1_000_000
+        );
+    }
+
+    #[test]
+    fn cancelled_renders_stop_their_executables() {
+        let executable = Executable::new(
+            "sh",
+            PROJECT_DIRECTORY,
+            MediaType::from_media_range(mime::TEXT_PLAIN).unwrap(),
+        )
+        .with_arguments(vec![String::from("-c"), String::from("sleep 60")]);
+
+        let cancellation = Cancellation::new();
+        let output = cancellation
+            .scope(|| executable.render_to_native_media_type(test_render_data(), None))
+            .expect("Executable failed but it should have succeeded");
+        cancellation.cancel();
+
+        let started_at = Instant::now();
+        assert!(
+            block_on_content(output).is_err(),
+            "Content stream succeeded but it should have been cancelled"
+        );
+        assert!(started_at.elapsed() < Duration::from_secs(10));
+    }
+
+    #[test]
+    fn executables_can_send_files() {
+        let root = tempfile::tempdir().expect("Failed to create temporary directory");
+        let outside = tempfile::tempdir().expect("Failed to create temporary directory");
+        fs::write(root.path().join("allowed.txt"), "allowed file").unwrap();
+        fs::write(outside.path().join("secret.txt"), "secret file").unwrap();
+        let secret_path = outside.path().join("secret.txt");
+        let secret_path = secret_path.to_str().unwrap();
+
+        let new_executable = |script: &str, sendfile_roots: Option<Vec<PathBuf>>| {
+            Executable::new(
+                "sh",
+                root.path(),
+                MediaType::from_media_range(mime::TEXT_PLAIN).unwrap(),
+            )
+            .with_arguments(vec![String::from("-c"), String::from(script)])
+            .with_sendfile_roots(sendfile_roots)
+        };
+        let render = |script: &str, sendfile_roots: Option<Vec<PathBuf>>| {
+            let output = new_executable(script, sendfile_roots)
+                .render_to_native_media_type(test_render_data(), None)
+                .expect("Executable failed but it should have succeeded");
+            block_on_content(output).map(|bytes| String::from_utf8(bytes.to_vec()).unwrap())
+        };
+        let content_root = Some(vec![root.path().to_path_buf()]);
+
+        assert_eq!(
+            render(
+                "echo 'X-Sendfile: allowed.txt'; echo ignored",
+                content_root.clone()
+            )
+            .unwrap(),
+            "allowed file",
+        );
+        assert_eq!(
+            render("echo 'X-Sendfile: allowed.txt'", None).unwrap(),
+            "X-Sendfile: allowed.txt\n",
+        );
+        assert_eq!(
+            render(
+                "printf X-; sleep 0.1; printf Send; echo ing",
+                content_root.clone()
+            )
+            .unwrap(),
+            "X-Sending\n",
+        );
+        assert_eq!(
+            render("echo hello", content_root.clone()).unwrap(),
+            "hello\n"
+        );
+        for script in [
+            format!("echo 'X-Sendfile: {}'", secret_path),
+            String::from("echo 'X-Sendfile: ../secret.txt'"),
+            String::from("echo 'X-Sendfile: missing.txt'"),
+            String::from("echo 'X-Sendfile: .'"),
+        ] {
+            match render(&script, content_root.clone()) {
+                Err(StreamError::SendfileFailed { .. }) => {}
+                Err(error) => panic!("Got a different error than expected: {}", error),
+                Ok(output) => panic!("`{}` succeeded with {:?}", script, output),
+            }
+        }
+        assert_eq!(
+            render(
+                &format!("echo 'X-Sendfile: {}'", secret_path),
+                Some(vec![
+                    root.path().to_path_buf(),
+                    outside.path().to_path_buf()
+                ]),
+            )
+            .unwrap(),
+            "secret file",
+        );
+    }
+
+    #[test]
+    fn executables_can_write_lots_to_stderr() {
+        let new_executable = |script: &str| {
+            Executable::new(
+                "sh",
+                PROJECT_DIRECTORY,
+                MediaType::from_media_range(mime::TEXT_PLAIN).unwrap(),
+            )
+            .with_arguments(vec![String::from("-c"), String::from(script)])
+        };
+
+        // This is more than fits in a pipe buffer, so if stderr was not read
+        // until the end the process would never finish.
+        let output = new_executable("seq 1 100000 >&2; echo done")
+            .render_to_native_media_type(test_render_data(), None)
+            .expect("Executable failed but it should have succeeded");
+        assert_eq!(media_to_string(output), "done\n");
+
+        let output = new_executable("seq 1 100000 >&2; exit 3")
+            .render_to_native_media_type(test_render_data(), None)
+            .expect("Executable failed but it should have succeeded");
+        match block_on_content(output) {
+            Err(StreamError::ExecutableExitedWithNonzero {
+                exit_code,
+                stderr_contents: Some(stderr_contents),
+                ..
+            }) => {
+                assert_eq!(exit_code, Some(3));
+                assert!(stderr_contents.len() <= 4096);
+                assert!(stderr_contents.ends_with("99999\n100000\n"));
+            }
+            Err(error) => panic!("Got a different error than expected: {}", error),
+            Ok(_) => panic!("Expected an error"),
+        }
+    }
+
+    #[test]
+    fn executables_require_working_directory_that_exists() {
+        let working_directory = "/hopefully/this/path/does/not/actually/exist/on/your/system";
+        let executable = Executable::new(
+            "pwd",
+            working_directory,
+            MediaType::from_media_range(mime::TEXT_PLAIN).unwrap(),
+        );
+        let result = executable.render_to_native_media_type(test_render_data(), None);
+        assert!(
+            result.is_err(),
+            "Executable succeeded but it should have failed"
+        );
+    }
+
+    #[test]
+    fn executables_emit_stream_error_if_exit_code_is_not_zero() {
+        let path = format!("{}/src", PROJECT_DIRECTORY);
+        let working_directory =
+            fs::canonicalize(path).expect("Could not canonicalize path for test");
+
+        // Exits with 1 and prints nothing to stdout.
+        {
+            let executable = Executable::new(
+                "false",
+                working_directory.clone(),
+                MediaType::from_media_range(mime::TEXT_PLAIN).unwrap(),
+            );
+            let output = executable
+                .render_to_native_media_type(test_render_data(), None)
+                .expect("Executable failed but it should have succeeded");
+
+            match block_on_content(output) {
+                Err(StreamError::ExecutableExitedWithNonzero {
+                    exit_code,
+                    stderr_contents,
+                    ..
+                }) => {
+                    assert_eq!(exit_code, Some(1));
+                    assert_eq!(stderr_contents, None);
+                }
+                Err(_) => panic!("Got a different error than expected"),
+                Ok(_) => panic!("Expected an error"),
+            }
+        }
+
+        // Exits with nonzero and prints a message to stdout.
+        {
+            let executable = Executable::new(
+                "mv",
+                working_directory.clone(),
+                MediaType::from_media_range(mime::TEXT_PLAIN).unwrap(),
+            );
+            let output = executable
+                .render_to_native_media_type(test_render_data(), None)
+                .expect("Executable failed but it should have succeeded");
+
+            match block_on_content(output) {
+                Err(StreamError::ExecutableExitedWithNonzero {
+                    exit_code,
+                    stderr_contents,
+                    ..
+                }) => {
+                    assert!(exit_code.is_some() && exit_code != Some(0));
+                    assert!(stderr_contents.is_some());
+                    assert!(!stderr_contents.unwrap().is_empty());
+                }
+                Err(_) => panic!("Got a different error than expected"),
+                Ok(_) => panic!("Expected an error"),
+            }
+        }
+    }
+}
@@ -0,0 +1,536 @@
+use super::Route;
+use crate::config::{
+    deserialize_front_matter_arguments, deserialize_status,
+    interpolate_public_environment_variables, EntityTagStrength, EnvironmentVariableError,
+    TaskConfig,
+};
+use serde::{Deserialize, Deserializer};
+use std::io::{self, Read};
+use std::time::SystemTime;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum FrontMatterError {
+    #[error("Front matter is never closed (it must end with a `---` line).")]
+    Unterminated,
+
+    #[error("Front matter is not valid YAML: {}", .source)]
+    InvalidYaml { source: serde_yaml::Error },
+
+    #[error("Front matter must be a mapping, but it was `{}`.", .0)]
+    NotAMapping(serde_json::Value),
+
+    #[error("Front matter has invalid settings: {}", .source)]
+    InvalidSettings { source: serde_json::Error },
+
+    #[error("Front matter is invalid: {}", .source)]
+    EnvironmentVariable {
+        #[from]
+        source: EnvironmentVariableError,
+    },
+
+    #[error("Front matter could not be read: {}", .source)]
+    Unreadable { source: io::Error },
+}
+
+/// Data and settings for an individual template, written as YAML at the very
+/// beginning of its file between lines containing only `---`. For example:
+///
+/// ```text
+/// ---
+/// title: Hello
+/// strict: false
+/// ---
+/// <h1>{{front-matter.title}}</h1>
+/// ```
+///
+/// All properties are available to the template under `front-matter`. Some
+/// properties are also settings which affect how Operator treats the template
+/// (see [`FrontMatterSettings`]). String values can refer to environment
+/// variables named like `OPERATOR_PUBLIC_*` as `${env:NAME}`, which are
+/// resolved when the template is loaded. Other variables are not allowed,
+/// since front matter is visible to templates.
+///
+/// Templates which begin with a `---` line that is not followed by a YAML
+/// mapping and a closing `---` line (like a Markdown document that starts
+/// with a horizontal rule) have no front matter, and are left as they are.
+#[derive(Clone, Debug, Default)]
+pub struct FrontMatter {
+    data: serde_json::Map<String, serde_json::Value>,
+    settings: FrontMatterSettings,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct FrontMatterSettings {
+    /// Overrides whether rendering fails when the template references missing
+    /// data.
+    pub strict: Option<bool>,
+
+    /// Values to use for render data which would otherwise be missing. This
+    /// is merged beneath the render data, so nested properties can be
+    /// defaulted individually.
+    pub defaults: Option<serde_json::Value>,
+
+    /// Render this template when the server starts, before it accepts any
+    /// requests, to fill the output caches of executables that it `get`s
+    /// (see the `--prerender` option of `operator serve`).
+    pub prerender: bool,
+
+    /// Runs this template on a schedule while the server is running, like
+    /// the `tasks` in the config (which take precedence).
+    pub task: Option<TaskConfig>,
+
+    /// Drafts are not served (or rendered by `operator render-all`) unless
+    /// `--include-drafts` is used.
+    pub draft: bool,
+
+    /// Before this time the template is treated like a draft. This can be a
+    /// date (`2024-05-01`) or a date and time (`2024-05-01T09:30:00`), and is
+    /// interpreted as UTC.
+    #[serde(deserialize_with = "deserialize_publish_date")]
+    pub publish_date: Option<SystemTime>,
+
+    /// Overrides the `cache-control` setting for the template's route.
+    pub cache_control: Option<String>,
+
+    /// Overrides the `expires` setting for the template's route.
+    pub expires: Option<u64>,
+
+    /// Overrides the `surrogate-control` setting for the template's route.
+    pub surrogate_control: Option<String>,
+
+    /// Overrides the `etag` setting for the template's route.
+    pub etag: Option<EntityTagStrength>,
+
+    /// Overrides the `fallback` setting for the template's route.
+    pub fallback: Option<Route>,
+
+    /// Overrides the `status` setting for the template's route.
+    #[serde(deserialize_with = "deserialize_status")]
+    pub status: Option<u16>,
+}
+
+impl FrontMatterSettings {
+    /// Whether the template is ready to be served at time `now`.
+    pub fn is_published(&self, now: SystemTime) -> bool {
+        !self.draft
+            && self
+                .publish_date
+                .is_none_or(|publish_date| publish_date <= now)
+    }
+}
+
+fn deserialize_publish_date<'de, D>(deserializer: D) -> Result<Option<SystemTime>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let publish_date = String::deserialize(deserializer)?;
+    // Dates without times mean midnight.
+    let publish_date = if publish_date.contains(['T', 't', ' ']) {
+        publish_date
+    } else {
+        format!("{}T00:00:00Z", publish_date)
+    };
+    humantime::parse_rfc3339_weak(&publish_date)
+        .map(Some)
+        .map_err(|error| {
+            serde::de::Error::custom(format!(
+                "`publish-date` must be a date like `2024-05-01` or a date and time like \
+                `2024-05-01T09:30:00`, but it was `{}` ({})",
+                publish_date, error,
+            ))
+        })
+}
+
+impl FrontMatter {
+    const DELIMITER: &'static str = "---";
+
+    /// Separates front matter from the rest of a template's source. The
+    /// returned body starts on line `body_line_offset + 1` of `source`.
+    pub fn split(source: &str) -> Result<(FrontMatter, &str, usize), FrontMatterError> {
+        let no_front_matter = Ok((FrontMatter::default(), source, 0));
+        let rest = match source.strip_prefix(Self::DELIMITER).and_then(|rest| {
+            rest.strip_prefix("\r\n")
+                .or_else(|| rest.strip_prefix('\n'))
+        }) {
+            Some(rest) => rest,
+            None => return no_front_matter,
+        };
+
+        let mut yaml_length = 0;
+        for (index, line) in rest.split_inclusive('\n').enumerate() {
+            if line.trim_end_matches(['\r', '\n']) == Self::DELIMITER {
+                let front_matter = match Self::from_yaml(&rest[..yaml_length]) {
+                    // Whatever is between the delimiters is not front matter
+                    // after all, just content which happens to look like it.
+                    Err(
+                        FrontMatterError::InvalidYaml { .. } | FrontMatterError::NotAMapping(_),
+                    ) => return no_front_matter,
+                    result => result?,
+                };
+                let body = &rest[yaml_length + line.len()..];
+                // The opening delimiter, the YAML, and the closing delimiter.
+                let body_line_offset = index + 2;
+                return Ok((front_matter, body, body_line_offset));
+            }
+            yaml_length += line.len();
+        }
+        no_front_matter
+    }
+
+    fn from_yaml(yaml: &str) -> Result<FrontMatter, FrontMatterError> {
+        let data = yaml_mapping(yaml)?;
+        let settings = FrontMatterSettings::deserialize(serde_json::Value::Object(data.clone()))
+            .map_err(|source| FrontMatterError::InvalidSettings { source })?;
+        Ok(FrontMatter { data, settings })
+    }
+
+    pub fn data(&self) -> &serde_json::Map<String, serde_json::Value> {
+        &self.data
+    }
+
+    pub fn settings(&self) -> &FrontMatterSettings {
+        &self.settings
+    }
+}
+
+/// Settings for an executable. Executables cannot begin with `---`, so their
+/// front matter is written in comments at the top of the file (after the
+/// shebang line, if there is one). It opens with a `---operator` line, so
+/// that other comments which happen to look like YAML are not mistaken for
+/// it, and closes with a `---` line. Each line of it starts with the same
+/// comment marker as the opening line. For example:
+///
+/// ```text
+/// #!/bin/sh
+/// # ---operator
+/// # arguments: ["--city={query:city}"]
+/// # ---
+/// curl "https://weather.example/${1#--city=}"
+/// ```
+///
+/// Unlike the front matter of templates, only these settings are allowed.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct ExecutableFrontMatter {
+    /// Overrides the `arguments` setting for the executable's route.
+    #[serde(deserialize_with = "deserialize_front_matter_arguments")]
+    pub arguments: Option<Vec<String>>,
+
+    /// Overrides the `cache-ttl` setting for the executable's route.
+    pub cache_ttl: Option<u64>,
+
+    /// Overrides the `stale-while-revalidate` setting for the executable's
+    /// route.
+    pub stale_while_revalidate: Option<u64>,
+}
+
+impl ExecutableFrontMatter {
+    const OPENING_DELIMITER: &'static str = "---operator";
+
+    /// Front matter has to begin on the first or second line, so only the
+    /// start of the file is read (which also avoids reading large binaries).
+    const MAX_LENGTH: u64 = 64 * 1024;
+
+    /// Reads front matter from the start of an executable file, which is
+    /// empty if there is none.
+    pub fn read<R: Read>(executable: R) -> Result<Self, FrontMatterError> {
+        let mut start = Vec::new();
+        executable
+            .take(Self::MAX_LENGTH)
+            .read_to_end(&mut start)
+            .map_err(|source| FrontMatterError::Unreadable { source })?;
+        Self::parse(&String::from_utf8_lossy(&start))
+    }
+
+    fn parse(source: &str) -> Result<Self, FrontMatterError> {
+        let mut lines = source.lines().peekable();
+        if lines.peek().is_some_and(|line| line.starts_with("#!")) {
+            lines.next();
+        }
+        // The opening line is a comment marker followed by `---operator`,
+        // like `# ---operator` or `// ---operator`.
+        let marker = match lines
+            .next()
+            .and_then(|line| line.trim_end().split_once(' '))
+        {
+            Some((marker, rest))
+                if !marker.is_empty() && rest.trim_start() == Self::OPENING_DELIMITER =>
+            {
+                marker
+            }
+            _ => return Ok(ExecutableFrontMatter::default()),
+        };
+
+        let mut yaml = String::new();
+        for line in lines {
+            let line = match line.strip_prefix(marker) {
+                Some(line) => line.strip_prefix(' ').unwrap_or(line),
+                None => break,
+            };
+            if line.trim_end() == FrontMatter::DELIMITER {
+                let data = yaml_mapping(&yaml)?;
+                return ExecutableFrontMatter::deserialize(serde_json::Value::Object(data))
+                    .map_err(|source| FrontMatterError::InvalidSettings { source });
+            }
+            yaml.push_str(line);
+            yaml.push('\n');
+        }
+        Err(FrontMatterError::Unterminated)
+    }
+}
+
+/// Parses YAML front matter (which must be a mapping), resolving environment
+/// variables.
+fn yaml_mapping(
+    yaml: &str,
+) -> Result<serde_json::Map<String, serde_json::Value>, FrontMatterError> {
+    let value = if yaml.trim().is_empty() {
+        serde_json::Value::Object(serde_json::Map::new())
+    } else {
+        serde_yaml::from_str::<serde_json::Value>(yaml)
+            .map_err(|source| FrontMatterError::InvalidYaml { source })?
+    };
+    let mut data = match value {
+        serde_json::Value::Object(data) => data,
+        value => return Err(FrontMatterError::NotAMapping(value)),
+    };
+    for value in data.values_mut() {
+        interpolate_json_value(value)?;
+    }
+    Ok(data)
+}
+
+/// Resolves `${env:NAME}` environment variable references in string values.
+fn interpolate_json_value(value: &mut serde_json::Value) -> Result<(), EnvironmentVariableError> {
+    match value {
+        serde_json::Value::String(string) => {
+            *string = interpolate_public_environment_variables(string)?
+        }
+        serde_json::Value::Array(values) => {
+            for value in values {
+                interpolate_json_value(value)?;
+            }
+        }
+        serde_json::Value::Object(object) => {
+            for value in object.values_mut() {
+                interpolate_json_value(value)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Recursively copies properties from `defaults` into `target` where `target`
+/// does not already have them.
+pub fn fill_missing(target: &mut serde_json::Value, defaults: &serde_json::Value) {
+    if let (serde_json::Value::Object(target), serde_json::Value::Object(defaults)) =
+        (target, defaults)
+    {
+        for (key, default) in defaults {
+            match target.get_mut(key) {
+                Some(existing) => fill_missing(existing, default),
+                None => {
+                    target.insert(key.clone(), default.clone());
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use test_log::test;
+
+    #[test]
+    fn templates_without_front_matter_are_unchanged() {
+        for source in ["", "hello", "--- not front matter\n---\n", "\n---\n---\n"] {
+            let (front_matter, body, body_line_offset) =
+                FrontMatter::split(source).expect("Front matter was invalid");
+            assert!(front_matter.data().is_empty());
+            assert_eq!(body, source);
+            assert_eq!(body_line_offset, 0);
+        }
+    }
+
+    #[test]
+    fn front_matter_can_be_split_from_templates() {
+        let (front_matter, body, body_line_offset) =
+            FrontMatter::split("---\ntitle: Hi\nstrict: false\n---\nhello\n")
+                .expect("Front matter was invalid");
+        assert_eq!(
+            serde_json::Value::Object(front_matter.data().clone()),
+            json!({ "title": "Hi", "strict": false })
+        );
+        assert_eq!(front_matter.settings().strict, Some(false));
+        assert_eq!(body, "hello\n");
+        assert_eq!(body_line_offset, 4);
+
+        let (front_matter, body, _) =
+            FrontMatter::split("---\r\n---\r\nhello").expect("Front matter was invalid");
+        assert!(front_matter.data().is_empty());
+        assert_eq!(body, "hello");
+    }
+
+    #[test]
+    fn front_matter_can_refer_to_environment_variables() {
+        std::env::set_var("OPERATOR_PUBLIC_TEST_FRONT_MATTER_HOST", "example.com");
+        let (front_matter, _, _) = FrontMatter::split(
+            "---\nlinks: [\"https://${env:OPERATOR_PUBLIC_TEST_FRONT_MATTER_HOST}/\"]\nprice: ${amount}\n---\n",
+        )
+        .expect("Front matter was invalid");
+        assert_eq!(
+            serde_json::Value::Object(front_matter.data().clone()),
+            json!({ "links": ["https://example.com/"], "price": "${amount}" }),
+        );
+    }
+
+    #[test]
+    fn templates_starting_with_dashes_can_lack_front_matter() {
+        for source in [
+            "---\ntitle: Hi\n",
+            "---\n\nThe rest of the document.\n",
+            "---\nJust a sentence.\n---\n",
+            "---\n[not, a, mapping]\n---\n",
+            "---\n: : :\n---\n",
+            "----\ntitle: Hi\n----\n",
+        ] {
+            let (front_matter, body, body_line_offset) =
+                FrontMatter::split(source).expect("Front matter was invalid");
+            assert!(
+                front_matter.data().is_empty(),
+                "Front matter was found in `{}`",
+                source,
+            );
+            assert_eq!((body, body_line_offset), (source, 0));
+        }
+    }
+
+    #[test]
+    fn invalid_front_matter_is_an_error() {
+        for source in [
+            "---\nstrict: sometimes\n---\n",
+            "---\npublish-date: someday\n---\n",
+            "---\nstatus: 2000\n---\n",
+            "---\nstatus: 99\n---\n",
+            "---\napi-key: ${env:OPERATOR_PUBLIC_TEST_FRONT_MATTER_MISSING}\n---\n",
+            "---\napi-key: ${env:HOME}\n---\n",
+        ] {
+            assert!(
+                FrontMatter::split(source).is_err(),
+                "Front matter for `{}` was valid, but it should not have been",
+                source,
+            );
+        }
+    }
+
+    #[test]
+    fn drafts_and_future_publish_dates_are_unpublished() {
+        let publish_date = humantime::parse_rfc3339("2024-05-01T00:00:00Z").unwrap();
+        let day = std::time::Duration::from_secs(60 * 60 * 24);
+        for (source, published_before, published_after) in [
+            ("---\ntitle: Hi\n---\n", true, true),
+            ("---\ndraft: true\n---\n", false, false),
+            ("---\ndraft: false\n---\n", true, true),
+            ("---\npublish-date: 2024-05-01\n---\n", false, true),
+            ("---\npublish-date: 2024-05-01T00:00:00\n---\n", false, true),
+            (
+                "---\npublish-date: 2024-05-01T00:00:00Z\n---\n",
+                false,
+                true,
+            ),
+            (
+                "---\ndraft: true\npublish-date: 2024-05-01\n---\n",
+                false,
+                false,
+            ),
+        ] {
+            let (front_matter, ..) = FrontMatter::split(source).expect("Front matter was invalid");
+            assert_eq!(
+                (
+                    front_matter.settings().is_published(publish_date - day),
+                    front_matter.settings().is_published(publish_date),
+                ),
+                (published_before, published_after),
+                "Publication status was wrong for `{}`",
+                source,
+            );
+        }
+    }
+
+    #[test]
+    fn defaults_fill_in_missing_properties() {
+        let mut target = json!({ "a": 1, "b": { "c": 2 } });
+        fill_missing(
+            &mut target,
+            &json!({ "a": 100, "b": { "c": 200, "d": 300 }, "e": 400 }),
+        );
+        assert_eq!(
+            target,
+            json!({ "a": 1, "b": { "c": 2, "d": 300 }, "e": 400 })
+        );
+    }
+
+    #[test]
+    fn executables_can_have_front_matter_in_comments() {
+        let arguments = |source| {
+            ExecutableFrontMatter::parse(source)
+                .expect("Front matter was invalid")
+                .arguments
+        };
+        assert_eq!(
+            arguments(
+                "#!/bin/sh\n# ---operator\n# arguments: [\"-a\", \"{route}\"]\n# ---\necho\n"
+            ),
+            Some(vec![String::from("-a"), String::from("{route}")])
+        );
+        assert_eq!(
+            arguments("// ---operator\n// arguments:\n//   - one\n// ---\n"),
+            Some(vec![String::from("one")])
+        );
+        assert_eq!(
+            ExecutableFrontMatter::parse(
+                "# ---operator\n# cache-ttl: 60\n# stale-while-revalidate: 30\n# ---\n"
+            )
+            .expect("Front matter was invalid"),
+            ExecutableFrontMatter {
+                arguments: None,
+                cache_ttl: Some(60),
+                stale_while_revalidate: Some(30),
+            }
+        );
+        for source in [
+            "",
+            "#!/bin/sh\necho hi\n",
+            "#!/bin/sh\n# ----------\necho hi\n",
+            "#!/bin/sh\n# ---\n# arguments: [a]\n# ---\n",
+            "#!/bin/sh\n# ---\n# Just a comment: not front matter\necho hi\n",
+            "#!/bin/sh\n\n# ---operator\n# arguments: [a]\n# ---\n",
+            "\u{7f}ELF\u{2}\u{1}\u{1}\0\0",
+        ] {
+            assert_eq!(
+                arguments(source),
+                None,
+                "Front matter was found in `{}`",
+                source
+            );
+        }
+
+        for source in [
+            "#!/bin/sh\n# ---operator\n# arguments: [a]\necho\n",
+            "#!/bin/sh\n# ---operator\n# arguments: not-a-list\n# ---\n",
+            "#!/bin/sh\n# ---operator\n# title: Not for executables\n# ---\n",
+            "#!/bin/sh\n# ---operator\n# arguments: [\"{query:flag}\"]\n# ---\n",
+        ] {
+            assert!(
+                ExecutableFrontMatter::parse(source).is_err(),
+                "Front matter for `{}` was valid, but it should not have been",
+                source,
+            );
+        }
+    }
+}
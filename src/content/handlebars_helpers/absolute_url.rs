@@ -0,0 +1,79 @@
+use crate::content::*;
+use handlebars::{self, Handlebars};
+
+/// Turns a path into an absolute URL.
+///
+/// `{{absolute-url "/feed.xml"}}` prefixes the path with the `base-url`
+/// setting. If that is not set then the origin and path prefix of the HTTP
+/// request are used instead, as long as its host can be trusted (see
+/// `request.http.origin`). Otherwise it's an error.
+pub struct AbsoluteUrlHelper;
+
+impl handlebars::HelperDef for AbsoluteUrlHelper {
+    fn call_inner<'registry: 'context, 'context>(
+        &self,
+        helper: &handlebars::Helper<'context>,
+        _: &'registry Handlebars<'registry>,
+        handlebars_context: &'context handlebars::Context,
+        _: &mut handlebars::RenderContext<'registry, 'context>,
+    ) -> Result<handlebars::ScopedJson<'context>, handlebars::RenderError> {
+        let param_0 = helper
+            .param(0)
+            .ok_or_else(|| {
+                handlebars::RenderErrorReason::Other(String::from(
+                    "The `absolute-url` helper requires an argument (a path).",
+                ))
+            })?
+            .value();
+        let path = param_0
+            .as_str()
+            .filter(|path| path.starts_with('/'))
+            .ok_or_else(|| {
+                handlebars::RenderErrorReason::Other(format!(
+                    "The `absolute-url` helper's argument must be a string starting with a slash \
+                    (a path), but it was `{}`.",
+                    param_0,
+                ))
+            })?;
+
+        let base_url = base_url(handlebars_context.data()).ok_or_else(|| {
+            handlebars::RenderErrorReason::Other(format!(
+                "`absolute-url \"{}\"` failed because the base URL is unknown. Set `base-url` in \
+                the config file (or use `--base-url`).",
+                path,
+            ))
+        })?;
+
+        Ok(handlebars::ScopedJson::Derived(serde_json::Value::String(
+            format!("{}{}", base_url, path),
+        )))
+    }
+}
+
+/// The `base-url` setting, or else the trusted origin and path prefix of the
+/// HTTP request (if there is one), without a trailing slash.
+pub(super) fn base_url(render_data: &serde_json::Value) -> Option<String> {
+    let request_data = render_data.get(REQUEST_DATA_PROPERTY_NAME);
+    let http_request_data =
+        request_data.and_then(|request_data| request_data.get(HTTP_REQUEST_DATA_PROPERTY_NAME));
+    let http_request_string = |property_name| {
+        http_request_data
+            .and_then(|http_request_data| http_request_data.get(property_name))
+            .and_then(|value| value.as_str())
+    };
+
+    match (
+        request_data
+            .and_then(|request_data| request_data.get(BASE_URL_PROPERTY_NAME))
+            .and_then(|value| value.as_str()),
+        http_request_string(ORIGIN_PROPERTY_NAME),
+    ) {
+        (Some(base_url), _) => Some(String::from(base_url)),
+        (None, Some(origin)) => Some(format!(
+            "{}{}",
+            origin,
+            http_request_string(PATH_PREFIX_PROPERTY_NAME).unwrap_or_default()
+        )),
+        _ => None,
+    }
+}
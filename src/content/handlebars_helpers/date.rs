@@ -0,0 +1,135 @@
+use crate::clock::{Clock, TimeZone};
+use handlebars::{self, Handlebars};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Renders the current time.
+///
+/// `{{now}}` renders something like `2024-05-01T09:30:00+00:00`, and
+/// `{{now format="%Y"}}` renders just the year (see [`TimeZone::format`] for
+/// the specifiers). Times are shown in the configured time zone unless
+/// `timezone` is given (e.g. `{{now timezone="America/New_York"}}` or
+/// `{{now timezone="-05:00"}}`). The current time
+/// comes from the configured clock, which can be stopped for tests.
+pub struct NowHelper {
+    clock: Clock,
+    timezone: TimeZone,
+}
+
+impl NowHelper {
+    pub fn new(clock: Clock, timezone: TimeZone) -> Self {
+        NowHelper { clock, timezone }
+    }
+}
+
+impl handlebars::HelperDef for NowHelper {
+    fn call_inner<'registry: 'context, 'context>(
+        &self,
+        helper: &handlebars::Helper<'context>,
+        _: &'registry Handlebars<'registry>,
+        _: &'context handlebars::Context,
+        _: &mut handlebars::RenderContext<'registry, 'context>,
+    ) -> Result<handlebars::ScopedJson<'context>, handlebars::RenderError> {
+        format_time("now", helper, self.clock.now(), self.timezone)
+    }
+}
+
+/// Renders a date from render data (like a `publish-date` in front matter).
+///
+/// `{{format-date date format="%e %B %Y"}}` accepts dates like `2024-05-01`,
+/// timestamps like `2024-05-01T09:30:00Z`, or numbers of seconds since the
+/// Unix epoch. Dates and timestamps are read as UTC (so timestamps can end
+/// with `Z` but not another offset). `format` and `timezone` work the same
+/// way as for `now`.
+pub struct FormatDateHelper {
+    timezone: TimeZone,
+}
+
+impl FormatDateHelper {
+    pub fn new(timezone: TimeZone) -> Self {
+        FormatDateHelper { timezone }
+    }
+}
+
+impl handlebars::HelperDef for FormatDateHelper {
+    fn call_inner<'registry: 'context, 'context>(
+        &self,
+        helper: &handlebars::Helper<'context>,
+        _: &'registry Handlebars<'registry>,
+        _: &'context handlebars::Context,
+        _: &mut handlebars::RenderContext<'registry, 'context>,
+    ) -> Result<handlebars::ScopedJson<'context>, handlebars::RenderError> {
+        let param_0 = helper
+            .param(0)
+            .ok_or_else(|| {
+                handlebars::RenderErrorReason::Other(String::from(
+                    "The `format-date` helper requires an argument (a date).",
+                ))
+            })?
+            .value();
+        let time = parse_date(param_0).ok_or_else(|| {
+            handlebars::RenderErrorReason::Other(format!(
+                "The `format-date` helper's argument must be a date like `2024-05-01`, a \
+                timestamp like `2024-05-01T09:30:00Z`, or a number of seconds since the Unix \
+                epoch, but it was `{}`.",
+                param_0,
+            ))
+        })?;
+        format_time("format-date", helper, time, self.timezone)
+    }
+}
+
+fn parse_date(value: &serde_json::Value) -> Option<SystemTime> {
+    match value {
+        serde_json::Value::Number(seconds) => seconds
+            .as_u64()
+            .map(|seconds| UNIX_EPOCH + Duration::from_secs(seconds)),
+        serde_json::Value::String(date) => {
+            let timestamp = if date.contains(['T', 't', ' ']) {
+                date.clone()
+            } else {
+                format!("{}T00:00:00Z", date)
+            };
+            humantime::parse_rfc3339_weak(&timestamp).ok()
+        }
+        _ => None,
+    }
+}
+
+/// Formats `time` according to the `format` and `timezone` hash parameters
+/// of `helper`.
+fn format_time<'context>(
+    helper_name: &str,
+    helper: &handlebars::Helper<'context>,
+    time: SystemTime,
+    default_timezone: TimeZone,
+) -> Result<handlebars::ScopedJson<'context>, handlebars::RenderError> {
+    let string_parameter = |name| match helper.hash_get(name).map(|value| value.value()) {
+        None => Ok(None),
+        Some(serde_json::Value::String(value)) => Ok(Some(value.as_str())),
+        Some(value) => Err(handlebars::RenderErrorReason::Other(format!(
+            "The `{}` helper's `{}` must be a string, but it was `{}`.",
+            helper_name, name, value,
+        ))),
+    };
+
+    let timezone = match string_parameter("timezone")? {
+        None => default_timezone,
+        Some(timezone) => timezone.parse::<TimeZone>().map_err(|error| {
+            handlebars::RenderErrorReason::Other(format!(
+                "The `{}` helper's `timezone` is invalid: {}",
+                helper_name, error,
+            ))
+        })?,
+    };
+    let format = string_parameter("format")?.unwrap_or(TimeZone::DEFAULT_FORMAT);
+    let formatted = timezone.format(time, format).map_err(|error| {
+        handlebars::RenderErrorReason::Other(format!(
+            "The `{}` helper's `format` is invalid: {}",
+            helper_name, error,
+        ))
+    })?;
+
+    Ok(handlebars::ScopedJson::Derived(serde_json::Value::String(
+        formatted,
+    )))
+}
@@ -0,0 +1,435 @@
+use crate::content::template_engine::is_xml_character;
+use crate::content::Escaping;
+use handlebars::{self, Handlebars, Renderable};
+use std::sync::OnceLock;
+
+/// Removes everything but harmless markup from an HTML fragment, so that
+/// untrusted HTML (like user-provided data or the output of an executable)
+/// can be included in a page.
+///
+/// `{{sanitize-html data.comment}}` (or
+/// `{{#sanitize-html}}{{{get "/comments/latest"}}}{{/sanitize-html}}`) keeps
+/// text formatting, lists, tables, links, and images, but drops every other
+/// element (and the contents of `<script>`, `<style>`, and `<template>`),
+/// every attribute not in the allowlist (see [`ELEMENT_ATTRIBUTES`]), and
+/// URLs whose scheme is not `http`, `https`, or `mailto`. Links get
+/// `rel="noopener noreferrer"`, and unclosed elements are closed. This uses
+/// [ammonia](https://docs.rs/ammonia), which parses HTML like browsers do.
+pub struct SanitizeHtmlHelper;
+
+impl handlebars::HelperDef for SanitizeHtmlHelper {
+    fn call<'registry: 'context, 'context>(
+        &self,
+        helper: &handlebars::Helper<'context>,
+        handlebars_registry: &'registry Handlebars<'registry>,
+        handlebars_context: &'context handlebars::Context,
+        handlebars_render_context: &mut handlebars::RenderContext<'registry, 'context>,
+        output: &mut dyn handlebars::Output,
+    ) -> handlebars::HelperResult {
+        let html = match (helper.param(0), helper.template()) {
+            (Some(param_0), _) => match param_0.value() {
+                serde_json::Value::String(html) => html.clone(),
+                serde_json::Value::Null => String::new(),
+                value => {
+                    return Err(handlebars::RenderError::from(
+                        handlebars::RenderErrorReason::Other(format!(
+                            "The `sanitize-html` helper's argument must be a string of HTML, \
+                            but it was `{}`.",
+                            value,
+                        )),
+                    ))
+                }
+            },
+            (None, Some(block)) => block.renders(
+                handlebars_registry,
+                handlebars_context,
+                handlebars_render_context,
+            )?,
+            (None, None) => {
+                return Err(handlebars::RenderError::from(
+                    handlebars::RenderErrorReason::Other(String::from(
+                        "The `sanitize-html` helper requires some HTML, either as an argument \
+                        or as a block.",
+                    )),
+                ))
+            }
+        };
+        output.write(&sanitize_html(&html))?;
+        Ok(())
+    }
+}
+
+/// Renders a value as JSON which is safe to embed in a `<script>` element.
+///
+/// `<script>const data = {{json-escape data}};</script>` renders the value as
+/// a JSON literal (so strings include their quotes), with `<`, `>`, `&`, and
+/// the line and paragraph separators written as `\u` escapes so that nothing
+/// in the value can end the script or be mistaken for markup.
+pub struct JsonEscapeHelper;
+
+impl handlebars::HelperDef for JsonEscapeHelper {
+    fn call<'registry: 'context, 'context>(
+        &self,
+        helper: &handlebars::Helper<'context>,
+        _: &'registry Handlebars<'registry>,
+        _: &'context handlebars::Context,
+        _: &mut handlebars::RenderContext<'registry, 'context>,
+        output: &mut dyn handlebars::Output,
+    ) -> handlebars::HelperResult {
+        let value = helper
+            .param(0)
+            .ok_or_else(|| {
+                handlebars::RenderErrorReason::Other(String::from(
+                    "The `json-escape` helper requires an argument.",
+                ))
+            })?
+            .value();
+        output.write(&json_escape(value))?;
+        Ok(())
+    }
+}
+
+/// Escapes a value for use as an HTML attribute value, even an unquoted one.
+///
+/// `<div data-name={{attr-escape name}}>` writes every character except ASCII
+/// letters and digits (and characters beyond Latin-1) as a character
+/// reference. Strings, numbers, and booleans can be escaped, and null renders
+/// nothing.
+pub struct AttrEscapeHelper;
+
+impl handlebars::HelperDef for AttrEscapeHelper {
+    fn call<'registry: 'context, 'context>(
+        &self,
+        helper: &handlebars::Helper<'context>,
+        _: &'registry Handlebars<'registry>,
+        _: &'context handlebars::Context,
+        _: &mut handlebars::RenderContext<'registry, 'context>,
+        output: &mut dyn handlebars::Output,
+    ) -> handlebars::HelperResult {
+        let value = scalar_param("attr-escape", helper)?;
+        output.write(&attr_escape(&value))?;
+        Ok(())
+    }
+}
+
+/// HTML-escapes a value regardless of the template's media type.
+///
+/// Only HTML templates escape values as HTML automatically (see
+/// [`Escaping`]), so `{{html-escape message}}` is for when other kinds of
+/// templates (like `text/plain` ones) need it.
+/// Strings, numbers, and booleans can be escaped, and null renders nothing.
+pub struct HtmlEscapeHelper;
+
+impl handlebars::HelperDef for HtmlEscapeHelper {
+    fn call<'registry: 'context, 'context>(
+        &self,
+        helper: &handlebars::Helper<'context>,
+        _: &'registry Handlebars<'registry>,
+        _: &'context handlebars::Context,
+        _: &mut handlebars::RenderContext<'registry, 'context>,
+        output: &mut dyn handlebars::Output,
+    ) -> handlebars::HelperResult {
+        let value = scalar_param("html-escape", helper)?;
+        output.write(&handlebars::html_escape(&value))?;
+        Ok(())
+    }
+}
+
+/// XML-escapes a value regardless of the template's media type.
+///
+/// XML templates (including ones for `+xml` media types like Atom feeds)
+/// already escape values this way, so `{{xml-escape title}}` is for other
+/// kinds of templates. Besides escaping markup characters, characters which
+/// are not allowed in XML (like most control characters) are removed.
+pub struct XmlEscapeHelper;
+
+impl handlebars::HelperDef for XmlEscapeHelper {
+    fn call<'registry: 'context, 'context>(
+        &self,
+        helper: &handlebars::Helper<'context>,
+        _: &'registry Handlebars<'registry>,
+        _: &'context handlebars::Context,
+        _: &mut handlebars::RenderContext<'registry, 'context>,
+        output: &mut dyn handlebars::Output,
+    ) -> handlebars::HelperResult {
+        let value = scalar_param("xml-escape", helper)?;
+        output.write(&Escaping::Xml.escape(&value))?;
+        Ok(())
+    }
+}
+
+/// Wraps text in a CDATA section, for putting markup in XML without escaping
+/// it (like the content of feed entries).
+///
+/// `<content type="html">{{#cdata}}{{{get "/posts/hello"}}}{{/cdata}}</content>`
+/// (or `{{cdata post.body}}`) renders `<![CDATA[...]]>`. Any `]]>` in the
+/// text is split across two sections so that it cannot end the section early,
+/// and characters which are not allowed in XML are removed.
+pub struct CdataHelper;
+
+impl handlebars::HelperDef for CdataHelper {
+    fn call<'registry: 'context, 'context>(
+        &self,
+        helper: &handlebars::Helper<'context>,
+        handlebars_registry: &'registry Handlebars<'registry>,
+        handlebars_context: &'context handlebars::Context,
+        handlebars_render_context: &mut handlebars::RenderContext<'registry, 'context>,
+        output: &mut dyn handlebars::Output,
+    ) -> handlebars::HelperResult {
+        let text = match (helper.param(0), helper.template()) {
+            (Some(_), _) => scalar_param("cdata", helper)?,
+            (None, Some(block)) => block.renders(
+                handlebars_registry,
+                handlebars_context,
+                handlebars_render_context,
+            )?,
+            (None, None) => {
+                return Err(handlebars::RenderError::from(
+                    handlebars::RenderErrorReason::Other(String::from(
+                        "The `cdata` helper requires some text, either as an argument or as a \
+                        block.",
+                    )),
+                ))
+            }
+        };
+        output.write(&cdata(&text))?;
+        Ok(())
+    }
+}
+
+/// The first parameter of a helper as a string, if it is a string, number,
+/// boolean, or null (which is empty).
+fn scalar_param(
+    helper_name: &str,
+    helper: &handlebars::Helper,
+) -> Result<String, handlebars::RenderErrorReason> {
+    let param_0 = helper
+        .param(0)
+        .ok_or_else(|| {
+            handlebars::RenderErrorReason::Other(format!(
+                "The `{}` helper requires an argument.",
+                helper_name,
+            ))
+        })?
+        .value();
+    match param_0 {
+        serde_json::Value::String(value) => Ok(value.clone()),
+        serde_json::Value::Number(value) => Ok(value.to_string()),
+        serde_json::Value::Bool(value) => Ok(value.to_string()),
+        serde_json::Value::Null => Ok(String::new()),
+        value => Err(handlebars::RenderErrorReason::Other(format!(
+            "The `{}` helper's argument must be a string, number, or boolean, but it was `{}`.",
+            helper_name, value,
+        ))),
+    }
+}
+
+/// Elements whose start and end tags are kept.
+const ALLOWED_ELEMENTS: &[&str] = &[
+    "a",
+    "abbr",
+    "b",
+    "blockquote",
+    "br",
+    "caption",
+    "code",
+    "dd",
+    "del",
+    "dfn",
+    "div",
+    "dl",
+    "dt",
+    "em",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "hr",
+    "i",
+    "img",
+    "ins",
+    "kbd",
+    "li",
+    "mark",
+    "ol",
+    "p",
+    "pre",
+    "q",
+    "s",
+    "samp",
+    "small",
+    "span",
+    "strong",
+    "sub",
+    "sup",
+    "table",
+    "tbody",
+    "td",
+    "tfoot",
+    "th",
+    "thead",
+    "tr",
+    "u",
+    "ul",
+];
+
+/// Elements which are removed along with everything inside them.
+const REMOVED_ELEMENTS: &[&str] = &["script", "style", "template"];
+
+/// Attributes which any allowed element can keep.
+const GENERIC_ATTRIBUTES: &[&str] = &["title", "lang"];
+
+/// Attributes which specific elements can keep.
+const ELEMENT_ATTRIBUTES: &[(&str, &[&str])] = &[
+    ("a", &["href"]),
+    ("img", &["src", "alt", "width", "height"]),
+    ("td", &["colspan", "rowspan"]),
+    ("th", &["colspan", "rowspan"]),
+    ("ol", &["start"]),
+    ("q", &["cite"]),
+    ("blockquote", &["cite"]),
+    ("del", &["cite"]),
+    ("ins", &["cite"]),
+];
+
+/// URL schemes which links and images can use. URLs without a scheme are
+/// always allowed.
+const ALLOWED_URL_SCHEMES: &[&str] = &["http", "https", "mailto"];
+
+/// `html` with disallowed elements, attributes, and URLs removed.
+fn sanitize_html(html: &str) -> String {
+    static SANITIZER: OnceLock<ammonia::Builder<'static>> = OnceLock::new();
+    SANITIZER
+        .get_or_init(|| {
+            let mut sanitizer = ammonia::Builder::empty();
+            sanitizer
+                .tags(ALLOWED_ELEMENTS.iter().copied().collect())
+                .clean_content_tags(REMOVED_ELEMENTS.iter().copied().collect())
+                .generic_attributes(GENERIC_ATTRIBUTES.iter().copied().collect())
+                .tag_attributes(
+                    ELEMENT_ATTRIBUTES
+                        .iter()
+                        .map(|(element, attributes)| {
+                            (*element, attributes.iter().copied().collect())
+                        })
+                        .collect(),
+                )
+                .url_schemes(ALLOWED_URL_SCHEMES.iter().copied().collect())
+                .url_relative(ammonia::UrlRelative::PassThrough)
+                .link_rel(Some("noopener noreferrer"));
+            sanitizer
+        })
+        .clean(html)
+        .to_string()
+}
+
+fn json_escape(value: &serde_json::Value) -> String {
+    let mut escaped = String::new();
+    for character in value.to_string().chars() {
+        match character {
+            '<' => escaped.push_str("\\u003c"),
+            '>' => escaped.push_str("\\u003e"),
+            '&' => escaped.push_str("\\u0026"),
+            '\u{2028}' => escaped.push_str("\\u2028"),
+            '\u{2029}' => escaped.push_str("\\u2029"),
+            _ => escaped.push(character),
+        }
+    }
+    escaped
+}
+
+fn attr_escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for character in value.chars() {
+        if character.is_ascii_alphanumeric() || u32::from(character) > 0xff {
+            escaped.push(character);
+        } else {
+            escaped.push_str(&format!("&#x{:02X};", u32::from(character)));
+        }
+    }
+    escaped
+}
+
+fn cdata(text: &str) -> String {
+    let text = text
+        .chars()
+        .filter(|character| is_xml_character(*character))
+        .collect::<String>();
+    format!("<![CDATA[{}]]>", text.replace("]]>", "]]]]><![CDATA[>"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn html_is_sanitized() {
+        let cases = [
+            ("plain & simple", "plain &amp; simple"),
+            ("<p>Hi &amp; bye</p>", "<p>Hi &amp; bye</p>"),
+            (
+                "<P CLASS=x onclick='alert(1)' title=\"a&quot;b\">x</p>",
+                "<p title=\"a&quot;b\">x</p>",
+            ),
+            ("a<script>alert('<b>')</script>b", "ab"),
+            ("<style>p { color: red }</style><em>x", "<em>x</em>"),
+            ("<!-- secret --><b>bold</b><!DOCTYPE html>", "<b>bold</b>"),
+            ("1 < 2 > 0", "1 &lt; 2 &gt; 0"),
+            ("<iframe src=x>inside</iframe>", "inside"),
+            ("</b>x<b>y</i>", "x<b>y</b>"),
+            ("<ul><li>a<li>b</ul>", "<ul><li>a</li><li>b</li></ul>"),
+            (
+                "<br/><img src=/a.png alt=A onerror=x>",
+                "<br><img src=\"/a.png\" alt=\"A\">",
+            ),
+            (
+                "<a href=\"https://example.com/?a=1&amp;b=2\">x</a>",
+                "<a href=\"https://example.com/?a=1&amp;b=2\" rel=\"noopener noreferrer\">x</a>",
+            ),
+            (
+                "<a href=\"javascript:alert(1)\">x</a>",
+                "<a rel=\"noopener noreferrer\">x</a>",
+            ),
+            (
+                "<a href=\"java&#x09;script&colon;alert(1)\">x</a>",
+                "<a rel=\"noopener noreferrer\">x</a>",
+            ),
+            (
+                "<a href=\"/a:b\" href=\"javascript:x\">x</a>",
+                "<a href=\"/a:b\" rel=\"noopener noreferrer\">x</a>",
+            ),
+            ("<img src='data:image/png;base64,AAAA'>", "<img>"),
+            ("<b>x</b><i title=\"unterminated>", "<b>x</b>"),
+            (
+                "caf&eacute; &amp; cr&egrave;me &bogus;",
+                "café &amp; crème &amp;bogus;",
+            ),
+        ];
+        for (html, expected) in cases {
+            assert_eq!(sanitize_html(html), expected, "sanitizing `{}`", html);
+        }
+    }
+
+    #[test]
+    fn text_can_be_wrapped_in_cdata_sections() {
+        assert_eq!(cdata("<p>a & b</p>"), "<![CDATA[<p>a & b</p>]]>");
+        assert_eq!(cdata("x]]>y\u{0}"), "<![CDATA[x]]]]><![CDATA[>y]]>");
+        assert_eq!(cdata(""), "<![CDATA[]]>");
+    }
+
+    #[test]
+    fn values_are_escaped_for_scripts_and_attributes() {
+        assert_eq!(
+            json_escape(&json!({"a": "</script><!--", "b": [1, true, null]})),
+            r#"{"a":"\u003c/script\u003e\u003c!--","b":[1,true,null]}"#
+        );
+        assert_eq!(json_escape(&json!("Q&A\u{2028}")), r#""Q\u0026A\u2028""#);
+        assert_eq!(
+            attr_escape("a b\"c'<d>=é日"),
+            "a&#x20;b&#x22;c&#x27;&#x3C;d&#x3E;&#x3D;&#xE9;日"
+        );
+    }
+}
@@ -0,0 +1,180 @@
+use crate::clock::Clock;
+use crate::content::content_item::StaticContentItem;
+use crate::content::*;
+use crate::render::split_extension;
+use handlebars::{self, Handlebars};
+use std::marker::PhantomData;
+use std::sync::{Arc, RwLock};
+use std::time::SystemTime;
+
+/// Describes the file behind a static route, for things like download links.
+///
+/// `{{#with (file-info "/downloads/big.iso")}}{{human-size}}, updated
+/// {{format-date modified format="%F"}}{{/with}}` renders something like `42
+/// MB, updated 2024-05-01`. The result is an object with `size` (in bytes),
+/// `human-size`, `modified` (a UTC timestamp), and `media-type`. The argument
+/// can be a route or a URL path with an extension, which picks between
+/// representations. Only static files have file info. In deterministic mode
+/// every file seems to have been modified at the Unix epoch.
+pub struct FileInfoHelper<ServerInfo, Engine>
+where
+    ServerInfo: Clone + Serialize,
+    Engine: ContentEngine<ServerInfo>,
+{
+    content_engine: Arc<RwLock<Engine>>,
+    deterministic: bool,
+    server_info_type: PhantomData<ServerInfo>,
+}
+impl<ServerInfo, Engine> FileInfoHelper<ServerInfo, Engine>
+where
+    ServerInfo: Clone + Serialize,
+    Engine: ContentEngine<ServerInfo>,
+{
+    pub fn new(content_engine: Arc<RwLock<Engine>>, deterministic: bool) -> Self {
+        Self {
+            content_engine,
+            deterministic,
+            server_info_type: PhantomData,
+        }
+    }
+}
+
+impl<ServerInfo, Engine> handlebars::HelperDef for FileInfoHelper<ServerInfo, Engine>
+where
+    ServerInfo: Clone + Serialize,
+    Engine: ContentEngine<ServerInfo>,
+{
+    fn call_inner<'registry: 'context, 'context>(
+        &self,
+        helper: &handlebars::Helper<'context>,
+        _: &'registry Handlebars<'registry>,
+        _: &'context handlebars::Context,
+        _: &mut handlebars::RenderContext<'registry, 'context>,
+    ) -> Result<handlebars::ScopedJson<'context>, handlebars::RenderError> {
+        let content_engine = self
+            .content_engine
+            .read()
+            .expect("RwLock for ContentEngine has been poisoned");
+
+        let param_0 = helper
+            .param(0)
+            .ok_or_else(|| {
+                handlebars::RenderErrorReason::Other(String::from(
+                    "The `file-info` helper requires an argument (a route).",
+                ))
+            })?
+            .value();
+        let (media_type, item) = find_static_file("file-info", param_0, &*content_engine)?;
+
+        let file_error = |error: RenderingFailedError| {
+            handlebars::RenderErrorReason::Other(format!(
+                "Unable to get file info for `file-info {}`: {}",
+                param_0, error,
+            ))
+        };
+        let size = item.size().map_err(file_error)?;
+        let modified = if self.deterministic {
+            Clock::DETERMINISTIC_TIME
+        } else {
+            item.modified().map_err(file_error)?
+        };
+
+        Ok(handlebars::ScopedJson::Derived(serde_json::json!({
+            "size": size,
+            "human-size": human_size(size),
+            "modified": format_modified(modified),
+            "media-type": media_type.to_string(),
+        })))
+    }
+}
+
+/// The static file at the path in `param_0` (which can have an extension to
+/// pick between representations), and its media type.
+pub(super) fn find_static_file<'engine, ServerInfo, Engine>(
+    helper_name: &str,
+    param_0: &serde_json::Value,
+    content_engine: &'engine Engine,
+) -> Result<(&'engine MediaType, &'engine StaticContentItem), handlebars::RenderErrorReason>
+where
+    ServerInfo: Clone + Serialize,
+    Engine: ContentEngine<ServerInfo>,
+{
+    let path = param_0.as_str().ok_or_else(|| {
+        handlebars::RenderErrorReason::Other(format!(
+            "The `{}` helper's argument must be a string (a route), but it was `{}`.",
+            helper_name, param_0,
+        ))
+    })?;
+    let (path, media_range) = split_extension(path);
+    let route = path.parse::<Route>().map_err(|error| {
+        handlebars::RenderErrorReason::Other(format!(
+            "The `{}` helper's argument (`{}`) must be a valid route: {}",
+            helper_name, param_0, error,
+        ))
+    })?;
+
+    let not_found = || {
+        handlebars::RenderErrorReason::Other(format!(
+            "No static file found for `{} {}`.",
+            helper_name, param_0
+        ))
+    };
+    let representations = content_engine.get(&route).ok_or_else(not_found)?;
+    let mut static_files = representations
+        .iter()
+        .filter(|(media_type, _)| {
+            media_range
+                .as_ref()
+                .is_none_or(|media_range| media_type.is_within_media_range(media_range))
+        })
+        .filter_map(|(media_type, content)| match content {
+            RegisteredContent::StaticContentItem(item) => Some((media_type, item)),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    // Representations are unordered, so choose consistently.
+    static_files.sort_by_key(|(media_type, _)| media_type.to_string());
+    static_files.into_iter().next().ok_or_else(not_found)
+}
+
+/// A size like `42 MB`, in powers of 1000 with at most one decimal place.
+fn human_size(bytes: u64) -> String {
+    const UNITS: [&str; 6] = ["kB", "MB", "GB", "TB", "PB", "EB"];
+    if bytes < 1000 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64;
+    let mut unit = UNITS[0];
+    for next_unit in UNITS {
+        size /= 1000.0;
+        unit = next_unit;
+        if size < 999.95 {
+            break;
+        }
+    }
+    if size < 9.95 {
+        format!("{:.1} {}", size, unit)
+    } else {
+        format!("{:.0} {}", size, unit)
+    }
+}
+
+fn format_modified(modified: SystemTime) -> String {
+    humantime::format_rfc3339_seconds(modified).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sizes_are_human_readable() {
+        assert_eq!(human_size(0), "0 B");
+        assert_eq!(human_size(999), "999 B");
+        assert_eq!(human_size(1000), "1.0 kB");
+        assert_eq!(human_size(4_240_000), "4.2 MB");
+        assert_eq!(human_size(42_000_000), "42 MB");
+        assert_eq!(human_size(999_999_999), "1.0 GB");
+        assert_eq!(human_size(u64::MAX), "18 EB");
+    }
+}
@@ -57,22 +57,20 @@ where
                 ))
             })?
             .value();
-        let route = param_0
-            .as_str()
-            .ok_or_else(|| {
-                handlebars::RenderErrorReason::Other(format!(
-                    "The `get` helper's first argument must be a string (the route of the content \
+        let route = param_0.as_str().ok_or_else(|| {
+            handlebars::RenderErrorReason::Other(format!(
+                "The `get` helper's first argument must be a string (the route of the content \
                     item to get), but it was `{}`.",
-                    param_0,
-                ))
-            })?
-            .parse::<Route>()
-            .map_err(|error| {
-                handlebars::RenderErrorReason::Other(format!(
-                    "The `get` helper's first argument (`{}`) must be a valid route: {}",
-                    param_0, error,
-                ))
-            })?;
+                param_0,
+            ))
+        })?;
+        let route = Route::parse_without_dot_segments(route).map_err(|error| {
+            handlebars::RenderErrorReason::Other(format!(
+                "The `get` helper's first argument (`{}`) must be a valid route: {}. Use \
+                `route-join` to build routes from untrusted segments.",
+                param_0, error,
+            ))
+        })?;
 
         // The second param is an (optional) custom context for the included
         // content.
@@ -104,6 +102,7 @@ where
                 helper
                     .hash()
                     .iter()
+                    .filter(|(key, _)| !is_request_override(key))
                     .map(|(key, value)| (*key, value.value()))
                     .collect::<BTreeMap<&str, &serde_json::Value>>()
             });
@@ -142,6 +141,10 @@ where
             None => serde_json::Map::default(),
         };
 
+        let mut query_parameters = get_query_parameters(current_render_data, &route)?;
+        let mut request_headers = get_request_headers(current_render_data, &route)?;
+        apply_request_overrides(helper, &mut query_parameters, &mut request_headers, &route)?;
+
         // Merge render data and custom context atop the existing RenderContext data.
         for (key, value) in current_render_data {
             modified_context_data_as_json_map.insert(key.to_string(), value.clone());
@@ -149,48 +152,81 @@ where
         for (key, value) in custom_context {
             modified_context_data_as_json_map.insert(key.to_string(), value.clone());
         }
+
+        // The nested render sees any overridden request details.
+        if let Some(serde_json::Value::Object(request_data)) =
+            modified_context_data_as_json_map.get_mut(REQUEST_DATA_PROPERTY_NAME)
+        {
+            request_data.insert(
+                String::from(QUERY_PARAMETERS_PROPERTY_NAME),
+                serde_json::json!(query_parameters.last_values()),
+            );
+            request_data.insert(
+                String::from(QUERY_PARAMETER_LISTS_PROPERTY_NAME),
+                serde_json::json!(query_parameters.lists()),
+            );
+            request_data.insert(
+                String::from(REQUEST_HEADERS_PROPERTY_NAME),
+                serde_json::json!(request_headers),
+            );
+        }
         handlebars_render_context.set_context(handlebars::Context::from(
             serde_json::Value::Object(modified_context_data_as_json_map),
         ));
 
         let target_media_type = get_target_media_type(current_render_data, &route)?;
         let optional_request_route = get_optional_request_route(current_render_data, &route)?;
-        let query_parameters = get_query_parameters(current_render_data, &route)?;
-        let request_headers = get_request_headers(current_render_data, &route)?;
+
+        let http_request_data = get_http_request_data(current_render_data);
 
         let context = content_engine
             .render_context(optional_request_route, query_parameters, request_headers)
+            .with_http_request_data(http_request_data)
             .with_handlebars_render_context(handlebars_render_context.clone());
 
-        let rendered = content_item
-            .render(context, &[target_media_type.into_media_range()])
-            .map_err(|render_error| {
+        if let Some(current_route) = current_route() {
+            content_engine.record_dependency(current_route, route.clone());
+        }
+        if Cancellation::current().is_cancelled() {
+            return Err(handlebars::RenderErrorReason::Other(format!(
+                "The `get \"{}\"` helper call was skipped because the render was cancelled.",
+                route
+            ))
+            .into());
+        }
+        let (bytes, trace) = trace(&route, || -> Result<Vec<u8>, handlebars::RenderError> {
+            let rendered = content_item
+                .render(context, &[target_media_type.into_media_range()])
+                .map_err(|render_error| {
+                    handlebars::RenderErrorReason::Other(format!(
+                        "The `get \"{}\"` helper call failed because {} could not be rendered: {}",
+                        route, route, render_error,
+                    ))
+                })?;
+
+            // Unfortunately handlebars-rust needs a string, so we block the thread
+            // until the stream has been exhausted (or produces an error).
+            let (size_lower_bound, _) = rendered.content.size_hint();
+            let bytes = executor::block_on(rendered.content.try_fold(
+                Vec::with_capacity(size_lower_bound),
+                |mut all_bytes, additional_bytes| async {
+                    all_bytes.extend(additional_bytes);
+                    Ok(all_bytes)
+                },
+            ))
+            .map_err(|streaming_error| {
                 handlebars::RenderErrorReason::Other(format!(
-                    "The `get \"{}\"` helper call failed because {} could not be rendered: {}",
-                    route, route, render_error,
+                    "The `get \"{}\"` helper call failed because there was an error collecting the rendered content \
+                    for {}: {}",
+                    route,
+                    route,
+                    streaming_error,
                 ))
             })?;
-
-        // Unfortunately handlebars-rust needs a string, so we block the thread
-        // until the stream has been exhausted (or produces an error).
-        let (size_lower_bound, _) = rendered.content.size_hint();
-        let bytes = executor::block_on(rendered.content.try_fold(
-            Vec::with_capacity(size_lower_bound),
-            |mut all_bytes, additional_bytes| async {
-                all_bytes.extend(additional_bytes);
-                Ok(all_bytes)
-            },
-        ))
-        .map_err(|streaming_error| {
-            handlebars::RenderErrorReason::Other(format!(
-                "The `get \"{}\"` helper call failed because there was an error collecting the rendered content \
-                for {}: {}",
-                route,
-                route,
-                streaming_error,
-            ))
-        })?;
-        let rendered_content_as_string = String::from_utf8(bytes)?;
+            Ok(bytes)
+        });
+        log::debug!("Rendered `get \"{}\"` in {:.1?}", route, trace.duration);
+        let rendered_content_as_string = String::from_utf8(bytes?)?;
 
         output.write(&rendered_content_as_string)?;
         Ok(())
@@ -265,9 +301,29 @@ fn get_optional_request_route(
 fn get_query_parameters(
     render_data: &serde_json::value::Map<String, serde_json::Value>,
     route: &Route,
-) -> Result<HashMap<String, String>, handlebars::RenderError> {
-    let query_parameters = render_data
-        .get(REQUEST_DATA_PROPERTY_NAME)
+) -> Result<QueryParameters, handlebars::RenderError> {
+    let request_data = render_data.get(REQUEST_DATA_PROPERTY_NAME);
+
+    // Prefer the lists since they include repeated keys.
+    if let Some(query_parameter_lists) = request_data
+        .and_then(|request_data| request_data.get(QUERY_PARAMETER_LISTS_PROPERTY_NAME))
+        .and_then(|lists| lists.as_object())
+    {
+        let query_parameters = query_parameter_lists
+            .iter()
+            .flat_map(|(key, values)| {
+                values
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .flat_map(|value| value.as_str())
+                    .map(move |value| (key.clone(), String::from(value)))
+            })
+            .collect::<Vec<_>>();
+        return Ok(QueryParameters::new(query_parameters));
+    }
+
+    let query_parameters = request_data
         .and_then(|request_data| request_data.get(QUERY_PARAMETERS_PROPERTY_NAME))
         .ok_or_else(|| {
             handlebars::RenderErrorReason::Other(format!(
@@ -292,8 +348,78 @@ fn get_query_parameters(
                 .as_str()
                 .map(|value| (key.clone(), String::from(value)))
         })
-        .collect::<HashMap<String, String>>();
-    Ok(query_parameters)
+        .collect::<Vec<_>>();
+    Ok(QueryParameters::new(query_parameters))
+}
+
+/// Hash params named like `query-name` or `header-name` override the query
+/// parameters and request headers for the nested render instead of becoming
+/// render data.
+const QUERY_OVERRIDE_PREFIX: &str = "query-";
+const HEADER_OVERRIDE_PREFIX: &str = "header-";
+
+fn is_request_override(hash_param_name: &str) -> bool {
+    hash_param_name.starts_with(QUERY_OVERRIDE_PREFIX)
+        || hash_param_name.starts_with(HEADER_OVERRIDE_PREFIX)
+}
+
+/// Values can be strings, numbers, or booleans. Arrays set multiple values
+/// for a query parameter, and `null` removes a query parameter or header.
+fn apply_request_overrides(
+    helper: &handlebars::Helper,
+    query_parameters: &mut QueryParameters,
+    request_headers: &mut HashMap<String, String>,
+    route: &Route,
+) -> Result<(), handlebars::RenderError> {
+    let override_values = |name: &str, value: &serde_json::Value| {
+        let scalar_to_string = |value: &serde_json::Value| match value {
+            serde_json::Value::String(string) => Some(string.clone()),
+            serde_json::Value::Number(number) => Some(number.to_string()),
+            serde_json::Value::Bool(boolean) => Some(boolean.to_string()),
+            _ => None,
+        };
+        match value {
+            serde_json::Value::Null => Some(Vec::new()),
+            serde_json::Value::Array(values) => values.iter().map(scalar_to_string).collect(),
+            value => scalar_to_string(value).map(|value| vec![value]),
+        }
+        .ok_or_else(|| {
+            handlebars::RenderErrorReason::Other(format!(
+                "The `get \"{}\"` helper's `{}` hash param must be a string, number, boolean, \
+                array of those, or null, but it was `{}`.",
+                route, name, value,
+            ))
+        })
+    };
+
+    for (name, value) in helper.hash() {
+        if let Some(key) = name.strip_prefix(QUERY_OVERRIDE_PREFIX) {
+            let values = override_values(name, value.value())?;
+            query_parameters.set(key, values);
+        } else if let Some(header_name) = name.strip_prefix(HEADER_OVERRIDE_PREFIX) {
+            let header_name = header_name.to_lowercase();
+            match override_values(name, value.value())?.pop() {
+                Some(header_value) => {
+                    request_headers.insert(header_name, header_value);
+                }
+                None => {
+                    request_headers.remove(&header_name);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// These details are optional, so missing or malformed values are ignored.
+fn get_http_request_data(
+    render_data: &serde_json::value::Map<String, serde_json::Value>,
+) -> HttpRequestData {
+    render_data
+        .get(REQUEST_DATA_PROPERTY_NAME)
+        .and_then(|request_data| request_data.get(HTTP_REQUEST_DATA_PROPERTY_NAME))
+        .and_then(|http_request_data| HttpRequestData::deserialize(http_request_data).ok())
+        .unwrap_or_default()
 }
 
 fn get_request_headers(
@@ -0,0 +1,161 @@
+use handlebars::{self, Handlebars, Renderable};
+use std::sync::OnceLock;
+use syntect::html::{ClassStyle, ClassedHTMLGenerator};
+use syntect::parsing::{SyntaxReference, SyntaxSet};
+use syntect::util::LinesWithEndings;
+
+/// Highlights source code, so that code samples do not need client-side
+/// highlighting.
+///
+/// `{{#highlight lang="rust"}}fn main() {}{{/highlight}}` renders
+/// `<pre class="highlight"><code class="language-rust">...</code></pre>`, with
+/// the code wrapped in spans whose classes are its
+/// [syntect](https://docs.rs/syntect) scopes prefixed with `hl-` (like
+/// `hl-keyword`, `hl-string`, `hl-numeric`, and `hl-comment`; the site's CSS
+/// decides how they look). Code can also come from render data, as in
+/// `{{highlight data.example lang="python"}}`. The block is rendered as a
+/// template first, so values inside it should use triple braces to avoid
+/// being escaped twice. `lang` is a language name or file extension, and
+/// languages which syntect does not know are escaped but not highlighted.
+pub struct HighlightHelper;
+
+impl handlebars::HelperDef for HighlightHelper {
+    fn call<'registry: 'context, 'context>(
+        &self,
+        helper: &handlebars::Helper<'context>,
+        handlebars_registry: &'registry Handlebars<'registry>,
+        handlebars_context: &'context handlebars::Context,
+        handlebars_render_context: &mut handlebars::RenderContext<'registry, 'context>,
+        output: &mut dyn handlebars::Output,
+    ) -> handlebars::HelperResult {
+        let language_name = match helper.hash_get("lang").map(|value| value.value()) {
+            None => None,
+            Some(serde_json::Value::String(language_name)) => Some(language_name.as_str()),
+            Some(value) => {
+                return Err(handlebars::RenderError::from(
+                    handlebars::RenderErrorReason::Other(format!(
+                        "The `highlight` helper's `lang` must be a string, but it was `{}`.",
+                        value,
+                    )),
+                ))
+            }
+        };
+
+        let code = match (helper.param(0), helper.template()) {
+            (Some(param_0), _) => match param_0.value() {
+                serde_json::Value::String(code) => code.clone(),
+                value => {
+                    return Err(handlebars::RenderError::from(
+                        handlebars::RenderErrorReason::Other(format!(
+                            "The `highlight` helper's argument must be a string (the code to \
+                            highlight), but it was `{}`.",
+                            value,
+                        )),
+                    ))
+                }
+            },
+            (None, Some(block)) => {
+                let code = block.renders(
+                    handlebars_registry,
+                    handlebars_context,
+                    handlebars_render_context,
+                )?;
+                // Allow the code to start on the line after the opening tag.
+                let code = code.strip_prefix('\n').unwrap_or(&code);
+                String::from(code.trim_end())
+            }
+            (None, None) => {
+                return Err(handlebars::RenderError::from(
+                    handlebars::RenderErrorReason::Other(String::from(
+                        "The `highlight` helper requires code to highlight, either as a block \
+                        (`{{#highlight lang=\"rust\"}}...{{/highlight}}`) or as an argument.",
+                    )),
+                ))
+            }
+        };
+
+        let highlighted = match language_name.and_then(find_syntax) {
+            Some(syntax) => highlight(&code, syntax)?,
+            None => handlebars::html_escape(&code),
+        };
+        let code_class = language_name
+            .map(|language_name| {
+                format!(
+                    " class=\"language-{}\"",
+                    handlebars::html_escape(language_name)
+                )
+            })
+            .unwrap_or_default();
+        output.write(&format!(
+            "<pre class=\"highlight\"><code{}>{}</code></pre>",
+            code_class, highlighted,
+        ))?;
+        Ok(())
+    }
+}
+
+fn syntax_set() -> &'static SyntaxSet {
+    static SYNTAX_SET: OnceLock<SyntaxSet> = OnceLock::new();
+    SYNTAX_SET.get_or_init(SyntaxSet::load_defaults_newlines)
+}
+
+/// The syntax for a language name (like `Rust`) or file extension (like
+/// `rs`), ignoring case.
+fn find_syntax(language_name: &str) -> Option<&'static SyntaxReference> {
+    let syntax_set = syntax_set();
+    syntax_set
+        .find_syntax_by_token(language_name)
+        .or_else(|| syntax_set.find_syntax_by_extension(&language_name.to_ascii_lowercase()))
+}
+
+/// `code` as escaped HTML, with spans around each part of its syntax.
+fn highlight(
+    code: &str,
+    syntax: &SyntaxReference,
+) -> Result<String, handlebars::RenderErrorReason> {
+    let mut generator = ClassedHTMLGenerator::new_with_class_style(
+        syntax,
+        syntax_set(),
+        ClassStyle::SpacedPrefixed { prefix: "hl-" },
+    );
+    for line in LinesWithEndings::from(code) {
+        generator
+            .parse_html_for_line_which_includes_newline(line)
+            .map_err(|error| {
+                handlebars::RenderErrorReason::Other(format!(
+                    "The `highlight` helper could not highlight the code: {}",
+                    error,
+                ))
+            })?;
+    }
+    Ok(generator.finalize())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn code_is_highlighted() {
+        let highlight = |code: &str, language_name: &str| {
+            highlight(code, find_syntax(language_name).unwrap()).unwrap()
+        };
+        let rust = highlight("let x = \"<a>\"; // 42\nfn f() -> u8 { 0x2a }", "rust");
+        assert!(
+            rust.starts_with("<span class=\"hl-source hl-rust\">"),
+            "{}",
+            rust
+        );
+        assert!(rust.contains("<span class=\"hl-storage hl-type hl-rust\">let</span>"));
+        assert!(rust.contains("&lt;a&gt;"), "{}", rust);
+        assert!(rust.contains("<span class=\"hl-comment hl-line hl-double-slash hl-rust\">"));
+        assert!(rust.contains(
+            "<span class=\"hl-constant hl-numeric hl-integer hl-hexadecimal hl-rust\">0x2a</span>"
+        ));
+
+        // Languages can be named with any case or by their file extension.
+        assert!(highlight("/* unterminated", "JS").contains("hl-comment hl-block hl-js"));
+        assert_eq!(highlight("x = 1", "Python"), highlight("x = 1", "py"),);
+        assert!(find_syntax("cobol").is_none());
+    }
+}
@@ -0,0 +1,87 @@
+use super::file_info::find_static_file;
+use crate::content::content_item::IntegrityAlgorithm;
+use crate::content::*;
+use handlebars::{self, Handlebars};
+use std::marker::PhantomData;
+use std::sync::{Arc, RwLock};
+
+/// Renders the Subresource Integrity metadata of a script or stylesheet.
+///
+/// `<script src="/js/app.js" integrity="{{integrity "/js/app.js"}}">` renders
+/// something like `sha384-oqVuAfXRKap7fdgcCY5uykM6+R9GqQ8K/uxy9rx7HNQlGYl1kPzQho1wx4JwY8wC`.
+/// `algo` can be `sha256`, `sha384` (the default), or `sha512`. Digests are
+/// computed when content is loaded, and only for JavaScript and CSS files.
+pub struct IntegrityHelper<ServerInfo, Engine>
+where
+    ServerInfo: Clone + Serialize,
+    Engine: ContentEngine<ServerInfo>,
+{
+    content_engine: Arc<RwLock<Engine>>,
+    server_info_type: PhantomData<ServerInfo>,
+}
+impl<ServerInfo, Engine> IntegrityHelper<ServerInfo, Engine>
+where
+    ServerInfo: Clone + Serialize,
+    Engine: ContentEngine<ServerInfo>,
+{
+    pub fn new(content_engine: Arc<RwLock<Engine>>) -> Self {
+        Self {
+            content_engine,
+            server_info_type: PhantomData,
+        }
+    }
+}
+
+impl<ServerInfo, Engine> handlebars::HelperDef for IntegrityHelper<ServerInfo, Engine>
+where
+    ServerInfo: Clone + Serialize,
+    Engine: ContentEngine<ServerInfo>,
+{
+    fn call_inner<'registry: 'context, 'context>(
+        &self,
+        helper: &handlebars::Helper<'context>,
+        _: &'registry Handlebars<'registry>,
+        _: &'context handlebars::Context,
+        _: &mut handlebars::RenderContext<'registry, 'context>,
+    ) -> Result<handlebars::ScopedJson<'context>, handlebars::RenderError> {
+        let content_engine = self
+            .content_engine
+            .read()
+            .expect("RwLock for ContentEngine has been poisoned");
+
+        let algorithm = match helper.hash_get("algo").map(|value| value.value()) {
+            None => IntegrityAlgorithm::default(),
+            Some(value) => value
+                .as_str()
+                .and_then(IntegrityAlgorithm::from_name)
+                .ok_or_else(|| {
+                    handlebars::RenderErrorReason::Other(format!(
+                        "The `integrity` helper's `algo` must be \"sha256\", \"sha384\", or \
+                        \"sha512\", but it was `{}`.",
+                        value,
+                    ))
+                })?,
+        };
+
+        let param_0 = helper
+            .param(0)
+            .ok_or_else(|| {
+                handlebars::RenderErrorReason::Other(String::from(
+                    "The `integrity` helper requires an argument (a route).",
+                ))
+            })?
+            .value();
+        let (media_type, item) = find_static_file("integrity", param_0, &*content_engine)?;
+        let integrity = item.integrity().ok_or_else(|| {
+            handlebars::RenderErrorReason::Other(format!(
+                "`integrity {}` failed because integrity is only computed for JavaScript and CSS \
+                files, but this file is `{}`.",
+                param_0, media_type,
+            ))
+        })?;
+
+        Ok(handlebars::ScopedJson::Derived(serde_json::Value::String(
+            integrity.metadata(algorithm),
+        )))
+    }
+}
@@ -0,0 +1,122 @@
+use super::GetHelper;
+use crate::content::content_engine::InternalContentEngine;
+use crate::content::*;
+use handlebars::template::{Parameter, TemplateElement};
+use handlebars::{self, Handlebars};
+use std::cell::Cell;
+
+/// The name of the partial that layouts use to include the body of the page.
+const LAYOUT_CONTENT_PARTIAL_NAME: &str = "content";
+
+thread_local! {
+    /// How many layouts are being rendered on this thread. Layouts render
+    /// synchronously within the template that uses them (like `get`), so
+    /// this is only nonzero while inside of one.
+    static DEPTH: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Counts a layout as being rendered until this is dropped (even if
+/// rendering panics).
+struct DepthGuard;
+impl DepthGuard {
+    fn enter() -> Self {
+        DEPTH.with(|depth| depth.set(depth.get() + 1));
+        DepthGuard
+    }
+}
+impl Drop for DepthGuard {
+    fn drop(&mut self) {
+        DEPTH.with(|depth| depth.set(depth.get().saturating_sub(1)));
+    }
+}
+
+/// Wraps the helper's block in another template.
+///
+/// `{{#layout "/_layouts/base"}}...{{/layout}}` renders the content at
+/// `/_layouts/base` (exactly like `get` would, including custom context and
+/// hash params). Within the layout, `{{> content}}` renders the block. Any
+/// `{{#*inline "name"}}` blocks at the top level of the block are registered
+/// as partials before the layout is rendered, so layouts can use them to fill
+/// in named sections (e.g. `{{> title}}`), falling back to a default via
+/// `{{#> title}}default{{/title}}`. These partials are only visible to the
+/// layout, not to the rest of the template which uses it.
+///
+/// Layouts cannot be nested, but a template can use more than one in turn.
+pub struct LayoutHelper<ServerInfo, Engine>
+where
+    ServerInfo: Clone + Serialize,
+    Engine: ContentEngine<ServerInfo>,
+{
+    get_helper: GetHelper<ServerInfo, Engine>,
+}
+impl<ServerInfo, Engine> LayoutHelper<ServerInfo, Engine>
+where
+    ServerInfo: Clone + Serialize,
+    Engine: ContentEngine<ServerInfo>,
+{
+    pub fn new(get_helper: GetHelper<ServerInfo, Engine>) -> Self {
+        Self { get_helper }
+    }
+}
+
+impl<ServerInfo, Engine> handlebars::HelperDef for LayoutHelper<ServerInfo, Engine>
+where
+    ServerInfo: Clone + Serialize,
+    Engine: ContentEngine<ServerInfo> + InternalContentEngine,
+{
+    fn call<'registry: 'context, 'context>(
+        &self,
+        helper: &handlebars::Helper<'context>,
+        handlebars_registry: &'registry Handlebars<'registry>,
+        handlebars_context: &'context handlebars::Context,
+        handlebars_render_context: &mut handlebars::RenderContext<'registry, 'context>,
+        output: &mut dyn handlebars::Output,
+    ) -> handlebars::HelperResult {
+        let block = helper.template().ok_or_else(|| {
+            handlebars::RenderErrorReason::Other(String::from(
+                "The `layout` helper must be used as a block (`{{#layout \"/route\"}}...{{/layout}}`).",
+            ))
+        })?;
+
+        if DEPTH.with(Cell::get) > 0 {
+            return Err(handlebars::RenderError::from(
+                handlebars::RenderErrorReason::Other(String::from(
+                    "The `layout` helper cannot be used within another layout (layouts cannot be \
+                    nested).",
+                )),
+            ));
+        }
+        let _depth_guard = DepthGuard::enter();
+
+        // Partials are set on a copy of the render context, so that they do
+        // not replace any of the same name once the layout is done.
+        let mut layout_render_context = handlebars_render_context.clone();
+        for element in &block.elements {
+            if let TemplateElement::DecoratorBlock(decorator) = element {
+                let is_inline =
+                    matches!(&decorator.name, Parameter::Name(name) if name == "inline");
+                let partial_name = decorator
+                    .params
+                    .first()
+                    .and_then(|parameter| match parameter {
+                        Parameter::Literal(serde_json::Value::String(name)) => Some(name),
+                        _ => None,
+                    });
+                if let (true, Some(partial_name), Some(template)) =
+                    (is_inline, partial_name, &decorator.template)
+                {
+                    layout_render_context.set_partial(partial_name.clone(), template);
+                }
+            }
+        }
+        layout_render_context.set_partial(String::from(LAYOUT_CONTENT_PARTIAL_NAME), block);
+
+        self.get_helper.call(
+            helper,
+            handlebars_registry,
+            handlebars_context,
+            &mut layout_render_context,
+            output,
+        )
+    }
+}
@@ -1,3 +1,36 @@
+mod absolute_url;
+mod date;
+mod escape;
+mod file_info;
 mod get;
+mod highlight;
+mod integrity;
+mod layout;
+mod navigation;
+mod og_meta;
+mod paginate;
+mod representations;
+mod route_join;
+mod serialize;
+mod toc;
+mod url_path;
 
+pub use absolute_url::AbsoluteUrlHelper;
+pub use date::{FormatDateHelper, NowHelper};
+pub use escape::{
+    AttrEscapeHelper, CdataHelper, HtmlEscapeHelper, JsonEscapeHelper, SanitizeHtmlHelper,
+    XmlEscapeHelper,
+};
+pub use file_info::FileInfoHelper;
 pub use get::GetHelper;
+pub use highlight::HighlightHelper;
+pub use integrity::IntegrityHelper;
+pub use layout::LayoutHelper;
+pub use navigation::{BreadcrumbsHelper, NavigationHelper};
+pub use og_meta::OgMetaHelper;
+pub use paginate::PaginateHelper;
+pub use representations::RepresentationsHelper;
+pub use route_join::RouteJoinHelper;
+pub use serialize::{ToCsvHelper, ToJsonHelper};
+pub use toc::TocHelper;
+pub use url_path::UrlPathHelper;
@@ -0,0 +1,322 @@
+use crate::bug_message;
+use crate::content::content_engine::InternalContentEngine;
+use crate::content::content_index::ContentIndexEntries;
+use crate::content::*;
+use handlebars::{self, Handlebars};
+use std::marker::PhantomData;
+use std::sync::{Arc, RwLock};
+
+/// Lists the pages leading to a route.
+///
+/// `{{#each (breadcrumbs request.route)}}...{{/each}}` iterates over objects
+/// with `route`, `url` (the route percent-encoded for use in links), `title`,
+/// and `current` properties for each ancestor of the route that is content
+/// (internal routes are left out), ending with the route itself. Titles come
+/// from the `title` in a template's front matter, falling back to the last
+/// component of the route. The route defaults to `request.route`.
+pub struct BreadcrumbsHelper<ServerInfo, Engine>
+where
+    ServerInfo: Clone + Serialize,
+    Engine: ContentEngine<ServerInfo>,
+{
+    content_engine: Arc<RwLock<Engine>>,
+    server_info_type: PhantomData<ServerInfo>,
+}
+impl<ServerInfo, Engine> BreadcrumbsHelper<ServerInfo, Engine>
+where
+    ServerInfo: Clone + Serialize,
+    Engine: ContentEngine<ServerInfo>,
+{
+    pub fn new(content_engine: Arc<RwLock<Engine>>) -> Self {
+        Self {
+            content_engine,
+            server_info_type: PhantomData,
+        }
+    }
+}
+
+impl<ServerInfo, Engine> handlebars::HelperDef for BreadcrumbsHelper<ServerInfo, Engine>
+where
+    ServerInfo: Clone + Serialize,
+    Engine: ContentEngine<ServerInfo> + InternalContentEngine,
+{
+    fn call_inner<'registry: 'context, 'context>(
+        &self,
+        helper: &handlebars::Helper<'context>,
+        _: &'registry Handlebars<'registry>,
+        handlebars_context: &'context handlebars::Context,
+        _: &mut handlebars::RenderContext<'registry, 'context>,
+    ) -> Result<handlebars::ScopedJson<'context>, handlebars::RenderError> {
+        let content_engine = self
+            .content_engine
+            .read()
+            .expect("RwLock for ContentEngine has been poisoned");
+        let route = route_argument("breadcrumbs", helper, handlebars_context)?;
+
+        let mut ancestor = String::new();
+        let breadcrumbs = route
+            .as_ref()
+            .split('/')
+            .filter(|component| !component.is_empty())
+            .filter_map(|component| {
+                ancestor.push('/');
+                ancestor.push_str(component);
+                let ancestor = Route::from_decoded(&ancestor).ok()?;
+                content_engine.get(&ancestor)?;
+                Some(serde_json::json!({
+                    "title": title(&*content_engine, &ancestor),
+                    "current": ancestor == route,
+                    "url": ancestor.to_url_path(),
+                    "route": ancestor,
+                }))
+            })
+            .collect::<Vec<_>>();
+
+        Ok(handlebars::ScopedJson::Derived(serde_json::Value::Array(
+            breadcrumbs,
+        )))
+    }
+}
+
+/// Lists the content beneath a route as a tree.
+///
+/// `{{#each (navigation "/docs")}}...{{/each}}` iterates over the entries
+/// directly within `/docs`. Each has a `name`, a `route`, `url` (the route
+/// percent-encoded for use in links), and `title` (all null for directories
+/// which are not content themselves), whether it is
+/// `active` (the request route is it or one of its descendants), and
+/// `children` (entries beneath it, in the same format). Children are only
+/// listed to the given `depth` (which defaults to 1, meaning no children).
+/// Internal routes are left out. The route defaults to the parent of
+/// `request.route`, which lists the current page's siblings.
+pub struct NavigationHelper<ServerInfo, Engine>
+where
+    ServerInfo: Clone + Serialize,
+    Engine: ContentEngine<ServerInfo>,
+{
+    content_engine: Arc<RwLock<Engine>>,
+    server_info_type: PhantomData<ServerInfo>,
+}
+impl<ServerInfo, Engine> NavigationHelper<ServerInfo, Engine>
+where
+    ServerInfo: Clone + Serialize,
+    Engine: ContentEngine<ServerInfo>,
+{
+    pub fn new(content_engine: Arc<RwLock<Engine>>) -> Self {
+        Self {
+            content_engine,
+            server_info_type: PhantomData,
+        }
+    }
+}
+
+impl<ServerInfo, Engine> handlebars::HelperDef for NavigationHelper<ServerInfo, Engine>
+where
+    ServerInfo: Clone + Serialize,
+    Engine: ContentEngine<ServerInfo> + InternalContentEngine,
+{
+    fn call_inner<'registry: 'context, 'context>(
+        &self,
+        helper: &handlebars::Helper<'context>,
+        _: &'registry Handlebars<'registry>,
+        handlebars_context: &'context handlebars::Context,
+        _: &mut handlebars::RenderContext<'registry, 'context>,
+    ) -> Result<handlebars::ScopedJson<'context>, handlebars::RenderError> {
+        let content_engine = self
+            .content_engine
+            .read()
+            .expect("RwLock for ContentEngine has been poisoned");
+
+        let request_route = request_route(handlebars_context);
+        let route = match helper.param(0) {
+            Some(_) => route_argument("navigation", helper, handlebars_context)?,
+            None => request_route
+                .as_ref()
+                .map(parent)
+                .unwrap_or_else(|| parent(&root())),
+        };
+        let depth = match helper.hash_get("depth").map(|value| value.value()) {
+            None => 1,
+            Some(value) => value.as_u64().filter(|depth| *depth > 0).ok_or_else(|| {
+                handlebars::RenderErrorReason::Other(format!(
+                    "The `navigation` helper's `depth` must be a positive integer, but it was \
+                        `{}`.",
+                    value,
+                ))
+            })?,
+        };
+
+        let entries = match content_engine.index() {
+            ContentIndex::Directory(index) => index.directory(&route),
+            ContentIndex::Resource(_) => None,
+        };
+        let tree = entries
+            .map(|entries| {
+                navigation_tree(
+                    &*content_engine,
+                    &route,
+                    entries,
+                    request_route.as_ref(),
+                    depth,
+                )
+            })
+            .unwrap_or_default();
+
+        Ok(handlebars::ScopedJson::Derived(serde_json::Value::Array(
+            tree,
+        )))
+    }
+}
+
+fn navigation_tree<ServerInfo, Engine>(
+    content_engine: &Engine,
+    route: &Route,
+    entries: &ContentIndexEntries,
+    request_route: Option<&Route>,
+    depth: u64,
+) -> Vec<serde_json::Value>
+where
+    ServerInfo: Clone + Serialize,
+    Engine: ContentEngine<ServerInfo> + InternalContentEngine,
+{
+    // A resource and a directory with the same name (like `/foo` and
+    // `/foo/bar`) are combined into one entry.
+    let mut names = entries
+        .iter()
+        .map(|(name, _)| String::from(name.trim_end_matches('/')))
+        .collect::<Vec<_>>();
+    names.sort();
+    names.dedup();
+
+    names
+        .into_iter()
+        .filter_map(|name| {
+            let entry_route = Route::from_decoded(&format!("{}/{}", route, name)).ok()?;
+            let is_content = content_engine.get(&entry_route).is_some();
+            let directory = entries.iter().find_map(|(entry_name, entry)| match entry {
+                ContentIndex::Directory(child_entries)
+                    if entry_name.trim_end_matches('/') == name =>
+                {
+                    Some(child_entries)
+                }
+                _ => None,
+            });
+
+            // Internal content is left out, as are directories that only
+            // contain internal content.
+            let is_listed = is_content
+                || directory.is_some_and(|directory| has_public_content(content_engine, directory));
+            if !is_listed {
+                return None;
+            }
+
+            let children = match directory {
+                Some(directory) if depth > 1 => navigation_tree(
+                    content_engine,
+                    &entry_route,
+                    directory,
+                    request_route,
+                    depth - 1,
+                ),
+                _ => Vec::new(),
+            };
+            Some(serde_json::json!({
+                "name": name,
+                "title": if is_content { Some(title(content_engine, &entry_route)) } else { None },
+                "active": request_route.is_some_and(|request_route| request_route.is_within(&entry_route)),
+                "url": if is_content { Some(entry_route.to_url_path()) } else { None },
+                "route": if is_content { Some(entry_route) } else { None },
+                "children": children,
+            }))
+        })
+        .collect()
+}
+
+fn has_public_content<ServerInfo, Engine>(
+    content_engine: &Engine,
+    entries: &ContentIndexEntries,
+) -> bool
+where
+    ServerInfo: Clone + Serialize,
+    Engine: ContentEngine<ServerInfo> + InternalContentEngine,
+{
+    entries.iter().any(|(_, entry)| match entry {
+        ContentIndex::Resource(route) => content_engine.get(route).is_some(),
+        ContentIndex::Directory(entries) => has_public_content(content_engine, entries),
+    })
+}
+
+/// The `title` from the front matter of one of the route's templates, or
+/// else the last component of the route.
+fn title<ServerInfo, Engine>(content_engine: &Engine, route: &Route) -> String
+where
+    ServerInfo: Clone + Serialize,
+    Engine: ContentEngine<ServerInfo> + InternalContentEngine,
+{
+    let mut representations = content_engine
+        .get_internal(route)
+        .map(|representations| representations.iter().collect::<Vec<_>>())
+        .unwrap_or_default();
+    representations.sort_by_key(|(media_type, _)| media_type.to_string());
+    representations
+        .into_iter()
+        .find_map(|(_, content)| match content {
+            RegisteredContent::RegisteredTemplate(template) => template
+                .front_matter()
+                .data()
+                .get("title")
+                .and_then(|title| title.as_str())
+                .map(String::from),
+            _ => None,
+        })
+        .unwrap_or_else(|| String::from(route.as_ref().rsplit('/').next().unwrap_or_default()))
+}
+
+fn route_argument(
+    helper_name: &str,
+    helper: &handlebars::Helper,
+    handlebars_context: &handlebars::Context,
+) -> Result<Route, handlebars::RenderError> {
+    let value = match helper.param(0) {
+        Some(param) => param.value().clone(),
+        None => request_route(handlebars_context)
+            .map(|route| serde_json::Value::String(String::from(route.as_ref())))
+            .ok_or_else(|| {
+                handlebars::RenderErrorReason::Other(format!(
+                    "The `{}` helper requires an argument (a route) when there is no request \
+                    route.",
+                    helper_name,
+                ))
+            })?,
+    };
+    value
+        .as_str()
+        .and_then(|route| Route::from_decoded(route).ok())
+        .ok_or_else(|| {
+            handlebars::RenderError::from(handlebars::RenderErrorReason::Other(format!(
+                "The `{}` helper's argument must be a route, but it was `{}`.",
+                helper_name, value,
+            )))
+        })
+}
+
+fn request_route(handlebars_context: &handlebars::Context) -> Option<Route> {
+    handlebars_context
+        .data()
+        .get(REQUEST_DATA_PROPERTY_NAME)
+        .and_then(|request_data| request_data.get(ROUTE_PROPERTY_NAME))
+        .and_then(|route| route.as_str())
+        .and_then(|route| Route::from_decoded(route).ok())
+}
+
+fn parent(route: &Route) -> Route {
+    match route.as_ref().rsplit_once('/') {
+        Some((parent, _)) => Route::from_decoded(parent).unwrap_or_else(|_| root()),
+        None => root(),
+    }
+}
+
+fn root() -> Route {
+    "/".parse::<Route>()
+        .expect(bug_message!("The root route was invalid."))
+}
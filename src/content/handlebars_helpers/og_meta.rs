@@ -0,0 +1,99 @@
+use super::absolute_url::base_url;
+use crate::content::*;
+use handlebars::{self, Handlebars};
+
+/// Renders Open Graph and Twitter card `<meta>` tags for the current page.
+///
+/// `{{og-meta}}` (usually in the `<head>` of a layout) uses `title`,
+/// `description`, and `image` from the page's front matter, falling back to
+/// the same properties of the `site` settings. The page URL and relative
+/// image paths are made absolute with the base URL when it is known (see
+/// `absolute-url`). `site.title` is also the site name, `og-type` in front
+/// matter overrides the `website` type, and `site.twitter` (like
+/// `@example`) names the site's Twitter account. Tags without values are
+/// left out.
+pub struct OgMetaHelper;
+
+impl handlebars::HelperDef for OgMetaHelper {
+    fn call<'registry: 'context, 'context>(
+        &self,
+        _: &handlebars::Helper<'context>,
+        _: &'registry Handlebars<'registry>,
+        handlebars_context: &'context handlebars::Context,
+        _: &mut handlebars::RenderContext<'registry, 'context>,
+        output: &mut dyn handlebars::Output,
+    ) -> handlebars::HelperResult {
+        let render_data = handlebars_context.data();
+        let page_string = |property_name| {
+            render_data
+                .get(FRONT_MATTER_PROPERTY_NAME)
+                .and_then(|front_matter| front_matter.get(property_name))
+                .and_then(|value| value.as_str())
+                .filter(|value| !value.is_empty())
+        };
+        let site_string = |property_name| {
+            render_data
+                .get(SITE_PROPERTY_NAME)
+                .and_then(|site| site.get(property_name))
+                .and_then(|value| value.as_str())
+                .filter(|value| !value.is_empty())
+        };
+        let base_url = base_url(render_data);
+        let absolute = |url: &str| match &base_url {
+            Some(base_url) if url.starts_with('/') => format!("{}{}", base_url, url),
+            _ => String::from(url),
+        };
+
+        let title = page_string("title").or_else(|| site_string("title"));
+        let description = page_string("description").or_else(|| site_string("description"));
+        let image = page_string("image")
+            .or_else(|| site_string("image"))
+            .map(absolute);
+        let url = base_url.as_ref().and_then(|base_url| {
+            render_data
+                .get(REQUEST_DATA_PROPERTY_NAME)
+                .and_then(|request_data| request_data.get(ROUTE_PROPERTY_NAME))
+                .and_then(|route| route.as_str())
+                .and_then(|route| route.parse::<Route>().ok())
+                .map(|route| format!("{}{}", base_url, route.to_url_path()))
+        });
+        let card = if image.is_some() {
+            "summary_large_image"
+        } else {
+            "summary"
+        };
+
+        let tags = [
+            (
+                "property",
+                "og:type",
+                Some(page_string("og-type").unwrap_or("website")),
+            ),
+            ("property", "og:title", title),
+            ("property", "og:description", description),
+            ("property", "og:url", url.as_deref()),
+            ("property", "og:image", image.as_deref()),
+            ("property", "og:site_name", site_string("title")),
+            ("name", "twitter:card", Some(card)),
+            ("name", "twitter:site", site_string("twitter")),
+            ("name", "twitter:title", title),
+            ("name", "twitter:description", description),
+            ("name", "twitter:image", image.as_deref()),
+        ];
+        let meta_tags = tags
+            .iter()
+            .filter_map(|(attribute, name, content)| {
+                content.map(|content| {
+                    format!(
+                        "<meta {}=\"{}\" content=\"{}\">",
+                        attribute,
+                        name,
+                        handlebars::html_escape(content),
+                    )
+                })
+            })
+            .collect::<Vec<_>>();
+        output.write(&meta_tags.join("\n"))?;
+        Ok(())
+    }
+}
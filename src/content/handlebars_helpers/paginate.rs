@@ -0,0 +1,178 @@
+use crate::content::*;
+use handlebars::{self, Handlebars, Renderable};
+
+/// The query parameter which selects a page, unless `page-parameter` is set.
+const DEFAULT_PAGE_PARAMETER: &str = "page";
+
+/// Renders its block with one page of a collection.
+///
+/// `{{#paginate posts per-page=10 page=request.query-parameters.page}}...{{/paginate}}`
+/// renders the block with `items` (the posts on the current page),
+/// `current-page`, `total-pages`, `total-items`, `per-page`, and
+/// `previous-url`/`next-url` (which are null on the first/last page).
+/// Pages are numbered from 1 and out-of-range pages are clamped. URLs keep
+/// the current request's path and query parameters, only changing `page` (or
+/// the query parameter named by `page-parameter`). Collections can be arrays
+/// or objects (whose values are used). If the collection is empty the
+/// `{{else}}` block is rendered instead.
+pub struct PaginateHelper;
+
+impl handlebars::HelperDef for PaginateHelper {
+    fn call<'registry: 'context, 'context>(
+        &self,
+        helper: &handlebars::Helper<'context>,
+        handlebars_registry: &'registry Handlebars<'registry>,
+        handlebars_context: &'context handlebars::Context,
+        handlebars_render_context: &mut handlebars::RenderContext<'registry, 'context>,
+        output: &mut dyn handlebars::Output,
+    ) -> handlebars::HelperResult {
+        let block = helper.template().ok_or_else(|| {
+            handlebars::RenderErrorReason::Other(String::from(
+                "The `paginate` helper must be used as a block \
+                (`{{#paginate collection per-page=10}}...{{/paginate}}`).",
+            ))
+        })?;
+
+        let collection = helper
+            .param(0)
+            .ok_or_else(|| {
+                handlebars::RenderErrorReason::Other(String::from(
+                    "The `paginate` helper requires an argument (the collection to paginate).",
+                ))
+            })?
+            .value();
+        let items = match collection {
+            serde_json::Value::Array(items) => items.clone(),
+            serde_json::Value::Object(items) => items.values().cloned().collect(),
+            serde_json::Value::Null => Vec::new(),
+            _ => {
+                return Err(handlebars::RenderError::from(
+                    handlebars::RenderErrorReason::Other(format!(
+                        "The `paginate` helper's argument must be an array or object, but it was \
+                        `{}`.",
+                        collection,
+                    )),
+                ))
+            }
+        };
+
+        let per_page = match helper.hash_get("per-page").map(|value| value.value()) {
+            None => 10,
+            Some(value) => positive_integer(value).ok_or_else(|| {
+                handlebars::RenderErrorReason::Other(format!(
+                    "The `paginate` helper's `per-page` must be a positive integer, but it was \
+                    `{}`.",
+                    value,
+                ))
+            })?,
+        };
+        let page_parameter = match helper.hash_get("page-parameter").map(|value| value.value()) {
+            None => DEFAULT_PAGE_PARAMETER,
+            Some(value) => value.as_str().ok_or_else(|| {
+                handlebars::RenderErrorReason::Other(format!(
+                    "The `paginate` helper's `page-parameter` must be a string, but it was `{}`.",
+                    value,
+                ))
+            })?,
+        };
+        // Missing or nonsensical page numbers (e.g. from a query parameter
+        // that was not provided) are treated as the first page.
+        let requested_page = helper
+            .hash_get("page")
+            .and_then(|value| positive_integer(value.value()))
+            .unwrap_or(1);
+
+        if items.is_empty() {
+            return match helper.inverse() {
+                Some(inverse) => inverse.render(
+                    handlebars_registry,
+                    handlebars_context,
+                    handlebars_render_context,
+                    output,
+                ),
+                None => Ok(()),
+            };
+        }
+
+        let total_items = items.len();
+        let total_pages = total_items.div_ceil(per_page);
+        let current_page = requested_page.min(total_pages);
+        let page_items = items
+            .into_iter()
+            .skip((current_page - 1) * per_page)
+            .take(per_page)
+            .collect::<Vec<_>>();
+
+        let page_url = |page| page_url(handlebars_context.data(), page_parameter, page);
+        let page_data = serde_json::json!({
+            "items": page_items,
+            "current-page": current_page,
+            "total-pages": total_pages,
+            "total-items": total_items,
+            "per-page": per_page,
+            "previous-url": (current_page > 1).then(|| page_url(current_page - 1)),
+            "next-url": (current_page < total_pages).then(|| page_url(current_page + 1)),
+        });
+
+        let mut block_context = handlebars::BlockContext::new();
+        block_context.set_base_value(page_data);
+        handlebars_render_context.push_block(block_context);
+        let result = block.render(
+            handlebars_registry,
+            handlebars_context,
+            handlebars_render_context,
+            output,
+        );
+        handlebars_render_context.pop_block();
+        result
+    }
+}
+
+fn positive_integer(value: &serde_json::Value) -> Option<usize> {
+    let number = match value {
+        serde_json::Value::Number(number) => number.as_u64(),
+        serde_json::Value::String(string) => string.trim().parse::<u64>().ok(),
+        _ => None,
+    }?;
+    usize::try_from(number).ok().filter(|number| *number > 0)
+}
+
+/// A relative URL for the current request with the page query parameter
+/// replaced.
+fn page_url(render_data: &serde_json::Value, page_parameter: &str, page: usize) -> String {
+    let request_data = render_data.get(REQUEST_DATA_PROPERTY_NAME);
+    let request_string = |property_name| {
+        request_data
+            .and_then(|request_data| request_data.get(property_name))
+            .and_then(|value| value.as_str())
+    };
+    let path = request_data
+        .and_then(|request_data| request_data.get(HTTP_REQUEST_DATA_PROPERTY_NAME))
+        .and_then(|http_request_data| http_request_data.get(URI_PATH_PROPERTY_NAME))
+        .and_then(|value| value.as_str())
+        .or_else(|| request_string(ROUTE_PROPERTY_NAME))
+        .unwrap_or("");
+
+    let mut query_parameters = request_data
+        .and_then(|request_data| request_data.get(QUERY_PARAMETER_LISTS_PROPERTY_NAME))
+        .and_then(|lists| lists.as_object())
+        .into_iter()
+        .flatten()
+        .filter(|(key, _)| key.as_str() != page_parameter)
+        .flat_map(|(key, values)| {
+            values
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|value| value.as_str())
+                .map(move |value| (key.as_str(), value))
+        })
+        .collect::<Vec<_>>();
+    let page = page.to_string();
+    query_parameters.push((page_parameter, &page));
+
+    match serde_urlencoded::to_string(&query_parameters) {
+        Ok(query_string) => format!("{}?{}", path, query_string),
+        Err(_) => format!("{}?{}={}", path, page_parameter, page),
+    }
+}
@@ -0,0 +1,118 @@
+use crate::content::content_engine::InternalContentEngine;
+use crate::content::*;
+use handlebars::{self, Handlebars};
+use std::marker::PhantomData;
+use std::sync::{Arc, RwLock};
+
+/// Lists the media types available for a route, along with URLs which select
+/// each one via an extension (e.g. `/resume.pdf`).
+///
+/// `{{#each (representations "/resume")}}...{{/each}}` iterates over objects
+/// with `media-type`, `extension`, and `url` properties, sorted by media type.
+/// Extensions come from the names of the content files. The representation
+/// currently being rendered is left out.
+pub struct RepresentationsHelper<ServerInfo, Engine>
+where
+    ServerInfo: Clone + Serialize,
+    Engine: ContentEngine<ServerInfo>,
+{
+    content_engine: Arc<RwLock<Engine>>,
+    server_info_type: PhantomData<ServerInfo>,
+}
+impl<ServerInfo, Engine> RepresentationsHelper<ServerInfo, Engine>
+where
+    ServerInfo: Clone + Serialize,
+    Engine: ContentEngine<ServerInfo>,
+{
+    pub fn new(content_engine: Arc<RwLock<Engine>>) -> Self {
+        Self {
+            content_engine,
+            server_info_type: PhantomData,
+        }
+    }
+}
+
+impl<ServerInfo, Engine> handlebars::HelperDef for RepresentationsHelper<ServerInfo, Engine>
+where
+    ServerInfo: Clone + Serialize,
+    Engine: ContentEngine<ServerInfo> + InternalContentEngine,
+{
+    fn call_inner<'registry: 'context, 'context>(
+        &self,
+        helper: &handlebars::Helper<'context>,
+        _: &'registry Handlebars<'registry>,
+        handlebars_context: &'context handlebars::Context,
+        _: &mut handlebars::RenderContext<'registry, 'context>,
+    ) -> Result<handlebars::ScopedJson<'context>, handlebars::RenderError> {
+        let content_engine = self
+            .content_engine
+            .read()
+            .expect("RwLock for ContentEngine has been poisoned");
+
+        let param_0 = helper
+            .param(0)
+            .ok_or_else(|| {
+                handlebars::RenderErrorReason::Other(String::from(
+                    "The `representations` helper requires an argument (a route).",
+                ))
+            })?
+            .value();
+        let route = param_0
+            .as_str()
+            .ok_or_else(|| {
+                handlebars::RenderErrorReason::Other(format!(
+                    "The `representations` helper's argument must be a string (a route), but it \
+                    was `{}`.",
+                    param_0,
+                ))
+            })?
+            .parse::<Route>()
+            .map_err(|error| {
+                handlebars::RenderErrorReason::Other(format!(
+                    "The `representations` helper's argument (`{}`) must be a valid route: {}",
+                    param_0, error,
+                ))
+            })?;
+
+        let representations = content_engine.get(&route).ok_or_else(|| {
+            handlebars::RenderErrorReason::Other(format!(
+                "No content found for `representations \"{}\"`.",
+                route
+            ))
+        })?;
+
+        let render_data = handlebars_context.data();
+        let is_rendering_route = render_data
+            .get(REQUEST_DATA_PROPERTY_NAME)
+            .and_then(|request| request.get(ROUTE_PROPERTY_NAME))
+            .and_then(|current_route| current_route.as_str())
+            == Some(route.as_ref());
+        let target_media_type = render_data
+            .get(TARGET_MEDIA_TYPE_PROPERTY_NAME)
+            .and_then(|media_type| media_type.as_str());
+
+        let mut media_types = representations
+            .keys()
+            .filter(|media_type| {
+                !(is_rendering_route && target_media_type == Some(&media_type.to_string()))
+            })
+            .collect::<Vec<_>>();
+        media_types.sort_by_key(|media_type| media_type.to_string());
+
+        let listing = media_types
+            .into_iter()
+            .filter_map(|media_type| {
+                let extension = content_engine.url_extension(&route, media_type)?;
+                Some(serde_json::json!({
+                    "media-type": media_type.to_string(),
+                    "extension": extension,
+                    "url": format!("{}.{}", route.to_url_path(), extension),
+                }))
+            })
+            .collect::<Vec<_>>();
+
+        Ok(handlebars::ScopedJson::Derived(serde_json::Value::Array(
+            listing,
+        )))
+    }
+}
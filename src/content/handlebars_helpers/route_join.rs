@@ -0,0 +1,80 @@
+use crate::content::*;
+use handlebars::{self, Handlebars};
+
+/// Builds a route from a base route and one or more segments.
+///
+/// `{{get (route-join "/posts" slug)}}` renders `/posts/<slug>`. Segments can
+/// be strings or numbers, and the result is guaranteed to be within the base
+/// route (segments like `..` are an error).
+pub struct RouteJoinHelper;
+
+impl handlebars::HelperDef for RouteJoinHelper {
+    fn call_inner<'registry: 'context, 'context>(
+        &self,
+        helper: &handlebars::Helper<'context>,
+        _: &'registry Handlebars<'registry>,
+        _: &'context handlebars::Context,
+        _: &mut handlebars::RenderContext<'registry, 'context>,
+    ) -> Result<handlebars::ScopedJson<'context>, handlebars::RenderError> {
+        let param_0 = helper
+            .param(0)
+            .ok_or_else(|| {
+                handlebars::RenderErrorReason::Other(String::from(
+                    "The `route-join` helper requires a base route and at least one segment.",
+                ))
+            })?
+            .value();
+        let base = param_0
+            .as_str()
+            .ok_or_else(|| {
+                handlebars::RenderErrorReason::Other(format!(
+                    "The `route-join` helper's first argument must be a string (the base route), \
+                    but it was `{}`.",
+                    param_0,
+                ))
+            })?
+            .parse::<Route>()
+            .map_err(|error| {
+                handlebars::RenderErrorReason::Other(format!(
+                    "The `route-join` helper's first argument (`{}`) must be a valid route: {}",
+                    param_0, error,
+                ))
+            })?;
+
+        let segments = helper.params().iter().skip(1).collect::<Vec<_>>();
+        if segments.is_empty() {
+            return Err(handlebars::RenderError::from(
+                handlebars::RenderErrorReason::Other(String::from(
+                    "The `route-join` helper requires a base route and at least one segment.",
+                )),
+            ));
+        }
+
+        let mut route = base;
+        for segment in segments {
+            let segment = match segment.value() {
+                serde_json::Value::String(segment) => segment.clone(),
+                serde_json::Value::Number(segment) => segment.to_string(),
+                segment => {
+                    return Err(handlebars::RenderError::from(
+                        handlebars::RenderErrorReason::Other(format!(
+                            "The `route-join` helper's segments must be strings or numbers, but \
+                            one was `{}`.",
+                            segment,
+                        )),
+                    ))
+                }
+            };
+            route = route.join(&segment).map_err(|error| {
+                handlebars::RenderErrorReason::Other(format!(
+                    "The `route-join` helper could not add `{}` to `{}`: {}",
+                    segment, route, error,
+                ))
+            })?;
+        }
+
+        Ok(handlebars::ScopedJson::Derived(serde_json::Value::String(
+            String::from(route.as_ref()),
+        )))
+    }
+}
@@ -0,0 +1,231 @@
+use handlebars::{self, Handlebars};
+
+/// Serializes a value as JSON.
+///
+/// `{{to-json data.posts}}` renders compact JSON, and
+/// `{{to-json data.posts pretty=true}}` indents it. The output is not
+/// escaped, so this is meant for templates which produce JSON (to embed a
+/// value in an HTML `<script>`, use `json-escape` instead).
+pub struct ToJsonHelper;
+
+impl handlebars::HelperDef for ToJsonHelper {
+    fn call<'registry: 'context, 'context>(
+        &self,
+        helper: &handlebars::Helper<'context>,
+        _: &'registry Handlebars<'registry>,
+        _: &'context handlebars::Context,
+        _: &mut handlebars::RenderContext<'registry, 'context>,
+        output: &mut dyn handlebars::Output,
+    ) -> handlebars::HelperResult {
+        let value = helper
+            .param(0)
+            .ok_or_else(|| {
+                handlebars::RenderErrorReason::Other(String::from(
+                    "The `to-json` helper requires an argument.",
+                ))
+            })?
+            .value();
+        let pretty = match helper.hash_get("pretty").map(|value| value.value()) {
+            None => false,
+            Some(serde_json::Value::Bool(pretty)) => *pretty,
+            Some(value) => {
+                return Err(handlebars::RenderError::from(
+                    handlebars::RenderErrorReason::Other(format!(
+                        "The `to-json` helper's `pretty` must be true or false, but it was `{}`.",
+                        value,
+                    )),
+                ))
+            }
+        };
+        let json = if pretty {
+            serde_json::to_string_pretty(value)
+        } else {
+            serde_json::to_string(value)
+        }
+        .map_err(handlebars::RenderErrorReason::SerdeError)?;
+        output.write(&json)?;
+        Ok(())
+    }
+}
+
+/// Serializes an array of rows as CSV.
+///
+/// Rows can be objects or arrays. `{{to-csv data.people}}` renders a header
+/// row with every property name of the objects (in alphabetical order),
+/// followed by a row for each object. `headers` can be an array of names
+/// (like `{{to-csv data.people headers=front-matter.columns}}`) to choose the
+/// columns and their order, or `false` to leave out the header row. Rows
+/// which are arrays are written as they are, after the header row if
+/// `headers` is an array. Fields are quoted when necessary, nested arrays and
+/// objects are written as JSON, null is empty, and lines end with CRLF (as in
+/// RFC 4180).
+pub struct ToCsvHelper;
+
+impl handlebars::HelperDef for ToCsvHelper {
+    fn call<'registry: 'context, 'context>(
+        &self,
+        helper: &handlebars::Helper<'context>,
+        _: &'registry Handlebars<'registry>,
+        _: &'context handlebars::Context,
+        _: &mut handlebars::RenderContext<'registry, 'context>,
+        output: &mut dyn handlebars::Output,
+    ) -> handlebars::HelperResult {
+        let param_0 = helper
+            .param(0)
+            .ok_or_else(|| {
+                handlebars::RenderErrorReason::Other(String::from(
+                    "The `to-csv` helper requires an argument (an array of rows).",
+                ))
+            })?
+            .value();
+        let rows = param_0.as_array().ok_or_else(|| {
+            handlebars::RenderErrorReason::Other(format!(
+                "The `to-csv` helper's argument must be an array of rows, but it was `{}`.",
+                param_0,
+            ))
+        })?;
+        let headers = match helper.hash_get("headers").map(|value| value.value()) {
+            None | Some(serde_json::Value::Bool(true)) => Headers::Automatic,
+            Some(serde_json::Value::Bool(false)) => Headers::Omitted,
+            Some(serde_json::Value::Array(names)) => Headers::Named(
+                names
+                    .iter()
+                    .map(|name| match name {
+                        serde_json::Value::String(name) => Ok(name.clone()),
+                        name => Err(handlebars::RenderErrorReason::Other(format!(
+                            "The `to-csv` helper's `headers` must be strings, but one was `{}`.",
+                            name,
+                        ))),
+                    })
+                    .collect::<Result<_, _>>()?,
+            ),
+            Some(value) => {
+                return Err(handlebars::RenderError::from(
+                    handlebars::RenderErrorReason::Other(format!(
+                        "The `to-csv` helper's `headers` must be an array of names or a boolean, \
+                        but it was `{}`.",
+                        value,
+                    )),
+                ))
+            }
+        };
+        output.write(&to_csv(rows, headers)?)?;
+        Ok(())
+    }
+}
+
+enum Headers {
+    /// Every property name of the rows which are objects.
+    Automatic,
+    Named(Vec<String>),
+    Omitted,
+}
+
+fn to_csv(
+    rows: &[serde_json::Value],
+    headers: Headers,
+) -> Result<String, handlebars::RenderErrorReason> {
+    let include_header_row = !matches!(headers, Headers::Omitted);
+    let columns = match headers {
+        Headers::Named(names) => Some(names),
+        Headers::Automatic | Headers::Omitted => {
+            let mut names = rows
+                .iter()
+                .filter_map(|row| row.as_object())
+                .flat_map(|row| row.keys().cloned())
+                .collect::<Vec<_>>();
+            names.sort();
+            names.dedup();
+            Some(names).filter(|names| !names.is_empty())
+        }
+    };
+
+    let mut csv = String::new();
+    if let Some(columns) = columns.as_ref().filter(|_| include_header_row) {
+        push_record(&mut csv, columns.iter().map(String::as_str));
+    }
+    for row in rows {
+        match row {
+            serde_json::Value::Object(row) => {
+                let columns = columns.as_deref().unwrap_or_default();
+                let fields = columns
+                    .iter()
+                    .map(|column| row.get(column).map(field).unwrap_or_default())
+                    .collect::<Vec<_>>();
+                push_record(&mut csv, fields.iter().map(String::as_str));
+            }
+            serde_json::Value::Array(row) => {
+                let fields = row.iter().map(field).collect::<Vec<_>>();
+                push_record(&mut csv, fields.iter().map(String::as_str));
+            }
+            row => {
+                return Err(handlebars::RenderErrorReason::Other(format!(
+                    "The `to-csv` helper's rows must be objects or arrays, but one was `{}`.",
+                    row,
+                )))
+            }
+        }
+    }
+    Ok(csv)
+}
+
+fn field(value: &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::String(value) => value.clone(),
+        serde_json::Value::Null => String::new(),
+        value => value.to_string(),
+    }
+}
+
+fn push_record<'a>(csv: &mut String, fields: impl Iterator<Item = &'a str>) {
+    for (index, field) in fields.enumerate() {
+        if index > 0 {
+            csv.push(',');
+        }
+        if field.contains([',', '"', '\r', '\n']) {
+            csv.push('"');
+            csv.push_str(&field.replace('"', "\"\""));
+            csv.push('"');
+        } else {
+            csv.push_str(field);
+        }
+    }
+    csv.push_str("\r\n");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn rows_are_written_as_csv() {
+        let rows = json!([
+            {"name": "Ann", "note": "says \"hi\", often", "age": 41},
+            {"name": "Bob", "tags": ["a", "b"], "age": null},
+        ]);
+        let rows = rows.as_array().unwrap();
+        assert_eq!(
+            to_csv(rows, Headers::Automatic).unwrap(),
+            "age,name,note,tags\r\n\
+            41,Ann,\"says \"\"hi\"\", often\",\r\n\
+            ,Bob,,\"[\"\"a\"\",\"\"b\"\"]\"\r\n"
+        );
+        assert_eq!(
+            to_csv(rows, Headers::Named(vec![String::from("name")])).unwrap(),
+            "name\r\nAnn\r\nBob\r\n"
+        );
+        assert_eq!(
+            to_csv(rows, Headers::Omitted).unwrap(),
+            "41,Ann,\"says \"\"hi\"\", often\",\r\n\
+            ,Bob,,\"[\"\"a\"\",\"\"b\"\"]\"\r\n"
+        );
+
+        let rows = json!([[1, "two\nlines", true]]);
+        assert_eq!(
+            to_csv(rows.as_array().unwrap(), Headers::Automatic).unwrap(),
+            "1,\"two\nlines\",true\r\n"
+        );
+        assert!(to_csv(json!(["nope"]).as_array().unwrap(), Headers::Automatic).is_err());
+    }
+}
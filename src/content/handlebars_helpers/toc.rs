@@ -0,0 +1,213 @@
+use crate::content::search::{html_text, tag_name};
+use handlebars::{self, Handlebars};
+
+/// Builds a table of contents from the headings in some HTML.
+///
+/// `{{#each (toc (get "/docs/guide"))}}...{{/each}}` iterates over the
+/// top-level headings of the rendered route, each of which is an object with
+/// `level` (1 to 6), `text`, `id`, `url` (like `#installation`), and
+/// `children` (the headings nested beneath it, in the same form). Headings
+/// without an `id` attribute have null `id` and `url`, since there is nothing
+/// to link to. `min-level` and `max-level` limit which headings are included
+/// (e.g. `{{toc html min-level=2 max-level=3}}`).
+pub struct TocHelper;
+
+impl handlebars::HelperDef for TocHelper {
+    fn call_inner<'registry: 'context, 'context>(
+        &self,
+        helper: &handlebars::Helper<'context>,
+        _: &'registry Handlebars<'registry>,
+        _: &'context handlebars::Context,
+        _: &mut handlebars::RenderContext<'registry, 'context>,
+    ) -> Result<handlebars::ScopedJson<'context>, handlebars::RenderError> {
+        let param_0 = helper
+            .param(0)
+            .ok_or_else(|| {
+                handlebars::RenderErrorReason::Other(String::from(
+                    "The `toc` helper requires an argument (some HTML, like `(get \"/route\")`).",
+                ))
+            })?
+            .value();
+        let html = param_0.as_str().ok_or_else(|| {
+            handlebars::RenderErrorReason::Other(format!(
+                "The `toc` helper's argument must be a string of HTML, but it was `{}`.",
+                param_0,
+            ))
+        })?;
+
+        let level_parameter = |name, default| match helper.hash_get(name).map(|value| value.value())
+        {
+            None => Ok(default),
+            Some(value) => value
+                .as_u64()
+                .filter(|level| (1..=6).contains(level))
+                .map(|level| level as u8)
+                .ok_or_else(|| {
+                    handlebars::RenderErrorReason::Other(format!(
+                        "The `toc` helper's `{}` must be a number from 1 to 6, but it was `{}`.",
+                        name, value,
+                    ))
+                }),
+        };
+        let min_level = level_parameter("min-level", 1)?;
+        let max_level = level_parameter("max-level", 6)?;
+
+        let headings = headings(html)
+            .into_iter()
+            .filter(|heading| (min_level..=max_level).contains(&heading.level))
+            .collect::<Vec<_>>();
+
+        Ok(handlebars::ScopedJson::Derived(serde_json::Value::Array(
+            nest(&headings),
+        )))
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct Heading {
+    level: u8,
+    text: String,
+    id: Option<String>,
+}
+
+/// The `<h1>` to `<h6>` elements in `html`, in order.
+fn headings(html: &str) -> Vec<Heading> {
+    let lowercase_html = html.to_ascii_lowercase();
+    let mut headings = Vec::new();
+    let mut position = 0;
+    while let Some(index) = lowercase_html[position..].find("<h") {
+        let tag_start = position + index;
+        position = tag_start + 2;
+        let level = match lowercase_html.as_bytes().get(tag_start + 2) {
+            Some(digit @ b'1'..=b'6') => digit - b'0',
+            _ => continue,
+        };
+        let Some(tag_length) = lowercase_html[tag_start..].find('>') else {
+            break;
+        };
+        let tag = &html[tag_start..tag_start + tag_length + 1];
+        if tag_name(tag) != format!("h{}", level) {
+            continue;
+        }
+
+        let content_start = tag_start + tag.len();
+        let closing_tag = format!("</h{}", level);
+        let content_end = lowercase_html[content_start..]
+            .find(&closing_tag)
+            .map_or(html.len(), |index| content_start + index);
+        let text = html_text(&html[content_start..content_end])
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ");
+        headings.push(Heading {
+            level,
+            text,
+            id: attribute(tag, "id"),
+        });
+        position = content_end;
+    }
+    headings
+}
+
+/// The value of the attribute called `name` in an HTML start tag.
+fn attribute(tag: &str, name: &str) -> Option<String> {
+    let lowercase_tag = tag.to_ascii_lowercase();
+    let mut position = 0;
+    while let Some(index) = lowercase_tag[position..].find(name) {
+        let name_start = position + index;
+        position = name_start + name.len();
+        let preceded_by_space = lowercase_tag[..name_start]
+            .ends_with(|character: char| character.is_ascii_whitespace());
+        let value = tag[position..].trim_start();
+        let Some(value) = value.strip_prefix('=').filter(|_| preceded_by_space) else {
+            continue;
+        };
+        let value = value.trim_start();
+        let value = match value.chars().next() {
+            Some(quote @ ('"' | '\'')) => value[1..].split(quote).next(),
+            _ => value
+                .split(|character: char| character.is_ascii_whitespace() || character == '>')
+                .next(),
+        };
+        return value
+            .filter(|value| !value.is_empty())
+            .map(|value| html_text(value).trim().to_owned());
+    }
+    None
+}
+
+/// Nests each heading beneath the closest preceding heading with a lower
+/// level.
+fn nest(headings: &[Heading]) -> Vec<serde_json::Value> {
+    let mut entries = Vec::new();
+    let mut index = 0;
+    while let Some(heading) = headings.get(index) {
+        let children_end = headings[index + 1..]
+            .iter()
+            .position(|child| child.level <= heading.level)
+            .map_or(headings.len(), |position| index + 1 + position);
+        entries.push(serde_json::json!({
+            "level": heading.level,
+            "text": heading.text,
+            "id": heading.id,
+            "url": heading.id.as_ref().map(|id| format!("#{}", id)),
+            "children": nest(&headings[index + 1..children_end]),
+        }));
+        index = children_end;
+    }
+    entries
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn headings_are_nested() {
+        let html = "<h1>Guide</h1>\n\
+            <p>Intro</p>\n\
+            <H2 class=\"x\" id=\"install\">Installing <code>operator</code></H2>\n\
+            <h3 id='linux'>Linux &amp; BSD</h3>\n\
+            <h2 id=usage>Usage</h2>\n\
+            <header>not a heading</header>\n\
+            <h4 data-id=\"nope\">Deep</h4>";
+        assert_eq!(
+            nest(&headings(html)),
+            vec![json!({
+                "level": 1,
+                "text": "Guide",
+                "id": null,
+                "url": null,
+                "children": [
+                    {
+                        "level": 2,
+                        "text": "Installing operator",
+                        "id": "install",
+                        "url": "#install",
+                        "children": [{
+                            "level": 3,
+                            "text": "Linux & BSD",
+                            "id": "linux",
+                            "url": "#linux",
+                            "children": [],
+                        }],
+                    },
+                    {
+                        "level": 2,
+                        "text": "Usage",
+                        "id": "usage",
+                        "url": "#usage",
+                        "children": [{
+                            "level": 4,
+                            "text": "Deep",
+                            "id": null,
+                            "url": null,
+                            "children": [],
+                        }],
+                    },
+                ],
+            })]
+        );
+    }
+}
@@ -0,0 +1,49 @@
+use crate::content::*;
+use handlebars::{self, Handlebars};
+
+/// Percent-encodes a route for use in a URL.
+///
+/// Routes in render data (like `request.route`) are not encoded, so
+/// `<a href="{{url-path route}}">` should be used when linking to content
+/// whose name may contain spaces or non-ASCII characters.
+pub struct UrlPathHelper;
+
+impl handlebars::HelperDef for UrlPathHelper {
+    fn call_inner<'registry: 'context, 'context>(
+        &self,
+        helper: &handlebars::Helper<'context>,
+        _: &'registry Handlebars<'registry>,
+        _: &'context handlebars::Context,
+        _: &mut handlebars::RenderContext<'registry, 'context>,
+    ) -> Result<handlebars::ScopedJson<'context>, handlebars::RenderError> {
+        let param_0 = helper
+            .param(0)
+            .ok_or_else(|| {
+                handlebars::RenderErrorReason::Other(String::from(
+                    "The `url-path` helper requires an argument (a route).",
+                ))
+            })?
+            .value();
+        let route = param_0
+            .as_str()
+            .ok_or_else(|| {
+                handlebars::RenderErrorReason::Other(format!(
+                    "The `url-path` helper's argument must be a string (a route), but it was \
+                    `{}`.",
+                    param_0,
+                ))
+            })
+            .and_then(|route| {
+                Route::from_decoded(route).map_err(|error| {
+                    handlebars::RenderErrorReason::Other(format!(
+                        "The `url-path` helper's argument (`{}`) must be a valid route: {}",
+                        param_0, error,
+                    ))
+                })
+            })?;
+
+        Ok(handlebars::ScopedJson::Derived(serde_json::Value::String(
+            route.to_url_path(),
+        )))
+    }
+}
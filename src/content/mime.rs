@@ -1,5 +1,5 @@
 use mime::Mime;
-use serde::{Serialize, Serializer};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
 use std::fmt;
 use std::str::FromStr;
 use thiserror::Error;
@@ -27,13 +27,30 @@ impl MediaType {
         } else if media_range.subtype() == "*" {
             self.0.type_() == media_range.type_()
         } else {
-            self == media_range
+            self == media_range || self.is_within_media_range_as_xml(media_range)
         }
     }
 
+    /// Whether this is within `media_range` only because both are XML:
+    /// `media_range` is generic XML (`application/xml` or `text/xml`) and
+    /// this is a different XML media type (like `text/xml` or
+    /// `application/atom+xml`). Clients which accept XML can parse any of it,
+    /// but a representation which is exactly what they asked for is better.
+    pub fn is_within_media_range_as_xml(&self, media_range: &MediaRange) -> bool {
+        let is_generic_xml = |essence| matches!(essence, "application/xml" | "text/xml");
+        self != media_range
+            && is_generic_xml(media_range.essence_str())
+            && (is_generic_xml(self.essence_str()) || self.0.suffix() == Some(::mime::XML))
+    }
+
     pub fn into_media_range(self) -> MediaRange {
         self.0
     }
+
+    /// The type and subtype, without any parameters (e.g. `text/html`).
+    pub fn essence_str(&self) -> &str {
+        self.0.essence_str()
+    }
 }
 
 #[derive(Error, Debug)]
@@ -74,6 +91,18 @@ impl Serialize for MediaType {
     }
 }
 
+impl<'de> Deserialize<'de> for MediaType {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let media_type_essence = String::deserialize(deserializer)?;
+        media_type_essence
+            .parse::<MediaType>()
+            .map_err(de::Error::custom)
+    }
+}
+
 impl PartialEq<MediaRange> for MediaType {
     fn eq(&self, other: &MediaRange) -> bool {
         &self.0 == other
@@ -1,32 +1,66 @@
 mod body;
+mod caching_content_engine;
+mod cancellation;
 mod content_directory;
 mod content_engine;
 mod content_index;
 mod content_item;
 mod content_registry;
+mod content_stats;
+mod dependency_graph;
+#[cfg(feature = "executables")]
+mod executable;
+mod front_matter;
 mod handlebars_helpers;
 mod mime;
+#[cfg(feature = "executables")]
+mod process_group;
+mod redirect;
+mod render_trace;
 mod route;
+mod search;
+mod template_engine;
 mod test_lib;
 
 use crate::bug_message;
 use bytes::Bytes;
 use content_item::RenderingFailedError;
 use futures::Stream;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::io;
+use std::path::PathBuf;
 use thiserror::Error;
 
 pub use self::mime::{MediaRange, MediaType};
-pub use content_directory::ContentDirectory;
+#[cfg(feature = "executables")]
+pub use body::OutputBufferMetrics;
+pub use caching_content_engine::{CachePolicy, CachingContentEngine};
+pub use cancellation::{CancelOnDrop, Cancellation};
+pub use content_directory::{
+    ContentDirectory, ContentDirectoryFromRootError, ContentDirectorySource,
+};
+pub(crate) use content_engine::InternalContentEngine;
 pub use content_engine::{
-    ContentEngine, ContentLoadingError, FilesystemBasedContentEngine, TemplateError,
+    ContentEngine, ContentLoadingError, ContentLoadingErrors, ContentReloadError,
+    FilesystemBasedContentEngine, ReloadedContent, TemplateError,
 };
 pub use content_index::ContentIndex;
-pub use content_item::UnregisteredTemplate;
+pub use content_item::{ContentCoding, UnregisteredTemplate};
 pub use content_registry::{ContentRepresentations, RegisteredContent};
+pub use content_stats::{ContentKind, ContentStats};
+pub use dependency_graph::DependencyGraph;
+pub use front_matter::{
+    fill_missing, ExecutableFrontMatter, FrontMatter, FrontMatterError, FrontMatterSettings,
+};
+#[cfg(all(feature = "executables", feature = "server"))]
+pub(crate) use process_group::kill_all_process_groups;
+pub use redirect::Redirect;
+pub use render_trace::RenderTrace;
+pub(crate) use render_trace::{current_route, trace};
 pub use route::Route;
+pub use search::{SearchIndex, SearchResult};
+pub use template_engine::{Escaping, MiniJinjaEngine, TemplateEngine, TemplateLanguage};
 
 // This is just a trait alias to help make type signatures a bit saner.
 pub trait ByteStream: Stream<Item = Result<Bytes, StreamError>>
@@ -50,6 +84,28 @@ impl<Content: ByteStream> Media<Content> {
     }
 }
 
+/// Rendered content along with how it should be served. This is independent
+/// of any particular HTTP server, so it can be turned into a response by
+/// whatever is in front of the renderer.
+pub struct Response<Content: ByteStream> {
+    pub status: u16,
+
+    /// Header names are lowercase. A name may appear more than once.
+    pub headers: Vec<(String, String)>,
+
+    pub media: Media<Content>,
+}
+impl<Content: ByteStream> Response<Content> {
+    /// The first value of the header called `name` (which is compared
+    /// case-insensitively), if there is one.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header_name, _)| header_name.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
 /// Indicates that it was not possible to produce rendered output, either
 /// because rendering was attempted and failed or because no acceptable media
 /// types are available.
@@ -66,6 +122,31 @@ pub enum RenderError {
     Bug(String),
 }
 
+impl RenderError {
+    /// Which template or executable failed, if that is known.
+    pub fn location(&self) -> Option<FailureLocation> {
+        match self {
+            RenderError::RenderingFailed(error) => error.location(),
+            _ => None,
+        }
+    }
+}
+
+/// Where in the content directory a render failed.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum FailureLocation {
+    /// A template, by its name in the template engine (its path relative to
+    /// the content directory). Lines are counted from the start of the
+    /// template's body, after any front matter.
+    Template { name: String, line: Option<usize> },
+
+    /// An executable, with the directory it was run in.
+    Executable {
+        program: String,
+        working_directory: PathBuf,
+    },
+}
+
 /// Indicates that something went wrong after starting to stream content.
 #[derive(Error, Debug)]
 pub enum StreamError {
@@ -86,6 +167,12 @@ pub enum StreamError {
     #[error("Executable output could not be captured")]
     ExecutableOutputCouldNotBeCaptured { pid: u32 },
 
+    #[error("Process was killed after its output exceeded the limit of {} bytes", .limit)]
+    ExecutableOutputTooLarge { pid: u32, limit: u64 },
+
+    #[error("Could not send file '{}': {}", .path, .message)]
+    SendfileFailed { path: String, message: String },
+
     #[error("Input/output error during rendering")]
     IOError {
         #[from]
@@ -105,7 +192,7 @@ pub trait Render {
     ) -> Result<Media<Self::Output>, RenderError>
     where
         ServerInfo: Clone + Serialize,
-        Engine: ContentEngine<ServerInfo>,
+        Engine: ContentEngine<ServerInfo> + ?Sized,
         Accept: IntoIterator<Item = &'accept MediaRange>,
         Self::Output: ByteStream;
 }
@@ -113,10 +200,18 @@ pub trait Render {
 // These must match up with serialized property names in RequestData and
 // RenderData.
 const TARGET_MEDIA_TYPE_PROPERTY_NAME: &str = "target-media-type";
+const FRONT_MATTER_PROPERTY_NAME: &str = "front-matter";
+const SITE_PROPERTY_NAME: &str = "site";
 const REQUEST_DATA_PROPERTY_NAME: &str = "request";
 const ROUTE_PROPERTY_NAME: &str = "route";
 const QUERY_PARAMETERS_PROPERTY_NAME: &str = "query-parameters";
+const QUERY_PARAMETER_LISTS_PROPERTY_NAME: &str = "query-parameter-lists";
 const REQUEST_HEADERS_PROPERTY_NAME: &str = "request-headers";
+const BASE_URL_PROPERTY_NAME: &str = "base-url";
+const URI_PATH_PROPERTY_NAME: &str = "uri-path";
+const ORIGIN_PROPERTY_NAME: &str = "origin";
+const PATH_PREFIX_PROPERTY_NAME: &str = "path-prefix";
+const HTTP_REQUEST_DATA_PROPERTY_NAME: &str = "http";
 
 /// Render data that comes from requests.
 #[derive(Clone, Serialize)]
@@ -125,11 +220,143 @@ pub struct RequestData {
     /// The request [`Route`] that caused this content to be rendered, if any.
     pub route: Option<Route>,
 
-    /// A parsed version of the request URI's query string.
+    /// A parsed version of the request URI's query string. If a key appears
+    /// more than once then the last value is used.
     pub query_parameters: HashMap<String, String>,
 
+    /// Every value for each key in the request URI's query string, in order.
+    /// Keys written like `key[]` are listed without the brackets.
+    pub query_parameter_lists: HashMap<String, Vec<String>>,
+
     /// Headers that were sent in the request.
     pub request_headers: HashMap<String, String>,
+
+    /// The `base-url` setting, without a trailing slash.
+    pub base_url: Option<String>,
+
+    /// The route prefix of the mounted content directory that the request
+    /// route is in, if any. Content in a mounted directory can use this to
+    /// link to its siblings without knowing where it was mounted.
+    pub mount_prefix: Option<Route>,
+
+    /// Details which are only known for HTTP requests.
+    pub http: HttpRequestData,
+}
+
+/// Render data that is only available when rendering for an HTTP request
+/// (everything is empty when using the CLI).
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct HttpRequestData {
+    /// The path of the request URI exactly as it was sent, before the
+    /// extension is removed or the index route is substituted.
+    pub uri_path: Option<String>,
+
+    /// The query string of the request URI, without parsing (or the `?`).
+    pub query_string: Option<String>,
+
+    /// Either `http` or `https`. Behind a trusted proxy this is the scheme
+    /// the client used (from `X-Forwarded-Proto`).
+    pub scheme: Option<String>,
+
+    /// The value of the `Host` request header, or of `X-Forwarded-Host` if
+    /// the request came through a trusted proxy.
+    pub host: Option<String>,
+
+    /// The scheme and host (like `https://example.com`), but only if the host
+    /// can be trusted: it was forwarded by a trusted proxy or is one of the
+    /// virtual hosts. Clients can send any `Host` header, so absolute URLs are
+    /// only built from this (when the `base-url` setting is missing).
+    pub origin: Option<String>,
+
+    /// The URL path that the site is served under (like `/app`), if it is
+    /// behind a reverse proxy. This never has a trailing slash, so links can
+    /// be written like `{{request.http.path-prefix}}/page`.
+    pub path_prefix: Option<String>,
+
+    /// The extension from the request URI (without the `.`) if it was used to
+    /// choose the media type, as in `/resume.pdf`.
+    pub url_extension: Option<String>,
+
+    /// Where the acceptable media types came from: either `url-extension` or
+    /// `accept-header`.
+    pub negotiation_source: Option<String>,
+}
+
+impl RequestData {
+    pub fn new(
+        route: Option<Route>,
+        query_parameters: QueryParameters,
+        request_headers: HashMap<String, String>,
+    ) -> Self {
+        RequestData {
+            route,
+            query_parameters: query_parameters.last_values(),
+            query_parameter_lists: query_parameters.lists(),
+            request_headers,
+            base_url: None,
+            mount_prefix: None,
+            http: HttpRequestData::default(),
+        }
+    }
+
+    pub fn with_base_url(self, base_url: Option<&str>) -> Self {
+        RequestData {
+            base_url: base_url.map(|base_url| String::from(base_url.trim_end_matches('/'))),
+            ..self
+        }
+    }
+
+    pub fn with_mount_prefix(self, mount_prefix: Option<Route>) -> Self {
+        RequestData {
+            mount_prefix,
+            ..self
+        }
+    }
+}
+
+/// Parameters from a request URI's query string, in their original order.
+/// Keys may be repeated.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct QueryParameters(Vec<(String, String)>);
+
+impl QueryParameters {
+    pub fn new(pairs: Vec<(String, String)>) -> Self {
+        QueryParameters(pairs)
+    }
+
+    /// The last value for each key.
+    pub fn last_values(&self) -> HashMap<String, String> {
+        self.0.iter().cloned().collect()
+    }
+
+    /// Replaces all values for `key` (including any written as `key[]`).
+    pub fn set(&mut self, key: &str, values: Vec<String>) {
+        self.0.retain(|(existing_key, _)| {
+            existing_key.strip_suffix("[]").unwrap_or(existing_key) != key
+        });
+        self.0
+            .extend(values.into_iter().map(|value| (String::from(key), value)));
+    }
+
+    /// All values for each key. `key[]` is treated as `key`.
+    pub fn lists(&self) -> HashMap<String, Vec<String>> {
+        let mut lists = HashMap::<String, Vec<String>>::new();
+        for (key, value) in &self.0 {
+            let key = key.strip_suffix("[]").unwrap_or(key);
+            lists
+                .entry(String::from(key))
+                .or_default()
+                .push(value.clone());
+        }
+        lists
+    }
+}
+
+impl From<HashMap<String, String>> for QueryParameters {
+    fn from(query_parameters: HashMap<String, String>) -> Self {
+        QueryParameters(query_parameters.into_iter().collect())
+    }
 }
 
 /// Data passed to handlebars templates and executables.
@@ -153,6 +380,19 @@ pub struct RenderData<ServerInfo: Clone + Serialize> {
     /// Data that comes from requests.
     pub request: RequestData,
 
+    /// Data from the `site` settings in the config file which apply to the
+    /// request route.
+    pub site: serde_json::Map<String, serde_json::Value>,
+
+    /// Parsed YAML, JSON, and TOML files from the `_data` directory, keyed
+    /// by their names without extensions. Files in subdirectories are nested
+    /// (e.g. `_data/authors/alice.yaml` is at `data.authors.alice`).
+    pub data: serde_json::Map<String, serde_json::Value>,
+
+    /// Properties from the front matter of the template being rendered. This
+    /// is not set when rendering other kinds of content.
+    pub front_matter: Option<serde_json::Map<String, serde_json::Value>>,
+
     /// An [HTTP `4xx` or `5xx` status code](https://datatracker.ietf.org/doc/html/rfc7231#section-6)
     /// indicating that something went wrong. This will be set while rendering
     /// content for the `--error-handler-route`.
@@ -164,17 +404,20 @@ pub struct RenderData<ServerInfo: Clone + Serialize> {
 pub struct RenderContext<'engine, ServerInfo, Engine>
 where
     ServerInfo: Clone + Serialize,
-    Engine: ContentEngine<ServerInfo>,
+    Engine: ContentEngine<ServerInfo> + ?Sized,
 {
     content_engine: &'engine Engine,
     data: RenderData<ServerInfo>,
     handlebars_render_context: Option<handlebars::RenderContext<'engine, 'engine>>,
+    /// Only executables have caches.
+    #[cfg_attr(not(feature = "executables"), allow(dead_code))]
+    refresh_caches: bool,
 }
 
 impl<'engine, ServerInfo, Engine> RenderContext<'engine, ServerInfo, Engine>
 where
     ServerInfo: Clone + Serialize,
-    Engine: ContentEngine<ServerInfo>,
+    Engine: ContentEngine<ServerInfo> + ?Sized,
 {
     pub fn into_error_context(self, error_code: u16) -> Self {
         RenderContext {
@@ -186,6 +429,27 @@ where
         }
     }
 
+    /// Adds `data` alongside the data from the `_data` directory, replacing
+    /// any entries with the same names.
+    pub fn with_data(mut self, data: serde_json::Map<String, serde_json::Value>) -> Self {
+        self.data.data.extend(data);
+        self
+    }
+
+    pub fn with_http_request_data(mut self, http_request_data: HttpRequestData) -> Self {
+        self.data.request.http = http_request_data;
+        self
+    }
+
+    /// Runs executables even if they have cached output, replacing it with
+    /// their new output (this is how scheduled tasks keep caches fresh).
+    pub fn refreshing_caches(self) -> Self {
+        RenderContext {
+            refresh_caches: true,
+            ..self
+        }
+    }
+
     pub fn with_handlebars_render_context(
         self,
         handlebars_render_context: handlebars::RenderContext<'engine, 'engine>,
@@ -0,0 +1,206 @@
+//! Executables are started in their own process groups, so that anything
+//! they start can be killed along with them instead of being orphaned.
+
+use std::collections::BTreeSet;
+use std::io::{self, Read};
+use std::process::{Child, Command, ExitStatus, Output};
+use std::sync::Mutex;
+use std::thread;
+
+/// The groups of executables which have not been waited for yet.
+static RUNNING_GROUPS: Mutex<BTreeSet<u32>> = Mutex::new(BTreeSet::new());
+
+/// Makes the program started by `command` the leader of a new process group.
+pub(super) fn isolate(command: &mut Command) {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        command.process_group(0);
+    }
+    #[cfg(not(unix))]
+    let _ = command;
+}
+
+/// Kills the groups of every executable which is still running, e.g. when the
+/// server shuts down.
+#[cfg(feature = "server")]
+pub(crate) fn kill_all_process_groups() {
+    let group_ids = match RUNNING_GROUPS.lock() {
+        Ok(running_groups) => running_groups.clone(),
+        Err(_) => return,
+    };
+    for group_id in group_ids {
+        log::debug!("Killing process group {}", group_id);
+        signal_group(group_id);
+    }
+}
+
+/// A child process which leads its own process group (because its command
+/// was passed to [`isolate`]). Unless the child has been waited for, the
+/// whole group is killed when this is dropped.
+pub(super) struct ProcessGroup {
+    child: Child,
+    exited: bool,
+}
+impl ProcessGroup {
+    pub fn new(child: Child) -> Self {
+        if let Ok(mut running_groups) = RUNNING_GROUPS.lock() {
+            running_groups.insert(child.id());
+        }
+        ProcessGroup {
+            child,
+            exited: false,
+        }
+    }
+
+    pub fn child(&mut self) -> &mut Child {
+        &mut self.child
+    }
+
+    /// Like [`Child::try_wait`]. Once the child has exited, the rest of its
+    /// group is left alone.
+    pub fn try_wait(&mut self) -> Result<Option<ExitStatus>, io::Error> {
+        let status = self.child.try_wait()?;
+        if status.is_some() {
+            self.forget();
+        }
+        Ok(status)
+    }
+
+    /// Like [`Child::wait`]. Once the child has exited, the rest of its group
+    /// is left alone.
+    pub fn wait(&mut self) -> Result<ExitStatus, io::Error> {
+        let status = self.child.wait()?;
+        self.forget();
+        Ok(status)
+    }
+
+    /// Like [`Child::wait_with_output`]. Stderr is read on another thread so
+    /// that the child cannot block on either pipe.
+    ///
+    /// If the child writes more than `stdout_limit` bytes to stdout, the
+    /// group is killed as soon as that is noticed and `None` is returned.
+    pub fn wait_with_output(
+        &mut self,
+        stdout_limit: Option<u64>,
+    ) -> Result<Option<Output>, io::Error> {
+        let stderr_reader = self.child.stderr.take().map(|mut stderr| {
+            thread::spawn(move || {
+                let mut stderr_contents = Vec::new();
+                stderr
+                    .read_to_end(&mut stderr_contents)
+                    .map(|_| stderr_contents)
+            })
+        });
+        let mut stdout = Vec::new();
+        if let Some(child_stdout) = self.child.stdout.take() {
+            // Reading one byte past the limit is enough to tell that it was
+            // exceeded.
+            child_stdout
+                .take(stdout_limit.map_or(u64::MAX, |limit| limit.saturating_add(1)))
+                .read_to_end(&mut stdout)?;
+            if stdout_limit.is_some_and(|limit| stdout.len() as u64 > limit) {
+                // The stderr reader is not joined, since something outside
+                // the group could still be holding the pipe open.
+                self.kill();
+                return Ok(None);
+            }
+        }
+        let status = self.wait()?;
+        let stderr = match stderr_reader {
+            None => Vec::new(),
+            Some(stderr_reader) => stderr_reader.join().unwrap_or_else(|_| Ok(Vec::new()))?,
+        };
+        Ok(Some(Output {
+            status,
+            stdout,
+            stderr,
+        }))
+    }
+
+    /// Kills the child and everything else in its group, then waits for the
+    /// child. Errors are ignored because the processes may have already
+    /// exited on their own.
+    pub fn kill(&mut self) {
+        if self.exited {
+            return;
+        }
+        // The child has not been waited for, so its ID (which is also the
+        // group's ID) cannot have been reused.
+        signal_group(self.child.id());
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        self.forget();
+    }
+
+    fn forget(&mut self) {
+        self.exited = true;
+        if let Ok(mut running_groups) = RUNNING_GROUPS.lock() {
+            running_groups.remove(&self.child.id());
+        }
+    }
+}
+impl Drop for ProcessGroup {
+    fn drop(&mut self) {
+        self.kill();
+    }
+}
+
+#[cfg(unix)]
+fn signal_group(group_id: u32) {
+    if let Ok(group_id) = libc::pid_t::try_from(group_id) {
+        // SAFETY: kill has no memory safety requirements. A negative ID
+        // signals every process in that group.
+        unsafe {
+            libc::kill(-group_id, libc::SIGKILL);
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn signal_group(_: u32) {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader};
+    use std::process::Stdio;
+    use std::time::{Duration, Instant};
+
+    /// Whether `process_id` is a process which has not exited. Zombies (which
+    /// may not be reaped in some containers) count as having exited.
+    #[cfg(target_os = "linux")]
+    fn is_running(process_id: libc::pid_t) -> bool {
+        std::fs::read_to_string(format!("/proc/{}/stat", process_id)).is_ok_and(|stat| {
+            stat.rsplit_once(')')
+                .is_some_and(|(_, fields)| !fields.trim_start().starts_with('Z'))
+        })
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn descendants_are_killed_with_their_group() {
+        let mut command = Command::new("sh");
+        command
+            .args(["-c", "sleep 60 & echo $!; wait"])
+            .stdout(Stdio::piped());
+        isolate(&mut command);
+        let mut child = command.spawn().expect("Failed to spawn process");
+        let mut line = String::new();
+        BufReader::new(child.stdout.take().unwrap())
+            .read_line(&mut line)
+            .expect("Failed to read grandchild process ID");
+        let grandchild_id = line.trim().parse::<libc::pid_t>().unwrap();
+
+        drop(ProcessGroup::new(child));
+
+        let started_at = Instant::now();
+        while is_running(grandchild_id) {
+            assert!(
+                started_at.elapsed() < Duration::from_secs(10),
+                "The grandchild process was not killed"
+            );
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+}
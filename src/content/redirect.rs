@@ -0,0 +1,119 @@
+use thiserror::Error;
+
+/// The status code used when a redirect file does not specify one.
+const DEFAULT_STATUS_CODE: u16 = 301;
+
+const STATUS_CODES: [u16; 5] = [301, 302, 303, 307, 308];
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum RedirectError {
+    #[error("Redirect files must contain a target route or URL, but this one is empty.")]
+    MissingLocation,
+
+    #[error(
+        "Redirect targets must be routes (starting with `/`) or absolute URLs, but `{}` is not.",
+        .0
+    )]
+    InvalidLocation(String),
+
+    #[error(
+        "`{}` is not a redirect status code (use one of {}).",
+        .0,
+        STATUS_CODES.map(|code| code.to_string()).join(", ")
+    )]
+    InvalidStatusCode(String),
+
+    #[error("Redirect files can only contain a target and a status code, but there was also `{}`.", .0)]
+    UnexpectedContent(String),
+}
+
+/// Content which sends clients elsewhere, from a file like
+/// `old-page.redirect`.
+///
+/// The file contains the target (a route like `/new-page` or an absolute URL),
+/// optionally followed by whitespace and a status code (301, 302, 303, 307, or
+/// 308). The status code defaults to 301.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Redirect {
+    location: String,
+    status_code: u16,
+}
+
+impl Redirect {
+    pub fn parse(source: &str) -> Result<Self, RedirectError> {
+        let mut words = source.split_whitespace();
+        let location = words.next().ok_or(RedirectError::MissingLocation)?;
+        if !location.starts_with('/') && !location.contains("://") {
+            return Err(RedirectError::InvalidLocation(String::from(location)));
+        }
+        let status_code = match words.next() {
+            None => DEFAULT_STATUS_CODE,
+            Some(status_code) => status_code
+                .parse::<u16>()
+                .ok()
+                .filter(|status_code| STATUS_CODES.contains(status_code))
+                .ok_or_else(|| RedirectError::InvalidStatusCode(String::from(status_code)))?,
+        };
+        if let Some(unexpected) = words.next() {
+            return Err(RedirectError::UnexpectedContent(String::from(unexpected)));
+        }
+        Ok(Redirect {
+            location: String::from(location),
+            status_code,
+        })
+    }
+
+    /// The value for the `Location` header.
+    pub fn location(&self) -> &str {
+        &self.location
+    }
+
+    pub fn status_code(&self) -> u16 {
+        self.status_code
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_log::test;
+
+    #[test]
+    fn redirects_can_be_parsed() {
+        for (source, location, status_code) in [
+            ("/new-page", "/new-page", 301),
+            ("/new-page\n", "/new-page", 301),
+            ("https://example.com/ 302\n", "https://example.com/", 302),
+            ("  /a\n308", "/a", 308),
+        ] {
+            let redirect = Redirect::parse(source).expect("Redirect was invalid");
+            assert_eq!(redirect.location(), location);
+            assert_eq!(redirect.status_code(), status_code);
+        }
+    }
+
+    #[test]
+    fn invalid_redirects_are_errors() {
+        for (source, expected_error) in [
+            ("", RedirectError::MissingLocation),
+            (
+                "new-page",
+                RedirectError::InvalidLocation(String::from("new-page")),
+            ),
+            (
+                "/new-page 200",
+                RedirectError::InvalidStatusCode(String::from("200")),
+            ),
+            (
+                "/new-page moved",
+                RedirectError::InvalidStatusCode(String::from("moved")),
+            ),
+            (
+                "/new-page 301 extra",
+                RedirectError::UnexpectedContent(String::from("extra")),
+            ),
+        ] {
+            assert_eq!(Redirect::parse(source), Err(expected_error));
+        }
+    }
+}
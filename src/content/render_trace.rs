@@ -0,0 +1,161 @@
+use super::Route;
+use crate::bug_message;
+use std::cell::RefCell;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+thread_local! {
+    /// Traces which are still being rendered on this thread, outermost
+    /// first. Nested renders happen synchronously (the `get` helper blocks
+    /// until included content is done), so a stack is enough to know which
+    /// render included which.
+    static IN_PROGRESS: RefCell<Vec<RenderTrace>> = const { RefCell::new(Vec::new()) };
+}
+
+/// How long it took to render a route, along with the routes it included via
+/// the `get` helper.
+#[derive(Clone, Debug)]
+pub struct RenderTrace {
+    pub route: Route,
+    pub duration: Duration,
+    pub children: Vec<RenderTrace>,
+}
+
+impl RenderTrace {
+    /// The trace as an HTML comment. Routes cannot end the comment early.
+    pub fn to_html_comment(&self) -> String {
+        format!(
+            "<!-- render trace\n{}-->\n",
+            self.to_string().replace("--", "-&#45;")
+        )
+    }
+
+    fn write_indented(&self, formatter: &mut fmt::Formatter, depth: usize) -> fmt::Result {
+        writeln!(
+            formatter,
+            "{:indent$}{} ({:.1?})",
+            "",
+            self.route,
+            self.duration,
+            indent = depth * 2
+        )?;
+        for child in &self.children {
+            child.write_indented(formatter, depth + 1)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for RenderTrace {
+    /// One line per route, with included routes indented beneath the route
+    /// that included them.
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        self.write_indented(formatter, 0)
+    }
+}
+
+/// Runs `render` (which renders `route`) and records how long it took. Any
+/// traced renders that happen inside `render` become children of the
+/// returned trace, and the returned trace becomes a child of whatever traced
+/// render this is inside of.
+pub(crate) fn trace<Output>(
+    route: &Route,
+    render: impl FnOnce() -> Output,
+) -> (Output, RenderTrace) {
+    let depth = IN_PROGRESS.with(|in_progress| {
+        let mut in_progress = in_progress.borrow_mut();
+        in_progress.push(RenderTrace {
+            route: route.clone(),
+            duration: Duration::ZERO,
+            children: Vec::new(),
+        });
+        in_progress.len()
+    });
+    let _unwind_guard = UnwindGuard { depth };
+    let started_at = Instant::now();
+
+    let output = render();
+
+    let trace = IN_PROGRESS.with(|in_progress| {
+        let mut in_progress = in_progress.borrow_mut();
+        in_progress.truncate(depth);
+        let mut trace = in_progress
+            .pop()
+            .expect(bug_message!("Render trace was missing from the stack"));
+        trace.duration = started_at.elapsed();
+        if let Some(parent) = in_progress.last_mut() {
+            parent.children.push(trace.clone());
+        }
+        trace
+    });
+    (output, trace)
+}
+
+/// The route of the innermost traced render in progress on this thread.
+pub(crate) fn current_route() -> Option<Route> {
+    IN_PROGRESS.with(|in_progress| in_progress.borrow().last().map(|trace| trace.route.clone()))
+}
+
+/// Keeps the stack of in-progress traces balanced if rendering panics.
+struct UnwindGuard {
+    depth: usize,
+}
+impl Drop for UnwindGuard {
+    fn drop(&mut self) {
+        IN_PROGRESS.with(|in_progress| in_progress.borrow_mut().truncate(self.depth - 1));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_lib::*;
+    use test_log::test;
+
+    #[test]
+    fn nested_renders_are_traced() {
+        let ((), trace) = trace(&route("/page"), || {
+            trace(&route("/widget"), || {
+                trace(&route("/icon"), || ());
+            });
+            trace(&route("/footer"), || ());
+        });
+
+        assert_eq!(trace.route, route("/page"));
+        let children = trace
+            .children
+            .iter()
+            .map(|child| child.route.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(children, vec![route("/widget"), route("/footer")]);
+        assert_eq!(trace.children[0].children[0].route, route("/icon"));
+        assert!(trace.duration >= trace.children[0].duration);
+
+        let lines = trace
+            .to_string()
+            .lines()
+            .map(|line| line.split(" (").next().unwrap().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(lines, vec!["/page", "  /widget", "    /icon", "  /footer"]);
+    }
+
+    #[test]
+    fn traces_are_not_left_behind_by_panics() {
+        let panicked = std::panic::catch_unwind(|| {
+            trace(&route("/broken"), || panic!("oh no"));
+        });
+        assert!(panicked.is_err());
+
+        let ((), trace) = trace(&route("/fine"), || ());
+        IN_PROGRESS.with(|in_progress| assert!(in_progress.borrow().is_empty()));
+        assert_eq!(trace.route, route("/fine"));
+    }
+
+    #[test]
+    fn html_comments_cannot_be_closed_by_routes() {
+        let ((), trace) = trace(&route("/a--b"), || ());
+        let comment = trace.to_html_comment();
+        assert!(comment.starts_with("<!-- render trace\n/a-&#45;b ("));
+        assert_eq!(comment.matches("--").count(), 2);
+    }
+}
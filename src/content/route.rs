@@ -1,4 +1,5 @@
-use serde::Serialize;
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, CONTROLS};
+use serde::{de, Deserialize, Deserializer, Serialize};
 use std::fmt;
 use std::str::FromStr;
 use thiserror::Error;
@@ -10,22 +11,72 @@ pub struct InvalidRouteError {
     message: String,
 }
 
+/// Characters which are percent-encoded when a route is used in a URL. This is
+/// the WHATWG path percent-encode set plus `%` (so that decoding gets back the
+/// original route) and `\` (which some clients treat as a separator).
+/// Non-ASCII characters are always encoded.
+const PATH_COMPONENT_ENCODE_SET: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'\\')
+    .add(b'`')
+    .add(b'{')
+    .add(b'}');
+
+fn encode_path(path: &str) -> String {
+    path.split('/')
+        .map(|component| utf8_percent_encode(component, PATH_COMPONENT_ENCODE_SET).to_string())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Normalizes an absolute URI path as described in RFC 3986 (section 6.2.2),
+/// but with percent-encoded characters decoded rather than just having their
+/// case normalized. `.` and `..` components are resolved (`..` never goes
+/// above the root) and empty components are removed.
+///
+/// Paths containing control characters (including NUL) or an encoded `/` are
+/// rejected, since no content can be named with them.
 fn canonicalize_route(route: &str) -> Result<String, InvalidRouteError> {
-    if !route.starts_with('/') {
-        Err(InvalidRouteError {
-            route: String::from(route),
-            message: String::from("Routes must be absolute (start with a '/')"),
-        })
-    } else {
-        let canonicalized_components = route.split('/').filter(|component| !component.is_empty());
+    let invalid = |message: &str| InvalidRouteError {
+        route: String::from(route),
+        message: String::from(message),
+    };
 
-        let canonicalized_route = canonicalized_components.collect::<Vec<&str>>().join("/");
+    if !route.starts_with('/') {
+        return Err(invalid("Routes must be absolute (start with a '/')"));
+    }
 
-        Ok(format!("/{}", canonicalized_route))
+    let mut canonicalized_components = Vec::new();
+    for component in route.split('/') {
+        let decoded_component = percent_decode_str(component)
+            .decode_utf8()
+            .map_err(|_| invalid("Routes must be valid UTF-8 once percent-decoded"))?;
+        if decoded_component.chars().any(char::is_control) {
+            return Err(invalid("Routes cannot contain control characters"));
+        }
+        if decoded_component.contains('/') {
+            return Err(invalid("Routes cannot contain encoded slashes"));
+        }
+        match decoded_component.as_ref() {
+            "" | "." => {}
+            ".." => {
+                canonicalized_components.pop();
+            }
+            _ => canonicalized_components.push(decoded_component),
+        }
     }
+
+    Ok(format!("/{}", canonicalized_components.join("/")))
 }
 
-/// A canonicalized absolute URI path.
+/// A canonicalized absolute URI path. Routes are stored with percent-encoded
+/// characters decoded, and never contain `.` or `..` components.
 #[derive(Debug, Clone, Hash, Eq, Ord, PartialEq, PartialOrd, Serialize)]
 pub struct Route(String);
 impl FromStr for Route {
@@ -34,6 +85,87 @@ impl FromStr for Route {
         canonicalize_route(input).map(Route)
     }
 }
+impl Route {
+    /// Creates a route from a path which is not percent-encoded, like a file
+    /// path or the string form of another route. Characters like `%` are taken
+    /// literally rather than being decoded.
+    pub fn from_decoded(path: &str) -> Result<Route, InvalidRouteError> {
+        encode_path(path).parse::<Route>()
+    }
+
+    /// This route as a percent-encoded URL path, suitable for links and HTTP
+    /// requests. Use this instead of the route's `Display` implementation
+    /// whenever the output is a URL.
+    pub fn to_url_path(&self) -> String {
+        encode_path(&self.0)
+    }
+
+    /// Whether this route is `ancestor` or a descendant of it. For example
+    /// `/foo/bar` is within `/foo`, but `/foobar` is not.
+    pub fn is_within(&self, ancestor: &Route) -> bool {
+        let ancestor = ancestor.as_ref();
+        ancestor == "/"
+            || self.0 == ancestor
+            || (self.0.starts_with(ancestor) && self.0[ancestor.len()..].starts_with('/'))
+    }
+
+    /// Appends `segment` (which may contain slashes) to this route. The
+    /// result is always within this route, so `segment` must not contain `.`
+    /// or `..` components (including percent-encoded ones) or backslashes.
+    pub fn join(&self, segment: &str) -> Result<Route, InvalidRouteError> {
+        let invalid = |message: &str| InvalidRouteError {
+            route: format!("{}/{}", self.0.trim_end_matches('/'), segment),
+            message: String::from(message),
+        };
+        for component in segment.split('/') {
+            if is_dot_segment(component) {
+                return Err(invalid("Route segments cannot be '.' or '..'"));
+            }
+            if component.contains('\\') || component.chars().any(char::is_control) {
+                return Err(invalid(
+                    "Route segments cannot contain backslashes or control characters",
+                ));
+            }
+        }
+        // This route is already decoded, so only `segment` is parsed.
+        let Route(relative) = format!("/{}", segment).parse::<Route>()?;
+        if self.0 == "/" {
+            Ok(Route(relative))
+        } else if relative == "/" {
+            Ok(self.clone())
+        } else {
+            Ok(Route(format!("{}{}", self.0, relative)))
+        }
+    }
+
+    /// Parses a route like [`FromStr`], except that `.` and `..` components
+    /// (including percent-encoded ones) are rejected rather than resolved.
+    pub fn parse_without_dot_segments(input: &str) -> Result<Route, InvalidRouteError> {
+        if input.split('/').any(is_dot_segment) {
+            Err(InvalidRouteError {
+                route: String::from(input),
+                message: String::from("Route components cannot be '.' or '..'"),
+            })
+        } else {
+            input.parse::<Route>()
+        }
+    }
+}
+
+fn is_dot_segment(component: &str) -> bool {
+    let decoded = percent_decode_str(component).decode_utf8_lossy();
+    decoded == "." || decoded == ".."
+}
+
+impl<'de> Deserialize<'de> for Route {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let route = String::deserialize(deserializer)?;
+        route.parse::<Route>().map_err(de::Error::custom)
+    }
+}
 impl AsRef<str> for Route {
     fn as_ref(&self) -> &str {
         &self.0
@@ -81,6 +213,146 @@ mod tests {
         }
     }
 
+    #[test]
+    fn routes_can_be_within_other_routes() {
+        let foo = "/foo".parse::<Route>().unwrap();
+        let foo_bar = "/foo/bar".parse::<Route>().unwrap();
+        let foobar = "/foobar".parse::<Route>().unwrap();
+        let root = "/".parse::<Route>().unwrap();
+
+        assert!(foo_bar.is_within(&foo));
+        assert!(foo.is_within(&foo));
+        assert!(foo.is_within(&root));
+        assert!(!foobar.is_within(&foo));
+        assert!(!foo.is_within(&foo_bar));
+        assert!(!root.is_within(&foo));
+    }
+
+    #[test]
+    fn routes_can_be_joined() {
+        let blog = "/blog".parse::<Route>().unwrap();
+        let root = "/".parse::<Route>().unwrap();
+
+        assert_eq!(blog.join("hello").unwrap().as_ref(), "/blog/hello");
+        assert_eq!(
+            blog.join("2024/hello/").unwrap().as_ref(),
+            "/blog/2024/hello"
+        );
+        assert_eq!(root.join("hello").unwrap().as_ref(), "/hello");
+
+        for traversal in [
+            "..",
+            ".",
+            "../secret",
+            "a/../../b",
+            "%2e%2E",
+            ".%2e/x",
+            "a\\b",
+        ] {
+            assert!(
+                blog.join(traversal).is_err(),
+                "Joining `{}` succeeded, but it should have failed",
+                traversal,
+            );
+        }
+    }
+
+    #[test]
+    fn routes_are_percent_decoded() {
+        assert_eq!(
+            "/hello%20world/%E2%98%83"
+                .parse::<Route>()
+                .unwrap()
+                .as_ref(),
+            "/hello world/☃"
+        );
+        assert_eq!(
+            "/hello%20world".parse::<Route>().unwrap(),
+            "/hello world".parse::<Route>().unwrap()
+        );
+    }
+
+    #[test]
+    fn routes_can_be_created_from_decoded_paths() {
+        assert_eq!(
+            Route::from_decoded("/100% real/café").unwrap().as_ref(),
+            "/100% real/café"
+        );
+        assert_eq!(
+            Route::from_decoded("/hello%20world").unwrap().as_ref(),
+            "/hello%20world"
+        );
+        assert!(Route::from_decoded("/null\0byte").is_err());
+    }
+
+    #[test]
+    fn routes_are_percent_encoded_in_urls() {
+        let cases = [
+            ("/", "/"),
+            ("/foo/bar", "/foo/bar"),
+            ("/hello world", "/hello%20world"),
+            ("/café/日本", "/caf%C3%A9/%E6%97%A5%E6%9C%AC"),
+            ("/100%?#", "/100%25%3F%23"),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(Route::from_decoded(input).unwrap().to_url_path(), expected);
+        }
+    }
+
+    #[test]
+    fn dot_segments_are_resolved() {
+        let cases = [
+            ("/foo/./bar", "/foo/bar"),
+            ("/foo/../bar", "/bar"),
+            ("/foo/bar/..", "/foo"),
+            ("/..", "/"),
+            ("/../../foo", "/foo"),
+            ("/foo/%2e%2E/bar", "/bar"),
+            ("/foo/%2E/bar", "/foo/bar"),
+            ("/foo/.bar/..baz", "/foo/.bar/..baz"),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(
+                input.parse::<Route>().unwrap().as_ref(),
+                expected,
+                "`{}` was not normalized correctly",
+                input,
+            );
+        }
+    }
+
+    #[test]
+    fn unrepresentable_routes_fail() {
+        for input in [
+            "/foo%00bar",
+            "/foo\0bar",
+            "/foo%0Abar",
+            "/foo\nbar",
+            "/foo%7F",
+            "/foo%2Fbar",
+            "/foo%2f..%2fbar",
+            "/%FF",
+        ] {
+            assert!(
+                input.parse::<Route>().is_err(),
+                "Parsing `{:?}` succeeded, but it should have failed",
+                input,
+            );
+        }
+    }
+
+    #[test]
+    fn dot_segments_can_be_rejected() {
+        assert!(Route::parse_without_dot_segments("/foo/bar").is_ok());
+        for input in ["/foo/../bar", "/foo/./bar", "/%2e%2e/bar", "/.."] {
+            assert!(
+                Route::parse_without_dot_segments(input).is_err(),
+                "Parsing `{}` succeeded, but it should have failed",
+                input,
+            );
+        }
+    }
+
     #[test]
     fn root_route_can_exist() {
         let one_slash_result = "/".parse::<Route>();
@@ -95,4 +367,65 @@ mod tests {
         assert_eq!(one_slash_route.as_ref(), "/");
         assert_eq!(buncha_slashes_route.as_ref(), "/");
     }
+
+    mod properties {
+        use super::super::Route;
+        use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+        use proptest::prelude::*;
+
+        /// Paths made of characters which are interesting to the parser.
+        fn path() -> impl Strategy<Value = String> {
+            proptest::string::string_regex(
+                "/([a-z./\\\\%\u{0}\n ☃]|%2[eEfF]|%00|%[0-9A-F]{2}){0,24}",
+            )
+            .unwrap()
+        }
+
+        proptest! {
+            #[test]
+            fn parsing_never_panics(input in any::<String>()) {
+                let _ = input.parse::<Route>();
+            }
+
+            #[test]
+            fn routes_are_normalized(input in path()) {
+                if let Ok(route) = input.parse::<Route>() {
+                    let route = route.as_ref();
+                    prop_assert!(route.starts_with('/'));
+                    prop_assert!(route == "/" || !route.ends_with('/'));
+                    prop_assert!(!route.contains("//"));
+                    prop_assert!(!route.chars().any(char::is_control));
+                    prop_assert!(!route
+                        .split('/')
+                        .any(|component| component == "." || component == ".."));
+                }
+            }
+
+            #[test]
+            fn encoded_routes_round_trip(input in path()) {
+                if let Ok(route) = input.parse::<Route>() {
+                    let encoded = route
+                        .as_ref()
+                        .split('/')
+                        .map(|component| {
+                            utf8_percent_encode(component, NON_ALPHANUMERIC).to_string()
+                        })
+                        .collect::<Vec<_>>()
+                        .join("/");
+                    prop_assert_eq!(encoded.parse::<Route>().unwrap(), route.clone());
+                    prop_assert_eq!(route.to_url_path().parse::<Route>().unwrap(), route.clone());
+                    prop_assert_eq!(Route::from_decoded(route.as_ref()).unwrap(), route);
+                }
+            }
+
+            #[test]
+            fn joined_routes_stay_within_their_base(base in path(), segment in path()) {
+                if let Ok(base) = base.parse::<Route>() {
+                    if let Ok(joined) = base.join(&segment[1..]) {
+                        prop_assert!(joined.is_within(&base));
+                    }
+                }
+            }
+        }
+    }
 }
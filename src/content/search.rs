@@ -0,0 +1,260 @@
+use super::*;
+use std::collections::BTreeMap;
+
+/// Terms shorter than this are not indexed (or searched for).
+const MINIMUM_TERM_LENGTH: usize = 2;
+
+/// A full-text index of rendered content.
+///
+/// This serializes into a JSON structure which client-side code can use for
+/// searching: `documents` is a list of objects with `route` and `title`
+/// properties, and `terms` maps each lowercased word to a list of
+/// `[document-index, occurrences]` pairs.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct SearchIndex {
+    documents: Vec<SearchDocument>,
+    terms: BTreeMap<String, Vec<(usize, usize)>>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+struct SearchDocument {
+    route: Route,
+    title: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct SearchResult {
+    pub route: Route,
+    pub title: String,
+    pub score: f64,
+}
+
+impl SearchIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds rendered content to the index. HTML markup (along with the
+    /// contents of `script` and `style` elements) is ignored. If `title` is
+    /// `None` then the HTML `title` element is used, falling back to the last
+    /// component of the route.
+    pub fn add(
+        &mut self,
+        route: Route,
+        media_type: &MediaType,
+        content: &str,
+        title: Option<String>,
+    ) {
+        let is_html = media_type.clone().into_media_range() == ::mime::TEXT_HTML;
+        let text = if is_html {
+            html_text(content)
+        } else {
+            String::from(content)
+        };
+        let title = title
+            .or_else(|| if is_html { html_title(content) } else { None })
+            .unwrap_or_else(|| String::from(route.as_ref().rsplit('/').next().unwrap_or_default()));
+
+        let document_index = self.documents.len();
+        let mut occurrences = BTreeMap::<String, usize>::new();
+        for term in terms(&text) {
+            *occurrences.entry(term).or_default() += 1;
+        }
+        for (term, count) in occurrences {
+            self.terms
+                .entry(term)
+                .or_default()
+                .push((document_index, count));
+        }
+        self.documents.push(SearchDocument { route, title });
+    }
+
+    /// Finds documents containing every term in `query`, best matches first.
+    /// Documents are scored by how often each term appears in them, with
+    /// rarer terms counting for more.
+    pub fn search(&self, query: &str, limit: usize) -> Vec<SearchResult> {
+        let mut query_terms = terms(query).collect::<Vec<_>>();
+        query_terms.sort();
+        query_terms.dedup();
+        if query_terms.is_empty() {
+            return Vec::new();
+        }
+
+        let document_count = self.documents.len() as f64;
+        let mut scores = BTreeMap::<usize, (usize, f64)>::new();
+        for term in &query_terms {
+            let postings = self.terms.get(term).map(Vec::as_slice).unwrap_or_default();
+            let inverse_document_frequency =
+                (document_count / postings.len().max(1) as f64).ln() + 1.0;
+            for (document_index, count) in postings {
+                let (matched_terms, score) = scores.entry(*document_index).or_default();
+                *matched_terms += 1;
+                *score += *count as f64 * inverse_document_frequency;
+            }
+        }
+
+        let mut results = scores
+            .into_iter()
+            .filter(|(_, (matched_terms, _))| *matched_terms == query_terms.len())
+            .map(|(document_index, (_, score))| {
+                let document = &self.documents[document_index];
+                SearchResult {
+                    route: document.route.clone(),
+                    title: document.title.clone(),
+                    score,
+                }
+            })
+            .collect::<Vec<_>>();
+        results.sort_by(|a, b| {
+            b.score
+                .total_cmp(&a.score)
+                .then_with(|| a.route.cmp(&b.route))
+        });
+        results.truncate(limit);
+        results
+    }
+}
+
+/// Lowercased words from `text`.
+fn terms(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|character: char| !character.is_alphanumeric())
+        .filter(|word| word.chars().count() >= MINIMUM_TERM_LENGTH)
+        .map(str::to_lowercase)
+}
+
+/// The text of an HTML document, without markup.
+pub(super) fn html_text(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut remaining = html;
+    while let Some(tag_start) = remaining.find('<') {
+        text.push_str(&decode_entities(&remaining[..tag_start]));
+        text.push(' ');
+        let tag = &remaining[tag_start..];
+        let tag_end = tag.find('>').map(|index| index + 1).unwrap_or(tag.len());
+        let tag_name = tag_name(&tag[..tag_end]);
+        remaining = &tag[tag_end..];
+
+        // Skip over the contents of elements which are not text.
+        if tag_name == "script" || tag_name == "style" {
+            let closing_tag = format!("</{}", tag_name);
+            remaining = match remaining.to_ascii_lowercase().find(&closing_tag) {
+                Some(index) => &remaining[index..],
+                None => "",
+            };
+        }
+    }
+    text.push_str(&decode_entities(remaining));
+    text
+}
+
+fn html_title(html: &str) -> Option<String> {
+    let lowercase_html = html.to_ascii_lowercase();
+    let start = lowercase_html.find("<title")?;
+    let start = start + lowercase_html[start..].find('>')? + 1;
+    let end = start + lowercase_html[start..].find("</title")?;
+    let title = decode_entities(html[start..end].trim());
+    if title.is_empty() {
+        None
+    } else {
+        Some(title)
+    }
+}
+
+/// The lowercased name of an HTML tag like `<div class="x">`.
+pub(super) fn tag_name(tag: &str) -> String {
+    tag.trim_start_matches('<')
+        .chars()
+        .take_while(|character| character.is_ascii_alphanumeric())
+        .collect::<String>()
+        .to_ascii_lowercase()
+}
+
+fn decode_entities(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&#x27;", "'")
+        .replace("&nbsp;", " ")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_lib::*;
+    use test_log::test;
+
+    fn html() -> MediaType {
+        MediaType::from_media_range(::mime::TEXT_HTML).unwrap()
+    }
+
+    fn plain_text() -> MediaType {
+        MediaType::from_media_range(::mime::TEXT_PLAIN).unwrap()
+    }
+
+    #[test]
+    fn html_markup_is_not_indexed() {
+        let mut index = SearchIndex::new();
+        index.add(
+            route("/page"),
+            &html(),
+            "<html><head><title>Cats &amp; Dogs</title><style>.kitten { color: red }</style>\
+            </head><body><p class=\"fluffy\">All about cats.</p>\
+            <script>let puppy = 1;</script></body></html>",
+            None,
+        );
+
+        assert_eq!(index.search("cats", 10).len(), 1);
+        assert_eq!(index.search("dogs", 10).len(), 1);
+        for markup in ["title", "fluffy", "kitten", "puppy", "class"] {
+            assert_eq!(
+                index.search(markup, 10),
+                Vec::new(),
+                "`{}` was indexed",
+                markup,
+            );
+        }
+        assert_eq!(index.search("cats", 10)[0].title, "Cats & Dogs");
+    }
+
+    #[test]
+    fn titles_fall_back_to_the_route() {
+        let mut index = SearchIndex::new();
+        index.add(route("/notes/first"), &plain_text(), "hello", None);
+        index.add(
+            route("/notes/second"),
+            &plain_text(),
+            "hello",
+            Some(String::from("Second")),
+        );
+
+        let titles = index
+            .search("hello", 10)
+            .into_iter()
+            .map(|result| result.title)
+            .collect::<Vec<_>>();
+        assert_eq!(titles, vec!["first", "Second"]);
+    }
+
+    #[test]
+    fn results_must_match_every_term_and_are_ranked() {
+        let mut index = SearchIndex::new();
+        index.add(route("/a"), &plain_text(), "rust rust rust and more", None);
+        index.add(route("/b"), &plain_text(), "rust and serde", None);
+        index.add(route("/c"), &plain_text(), "RUST, SERDE, and SERDE!", None);
+
+        let routes = |query| {
+            index
+                .search(query, 10)
+                .into_iter()
+                .map(|result| result.route)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(routes("rust"), vec![route("/a"), route("/b"), route("/c")]);
+        assert_eq!(routes("Serde rust"), vec![route("/c"), route("/b")]);
+        assert_eq!(routes("rust missing"), Vec::<Route>::new());
+        assert_eq!(routes(""), Vec::<Route>::new());
+        assert_eq!(index.search("rust", 1).len(), 1);
+    }
+}
@@ -0,0 +1,559 @@
+use super::content_item::RenderingFailedError;
+use super::*;
+use handlebars::{self, Handlebars, Renderable as _};
+#[cfg(feature = "templating")]
+use mime_guess::MimeGuess;
+#[cfg(feature = "templating")]
+use minijinja::syntax::SyntaxConfig;
+#[cfg(feature = "templating")]
+use minijinja::value::Serde;
+#[cfg(feature = "templating")]
+use minijinja::UndefinedBehavior;
+use std::cell::Cell;
+use std::fmt;
+
+thread_local! {
+    /// The escaping for the handlebars template being rendered on this
+    /// thread. Content included via `get` renders synchronously within its
+    /// caller, so nesting is handled by [`Escaping::scope`].
+    static CURRENT_ESCAPING: Cell<Escaping> = const { Cell::new(Escaping::Html) };
+}
+
+/// The languages that templates in the content directory can be written in.
+/// A template's language is determined by its final filename extension.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TemplateLanguage {
+    /// Files named like `foo.html.hbs`.
+    Handlebars,
+
+    /// Files named like `foo.html.jinja`.
+    MiniJinja,
+}
+
+impl TemplateLanguage {
+    pub const ALL: [TemplateLanguage; 2] =
+        [TemplateLanguage::Handlebars, TemplateLanguage::MiniJinja];
+
+    pub fn from_file_extension(extension: &str) -> Option<Self> {
+        TemplateLanguage::ALL
+            .into_iter()
+            .find(|language| language.file_extension() == extension)
+    }
+
+    pub const fn file_extension(self) -> &'static str {
+        match self {
+            TemplateLanguage::Handlebars => "hbs",
+            TemplateLanguage::MiniJinja => "jinja",
+        }
+    }
+}
+
+impl fmt::Display for TemplateLanguage {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TemplateLanguage::Handlebars => write!(formatter, "handlebars"),
+            TemplateLanguage::MiniJinja => write!(formatter, "minijinja"),
+        }
+    }
+}
+
+/// How values are escaped when templates print them, which depends on the
+/// media type that the template renders.
+///
+/// Handlebars uses this for `{{value}}` (but not `{{{value}}}`), and MiniJinja
+/// uses it for auto-escaping. Templates for other media types (like
+/// `text/plain` or `text/csv`) are not escaped at all; they can use helpers
+/// like `html-escape` where needed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Escaping {
+    /// HTML escaping, for HTML.
+    Html,
+
+    /// XML escaping, for XML (including `+xml` media types). This is like
+    /// HTML escaping, but also removes characters which cannot appear in XML
+    /// documents (like most control characters).
+    Xml,
+
+    /// JSON string escaping, for JSON (including `+json` media types). In
+    /// handlebars templates values are escaped to go inside a string
+    /// (`"{{title}}"`), while MiniJinja prints them as JSON literals
+    /// (`{{ title }}`).
+    Json,
+
+    /// No escaping.
+    None,
+}
+
+impl Escaping {
+    pub fn for_media_type(media_type: &MediaType) -> Self {
+        let essence = media_type.essence_str();
+        match essence {
+            "text/html" => Escaping::Html,
+            "text/xml" | "application/xml" => Escaping::Xml,
+            "application/json" => Escaping::Json,
+            _ if essence.ends_with("+xml") => Escaping::Xml,
+            _ if essence.ends_with("+json") => Escaping::Json,
+            _ => Escaping::None,
+        }
+    }
+
+    /// The escaping for a template named like `foo.json.hbs`, from its first
+    /// extension.
+    #[cfg(feature = "templating")]
+    fn for_template_name(name: &str) -> Self {
+        let file_name = name.rsplit('/').next().unwrap_or(name);
+        let mut extensions = file_name.rsplit('.').skip(1);
+        match (extensions.next(), extensions.next()) {
+            (Some(extension), Some(_)) => MimeGuess::from_ext(extension)
+                .first()
+                .and_then(MediaType::from_media_range)
+                .map_or(Escaping::None, |media_type| {
+                    Escaping::for_media_type(&media_type)
+                }),
+            _ => Escaping::None,
+        }
+    }
+
+    pub fn escape(self, text: &str) -> String {
+        match self {
+            Escaping::Html => handlebars::html_escape(text),
+            Escaping::Xml => {
+                let mut escaped = String::with_capacity(text.len());
+                for character in text
+                    .chars()
+                    .filter(|character| is_xml_character(*character))
+                {
+                    match character {
+                        '&' => escaped.push_str("&amp;"),
+                        '<' => escaped.push_str("&lt;"),
+                        '>' => escaped.push_str("&gt;"),
+                        '"' => escaped.push_str("&quot;"),
+                        '\'' => escaped.push_str("&apos;"),
+                        _ => escaped.push(character),
+                    }
+                }
+                escaped
+            }
+            Escaping::Json => {
+                let quoted = serde_json::Value::from(text).to_string();
+                String::from(&quoted[1..quoted.len() - 1])
+            }
+            Escaping::None => String::from(text),
+        }
+    }
+
+    /// Escapes `text` for the handlebars template being rendered. This is
+    /// the escape function of every handlebars registry for content.
+    pub fn escape_current(text: &str) -> String {
+        CURRENT_ESCAPING.with(Cell::get).escape(text)
+    }
+
+    /// Runs `render` with this as the escaping used by
+    /// [`escape_current`](Self::escape_current).
+    pub(crate) fn scope<Output>(self, render: impl FnOnce() -> Output) -> Output {
+        let previous = CURRENT_ESCAPING.with(|current| current.replace(self));
+        let _restore_guard = RestoreGuard(previous);
+        render()
+    }
+
+    #[cfg(feature = "templating")]
+    fn auto_escape(self) -> minijinja::AutoEscape {
+        match self {
+            // MiniJinja's HTML escaping is also valid XML.
+            Escaping::Html | Escaping::Xml => minijinja::AutoEscape::Html,
+            Escaping::Json => minijinja::AutoEscape::Json,
+            Escaping::None => minijinja::AutoEscape::None,
+        }
+    }
+}
+
+/// Whether `character` is allowed in XML 1.0 documents.
+pub(super) fn is_xml_character(character: char) -> bool {
+    matches!(
+        character,
+        '\t' | '\n' | '\r' | '\u{20}'..='\u{d7ff}' | '\u{e000}'..='\u{fffd}' | '\u{10000}'..
+    )
+}
+
+/// Puts back the previous escaping, even if rendering panics.
+struct RestoreGuard(Escaping);
+impl Drop for RestoreGuard {
+    fn drop(&mut self) {
+        CURRENT_ESCAPING.with(|current| current.set(self.0));
+    }
+}
+
+/// Parses and renders templates written in one [`TemplateLanguage`].
+///
+/// Templates are registered by name (their path relative to the content
+/// directory, including extensions) and rendered with JSON render data.
+pub trait TemplateEngine {
+    fn has_template(&self, name: &str) -> bool;
+
+    /// `body` is the part of `template_source` after any front matter, which
+    /// spans `body_line_offset` lines. The whole source is used to describe
+    /// syntax errors.
+    fn register_template(
+        &mut self,
+        name: &str,
+        body: &str,
+        template_source: &str,
+        body_line_offset: usize,
+    ) -> Result<(), TemplateError>;
+
+    /// `handlebars_render_context` is present when the template is being
+    /// included from a handlebars template via the `get` helper. Its data
+    /// has already been merged into `render_data`.
+    fn render_template(
+        &self,
+        name: &str,
+        render_data: &serde_json::Value,
+        handlebars_render_context: Option<handlebars::RenderContext>,
+    ) -> Result<String, RenderingFailedError>;
+}
+
+impl TemplateEngine for Handlebars<'_> {
+    fn has_template(&self, name: &str) -> bool {
+        Handlebars::has_template(self, name)
+    }
+
+    fn register_template(
+        &mut self,
+        name: &str,
+        body: &str,
+        template_source: &str,
+        body_line_offset: usize,
+    ) -> Result<(), TemplateError> {
+        self.register_template_string(name, body).map_err(|error| {
+            TemplateError::new(
+                error,
+                Some(String::from(name)),
+                template_source,
+                body_line_offset,
+            )
+        })
+    }
+
+    fn render_template(
+        &self,
+        name: &str,
+        render_data: &serde_json::Value,
+        handlebars_render_context: Option<handlebars::RenderContext>,
+    ) -> Result<String, RenderingFailedError> {
+        match handlebars_render_context {
+            None => Ok(self.render(name, render_data)?),
+            Some(mut handlebars_render_context) => {
+                if handlebars_render_context.context().is_some() {
+                    handlebars_render_context
+                        .set_context(handlebars::Context::from(render_data.clone()));
+                }
+                let rendered = self
+                    .get_template(name)
+                    .ok_or_else(|| {
+                        RenderingFailedError::Bug(format!(
+                            "Template '{}' was not found in the registry",
+                            name
+                        ))
+                    })?
+                    .renders(self, &handlebars::Context::wraps(render_data)?, &mut {
+                        handlebars_render_context
+                    })?;
+                Ok(rendered)
+            }
+        }
+    }
+}
+
+/// Renders templates with [MiniJinja](https://docs.rs/minijinja), which
+/// implements most of the Jinja2 language. Templates can `include`, `import`,
+/// and `extend` each other by name.
+///
+/// Jinja identifiers cannot contain hyphens, so top-level render data is also
+/// available with underscores in place of hyphens (e.g. `front_matter`).
+#[cfg(feature = "templating")]
+#[derive(Clone)]
+pub struct MiniJinjaEngine {
+    environment: minijinja::Environment<'static>,
+}
+
+#[cfg(feature = "templating")]
+impl MiniJinjaEngine {
+    pub fn new(strict: bool) -> Self {
+        let mut environment = minijinja::Environment::new();
+        environment.set_debug(true);
+        environment
+            .set_auto_escape_callback(|name| Escaping::for_template_name(name).auto_escape());
+        environment.set_syntax(
+            SyntaxConfig::builder()
+                .keep_trailing_newline(true)
+                .build()
+                .expect("Default MiniJinja syntax was invalid"),
+        );
+        let mut engine = MiniJinjaEngine { environment };
+        engine.set_strict_mode(strict);
+        engine
+    }
+
+    /// Strict mode makes printing or iterating over missing data an error
+    /// (checking whether it exists is still allowed).
+    pub fn set_strict_mode(&mut self, strict: bool) {
+        self.environment.set_undefined_behavior(if strict {
+            UndefinedBehavior::SemiStrict
+        } else {
+            UndefinedBehavior::Chainable
+        });
+    }
+
+    pub fn strict_mode(&self) -> bool {
+        self.environment.undefined_behavior() != UndefinedBehavior::Chainable
+    }
+
+    /// Forgets the template named `name`, if there is one.
+    pub fn remove_template(&mut self, name: &str) {
+        self.environment.remove_template(name);
+    }
+}
+
+#[cfg(feature = "templating")]
+impl TemplateEngine for MiniJinjaEngine {
+    fn has_template(&self, name: &str) -> bool {
+        self.environment.get_template(name).is_ok()
+    }
+
+    fn register_template(
+        &mut self,
+        name: &str,
+        body: &str,
+        template_source: &str,
+        body_line_offset: usize,
+    ) -> Result<(), TemplateError> {
+        self.environment
+            .add_template_owned(String::from(name), String::from(body))
+            .map_err(|error| {
+                let position = error.line().map(|line| {
+                    let column = error
+                        .range()
+                        .and_then(|range| body.get(..range.start))
+                        .and_then(|preceding| preceding.rsplit('\n').next())
+                        .map(|line_prefix| line_prefix.chars().count() + 1)
+                        .unwrap_or(1);
+                    (line, column)
+                });
+                TemplateError::in_language(
+                    TemplateLanguage::MiniJinja,
+                    Some(String::from(name)),
+                    position,
+                    error
+                        .detail()
+                        .map(String::from)
+                        .unwrap_or_else(|| error.kind().to_string()),
+                    template_source,
+                    body_line_offset,
+                )
+            })
+    }
+
+    fn render_template(
+        &self,
+        name: &str,
+        render_data: &serde_json::Value,
+        _: Option<handlebars::RenderContext>,
+    ) -> Result<String, RenderingFailedError> {
+        let mut render_data = render_data.clone();
+        if let Some(render_data) = render_data.as_object_mut() {
+            let aliases = render_data
+                .iter()
+                .filter(|(key, _)| key.contains('-'))
+                .map(|(key, value)| (key.replace('-', "_"), value.clone()))
+                .collect::<Vec<_>>();
+            for (alias, value) in aliases {
+                render_data.entry(alias).or_insert(value);
+            }
+        }
+        let rendered = self
+            .environment
+            .get_template(name)?
+            .render(minijinja::Value::from(Serde(&render_data)))?;
+        Ok(rendered)
+    }
+}
+
+/// Without the `templating` cargo feature there is no MiniJinja, and this
+/// never has any templates.
+#[cfg(not(feature = "templating"))]
+#[derive(Clone)]
+pub struct MiniJinjaEngine {
+    strict: bool,
+}
+
+#[cfg(not(feature = "templating"))]
+impl MiniJinjaEngine {
+    pub fn new(strict: bool) -> Self {
+        MiniJinjaEngine { strict }
+    }
+
+    pub fn set_strict_mode(&mut self, strict: bool) {
+        self.strict = strict;
+    }
+
+    pub fn strict_mode(&self) -> bool {
+        self.strict
+    }
+
+    pub fn remove_template(&mut self, _name: &str) {}
+}
+
+#[cfg(not(feature = "templating"))]
+impl TemplateEngine for MiniJinjaEngine {
+    fn has_template(&self, _name: &str) -> bool {
+        false
+    }
+
+    fn register_template(
+        &mut self,
+        name: &str,
+        _body: &str,
+        template_source: &str,
+        body_line_offset: usize,
+    ) -> Result<(), TemplateError> {
+        Err(TemplateError::in_language(
+            TemplateLanguage::MiniJinja,
+            Some(String::from(name)),
+            None,
+            String::from("This build does not support templates."),
+            template_source,
+            body_line_offset,
+        ))
+    }
+
+    fn render_template(
+        &self,
+        name: &str,
+        _render_data: &serde_json::Value,
+        _: Option<handlebars::RenderContext>,
+    ) -> Result<String, RenderingFailedError> {
+        Err(RenderingFailedError::Bug(format!(
+            "Template '{}' was not found because this build does not support templates",
+            name
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "templating")]
+    use serde_json::json;
+    use test_log::test;
+
+    #[test]
+    fn template_languages_are_determined_by_extension() {
+        assert_eq!(
+            TemplateLanguage::from_file_extension("hbs"),
+            Some(TemplateLanguage::Handlebars)
+        );
+        assert_eq!(
+            TemplateLanguage::from_file_extension("jinja"),
+            Some(TemplateLanguage::MiniJinja)
+        );
+        assert_eq!(TemplateLanguage::from_file_extension("html"), None);
+    }
+
+    #[test]
+    fn escaping_depends_on_media_type() {
+        let escaping =
+            |media_type: &str| Escaping::for_media_type(&media_type.parse::<MediaType>().unwrap());
+        assert_eq!(escaping("text/html"), Escaping::Html);
+        assert_eq!(escaping("application/xml"), Escaping::Xml);
+        assert_eq!(escaping("application/atom+xml"), Escaping::Xml);
+        assert_eq!(escaping("application/json"), Escaping::Json);
+        assert_eq!(escaping("application/ld+json"), Escaping::Json);
+        assert_eq!(escaping("text/plain"), Escaping::None);
+        assert_eq!(escaping("text/csv"), Escaping::None);
+
+        let text = "\"<a & b>\"\n";
+        assert_eq!(
+            Escaping::Html.escape(text),
+            "&quot;&lt;a &amp; b&gt;&quot;\n"
+        );
+        assert_eq!(Escaping::Json.escape(text), "\\\"<a & b>\\\"\\n");
+        assert_eq!(
+            Escaping::Xml.escape("it's <\u{1}ok>"),
+            "it&apos;s &lt;ok&gt;"
+        );
+        assert_eq!(Escaping::None.escape(text), text);
+        assert_eq!(
+            Escaping::Json.scope(|| Escaping::escape_current(text)),
+            Escaping::Json.escape(text)
+        );
+        assert_eq!(Escaping::escape_current(text), Escaping::Html.escape(text));
+    }
+
+    #[test]
+    #[cfg(feature = "templating")]
+    fn minijinja_escaping_depends_on_template_name() {
+        assert_eq!(
+            Escaping::for_template_name("a.b/feed.xml.jinja"),
+            Escaping::Xml
+        );
+        assert_eq!(Escaping::for_template_name("data.jinja"), Escaping::None);
+    }
+
+    #[test]
+    #[cfg(feature = "templating")]
+    fn minijinja_templates_can_be_rendered() {
+        let mut engine = MiniJinjaEngine::new(true);
+        engine
+            .register_template(
+                "test.html.jinja",
+                "{% for x in xs %}{{ x }}{% endfor %} {{ front_matter.title }} {{ '<b>' }}",
+                "",
+                0,
+            )
+            .expect("Template could not be registered");
+        let rendered = engine
+            .render_template(
+                "test.html.jinja",
+                &json!({ "xs": [1, 2, 3], "front-matter": { "title": "hi" } }),
+                None,
+            )
+            .expect("Rendering failed");
+        assert_eq!(rendered, "123 hi &lt;b&gt;");
+    }
+
+    #[test]
+    #[cfg(feature = "templating")]
+    fn minijinja_strict_mode_can_be_disabled() {
+        let source = "[{{ missing }}]";
+        let mut strict_engine = MiniJinjaEngine::new(true);
+        strict_engine
+            .register_template("test.txt.jinja", source, source, 0)
+            .unwrap();
+        assert!(strict_engine
+            .render_template("test.txt.jinja", &json!({}), None)
+            .is_err());
+
+        let mut lenient_engine = strict_engine.clone();
+        lenient_engine.set_strict_mode(false);
+        assert_eq!(
+            lenient_engine
+                .render_template("test.txt.jinja", &json!({}), None)
+                .expect("Rendering failed"),
+            "[]"
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "templating")]
+    fn minijinja_syntax_errors_describe_where_the_problem_is() {
+        let source = "---\na: b\n---\nfine\n  {% if %}";
+        let body = "fine\n  {% if %}";
+        let error = MiniJinjaEngine::new(true)
+            .register_template("test.html.jinja", body, source, 3)
+            .expect_err("Template was registered, but this should have failed");
+        assert_eq!(error.language(), TemplateLanguage::MiniJinja);
+        assert_eq!(error.name(), Some("test.html.jinja"));
+        assert_eq!(error.line(), Some(5));
+        assert_eq!(error.column(), Some(9));
+    }
+}
@@ -8,10 +8,10 @@ use bytes::{Bytes, BytesMut};
 use futures::executor;
 use handlebars::Handlebars;
 
-pub struct MockContentEngine<'a>(Handlebars<'a>);
+pub struct MockContentEngine<'a>(Handlebars<'a>, MiniJinjaEngine);
 impl<'a> MockContentEngine<'a> {
     pub fn new() -> Self {
-        Self(Handlebars::new())
+        Self(Handlebars::new(), MiniJinjaEngine::new(false))
     }
     pub fn register_template(
         &mut self,
@@ -23,26 +23,21 @@ impl<'a> MockContentEngine<'a> {
     }
 }
 impl<'a> ContentEngine<()> for MockContentEngine<'a> {
-    fn render_context(
+    fn render_data(
         &self,
         route: Option<Route>,
-        query_parameters: HashMap<String, String>,
+        query_parameters: QueryParameters,
         request_headers: HashMap<String, String>,
-    ) -> RenderContext<(), Self> {
-        RenderContext {
-            content_engine: self,
-            handlebars_render_context: None,
-            data: RenderData {
-                server_info: (),
-                index: ContentIndex::Directory(ContentIndexEntries::new()),
-                target_media_type: None,
-                error_code: None,
-                request: RequestData {
-                    route,
-                    query_parameters,
-                    request_headers,
-                },
-            },
+    ) -> RenderData<()> {
+        RenderData {
+            server_info: (),
+            index: ContentIndex::Directory(ContentIndexEntries::new()),
+            target_media_type: None,
+            site: serde_json::Map::new(),
+            data: serde_json::Map::new(),
+            front_matter: None,
+            error_code: None,
+            request: RequestData::new(route, query_parameters, request_headers),
         }
     }
     fn new_template(
@@ -55,17 +50,32 @@ impl<'a> ContentEngine<()> for MockContentEngine<'a> {
     fn get(&self, _: &Route) -> Option<&ContentRepresentations> {
         None
     }
-    fn handlebars_registry(&self) -> &Handlebars {
+    fn handlebars_registry(&self) -> &Handlebars<'_> {
         &self.0
     }
+    fn search_index(&self) -> Option<&SearchIndex> {
+        None
+    }
+    fn stats(&self) -> Option<&ContentStats> {
+        None
+    }
+    fn redirect(&self, _: &Route) -> Option<&Redirect> {
+        None
+    }
+    fn template_engine(&self, language: TemplateLanguage, _: bool) -> &dyn TemplateEngine {
+        match language {
+            TemplateLanguage::Handlebars => &self.0,
+            TemplateLanguage::MiniJinja => &self.1,
+        }
+    }
 }
 
-pub fn media_to_string(media: Media<impl ByteStream + Unpin>) -> String {
+pub fn media_to_string(media: Media<impl ByteStream>) -> String {
     let bytes = block_on_content(media).expect("There was an error in the content stream");
     String::from_utf8(bytes.into_iter().collect()).expect("Failed to read media into a string")
 }
 
-pub fn block_on_content(media: Media<impl ByteStream + Unpin>) -> Result<Bytes, StreamError> {
+pub fn block_on_content(media: Media<impl ByteStream>) -> Result<Bytes, StreamError> {
     let mut all_bytes = BytesMut::new();
     for result in executor::block_on_stream(media.content) {
         match result {
@@ -75,3 +85,12 @@ pub fn block_on_content(media: Media<impl ByteStream + Unpin>) -> Result<Bytes,
     }
     Ok(all_bytes.freeze())
 }
+
+/// The length of each (non-empty) chunk of the content stream.
+pub fn chunk_lengths(media: Media<impl ByteStream>) -> Vec<usize> {
+    executor::block_on_stream(media.content)
+        .map(|result| result.expect("There was an error in the content stream"))
+        .map(|bytes| bytes.len())
+        .filter(|length| *length > 0)
+        .collect()
+}
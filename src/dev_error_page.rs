@@ -0,0 +1,328 @@
+//! Pages describing failed renders, which are served instead of the usual
+//! error response in development mode.
+
+use crate::config::REDACTED;
+use crate::content::{ContentEngine, FailureLocation, FrontMatter, Route};
+use percent_encoding::percent_decode_str;
+use serde::Serialize;
+use std::error::Error;
+use std::fmt::Write as _;
+
+/// Render data properties whose names contain any of these (ignoring case)
+/// have their values redacted.
+const SECRET_NAME_FRAGMENTS: [&str; 9] = [
+    "authorization",
+    "cookie",
+    "credential",
+    "password",
+    "secret",
+    "session",
+    "token",
+    "api-key",
+    "private-key",
+];
+
+/// How many lines are shown on each side of the line where a template
+/// failed.
+const SURROUNDING_LINES: usize = 3;
+
+/// What is known about a failed render.
+pub(crate) struct RenderFailure {
+    route: Route,
+    /// Messages from the error and each of its sources, outermost first.
+    errors: Vec<String>,
+    location: Option<FailureLocation>,
+}
+
+impl RenderFailure {
+    pub fn new(
+        route: Route,
+        error: &(dyn Error + 'static),
+        location: Option<FailureLocation>,
+    ) -> Self {
+        let mut errors: Vec<String> = Vec::new();
+        let mut error = Some(error);
+        while let Some(current) = error {
+            let message = current.to_string();
+            // Transparent wrappers repeat the message of their source.
+            if errors.last() != Some(&message) {
+                errors.push(message);
+            }
+            error = current.source();
+        }
+        RenderFailure {
+            route,
+            errors,
+            location,
+        }
+    }
+
+    /// An HTML page describing the failure. Templates are looked up in
+    /// `content_engine` to show the line that failed.
+    pub fn to_html<ServerInfo, Engine>(
+        &self,
+        content_engine: &Engine,
+        render_data: &impl Serialize,
+    ) -> String
+    where
+        ServerInfo: Clone + Serialize,
+        Engine: ContentEngine<ServerInfo> + ?Sized,
+    {
+        let mut body = String::new();
+        body.push_str("<h2>Errors</h2>\n<ol>\n");
+        for error in &self.errors {
+            let _ = writeln!(body, "<li><pre>{}</pre></li>", escape_html(error));
+        }
+        body.push_str("</ol>\n");
+
+        match &self.location {
+            Some(FailureLocation::Template { name, line }) => {
+                let source = content_engine.template_source(name);
+                let (line, excerpt) = match (line, source) {
+                    (Some(line), Some(source)) => {
+                        // Lines from template engines are counted from the
+                        // start of the body.
+                        let body_line_offset = FrontMatter::split(&source)
+                            .map(|(_, _, body_line_offset)| body_line_offset)
+                            .unwrap_or_default();
+                        let line = line + body_line_offset;
+                        (Some(line), source_excerpt(&source, line))
+                    }
+                    (line, _) => (*line, None),
+                };
+                body.push_str("<h2>Template</h2>\n");
+                let _ = write!(body, "<p><code>{}</code>", escape_html(name));
+                if let Some(line) = line {
+                    let _ = write!(body, ", line {}", line);
+                }
+                body.push_str("</p>\n");
+                if let Some(excerpt) = excerpt {
+                    let _ = writeln!(body, "<pre>{}</pre>", excerpt);
+                }
+            }
+            Some(FailureLocation::Executable {
+                program,
+                working_directory,
+            }) => {
+                let _ = writeln!(
+                    body,
+                    "<h2>Executable</h2>\n<p><code>{}</code> in <code>{}</code></p>",
+                    escape_html(program),
+                    escape_html(&working_directory.display().to_string()),
+                );
+            }
+            None => {}
+        }
+
+        let render_data = serde_json::to_value(render_data)
+            .map(|mut render_data| {
+                if let Some(render_data) = render_data.as_object_mut() {
+                    // The content index is large and does not vary by request.
+                    render_data.remove("/");
+                }
+                redact_secrets(&mut render_data);
+                redact_query_string(&mut render_data);
+                serde_json::to_string_pretty(&render_data).unwrap_or_default()
+            })
+            .unwrap_or_else(|error| format!("Render data could not be serialized: {}", error));
+        let _ = writeln!(
+            body,
+            "<h2>Render data</h2>\n<pre>{}</pre>",
+            escape_html(&render_data)
+        );
+
+        let route = escape_html(self.route.as_ref());
+        format!(
+            "<!DOCTYPE html>\n\
+            <html lang=\"en\">\n\
+            <head>\n\
+            <meta charset=\"utf-8\">\n\
+            <title>Failed to render {route}</title>\n\
+            <style>body {{ font-family: sans-serif; margin: 2em; color: #333; }} \
+            pre {{ background: #f4f4f4; padding: 0.5em; overflow-x: auto; }} \
+            mark {{ background: #fdd; }}</style>\n\
+            </head>\n\
+            <body>\n\
+            <h1>Failed to render <code>{route}</code></h1>\n\
+            {body}\
+            <p><small>This page is shown because development mode is on.</small></p>\n\
+            </body>\n\
+            </html>\n",
+            route = route,
+            body = body,
+        )
+    }
+}
+
+/// Numbered lines of `source` around `line` (counting from 1), with `line`
+/// highlighted. The result is already escaped.
+fn source_excerpt(source: &str, line: usize) -> Option<String> {
+    let lines = source.lines().collect::<Vec<_>>();
+    if line == 0 || line > lines.len() {
+        return None;
+    }
+    let first = line.saturating_sub(SURROUNDING_LINES).max(1);
+    let last = (line + SURROUNDING_LINES).min(lines.len());
+    let width = last.to_string().len();
+    let mut excerpt = String::new();
+    for (number, text) in (first..=last).zip(&lines[first - 1..last]) {
+        let text = format!("{:>width$} | {}", number, escape_html(text), width = width);
+        if number == line {
+            let _ = writeln!(excerpt, "<mark>{}</mark>", text);
+        } else {
+            let _ = writeln!(excerpt, "{}", text);
+        }
+    }
+    Some(excerpt)
+}
+
+fn is_secret_name(name: &str) -> bool {
+    let name = name.to_ascii_lowercase().replace('_', "-");
+    SECRET_NAME_FRAGMENTS
+        .iter()
+        .any(|fragment| name.contains(fragment))
+}
+
+/// Replaces the values of properties which look like they hold secrets.
+fn redact_secrets(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::Object(properties) => {
+            for (name, value) in properties.iter_mut() {
+                if is_secret_name(name) {
+                    *value = serde_json::Value::from(REDACTED);
+                } else {
+                    redact_secrets(value);
+                }
+            }
+        }
+        serde_json::Value::Array(values) => values.iter_mut().for_each(redact_secrets),
+        _ => {}
+    }
+}
+
+/// Rebuilds `request.http.query-string` with the values of parameters which
+/// look like they hold secrets replaced. The parsed query parameters are
+/// already handled by [`redact_secrets`], but the raw query string repeats
+/// them.
+fn redact_query_string(render_data: &mut serde_json::Value) {
+    let query_string = match render_data.pointer_mut("/request/http/query-string") {
+        Some(serde_json::Value::String(query_string)) => query_string,
+        _ => return,
+    };
+    *query_string = query_string
+        .split('&')
+        .map(|parameter| {
+            let raw_name = parameter
+                .split_once('=')
+                .map_or(parameter, |(raw_name, _)| raw_name);
+            let name = raw_name.replace('+', " ");
+            if is_secret_name(&percent_decode_str(&name).decode_utf8_lossy()) {
+                format!("{}={}", raw_name, REDACTED)
+            } else {
+                String::from(parameter)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("&");
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for character in text.chars() {
+        match character {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(character),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn secrets_are_redacted() {
+        let mut render_data = json!({
+            "request": {
+                "headers": {
+                    "Authorization": "Bearer abc",
+                    "Cookie": "session=abc",
+                    "accept": "text/html",
+                },
+            },
+            "data": {
+                "services": [{ "name": "maps", "api_key": "abc" }],
+                "password-hint": "abc",
+            },
+        });
+        redact_secrets(&mut render_data);
+        assert_eq!(
+            render_data,
+            json!({
+                "request": {
+                    "headers": {
+                        "Authorization": REDACTED,
+                        "Cookie": REDACTED,
+                        "accept": "text/html",
+                    },
+                },
+                "data": {
+                    "services": [{ "name": "maps", "api_key": REDACTED }],
+                    "password-hint": REDACTED,
+                },
+            })
+        );
+    }
+
+    #[test]
+    fn secret_query_parameters_are_redacted() {
+        let mut render_data = json!({
+            "request": {
+                "query-parameters": { "page": "2", "access_token": "abc" },
+                "query-parameter-lists": { "page": ["2"], "access_token": ["abc"] },
+                "http": {
+                    "uri-path": "/search",
+                    "query-string": "page=2&access_token=abc&API%2DKEY=def&q=a+b&flag",
+                },
+            },
+        });
+        redact_secrets(&mut render_data);
+        redact_query_string(&mut render_data);
+        assert_eq!(
+            render_data,
+            json!({
+                "request": {
+                    "query-parameters": { "page": "2", "access_token": REDACTED },
+                    "query-parameter-lists": { "page": ["2"], "access_token": REDACTED },
+                    "http": {
+                        "uri-path": "/search",
+                        "query-string": format!(
+                            "page=2&access_token={0}&API%2DKEY={0}&q=a+b&flag",
+                            REDACTED,
+                        ),
+                    },
+                },
+            })
+        );
+    }
+
+    #[test]
+    fn excerpts_highlight_the_failing_line() {
+        let source = "one\ntwo\n<three>\nfour\nfive\nsix\nseven\neight\nnine\nten\n";
+        assert_eq!(
+            source_excerpt(source, 3).unwrap(),
+            "1 | one\n2 | two\n<mark>3 | &lt;three&gt;</mark>\n4 | four\n5 | five\n6 | six\n"
+        );
+        assert_eq!(
+            source_excerpt(source, 9).unwrap().lines().next(),
+            Some(" 6 | six")
+        );
+        assert_eq!(source_excerpt(source, 11), None);
+    }
+}
@@ -0,0 +1,151 @@
+//! The limit on how many files the process may have open at once.
+//!
+//! Content files are eagerly opened, so large content directories can need
+//! more file descriptors than the default soft limit allows (often 1024).
+
+use std::fs;
+use std::io;
+
+/// Descriptors to keep free for sockets, executables, and everything else
+/// besides content files.
+const HEADROOM: u64 = 256;
+
+/// The soft limit on open files for this process, unless it is unlimited.
+pub(crate) fn soft_limit() -> Option<u64> {
+    get_limits().ok().and_then(|(soft_limit, _)| soft_limit)
+}
+
+/// The soft limit to ask for when the hard limit is unlimited, since that
+/// cannot always be reached (Linux caps it at `fs.nr_open`, which defaults
+/// to this).
+const UNLIMITED_HARD_LIMIT_TARGET: u64 = 1 << 20;
+
+/// Raises the soft limit on open files to the hard limit, which needs no
+/// privileges. Content files are held open, so programs which load content
+/// directories (like the `operator` command line) should call this once at
+/// startup. The library never changes the limit itself.
+pub fn raise_soft_limit() {
+    let (soft_limit, hard_limit) = match get_limits() {
+        Ok((Some(soft_limit), hard_limit)) => (soft_limit, hard_limit),
+        Ok((None, _)) => return,
+        Err(error) => {
+            log::debug!("Unable to get the open file limit: {}", error);
+            return;
+        }
+    };
+    let target_limit = hard_limit.unwrap_or(UNLIMITED_HARD_LIMIT_TARGET);
+    if target_limit <= soft_limit {
+        return;
+    }
+    match set_soft_limit(target_limit) {
+        Ok(()) => log::debug!(
+            "Raised the open file limit from {} to {}",
+            soft_limit,
+            target_limit,
+        ),
+        Err(error) => log::debug!("Unable to raise the open file limit: {}", error),
+    }
+}
+
+/// Warns if opening `count` more files would likely exceed the soft limit,
+/// explaining what limit is needed.
+pub(crate) fn warn_if_insufficient(count: usize) {
+    let soft_limit = match soft_limit() {
+        Some(soft_limit) => soft_limit,
+        None => return,
+    };
+    let needed = open_file_count().unwrap_or_default() + count as u64 + HEADROOM;
+    if needed > soft_limit {
+        log::warn!(
+            "Content needs about {} open files, but this process is limited to {}. Loading may \
+            fail with \"Too many open files\". Raise the limit before starting Operator (e.g. \
+            with `ulimit -n {}`, or `LimitNOFILE={}` for a systemd service).",
+            needed,
+            soft_limit,
+            needed,
+            needed,
+        );
+    }
+}
+
+/// How many files this process has open, where the platform lists them.
+fn open_file_count() -> Option<u64> {
+    ["/proc/self/fd", "/dev/fd"]
+        .iter()
+        .find_map(|path| fs::read_dir(path).ok())
+        .map(|entries| entries.count() as u64)
+}
+
+/// The soft and hard limits, where `None` means unlimited.
+fn get_limits() -> Result<(Option<u64>, Option<u64>), io::Error> {
+    let mut limits = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    // SAFETY: `limits` is a valid rlimit for getrlimit to write to.
+    if unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, &mut limits) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok((from_rlim(limits.rlim_cur), from_rlim(limits.rlim_max)))
+}
+
+fn set_soft_limit(soft_limit: u64) -> Result<(), io::Error> {
+    let mut limits = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    // SAFETY: `limits` is a valid rlimit for getrlimit to write to, and
+    // setrlimit only reads it.
+    unsafe {
+        if libc::getrlimit(libc::RLIMIT_NOFILE, &mut limits) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        limits.rlim_cur = soft_limit as libc::rlim_t;
+        if libc::setrlimit(libc::RLIMIT_NOFILE, &limits) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+// `rlim_t` is not `u64` on every platform.
+#[allow(clippy::unnecessary_cast)]
+fn from_rlim(limit: libc::rlim_t) -> Option<u64> {
+    if limit == libc::RLIM_INFINITY {
+        None
+    } else {
+        Some(limit as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn soft_limit_matches_what_is_in_effect() {
+        let (soft, hard) = get_limits().expect("Unable to get the open file limit");
+        assert_eq!(soft_limit(), soft);
+        if let (Some(soft), Some(hard)) = (soft, hard) {
+            assert!(soft <= hard);
+        }
+    }
+
+    #[test]
+    fn soft_limit_can_be_raised() {
+        let (soft_before, _) = get_limits().expect("Unable to get the open file limit");
+        raise_soft_limit();
+        let (soft_after, hard) = get_limits().expect("Unable to get the open file limit");
+        if let (Some(soft_before), Some(soft_after)) = (soft_before, soft_after) {
+            assert!(soft_after >= soft_before);
+        }
+        if let (Some(soft_after), Some(hard)) = (soft_after, hard) {
+            assert!(soft_after <= hard);
+        }
+    }
+
+    #[test]
+    fn open_files_can_be_counted() {
+        assert!(open_file_count().is_some_and(|count| count > 0));
+    }
+}
@@ -1,18 +1,35 @@
+use crate::bulkhead::{BulkheadPermit, Bulkheads};
+use crate::config::{
+    Config, CorsConfig, EntityTagStrength, RouteConfig, SearchConfig, StatusConfig, StreamingConfig,
+};
 use crate::content::*;
+use crate::dev_error_page::RenderFailure;
+use crate::render::{self, split_extension, RenderOutcome, RenderRequest, Rendered};
 use crate::*;
-use actix_rt::System;
-use actix_web::error::QueryPayloadError;
-use actix_web::http::header::{self, Header, HeaderMap};
+use actix_rt::{signal, time, System};
+use actix_web::dev::{Body, BodySize, HttpResponseBuilder, MessageBody, ResponseBody, Server};
+use actix_web::error::{BlockingError, QueryPayloadError};
+use actix_web::http::header::{self, HeaderMap};
 use actix_web::http::HeaderValue;
 use actix_web::{http, web, App, HttpRequest, HttpResponse, HttpServer};
-use futures::TryStreamExt;
-use mime_guess::MimeGuess;
-use std::cmp::Ordering;
+use bytes::{Bytes, BytesMut};
+use futures::stream::{self, LocalBoxStream};
+use futures::{future, FutureExt, StreamExt, TryStreamExt};
+use sha2::{Digest, Sha256};
+use std::cmp;
+use std::collections::hash_map::DefaultHasher;
 use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::hash::{Hash, Hasher};
 use std::io;
-use std::net::ToSocketAddrs;
+use std::net::{IpAddr, SocketAddr, TcpListener, ToSocketAddrs};
+use std::pin::Pin;
 use std::str::FromStr;
-use std::sync::{Arc, RwLock};
+use std::sync::atomic::{self, AtomicU64};
+use std::sync::{Arc, PoisonError, RwLock, RwLockReadGuard};
+use std::task::{Context, Poll};
+use std::time::{Duration, SystemTime};
 
 // TODO: Currently GET and OPTIONS are allowed for all paths, but if Operator
 // supports other methods (see https://github.com/mkantor/operator/issues/13)
@@ -21,6 +38,27 @@ use std::sync::{Arc, RwLock};
 /// This can be used as a value for the `Allow` response header.
 const ALLOWED_REQUEST_METHODS: &str = "GET, OPTIONS";
 
+/// Requests for more byte ranges than this get the whole content instead.
+const MAX_BYTE_RANGES: usize = 64;
+
+/// The query parameter containing search terms for the search query route.
+const SEARCH_QUERY_PARAMETER: &str = "q";
+
+/// Details for responses to requests which arrive after another thread
+/// panicked while holding the content engine's write lock.
+const POISONED_CONTENT_ENGINE: &str =
+    bug_message!("The content engine's lock was poisoned by a panic in another thread.");
+
+/// Counts responses for routes with a `body-trace-interval`.
+static BODY_TRACE_SAMPLING_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Set by reverse proxies to describe the original request. These are only
+/// believed when they come from one of the `trusted-proxies`.
+const X_FORWARDED_FOR: &str = "x-forwarded-for";
+const X_FORWARDED_HOST: &str = "x-forwarded-host";
+const X_FORWARDED_PREFIX: &str = "x-forwarded-prefix";
+const X_FORWARDED_PROTO: &str = "x-forwarded-proto";
+
 #[derive(Error, Debug)]
 #[error("Invalid query string '{}'", .query_string)]
 pub struct InvalidQueryStringError {
@@ -28,11 +66,20 @@ pub struct InvalidQueryStringError {
     source: QueryPayloadError,
 }
 
+#[derive(Error, Debug)]
+pub enum RunServerError {
+    #[error("Unable to bind to the socket address.")]
+    BindError { source: io::Error },
+
+    #[error("Server terminated unexpectedly.")]
+    RunError { source: io::Error },
+}
+
 #[derive(Clone, Default)]
-pub struct QueryString(HashMap<String, String>);
+pub struct QueryString(QueryParameters);
 
-impl From<QueryString> for HashMap<String, String> {
-    fn from(query_string: QueryString) -> HashMap<String, String> {
+impl From<QueryString> for QueryParameters {
+    fn from(query_string: QueryString) -> QueryParameters {
         query_string.0
     }
 }
@@ -40,8 +87,10 @@ impl From<QueryString> for HashMap<String, String> {
 impl FromStr for QueryString {
     type Err = InvalidQueryStringError;
     fn from_str(input: &str) -> Result<Self, Self::Err> {
-        web::Query::<HashMap<String, String>>::from_query(input)
-            .map(|query_parameters| QueryString(query_parameters.to_owned()))
+        web::Query::<Vec<(String, String)>>::from_query(input)
+            .map(|query_parameters| {
+                QueryString(QueryParameters::new(query_parameters.into_inner()))
+            })
             .map_err(|source| InvalidQueryStringError {
                 query_string: String::from(input),
                 source,
@@ -49,18 +98,398 @@ impl FromStr for QueryString {
     }
 }
 
-struct AppData<Engine: 'static + ContentEngine<ServerInfo> + Send + Sync> {
+/// Content engines keyed by the host name (without a port) which serves
+/// them.
+pub type VirtualHosts<Engine> = HashMap<String, Arc<RwLock<Engine>>>;
+
+/// Loads served content again. This happens on `SIGHUP` and for authorized
+/// requests to the reload route. It is given the paths of changed content
+/// files (relative to the content directory) when they are known, and
+/// otherwise nothing, in which case all content is loaded again.
+pub type Reloader = Arc<dyn Fn(&[String]) -> Result<(), ContentReloadError> + Send + Sync>;
+
+/// Everything [`handle_request`] needs, which must be in the actix `App`'s
+/// data. See [`OperatorServerBuilder::app_data`].
+pub struct AppData<Engine: 'static + ContentEngine<ServerInfo> + Send + Sync> {
     shared_content_engine: Arc<RwLock<Engine>>,
+    virtual_hosts: VirtualHosts<Engine>,
     index_route: Option<Route>,
     error_handler_route: Option<Route>,
+    config: Config,
+    reloader: Option<Reloader>,
+    bulkheads: Bulkheads,
+}
+
+impl<Engine: 'static + ContentEngine<ServerInfo> + Send + Sync> Clone for AppData<Engine> {
+    fn clone(&self) -> Self {
+        AppData {
+            shared_content_engine: self.shared_content_engine.clone(),
+            virtual_hosts: self.virtual_hosts.clone(),
+            index_route: self.index_route.clone(),
+            error_handler_route: self.error_handler_route.clone(),
+            config: self.config.clone(),
+            reloader: self.reloader.clone(),
+            bulkheads: self.bulkheads.clone(),
+        }
+    }
+}
+
+impl<Engine: 'static + ContentEngine<ServerInfo> + Send + Sync> AppData<Engine> {
+    /// The content engine for the request's host, or the default one if that
+    /// host has no content of its own.
+    fn content_engine(&self, request: &HttpRequest) -> &Arc<RwLock<Engine>> {
+        self.host(request)
+            .map(|host| host_name(host).to_ascii_lowercase())
+            .and_then(|host_name| self.virtual_hosts.get(&host_name))
+            .unwrap_or(&self.shared_content_engine)
+    }
+
+    /// Locks the request's content engine for reading. If another thread
+    /// panicked while writing to it the lock is still acquired, but returned
+    /// as an error since the engine may be in an inconsistent state.
+    fn read_content_engine(
+        &self,
+        request: &HttpRequest,
+    ) -> Result<RwLockReadGuard<'_, Engine>, RwLockReadGuard<'_, Engine>> {
+        self.content_engine(request)
+            .read()
+            .map_err(PoisonError::into_inner)
+    }
+
+    /// The host that the client asked for. Trusted proxies can set this with
+    /// `X-Forwarded-Host`, otherwise it comes from the `Host` header.
+    fn host<'request>(&self, request: &'request HttpRequest) -> Option<&'request str> {
+        self.forwarded_header(request, X_FORWARDED_HOST)
+            .or_else(|| {
+                request
+                    .headers()
+                    .get(header::HOST)
+                    .and_then(|host| host.to_str().ok())
+            })
+    }
+
+    /// The host that the client asked for, if it did not simply come from the
+    /// client: either a trusted proxy forwarded it or it is one of the
+    /// virtual hosts.
+    fn trusted_host<'request>(&self, request: &'request HttpRequest) -> Option<&'request str> {
+        self.forwarded_header(request, X_FORWARDED_HOST)
+            .or_else(|| {
+                self.host(request).filter(|host| {
+                    self.virtual_hosts
+                        .contains_key(&host_name(host).to_ascii_lowercase())
+                })
+            })
+    }
+
+    /// Either `http` or `https`. Trusted proxies (which may have terminated
+    /// TLS themselves) can set this with `X-Forwarded-Proto`.
+    fn scheme(&self, request: &HttpRequest) -> String {
+        match self.forwarded_header(request, X_FORWARDED_PROTO) {
+            Some(scheme) => scheme.to_ascii_lowercase(),
+            None if request.app_config().secure() => String::from("https"),
+            None => String::from("http"),
+        }
+    }
+
+    /// The URL path that the site is being served under, without a trailing
+    /// slash. Trusted proxies can set this per request with
+    /// `X-Forwarded-Prefix`, otherwise it comes from the config.
+    fn path_prefix(&self, request: &HttpRequest) -> Option<String> {
+        self.forwarded_header(request, X_FORWARDED_PREFIX)
+            .or(self.config.path_prefix.as_deref())
+            .and_then(normalize_path_prefix)
+    }
+
+    /// The route that the request's path refers to, if it is valid.
+    fn route(&self, request: &HttpRequest) -> Option<Route> {
+        let path = strip_path_prefix(request.uri().path(), self.path_prefix(request).as_deref());
+        split_extension(path).0.parse::<Route>().ok()
+    }
+
+    /// The value of an `X-Forwarded-*` header, if the request came from a
+    /// trusted proxy.
+    ///
+    /// Each proxy appends to these headers, and anything before the values
+    /// added by trusted proxies came from the client. So when there is a
+    /// chain of proxies the value added by the outermost trusted one is used,
+    /// counting from the right past each trusted address at the end of
+    /// `X-Forwarded-For`.
+    fn forwarded_header<'request>(
+        &self,
+        request: &'request HttpRequest,
+        header_name: &str,
+    ) -> Option<&'request str> {
+        let is_trusted = |address: &IpAddr| self.config.trusted_proxies.contains(address);
+        let is_from_trusted_proxy = request
+            .peer_addr()
+            .is_some_and(|address| is_trusted(&address.ip()));
+        if !is_from_trusted_proxy {
+            return None;
+        }
+        let header_values = |header_name| {
+            request
+                .headers()
+                .get(header_name)
+                .and_then(|value| value.to_str().ok())
+                .map(|value| value.split(',').map(str::trim).collect::<Vec<_>>())
+                .unwrap_or_default()
+        };
+        let trusted_proxy_count = 1 + header_values(X_FORWARDED_FOR)
+            .into_iter()
+            .rev()
+            .take_while(|address| address.parse().is_ok_and(|address| is_trusted(&address)))
+            .count();
+        let values = header_values(header_name);
+        values
+            .len()
+            .checked_sub(trusted_proxy_count)
+            .map_or(values.first(), |index| values.get(index))
+            .copied()
+            .filter(|value| !value.is_empty())
+    }
+
+    /// Logs messages about `request` according to its route's settings.
+    fn request_logger(&self, request: &HttpRequest) -> RequestLogger {
+        let path = strip_path_prefix(request.uri().path(), self.path_prefix(request).as_deref());
+        match split_extension(path).0.parse::<Route>() {
+            Ok(route) => RequestLogger::new(&self.config.route_config(&route)),
+            Err(_) => RequestLogger::default(),
+        }
+    }
+
+    /// Details about the request URI and connection which are exposed as
+    /// render data.
+    fn http_request_data(&self, request: &HttpRequest) -> HttpRequestData {
+        HttpRequestData {
+            uri_path: Some(String::from(request.uri().path())),
+            query_string: request.uri().query().map(String::from),
+            scheme: Some(self.scheme(request)),
+            host: self.host(request).map(String::from),
+            origin: self
+                .trusted_host(request)
+                .map(|host| format!("{}://{}", self.scheme(request), host)),
+            path_prefix: self.path_prefix(request),
+            url_extension: None,
+            negotiation_source: None,
+        }
+    }
+}
+
+/// Adds a leading slash and removes trailing ones. A prefix of `/` is the
+/// same as no prefix at all.
+fn normalize_path_prefix(prefix: &str) -> Option<String> {
+    let prefix = prefix.trim().trim_matches('/');
+    if prefix.is_empty() {
+        None
+    } else {
+        Some(format!("/{}", prefix))
+    }
+}
+
+/// Removes `prefix` from the start of `path`. Paths outside of the prefix are
+/// left alone, since some proxies remove the prefix themselves.
+fn strip_path_prefix<'a>(path: &'a str, prefix: Option<&str>) -> &'a str {
+    match prefix.and_then(|prefix| path.strip_prefix(prefix)) {
+        Some("") => "/",
+        Some(rest) if rest.starts_with('/') => rest,
+        _ => path,
+    }
+}
+
+/// Strips the port (if any) from a `Host` header value.
+fn host_name(host: &str) -> &str {
+    match host.rsplit_once(':') {
+        // IPv6 addresses contain colons, but are in brackets.
+        Some((host_name, port)) if !port.contains(']') => host_name,
+        _ => host,
+    }
+}
+
+/// Configures a server for content. This is how to embed operator in an
+/// application which already has an actix system (or its own actix `App`).
+///
+/// ```no_run
+/// # use operator::http::OperatorServer;
+/// # use operator::content::{ContentDirectory, FilesystemBasedContentEngine};
+/// # use operator::ServerInfo;
+/// # async fn example(server_info: ServerInfo) -> Result<(), Box<dyn std::error::Error>> {
+/// let content_directory = ContentDirectory::from_root(&"/path/to/content")?;
+/// let shared_content_engine =
+///     FilesystemBasedContentEngine::from_content_directory(content_directory, server_info)?;
+/// let server = OperatorServer::builder(shared_content_engine).bind("127.0.0.1:8080")?;
+/// let handle = server.handle();
+/// // Call `handle.stop(true).await` to stop the server.
+/// server.await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct OperatorServerBuilder<Engine: 'static + ContentEngine<ServerInfo> + Send + Sync> {
+    app_data: AppData<Engine>,
+}
+
+impl<Engine: 'static + ContentEngine<ServerInfo> + Send + Sync> OperatorServerBuilder<Engine> {
+    /// Requests with a `Host` matching one of the `virtual_hosts` are served
+    /// from that host's content engine instead of the default one.
+    pub fn with_virtual_hosts(mut self, virtual_hosts: VirtualHosts<Engine>) -> Self {
+        self.app_data.virtual_hosts = virtual_hosts;
+        self
+    }
+
+    /// Serves content at `index_route` for requests to `/`.
+    pub fn with_index_route(mut self, index_route: Route) -> Self {
+        self.app_data.index_route = Some(index_route);
+        self
+    }
+
+    /// Renders content at `error_handler_route` for error responses.
+    pub fn with_error_handler_route(mut self, error_handler_route: Route) -> Self {
+        self.app_data.error_handler_route = Some(error_handler_route);
+        self
+    }
+
+    pub fn with_config(mut self, config: Config) -> Self {
+        self.app_data.config = config;
+        self
+    }
+
+    /// Used for authorized requests to the reload route.
+    pub fn with_reloader(mut self, reloader: Reloader) -> Self {
+        self.app_data.reloader = Some(reloader);
+        self
+    }
+
+    /// The data needed to serve content from an actix `App` of your own,
+    /// with [`handle_request`] as (for example) its default service.
+    pub fn app_data(&self) -> AppData<Engine> {
+        self.app_data.clone()
+    }
+
+    /// Starts serving on `socket_address`. This must be called from within an
+    /// actix system.
+    pub fn bind<SocketAddress: ToSocketAddrs>(
+        self,
+        socket_address: SocketAddress,
+    ) -> Result<OperatorServer, RunServerError> {
+        let socket_addresses = socket_address
+            .to_socket_addrs()
+            .map_err(|source| RunServerError::BindError { source })?
+            .collect::<Vec<_>>();
+        self.start(Socket::Addresses(socket_addresses))
+    }
+
+    /// Starts serving on an already-bound `listener`. This must be called
+    /// from within an actix system.
+    pub fn listen(self, listener: TcpListener) -> Result<OperatorServer, RunServerError> {
+        self.start(Socket::Listener(listener))
+    }
+
+    fn start(self, socket: Socket) -> Result<OperatorServer, RunServerError> {
+        let timeouts = self.app_data.config.timeouts.clone();
+        let app_data = self.app_data;
+        let http_server = HttpServer::new(move || {
+            App::new()
+                .app_data(app_data.clone())
+                .default_service(web::to(handle_request::<Engine>))
+        })
+        .keep_alive(None)
+        .client_timeout(timeouts.client_request * 1000)
+        .client_shutdown(timeouts.client_shutdown * 1000);
+        let http_server = match socket {
+            Socket::Addresses(socket_addresses) => http_server.bind(&socket_addresses[..]),
+            Socket::Listener(listener) => http_server.listen(listener),
+        }
+        .map_err(|source| RunServerError::BindError { source })?;
+        Ok(OperatorServer {
+            server: http_server.run(),
+        })
+    }
+}
+
+enum Socket {
+    Addresses(Vec<SocketAddr>),
+    Listener(TcpListener),
+}
+
+/// A running server. It serves content until it is stopped (via its
+/// [`handle`](OperatorServer::handle)), and can be awaited to find out when
+/// that happens.
+pub struct OperatorServer {
+    server: Server,
+}
+
+impl OperatorServer {
+    /// Configures a server which serves content from `shared_content_engine`.
+    pub fn builder<Engine>(
+        shared_content_engine: Arc<RwLock<Engine>>,
+    ) -> OperatorServerBuilder<Engine>
+    where
+        Engine: 'static + ContentEngine<ServerInfo> + Send + Sync,
+    {
+        OperatorServerBuilder {
+            app_data: AppData {
+                shared_content_engine,
+                virtual_hosts: HashMap::new(),
+                index_route: None,
+                error_handler_route: None,
+                config: Config::default(),
+                reloader: None,
+                bulkheads: Bulkheads::default(),
+            },
+        }
+    }
+
+    pub fn handle(&self) -> OperatorServerHandle {
+        OperatorServerHandle {
+            server: self.server.clone(),
+        }
+    }
+}
+
+impl Future for OperatorServer {
+    type Output = Result<(), io::Error>;
+
+    fn poll(mut self: Pin<&mut Self>, context: &mut Context<'_>) -> Poll<Self::Output> {
+        let poll = Pin::new(&mut self.server).poll(context);
+        // Executables run in their own process groups, so they would outlive
+        // the server if they were not killed.
+        #[cfg(feature = "executables")]
+        if poll.is_ready() {
+            crate::content::kill_all_process_groups();
+        }
+        poll
+    }
+}
+
+/// Controls a running [`OperatorServer`].
+#[derive(Clone)]
+pub struct OperatorServerHandle {
+    server: Server,
+}
+
+impl OperatorServerHandle {
+    /// Stops the server. If `graceful` is true requests which are in progress
+    /// are finished first.
+    pub async fn stop(&self, graceful: bool) {
+        self.server.stop(graceful).await
+    }
 }
 
+/// Serves content until the server is stopped. Requests are served by
+/// `shared_content_engine` unless their `Host` matches one of the
+/// `virtual_hosts`. If there is a `reloader` it is used whenever the process
+/// receives `SIGHUP`.
+///
+/// This starts its own actix system and blocks until the server stops. Use
+/// [`OperatorServer::builder`] to run within an existing one.
 pub fn run_server<SocketAddress, Engine>(
     shared_content_engine: Arc<RwLock<Engine>>,
+    virtual_hosts: VirtualHosts<Engine>,
     index_route: Option<Route>,
     error_handler_route: Option<Route>,
     socket_address: SocketAddress,
-) -> Result<(), io::Error>
+    config: Config,
+    reloader: Option<Reloader>,
+) -> Result<(), RunServerError>
 where
     SocketAddress: 'static + ToSocketAddrs,
     Engine: 'static + ContentEngine<ServerInfo> + Send + Sync,
@@ -68,33 +497,271 @@ where
     log::info!("Initializing HTTP server");
     let mut system = System::new("server");
     let result = system.block_on(async move {
-        HttpServer::new(move || {
-            App::new()
-                .app_data(AppData {
-                    shared_content_engine: shared_content_engine.clone(),
-                    index_route: index_route.clone(),
-                    error_handler_route: error_handler_route.clone(),
-                })
-                .default_service(web::to(dispatch::<Engine>))
-        })
-        .keep_alive(None)
-        .bind(socket_address)?
-        .run()
-        .await
+        let mut builder = OperatorServer::builder(shared_content_engine)
+            .with_virtual_hosts(virtual_hosts)
+            .with_config(config);
+        if let Some(index_route) = index_route {
+            builder = builder.with_index_route(index_route);
+        }
+        if let Some(error_handler_route) = error_handler_route {
+            builder = builder.with_error_handler_route(error_handler_route);
+        }
+        if let Some(reloader) = reloader {
+            actix_rt::spawn(reload_on_hangup(reloader.clone()));
+            builder = builder.with_reloader(reloader);
+        }
+        builder
+            .bind(socket_address)?
+            .await
+            .map_err(|source| RunServerError::RunError { source })
     });
 
     log::info!("HTTP server has terminated");
     result
 }
 
-async fn dispatch<Engine>(request: HttpRequest) -> HttpResponse
+/// Responds to a request for content. This can be used as a service in an
+/// actix `App` which has [`AppData`] (from
+/// [`OperatorServerBuilder::app_data`]).
+pub async fn handle_request<Engine>(request: HttpRequest) -> HttpResponse
+where
+    Engine: 'static + ContentEngine<ServerInfo> + Send + Sync,
+{
+    let cors_headers = cors_headers::<Engine>(&request);
+    let request_timeout = request
+        .app_data::<AppData<Engine>>()
+        .and_then(|app_data| app_data.config.timeouts.request_timeout());
+    let loggable_request = format!("{} {}", request.method(), request.uri());
+
+    // Renders are cancelled if this future is dropped before it completes
+    // (e.g. because the client disconnected).
+    let cancellation = Cancellation::new();
+    request.extensions_mut().insert(cancellation.clone());
+    let render_guard = cancellation.cancel_on_drop();
+
+    let (bulkhead_permit, response) = match enter_bulkhead::<Engine>(&request).await {
+        Err(response) => (None, future::ready(response).boxed_local()),
+        Ok(bulkhead_permit) => (
+            bulkhead_permit,
+            match *request.method() {
+                http::Method::GET => get::<Engine>(request).boxed_local(),
+                http::Method::OPTIONS => options(request).boxed_local(),
+                http::Method::POST if is_reload_request::<Engine>(&request) => {
+                    reload::<Engine>(request).boxed_local()
+                }
+                _ => unsupported_request_method::<Engine>(request).boxed_local(),
+            },
+        ),
+    };
+    let mut response = match request_timeout {
+        None => response.await,
+        Some(request_timeout) => match time::timeout(request_timeout, response).await {
+            Ok(response) => response,
+            Err(_) => {
+                log::error!(
+                    "Request {} was aborted after exceeding the {} second timeout",
+                    loggable_request,
+                    request_timeout.as_secs(),
+                );
+                cancellation.cancel();
+                HttpResponse::ServiceUnavailable()
+                    .content_type(mime::TEXT_PLAIN.to_string())
+                    .body("The response took too long.")
+            }
+        },
+    };
+    render_guard.disarm();
+    for (header_name, header_value) in cors_headers.iter() {
+        if header_name == header::VARY {
+            response
+                .headers_mut()
+                .append(header_name.clone(), header_value.clone());
+        } else {
+            response
+                .headers_mut()
+                .insert(header_name.clone(), header_value.clone());
+        }
+    }
+    match bulkhead_permit {
+        None => response,
+        // Streaming bodies can keep executables running, so the slot is held
+        // until the body has been sent.
+        Some(bulkhead_permit) => response.map_body(|_, body| {
+            ResponseBody::Body(Body::from_message(PermittedBody {
+                body,
+                _bulkhead_permit: bulkhead_permit,
+            }))
+        }),
+    }
+}
+
+/// Waits for a slot if the request's route has `max-concurrent-requests`.
+/// Requests which wait too long get an error response instead.
+async fn enter_bulkhead<Engine>(
+    request: &HttpRequest,
+) -> Result<Option<BulkheadPermit>, HttpResponse>
+where
+    Engine: 'static + ContentEngine<ServerInfo> + Send + Sync,
+{
+    let app_data = match request.app_data::<AppData<Engine>>() {
+        Some(app_data) => app_data,
+        None => return Ok(None),
+    };
+    let route = match app_data.route(request) {
+        Some(route) => route,
+        None => return Ok(None),
+    };
+    let limited_route = match app_data.config.concurrency_limited_route(&route) {
+        Some(limited_route) => limited_route,
+        None => return Ok(None),
+    };
+    let route_config = app_data.config.route_config(limited_route);
+    let bulkhead = app_data.bulkheads.get(
+        limited_route,
+        route_config.max_concurrent_requests.unwrap_or(usize::MAX),
+    );
+    let queue_timeout = Duration::from_millis(route_config.queue_timeout_ms.unwrap_or(500));
+    match bulkhead.enter(queue_timeout).await {
+        Some(bulkhead_permit) => Ok(Some(bulkhead_permit)),
+        None => {
+            log::warn!(
+                "Rejecting {} {} because too many requests within {} are in progress",
+                request.method(),
+                request.uri(),
+                limited_route,
+            );
+            Err(HttpResponse::ServiceUnavailable()
+                .content_type(mime::TEXT_PLAIN.to_string())
+                .header(
+                    header::RETRY_AFTER,
+                    route_config.retry_after.unwrap_or(1).to_string(),
+                )
+                .body("The server is too busy to handle this request."))
+        }
+    }
+}
+
+/// A response body which holds a [`BulkheadPermit`] until it is dropped.
+struct PermittedBody {
+    body: ResponseBody<Body>,
+    _bulkhead_permit: BulkheadPermit,
+}
+
+impl MessageBody for PermittedBody {
+    fn size(&self) -> BodySize {
+        self.body.size()
+    }
+
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        context: &mut Context<'_>,
+    ) -> Poll<Option<Result<Bytes, actix_web::Error>>> {
+        Pin::new(&mut self.body).poll_next(context)
+    }
+}
+
+/// `Access-Control-*` response headers for a request from another origin, if
+/// the request's route has CORS settings which allow it. Preflight requests
+/// (`OPTIONS` requests with `Access-Control-Request-Method`) also get the
+/// allowed methods and headers.
+fn cors_headers<Engine>(request: &HttpRequest) -> HeaderMap
 where
     Engine: 'static + ContentEngine<ServerInfo> + Send + Sync,
 {
-    match *request.method() {
-        http::Method::GET => get::<Engine>(request).await,
-        http::Method::OPTIONS => options(request).await,
-        _ => unsupported_request_method::<Engine>(request).await,
+    let mut headers = HeaderMap::new();
+    let app_data = match request.app_data::<AppData<Engine>>() {
+        Some(app_data) => app_data,
+        None => return headers,
+    };
+
+    let route = match app_data.route(request) {
+        Some(route) => route,
+        None => return headers,
+    };
+    let cors_config = match app_data.config.route_config(&route).cors {
+        Some(cors_config) => cors_config,
+        None => return headers,
+    };
+    // Unless any origin is allowed, the response differs depending on the
+    // origin (even when there is none, or it is not allowed), so caches must
+    // not reuse it for other origins.
+    if !cors_config.allows_any_origin() {
+        headers.insert(header::VARY, HeaderValue::from_static("Origin"));
+    }
+
+    let origin = match request.headers().get(header::ORIGIN) {
+        Some(origin) => origin,
+        None => return headers,
+    };
+    if !origin
+        .to_str()
+        .is_ok_and(|origin| cors_config.allows_origin(origin))
+    {
+        return headers;
+    }
+
+    if request.method() == http::Method::OPTIONS {
+        if let Some(requested_method) = request.headers().get(header::ACCESS_CONTROL_REQUEST_METHOD)
+        {
+            if !is_allowed_preflight(&cors_config, requested_method, request.headers()) {
+                return headers;
+            }
+            insert_joined_header(
+                &mut headers,
+                header::ACCESS_CONTROL_ALLOW_METHODS,
+                &cors_config.allowed_methods,
+            );
+            insert_joined_header(
+                &mut headers,
+                header::ACCESS_CONTROL_ALLOW_HEADERS,
+                &cors_config.allowed_headers,
+            );
+            if let Some(max_age) = cors_config.max_age {
+                headers.insert(header::ACCESS_CONTROL_MAX_AGE, HeaderValue::from(max_age));
+            }
+        }
+    }
+
+    if cors_config.allows_any_origin() {
+        headers.insert(
+            header::ACCESS_CONTROL_ALLOW_ORIGIN,
+            HeaderValue::from_static("*"),
+        );
+    } else {
+        headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, origin.clone());
+    }
+    headers
+}
+
+fn is_allowed_preflight(
+    cors_config: &CorsConfig,
+    requested_method: &HeaderValue,
+    request_headers: &HeaderMap,
+) -> bool {
+    let is_allowed_method = requested_method
+        .to_str()
+        .is_ok_and(|method| cors_config.allows_method(method));
+    let are_allowed_headers = request_headers
+        .get_all(header::ACCESS_CONTROL_REQUEST_HEADERS)
+        .all(|requested_headers| {
+            requested_headers.to_str().is_ok_and(|requested_headers| {
+                requested_headers
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|header_name| !header_name.is_empty())
+                    .all(|header_name| cors_config.allows_header(header_name))
+            })
+        });
+    is_allowed_method && are_allowed_headers
+}
+
+fn insert_joined_header(headers: &mut HeaderMap, name: header::HeaderName, values: &[String]) {
+    if values.is_empty() {
+        return;
+    }
+    match HeaderValue::from_str(&values.join(", ")) {
+        Ok(value) => headers.insert(name, value),
+        Err(error) => log::error!("Invalid {} header {:?}: {}", name, values, error),
     }
 }
 
@@ -113,33 +780,41 @@ where
 /// PDF format, visit http://mysite.com/resume.pdf" to "...first install this
 /// browser extension that lets you customize HTTP headers, then set the accept
 /// header to application/pdf, then visit http://mysite.com/resume").
+// The content engine lock is explicitly dropped before any awaits, but clippy
+// can't tell (see <https://github.com/rust-lang/rust-clippy/issues/6446>).
+#[allow(clippy::await_holding_lock)]
 async fn get<Engine>(request: HttpRequest) -> HttpResponse
 where
     Engine: 'static + ContentEngine<ServerInfo> + Send + Sync,
 {
-    log_request(&request);
-
-    let app_data = request
-        .app_data::<AppData<Engine>>()
-        .expect("App data was not of the expected type!");
+    let app_data = match request.app_data::<AppData<Engine>>() {
+        Some(app_data) => app_data,
+        None => return missing_app_data_response(),
+    };
 
-    let path = request.uri().path();
+    let request_logger = app_data.request_logger(&request);
+    log_request(&request, request_logger);
+
+    let deadline = app_data
+        .config
+        .timeouts
+        .request_timeout()
+        .map(|request_timeout| time::Instant::now() + request_timeout);
+    let mut http_request_data = app_data.http_request_data(&request);
+    let path = strip_path_prefix(
+        request.uri().path(),
+        http_request_data.path_prefix.as_deref(),
+    );
 
-    let content_engine = app_data
-        .shared_content_engine
-        .read()
-        .expect("RwLock for ContentEngine has been poisoned");
+    let content_engine = match app_data.read_content_engine(&request) {
+        Ok(content_engine) => content_engine,
+        Err(content_engine) => {
+            return poisoned_content_engine_response(&*content_engine, app_data, http_request_data)
+        }
+    };
 
     let (route, media_range_from_url) = {
-        let media_range_from_url = MimeGuess::from_path(path).first();
-        let path_without_extension = if media_range_from_url.is_some() {
-            // Drop the extension from the path.
-            path.rsplitn(2, '.').last().expect(bug_message!(
-                "Calling rsplitn(2, ..) on a non-empty string returned an empty iterator. This should be impossible!",
-            ))
-        } else {
-            path
-        };
+        let (path_without_extension, media_range_from_url) = split_extension(path);
 
         match path_without_extension.parse::<Route>() {
             Err(error) => {
@@ -150,11 +825,10 @@ where
                         path, error
                     ),
                     &*content_engine,
-                    RequestData {
-                        route: None,
-                        query_parameters: HashMap::new(),
-                        request_headers: HashMap::new(),
-                    },
+                    None,
+                    QueryParameters::default(),
+                    HashMap::new(),
+                    http_request_data.clone(),
                     &app_data.error_handler_route,
                     vec![&mime::TEXT_PLAIN],
                     HeaderMap::new(),
@@ -184,11 +858,10 @@ where
                 http::StatusCode::BAD_REQUEST,
                 format!("Malformed query string `{}`: {}", query_string, error),
                 &*content_engine,
-                RequestData {
-                    route: Some(route),
-                    query_parameters: HashMap::new(),
-                    request_headers: HashMap::new(),
-                },
+                Some(route),
+                QueryParameters::default(),
+                HashMap::new(),
+                http_request_data.clone(),
                 &app_data.error_handler_route,
                 vec![&mime::TEXT_PLAIN],
                 HeaderMap::new(),
@@ -203,11 +876,10 @@ where
                 http::StatusCode::BAD_REQUEST,
                 format!("Failed to handle request headers: {}", error),
                 &*content_engine,
-                RequestData {
-                    route: Some(route),
-                    query_parameters,
-                    request_headers: HashMap::new(),
-                },
+                Some(route),
+                query_parameters,
+                HashMap::new(),
+                http_request_data.clone(),
                 &app_data.error_handler_route,
                 vec![&mime::TEXT_PLAIN],
                 HeaderMap::new(),
@@ -215,27 +887,34 @@ where
         }
     };
 
+    if media_range_from_url.is_some() {
+        http_request_data.url_extension = path.rsplit('.').next().map(String::from);
+        http_request_data.negotiation_source = Some(String::from("url-extension"));
+    } else {
+        http_request_data.negotiation_source = Some(String::from("accept-header"));
+    }
+
     // Use the media type from the URL path extension if there was one,
     // otherwise use the accept header.
-    let mut parsed_accept_header_value = header::Accept::parse(&request);
-    let acceptable_media_ranges = match media_range_from_url {
-        Some(ref media_range_from_url) => vec![media_range_from_url],
-        None => match parsed_accept_header_value {
-            Ok(ref mut accept_value) => acceptable_media_ranges_from_accept_header(accept_value),
-            Err(error) => {
+    let accept_header_media_ranges = match media_range_from_url {
+        Some(_) => Vec::new(),
+        None => match accept_header_value(&request)
+            .as_deref()
+            .and_then(render::acceptable_media_ranges)
+        {
+            Some(media_ranges) => media_ranges,
+            None => {
                 return error_response(
                     http::StatusCode::BAD_REQUEST,
                     format!(
-                        "Malformed Accept header value `{:?}`: {}",
+                        "Malformed Accept header value `{:?}`",
                         request.headers().get(header::ACCEPT),
-                        error
                     ),
                     &*content_engine,
-                    RequestData {
-                        route: Some(route),
-                        query_parameters,
-                        request_headers,
-                    },
+                    Some(route),
+                    query_parameters,
+                    request_headers,
+                    http_request_data.clone(),
                     &app_data.error_handler_route,
                     vec![&mime::TEXT_PLAIN],
                     HeaderMap::new(),
@@ -243,1053 +922,3513 @@ where
             }
         },
     };
+    let acceptable_media_ranges = match media_range_from_url {
+        Some(ref media_range_from_url) => vec![media_range_from_url],
+        None => accept_header_media_ranges.iter().collect(),
+    };
 
-    let render_result = content_engine.get(&route).map(|content| {
-        let render_context = content_engine.render_context(
-            Some(route.clone()),
-            query_parameters.clone(),
-            request_headers.clone(),
-        );
-        content.render(render_context, acceptable_media_ranges.clone())
-    });
+    if let Some(response) = search_response(
+        &*content_engine,
+        &app_data.config.search,
+        &route,
+        &query_parameters,
+    ) {
+        return response;
+    }
 
-    match render_result {
-        Some(Ok(Media {
-            content,
-            media_type,
-        })) => {
-            log::info!(
-                "Responding with {}, body from {} as {}",
-                http::StatusCode::OK,
-                route,
-                media_type,
-            );
-            let loggable_media_type = media_type.clone();
-            let loggable_route = route.clone();
-            HttpResponse::Ok()
-                .content_type(media_type.to_string())
-                .streaming(
-                    content
-                        .map_err(|error| {
-                            log::error!(
-                                "An error occurred while streaming a response body: {}",
-                                error,
-                            );
-                        })
-                        .inspect_ok(move |bytes| {
-                            let max_length = 64;
-                            if bytes.len() > max_length {
-                                log::trace!(
-                                    "Streaming data for {} as {}: {:?} ...and {} more bytes",
-                                    loggable_route,
-                                    loggable_media_type,
-                                    bytes.slice(0..max_length),
-                                    bytes.len() - max_length
-                                );
-                            } else {
-                                log::trace!(
-                                    "Streaming data for {} as {}: {:?}",
-                                    loggable_route,
-                                    loggable_media_type,
-                                    bytes
-                                );
-                            }
-                        }),
-                )
+    if let Some(response) = status_response(&*content_engine, &app_data.config.status, &route) {
+        return response;
+    }
+
+    match render::outcome_without_rendering(&*content_engine, &route) {
+        Some(RenderOutcome::Redirect(redirect)) => {
+            return redirect_response(redirect, http_request_data.path_prefix.as_deref());
         }
-        Some(Err(error @ RenderError::CannotProvideAcceptableMediaType { .. })) => error_response(
-            http::StatusCode::NOT_ACCEPTABLE,
-            format!("Cannot provide an acceptable response: {}", error),
-            &*content_engine,
-            RequestData {
-                route: Some(route),
-                query_parameters,
-                request_headers,
-            },
-            &app_data.error_handler_route,
-            acceptable_media_ranges,
-            HeaderMap::new(),
-        ),
-        Some(Err(error)) => error_response(
-            http::StatusCode::INTERNAL_SERVER_ERROR,
-            format!("Failed to render content: {}", error),
-            &*content_engine,
-            RequestData {
-                route: Some(route),
-                query_parameters,
-                request_headers,
-            },
-            &app_data.error_handler_route,
-            acceptable_media_ranges,
-            HeaderMap::new(),
-        ),
-        None => error_response(
-            http::StatusCode::NOT_FOUND,
-            "No content found at route",
-            &*content_engine,
-            RequestData {
-                route: Some(route),
+        Some(_) => {
+            return error_response(
+                http::StatusCode::NOT_FOUND,
+                "No content found at route",
+                &*content_engine,
+                Some(route),
                 query_parameters,
                 request_headers,
-            },
-            &app_data.error_handler_route,
-            acceptable_media_ranges,
-            HeaderMap::new(),
-        ),
+                http_request_data.clone(),
+                &app_data.error_handler_route,
+                acceptable_media_ranges,
+                HeaderMap::new(),
+            );
+        }
+        None => {}
     }
-}
 
-async fn options(request: HttpRequest) -> HttpResponse {
-    log_request(&request);
+    let fallback_chain = render::fallback_chain(&*content_engine, &app_data.config, &route);
 
-    log::info!("Responding with {}", http::StatusCode::NO_CONTENT);
+    // Don't hold the lock while waiting for content to be buffered.
+    drop(content_engine);
 
-    let mut response_builder = HttpResponse::build(http::StatusCode::NO_CONTENT);
-    response_builder.header(
-        http::header::ALLOW,
-        HeaderValue::from_static(ALLOWED_REQUEST_METHODS),
+    let render_request = RenderRequest::new(
+        route.clone(),
+        acceptable_media_ranges.iter().copied().cloned().collect(),
+    )
+    .with_query_parameters(query_parameters.clone())
+    .with_request_headers(request_headers.clone())
+    .with_http_request_data(http_request_data.clone())
+    .with_cancellation(
+        request
+            .extensions()
+            .get::<Cancellation>()
+            .cloned()
+            .unwrap_or_default(),
     );
-    response_builder.finish()
-}
-
-async fn unsupported_request_method<Engine>(request: HttpRequest) -> HttpResponse
-where
-    Engine: 'static + ContentEngine<ServerInfo> + Send + Sync,
-{
-    log_request(&request);
 
-    let app_data = request
-        .app_data::<AppData<Engine>>()
-        .expect("App data was not of the expected type!");
+    // Try the requested route first, then any fallbacks.
+    let mut failure_details = None;
+    let mut render_failure = None;
+    for candidate_route in fallback_chain {
+        let render_result = {
+            let content_engine = match app_data.read_content_engine(&request) {
+                Ok(content_engine) => content_engine,
+                Err(content_engine) => {
+                    return poisoned_content_engine_response(
+                        &*content_engine,
+                        app_data,
+                        http_request_data,
+                    )
+                }
+            };
+            match render::render_candidate(
+                &*content_engine,
+                &app_data.config,
+                &render_request,
+                &candidate_route,
+            ) {
+                RenderOutcome::NotFound => {
+                    log::error!("Fallback route {} does not exist", candidate_route);
+                    continue;
+                }
+                RenderOutcome::Redirect(redirect) => {
+                    return redirect_response(redirect, http_request_data.path_prefix.as_deref());
+                }
+                RenderOutcome::NotAcceptable(error) => {
+                    return error_response(
+                        http::StatusCode::NOT_ACCEPTABLE,
+                        format!("Cannot provide an acceptable response: {}", error),
+                        &*content_engine,
+                        Some(route),
+                        query_parameters,
+                        request_headers,
+                        http_request_data.clone(),
+                        &app_data.error_handler_route,
+                        acceptable_media_ranges,
+                        HeaderMap::new(),
+                    );
+                }
+                RenderOutcome::Failed(error) => Err(error),
+                RenderOutcome::Rendered(rendered) => {
+                    rendered_media(&request, rendered, &app_data.config.route_config(&route))
+                }
+            }
+        };
 
-    let content_engine = app_data
-        .shared_content_engine
-        .read()
-        .expect("RwLock for ContentEngine has been poisoned");
+        if candidate_route != route {
+            request_logger.log(
+                log::Level::Warn,
+                format_args!("Using fallback route {} for {}", candidate_route, route),
+            );
+        }
 
-    let mut response_headers = HeaderMap::with_capacity(1);
-    response_headers.insert(
-        http::header::ALLOW,
-        HeaderValue::from_static(ALLOWED_REQUEST_METHODS),
-    );
+        let (
+            Media {
+                content,
+                media_type,
+            },
+            ResponseSettings {
+                status: response_status,
+                headers: mut response_headers,
+                etag,
+            },
+        ) = match render_result {
+            Ok(media) => media,
+            Err(error) => {
+                request_logger.log(
+                    log::Level::Warn,
+                    format_args!("Failed to render {}: {}", candidate_route, error),
+                );
+                failure_details = Some(format!("Failed to render content: {}", error));
+                if app_data.config.dev {
+                    render_failure = Some(RenderFailure::new(
+                        candidate_route,
+                        &error,
+                        error.location(),
+                    ));
+                }
+                continue;
+            }
+        };
+
+        let streaming_config = &app_data.config.streaming;
+        // Entity tags are computed from the whole body.
+        let buffer_threshold = match etag {
+            Some(_) => usize::MAX,
+            None => streaming_config.buffer_threshold_for(&media_type),
+        };
+        let content = match buffer_response_body(content, buffer_threshold).await {
+            Err(error) => {
+                request_logger.log(
+                    log::Level::Warn,
+                    format_args!("Failed to render {}: {}", candidate_route, error),
+                );
+                failure_details = Some(format!("Failed to render content: {}", error));
+                if app_data.config.dev {
+                    render_failure = Some(RenderFailure::new(candidate_route, &error, None));
+                }
+                continue;
+            }
+            Ok(BufferedResponseBody::Complete(bytes)) => {
+                // Conditional requests only apply to successful responses.
+                if let Some(strength) = etag.filter(|_| response_status.is_success()) {
+                    let entity_tag = entity_tag(&bytes, strength);
+                    let is_not_modified = request
+                        .headers()
+                        .get_all(header::IF_NONE_MATCH)
+                        .any(|if_none_match| entity_tag_matches(if_none_match, &entity_tag));
+                    match HeaderValue::from_str(&entity_tag) {
+                        Ok(value) => {
+                            response_headers.insert(header::ETAG, value);
+                        }
+                        Err(error) => log::error!("Invalid ETag `{}`: {}", entity_tag, error),
+                    }
+                    if is_not_modified {
+                        request_logger.log(
+                            log::Level::Info,
+                            format_args!(
+                                "Responding with {} for {}",
+                                http::StatusCode::NOT_MODIFIED,
+                                candidate_route,
+                            ),
+                        );
+                        return response_builder(http::StatusCode::NOT_MODIFIED, &response_headers)
+                            .finish();
+                    }
+                }
+                request_logger.log(
+                    log::Level::Info,
+                    format_args!(
+                        "Responding with {}, buffered body from {} as {}",
+                        response_status, candidate_route, media_type,
+                    ),
+                );
+                return response_builder(response_status, &response_headers)
+                    .content_type(media_type.to_string())
+                    .body(bytes);
+            }
+            Ok(BufferedResponseBody::Incomplete(content)) => content,
+        };
+
+        request_logger.log(
+            log::Level::Info,
+            format_args!(
+                "Responding with {}, body from {} as {}",
+                response_status, candidate_route, media_type,
+            ),
+        );
+        return response_builder(response_status, &response_headers)
+            .content_type(media_type.to_string())
+            .streaming(streaming_response_body(
+                content,
+                candidate_route,
+                media_type,
+                streaming_config,
+                deadline,
+                render_request.cancellation.cancel_on_drop(),
+                request_logger,
+            ));
+    }
 
+    let content_engine = match app_data.read_content_engine(&request) {
+        Ok(content_engine) => content_engine,
+        Err(content_engine) => {
+            return poisoned_content_engine_response(&*content_engine, app_data, http_request_data)
+        }
+    };
+    if let Some(render_failure) = render_failure {
+        let mut render_data =
+            content_engine.render_data(Some(route.clone()), query_parameters, request_headers);
+        render_data.request.http = http_request_data;
+        request_logger.log(
+            log::Level::Warn,
+            format_args!(
+                "Responding with {} for {}, body as a development error page",
+                http::StatusCode::INTERNAL_SERVER_ERROR,
+                route,
+            ),
+        );
+        return response_builder(http::StatusCode::INTERNAL_SERVER_ERROR, &HeaderMap::new())
+            .content_type("text/html")
+            .body(render_failure.to_html(&*content_engine, &render_data));
+    }
     error_response(
-        http::StatusCode::METHOD_NOT_ALLOWED,
-        format!("The {} request method is not supported", request.method()),
+        http::StatusCode::INTERNAL_SERVER_ERROR,
+        failure_details.unwrap_or_else(|| String::from("Failed to render content")),
         &*content_engine,
-        RequestData {
-            route: None,
-            query_parameters: HashMap::new(),
-            request_headers: HashMap::new(),
-        },
+        Some(route),
+        query_parameters,
+        request_headers,
+        http_request_data.clone(),
         &app_data.error_handler_route,
-        vec![&mime::TEXT_PLAIN],
-        response_headers,
+        acceptable_media_ranges,
+        HeaderMap::new(),
     )
 }
 
-fn log_request(request: &HttpRequest) {
-    log::info!(
-        // e.g. "Handling request GET /styles.css HTTP/1.1 with Accept: text/css,*/*;q=0.1"
-        "Handling request {} {} {}{}",
-        request.method(),
-        request.uri(),
-        match request.version() {
-            http::Version::HTTP_09 => "HTTP/0.9",
-            http::Version::HTTP_10 => "HTTP/1.0",
-            http::Version::HTTP_11 => "HTTP/1.1",
-            http::Version::HTTP_2 => "HTTP/2.0",
-            http::Version::HTTP_3 => "HTTP/3.0",
-            _ => "HTTP",
-        },
-        request
-            .headers()
-            .get(header::ACCEPT)
-            .and_then(|value| value.to_str().ok())
-            .map(|value| format!(" with Accept: {}", value))
-            .unwrap_or_default()
-    );
+/// Prepares successfully-rendered content to become a response to `request`,
+/// which may mean swapping it for a precompressed copy or part of the content.
+fn rendered_media(
+    request: &HttpRequest,
+    rendered: Rendered<'_>,
+    route_config: &RouteConfig,
+) -> Result<(Media<Box<dyn ByteStream>>, ResponseSettings), RenderError> {
+    let Response {
+        status,
+        headers,
+        media,
+    } = rendered.response;
+    let mut response_settings =
+        ResponseSettings::new(status, &headers, route_config, rendered.content);
+    let media = match rendered.content {
+        Some(registered_content) => {
+            let media = precompressed_media(
+                request,
+                registered_content,
+                media,
+                &mut response_settings.headers,
+            )?;
+            byte_range_media(request, registered_content, media, &mut response_settings)?
+        }
+        None => media,
+    };
+    Ok((media, response_settings))
 }
 
-fn error_response<Details, Engine>(
-    status_code: http::StatusCode,
-    details: Details,
-    content_engine: &Engine,
-    request_data: RequestData,
-    error_handler_route: &Option<Route>,
-    acceptable_media_ranges: Vec<&MediaRange>,
-    response_headers: HeaderMap,
-) -> HttpResponse
-where
-    Details: AsRef<str>,
-    Engine: 'static + ContentEngine<ServerInfo> + Send + Sync,
-{
-    let error_code = if !status_code.is_client_error() && !status_code.is_server_error() {
-        log::error!(
-            bug_message!(
-                "This should never happen: The HTTP status code given to the error handler ({}) does not indicate an error.",
-            ),
-            status_code,
-        );
-        http::StatusCode::INTERNAL_SERVER_ERROR
-    } else {
-        status_code
-    };
+/// How successfully-rendered content becomes a response, from the route's
+/// settings and the front matter of the content which was rendered (which
+/// takes precedence).
+struct ResponseSettings {
+    status: http::StatusCode,
+    headers: HeaderMap,
+    etag: Option<EntityTagStrength>,
+}
 
-    let mut response_builder = HttpResponse::build(error_code);
-    for (header_name, header_value) in response_headers.iter() {
-        response_builder.header(header_name, header_value.clone());
-    }
+impl ResponseSettings {
+    /// The `status` and `headers` come from the [`Response`] of the rendered
+    /// content.
+    fn new(
+        status: u16,
+        headers: &[(String, String)],
+        route_config: &RouteConfig,
+        content: Option<&RegisteredContent>,
+    ) -> Self {
+        let status = http::StatusCode::from_u16(status).unwrap_or_else(|error| {
+            log::error!("Invalid status code {}: {}", status, error);
+            http::StatusCode::INTERNAL_SERVER_ERROR
+        });
 
-    error_handler_route
-        .as_ref()
-        .and_then(|route| {
-            content_engine.get(route).and_then(|content| {
-                let error_context = content_engine
-                    .render_context(
-                        request_data.route.clone(),
-                        request_data.query_parameters,
-                        request_data.request_headers,
-                    )
-                    .into_error_context(status_code.as_u16());
-                match content.render(error_context, acceptable_media_ranges) {
-                    Ok(rendered_content) => Some((route, rendered_content)),
-                    Err(rendering_error) => {
-                        log::error!(
-                            "Error occurred while rendering error handler: {}",
-                            rendering_error
-                        );
-                        None
-                    }
-                }
-            })
-        })
-        .map(
-            |(
-                error_handler_route,
-                Media {
-                    media_type,
-                    content,
-                },
-            )| {
-                match request_data.route.clone() {
-                    Some(request_route) => log::warn!(
-                        "Responding with {} for {}, body from {} as {}: {}",
-                        status_code,
-                        request_route,
-                        error_handler_route,
-                        media_type,
-                        details.as_ref()
-                    ),
-                    None => log::warn!(
-                        "Responding with {}, body from {} as {}: {}",
-                        status_code,
-                        error_handler_route,
-                        media_type,
-                        details.as_ref()
-                    ),
-                };
-                response_builder
-                    .content_type(media_type.to_string())
-                    .streaming(content.map_err(|error| {
-                        log::error!(
-                            "An error occurred while streaming a response body: {}",
-                            error,
-                        );
-                    }))
-            },
-        )
-        .unwrap_or_else(|| {
-            // Send a default error response if the error handler failed or was
-            // not specified.
-            let media_type = "text/plain";
-            match request_data.route {
-                Some(request_route) => log::warn!(
-                    "Responding with {} for {}, body as {}: {}",
-                    status_code,
-                    request_route,
-                    media_type,
-                    details.as_ref()
-                ),
-                None => log::warn!(
-                    "Responding with {}, body as {}: {}",
-                    status_code,
-                    media_type,
-                    details.as_ref()
-                ),
-            };
-            response_builder.content_type(media_type).body(
-                error_code
-                    .canonical_reason()
-                    .unwrap_or("Something Went Wrong"),
-            )
-        })
+        let mut header_map = HeaderMap::new();
+        for (name, value) in headers {
+            match (
+                header::HeaderName::from_bytes(name.as_bytes()),
+                HeaderValue::from_str(value),
+            ) {
+                (Ok(name), Ok(value)) => header_map.append(name, value),
+                (Err(error), _) => log::error!("Invalid header name `{}`: {}", name, error),
+                (_, Err(error)) => log::error!("Invalid {} header `{}`: {}", name, value, error),
+            }
+        }
+
+        let etag = match content {
+            Some(RegisteredContent::RegisteredTemplate(template)) => {
+                template.front_matter().settings().etag
+            }
+            _ => None,
+        }
+        .or(route_config.etag);
+
+        ResponseSettings {
+            status,
+            headers: header_map,
+            etag,
+        }
+    }
 }
 
-fn acceptable_media_ranges_from_accept_header<'a>(
-    accept_value: &'a mut header::Accept,
-) -> Vec<&'a MediaRange> {
-    // If the accept header value is empty, allow any media type.
-    if accept_value.is_empty() {
-        vec![&mime::STAR_STAR]
-    } else {
-        // Sort in order of descending quality (so the client's most-preferred
-        // representation is first).
-        //
-        // Note that QualityItem only implements PartialOrd, not Ord. I thought
-        // that might be because the parser lossily converts decimal strings
-        // into integers (for the `q` parameter), but it turns out the
-        // implementation actually never returns None (as of actix-web v3.0.0).
-        // If that ever changes and there is some scenario where a pair of
-        // items from the accept header can't be ordered then they will be
-        // given equal preference. ¯\_(ツ)_/¯
-        accept_value.sort_by(|a, b| {
-            b.partial_cmp(a).unwrap_or_else(|| {
-                log::warn!(
-                    "Accept header items `{}` and `{}` could not be ordered by quality",
-                    a,
-                    b
-                );
-                Ordering::Equal
-            })
-        });
+/// A quoted hash of `body`, suitable for use as an `ETag` header. This is
+/// the first 128 bits of its SHA-256 digest, which is stable across builds
+/// and servers (unlike `DefaultHasher`) and collision-resistant.
+fn entity_tag(body: &[u8], strength: EntityTagStrength) -> String {
+    let hash = Sha256::digest(body)[..16]
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect::<String>();
+    match strength {
+        EntityTagStrength::Strong => format!("\"{}\"", hash),
+        EntityTagStrength::Weak => format!("W/\"{}\"", hash),
+    }
+}
 
-        accept_value
-            .iter()
-            .map(|quality_item| &quality_item.item)
-            .collect::<Vec<&'a MediaRange>>()
+/// Whether an `If-None-Match` header value matches `entity_tag`. This uses
+/// the weak comparison function, as RFC 7232 requires.
+fn entity_tag_matches(if_none_match: &HeaderValue, entity_tag: &str) -> bool {
+    let opaque_tag = |tag: &str| String::from(tag.trim().trim_start_matches("W/"));
+    match if_none_match.to_str() {
+        Ok(if_none_match) => if_none_match.split(',').any(|candidate| {
+            candidate.trim() == "*" || opaque_tag(candidate) == opaque_tag(entity_tag)
+        }),
+        Err(_) => false,
     }
 }
 
-fn simplify_http_headers(
-    header_map: &HeaderMap,
-) -> Result<HashMap<String, String>, header::ToStrError> {
-    let mut simplified_headers = HashMap::new();
-    for key in header_map.keys() {
-        let mut combined_value = None;
-        for value in header_map.get_all(key) {
-            // This doesn't quite follow the HTTP spec (see the last paragraph
-            // of <https://datatracker.ietf.org/doc/html/rfc7230#section-3.2.4>
-            // for example).
-            let utf8_value = value.to_str()?;
-            combined_value = match combined_value {
-                None => Some(utf8_value.to_string()),
-                Some(previous_value) => Some(format!("{},{}", previous_value, utf8_value)),
-            }
-        }
-        if let Some(value) = combined_value {
-            simplified_headers.insert(key.to_string(), value);
+/// Swaps `media` for a precompressed copy of the content if there is one that
+/// the client accepts, adding `Content-Encoding` to `response_headers`.
+/// Responses for content with precompressed copies always vary by
+/// `Accept-Encoding`.
+fn precompressed_media(
+    request: &HttpRequest,
+    registered_content: &RegisteredContent,
+    media: Media<Box<dyn ByteStream>>,
+    response_headers: &mut HeaderMap,
+) -> Result<Media<Box<dyn ByteStream>>, RenderError> {
+    let content_codings = registered_content.precompressed_codings();
+    if content_codings.is_empty() {
+        return Ok(media);
+    }
+    response_headers.append(header::VARY, HeaderValue::from_static("Accept-Encoding"));
+    match preferred_content_coding(request, &content_codings).and_then(|content_coding| {
+        registered_content
+            .render_precompressed(content_coding)
+            .map(|result| result.map(|media| (content_coding, media)))
+    }) {
+        None => Ok(media),
+        Some(result) => {
+            let (content_coding, precompressed_media) = result?;
+            response_headers.insert(
+                header::CONTENT_ENCODING,
+                HeaderValue::from_static(content_coding.as_str()),
+            );
+            Ok(precompressed_media)
         }
     }
-    Ok(simplified_headers)
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::test_lib::*;
-    use actix_web::body::{Body, ResponseBody};
-    use actix_web::http::{HeaderName, HeaderValue, StatusCode};
-    use actix_web::test::TestRequest;
-    use bytes::{Bytes, BytesMut};
-    use maplit::hashmap;
-    use std::path::Path;
-    use test_log::test;
+/// Narrows `media` down to the byte ranges asked for by the request's `Range`
+/// header, if any. Only static content can be requested in ranges, and not
+/// when it's precompressed or has entity tags (since those are computed from
+/// the whole body). Requests with `If-Range` always get the whole content.
+fn byte_range_media(
+    request: &HttpRequest,
+    registered_content: &RegisteredContent,
+    media: Media<Box<dyn ByteStream>>,
+    response_settings: &mut ResponseSettings,
+) -> Result<Media<Box<dyn ByteStream>>, RenderError> {
+    let static_content = match registered_content {
+        RegisteredContent::StaticContentItem(static_content) => static_content,
+        _ => return Ok(media),
+    };
+    if response_settings.status != http::StatusCode::OK
+        || response_settings.etag.is_some()
+        || response_settings
+            .headers
+            .contains_key(header::CONTENT_ENCODING)
+    {
+        return Ok(media);
+    }
+    response_settings
+        .headers
+        .insert(header::ACCEPT_RANGES, HeaderValue::from_static("bytes"));
 
-    type TestContentEngine<'a> = FilesystemBasedContentEngine<'a, ServerInfo>;
+    let range = match request.headers().get(header::RANGE) {
+        Some(range) if !request.headers().contains_key(header::IF_RANGE) => range,
+        _ => return Ok(media),
+    };
+    let size = static_content
+        .size()
+        .map_err(RenderError::RenderingFailed)?;
+    let ranges = match range
+        .to_str()
+        .ok()
+        .and_then(|range| parse_byte_ranges(range, size))
+    {
+        // Malformed ranges are ignored.
+        None => return Ok(media),
+        Some(ranges) => ranges,
+    };
 
-    fn test_request(
-        content_directory_path: &Path,
-        index_route: Option<&str>,
-        error_handler_route: Option<&str>,
-    ) -> TestRequest {
-        let directory = ContentDirectory::from_root(&content_directory_path).unwrap();
-        let shared_content_engine = FilesystemBasedContentEngine::from_content_directory(
-            directory,
-            ServerInfo {
-                version: ServerVersion(""),
-                operator_path: PathBuf::new(),
-                socket_address: None,
-            },
-        )
-        .expect("Content engine could not be created");
+    if ranges.is_empty() {
+        response_settings.status = http::StatusCode::RANGE_NOT_SATISFIABLE;
+        insert_header(
+            &mut response_settings.headers,
+            header::CONTENT_RANGE,
+            &format!("bytes */{}", size),
+        );
+        return Ok(Media {
+            media_type: media.media_type,
+            content: Box::new(stream::empty()),
+        });
+    }
 
-        TestRequest::default().app_data(AppData {
-            shared_content_engine: shared_content_engine,
-            index_route: index_route.map(route),
-            error_handler_route: error_handler_route.map(route),
-        })
+    response_settings.status = http::StatusCode::PARTIAL_CONTENT;
+    if let [(first, last)] = ranges.as_slice() {
+        insert_header(
+            &mut response_settings.headers,
+            header::CONTENT_RANGE,
+            &format!("bytes {}-{}/{}", first, last, size),
+        );
     }
+    let boundary = {
+        let mut hasher = DefaultHasher::new();
+        SystemTime::now().hash(&mut hasher);
+        request.uri().hash(&mut hasher);
+        format!("{:016x}", hasher.finish())
+    };
+    static_content
+        .render_byte_ranges(&ranges, &boundary)
+        .map_err(RenderError::RenderingFailed)
+}
 
-    async fn collect_response_body(body: ResponseBody<Body>) -> Result<Bytes, actix_web::Error> {
-        body.try_fold(BytesMut::new(), |mut accumulator, bytes| {
-            accumulator.extend_from_slice(&bytes);
-            async { Ok(accumulator) }
-        })
-        .await
-        .map(BytesMut::freeze)
+fn insert_header(headers: &mut HeaderMap, name: header::HeaderName, value: &str) {
+    match HeaderValue::from_str(value) {
+        Ok(value) => {
+            headers.insert(name, value);
+        }
+        Err(error) => log::error!("Invalid {} header `{}`: {}", name, value, error),
     }
+}
 
-    #[test]
-    fn empty_headers_are_handled() {
-        let simplified_headers =
-            simplify_http_headers(&HeaderMap::new()).expect("HTTP headers could not be converted");
-        assert_eq!(simplified_headers, hashmap![]);
+/// Parses a `Range` header value into inclusive byte ranges within content of
+/// the given size. Ranges which can't be satisfied are left out, so an empty
+/// result means that none of them can be. `None` means the header is
+/// malformed (or asks for too many ranges) and should be ignored.
+fn parse_byte_ranges(range: &str, size: u64) -> Option<Vec<(u64, u64)>> {
+    let (unit, range_set) = range.split_once('=')?;
+    if !unit.trim().eq_ignore_ascii_case("bytes") {
+        return None;
     }
 
-    #[test]
+    let mut ranges = Vec::new();
+    for range_spec in range_set.split(',') {
+        let (first, last) = range_spec.trim().split_once('-')?;
+        let parse = |position: &str| position.trim().parse::<u64>().ok();
+        let range = match (first.trim(), last.trim()) {
+            ("", "") => return None,
+            // A suffix, like `-500` for the last 500 bytes.
+            ("", suffix_length) => match parse(suffix_length)? {
+                0 => None,
+                suffix_length => size
+                    .checked_sub(1)
+                    .map(|last| (size.saturating_sub(suffix_length), last)),
+            },
+            (first, "") => {
+                let first = parse(first)?;
+                size.checked_sub(1)
+                    .filter(|last| first <= *last)
+                    .map(|last| (first, last))
+            }
+            (first, last) => {
+                let (first, last) = (parse(first)?, parse(last)?);
+                if last < first {
+                    return None;
+                }
+                size.checked_sub(1)
+                    .filter(|final_position| first <= *final_position)
+                    .map(|final_position| (first, cmp::min(last, final_position)))
+            }
+        };
+        ranges.extend(range);
+    }
+
+    if ranges.len() > MAX_BYTE_RANGES {
+        None
+    } else {
+        Some(ranges)
+    }
+}
+
+/// The coding from `available` which the request's `Accept-Encoding` header
+/// gives the highest quality, if any are acceptable. Ties go to whichever
+/// comes first in `available`.
+fn preferred_content_coding(
+    request: &HttpRequest,
+    available: &[ContentCoding],
+) -> Option<ContentCoding> {
+    let accepted = request
+        .headers()
+        .get_all(header::ACCEPT_ENCODING)
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|item| {
+            let mut parameters = item.split(';');
+            let coding = parameters
+                .next()
+                .unwrap_or_default()
+                .trim()
+                .to_ascii_lowercase();
+            let quality = parameters
+                .find_map(|parameter| parameter.trim().strip_prefix("q="))
+                .and_then(|quality| quality.trim().parse::<f32>().ok())
+                .unwrap_or(1.0);
+            (coding, quality)
+        })
+        .collect::<Vec<_>>();
+    let quality = |coding: &str| {
+        accepted
+            .iter()
+            .find(|(accepted_coding, _)| accepted_coding == coding)
+            .or_else(|| {
+                accepted
+                    .iter()
+                    .find(|(accepted_coding, _)| accepted_coding == "*")
+            })
+            .map(|(_, quality)| *quality)
+    };
+
+    let mut preferred: Option<(ContentCoding, f32)> = None;
+    for content_coding in available {
+        match quality(content_coding.as_str()) {
+            Some(quality)
+                if quality > 0.0
+                    && preferred
+                        .is_none_or(|(_, preferred_quality)| quality > preferred_quality) =>
+            {
+                preferred = Some((*content_coding, quality));
+            }
+            _ => {}
+        }
+    }
+    preferred.map(|(content_coding, _)| content_coding)
+}
+
+/// Starts a response with the given headers.
+fn response_builder(status: http::StatusCode, headers: &HeaderMap) -> HttpResponseBuilder {
+    let mut response_builder = HttpResponse::build(status);
+    for (header_name, header_value) in headers.iter() {
+        response_builder.header(header_name, header_value.clone());
+    }
+    response_builder
+}
+
+/// Responds with a redirect. Locations on this site (which start with `/`)
+/// are beneath `path_prefix`.
+fn redirect_response(redirect: &Redirect, path_prefix: Option<&str>) -> HttpResponse {
+    let status_code = match http::StatusCode::from_u16(redirect.status_code()) {
+        Ok(status_code) => status_code,
+        Err(error) => {
+            log::error!(
+                bug_message!("Redirect status code {} was invalid: {}"),
+                redirect.status_code(),
+                error,
+            );
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+    let location = match path_prefix {
+        Some(path_prefix) if redirect.location().starts_with('/') => {
+            format!("{}{}", path_prefix, redirect.location())
+        }
+        _ => String::from(redirect.location()),
+    };
+    log::info!(
+        "Responding with {}, redirecting to {}",
+        status_code,
+        location,
+    );
+    match HeaderValue::from_str(&location) {
+        Ok(location) => HttpResponse::build(status_code)
+            .header(header::LOCATION, location)
+            .finish(),
+        Err(error) => {
+            log::error!(
+                "Redirect location `{}` is not a valid header: {}",
+                location,
+                error
+            );
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// Responds to requests for the search index or search results, if `route`
+/// is one of the routes configured for search.
+fn search_response<Engine>(
+    content_engine: &Engine,
+    search_config: &SearchConfig,
+    route: &Route,
+    query_parameters: &QueryParameters,
+) -> Option<HttpResponse>
+where
+    Engine: ContentEngine<ServerInfo>,
+{
+    let search_index = content_engine.search_index()?;
+    let body = if search_config.index_route.as_ref() == Some(route) {
+        serde_json::to_string(search_index)
+    } else if search_config.query_route.as_ref() == Some(route) {
+        let query = query_parameters
+            .last_values()
+            .remove(SEARCH_QUERY_PARAMETER)
+            .unwrap_or_default();
+        let results = search_index.search(&query, search_config.max_results);
+        serde_json::to_string(&serde_json::json!({
+            "query": query,
+            "results": results,
+        }))
+    } else {
+        return None;
+    };
+
+    Some(match body {
+        Ok(body) => HttpResponse::Ok()
+            .content_type(mime::APPLICATION_JSON.to_string())
+            .body(body),
+        Err(error) => {
+            log::error!("Search response could not be serialized: {}", error);
+            HttpResponse::InternalServerError().finish()
+        }
+    })
+}
+
+/// Responds with a report on the loaded content, if `route` is the status
+/// route.
+fn status_response<Engine>(
+    content_engine: &Engine,
+    status_config: &StatusConfig,
+    route: &Route,
+) -> Option<HttpResponse>
+where
+    Engine: ContentEngine<ServerInfo>,
+{
+    if status_config.route.as_ref() != Some(route) {
+        return None;
+    }
+    let stats = content_engine.stats()?;
+    #[allow(unused_mut)]
+    let mut status = serde_json::json!({
+        "content": stats,
+        "cancelled-renders": Cancellation::cancelled_render_count(),
+    });
+    #[cfg(feature = "executables")]
+    {
+        status["executable-output"] =
+            serde_json::json!(crate::content::OutputBufferMetrics::current());
+    }
+    Some(match serde_json::to_string(&status) {
+        Ok(body) => HttpResponse::Ok()
+            .content_type(mime::APPLICATION_JSON.to_string())
+            .header(header::CACHE_CONTROL, "no-store")
+            .body(body),
+        Err(error) => {
+            log::error!("Status response could not be serialized: {}", error);
+            HttpResponse::InternalServerError().finish()
+        }
+    })
+}
+
+async fn options(request: HttpRequest) -> HttpResponse {
+    log_request(&request, RequestLogger::default());
+
+    log::info!("Responding with {}", http::StatusCode::NO_CONTENT);
+
+    let mut response_builder = HttpResponse::build(http::StatusCode::NO_CONTENT);
+    response_builder.header(
+        http::header::ALLOW,
+        HeaderValue::from_static(ALLOWED_REQUEST_METHODS),
+    );
+    response_builder.finish()
+}
+
+/// Whether `request` is for the reload route (the method is not checked).
+fn is_reload_request<Engine>(request: &HttpRequest) -> bool
+where
+    Engine: 'static + ContentEngine<ServerInfo> + Send + Sync,
+{
+    let app_data = match request.app_data::<AppData<Engine>>() {
+        Some(app_data) => app_data,
+        None => return false,
+    };
+    let reload_route = match (&app_data.reloader, app_data.config.reload.enabled_route()) {
+        (Some(_), Some(reload_route)) => reload_route,
+        _ => return false,
+    };
+    let path = strip_path_prefix(
+        request.uri().path(),
+        app_data.path_prefix(request).as_deref(),
+    );
+    path.parse::<Route>()
+        .is_ok_and(|route| &route == reload_route)
+}
+
+/// Reloads content if the request has the reload secret. Changed content
+/// files can be listed in `changed` query parameters to only load them again
+/// (e.g. `?changed=about.html.hbs&changed=style.css`).
+async fn reload<Engine>(request: HttpRequest) -> HttpResponse
+where
+    Engine: 'static + ContentEngine<ServerInfo> + Send + Sync,
+{
+    let app_data = match request.app_data::<AppData<Engine>>() {
+        Some(app_data) => app_data,
+        None => return missing_app_data_response(),
+    };
+    log_request(&request, app_data.request_logger(&request));
+
+    let (reloader, secret) = match (&app_data.reloader, &app_data.config.reload.secret) {
+        (Some(reloader), Some(secret)) => (reloader.clone(), secret),
+        _ => return missing_app_data_response(),
+    };
+    let is_authorized = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|authorization| authorization.to_str().ok())
+        .and_then(|authorization| authorization.strip_prefix("Bearer "))
+        .is_some_and(|token| constant_time_eq(token.trim().as_bytes(), secret.as_bytes()));
+    if !is_authorized {
+        log::warn!("Rejecting reload request without the correct secret");
+        return HttpResponse::Unauthorized()
+            .header(header::WWW_AUTHENTICATE, "Bearer")
+            .content_type(mime::TEXT_PLAIN.to_string())
+            .body("Reloading requires the reload secret.");
+    }
+
+    let changed_paths = web::Query::<Vec<(String, String)>>::from_query(request.query_string())
+        .map(|query_parameters| {
+            query_parameters
+                .into_inner()
+                .into_iter()
+                .filter(|(key, _)| key == "changed")
+                .map(|(_, value)| value)
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+
+    log::info!("Reloading content");
+    match web::block(move || reloader(&changed_paths)).await {
+        Ok(()) => {
+            log::info!("Reloaded content");
+            HttpResponse::NoContent().finish()
+        }
+        Err(error) => {
+            let message = match error {
+                BlockingError::Error(error) => format!("{:#}", anyhow::Error::from(error)),
+                BlockingError::Canceled => String::from("Reloading was canceled."),
+            };
+            log::error!("Reloading content failed: {}", message);
+            HttpResponse::InternalServerError()
+                .content_type(mime::TEXT_PLAIN.to_string())
+                .body(message)
+        }
+    }
+}
+
+/// Reloads content each time the process receives `SIGHUP`.
+async fn reload_on_hangup(reloader: Reloader) {
+    let mut hangups = match signal::unix::signal(signal::unix::SignalKind::hangup()) {
+        Ok(hangups) => hangups,
+        Err(error) => {
+            log::error!("Unable to listen for SIGHUP: {}", error);
+            return;
+        }
+    };
+    while hangups.recv().await.is_some() {
+        log::info!("Reloading content after SIGHUP");
+        let reloader = reloader.clone();
+        match web::block(move || reloader(&[])).await {
+            Ok(()) => log::info!("Reloaded content"),
+            Err(BlockingError::Error(error)) => log::error!(
+                "Reloading content failed, so the previous content is still being served: {:#}",
+                anyhow::Error::from(error)
+            ),
+            Err(BlockingError::Canceled) => log::error!("Reloading content was canceled"),
+        }
+    }
+}
+
+/// Compares secrets without revealing how much of them matched via timing.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len()
+        && a.iter()
+            .zip(b)
+            .fold(0, |difference, (a, b)| difference | (a ^ b))
+            == 0
+}
+
+async fn unsupported_request_method<Engine>(request: HttpRequest) -> HttpResponse
+where
+    Engine: 'static + ContentEngine<ServerInfo> + Send + Sync,
+{
+    let app_data = match request.app_data::<AppData<Engine>>() {
+        Some(app_data) => app_data,
+        None => return missing_app_data_response(),
+    };
+
+    log_request(&request, app_data.request_logger(&request));
+
+    let http_request_data = app_data.http_request_data(&request);
+
+    let content_engine = match app_data.read_content_engine(&request) {
+        Ok(content_engine) => content_engine,
+        Err(content_engine) => {
+            return poisoned_content_engine_response(&*content_engine, app_data, http_request_data)
+        }
+    };
+
+    let mut response_headers = HeaderMap::with_capacity(1);
+    response_headers.insert(
+        http::header::ALLOW,
+        HeaderValue::from_static(ALLOWED_REQUEST_METHODS),
+    );
+
+    error_response(
+        http::StatusCode::METHOD_NOT_ALLOWED,
+        format!("The {} request method is not supported", request.method()),
+        &*content_engine,
+        None,
+        QueryParameters::default(),
+        HashMap::new(),
+        http_request_data.clone(),
+        &app_data.error_handler_route,
+        vec![&mime::TEXT_PLAIN],
+        response_headers,
+    )
+}
+
+fn log_request(request: &HttpRequest, request_logger: RequestLogger) {
+    request_logger.log(
+        log::Level::Info,
+        // e.g. "Handling request GET /styles.css HTTP/1.1 with Accept: text/css,*/*;q=0.1"
+        format_args!(
+            "Handling request {} {} {}{}",
+            request.method(),
+            request.uri(),
+            match request.version() {
+                http::Version::HTTP_09 => "HTTP/0.9",
+                http::Version::HTTP_10 => "HTTP/1.0",
+                http::Version::HTTP_11 => "HTTP/1.1",
+                http::Version::HTTP_2 => "HTTP/2.0",
+                http::Version::HTTP_3 => "HTTP/3.0",
+                _ => "HTTP",
+            },
+            request
+                .headers()
+                .get(header::ACCEPT)
+                .and_then(|value| value.to_str().ok())
+                .map(|value| format!(" with Accept: {}", value))
+                .unwrap_or_default()
+        ),
+    );
+}
+
+/// Logs messages about a request according to its route's `log-level`
+/// setting, falling back to normal logging for routes without one.
+#[derive(Clone, Copy)]
+struct RequestLogger {
+    level: Option<log::LevelFilter>,
+
+    /// Whether this response was sampled for body logging (see
+    /// `body-trace-interval`).
+    is_body_sampled: bool,
+}
+
+impl Default for RequestLogger {
+    fn default() -> Self {
+        RequestLogger {
+            level: None,
+            is_body_sampled: true,
+        }
+    }
+}
+
+impl RequestLogger {
+    fn new(route_config: &RouteConfig) -> Self {
+        let is_body_sampled = match route_config.body_trace_interval {
+            None => true,
+            Some(interval) => {
+                let count = BODY_TRACE_SAMPLING_COUNTER.fetch_add(1, atomic::Ordering::Relaxed);
+                count.checked_rem(interval) == Some(0)
+            }
+        };
+        RequestLogger {
+            level: route_config.log_level,
+            is_body_sampled,
+        }
+    }
+
+    fn enabled(&self, level: log::Level) -> bool {
+        match self.level {
+            None => log::log_enabled!(level),
+            Some(route_level) => level <= route_level && level <= log::max_level(),
+        }
+    }
+
+    /// Whether chunks of the response body should be logged.
+    fn traces_bodies(&self) -> bool {
+        self.is_body_sampled && self.enabled(log::Level::Trace)
+    }
+
+    fn log(&self, level: log::Level, message: fmt::Arguments) {
+        match self.level {
+            None => log::log!(level, "{}", message),
+            Some(route_level) if level <= route_level => {
+                log::log!(target: logging::ROUTE_LOG_TARGET, level, "{}", message)
+            }
+            Some(_) => {}
+        }
+    }
+}
+
+/// Responds to requests which are missing the server's app data. The content
+/// engine is unavailable, so the error handler can't be used.
+fn missing_app_data_response() -> HttpResponse {
+    log::error!(bug_message!("App data was not of the expected type."));
+    HttpResponse::InternalServerError()
+        .content_type(mime::TEXT_PLAIN.to_string())
+        .body(
+            http::StatusCode::INTERNAL_SERVER_ERROR
+                .canonical_reason()
+                .unwrap_or("Something Went Wrong"),
+        )
+}
+
+/// Responds to requests which can't be handled because the content engine's
+/// lock was poisoned. `content_engine` is still used for the error handler.
+fn poisoned_content_engine_response<Engine>(
+    content_engine: &Engine,
+    app_data: &AppData<Engine>,
+    http_request_data: HttpRequestData,
+) -> HttpResponse
+where
+    Engine: 'static + ContentEngine<ServerInfo> + Send + Sync,
+{
+    log::error!("{}", POISONED_CONTENT_ENGINE);
+    error_response(
+        http::StatusCode::INTERNAL_SERVER_ERROR,
+        POISONED_CONTENT_ENGINE,
+        content_engine,
+        None,
+        QueryParameters::default(),
+        HashMap::new(),
+        http_request_data,
+        &app_data.error_handler_route,
+        vec![&mime::TEXT_PLAIN],
+        HeaderMap::new(),
+    )
+}
+
+#[allow(clippy::too_many_arguments)]
+fn error_response<Details, Engine>(
+    status_code: http::StatusCode,
+    details: Details,
+    content_engine: &Engine,
+    request_route: Option<Route>,
+    query_parameters: QueryParameters,
+    request_headers: HashMap<String, String>,
+    http_request_data: HttpRequestData,
+    error_handler_route: &Option<Route>,
+    acceptable_media_ranges: Vec<&MediaRange>,
+    response_headers: HeaderMap,
+) -> HttpResponse
+where
+    Details: AsRef<str>,
+    Engine: 'static + ContentEngine<ServerInfo> + Send + Sync,
+{
+    let error_code = if !status_code.is_client_error() && !status_code.is_server_error() {
+        log::error!(
+            bug_message!(
+                "This should never happen: The HTTP status code given to the error handler ({}) does not indicate an error.",
+            ),
+            status_code,
+        );
+        http::StatusCode::INTERNAL_SERVER_ERROR
+    } else {
+        status_code
+    };
+
+    let mut response_builder = response_builder(error_code, &response_headers);
+    // Without an error handler, clients which prefer HTML get a minimal page.
+    let default_is_html = error_handler_route.is_none() && prefers_html(&acceptable_media_ranges);
+
+    error_handler_route
+        .as_ref()
+        .and_then(|route| {
+            content_engine.get(route).and_then(|content| {
+                let error_context = content_engine
+                    .render_context(request_route.clone(), query_parameters, request_headers)
+                    .with_http_request_data(http_request_data)
+                    .into_error_context(status_code.as_u16());
+                match content.render(error_context, acceptable_media_ranges) {
+                    Ok(rendered_content) => Some((route, rendered_content)),
+                    Err(rendering_error) => {
+                        log::error!(
+                            "Error occurred while rendering error handler: {}",
+                            rendering_error
+                        );
+                        None
+                    }
+                }
+            })
+        })
+        .map(
+            |(
+                error_handler_route,
+                Media {
+                    media_type,
+                    content,
+                },
+            )| {
+                match &request_route {
+                    Some(request_route) => log::warn!(
+                        "Responding with {} for {}, body from {} as {}: {}",
+                        status_code,
+                        request_route,
+                        error_handler_route,
+                        media_type,
+                        details.as_ref()
+                    ),
+                    None => log::warn!(
+                        "Responding with {}, body from {} as {}: {}",
+                        status_code,
+                        error_handler_route,
+                        media_type,
+                        details.as_ref()
+                    ),
+                };
+                response_builder
+                    .content_type(media_type.to_string())
+                    .streaming(content.map_err(|error| {
+                        log::error!(
+                            "An error occurred while streaming a response body: {}",
+                            error,
+                        );
+                    }))
+            },
+        )
+        .unwrap_or_else(|| {
+            // Send a default error response if the error handler failed or was
+            // not specified.
+            let media_type = if default_is_html {
+                "text/html"
+            } else {
+                "text/plain"
+            };
+            match request_route {
+                Some(request_route) => log::warn!(
+                    "Responding with {} for {}, body as {}: {}",
+                    status_code,
+                    request_route,
+                    media_type,
+                    details.as_ref()
+                ),
+                None => log::warn!(
+                    "Responding with {}, body as {}: {}",
+                    status_code,
+                    media_type,
+                    details.as_ref()
+                ),
+            };
+            let reason = error_code
+                .canonical_reason()
+                .unwrap_or("Something Went Wrong");
+            if default_is_html {
+                response_builder
+                    .content_type(media_type)
+                    .body(default_error_page(error_code.as_u16(), reason))
+            } else {
+                response_builder.content_type(media_type).body(reason)
+            }
+        })
+}
+
+/// Whether the first of `acceptable_media_ranges` that allows HTML or plain
+/// text asks for HTML specifically. Wildcards count as plain text.
+fn prefers_html(acceptable_media_ranges: &[&MediaRange]) -> bool {
+    acceptable_media_ranges
+        .iter()
+        .find_map(|media_range| {
+            if media_range.essence_str() == mime::TEXT_HTML.essence_str() {
+                Some(true)
+            } else if media_range.type_() == mime::STAR
+                || (media_range.type_() == mime::TEXT
+                    && (media_range.subtype() == mime::STAR
+                        || media_range.subtype() == mime::PLAIN))
+            {
+                Some(false)
+            } else {
+                None
+            }
+        })
+        .unwrap_or(false)
+}
+
+/// The body of error responses for clients which prefer HTML, when there is
+/// no error handler route.
+fn default_error_page(status_code: u16, reason: &str) -> String {
+    format!(
+        "<!DOCTYPE html>\n\
+        <html lang=\"en\">\n\
+        <head>\n\
+        <meta charset=\"utf-8\">\n\
+        <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n\
+        <title>{status_code} {reason}</title>\n\
+        <style>body {{ font-family: sans-serif; margin: 4em auto; max-width: 40em; \
+        padding: 0 1em; color: #333; }}</style>\n\
+        </head>\n\
+        <body>\n\
+        <h1>{reason}</h1>\n\
+        <p>The server responded with status {status_code}.</p>\n\
+        </body>\n\
+        </html>\n",
+        status_code = status_code,
+        reason = reason,
+    )
+}
+
+enum BufferedResponseBody {
+    /// The entire body fit within the buffer.
+    Complete(Bytes),
+
+    /// The body was larger than the buffer (or buffering was disabled). Any
+    /// bytes that were buffered are at the front of this stream.
+    Incomplete(Box<dyn ByteStream>),
+}
+
+/// Reads from `content` until it ends or more than `threshold` bytes have
+/// been collected. This allows errors which occur early in rendering to be
+/// reported with an appropriate HTTP status code.
+async fn buffer_response_body(
+    mut content: Box<dyn ByteStream>,
+    threshold: usize,
+) -> Result<BufferedResponseBody, StreamError> {
+    if threshold == 0 {
+        return Ok(BufferedResponseBody::Incomplete(content));
+    }
+
+    let mut buffer = BytesMut::new();
+    while buffer.len() <= threshold {
+        match content.next().await {
+            None => return Ok(BufferedResponseBody::Complete(buffer.freeze())),
+            Some(Err(error)) => return Err(error),
+            Some(Ok(bytes)) => buffer.extend_from_slice(&bytes),
+        }
+    }
+
+    let buffered_bytes = buffer.freeze();
+    Ok(BufferedResponseBody::Incomplete(Box::new(
+        stream::once(future::ready(Ok(buffered_bytes))).chain(content),
+    )))
+}
+
+/// Adapts rendered content into an HTTP response body, applying the
+/// configured policy for errors that occur mid-stream. Streams which are
+/// still going at the `deadline` fail as if the content had errored. If the
+/// body is dropped before it ends, `render_guard` cancels the render.
+fn streaming_response_body(
+    content: Box<dyn ByteStream>,
+    route: Route,
+    media_type: MediaType,
+    streaming_config: &StreamingConfig,
+    deadline: Option<time::Instant>,
+    render_guard: CancelOnDrop,
+    request_logger: RequestLogger,
+) -> LocalBoxStream<'static, Result<Bytes, ()>> {
+    let stream_error_log_level = streaming_config.stream_error_log_level;
+    let loggable_route = route.clone();
+    let deadline_route = route.clone();
+    let content = content
+        .map_err(move |error| {
+            log::log!(
+                stream_error_log_level,
+                "An error occurred while streaming a response body for {}: {}",
+                loggable_route,
+                error,
+            );
+        })
+        .inspect_ok(move |bytes| {
+            if !request_logger.traces_bodies() {
+                return;
+            }
+            let max_length = 64;
+            if bytes.len() > max_length {
+                request_logger.log(
+                    log::Level::Trace,
+                    format_args!(
+                        "Streaming data for {} as {}: {:?} ...and {} more bytes",
+                        route,
+                        media_type,
+                        bytes.slice(0..max_length),
+                        bytes.len() - max_length
+                    ),
+                );
+            } else {
+                request_logger.log(
+                    log::Level::Trace,
+                    format_args!(
+                        "Streaming data for {} as {}: {:?}",
+                        route, media_type, bytes
+                    ),
+                );
+            }
+        });
+
+    let content = match deadline {
+        None => content.boxed_local(),
+        Some(deadline) => content
+            .take_until(time::delay_until(deadline))
+            .chain(
+                stream::once(future::lazy(move |_| time::Instant::now() >= deadline)).filter_map(
+                    move |is_past_deadline| {
+                        if is_past_deadline {
+                            log::error!(
+                                "The response body for {} was cut off after exceeding the \
+                                request timeout",
+                                deadline_route,
+                            );
+                        }
+                        future::ready(is_past_deadline.then_some(Err(())))
+                    },
+                ),
+            )
+            .boxed_local(),
+    };
+
+    content
+        .chain(
+            stream::once(future::lazy(move |_| render_guard.disarm()))
+                .filter_map(|()| future::ready(None)),
+        )
+        .boxed_local()
+}
+
+/// The request's `Accept` header values joined into one (as if they had been
+/// sent as a single header), or `None` if any of them is not visible ASCII.
+fn accept_header_value(request: &HttpRequest) -> Option<String> {
+    request
+        .headers()
+        .get_all(header::ACCEPT)
+        .map(|value| value.to_str().ok())
+        .collect::<Option<Vec<_>>>()
+        .map(|values| values.join(","))
+}
+
+fn simplify_http_headers(
+    header_map: &HeaderMap,
+) -> Result<HashMap<String, String>, header::ToStrError> {
+    let mut simplified_headers = HashMap::new();
+    for key in header_map.keys() {
+        let mut combined_value = None;
+        for value in header_map.get_all(key) {
+            // This doesn't quite follow the HTTP spec (see the last paragraph
+            // of <https://datatracker.ietf.org/doc/html/rfc7230#section-3.2.4>
+            // for example).
+            let utf8_value = value.to_str()?;
+            combined_value = match combined_value {
+                None => Some(utf8_value.to_string()),
+                Some(previous_value) => Some(format!("{},{}", previous_value, utf8_value)),
+            }
+        }
+        if let Some(value) = combined_value {
+            simplified_headers.insert(key.to_string(), value);
+        }
+    }
+    Ok(simplified_headers)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_lib::*;
+    use actix_web::body::{Body, ResponseBody};
+    use actix_web::http::{HeaderName, HeaderValue, StatusCode};
+    use actix_web::test::TestRequest;
+    use bytes::{Bytes, BytesMut};
+    use maplit::hashmap;
+    use std::path::Path;
+    use std::time::Duration;
+    use test_log::test;
+
+    type TestContentEngine<'a> = FilesystemBasedContentEngine<'a, ServerInfo>;
+
+    fn test_request(
+        content_directory_path: &Path,
+        index_route: Option<&str>,
+        error_handler_route: Option<&str>,
+    ) -> TestRequest {
+        test_request_with_config(
+            content_directory_path,
+            index_route,
+            error_handler_route,
+            Config::default(),
+        )
+    }
+
+    fn test_request_with_config(
+        content_directory_path: &Path,
+        index_route: Option<&str>,
+        error_handler_route: Option<&str>,
+        config: Config,
+    ) -> TestRequest {
+        TestRequest::default().app_data(AppData {
+            shared_content_engine: test_content_engine(content_directory_path, config.clone()),
+            virtual_hosts: HashMap::new(),
+            index_route: index_route.map(route),
+            error_handler_route: error_handler_route.map(route),
+            config,
+            reloader: None,
+            bulkheads: Bulkheads::default(),
+        })
+    }
+
+    fn test_content_engine(
+        content_directory_path: &Path,
+        config: Config,
+    ) -> Arc<RwLock<TestContentEngine<'static>>> {
+        let directory = ContentDirectory::from_root(&content_directory_path).unwrap();
+        FilesystemBasedContentEngine::from_content_directory_with_config(
+            directory,
+            ServerInfo {
+                version: ServerVersion(""),
+                operator_path: PathBuf::new(),
+                socket_address: None,
+            },
+            config,
+        )
+        .expect("Content engine could not be created")
+    }
+
+    async fn collect_response_body(body: ResponseBody<Body>) -> Result<Bytes, actix_web::Error> {
+        body.try_fold(BytesMut::new(), |mut accumulator, bytes| {
+            accumulator.extend_from_slice(&bytes);
+            async { Ok(accumulator) }
+        })
+        .await
+        .map(BytesMut::freeze)
+    }
+
+    #[test]
+    fn empty_headers_are_handled() {
+        let simplified_headers =
+            simplify_http_headers(&HeaderMap::new()).expect("HTTP headers could not be converted");
+        assert_eq!(simplified_headers, hashmap![]);
+    }
+
+    #[test]
     fn typical_headers_are_handled() {
         let mut headers = HeaderMap::new();
 
-        headers.append(
-            HeaderName::from_static("user-agent"),
-            HeaderValue::from_static("Operator tests"),
+        headers.append(
+            HeaderName::from_static("user-agent"),
+            HeaderValue::from_static("Operator tests"),
+        );
+        headers.append(
+            HeaderName::from_static("accept"),
+            HeaderValue::from_static("text/html,*/*;q=0.8"),
+        );
+        headers.append(
+            HeaderName::from_static("accept-language"),
+            HeaderValue::from_static("en-US,en;q=0.5"),
+        );
+        headers.append(
+            HeaderName::from_static("accept-encoding"),
+            HeaderValue::from_static("gzip, deflate, br"),
+        );
+        headers.append(
+            HeaderName::from_static("upgrade-insecure-requests"),
+            HeaderValue::from_static("1"),
+        );
+        headers.append(
+            HeaderName::from_static("connection"),
+            HeaderValue::from_static("keep-alive"),
+        );
+        headers.append(
+            HeaderName::from_static("cookie"),
+            HeaderValue::from_static("foo=bar; blah=stuff"),
+        );
+
+        let simplified_headers =
+            simplify_http_headers(&headers).expect("HTTP headers could not be converted");
+        assert_eq!(
+            simplified_headers,
+            hashmap![
+                String::from("user-agent") => String::from("Operator tests"),
+                String::from("accept") => String::from("text/html,*/*;q=0.8"),
+                String::from("accept-language") => String::from("en-US,en;q=0.5"),
+                String::from("accept-encoding") => String::from("gzip, deflate, br"),
+                String::from("upgrade-insecure-requests") => String::from("1"),
+                String::from("connection") => String::from("keep-alive"),
+                String::from("cookie") => String::from("foo=bar; blah=stuff"),
+            ]
+        );
+    }
+
+    // TODO: Enable this test after upgrading to actix-web v4. See
+    // <https://github.com/actix/actix-web/issues/2466>.
+    #[ignore]
+    #[test]
+    fn duplicate_headers_are_handled() {
+        let mut headers = HeaderMap::new();
+
+        headers.append(
+            HeaderName::from_static("user-agent"),
+            HeaderValue::from_static("Operator tests"),
+        );
+        headers.append(
+            HeaderName::from_static("accept"),
+            HeaderValue::from_static("text/html,image/*"),
+        );
+        headers.append(
+            HeaderName::from_static("accept-language"),
+            HeaderValue::from_static("en-US,en;q=0.5"),
+        );
+        headers.append(
+            HeaderName::from_static("accept"),
+            HeaderValue::from_static("*/*;q=0.8"),
+        );
+        headers.append(
+            HeaderName::from_static("accept-language"),
+            HeaderValue::from_static("de"),
+        );
+        headers.append(
+            HeaderName::from_static("accept-language"),
+            HeaderValue::from_static("de-CH"),
+        );
+        headers.append(
+            HeaderName::from_static("x-arbitrary-header-1"),
+            HeaderValue::from_static("a,b,c"),
+        );
+        headers.append(
+            HeaderName::from_static("x-arbitrary-header-1"),
+            HeaderValue::from_static("d,e,f"),
+        );
+        headers.append(
+            HeaderName::from_static("x-arbitrary-header-1"),
+            HeaderValue::from_static("g,h,i"),
+        );
+
+        let simplified_headers =
+            simplify_http_headers(&headers).expect("HTTP headers could not be converted");
+        assert_eq!(
+            simplified_headers,
+            hashmap![
+                String::from("user-agent") => String::from("Operator tests"),
+                String::from("accept-language") => String::from("en-US,en;q=0.5,de,de-CH"),
+                String::from("x-arbitrary-header-1") => String::from("a,b,c,d,e,f,g,h,i"),
+                String::from("accept") => String::from("text/html,image/*,*/*;q=0.8"),
+            ]
+        );
+    }
+
+    #[actix_rt::test]
+    async fn content_may_be_not_found() {
+        let request = test_request(&sample_path("empty"), None, None)
+            .uri("/nothing/exists/at/this/path")
+            .to_http_request();
+        let response = get::<TestContentEngine>(request).await;
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[actix_rt::test]
+    #[cfg_attr(not(feature = "templating"), ignore = "uses templates")]
+    async fn percent_encoded_paths_are_decoded() {
+        for (path, expected_body) in [
+            (
+                "/hello%20world",
+                "hello from a file with a space in its name\n",
+            ),
+            ("/%E6%97%A5%E6%9C%AC%E8%AA%9E", "こんにちは\n"),
+        ] {
+            let request = test_request(&sample_path("unicode-names"), None, None)
+                .uri(path)
+                .to_http_request();
+            let mut response = get::<TestContentEngine>(request).await;
+            let response_body = collect_response_body(response.take_body())
+                .await
+                .expect("There was an error in the content stream");
+
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(response_body, expected_body);
+        }
+    }
+
+    #[actix_rt::test]
+    async fn requests_without_app_data_get_an_internal_server_error() {
+        let request = TestRequest::default().uri("/hello").to_http_request();
+        let response = get::<TestContentEngine>(request).await;
+
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    #[actix_rt::test]
+    async fn poisoned_content_engines_get_an_internal_server_error() {
+        let config = Config::default();
+        let content_engine = test_content_engine(&sample_path("hello-world"), config.clone());
+        let poisoner = Arc::clone(&content_engine);
+        let _ = std::thread::spawn(move || {
+            let _guard = poisoner.write();
+            panic!("Poisoning the content engine lock on purpose");
+        })
+        .join();
+        assert!(content_engine.is_poisoned());
+
+        let request = TestRequest::default()
+            .app_data(AppData {
+                shared_content_engine: content_engine,
+                virtual_hosts: HashMap::new(),
+                index_route: None,
+                error_handler_route: None,
+                config,
+                reloader: None,
+                bulkheads: Bulkheads::default(),
+            })
+            .uri("/hello")
+            .to_http_request();
+        let response = get::<TestContentEngine>(request).await;
+
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    #[actix_rt::test]
+    async fn content_can_be_retrieved_with_exact_media_type() {
+        let request = test_request(&sample_path("hello-world"), None, None)
+            .uri("/hello")
+            .header(header::ACCEPT, "text/plain")
+            .to_http_request();
+
+        let mut response = get::<TestContentEngine>(request).await;
+        let response_body = collect_response_body(response.take_body())
+            .await
+            .expect("There was an error in the content stream");
+        let response_content_type = response
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .expect("Response was missing Content-Type header");
+
+        assert_eq!(
+            response.status(),
+            StatusCode::OK,
+            "Response status was not 200"
+        );
+        assert_eq!(
+            response_content_type, "text/plain",
+            "Response Content-Type was not text/plain",
+        );
+        assert_eq!(response_body, "hello world", "Response body was incorrect");
+    }
+
+    #[actix_rt::test]
+    async fn content_can_be_retrieved_with_media_range() {
+        let request = test_request(&sample_path("hello-world"), None, None)
+            .uri("/hello")
+            .header(header::ACCEPT, "text/*")
+            .to_http_request();
+
+        let mut response = get::<TestContentEngine>(request).await;
+        let response_body = collect_response_body(response.take_body())
+            .await
+            .expect("There was an error in the content stream");
+        let response_content_type = response
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .expect("Response was missing Content-Type header");
+
+        assert_eq!(
+            response.status(),
+            StatusCode::OK,
+            "Response status was not 200"
+        );
+        assert_eq!(
+            response_content_type, "text/plain",
+            "Response Content-Type was not text/plain",
+        );
+        assert_eq!(response_body, "hello world", "Response body was incorrect");
+    }
+
+    #[actix_rt::test]
+    async fn content_can_be_retrieved_with_star_star_media_range() {
+        let request = test_request(&sample_path("hello-world"), None, None)
+            .uri("/hello")
+            .header(header::ACCEPT, "*/*")
+            .to_http_request();
+
+        let mut response = get::<TestContentEngine>(request).await;
+        let response_body = collect_response_body(response.take_body())
+            .await
+            .expect("There was an error in the content stream");
+        let response_content_type = response
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .expect("Response was missing Content-Type header");
+
+        assert_eq!(
+            response.status(),
+            StatusCode::OK,
+            "Response status was not 200"
+        );
+        assert_eq!(
+            response_content_type, "text/plain",
+            "Response Content-Type was not text/plain",
+        );
+        assert_eq!(response_body, "hello world", "Response body was incorrect");
+    }
+
+    #[actix_rt::test]
+    async fn content_can_be_retrieved_with_elaborate_accept_header() {
+        let request = test_request(&sample_path("hello-world"), None, None)
+            .uri("/hello")
+            .header(header::ACCEPT, "audio/aac, text/*;q=0.9, image/gif;q=0.1")
+            .to_http_request();
+
+        let mut response = get::<TestContentEngine>(request).await;
+        let response_body = collect_response_body(response.take_body())
+            .await
+            .expect("There was an error in the content stream");
+        let response_content_type = response
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .expect("Response was missing Content-Type header");
+
+        assert_eq!(
+            response.status(),
+            StatusCode::OK,
+            "Response status was not 200"
+        );
+        assert_eq!(
+            response_content_type, "text/plain",
+            "Response Content-Type was not text/plain",
+        );
+        assert_eq!(response_body, "hello world", "Response body was incorrect");
+    }
+
+    #[actix_rt::test]
+    async fn media_ranges_with_zero_quality_are_not_acceptable() {
+        for (accept, expected_status) in [
+            ("text/plain;q=0", StatusCode::NOT_ACCEPTABLE),
+            ("text/plain;q=0, */*;q=0.1", StatusCode::OK),
+            // Malformed items are ignored.
+            ("text/plain;q=oops, nonsense, */*", StatusCode::OK),
+            ("nonsense", StatusCode::BAD_REQUEST),
+        ] {
+            let request = test_request(&sample_path("hello-world"), None, None)
+                .uri("/hello")
+                .header(header::ACCEPT, accept)
+                .to_http_request();
+
+            let response = get::<TestContentEngine>(request).await;
+            assert_eq!(
+                response.status(),
+                expected_status,
+                "Wrong status for `Accept: {}`",
+                accept,
+            );
+        }
+    }
+
+    #[actix_rt::test]
+    async fn content_can_be_retrieved_with_missing_accept_header() {
+        let request = test_request(&sample_path("hello-world"), None, None)
+            .uri("/hello")
+            .to_http_request();
+
+        let mut response = get::<TestContentEngine>(request).await;
+        let response_body = collect_response_body(response.take_body())
+            .await
+            .expect("There was an error in the content stream");
+        let response_content_type = response
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .expect("Response was missing Content-Type header");
+
+        assert_eq!(
+            response.status(),
+            StatusCode::OK,
+            "Response status was not 200"
+        );
+        assert_eq!(
+            response_content_type, "text/plain",
+            "Response Content-Type was not text/plain",
+        );
+        assert_eq!(response_body, "hello world", "Response body was incorrect");
+    }
+
+    #[actix_rt::test]
+    async fn multimedia_content_can_be_retrieved() {
+        let request = test_request(&sample_path("multimedia"), None, None)
+            .uri("/dramatic-prairie-dog")
+            .header(header::ACCEPT, "video/*")
+            .to_http_request();
+
+        let mut response = get::<TestContentEngine>(request).await;
+        let response_body = collect_response_body(response.take_body())
+            .await
+            .expect("There was an error in the content stream");
+        let response_content_type = response
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .expect("Response was missing Content-Type header");
+
+        assert_eq!(
+            response.status(),
+            StatusCode::OK,
+            "Response status was not 200"
+        );
+        assert_eq!(
+            response_content_type, "video/mp4",
+            "Response Content-Type was not video/mp4",
+        );
+
+        assert_eq!(
+            response_body.len(),
+            198946,
+            "Response body did not have the expected size",
+        );
+    }
+
+    #[actix_rt::test]
+    async fn content_cannot_be_retrieved_if_no_acceptable_media_type() {
+        let request = test_request(&sample_path("hello-world"), None, None)
+            .uri("/hello")
+            .header(
+                header::ACCEPT,
+                "application/msword, font/otf, audio/3gpp2;q=0.1",
+            )
+            .to_http_request();
+
+        let response = get::<TestContentEngine>(request).await;
+
+        assert_eq!(
+            response.status(),
+            StatusCode::NOT_ACCEPTABLE,
+            "Response status was not 406"
+        );
+    }
+
+    #[actix_rt::test]
+    async fn extension_on_url_takes_precedence_over_accept_header() {
+        // Note .txt extension on URL path, but no text/plain (nor any other
+        // workable media range) in the accept header.
+        let request = test_request(&sample_path("hello-world"), None, None)
+            .uri("/hello.txt")
+            .header(
+                header::ACCEPT,
+                "application/msword, font/otf, audio/3gpp2;q=0.1",
+            )
+            .to_http_request();
+
+        let response = get::<TestContentEngine>(request).await;
+        let response_content_type = response
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .expect("Response was missing Content-Type header");
+
+        assert_eq!(
+            response.status(),
+            StatusCode::OK,
+            "Response status was not 200"
+        );
+        assert_eq!(
+            response_content_type, "text/plain",
+            "Response Content-Type was not text/plain",
+        );
+    }
+
+    #[actix_rt::test]
+    async fn if_url_has_extension_accept_header_is_ignored() {
+        // URL path extension has the wrong media type, but accept header has
+        // the correct one. Should be HTTP 406 because the accept header is not
+        // considered when there is an extension.
+        let request = test_request(&sample_path("hello-world"), None, None)
+            .uri("/hello.doc")
+            .header(header::ACCEPT, "text/plain")
+            .to_http_request();
+
+        let response = get::<TestContentEngine>(request).await;
+
+        assert_eq!(
+            response.status(),
+            StatusCode::NOT_ACCEPTABLE,
+            "Response status was not 406"
+        );
+    }
+
+    #[actix_rt::test]
+    async fn index_route_is_used_for_empty_uri_path() {
+        let request = test_request(&sample_path("hello-world"), Some("/hello"), None)
+            .header(header::ACCEPT, "text/plain")
+            .to_http_request();
+
+        let mut response = get::<TestContentEngine>(request).await;
+        let response_body = collect_response_body(response.take_body())
+            .await
+            .expect("There was an error in the content stream");
+
+        assert_eq!(
+            response.status(),
+            StatusCode::OK,
+            "Response status was not 200"
+        );
+        assert_eq!(response_body, "hello world", "Response body was incorrect");
+    }
+
+    #[actix_rt::test]
+    #[cfg_attr(
+        not(all(feature = "executables", feature = "templating")),
+        ignore = "uses executables and templates"
+    )]
+    async fn error_handler_is_given_http_status_code() {
+        {
+            let request_not_found =
+                test_request(&sample_path("error-handling"), None, Some("/error-handler"))
+                    .header(header::ACCEPT, "text/plain")
+                    .uri("/not/a/real/path/so/this/should/404")
+                    .to_http_request();
+
+            let mut response = get::<TestContentEngine>(request_not_found).await;
+            let response_body = collect_response_body(response.take_body())
+                .await
+                .expect("There was an error in the content stream");
+
+            assert_eq!(
+                response.status(),
+                StatusCode::NOT_FOUND,
+                "Response status was not 404"
+            );
+            assert_eq!(
+                response_body, "error code: 404",
+                "Response body was incorrect"
+            );
+        }
+
+        {
+            let request_not_acceptable_error =
+                test_request(&sample_path("error-handling"), None, Some("/error-handler"))
+                    .header(header::ACCEPT, "text/plain")
+                    .uri("/json-file")
+                    .to_http_request();
+
+            let mut response = get::<TestContentEngine>(request_not_acceptable_error).await;
+            let response_body = collect_response_body(response.take_body())
+                .await
+                .expect("There was an error in the content stream");
+
+            assert_eq!(
+                response.status(),
+                StatusCode::NOT_ACCEPTABLE,
+                "Response status was not 406"
+            );
+            assert_eq!(
+                response_body, "error code: 406",
+                "Response body was incorrect"
+            );
+        }
+    }
+
+    #[actix_rt::test]
+    #[cfg_attr(
+        not(all(feature = "executables", feature = "templating")),
+        ignore = "uses executables and templates"
+    )]
+    async fn stream_errors_are_propagated() {
+        let request_internal_server_error =
+            test_request(&sample_path("error-handling"), None, Some("/error-handler"))
+                .header(header::ACCEPT, "text/plain")
+                .uri("/trigger-error")
+                .to_http_request();
+
+        let mut response = get::<TestContentEngine>(request_internal_server_error).await;
+        let response_body = collect_response_body(response.take_body()).await;
+
+        assert_eq!(
+            response.status(),
+            StatusCode::OK,
+            "Response status was not 200"
         );
-        headers.append(
-            HeaderName::from_static("accept"),
-            HeaderValue::from_static("text/html,*/*;q=0.8"),
+
+        assert_eq!(
+            response_body.unwrap_err().to_string(),
+            actix_web::Error::from(()).to_string()
         );
-        headers.append(
-            HeaderName::from_static("accept-language"),
-            HeaderValue::from_static("en-US,en;q=0.5"),
+    }
+
+    #[actix_rt::test]
+    #[cfg_attr(
+        not(all(feature = "executables", feature = "templating")),
+        ignore = "uses executables and templates"
+    )]
+    async fn stream_errors_within_buffer_threshold_are_error_responses() {
+        let mut config = Config::default();
+        config.streaming.buffer_threshold = 1024;
+        let request = test_request_with_config(
+            &sample_path("error-handling"),
+            None,
+            Some("/error-handler"),
+            config,
+        )
+        .header(header::ACCEPT, "text/plain")
+        .uri("/trigger-error")
+        .to_http_request();
+
+        let mut response = get::<TestContentEngine>(request).await;
+        let response_body = collect_response_body(response.take_body())
+            .await
+            .expect("There was an error in the content stream");
+
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(response_body, "error code: 500");
+    }
+
+    #[actix_rt::test]
+    #[cfg_attr(
+        not(all(feature = "executables", feature = "templating")),
+        ignore = "uses executables and templates"
+    )]
+    async fn buffer_threshold_can_be_set_per_media_type() {
+        let mut config = Config::default();
+        config.streaming.buffer_threshold = 1024;
+        config
+            .streaming
+            .buffer_thresholds
+            .insert(MediaType::from_media_range(mime::TEXT_PLAIN).unwrap(), 0);
+        let request = test_request_with_config(
+            &sample_path("error-handling"),
+            None,
+            Some("/error-handler"),
+            config,
+        )
+        .header(header::ACCEPT, "text/plain")
+        .uri("/trigger-error")
+        .to_http_request();
+
+        let response = get::<TestContentEngine>(request).await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[actix_rt::test]
+    #[cfg_attr(
+        not(all(feature = "executables", feature = "templating")),
+        ignore = "uses executables and templates"
+    )]
+    async fn stream_errors_abort_the_response() {
+        let request =
+            test_request_with_config(&sample_path("executables"), None, None, Config::default())
+                .header(header::ACCEPT, "text/plain")
+                .uri("/output-and-error")
+                .to_http_request();
+
+        let mut response = get::<TestContentEngine>(request).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        // The body fails instead of ending, so the response is never
+        // completed.
+        assert!(
+            collect_response_body(response.take_body()).await.is_err(),
+            "The response body ended normally, but it should have failed",
         );
-        headers.append(
-            HeaderName::from_static("accept-encoding"),
-            HeaderValue::from_static("gzip, deflate, br"),
+    }
+
+    #[actix_rt::test]
+    #[cfg_attr(
+        not(all(feature = "executables", feature = "templating")),
+        ignore = "uses executables and templates"
+    )]
+    async fn responses_which_take_too_long_time_out() {
+        let mut config = Config::default();
+        config.timeouts.request = 1;
+        config.streaming.buffer_threshold = 1024;
+        let request = test_request_with_config(&sample_path("executables"), None, None, config)
+            .header(header::ACCEPT, "text/plain")
+            .uri("/slow")
+            .to_http_request();
+
+        let response = handle_request::<TestContentEngine>(request).await;
+
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    #[actix_rt::test]
+    #[cfg_attr(
+        not(all(feature = "executables", feature = "templating")),
+        ignore = "uses executables and templates"
+    )]
+    async fn streams_which_take_too_long_are_cut_off() {
+        let mut config = Config::default();
+        config.timeouts.request = 1;
+        let request = test_request_with_config(&sample_path("executables"), None, None, config)
+            .header(header::ACCEPT, "text/plain")
+            .uri("/slow")
+            .to_http_request();
+
+        let mut response = handle_request::<TestContentEngine>(request).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(
+            collect_response_body(response.take_body()).await.is_err(),
+            "The response body ended normally, but it should have been cut off",
         );
-        headers.append(
-            HeaderName::from_static("upgrade-insecure-requests"),
-            HeaderValue::from_static("1"),
+    }
+
+    #[actix_rt::test]
+    #[cfg_attr(
+        not(all(feature = "executables", feature = "templating")),
+        ignore = "uses executables and templates"
+    )]
+    async fn requests_over_a_routes_concurrency_limit_are_rejected() {
+        let config = toml::from_str::<Config>(
+            r#"
+            routes."/slow" = { max-concurrent-requests = 1, queue-timeout-ms = 50, retry-after = 3 }
+            "#,
+        )
+        .expect("Config was invalid");
+        let app_data = test_request_with_config(&sample_path("executables"), None, None, config)
+            .to_http_request()
+            .app_data::<AppData<TestContentEngine>>()
+            .cloned()
+            .unwrap();
+        // These share bulkheads because they share app data.
+        let request = || {
+            TestRequest::default()
+                .app_data(app_data.clone())
+                .header(header::ACCEPT, "text/plain")
+                .uri("/slow")
+                .to_http_request()
+        };
+
+        let streaming_response = handle_request::<TestContentEngine>(request()).await;
+        assert_eq!(streaming_response.status(), StatusCode::OK);
+
+        let rejected_response = handle_request::<TestContentEngine>(request()).await;
+        assert_eq!(rejected_response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(
+            rejected_response.headers().get(header::RETRY_AFTER),
+            Some(&HeaderValue::from_static("3"))
         );
-        headers.append(
-            HeaderName::from_static("connection"),
-            HeaderValue::from_static("keep-alive"),
+
+        // The slot is freed once the first response body is gone.
+        drop(streaming_response);
+        let response = handle_request::<TestContentEngine>(request()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[actix_rt::test]
+    #[cfg_attr(
+        not(all(feature = "executables", feature = "templating")),
+        ignore = "uses executables and templates"
+    )]
+    async fn failed_renders_can_fall_back_to_another_route() {
+        let config = toml::from_str::<Config>(
+            r#"
+            streaming = { buffer-threshold = 1024 }
+            routes."/trigger-error" = { fallback = "/static-error-handler" }
+            "#,
+        )
+        .unwrap();
+        let request = test_request_with_config(
+            &sample_path("error-handling"),
+            None,
+            Some("/error-handler"),
+            config,
+        )
+        .header(header::ACCEPT, "text/plain")
+        .uri("/trigger-error")
+        .to_http_request();
+
+        let mut response = get::<TestContentEngine>(request).await;
+        let response_body = collect_response_body(response.take_body())
+            .await
+            .expect("There was an error in the content stream");
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response_body, "this is static error handler\n");
+    }
+
+    #[actix_rt::test]
+    #[cfg_attr(
+        not(all(feature = "executables", feature = "templating")),
+        ignore = "uses executables and templates"
+    )]
+    async fn failing_fallbacks_produce_error_responses() {
+        let config = toml::from_str::<Config>(
+            r#"
+            streaming = { buffer-threshold = 1024 }
+            routes."/trigger-error" = { fallback = "/does-not-exist" }
+            routes."/does-not-exist" = { fallback = "/trigger-error" }
+            "#,
+        )
+        .unwrap();
+        let request = test_request_with_config(
+            &sample_path("error-handling"),
+            None,
+            Some("/error-handler"),
+            config,
+        )
+        .header(header::ACCEPT, "text/plain")
+        .uri("/trigger-error")
+        .to_http_request();
+
+        let mut response = get::<TestContentEngine>(request).await;
+        let response_body = collect_response_body(response.take_body())
+            .await
+            .expect("There was an error in the content stream");
+
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(response_body, "error code: 500");
+    }
+
+    #[actix_rt::test]
+    #[cfg_attr(
+        not(all(feature = "executables", feature = "templating")),
+        ignore = "uses executables and templates"
+    )]
+    async fn error_handler_can_be_static_content() {
+        let request = test_request(
+            &sample_path("error-handling"),
+            None,
+            Some("/static-error-handler"),
+        )
+        .header(header::ACCEPT, "text/plain")
+        .uri("/not/a/real/path/so/this/should/404")
+        .to_http_request();
+
+        let mut response = get::<TestContentEngine>(request).await;
+        let response_body = collect_response_body(response.take_body())
+            .await
+            .expect("There was an error in the content stream");
+
+        assert_eq!(
+            response.status(),
+            StatusCode::NOT_FOUND,
+            "Response status was not 404"
         );
-        headers.append(
-            HeaderName::from_static("cookie"),
-            HeaderValue::from_static("foo=bar; blah=stuff"),
+        assert_eq!(
+            response_body, "this is static error handler\n",
+            "Response body was incorrect"
         );
+    }
+
+    #[actix_rt::test]
+    #[cfg_attr(
+        not(all(feature = "executables", feature = "templating")),
+        ignore = "uses executables and templates"
+    )]
+    async fn error_handler_can_be_executable() {
+        let request = test_request(
+            &sample_path("error-handling"),
+            None,
+            Some("/executable-error-handler"),
+        )
+        .header(header::ACCEPT, "text/plain")
+        .uri("/not/a/real/path/so/this/should/404")
+        .to_http_request();
+
+        let response = get::<TestContentEngine>(request).await;
 
-        let simplified_headers =
-            simplify_http_headers(&headers).expect("HTTP headers could not be converted");
         assert_eq!(
-            simplified_headers,
-            hashmap![
-                String::from("user-agent") => String::from("Operator tests"),
-                String::from("accept") => String::from("text/html,*/*;q=0.8"),
-                String::from("accept-language") => String::from("en-US,en;q=0.5"),
-                String::from("accept-encoding") => String::from("gzip, deflate, br"),
-                String::from("upgrade-insecure-requests") => String::from("1"),
-                String::from("connection") => String::from("keep-alive"),
-                String::from("cookie") => String::from("foo=bar; blah=stuff"),
-            ]
+            response.status(),
+            StatusCode::NOT_FOUND,
+            "Response status was not 404"
         );
     }
 
-    // TODO: Enable this test after upgrading to actix-web v4. See
-    // <https://github.com/actix/actix-web/issues/2466>.
-    #[ignore]
-    #[test]
-    fn duplicate_headers_are_handled() {
-        let mut headers = HeaderMap::new();
+    #[actix_rt::test]
+    #[cfg_attr(
+        not(all(feature = "executables", feature = "templating")),
+        ignore = "uses executables and templates"
+    )]
+    async fn error_handler_is_content_negotiated() {
+        {
+            let text_plain_request =
+                test_request(&sample_path("error-handling"), None, Some("/error-handler"))
+                    .header(header::ACCEPT, "text/plain")
+                    .uri("/not/a/real/path/so/this/should/404")
+                    .to_http_request();
+
+            let mut response = get::<TestContentEngine>(text_plain_request).await;
+            let response_body = collect_response_body(response.take_body())
+                .await
+                .expect("There was an error in the content stream");
+            let response_content_type = response
+                .headers()
+                .get(header::CONTENT_TYPE)
+                .expect("Response was missing Content-Type header");
+
+            assert_eq!(
+                response.status(),
+                StatusCode::NOT_FOUND,
+                "Response status was not 404"
+            );
+            assert_eq!(
+                response_body, "error code: 404",
+                "Response body was incorrect"
+            );
+            assert_eq!(
+                response_content_type, "text/plain",
+                "Response Content-Type was not text/plain",
+            );
+        }
+
+        {
+            let text_html_request =
+                test_request(&sample_path("error-handling"), None, Some("/error-handler"))
+                    .header(header::ACCEPT, "text/html")
+                    .uri("/not/a/real/path/so/this/should/404")
+                    .to_http_request();
+
+            let mut response = get::<TestContentEngine>(text_html_request).await;
+            let response_body = collect_response_body(response.take_body())
+                .await
+                .expect("There was an error in the content stream");
+            let response_content_type = response
+                .headers()
+                .get(header::CONTENT_TYPE)
+                .expect("Response was missing Content-Type header");
+
+            assert_eq!(
+                response.status(),
+                StatusCode::NOT_FOUND,
+                "Response status was not 404"
+            );
+            assert_eq!(
+                response_body, "<p>error code: 404</p>",
+                "Response body was incorrect"
+            );
+            assert_eq!(
+                response_content_type, "text/html",
+                "Response Content-Type was not text/html",
+            );
+        }
+    }
+
+    #[actix_rt::test]
+    async fn errors_without_a_handler_are_html_for_clients_that_prefer_it() {
+        for (accept, expected_content_type) in [
+            ("text/html,application/xhtml+xml,*/*;q=0.8", "text/html"),
+            ("text/plain,text/html;q=0.5", "text/plain"),
+            ("*/*", "text/plain"),
+            ("image/png", "text/plain"),
+        ] {
+            let request = test_request(&sample_path("hello-world"), None, None)
+                .header(header::ACCEPT, accept)
+                .uri("/not/a/real/path/so/this/should/404")
+                .to_http_request();
 
-        headers.append(
-            HeaderName::from_static("user-agent"),
-            HeaderValue::from_static("Operator tests"),
-        );
-        headers.append(
-            HeaderName::from_static("accept"),
-            HeaderValue::from_static("text/html,image/*"),
-        );
-        headers.append(
-            HeaderName::from_static("accept-language"),
-            HeaderValue::from_static("en-US,en;q=0.5"),
-        );
-        headers.append(
-            HeaderName::from_static("accept"),
-            HeaderValue::from_static("*/*;q=0.8"),
-        );
-        headers.append(
-            HeaderName::from_static("accept-language"),
-            HeaderValue::from_static("de"),
-        );
-        headers.append(
-            HeaderName::from_static("accept-language"),
-            HeaderValue::from_static("de-CH"),
-        );
-        headers.append(
-            HeaderName::from_static("x-arbitrary-header-1"),
-            HeaderValue::from_static("a,b,c"),
-        );
-        headers.append(
-            HeaderName::from_static("x-arbitrary-header-1"),
-            HeaderValue::from_static("d,e,f"),
-        );
-        headers.append(
-            HeaderName::from_static("x-arbitrary-header-1"),
-            HeaderValue::from_static("g,h,i"),
-        );
+            let mut response = get::<TestContentEngine>(request).await;
+            let response_body = collect_response_body(response.take_body())
+                .await
+                .expect("There was an error in the content stream");
 
-        let simplified_headers =
-            simplify_http_headers(&headers).expect("HTTP headers could not be converted");
-        assert_eq!(
-            simplified_headers,
-            hashmap![
-                String::from("user-agent") => String::from("Operator tests"),
-                String::from("accept-language") => String::from("en-US,en;q=0.5,de,de-CH"),
-                String::from("x-arbitrary-header-1") => String::from("a,b,c,d,e,f,g,h,i"),
-                String::from("accept") => String::from("text/html,image/*,*/*;q=0.8"),
-            ]
-        );
+            assert_eq!(response.status(), StatusCode::NOT_FOUND);
+            assert_eq!(
+                response.headers().get(header::CONTENT_TYPE),
+                Some(&HeaderValue::from_static(expected_content_type)),
+                "Wrong Content-Type for `Accept: {}`",
+                accept,
+            );
+            if expected_content_type == "text/html" {
+                let response_body = str::from_utf8(&response_body).unwrap();
+                assert!(response_body.starts_with("<!DOCTYPE html>"));
+                assert!(response_body.contains("<h1>Not Found</h1>"));
+            } else {
+                assert_eq!(response_body, "Not Found");
+            }
+        }
     }
 
     #[actix_rt::test]
-    async fn content_may_be_not_found() {
-        let request = test_request(&sample_path("empty"), None, None)
-            .uri("/nothing/exists/at/this/path")
-            .to_http_request();
-        let response = get::<TestContentEngine>(request).await;
+    #[cfg_attr(
+        not(all(feature = "executables", feature = "templating")),
+        ignore = "uses executables and templates"
+    )]
+    async fn use_a_default_error_handler_if_specified_handler_fails() {
+        {
+            // The error handler itself will trigger a rendering error.
+            let request =
+                test_request(&sample_path("error-handling"), None, Some("/trigger-error"))
+                    .header(header::ACCEPT, "text/html")
+                    .uri("/not/a/real/path/so/this/should/404")
+                    .to_http_request();
 
-        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+            let mut response = get::<TestContentEngine>(request).await;
+            let response_body = collect_response_body(response.take_body())
+                .await
+                .expect("There was an error in the content stream");
+            let response_content_type = response
+                .headers()
+                .get(header::CONTENT_TYPE)
+                .expect("Response was missing Content-Type header");
+
+            assert_eq!(
+                response.status(),
+                StatusCode::NOT_FOUND,
+                "Response status was not 404"
+            );
+            assert_eq!(response_body, "Not Found", "Response body was incorrect");
+            assert_eq!(
+                response_content_type, "text/plain",
+                "Response Content-Type was not text/plain",
+            );
+        }
+
+        {
+            // The error handler is fine, but is not an acceptable media type.
+            let request =
+                test_request(&sample_path("error-handling"), None, Some("/error-handler"))
+                    .header(header::ACCEPT, "video/mp4")
+                    .uri("/not/a/real/path/so/this/should/404")
+                    .to_http_request();
+
+            let mut response = get::<TestContentEngine>(request).await;
+            let response_body = collect_response_body(response.take_body())
+                .await
+                .expect("There was an error in the content stream");
+            let response_content_type = response
+                .headers()
+                .get(header::CONTENT_TYPE)
+                .expect("Response was missing Content-Type header");
+
+            assert_eq!(
+                response.status(),
+                StatusCode::NOT_FOUND,
+                "Response status was not 404"
+            );
+            assert_eq!(response_body, "Not Found", "Response body was incorrect");
+            assert_eq!(
+                // The default error handler always emits text/plain regardless
+                // of the accept header.
+                response_content_type,
+                "text/plain",
+                "Response Content-Type was not text/plain",
+            );
+        }
     }
 
     #[actix_rt::test]
-    async fn content_can_be_retrieved_with_exact_media_type() {
-        let request = test_request(&sample_path("hello-world"), None, None)
-            .uri("/hello")
-            .header(header::ACCEPT, "text/plain")
-            .to_http_request();
+    #[cfg_attr(
+        not(all(feature = "executables", feature = "templating")),
+        ignore = "uses executables and templates"
+    )]
+    async fn error_handler_sees_original_request_route() {
+        let request = test_request(
+            &sample_path("error-handling"),
+            None,
+            Some("/error-code-and-request-info"),
+        )
+        .header(header::ACCEPT, "text/plain")
+        .uri("/not/a/real/path/so/this/should/404")
+        .to_http_request();
 
         let mut response = get::<TestContentEngine>(request).await;
         let response_body = collect_response_body(response.take_body())
             .await
             .expect("There was an error in the content stream");
-        let response_content_type = response
-            .headers()
-            .get(header::CONTENT_TYPE)
-            .expect("Response was missing Content-Type header");
 
         assert_eq!(
             response.status(),
-            StatusCode::OK,
-            "Response status was not 200"
+            StatusCode::NOT_FOUND,
+            "Response status was not 404"
         );
         assert_eq!(
-            response_content_type, "text/plain",
-            "Response Content-Type was not text/plain",
+            response_body, "404 /not/a/real/path/so/this/should/404\nquery parameters:\nrequest headers:\n  accept: text/plain",
+            "Response body was incorrect"
         );
-        assert_eq!(response_body, "hello world", "Response body was incorrect");
     }
 
     #[actix_rt::test]
-    async fn content_can_be_retrieved_with_media_range() {
-        let request = test_request(&sample_path("hello-world"), None, None)
-            .uri("/hello")
-            .header(header::ACCEPT, "text/*")
+    #[cfg_attr(
+        not(all(feature = "executables", feature = "templating")),
+        ignore = "uses executables and templates"
+    )]
+    async fn query_parameters_are_handled() {
+        let request = test_request(&sample_path("executables"), None, None)
+            .uri("/render-data?a=hello&b=1&b=2&c")
             .to_http_request();
-
         let mut response = get::<TestContentEngine>(request).await;
         let response_body = collect_response_body(response.take_body())
             .await
             .expect("There was an error in the content stream");
-        let response_content_type = response
-            .headers()
-            .get(header::CONTENT_TYPE)
-            .expect("Response was missing Content-Type header");
 
-        assert_eq!(
-            response.status(),
-            StatusCode::OK,
-            "Response status was not 200"
-        );
-        assert_eq!(
-            response_content_type, "text/plain",
-            "Response Content-Type was not text/plain",
-        );
-        assert_eq!(response_body, "hello world", "Response body was incorrect");
+        let response_json = serde_json::from_slice::<serde_json::Value>(&response_body)
+            .expect("Could not parse JSON");
+
+        assert_eq!(&response_json["request"]["query-parameters"]["a"], "hello");
+        assert_eq!(&response_json["request"]["query-parameters"]["b"], "2");
+        assert_eq!(&response_json["request"]["query-parameters"]["c"], "");
     }
 
     #[actix_rt::test]
-    async fn content_can_be_retrieved_with_star_star_media_range() {
-        let request = test_request(&sample_path("hello-world"), None, None)
-            .uri("/hello")
-            .header(header::ACCEPT, "*/*")
+    #[cfg_attr(
+        not(all(feature = "executables", feature = "templating")),
+        ignore = "uses executables and templates"
+    )]
+    async fn request_uri_details_are_handled() {
+        let request = test_request(&sample_path("executables"), None, None)
+            .uri("/render-data.json?b=1&a")
+            .header("host", "example.com:8080")
             .to_http_request();
-
         let mut response = get::<TestContentEngine>(request).await;
         let response_body = collect_response_body(response.take_body())
             .await
             .expect("There was an error in the content stream");
-        let response_content_type = response
-            .headers()
-            .get(header::CONTENT_TYPE)
-            .expect("Response was missing Content-Type header");
 
-        assert_eq!(
-            response.status(),
-            StatusCode::OK,
-            "Response status was not 200"
-        );
-        assert_eq!(
-            response_content_type, "text/plain",
-            "Response Content-Type was not text/plain",
-        );
-        assert_eq!(response_body, "hello world", "Response body was incorrect");
+        let response_json = serde_json::from_slice::<serde_json::Value>(&response_body)
+            .expect("Could not parse JSON");
+
+        let request_json = &response_json["request"];
+        assert_eq!(request_json["route"], "/render-data");
+        let request_json = &request_json["http"];
+        assert_eq!(request_json["uri-path"], "/render-data.json");
+        assert_eq!(request_json["query-string"], "b=1&a");
+        assert_eq!(request_json["scheme"], "http");
+        assert_eq!(request_json["host"], "example.com:8080");
     }
 
     #[actix_rt::test]
-    async fn content_can_be_retrieved_with_elaborate_accept_header() {
-        let request = test_request(&sample_path("hello-world"), None, None)
-            .uri("/hello")
-            .header(header::ACCEPT, "audio/aac, text/*;q=0.9, image/gif;q=0.1")
+    #[cfg_attr(
+        not(all(feature = "executables", feature = "templating")),
+        ignore = "uses executables and templates"
+    )]
+    async fn negotiation_source_is_exposed() {
+        let request = test_request(&sample_path("executables"), None, None)
+            .uri("/render-data.json")
             .to_http_request();
-
         let mut response = get::<TestContentEngine>(request).await;
         let response_body = collect_response_body(response.take_body())
             .await
             .expect("There was an error in the content stream");
-        let response_content_type = response
-            .headers()
-            .get(header::CONTENT_TYPE)
-            .expect("Response was missing Content-Type header");
-
-        assert_eq!(
-            response.status(),
-            StatusCode::OK,
-            "Response status was not 200"
-        );
+        let response_json = serde_json::from_slice::<serde_json::Value>(&response_body)
+            .expect("Could not parse JSON");
+        assert_eq!(response_json["request"]["http"]["url-extension"], "json");
         assert_eq!(
-            response_content_type, "text/plain",
-            "Response Content-Type was not text/plain",
-        );
-        assert_eq!(response_body, "hello world", "Response body was incorrect");
-    }
-
-    #[actix_rt::test]
-    async fn content_can_be_retrieved_with_missing_accept_header() {
-        let request = test_request(&sample_path("hello-world"), None, None)
-            .uri("/hello")
-            .to_http_request();
+            response_json["request"]["http"]["negotiation-source"],
+            "url-extension"
+        );
 
+        let request = test_request(&sample_path("executables"), None, None)
+            .uri("/render-data")
+            .header("accept", "application/json")
+            .to_http_request();
         let mut response = get::<TestContentEngine>(request).await;
         let response_body = collect_response_body(response.take_body())
             .await
             .expect("There was an error in the content stream");
-        let response_content_type = response
-            .headers()
-            .get(header::CONTENT_TYPE)
-            .expect("Response was missing Content-Type header");
-
+        let response_json = serde_json::from_slice::<serde_json::Value>(&response_body)
+            .expect("Could not parse JSON");
         assert_eq!(
-            response.status(),
-            StatusCode::OK,
-            "Response status was not 200"
+            response_json["request"]["http"]["url-extension"],
+            serde_json::Value::Null
         );
         assert_eq!(
-            response_content_type, "text/plain",
-            "Response Content-Type was not text/plain",
+            response_json["request"]["http"]["negotiation-source"],
+            "accept-header"
         );
-        assert_eq!(response_body, "hello world", "Response body was incorrect");
     }
 
     #[actix_rt::test]
-    async fn multimedia_content_can_be_retrieved() {
-        let request = test_request(&sample_path("multimedia"), None, None)
-            .uri("/dramatic-prairie-dog")
-            .header(header::ACCEPT, "video/*")
-            .to_http_request();
+    async fn search_index_and_results_can_be_served() {
+        let config = toml::from_str::<Config>(
+            r#"
+            search = { index-route = "/search-index", query-route = "/_search" }
+            "#,
+        )
+        .unwrap();
 
+        let request =
+            test_request_with_config(&sample_path("realistic-basic"), None, None, config.clone())
+                .uri("/search-index.json")
+                .to_http_request();
         let mut response = get::<TestContentEngine>(request).await;
         let response_body = collect_response_body(response.take_body())
             .await
             .expect("There was an error in the content stream");
-        let response_content_type = response
-            .headers()
-            .get(header::CONTENT_TYPE)
-            .expect("Response was missing Content-Type header");
-
-        assert_eq!(
-            response.status(),
-            StatusCode::OK,
-            "Response status was not 200"
-        );
+        let response_json = serde_json::from_slice::<serde_json::Value>(&response_body)
+            .expect("Could not parse JSON");
+        assert_eq!(response.status(), StatusCode::OK);
         assert_eq!(
-            response_content_type, "video/mp4",
-            "Response Content-Type was not video/mp4",
+            response_json["documents"],
+            serde_json::json!([
+                { "route": "/page", "title": "Basic Demo" },
+                { "route": "/stuff/words", "title": "words" },
+            ])
         );
+        assert!(response_json["terms"]["boring"].is_array());
 
-        assert_eq!(
-            response_body.len(),
-            198946,
-            "Response body did not have the expected size",
-        );
+        let request = test_request_with_config(&sample_path("realistic-basic"), None, None, config)
+            .uri("/_search?q=Boring+HTML")
+            .to_http_request();
+        let mut response = get::<TestContentEngine>(request).await;
+        let response_body = collect_response_body(response.take_body())
+            .await
+            .expect("There was an error in the content stream");
+        let response_json = serde_json::from_slice::<serde_json::Value>(&response_body)
+            .expect("Could not parse JSON");
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response_json["query"], "Boring HTML");
+        assert_eq!(response_json["results"][0]["route"], "/page");
+        assert_eq!(response_json["results"].as_array().map(Vec::len), Some(1));
     }
 
     #[actix_rt::test]
-    async fn content_cannot_be_retrieved_if_no_acceptable_media_type() {
-        let request = test_request(&sample_path("hello-world"), None, None)
-            .uri("/hello")
-            .header(
-                header::ACCEPT,
-                "application/msword, font/otf, audio/3gpp2;q=0.1",
-            )
-            .to_http_request();
+    async fn cache_headers_can_be_set_per_route() {
+        let config = toml::from_str::<Config>(
+            r#"
+            [routes."/"]
+            cache-control = "no-cache"
+
+            [routes."/stuff"]
+            cache-control = "public, max-age=31536000, immutable"
+            expires = 3600
+            surrogate-control = "max-age=86400"
+            "#,
+        )
+        .unwrap();
 
+        let request =
+            test_request_with_config(&sample_path("realistic-basic"), None, None, config.clone())
+                .uri("/page.html")
+                .to_http_request();
         let response = get::<TestContentEngine>(request).await;
-
+        assert_eq!(response.status(), StatusCode::OK);
         assert_eq!(
-            response.status(),
-            StatusCode::NOT_ACCEPTABLE,
-            "Response status was not 406"
+            response.headers().get(header::CACHE_CONTROL),
+            Some(&HeaderValue::from_static("no-cache"))
         );
-    }
+        assert_eq!(response.headers().get(header::EXPIRES), None);
+        assert_eq!(response.headers().get("surrogate-control"), None);
 
-    #[actix_rt::test]
-    async fn extension_on_url_takes_precedence_over_accept_header() {
-        // Note .txt extension on URL path, but no text/plain (nor any other
-        // workable media range) in the accept header.
-        let request = test_request(&sample_path("hello-world"), None, None)
-            .uri("/hello.txt")
-            .header(
-                header::ACCEPT,
-                "application/msword, font/otf, audio/3gpp2;q=0.1",
-            )
+        let request = test_request_with_config(&sample_path("realistic-basic"), None, None, config)
+            .uri("/stuff/words.txt")
             .to_http_request();
-
         let response = get::<TestContentEngine>(request).await;
-        let response_content_type = response
-            .headers()
-            .get(header::CONTENT_TYPE)
-            .expect("Response was missing Content-Type header");
-
+        assert_eq!(response.status(), StatusCode::OK);
         assert_eq!(
-            response.status(),
-            StatusCode::OK,
-            "Response status was not 200"
+            response.headers().get(header::CACHE_CONTROL),
+            Some(&HeaderValue::from_static(
+                "public, max-age=31536000, immutable"
+            ))
         );
         assert_eq!(
-            response_content_type, "text/plain",
-            "Response Content-Type was not text/plain",
+            response.headers().get("surrogate-control"),
+            Some(&HeaderValue::from_static("max-age=86400"))
         );
+        let expires = response
+            .headers()
+            .get(header::EXPIRES)
+            .and_then(|expires| expires.to_str().ok())
+            .and_then(|expires| expires.parse::<header::HttpDate>().ok())
+            .map(SystemTime::from)
+            .expect("Expires header was missing or invalid");
+        assert!(expires > SystemTime::now() + Duration::from_secs(3500));
     }
 
     #[actix_rt::test]
-    async fn if_url_has_extension_accept_header_is_ignored() {
-        // URL path extension has the wrong media type, but accept header has
-        // the correct one. Should be HTTP 406 because the accept header is not
-        // considered when there is an extension.
-        let request = test_request(&sample_path("hello-world"), None, None)
-            .uri("/hello.doc")
-            .header(header::ACCEPT, "text/plain")
-            .to_http_request();
-
-        let response = get::<TestContentEngine>(request).await;
-
-        assert_eq!(
-            response.status(),
-            StatusCode::NOT_ACCEPTABLE,
-            "Response status was not 406"
-        );
-    }
+    #[cfg_attr(not(feature = "templating"), ignore = "uses templates")]
+    async fn failed_renders_show_details_in_dev_mode() {
+        let root = tempfile::tempdir().expect("Failed to create temporary directory");
+        std::fs::write(
+            root.path().join("broken.html.hbs"),
+            "---\ntitle: Broken\n---\n<p>\n{{no-such-helper 1}}\n</p>\n",
+        )
+        .unwrap();
+        let config = toml::from_str::<Config>(
+            r#"
+            dev = true
+            site = { api-token = "hunter2" }
+            "#,
+        )
+        .unwrap();
 
-    #[actix_rt::test]
-    async fn index_route_is_used_for_empty_uri_path() {
-        let request = test_request(&sample_path("hello-world"), Some("/hello"), None)
-            .header(header::ACCEPT, "text/plain")
+        let request = test_request_with_config(root.path(), None, None, config)
+            .header(header::ACCEPT, "text/html")
+            .header(header::AUTHORIZATION, "Bearer hunter2")
+            .uri("/broken")
             .to_http_request();
-
         let mut response = get::<TestContentEngine>(request).await;
         let response_body = collect_response_body(response.take_body())
             .await
             .expect("There was an error in the content stream");
+        let response_body = str::from_utf8(&response_body).unwrap();
 
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
         assert_eq!(
-            response.status(),
-            StatusCode::OK,
-            "Response status was not 200"
+            response.headers().get(header::CONTENT_TYPE),
+            Some(&HeaderValue::from_static("text/html"))
         );
-        assert_eq!(response_body, "hello world", "Response body was incorrect");
+        assert!(response_body.contains("no-such-helper"));
+        assert!(response_body.contains("<code>broken.html.hbs</code>, line 5"));
+        assert!(response_body.contains("<mark>5 | {{no-such-helper 1}}</mark>"));
+        assert!(response_body.contains("&quot;api-token&quot;: &quot;&lt;redacted&gt;&quot;"));
+        assert!(!response_body.contains("hunter2"));
     }
 
     #[actix_rt::test]
-    async fn error_handler_is_given_http_status_code() {
-        {
-            let request_not_found =
-                test_request(&sample_path("error-handling"), None, Some("/error-handler"))
-                    .header(header::ACCEPT, "text/plain")
-                    .uri("/not/a/real/path/so/this/should/404")
-                    .to_http_request();
-
-            let mut response = get::<TestContentEngine>(request_not_found).await;
-            let response_body = collect_response_body(response.take_body())
-                .await
-                .expect("There was an error in the content stream");
+    #[cfg_attr(not(feature = "templating"), ignore = "uses templates")]
+    async fn front_matter_can_override_cache_headers() {
+        let root = tempfile::tempdir().expect("Failed to create temporary directory");
+        std::fs::write(
+            root.path().join("private.txt.hbs"),
+            "---\ncache-control: private\n---\nhi",
+        )
+        .unwrap();
+        std::fs::write(root.path().join("public.txt.hbs"), "hi").unwrap();
+        let config = toml::from_str::<Config>(
+            r#"
+            routes."/" = { cache-control = "public" }
+            "#,
+        )
+        .unwrap();
 
+        for (path, cache_control) in [("/private", "private"), ("/public", "public")] {
+            let request = test_request_with_config(root.path(), None, None, config.clone())
+                .uri(path)
+                .to_http_request();
+            let response = get::<TestContentEngine>(request).await;
+            assert_eq!(response.status(), StatusCode::OK);
             assert_eq!(
-                response.status(),
-                StatusCode::NOT_FOUND,
-                "Response status was not 404"
-            );
-            assert_eq!(
-                response_body, "error code: 404",
-                "Response body was incorrect"
+                response.headers().get(header::CACHE_CONTROL),
+                Some(&HeaderValue::from_str(cache_control).unwrap())
             );
         }
+    }
 
-        {
-            let request_not_acceptable_error =
-                test_request(&sample_path("error-handling"), None, Some("/error-handler"))
-                    .header(header::ACCEPT, "text/plain")
-                    .uri("/json-file")
-                    .to_http_request();
+    #[actix_rt::test]
+    #[cfg_attr(not(feature = "templating"), ignore = "uses templates")]
+    async fn entity_tags_allow_conditional_requests() {
+        let root = tempfile::tempdir().expect("Failed to create temporary directory");
+        std::fs::write(
+            root.path().join("tagged.txt.hbs"),
+            "---\netag: strong\n---\nhello {{request.query-parameters.name}}",
+        )
+        .unwrap();
+        std::fs::write(root.path().join("untagged.txt.hbs"), "hello").unwrap();
 
-            let mut response = get::<TestContentEngine>(request_not_acceptable_error).await;
+        let request = test_request(root.path(), None, None)
+            .uri("/untagged")
+            .to_http_request();
+        let response = get::<TestContentEngine>(request).await;
+        assert_eq!(response.headers().get(header::ETAG), None);
+
+        let request = test_request(root.path(), None, None)
+            .uri("/tagged?name=a")
+            .to_http_request();
+        let response = get::<TestContentEngine>(request).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let entity_tag = response
+            .headers()
+            .get(header::ETAG)
+            .expect("ETag header was missing")
+            .clone();
+        // Tags only depend on the output, so every server agrees on them.
+        assert_eq!(entity_tag, "\"333780eab065cd51f5bbebc8d7a198f8\"");
+
+        for (if_none_match, expected_status) in [
+            (
+                entity_tag.to_str().unwrap().to_owned(),
+                StatusCode::NOT_MODIFIED,
+            ),
+            (
+                format!("\"other\", W/{}", entity_tag.to_str().unwrap()),
+                StatusCode::NOT_MODIFIED,
+            ),
+            (String::from("*"), StatusCode::NOT_MODIFIED),
+            (String::from("\"other\""), StatusCode::OK),
+        ] {
+            let request = test_request(root.path(), None, None)
+                .uri("/tagged?name=a")
+                .header(header::IF_NONE_MATCH, if_none_match.as_str())
+                .to_http_request();
+            let mut response = get::<TestContentEngine>(request).await;
             let response_body = collect_response_body(response.take_body())
                 .await
                 .expect("There was an error in the content stream");
-
             assert_eq!(
                 response.status(),
-                StatusCode::NOT_ACCEPTABLE,
-                "Response status was not 406"
-            );
-            assert_eq!(
-                response_body, "error code: 406",
-                "Response body was incorrect"
+                expected_status,
+                "Wrong status for If-None-Match: {}",
+                if_none_match,
             );
+            assert_eq!(response.headers().get(header::ETAG), Some(&entity_tag));
+            if expected_status == StatusCode::NOT_MODIFIED {
+                assert_eq!(response_body, "");
+            } else {
+                assert_eq!(response_body, "hello a");
+            }
         }
-    }
-
-    #[actix_rt::test]
-    async fn stream_errors_are_propagated() {
-        let request_internal_server_error =
-            test_request(&sample_path("error-handling"), None, Some("/error-handler"))
-                .header(header::ACCEPT, "text/plain")
-                .uri("/trigger-error")
-                .to_http_request();
-
-        let mut response = get::<TestContentEngine>(request_internal_server_error).await;
-        let response_body = collect_response_body(response.take_body()).await;
-
-        assert_eq!(
-            response.status(),
-            StatusCode::OK,
-            "Response status was not 200"
-        );
 
-        assert_eq!(
-            response_body.unwrap_err().to_string(),
-            actix_web::Error::from(()).to_string()
-        );
+        // Different output means a different tag.
+        let request = test_request(root.path(), None, None)
+            .uri("/tagged?name=b")
+            .header(header::IF_NONE_MATCH, entity_tag.clone())
+            .to_http_request();
+        let response = get::<TestContentEngine>(request).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_ne!(response.headers().get(header::ETAG), Some(&entity_tag));
     }
 
     #[actix_rt::test]
-    async fn error_handler_can_be_static_content() {
-        let request = test_request(
-            &sample_path("error-handling"),
-            None,
-            Some("/static-error-handler"),
+    async fn entity_tags_can_be_enabled_per_route() {
+        let config = toml::from_str::<Config>(
+            r#"
+            routes."/stuff" = { etag = "weak" }
+            "#,
         )
-        .header(header::ACCEPT, "text/plain")
-        .uri("/not/a/real/path/so/this/should/404")
-        .to_http_request();
-
-        let mut response = get::<TestContentEngine>(request).await;
-        let response_body = collect_response_body(response.take_body())
-            .await
-            .expect("There was an error in the content stream");
-
-        assert_eq!(
-            response.status(),
-            StatusCode::NOT_FOUND,
-            "Response status was not 404"
-        );
-        assert_eq!(
-            response_body, "this is static error handler\n",
-            "Response body was incorrect"
-        );
+        .unwrap();
+        let request = test_request_with_config(&sample_path("realistic-basic"), None, None, config)
+            .uri("/stuff/words.txt")
+            .to_http_request();
+        let response = get::<TestContentEngine>(request).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let entity_tag = response
+            .headers()
+            .get(header::ETAG)
+            .and_then(|entity_tag| entity_tag.to_str().ok())
+            .expect("ETag header was missing");
+        assert!(entity_tag.starts_with("W/\""));
     }
 
     #[actix_rt::test]
-    async fn error_handler_can_be_executable() {
-        let request = test_request(
-            &sample_path("error-handling"),
-            None,
-            Some("/executable-error-handler"),
-        )
-        .header(header::ACCEPT, "text/plain")
-        .uri("/not/a/real/path/so/this/should/404")
-        .to_http_request();
+    async fn precompressed_files_are_served_to_clients_that_accept_them() {
+        let root = tempfile::tempdir().expect("Failed to create temporary directory");
+        std::fs::write(root.path().join("page.html"), "uncompressed").unwrap();
+        std::fs::write(root.path().join("page.html.gz"), "gzipped").unwrap();
+        std::fs::write(root.path().join("page.html.br"), "brotlied").unwrap();
+        std::fs::write(root.path().join("plain.html"), "plain").unwrap();
+
+        for (path, accept_encoding, expected_encoding, expected_body) in [
+            ("/page", None, None, "uncompressed"),
+            ("/page", Some("gzip"), Some("gzip"), "gzipped"),
+            ("/page", Some("gzip, br"), Some("br"), "brotlied"),
+            ("/page", Some("br;q=0.5, gzip"), Some("gzip"), "gzipped"),
+            ("/page", Some("*"), Some("br"), "brotlied"),
+            ("/page", Some("br;q=0, gzip;q=0"), None, "uncompressed"),
+            ("/page", Some("deflate"), None, "uncompressed"),
+            ("/plain", Some("gzip"), None, "plain"),
+        ] {
+            let mut request = test_request(root.path(), None, None).uri(path);
+            if let Some(accept_encoding) = accept_encoding {
+                request = request.header(header::ACCEPT_ENCODING, accept_encoding);
+            }
+            let mut response = get::<TestContentEngine>(request.to_http_request()).await;
+            let response_body = collect_response_body(response.take_body())
+                .await
+                .expect("There was an error in the content stream");
 
-        let response = get::<TestContentEngine>(request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(
+                response
+                    .headers()
+                    .get(header::CONTENT_ENCODING)
+                    .and_then(|value| value.to_str().ok()),
+                expected_encoding,
+                "Wrong encoding for {} with Accept-Encoding {:?}",
+                path,
+                accept_encoding,
+            );
+            assert_eq!(
+                response
+                    .headers()
+                    .get(header::VARY)
+                    .and_then(|value| value.to_str().ok()),
+                if path == "/page" {
+                    Some("Accept-Encoding")
+                } else {
+                    None
+                },
+            );
+            assert_eq!(response_body, expected_body);
+        }
+    }
 
-        assert_eq!(
-            response.status(),
-            StatusCode::NOT_FOUND,
-            "Response status was not 404"
-        );
+    #[test]
+    fn byte_ranges_can_be_parsed() {
+        for (range, expected) in [
+            ("bytes=0-4", Some(vec![(0, 4)])),
+            ("bytes=0-4, 6-", Some(vec![(0, 4), (6, 9)])),
+            ("bytes=-3", Some(vec![(7, 9)])),
+            ("bytes=-30", Some(vec![(0, 9)])),
+            ("bytes=5-500", Some(vec![(5, 9)])),
+            ("BYTES=1-1", Some(vec![(1, 1)])),
+            ("bytes=10-", Some(vec![])),
+            ("bytes=20-30", Some(vec![])),
+            ("bytes=-0", Some(vec![])),
+            ("bytes=5-4", None),
+            ("bytes=-", None),
+            ("bytes=a-b", None),
+            ("bytes=0-4;6-8", None),
+            ("lines=0-4", None),
+            ("0-4", None),
+        ] {
+            assert_eq!(
+                parse_byte_ranges(range, 10),
+                expected,
+                "`{}` was not parsed correctly",
+                range,
+            );
+        }
+        assert_eq!(parse_byte_ranges("bytes=0-", 0), Some(vec![]));
+        assert_eq!(parse_byte_ranges("bytes=-5", 0), Some(vec![]));
     }
 
     #[actix_rt::test]
-    async fn error_handler_is_content_negotiated() {
-        {
-            let text_plain_request =
-                test_request(&sample_path("error-handling"), None, Some("/error-handler"))
-                    .header(header::ACCEPT, "text/plain")
-                    .uri("/not/a/real/path/so/this/should/404")
-                    .to_http_request();
+    async fn static_content_can_be_requested_in_byte_ranges() {
+        let root = tempfile::tempdir().expect("Failed to create temporary directory");
+        std::fs::write(root.path().join("digits.txt"), "0123456789").unwrap();
+        let request = |range: Option<&str>, if_range: Option<&str>| {
+            let mut request = test_request(root.path(), None, None).uri("/digits");
+            if let Some(range) = range {
+                request = request.header(header::RANGE, range);
+            }
+            if let Some(if_range) = if_range {
+                request = request.header(header::IF_RANGE, if_range);
+            }
+            request.to_http_request()
+        };
+        let header_value = |response: &HttpResponse, name| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(String::from)
+        };
 
-            let mut response = get::<TestContentEngine>(text_plain_request).await;
+        for (range, if_range, expected_status, expected_content_range, expected_body) in [
+            (None, None, StatusCode::OK, None, "0123456789"),
+            (
+                Some("bytes=2-4"),
+                None,
+                StatusCode::PARTIAL_CONTENT,
+                Some("bytes 2-4/10"),
+                "234",
+            ),
+            (
+                Some("bytes=-2"),
+                None,
+                StatusCode::PARTIAL_CONTENT,
+                Some("bytes 8-9/10"),
+                "89",
+            ),
+            (
+                Some("bytes=20-"),
+                None,
+                StatusCode::RANGE_NOT_SATISFIABLE,
+                Some("bytes */10"),
+                "",
+            ),
+            (Some("bytes=4-2"), None, StatusCode::OK, None, "0123456789"),
+            (
+                Some("bytes=2-4"),
+                Some("\"some-etag\""),
+                StatusCode::OK,
+                None,
+                "0123456789",
+            ),
+        ] {
+            let mut response = get::<TestContentEngine>(request(range, if_range)).await;
             let response_body = collect_response_body(response.take_body())
                 .await
                 .expect("There was an error in the content stream");
-            let response_content_type = response
-                .headers()
-                .get(header::CONTENT_TYPE)
-                .expect("Response was missing Content-Type header");
 
+            assert_eq!(response.status(), expected_status, "Range: {:?}", range);
             assert_eq!(
-                response.status(),
-                StatusCode::NOT_FOUND,
-                "Response status was not 404"
+                header_value(&response, header::ACCEPT_RANGES).as_deref(),
+                Some("bytes"),
             );
             assert_eq!(
-                response_body, "error code: 404",
-                "Response body was incorrect"
+                header_value(&response, header::CONTENT_RANGE).as_deref(),
+                expected_content_range,
             );
+            assert_eq!(response_body, expected_body, "Range: {:?}", range);
+        }
+
+        let mut response = get::<TestContentEngine>(request(Some("bytes=0-1,-3"), None)).await;
+        let response_body = collect_response_body(response.take_body())
+            .await
+            .expect("There was an error in the content stream");
+        let content_type = header_value(&response, header::CONTENT_TYPE)
+            .expect("Response was missing Content-Type header");
+        let boundary = content_type
+            .strip_prefix("multipart/byteranges; boundary=")
+            .expect("Response was not multipart/byteranges");
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(
+            response_body,
+            format!(
+                "\r\n--{boundary}\r\nContent-Type: text/plain\r\nContent-Range: bytes 0-1/10\r\n\r\n01\
+                \r\n--{boundary}\r\nContent-Type: text/plain\r\nContent-Range: bytes 7-9/10\r\n\r\n789\
+                \r\n--{boundary}--\r\n",
+                boundary = boundary,
+            ),
+        );
+    }
+
+    #[actix_rt::test]
+    async fn redirect_files_redirect() {
+        let root = tempfile::tempdir().expect("Failed to create temporary directory");
+        std::fs::write(root.path().join("moved.redirect"), "/new-home").unwrap();
+        std::fs::write(
+            root.path().join("elsewhere.redirect"),
+            "https://example.com/ 307",
+        )
+        .unwrap();
+
+        for (path, status, location) in [
+            ("/moved", StatusCode::MOVED_PERMANENTLY, "/new-home"),
+            ("/moved.html", StatusCode::MOVED_PERMANENTLY, "/new-home"),
+            (
+                "/elsewhere",
+                StatusCode::TEMPORARY_REDIRECT,
+                "https://example.com/",
+            ),
+        ] {
+            let request = test_request(root.path(), None, None)
+                .uri(path)
+                .to_http_request();
+            let response = get::<TestContentEngine>(request).await;
+            assert_eq!(response.status(), status);
             assert_eq!(
-                response_content_type, "text/plain",
-                "Response Content-Type was not text/plain",
+                response.headers().get(header::LOCATION),
+                Some(&HeaderValue::from_static(location))
             );
         }
+    }
 
-        {
-            let text_html_request =
-                test_request(&sample_path("error-handling"), None, Some("/error-handler"))
-                    .header(header::ACCEPT, "text/html")
-                    .uri("/not/a/real/path/so/this/should/404")
-                    .to_http_request();
-
-            let mut response = get::<TestContentEngine>(text_html_request).await;
+    #[actix_rt::test]
+    #[cfg_attr(not(feature = "templating"), ignore = "uses templates")]
+    async fn content_can_override_the_response_status() {
+        let root = tempfile::tempdir().expect("Failed to create temporary directory");
+        std::fs::write(
+            root.path().join("gone.txt.hbs"),
+            "---\nstatus: 410\n---\nThis page has been removed.",
+        )
+        .unwrap();
+        std::fs::create_dir(root.path().join("private")).unwrap();
+        std::fs::write(root.path().join("private/page.txt"), "Access denied.").unwrap();
+        std::fs::write(
+            root.path().join("private/teapot.txt.hbs"),
+            "---\nstatus: 418\n---\nI'm a teapot.",
+        )
+        .unwrap();
+        let config = toml::from_str::<Config>(
+            r#"
+            routes."/private" = { status = 403 }
+            "#,
+        )
+        .unwrap();
+
+        for (path, status, body) in [
+            ("/gone", StatusCode::GONE, "This page has been removed."),
+            ("/private/page", StatusCode::FORBIDDEN, "Access denied."),
+            ("/private/teapot", StatusCode::IM_A_TEAPOT, "I'm a teapot."),
+        ] {
+            let request = test_request_with_config(root.path(), None, None, config.clone())
+                .uri(path)
+                .to_http_request();
+            let mut response = get::<TestContentEngine>(request).await;
             let response_body = collect_response_body(response.take_body())
                 .await
                 .expect("There was an error in the content stream");
-            let response_content_type = response
-                .headers()
-                .get(header::CONTENT_TYPE)
-                .expect("Response was missing Content-Type header");
+            assert_eq!(response.status(), status);
+            assert_eq!(response_body, body);
+        }
+    }
 
-            assert_eq!(
-                response.status(),
-                StatusCode::NOT_FOUND,
-                "Response status was not 404"
-            );
-            assert_eq!(
-                response_body, "<p>error code: 404</p>",
-                "Response body was incorrect"
-            );
-            assert_eq!(
-                response_content_type, "text/html",
-                "Response Content-Type was not text/html",
-            );
+    #[actix_rt::test]
+    #[cfg_attr(not(feature = "templating"), ignore = "uses templates")]
+    async fn path_prefixes_are_stripped_and_exposed() {
+        let root = tempfile::tempdir().expect("Failed to create temporary directory");
+        std::fs::write(
+            root.path().join("page.html.hbs"),
+            "{{request.http.path-prefix}}/other",
+        )
+        .unwrap();
+        std::fs::write(root.path().join("moved.redirect"), "/page").unwrap();
+        let config = Config {
+            path_prefix: Some(String::from("/app/")),
+            ..Config::default()
+        };
+
+        for path in ["/app/page", "/app/page.html", "/page"] {
+            let request = test_request_with_config(root.path(), None, None, config.clone())
+                .uri(path)
+                .to_http_request();
+            let mut response = get::<TestContentEngine>(request).await;
+            let response_body = collect_response_body(response.take_body())
+                .await
+                .expect("There was an error in the content stream");
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(response_body, "/app/other");
         }
+
+        let request = test_request_with_config(root.path(), None, None, config.clone())
+            .uri("/app/moved")
+            .to_http_request();
+        let response = get::<TestContentEngine>(request).await;
+        assert_eq!(
+            response.headers().get(header::LOCATION),
+            Some(&HeaderValue::from_static("/app/page"))
+        );
+
+        let request = test_request_with_config(root.path(), None, None, config)
+            .uri("/application/page")
+            .to_http_request();
+        let response = get::<TestContentEngine>(request).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
     }
 
     #[actix_rt::test]
-    async fn use_a_default_error_handler_if_specified_handler_fails() {
-        {
-            // The error handler itself will trigger a rendering error.
-            let request =
-                test_request(&sample_path("error-handling"), None, Some("/trigger-error"))
-                    .header(header::ACCEPT, "text/html")
-                    .uri("/not/a/real/path/so/this/should/404")
-                    .to_http_request();
+    #[cfg_attr(not(feature = "templating"), ignore = "uses templates")]
+    async fn forwarded_prefixes_are_only_trusted_from_trusted_proxies() {
+        let root = tempfile::tempdir().expect("Failed to create temporary directory");
+        std::fs::write(
+            root.path().join("page.html.hbs"),
+            "{{request.http.path-prefix}}/other",
+        )
+        .unwrap();
+        let config = toml::from_str::<Config>(
+            r#"
+            trusted-proxies = ["10.0.0.1"]
+            "#,
+        )
+        .unwrap();
+
+        for (peer_address, path, expected_body) in [
+            ("10.0.0.1:1234", "/forwarded/page", "/forwarded/other"),
+            ("10.0.0.2:1234", "/page", "/other"),
+        ] {
+            let request = test_request_with_config(root.path(), None, None, config.clone())
+                .uri(path)
+                .peer_addr(peer_address.parse().unwrap())
+                .header("X-Forwarded-Prefix", "/forwarded")
+                .to_http_request();
+            let mut response = get::<TestContentEngine>(request).await;
+            let response_body = collect_response_body(response.take_body())
+                .await
+                .expect("There was an error in the content stream");
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(response_body, expected_body);
+        }
+    }
 
+    #[actix_rt::test]
+    #[cfg_attr(not(feature = "templating"), ignore = "uses templates")]
+    async fn only_trusted_hosts_are_used_for_absolute_urls() {
+        let root = tempfile::tempdir().expect("Failed to create temporary directory");
+        std::fs::write(
+            root.path().join("page.txt.hbs"),
+            "{{absolute-url \"/feed.xml\"}}",
+        )
+        .unwrap();
+        let config = toml::from_str::<Config>(
+            r#"
+            trusted-proxies = ["10.0.0.1", "10.0.0.3"]
+            "#,
+        )
+        .unwrap();
+
+        for (peer_address, forwarded_for, forwarded_host, expected_body) in [
+            (
+                "10.0.0.1:1234",
+                "203.0.113.7",
+                "example.com",
+                "https://example.com/app/feed.xml",
+            ),
+            // Values before those added by trusted proxies came from the
+            // client, so they are skipped.
+            (
+                "10.0.0.1:1234",
+                "203.0.113.7",
+                "spoofed.example.com, example.com",
+                "https://example.com/app/feed.xml",
+            ),
+            (
+                "10.0.0.1:1234",
+                "203.0.113.7, 10.0.0.3",
+                "spoofed.example.com, example.com, proxy.example.com",
+                "https://example.com/app/feed.xml",
+            ),
+        ] {
+            let request = test_request_with_config(root.path(), None, None, config.clone())
+                .uri("/page")
+                .peer_addr(peer_address.parse().unwrap())
+                .header("Host", "internal:8080")
+                .header("X-Forwarded-For", forwarded_for)
+                .header("X-Forwarded-Proto", "https")
+                .header("X-Forwarded-Host", forwarded_host)
+                .header("X-Forwarded-Prefix", "/app")
+                .to_http_request();
             let mut response = get::<TestContentEngine>(request).await;
             let response_body = collect_response_body(response.take_body())
                 .await
                 .expect("There was an error in the content stream");
-            let response_content_type = response
-                .headers()
-                .get(header::CONTENT_TYPE)
-                .expect("Response was missing Content-Type header");
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(response_body, expected_body);
+        }
+
+        // Without a trusted proxy the `Host` header came from the client.
+        let request = test_request_with_config(root.path(), None, None, config.clone())
+            .uri("/page")
+            .peer_addr("10.0.0.2:1234".parse().unwrap())
+            .header("Host", "attacker.example.com")
+            .header("X-Forwarded-Host", "example.com")
+            .to_http_request();
+        let response = get::<TestContentEngine>(request).await;
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
 
+    #[actix_rt::test]
+    async fn cors_headers_are_added_for_allowed_origins() {
+        let config = toml::from_str::<Config>(
+            r#"
+            routes."/stuff".cors = { allowed-origins = ["https://app.example.com"] }
+            routes."/stuff/words".cors = { allowed-origins = ["*"] }
+            "#,
+        )
+        .unwrap();
+
+        for (path, origin, expected_allow_origin, expected_vary) in [
+            (
+                "/stuff/pixels.jpg",
+                Some("https://app.example.com"),
+                Some("https://app.example.com"),
+                Some("Origin"),
+            ),
+            (
+                "/stuff/pixels.jpg",
+                Some("https://evil.example.com"),
+                None,
+                Some("Origin"),
+            ),
+            ("/stuff/pixels.jpg", None, None, Some("Origin")),
+            (
+                "/stuff/words.txt",
+                Some("https://evil.example.com"),
+                Some("*"),
+                None,
+            ),
+            ("/stuff/words.txt", None, None, None),
+            ("/page", Some("https://app.example.com"), None, None),
+        ] {
+            let mut request = test_request_with_config(
+                &sample_path("realistic-basic"),
+                None,
+                None,
+                config.clone(),
+            )
+            .uri(path);
+            if let Some(origin) = origin {
+                request = request.header(header::ORIGIN, origin);
+            }
+            let request = request.to_http_request();
+            let response = handle_request::<TestContentEngine>(request).await;
+            assert_eq!(response.status(), StatusCode::OK);
             assert_eq!(
-                response.status(),
-                StatusCode::NOT_FOUND,
-                "Response status was not 404"
+                response
+                    .headers()
+                    .get(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+                    .and_then(|value| value.to_str().ok()),
+                expected_allow_origin,
+                "Wrong Access-Control-Allow-Origin for {} from {:?}",
+                path,
+                origin,
             );
-            assert_eq!(response_body, "Not Found", "Response body was incorrect");
             assert_eq!(
-                response_content_type, "text/plain",
-                "Response Content-Type was not text/plain",
+                response
+                    .headers()
+                    .get(header::VARY)
+                    .and_then(|value| value.to_str().ok()),
+                expected_vary,
+                "Wrong Vary for {} from {:?}",
+                path,
+                origin,
             );
         }
+    }
 
-        {
-            // The error handler is fine, but is not an acceptable media type.
-            let request =
-                test_request(&sample_path("error-handling"), None, Some("/error-handler"))
-                    .header(header::ACCEPT, "video/mp4")
-                    .uri("/not/a/real/path/so/this/should/404")
-                    .to_http_request();
+    #[actix_rt::test]
+    async fn cors_preflight_requests_are_handled() {
+        let config = toml::from_str::<Config>(
+            r#"
+            [routes."/stuff".cors]
+            allowed-origins = ["https://app.example.com"]
+            allowed-headers = ["Authorization", "X-Requested-With"]
+            max-age = 600
+            "#,
+        )
+        .unwrap();
+        let preflight_request = |requested_method, requested_headers| {
+            test_request_with_config(&sample_path("realistic-basic"), None, None, config.clone())
+                .method(http::Method::OPTIONS)
+                .uri("/stuff/words")
+                .header(header::ORIGIN, "https://app.example.com")
+                .header(header::ACCESS_CONTROL_REQUEST_METHOD, requested_method)
+                .header(header::ACCESS_CONTROL_REQUEST_HEADERS, requested_headers)
+                .to_http_request()
+        };
 
-            let mut response = get::<TestContentEngine>(request).await;
-            let response_body = collect_response_body(response.take_body())
-                .await
-                .expect("There was an error in the content stream");
-            let response_content_type = response
-                .headers()
-                .get(header::CONTENT_TYPE)
-                .expect("Response was missing Content-Type header");
+        let response = handle_request::<TestContentEngine>(preflight_request(
+            "GET",
+            "authorization, x-requested-with",
+        ))
+        .await;
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        for (header_name, expected_value) in [
+            (
+                header::ACCESS_CONTROL_ALLOW_ORIGIN,
+                "https://app.example.com",
+            ),
+            (header::ACCESS_CONTROL_ALLOW_METHODS, "GET"),
+            (
+                header::ACCESS_CONTROL_ALLOW_HEADERS,
+                "Authorization, X-Requested-With",
+            ),
+            (header::ACCESS_CONTROL_MAX_AGE, "600"),
+        ] {
+            assert_eq!(
+                response.headers().get(&header_name),
+                Some(&HeaderValue::from_static(expected_value)),
+                "Wrong {} header",
+                header_name,
+            );
+        }
+
+        for (requested_method, requested_headers) in
+            [("DELETE", "authorization"), ("GET", "x-unexpected")]
+        {
+            let response = handle_request::<TestContentEngine>(preflight_request(
+                requested_method,
+                requested_headers,
+            ))
+            .await;
+            assert_eq!(response.status(), StatusCode::NO_CONTENT);
+            assert_eq!(
+                response.headers().get(header::ACCESS_CONTROL_ALLOW_ORIGIN),
+                None
+            );
+        }
+    }
 
+    #[actix_rt::test]
+    async fn virtual_hosts_are_served_by_host_name() {
+        let virtual_hosts = hashmap![
+            String::from("hello.example.com") => test_content_engine(
+                &sample_path("hello-world"),
+                Config::default(),
+            ),
+        ];
+
+        for (host, path, expected_status) in [
+            ("hello.example.com", "/hello", StatusCode::OK),
+            ("HELLO.example.com:8080", "/hello", StatusCode::OK),
+            ("hello.example.com", "/page", StatusCode::NOT_FOUND),
+            ("other.example.com", "/hello", StatusCode::NOT_FOUND),
+            ("other.example.com", "/page", StatusCode::OK),
+        ] {
+            let request = TestRequest::default()
+                .app_data(AppData {
+                    shared_content_engine: test_content_engine(
+                        &sample_path("realistic-basic"),
+                        Config::default(),
+                    ),
+                    virtual_hosts: virtual_hosts.clone(),
+                    index_route: None,
+                    error_handler_route: None,
+                    config: Config::default(),
+                    reloader: None,
+                    bulkheads: Bulkheads::default(),
+                })
+                .uri(path)
+                .header(header::HOST, host)
+                .to_http_request();
+            let response = get::<TestContentEngine>(request).await;
             assert_eq!(
                 response.status(),
-                StatusCode::NOT_FOUND,
-                "Response status was not 404"
+                expected_status,
+                "Wrong status for {} on {}",
+                path,
+                host,
             );
-            assert_eq!(response_body, "Not Found", "Response body was incorrect");
+        }
+    }
+
+    #[actix_rt::test]
+    async fn virtual_hosts_are_trusted_origins() {
+        let app_data = AppData {
+            shared_content_engine: test_content_engine(
+                &sample_path("realistic-basic"),
+                Config::default(),
+            ),
+            virtual_hosts: hashmap![
+                String::from("hello.example.com") => test_content_engine(
+                    &sample_path("hello-world"),
+                    Config::default(),
+                ),
+            ],
+            index_route: None,
+            error_handler_route: None,
+            config: Config::default(),
+            reloader: None,
+            bulkheads: Bulkheads::default(),
+        };
+        for (host, expected_origin) in [
+            (
+                "hello.example.com:8080",
+                Some("http://hello.example.com:8080"),
+            ),
+            ("other.example.com", None),
+        ] {
+            let request = TestRequest::default()
+                .header(header::HOST, host)
+                .to_http_request();
             assert_eq!(
-                // The default error handler always emits text/plain regardless
-                // of the accept header.
-                response_content_type,
-                "text/plain",
-                "Response Content-Type was not text/plain",
+                app_data.http_request_data(&request).origin.as_deref(),
+                expected_origin,
+                "Wrong origin for {}",
+                host,
             );
         }
     }
 
     #[actix_rt::test]
-    async fn error_handler_sees_original_request_route() {
-        let request = test_request(
-            &sample_path("error-handling"),
-            None,
-            Some("/error-code-and-request-info"),
+    async fn servers_can_be_embedded() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = OperatorServer::builder(test_content_engine(
+            &sample_path("hello-world"),
+            Config::default(),
+        ))
+        .with_index_route(route("/hello"))
+        .listen(listener)
+        .expect("Server could not be started");
+        let handle = server.handle();
+
+        let (result, ()) = future::join(server, async move {
+            let mut response = actix_web::client::Client::new()
+                .get(format!("http://{}/", address))
+                .send()
+                .await
+                .expect("Request failed");
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(response.body().await.unwrap(), "hello world");
+            handle.stop(true).await;
+        })
+        .await;
+        assert!(result.is_ok());
+    }
+
+    #[actix_rt::test]
+    async fn requests_can_be_handled_in_other_apps() {
+        let builder = OperatorServer::builder(test_content_engine(
+            &sample_path("hello-world"),
+            Config::default(),
+        ));
+        let mut app = actix_web::test::init_service(
+            App::new()
+                .app_data(builder.app_data())
+                .route("/custom", web::get().to(|| async { "custom" }))
+                .default_service(web::to(handle_request::<TestContentEngine>)),
         )
-        .header(header::ACCEPT, "text/plain")
-        .uri("/not/a/real/path/so/this/should/404")
-        .to_http_request();
+        .await;
 
-        let mut response = get::<TestContentEngine>(request).await;
+        for (path, expected_body) in [("/custom", "custom"), ("/hello", "hello world")] {
+            let request = TestRequest::get().uri(path).to_request();
+            let body = actix_web::test::read_response(&mut app, request).await;
+            assert_eq!(body, expected_body);
+        }
+    }
+
+    #[actix_rt::test]
+    async fn content_can_be_reloaded_by_authorized_requests() {
+        let root = tempfile::tempdir().expect("Failed to create temporary directory");
+        std::fs::write(root.path().join("page.txt"), "old").unwrap();
+        let config = toml::from_str::<Config>(
+            r#"
+            [reload]
+            route = "/_reload"
+            secret = "hunter2"
+            "#,
+        )
+        .expect("Config was invalid");
+        let shared_content_engine = test_content_engine(root.path(), config.clone());
+        let reloader: Reloader = {
+            let shared_content_engine = shared_content_engine.clone();
+            Arc::new(move |changed_paths| {
+                TestContentEngine::reload_changed(&shared_content_engine, changed_paths).map(|_| ())
+            })
+        };
+        let request = |method: http::Method, path: &str, authorization: Option<&str>| {
+            let mut request = TestRequest::default()
+                .app_data(AppData {
+                    shared_content_engine: shared_content_engine.clone(),
+                    virtual_hosts: HashMap::new(),
+                    index_route: None,
+                    error_handler_route: None,
+                    config: config.clone(),
+                    reloader: Some(reloader.clone()),
+                    bulkheads: Bulkheads::default(),
+                })
+                .method(method)
+                .uri(path);
+            if let Some(authorization) = authorization {
+                request = request.header(header::AUTHORIZATION, authorization);
+            }
+            handle_request::<TestContentEngine>(request.to_http_request())
+        };
+
+        std::fs::remove_file(root.path().join("page.txt")).unwrap();
+        std::fs::write(root.path().join("page.txt"), "new").unwrap();
+
+        let response = request(http::Method::POST, "/_reload", None).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let response = request(http::Method::POST, "/_reload", Some("Bearer hunter3")).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let response = request(http::Method::POST, "/page", Some("Bearer hunter2")).await;
+        assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+        let mut response = request(http::Method::GET, "/page", None).await;
         let response_body = collect_response_body(response.take_body())
             .await
             .expect("There was an error in the content stream");
+        assert_eq!(response_body, "old");
 
-        assert_eq!(
-            response.status(),
-            StatusCode::NOT_FOUND,
-            "Response status was not 404"
-        );
-        assert_eq!(
-            response_body, "404 /not/a/real/path/so/this/should/404\nquery parameters:\nrequest headers:\n  accept: text/plain",
-            "Response body was incorrect"
-        );
+        let response = request(http::Method::POST, "/_reload", Some("Bearer hunter2")).await;
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        let mut response = request(http::Method::GET, "/page", None).await;
+        let response_body = collect_response_body(response.take_body())
+            .await
+            .expect("There was an error in the content stream");
+        assert_eq!(response_body, "new");
+
+        std::fs::remove_file(root.path().join("page.txt")).unwrap();
+        std::fs::write(root.path().join("page.txt"), "newer").unwrap();
+        let response = request(
+            http::Method::POST,
+            "/_reload?changed=page.txt",
+            Some("Bearer hunter2"),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        let mut response = request(http::Method::GET, "/page", None).await;
+        let response_body = collect_response_body(response.take_body())
+            .await
+            .expect("There was an error in the content stream");
+        assert_eq!(response_body, "newer");
+    }
+
+    #[test]
+    fn ports_are_stripped_from_host_names() {
+        assert_eq!(host_name("example.com"), "example.com");
+        assert_eq!(host_name("example.com:8080"), "example.com");
+        assert_eq!(host_name("[::1]"), "[::1]");
+        assert_eq!(host_name("[::1]:8080"), "[::1]");
     }
 
     #[actix_rt::test]
-    async fn query_parameters_are_handled() {
+    #[cfg_attr(
+        not(all(feature = "executables", feature = "templating")),
+        ignore = "uses executables and templates"
+    )]
+    async fn repeated_query_parameters_are_listed() {
         let request = test_request(&sample_path("executables"), None, None)
-            .uri("/render-data?a=hello&b=1&b=2&c")
+            .uri("/render-data?a=hello&b=1&b=2&c[]=x&c[]=y")
             .to_http_request();
         let mut response = get::<TestContentEngine>(request).await;
         let response_body = collect_response_body(response.take_body())
@@ -1299,12 +4438,17 @@ mod tests {
         let response_json = serde_json::from_slice::<serde_json::Value>(&response_body)
             .expect("Could not parse JSON");
 
-        assert_eq!(&response_json["request"]["query-parameters"]["a"], "hello");
-        assert_eq!(&response_json["request"]["query-parameters"]["b"], "2");
-        assert_eq!(&response_json["request"]["query-parameters"]["c"], "");
+        let lists = &response_json["request"]["query-parameter-lists"];
+        assert_eq!(lists["a"], serde_json::json!(["hello"]));
+        assert_eq!(lists["b"], serde_json::json!(["1", "2"]));
+        assert_eq!(lists["c"], serde_json::json!(["x", "y"]));
     }
 
     #[actix_rt::test]
+    #[cfg_attr(
+        not(all(feature = "executables", feature = "templating")),
+        ignore = "uses executables and templates"
+    )]
     async fn query_parameters_are_forwarded_to_getted_content() {
         let request = test_request(&sample_path("executables"), None, None)
             .uri("/get-render-data?hello=world")
@@ -1321,9 +4465,41 @@ mod tests {
             &response_json["request"]["query-parameters"]["hello"],
             "world"
         );
+        assert_eq!(
+            &response_json["request"]["http"]["uri-path"],
+            "/get-render-data"
+        );
+    }
+
+    #[actix_rt::test]
+    #[cfg_attr(
+        not(all(feature = "executables", feature = "templating")),
+        ignore = "uses executables and templates"
+    )]
+    async fn repeated_query_parameters_are_forwarded_to_getted_content() {
+        let request = test_request(&sample_path("executables"), None, None)
+            .uri("/get-render-data?a=1&a=2")
+            .to_http_request();
+        let mut response = get::<TestContentEngine>(request).await;
+        let response_body = collect_response_body(response.take_body())
+            .await
+            .expect("There was an error in the content stream");
+
+        let response_json = serde_json::from_slice::<serde_json::Value>(&response_body)
+            .expect("Could not parse JSON");
+
+        assert_eq!(
+            &response_json["request"]["query-parameter-lists"]["a"],
+            &serde_json::json!(["1", "2"])
+        );
+        assert_eq!(&response_json["request"]["query-parameters"]["a"], "2");
     }
 
     #[actix_rt::test]
+    #[cfg_attr(
+        not(all(feature = "executables", feature = "templating")),
+        ignore = "uses executables and templates"
+    )]
     async fn query_parameters_are_forwarded_to_error_handler() {
         let request = test_request(
             &sample_path("error-handling"),
@@ -1344,6 +4520,10 @@ mod tests {
     }
 
     #[actix_rt::test]
+    #[cfg_attr(
+        not(all(feature = "executables", feature = "templating")),
+        ignore = "uses executables and templates"
+    )]
     async fn request_headers_are_handled() {
         let request = test_request(&sample_path("executables"), None, None)
             .uri("/render-data")
@@ -1365,6 +4545,10 @@ mod tests {
     }
 
     #[actix_rt::test]
+    #[cfg_attr(
+        not(all(feature = "executables", feature = "templating")),
+        ignore = "uses executables and templates"
+    )]
     async fn request_headers_are_forwarded_to_getted_content() {
         let request = test_request(&sample_path("executables"), None, None)
             .uri("/get-render-data")
@@ -1385,6 +4569,10 @@ mod tests {
     }
 
     #[actix_rt::test]
+    #[cfg_attr(
+        not(all(feature = "executables", feature = "templating")),
+        ignore = "uses executables and templates"
+    )]
     async fn request_headers_are_forwarded_to_error_handler() {
         let request = test_request(
             &sample_path("error-handling"),
@@ -0,0 +1,791 @@
+//! Serves content from AWS Lambda, behind a function URL or an API Gateway
+//! HTTP API (which both send events in payload format version 2.0). This
+//! module is only built with the `lambda` cargo feature.
+//!
+//! Responses are streamed back through the [Lambda runtime
+//! API](https://docs.aws.amazon.com/lambda/latest/dg/runtimes-api.html), so
+//! function URLs should use the `RESPONSE_STREAM` invoke mode. Content is
+//! rendered by the same code as the server, but HTTP-specific features
+//! (precompressed content, byte ranges, entity tags, and the error handler
+//! route) are left to the server.
+
+use crate::bug_message;
+use crate::config::Config;
+use crate::content::*;
+use crate::render::{self, split_extension, RenderOutcome, RenderRequest, Rendered};
+use crate::ServerInfo;
+use bytes::Bytes;
+use futures::executor;
+use futures::stream::{self, TryStreamExt};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::sync::{Arc, RwLock};
+use thiserror::Error;
+
+/// The version of the runtime API that is used.
+const RUNTIME_API_VERSION: &str = "2018-06-01";
+
+#[derive(Error, Debug)]
+pub enum LambdaError {
+    #[error("Unable to connect to the Lambda runtime API at '{}'.", .runtime_api)]
+    ConnectionError {
+        runtime_api: String,
+        source: io::Error,
+    },
+
+    #[error("Failed to communicate with the Lambda runtime API.")]
+    IoError {
+        #[from]
+        source: io::Error,
+    },
+
+    #[error("Unexpected response from the Lambda runtime API: {}", .message)]
+    ProtocolError { message: String },
+
+    #[error("Unable to emit rendered content.")]
+    StreamError {
+        #[from]
+        source: StreamError,
+    },
+}
+
+/// An HTTP request, as Lambda describes it.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Event {
+    pub raw_path: String,
+    pub raw_query_string: String,
+
+    /// Repeated headers are comma-separated.
+    pub headers: HashMap<String, String>,
+
+    /// The parts of the `Cookie` header, which Lambda does not include in
+    /// `headers`.
+    pub cookies: Vec<String>,
+
+    pub request_context: RequestContext,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct RequestContext {
+    pub domain_name: Option<String>,
+    pub http: RequestContextHttp,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct RequestContextHttp {
+    pub method: String,
+}
+
+/// The status and headers which begin a streamed response.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Prelude {
+    status_code: u16,
+    headers: BTreeMap<String, String>,
+    cookies: Vec<String>,
+}
+
+/// Turns Lambda events into responses using the shared renderer.
+pub struct LambdaHandler<Engine> {
+    shared_content_engine: Arc<RwLock<Engine>>,
+    config: Config,
+    index_route: Option<Route>,
+}
+
+impl<Engine> LambdaHandler<Engine>
+where
+    Engine: ContentEngine<ServerInfo>,
+{
+    pub fn new(shared_content_engine: Arc<RwLock<Engine>>, config: Config) -> Self {
+        LambdaHandler {
+            shared_content_engine,
+            config,
+            index_route: None,
+        }
+    }
+
+    /// What to render when the request path is `/`.
+    pub fn with_index_route(mut self, index_route: Route) -> Self {
+        self.index_route = Some(index_route);
+        self
+    }
+
+    /// Renders a response to `event`. Errors become plain text responses.
+    pub fn handle(&self, event: &Event) -> Response<Box<dyn ByteStream>> {
+        let method = event.request_context.http.method.as_str();
+        if method != "GET" && method != "HEAD" {
+            let mut response = text_response(405, "Method not allowed");
+            response
+                .headers
+                .push((String::from("allow"), String::from("GET, HEAD")));
+            return response;
+        }
+
+        let response = self.handle_get(event);
+        if method == "HEAD" {
+            Response {
+                media: Media {
+                    media_type: response.media.media_type,
+                    content: Box::new(stream::empty()),
+                },
+                ..response
+            }
+        } else {
+            response
+        }
+    }
+
+    fn handle_get(&self, event: &Event) -> Response<Box<dyn ByteStream>> {
+        let path = match event.raw_path.as_str() {
+            "" => "/",
+            path => path,
+        };
+        let (path_without_extension, media_range_from_url) = split_extension(path);
+        let (route, media_range_from_url) = match path_without_extension.parse::<Route>() {
+            Err(error) => {
+                return text_response(
+                    400,
+                    &format!(
+                        "Path `{}` could not be parsed into a route: {}",
+                        path, error
+                    ),
+                )
+            }
+            Ok(route) if route.as_ref() == "/" => (self.index_route.clone().unwrap_or(route), None),
+            Ok(route) => (route, media_range_from_url),
+        };
+
+        let query_parameters =
+            match serde_urlencoded::from_str::<Vec<(String, String)>>(&event.raw_query_string) {
+                Ok(pairs) => QueryParameters::new(pairs),
+                Err(error) => {
+                    return text_response(
+                        400,
+                        &format!(
+                            "Malformed query string `{}`: {}",
+                            event.raw_query_string, error
+                        ),
+                    )
+                }
+            };
+
+        let mut request_headers = event
+            .headers
+            .iter()
+            .map(|(name, value)| (name.to_ascii_lowercase(), value.clone()))
+            .collect::<HashMap<_, _>>();
+        if !event.cookies.is_empty() {
+            request_headers.insert(String::from("cookie"), event.cookies.join("; "));
+        }
+
+        let acceptable_media_ranges = match media_range_from_url {
+            Some(ref media_range) => vec![media_range.clone()],
+            None => match render::acceptable_media_ranges(
+                request_headers.get("accept").map_or("", String::as_str),
+            ) {
+                Some(media_ranges) => media_ranges,
+                None => {
+                    return text_response(
+                        400,
+                        &format!(
+                            "Malformed Accept header value `{}`",
+                            request_headers.get("accept").map_or("", String::as_str)
+                        ),
+                    )
+                }
+            },
+        };
+
+        let http_request_data = HttpRequestData {
+            uri_path: Some(String::from(path)),
+            query_string: Some(event.raw_query_string.clone()).filter(|query| !query.is_empty()),
+            scheme: Some(String::from("https")),
+            host: event
+                .request_context
+                .domain_name
+                .clone()
+                .or_else(|| request_headers.get("host").cloned()),
+            // The domain name comes from Lambda rather than the client.
+            origin: event
+                .request_context
+                .domain_name
+                .as_ref()
+                .map(|domain_name| format!("https://{}", domain_name)),
+            path_prefix: None,
+            url_extension: media_range_from_url
+                .as_ref()
+                .and_then(|_| path.rsplit('.').next().map(String::from)),
+            negotiation_source: Some(String::from(if media_range_from_url.is_some() {
+                "url-extension"
+            } else {
+                "accept-header"
+            })),
+        };
+
+        let content_engine = match self.shared_content_engine.read() {
+            Ok(content_engine) => content_engine,
+            Err(_) => {
+                log::error!("RwLock for ContentEngine has been poisoned");
+                return text_response(500, "Content is unavailable");
+            }
+        };
+        let request = RenderRequest::new(route, acceptable_media_ranges)
+            .with_query_parameters(query_parameters)
+            .with_request_headers(request_headers)
+            .with_http_request_data(http_request_data);
+        match render::render_route(&*content_engine, &self.config, &request) {
+            RenderOutcome::Redirect(redirect) => {
+                let mut response = text_response(redirect.status_code(), "");
+                response
+                    .headers
+                    .push((String::from("location"), String::from(redirect.location())));
+                response
+            }
+            RenderOutcome::NotFound => text_response(404, "Not found"),
+            RenderOutcome::NotAcceptable(error) => text_response(
+                406,
+                &format!("Cannot provide an acceptable response: {}", error),
+            ),
+            RenderOutcome::Failed(error) => {
+                log::error!("Failed to render {}: {}", request.route, error);
+                text_response(500, "Failed to render content")
+            }
+            RenderOutcome::Rendered(Rendered { response, .. }) => response,
+        }
+    }
+
+    /// Handles invocations from the Lambda runtime API at `runtime_api` (the
+    /// value of the `AWS_LAMBDA_RUNTIME_API` environment variable) until
+    /// communicating with it fails.
+    pub fn run(&self, runtime_api: &str) -> Result<(), LambdaError> {
+        loop {
+            let (request_id, body) = next_invocation(runtime_api)?;
+            match serde_json::from_slice::<Event>(&body) {
+                Err(error) => {
+                    log::error!("Invalid event for invocation {}: {}", request_id, error);
+                    send_invocation_error(runtime_api, &request_id, "InvalidEvent", &error)?;
+                }
+                Ok(event) => {
+                    let response = self.handle(&event);
+                    send_response(runtime_api, &request_id, response)?;
+                }
+            }
+        }
+    }
+}
+
+/// Writes `response` in the format that Lambda expects for streamed HTTP
+/// responses: a JSON prelude with the status and headers, eight null bytes,
+/// and then the body.
+pub fn write_response<W: Write>(
+    response: Response<Box<dyn ByteStream>>,
+    output: &mut W,
+) -> Result<(), LambdaError> {
+    let mut prelude = Prelude {
+        status_code: response.status,
+        headers: BTreeMap::new(),
+        cookies: Vec::new(),
+    };
+    prelude.headers.insert(
+        String::from("content-type"),
+        response.media.media_type.to_string(),
+    );
+    for (name, value) in response.headers {
+        if name.eq_ignore_ascii_case("set-cookie") {
+            prelude.cookies.push(value);
+        } else {
+            prelude
+                .headers
+                .entry(name.to_ascii_lowercase())
+                .and_modify(|previous_value| {
+                    previous_value.push(',');
+                    previous_value.push_str(&value);
+                })
+                .or_insert(value);
+        }
+    }
+    serde_json::to_writer(&mut *output, &prelude).map_err(io::Error::from)?;
+    output.write_all(&[0; 8])?;
+    output.flush()?;
+
+    executor::block_on(
+        response
+            .media
+            .content
+            .map_err(LambdaError::from)
+            .try_for_each(|bytes| {
+                let result = output
+                    .write_all(&bytes)
+                    .and_then(|_| output.flush())
+                    .map_err(LambdaError::from);
+                async { result }
+            }),
+    )
+}
+
+fn text_response(status: u16, message: &str) -> Response<Box<dyn ByteStream>> {
+    Response {
+        status,
+        headers: Vec::new(),
+        media: Media {
+            media_type: MediaType::from_media_range(mime::TEXT_PLAIN_UTF_8)
+                .expect(bug_message!("text/plain was not a media type.")),
+            content: Box::new(stream::iter(vec![Ok(Bytes::from(String::from(message)))])),
+        },
+    }
+}
+
+fn connect(runtime_api: &str) -> Result<TcpStream, LambdaError> {
+    TcpStream::connect(runtime_api).map_err(|source| LambdaError::ConnectionError {
+        runtime_api: String::from(runtime_api),
+        source,
+    })
+}
+
+/// Waits for the next invocation, returning its request ID and event.
+fn next_invocation(runtime_api: &str) -> Result<(String, Vec<u8>), LambdaError> {
+    let mut connection = connect(runtime_api)?;
+    write!(
+        connection,
+        "GET /{}/runtime/invocation/next HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n\r\n",
+        RUNTIME_API_VERSION, runtime_api
+    )?;
+    let (headers, body) = read_http_response(connection)?;
+    let request_id = headers
+        .get("lambda-runtime-aws-request-id")
+        .cloned()
+        .ok_or_else(|| LambdaError::ProtocolError {
+            message: String::from("The next invocation had no request ID"),
+        })?;
+    Ok((request_id, body))
+}
+
+/// Streams `response` as the result of an invocation. If the content fails
+/// while it is being streamed then the invocation fails too.
+fn send_response(
+    runtime_api: &str,
+    request_id: &str,
+    response: Response<Box<dyn ByteStream>>,
+) -> Result<(), LambdaError> {
+    let mut connection = connect(runtime_api)?;
+    write!(
+        connection,
+        "POST /{}/runtime/invocation/{}/response HTTP/1.1\r\n\
+        Host: {}\r\n\
+        Connection: close\r\n\
+        Content-Type: application/vnd.awslambda.http-integration-response\r\n\
+        Lambda-Runtime-Function-Response-Mode: streaming\r\n\
+        Transfer-Encoding: chunked\r\n\
+        Trailer: Lambda-Runtime-Function-Error-Type, Lambda-Runtime-Function-Error-Body\r\n\r\n",
+        RUNTIME_API_VERSION, request_id, runtime_api
+    )?;
+    let mut chunked_writer = ChunkedWriter(&mut connection);
+    match write_response(response, &mut chunked_writer) {
+        Ok(()) => write!(connection, "0\r\n\r\n")?,
+        Err(LambdaError::StreamError { source }) => {
+            log::error!("Failed to stream response for {}: {}", request_id, source);
+            write!(
+                connection,
+                "0\r\nLambda-Runtime-Function-Error-Type: StreamError\r\n\
+                Lambda-Runtime-Function-Error-Body: {}\r\n\r\n",
+                base64::encode(source.to_string())
+            )?;
+        }
+        Err(error) => return Err(error),
+    }
+    read_http_response(connection).map(|_| ())
+}
+
+fn send_invocation_error(
+    runtime_api: &str,
+    request_id: &str,
+    error_type: &str,
+    error: &dyn std::error::Error,
+) -> Result<(), LambdaError> {
+    let body = serde_json::json!({
+        "errorType": error_type,
+        "errorMessage": error.to_string(),
+    })
+    .to_string();
+    let mut connection = connect(runtime_api)?;
+    write!(
+        connection,
+        "POST /{}/runtime/invocation/{}/error HTTP/1.1\r\n\
+        Host: {}\r\n\
+        Connection: close\r\n\
+        Content-Type: application/json\r\n\
+        Content-Length: {}\r\n\r\n{}",
+        RUNTIME_API_VERSION,
+        request_id,
+        runtime_api,
+        body.len(),
+        body
+    )?;
+    read_http_response(connection).map(|_| ())
+}
+
+/// Reads a response from the runtime API, returning its headers (with
+/// lowercase names) and body. Only successful responses are accepted.
+fn read_http_response<R: Read>(
+    connection: R,
+) -> Result<(HashMap<String, String>, Vec<u8>), LambdaError> {
+    let mut reader = BufReader::new(connection);
+    let mut status_line = String::new();
+    reader.read_line(&mut status_line)?;
+    let status = status_line
+        .split_whitespace()
+        .nth(1)
+        .and_then(|status| status.parse::<u16>().ok());
+
+    let mut headers = HashMap::new();
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            break;
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            headers.insert(name.trim().to_ascii_lowercase(), String::from(value.trim()));
+        }
+    }
+
+    let mut body = Vec::new();
+    match headers.get("transfer-encoding") {
+        Some(encoding) if encoding.eq_ignore_ascii_case("chunked") => {
+            read_chunked_body(&mut reader, &mut body)?
+        }
+        Some(encoding) => {
+            return Err(LambdaError::ProtocolError {
+                message: format!("Unsupported transfer encoding `{}`", encoding),
+            })
+        }
+        None => {
+            match headers
+                .get("content-length")
+                .and_then(|length| length.parse::<u64>().ok())
+            {
+                Some(length) => reader.take(length).read_to_end(&mut body)?,
+                None => reader.read_to_end(&mut body)?,
+            };
+        }
+    }
+
+    match status {
+        Some(200..=299) => Ok((headers, body)),
+        _ => Err(LambdaError::ProtocolError {
+            message: format!(
+                "{} {}",
+                status_line.trim(),
+                String::from_utf8_lossy(&body).trim()
+            ),
+        }),
+    }
+}
+
+/// Reads an HTTP message body with chunked transfer encoding into `body`.
+/// Chunk extensions and trailers are ignored.
+fn read_chunked_body<R: BufRead>(reader: &mut R, body: &mut Vec<u8>) -> Result<(), LambdaError> {
+    let invalid = |message: &str| LambdaError::ProtocolError {
+        message: format!("Invalid chunked response body: {}", message),
+    };
+    loop {
+        let mut size_line = String::new();
+        if reader.read_line(&mut size_line)? == 0 {
+            return Err(invalid("it ended before the last chunk"));
+        }
+        let size = size_line
+            .split(';')
+            .next()
+            .and_then(|size| u64::from_str_radix(size.trim(), 16).ok())
+            .ok_or_else(|| invalid("a chunk size was not a hexadecimal number"))?;
+        if size == 0 {
+            break;
+        }
+        let chunk_length = body.len();
+        reader.take(size).read_to_end(body)?;
+        if ((body.len() - chunk_length) as u64) < size {
+            return Err(invalid("a chunk was shorter than its size"));
+        }
+        let mut line_ending = String::new();
+        reader.read_line(&mut line_ending)?;
+        if !line_ending.trim_end_matches(['\r', '\n']).is_empty() {
+            return Err(invalid("a chunk was longer than its size"));
+        }
+    }
+    // Skip trailers, up to the blank line that ends the message.
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 || line.trim_end().is_empty() {
+            return Ok(());
+        }
+    }
+}
+
+/// Writes each buffer as a chunk of an HTTP message with chunked transfer
+/// encoding. The final (empty) chunk is left to the caller, so that it can
+/// include trailers.
+struct ChunkedWriter<'a, W: Write>(&'a mut W);
+
+impl<W: Write> Write for ChunkedWriter<'_, W> {
+    fn write(&mut self, buffer: &[u8]) -> io::Result<usize> {
+        if !buffer.is_empty() {
+            write!(self.0, "{:x}\r\n", buffer.len())?;
+            self.0.write_all(buffer)?;
+            self.0.write_all(b"\r\n")?;
+        }
+        Ok(buffer.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_lib::*;
+    use std::fs;
+    use std::net::TcpListener;
+    use std::thread;
+    use test_log::test;
+
+    fn event(method: &str, raw_path: &str, headers: &[(&str, &str)]) -> Event {
+        Event {
+            raw_path: String::from(raw_path),
+            headers: headers
+                .iter()
+                .map(|(name, value)| (String::from(*name), String::from(*value)))
+                .collect(),
+            request_context: RequestContext {
+                domain_name: Some(String::from("example.lambda-url.us-east-1.on.aws")),
+                http: RequestContextHttp {
+                    method: String::from(method),
+                },
+            },
+            ..Event::default()
+        }
+    }
+
+    fn body(response: Response<Box<dyn ByteStream>>) -> String {
+        let mut bytes = Vec::new();
+        for result in executor::block_on_stream(response.media.content) {
+            bytes.extend_from_slice(&result.expect("Content could not be streamed"));
+        }
+        String::from_utf8(bytes).expect("Content was not UTF-8")
+    }
+
+    fn test_handler() -> (
+        tempfile::TempDir,
+        LambdaHandler<FilesystemBasedContentEngine<'static, ServerInfo>>,
+    ) {
+        let root = tempfile::tempdir().expect("Failed to create temporary directory");
+        fs::write(root.path().join("hello.txt"), "hello").unwrap();
+        fs::write(root.path().join("hello.html"), "<p>hello</p>").unwrap();
+        fs::write(
+            root.path().join("host.txt.hbs"),
+            "{{request.http.host}} {{request.query-parameters.name}}",
+        )
+        .unwrap();
+        let config = toml::from_str::<Config>(
+            r#"
+            routes."/hello" = { cache-control = "max-age=60" }
+            "#,
+        )
+        .unwrap();
+        let shared_content_engine =
+            FilesystemBasedContentEngine::from_content_directory_with_config(
+                ContentDirectory::from_root(&root.path()).unwrap(),
+                ServerInfo::without_socket_address().unwrap(),
+                config.clone(),
+            )
+            .unwrap();
+        let handler =
+            LambdaHandler::new(shared_content_engine, config).with_index_route(route("/hello"));
+        (root, handler)
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "templating"), ignore = "uses templates")]
+    fn events_are_rendered() {
+        let (_root, handler) = test_handler();
+
+        let response = handler.handle(&event("GET", "/hello", &[("Accept", "text/plain")]));
+        assert_eq!(response.status, 200);
+        assert_eq!(response.media.media_type, mime::TEXT_PLAIN);
+        assert_eq!(response.header("cache-control"), Some("max-age=60"));
+        assert_eq!(body(response), "hello");
+
+        let response = handler.handle(&event(
+            "GET",
+            "/",
+            &[("accept", "text/plain;q=0.5, text/html")],
+        ));
+        assert_eq!(response.media.media_type, mime::TEXT_HTML);
+        assert_eq!(body(response), "<p>hello</p>");
+
+        let response = handler.handle(&event("GET", "/hello.txt", &[("accept", "text/html")]));
+        assert_eq!(body(response), "hello");
+
+        let mut host_event = event("GET", "/host", &[]);
+        host_event.raw_query_string = String::from("name=world");
+        assert_eq!(
+            body(handler.handle(&host_event)),
+            "example.lambda-url.us-east-1.on.aws world"
+        );
+
+        let response = handler.handle(&event("HEAD", "/hello", &[]));
+        assert_eq!(response.status, 200);
+        assert_eq!(body(response), "");
+
+        assert_eq!(handler.handle(&event("GET", "/nope", &[])).status, 404);
+        assert_eq!(handler.handle(&event("POST", "/hello", &[])).status, 405);
+        assert_eq!(
+            handler
+                .handle(&event("GET", "/hello", &[("accept", "image/png")]))
+                .status,
+            406
+        );
+        assert_eq!(
+            handler
+                .handle(&event("GET", "/hello", &[("accept", "*/*;q=0")]))
+                .status,
+            406
+        );
+        assert_eq!(
+            handler
+                .handle(&event(
+                    "GET",
+                    "/hello",
+                    &[("accept", "text/html;q=0, oops, text/plain;q=0.5")]
+                ))
+                .media
+                .media_type,
+            mime::TEXT_PLAIN
+        );
+        assert_eq!(
+            handler
+                .handle(&event("GET", "/hello", &[("accept", "not a media type")]))
+                .status,
+            400
+        );
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "templating"), ignore = "uses templates")]
+    fn responses_are_written_with_a_prelude() {
+        let (_root, handler) = test_handler();
+        let response = handler.handle(&event("GET", "/hello.txt", &[]));
+        let mut output = Vec::new();
+        write_response(response, &mut output).unwrap();
+
+        let separator = output
+            .windows(8)
+            .position(|window| window == [0; 8])
+            .expect("Prelude was not terminated");
+        let prelude = serde_json::from_slice::<serde_json::Value>(&output[..separator]).unwrap();
+        assert_eq!(
+            prelude,
+            serde_json::json!({
+                "statusCode": 200,
+                "headers": {
+                    "cache-control": "max-age=60",
+                    "content-type": "text/plain",
+                },
+                "cookies": [],
+            })
+        );
+        assert_eq!(&output[separator + 8..], b"hello");
+    }
+
+    #[test]
+    fn runtime_api_responses_can_be_chunked() {
+        let (headers, body) = read_http_response(
+            &b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n\
+            5\r\nhello\r\n7;name=value\r\n, world\r\n0\r\nTrailer: yes\r\n\r\n"[..],
+        )
+        .expect("Response could not be read");
+        assert_eq!(headers.get("transfer-encoding").unwrap(), "chunked");
+        assert_eq!(body, b"hello, world");
+
+        for response in [
+            &b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nhello\r\n"[..],
+            b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\nzz\r\nhello\r\n0\r\n\r\n",
+            b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n3\r\nhello\r\n0\r\n\r\n",
+            b"HTTP/1.1 200 OK\r\nTransfer-Encoding: gzip, chunked\r\n\r\n0\r\n\r\n",
+        ] {
+            assert!(
+                read_http_response(response).is_err(),
+                "Response `{}` was read, but it should have failed",
+                String::from_utf8_lossy(response),
+            );
+        }
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "templating"), ignore = "uses templates")]
+    fn invocations_are_handled_through_the_runtime_api() {
+        let (_root, handler) = test_handler();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let runtime_api = listener.local_addr().unwrap().to_string();
+
+        let runtime = thread::spawn(move || {
+            let read_request = |connection: &mut TcpStream| {
+                let mut request = Vec::new();
+                let mut buffer = [0; 1024];
+                // Requests from the handler are complete once they end with
+                // an empty chunk or a blank line after the headers.
+                while !(request.ends_with(b"0\r\n\r\n")
+                    || (request.starts_with(b"GET") && request.ends_with(b"\r\n\r\n")))
+                {
+                    let count = connection.read(&mut buffer).unwrap();
+                    if count == 0 {
+                        break;
+                    }
+                    request.extend_from_slice(&buffer[..count]);
+                }
+                String::from_utf8_lossy(&request).into_owned()
+            };
+
+            let (mut connection, _) = listener.accept().unwrap();
+            let next_request = read_request(&mut connection);
+            let event = r#"{"rawPath":"/hello.txt","requestContext":{"http":{"method":"GET"}}}"#;
+            write!(
+                connection,
+                "HTTP/1.1 200 OK\r\nLambda-Runtime-Aws-Request-Id: abc123\r\nContent-Length: {}\r\n\r\n{}",
+                event.len(),
+                event
+            )
+            .unwrap();
+            drop(connection);
+
+            let (mut connection, _) = listener.accept().unwrap();
+            let response_request = read_request(&mut connection);
+            write!(
+                connection,
+                "HTTP/1.1 202 Accepted\r\nContent-Length: 0\r\n\r\n"
+            )
+            .unwrap();
+            (next_request, response_request)
+            // The listener is dropped here, so the next invocation cannot be
+            // fetched.
+        });
+
+        let result = handler.run(&runtime_api);
+        let (next_request, response_request) = runtime.join().unwrap();
+        assert!(result.is_err(), "Unexpected result: {:?}", result);
+        assert!(next_request.starts_with("GET /2018-06-01/runtime/invocation/next HTTP/1.1\r\n"));
+        assert!(response_request
+            .starts_with("POST /2018-06-01/runtime/invocation/abc123/response HTTP/1.1\r\n"));
+        assert!(response_request.contains("Lambda-Runtime-Function-Response-Mode: streaming\r\n"));
+        assert!(response_request.contains("\r\n5\r\nhello\r\n0\r\n\r\n"));
+    }
+}
@@ -5,9 +5,24 @@ use std::net::{SocketAddr, ToSocketAddrs};
 use std::path::PathBuf;
 use thiserror::Error;
 
+#[cfg(feature = "server")]
+mod bulkhead;
+#[cfg(feature = "cli")]
 pub mod cli;
+pub mod clock;
+pub mod config;
 pub mod content;
+#[cfg(feature = "server")]
+mod dev_error_page;
+pub mod file_limit;
+#[cfg(feature = "server")]
 pub mod http;
+#[cfg(feature = "lambda")]
+pub mod lambda;
+pub mod logging;
+pub mod render;
+pub mod scheduler;
+pub mod testing;
 
 #[doc(hidden)]
 pub mod test_lib;
@@ -35,7 +50,11 @@ pub struct ServerInfo {
 }
 
 impl ServerInfo {
-    fn with_socket_address<A: 'static + ToSocketAddrs>(
+    const VERSION_PLACEHOLDER: &'static str = "0.0.0";
+    const OPERATOR_PATH_PLACEHOLDER: &'static str = "operator";
+
+    /// Info for a server listening on `socket_address`.
+    pub fn with_socket_address<A: 'static + ToSocketAddrs>(
         socket_address: &A,
     ) -> Result<Self, ServerInfoError> {
         Ok(ServerInfo {
@@ -45,13 +64,31 @@ impl ServerInfo {
             socket_address: socket_address.to_socket_addrs()?.next(),
         })
     }
-    fn without_socket_address() -> Result<Self, ServerInfoError> {
+
+    /// Info for rendering content without a server, as the command line does.
+    pub fn without_socket_address() -> Result<Self, ServerInfoError> {
         Ok(ServerInfo {
             version: VERSION,
             operator_path: env::current_exe()?,
             socket_address: None,
         })
     }
+
+    /// This info with placeholders for anything that varies between
+    /// machines or runs, if `config` asks for
+    /// [deterministic](config::Config::deterministic) rendering.
+    pub fn for_config(self, config: &config::Config) -> Self {
+        if !config.deterministic {
+            return self;
+        }
+        ServerInfo {
+            version: ServerVersion(Self::VERSION_PLACEHOLDER),
+            operator_path: PathBuf::from(Self::OPERATOR_PATH_PLACEHOLDER),
+            socket_address: self
+                .socket_address
+                .map(|_| SocketAddr::from(([0, 0, 0, 0], 0))),
+        }
+    }
 }
 
 #[doc(hidden)]
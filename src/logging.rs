@@ -0,0 +1,102 @@
+//! Logging which can be more or less verbose for specific routes (see
+//! [`RouteConfig::log_level`](crate::config::RouteConfig::log_level)).
+
+use log::{LevelFilter, Log, Metadata, Record, SetLoggerError};
+
+/// Messages about requests to routes which have their own `log-level` are
+/// logged with this target. Their route has already decided whether they
+/// should be logged, so they bypass the global level.
+pub const ROUTE_LOG_TARGET: &str = "operator::route";
+
+/// Passes messages up to `level` (and all messages for [`ROUTE_LOG_TARGET`])
+/// through to another logger.
+pub struct RouteAwareLogger<L: Log> {
+    inner: L,
+    level: LevelFilter,
+}
+
+impl<L: Log> RouteAwareLogger<L> {
+    /// `inner` should accept every message it is given; filtering happens
+    /// here instead.
+    pub fn new(inner: L, level: LevelFilter) -> Self {
+        RouteAwareLogger { inner, level }
+    }
+}
+
+impl<L: Log> Log for RouteAwareLogger<L> {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level || metadata.target() == ROUTE_LOG_TARGET
+    }
+
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            self.inner.log(record);
+        }
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+    }
+}
+
+/// Installs `logger` as the global logger. Use [`allow_route_log_level`]
+/// once route settings are known.
+pub fn init<L: 'static + Log>(logger: RouteAwareLogger<L>) -> Result<(), SetLoggerError> {
+    log::set_max_level(logger.level);
+    log::set_boxed_logger(Box::new(logger))
+}
+
+/// Makes sure that messages as verbose as `route_log_level` are not
+/// discarded before they reach the logger. Messages are never enabled if the
+/// global level is `Off` (e.g. via `--quiet`).
+pub fn allow_route_log_level(route_log_level: LevelFilter) {
+    if log::max_level() != LevelFilter::Off {
+        log::set_max_level(log::max_level().max(route_log_level));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use log::Level;
+    use std::sync::Mutex;
+    use test_log::test;
+
+    #[derive(Default)]
+    struct RecordingLogger(Mutex<Vec<String>>);
+
+    impl Log for RecordingLogger {
+        fn enabled(&self, _: &Metadata) -> bool {
+            true
+        }
+
+        fn log(&self, record: &Record) {
+            self.0.lock().unwrap().push(record.args().to_string());
+        }
+
+        fn flush(&self) {}
+    }
+
+    #[test]
+    fn route_messages_bypass_the_global_level() {
+        let logger = RouteAwareLogger::new(RecordingLogger::default(), LevelFilter::Warn);
+        let log = |target, level, message| {
+            logger.log(
+                &Record::builder()
+                    .target(target)
+                    .level(level)
+                    .args(format_args!("{}", message))
+                    .build(),
+            )
+        };
+
+        log("operator::http", Level::Warn, "warning");
+        log("operator::http", Level::Debug, "debug");
+        log(ROUTE_LOG_TARGET, Level::Trace, "route trace");
+
+        assert_eq!(
+            *logger.inner.0.lock().unwrap(),
+            vec!["warning", "route trace"]
+        );
+    }
+}
@@ -1,16 +1,37 @@
 use anyhow::Context;
 use clap::{Parser, Subcommand};
-use operator::content::{ContentDirectory, MediaRange, Route};
+use globset::Glob;
+use log::LevelFilter;
+use operator::cli::{ErrorFormat, ErrorReport, RenderAllFormat};
+use operator::config::{Config, ConfigError, ContentConfig, TimeoutsConfig};
+use operator::content::{
+    ContentDirectory, ContentDirectoryFromRootError, ContentLoadingErrors, MediaRange, RenderError,
+    Route, StreamError, TemplateError,
+};
 use operator::http::QueryString;
 use operator::*;
+use std::collections::HashMap;
 use std::fs;
-use std::io;
+use std::io::{self, IsTerminal};
 use std::net::SocketAddr;
 use std::path::{Path, PathBuf};
 use std::process;
 
 #[derive(Parser)]
-#[command(version, about, propagate_version = true)]
+#[command(
+    version,
+    about,
+    propagate_version = true,
+    after_help = "Exit codes:
+  0  Success
+  1  Any other failure
+  2  Invalid arguments
+  3  The config file could not be used
+  4  The content directory could not be loaded or has problems
+  5  Content failed to render
+  6  Content failed while it was being written out
+  7  The server could not bind to its socket address"
+)]
 struct OperatorCommand {
     /// Silence all output.
     #[arg(short, long, global = true)]
@@ -20,13 +41,81 @@ struct OperatorCommand {
     #[arg(short, long, global = true, action = clap::ArgAction::Count)]
     verbose: u8,
 
+    /// Path to a TOML file containing additional settings.
+    #[arg(long, global = true, value_name = "path")]
+    config: Option<PathBuf>,
+
+    /// Applies one of the `[profiles]` from the config file.
+    ///
+    /// For example: --profile=dev. A profile's settings override the rest of
+    /// the file, so one config file can serve both local authoring and
+    /// deployment.
+    #[arg(long, global = true, value_name = "name", env = "OPERATOR_PROFILE")]
+    profile: Option<String>,
+
+    /// Skip content files that cannot be served instead of failing.
+    ///
+    /// Files with unknown media types or unsupported names are logged and
+    /// left out. Equivalent to `lenient = true` in the `[content]` section of
+    /// the config file.
+    #[arg(long, global = true)]
+    lenient: bool,
+
+    /// Include drafts and content with a future publish date.
+    ///
+    /// Templates with `draft: true` or a `publish-date` in the future in
+    /// their front matter are otherwise treated as if they did not exist.
+    /// Equivalent to `include-drafts = true` in the `[content]` section of
+    /// the config file.
+    #[arg(long, global = true)]
+    include_drafts: bool,
+
+    /// Sets an environment variable for executables in the content directory.
+    ///
+    /// This can be repeated. For example: --executable-env=API_URL=https://...
+    /// Variables can also be set in the `[executables.env]` section of the
+    /// config file.
+    #[arg(long, global = true, value_name = "key=value", value_parser = parse_key_value)]
+    executable_env: Vec<(String, String)>,
+
+    /// The absolute URL where the site is hosted.
+    ///
+    /// For example: --base-url=https://example.com. Content uses this to
+    /// create absolute links (via `request.base-url` or the `absolute-url`
+    /// helper). Overrides `base-url` in the config file.
+    #[arg(long, global = true, value_name = "url", value_parser = config::parse_base_url)]
+    base_url: Option<String>,
+
+    /// Reads static files up to this size into memory when they are loaded.
+    ///
+    /// For example: --preload-max-bytes=64k. Sizes can end in `k`, `m`, or
+    /// `g` (powers of 1024). Overrides `preload-max-bytes` in the `[content]`
+    /// section of the config file.
+    #[arg(long, global = true, value_name = "size", value_parser = parse_byte_size)]
+    preload_max_bytes: Option<u64>,
+
+    /// Serves another content directory beneath a route prefix.
+    ///
+    /// This can be repeated. For example: --mount=/docs:/path/to/docs
+    /// serves `/path/to/docs/guide.html` at `/docs/guide`. Content in the
+    /// mounted directory can find its prefix in `request.mount-prefix`.
+    #[arg(long = "mount", global = true, value_name = "route:path", value_parser = parse_mount)]
+    mounts: Vec<(Route, PathBuf)>,
+
+    /// How to write errors to stderr.
+    ///
+    /// With `json`, each problem is written as a JSON object on its own line,
+    /// with the fields `kind`, `route`, `file`, `message`, and `sources`.
+    #[arg(long, global = true, value_name = "format", default_value = "text")]
+    error_format: ErrorFormat,
+
     #[command(subcommand)]
     subcommand: OperatorSubcommand,
 }
 
 #[derive(Subcommand)]
 enum OperatorSubcommand {
-    /// Evaluates a handlebars template from STDIN.
+    /// Evaluates a handlebars template from STDIN (or --template-file).
     Eval {
         /// Path to a directory containing content files.
         ///
@@ -41,6 +130,33 @@ enum OperatorSubcommand {
         /// For example: --query="a=1&b=2".
         #[arg(long, value_name = "query-string")]
         query: Option<QueryString>,
+
+        /// Simulates a request header, as in "Name: value".
+        ///
+        /// This can be repeated. Content can read headers from
+        /// `request.request-headers` (with lowercase names), just like when
+        /// it is served.
+        #[arg(long = "header", value_name = "header", value_parser = parse_header)]
+        headers: Vec<(String, String)>,
+
+        /// Declares what type of media the template produces.
+        ///
+        /// If this is a specific media type the template is rendered as that
+        /// type (which affects things like escaping). Defaults to "*/*".
+        #[arg(long, value_name = "media-range")]
+        accept: Option<MediaRange>,
+
+        /// Path to a file containing the template to render, instead of
+        /// reading it from stdin.
+        #[arg(long, value_name = "path")]
+        template_file: Option<PathBuf>,
+
+        /// Adds a string to the `data` available to the template.
+        ///
+        /// This can be repeated. For example: --data=title=Hello makes
+        /// `data.title` render as "Hello".
+        #[arg(long, value_name = "key=value", value_parser = parse_key_value)]
+        data: Vec<(String, String)>,
     },
 
     /// Renders content from a content directory.
@@ -54,9 +170,10 @@ enum OperatorSubcommand {
         /// Route specifying which piece of content to get.
         ///
         /// Routes are extension-less slash-delimited paths rooted in the
-        /// content directory. They must begin with a slash.
-        #[clap(long, value_name = "route")]
-        route: Route,
+        /// content directory. They must begin with a slash. This can be
+        /// repeated when --output-dir is used.
+        #[clap(long = "route", value_name = "route", required = true)]
+        routes: Vec<Route>,
 
         /// Optional query parameters.
         ///
@@ -65,6 +182,14 @@ enum OperatorSubcommand {
         #[clap(long, value_name = "query-string")]
         query: Option<QueryString>,
 
+        /// Simulates a request header, as in "Name: value".
+        ///
+        /// This can be repeated. Content can read headers from
+        /// `request.request-headers` (with lowercase names), just like when
+        /// it is served.
+        #[clap(long = "header", value_name = "header", value_parser = parse_header)]
+        headers: Vec<(String, String)>,
+
         /// Declares what types of media are acceptable as output.
         ///
         /// This serves the same purpose as the HTTP Accept header: to drive
@@ -72,16 +197,132 @@ enum OperatorSubcommand {
         /// media range. Defaults to "*/*".
         #[clap(long, value_name = "media-range")]
         accept: Option<MediaRange>,
+
+        /// Writes content to files in this directory instead of stdout.
+        ///
+        /// Each file is named after its route, with the filename extension of
+        /// the media type it was rendered as. For example, `/blog/post`
+        /// rendered as HTML is written to `blog/post.html`.
+        #[clap(long, value_name = "path")]
+        output_dir: Option<PathBuf>,
+    },
+
+    /// Renders everything in a content directory.
+    ///
+    /// Each content file is rendered as the media type of its first filename
+    /// extension and written to STDOUT, either as a tar archive or as a
+    /// sequence of NUL-delimited records (route, media type, and byte length,
+    /// followed by the rendered content).
+    RenderAll {
+        /// Path to a directory containing content files.
+        #[clap(long, value_name = "path")]
+        content_directory: PathBuf,
+
+        /// Only render content whose route matches this glob pattern.
+        ///
+        /// For example: --routes="/blog/**".
+        #[clap(long, value_name = "glob")]
+        routes: Option<Glob>,
+
+        /// Only render content whose media type is within this media range.
+        #[clap(long, value_name = "media-range")]
+        accept: Option<MediaRange>,
+
+        /// How to write the rendered content.
+        #[clap(long, value_enum, default_value_t)]
+        format: RenderAllFormat,
+    },
+
+    /// Lists the routes in a content directory.
+    ///
+    /// Each route is printed on its own line, followed by the media types it
+    /// can be rendered as.
+    Routes {
+        /// Path to a directory containing content files.
+        #[clap(long, value_name = "path")]
+        content_directory: PathBuf,
+
+        /// Print which routes include which others instead, in Graphviz DOT
+        /// format.
+        ///
+        /// Routes include others via partials and helpers like `get` and
+        /// `layout`. Only routes which are written literally in templates
+        /// are found.
+        #[clap(long)]
+        graph: bool,
+    },
+
+    /// Reports all problems with a content directory.
+    ///
+    /// Each problem is printed on its own line, prefixed with the path of the
+    /// content file that caused it. Exits unsuccessfully if there are any.
+    Check {
+        /// Path to a directory containing content files.
+        #[clap(long, value_name = "path")]
+        content_directory: PathBuf,
+
+        /// Run each executable to make sure that it can be.
+        ///
+        /// Executables are run once with OPERATOR_VALIDATE=1 in their
+        /// environment, and should exit successfully without doing any real
+        /// work when it is set. This catches problems like missing
+        /// interpreters which would otherwise only be noticed when the
+        /// executable is requested.
+        #[clap(long)]
+        probe_executables: bool,
+
+        /// Render every route to make sure that it can be.
+        ///
+        /// Each route is rendered as each of its media types with no query
+        /// parameters. This catches problems like template runtime errors and
+        /// executables which exit unsuccessfully.
+        #[clap(long)]
+        render_check: bool,
+    },
+
+    /// Measures how quickly content is served.
+    ///
+    /// This starts a server on a local port, sends it many requests, and
+    /// reports throughput and latency percentiles for each route.
+    Bench {
+        /// Path to a directory containing content files.
+        #[clap(long, value_name = "path")]
+        content_directory: PathBuf,
+
+        /// Route to send requests to.
+        ///
+        /// This can be repeated. If omitted, every route in the content
+        /// directory is tested.
+        #[clap(long, value_name = "route")]
+        route: Vec<Route>,
+
+        /// How many requests to send to each route.
+        #[clap(long, value_name = "count", default_value_t = 100)]
+        requests: usize,
+
+        /// How many requests to have in flight at once.
+        #[clap(long, value_name = "count", default_value_t = 10)]
+        concurrency: usize,
     },
 
     /// Starts an HTTP server.
     Serve {
         /// Path to a directory containing content files.
         ///
-        /// This directory is used to create the website.
+        /// This directory is used to create the website. It can be a symlink
+        /// which deploy tooling repoints to new releases; it is resolved
+        /// again each time content is reloaded (on SIGHUP).
         #[clap(long, value_name = "path")]
         content_directory: PathBuf,
 
+        /// Serves another content directory for requests to a host name.
+        ///
+        /// For example: --vhost=example.com:/path/to/content. This can be
+        /// repeated to serve multiple sites. Requests whose `Host` header
+        /// does not match any of these are served from --content-directory.
+        #[clap(long = "vhost", value_name = "host:path", value_parser = parse_virtual_host)]
+        virtual_hosts: Vec<(String, PathBuf)>,
+
         /// What to serve when the request URI has an empty path.
         ///
         /// A request for http://mysite.com/ gets a response from this route.
@@ -95,17 +336,117 @@ enum OperatorSubcommand {
         /// this route is used to create the response. The HTTP status code can
         /// be obtained from the `error-code` render parameter.
         ///
-        /// If the error handler itself fails then a default error message is
+        /// If this option is not set, clients which prefer HTML get a minimal
+        /// built-in error page and others get the status as plain text. If
+        /// the error handler itself fails then a default error message is
         /// used.
         #[clap(long, value_name = "route")]
         error_handler_route: Option<Route>,
 
+        /// Content to render before the server starts.
+        ///
+        /// This fills the output caches of executables (see `cache-ttl`),
+        /// including executables that templates `get`, so that the first
+        /// requests are not slow. Other content is not cached, so routes
+        /// which cannot fill any cache are skipped. Multiple routes can be
+        /// separated by commas. Templates can also be prerendered by setting
+        /// `prerender: true` in their front matter.
+        #[clap(long, value_name = "routes", value_delimiter = ',')]
+        prerender: Vec<Route>,
+
+        /// Run each executable before the server starts.
+        ///
+        /// This works like the option of the same name for `check`, and the
+        /// server does not start if any executables fail.
+        #[clap(long)]
+        probe_executables: bool,
+
+        /// Render every route before the server starts.
+        ///
+        /// This works like the option of the same name for `check`, and the
+        /// server does not start if anything fails to render.
+        #[clap(long)]
+        render_check: bool,
+
+        /// The URL path that the site is served under.
+        ///
+        /// Use this when a reverse proxy forwards requests for (for example)
+        /// `/app/page` to `/app/page` on this server. Content can find the
+        /// prefix in `request.http.path-prefix`. Overrides `path-prefix` in the
+        /// config file.
+        #[clap(long, value_name = "path")]
+        path_prefix: Option<String>,
+
+        /// How many seconds a response can take from start to finish.
+        ///
+        /// Responses which are not ready in time get a 503, and bodies which
+        /// are still streaming are cut off. Overrides `request` in the
+        /// `[timeouts]` section of the config file.
+        #[clap(long, value_name = "seconds")]
+        request_timeout: Option<u64>,
+
+        /// How many seconds clients have to send their request headers.
+        ///
+        /// Slower clients are disconnected. Overrides `client-request` in the
+        /// `[timeouts]` section of the config file.
+        #[clap(long, value_name = "seconds")]
+        client_timeout: Option<u64>,
+
+        /// How many bytes of a static file are read and sent at a time.
+        ///
+        /// Accepts suffixes like 64k. Overrides `file-chunk-size` in the
+        /// `[content]` section of the config file.
+        #[clap(long, value_name = "size", value_parser = parse_byte_size)]
+        file_chunk_size: Option<u64>,
+
+        /// How many bytes of an executable's output are read at a time.
+        ///
+        /// The buffer starts at this size and grows for executables which
+        /// keep filling it. Accepts suffixes like 4k. Overrides `chunk-size`
+        /// in the `[executables]` section of the config file.
+        #[clap(long, value_name = "size", value_parser = parse_byte_size)]
+        executable_chunk_size: Option<u64>,
+
+        /// Show details about failed renders in error responses.
+        ///
+        /// Failed renders respond with a page showing the chain of errors,
+        /// the template or executable that failed, and the render data (with
+        /// anything that looks secret redacted). Never use this for a public
+        /// site. Overrides `dev` in the config file.
+        #[clap(long)]
+        dev: bool,
+
+        /// Print the effective configuration and exit instead of serving.
+        ///
+        /// This is the config file merged with command line options and
+        /// defaults, written as TOML (with secrets redacted). The same
+        /// settings are logged at the info level when the server starts.
+        #[clap(long)]
+        print_config: bool,
+
         /// The TCP address/port that the server should bind to.
         ///
         /// This is an IP address and port number. For example, "127.0.0.1:80".
         #[clap(long, value_name = "socket-address")]
         bind_to: SocketAddr,
     },
+
+    /// Serves content from AWS Lambda.
+    ///
+    /// This handles invocations from the Lambda runtime API (found using the
+    /// AWS_LAMBDA_RUNTIME_API environment variable), so it should be the
+    /// entrypoint of a Lambda function behind a function URL (with the
+    /// RESPONSE_STREAM invoke mode) or an API Gateway HTTP API.
+    #[cfg(feature = "lambda")]
+    Lambda {
+        /// Path to a directory containing content files.
+        #[clap(long, value_name = "path")]
+        content_directory: PathBuf,
+
+        /// What to serve when the request URI has an empty path.
+        #[clap(long, value_name = "route")]
+        index_route: Option<Route>,
+    },
 }
 
 fn main() {
@@ -116,27 +457,120 @@ fn main() {
     let mut input = stdin.lock();
     let mut output = stdout.lock();
 
-    let result = stderrlog::new()
-        .quiet(command.quiet)
-        .verbosity(usize::from(command.verbose))
-        .timestamp(stderrlog::Timestamp::Millisecond)
-        .init()
-        .map_err(anyhow::Error::from)
-        .and_then(|()| handle_subcommand(command.subcommand, &mut input, &mut output));
+    let quiet = command.quiet;
+    let error_format = command.error_format;
+    let route = match &command.subcommand {
+        OperatorSubcommand::Get { routes, .. } if routes.len() == 1 => Some(routes[0].clone()),
+        _ => None,
+    };
+
+    let result = init_logger(command.quiet, command.verbose)
+        .map(|()| file_limit::raise_soft_limit())
+        .and_then(|()| get_config(command.config, command.profile.as_deref()))
+        .map(|mut config| {
+            logging::allow_route_log_level(config.max_route_log_level());
+            config.content.lenient |= command.lenient;
+            config.content.include_drafts |= command.include_drafts;
+            config.executables.env.extend(command.executable_env);
+            if command.base_url.is_some() {
+                config.base_url = command.base_url;
+            }
+            if let Some(preload_max_bytes) = command.preload_max_bytes {
+                config.content.preload_max_bytes = preload_max_bytes;
+            }
+            config
+        })
+        .and_then(|config| {
+            handle_subcommand(
+                command.subcommand,
+                &command.mounts,
+                config,
+                &mut input,
+                &mut output,
+            )
+        });
 
     match result {
         Err(error) => {
-            log::error!("{:?}", error);
-            process::exit(1);
+            match error_format {
+                ErrorFormat::Text => log::error!("{:?}", error),
+                ErrorFormat::Json if quiet => {}
+                ErrorFormat::Json => {
+                    for mut report in ErrorReport::all_from(error.as_ref()) {
+                        report.route = report.route.or_else(|| route.clone());
+                        eprintln!(
+                            "{}",
+                            serde_json::to_string(&report)
+                                .expect("Error report could not be serialized")
+                        );
+                    }
+                }
+            }
+            process::exit(ExitCode::of(&error) as i32);
         }
         Ok(_) => {
-            process::exit(0);
+            process::exit(ExitCode::Success as i32);
         }
     }
 }
 
+/// How the process exits, so that scripts can tell different kinds of
+/// failure apart. Invalid arguments are handled by clap, which exits with 2.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ExitCode {
+    Success = 0,
+    Failure = 1,
+    ConfigError = 3,
+    ContentLoadingError = 4,
+    RenderError = 5,
+    StreamError = 6,
+    ServerBindError = 7,
+}
+
+impl ExitCode {
+    /// Classifies `error` by the first of its causes (from outermost to
+    /// innermost) which has a dedicated exit code.
+    fn of(error: &anyhow::Error) -> Self {
+        error
+            .chain()
+            .find_map(|cause| {
+                if cause.is::<ConfigError>() {
+                    Some(ExitCode::ConfigError)
+                } else if cause.is::<ContentLoadingErrors>()
+                    || cause.is::<ContentDirectoryFromRootError>()
+                    || matches!(
+                        cause.downcast_ref(),
+                        Some(cli::CheckCommandError::InvalidContent { .. })
+                    )
+                {
+                    Some(ExitCode::ContentLoadingError)
+                } else if cause.is::<RenderError>()
+                    || cause.is::<TemplateError>()
+                    || matches!(
+                        cause.downcast_ref(),
+                        Some(cli::GetCommandError::ErrorStatus { .. })
+                    )
+                {
+                    Some(ExitCode::RenderError)
+                } else if cause.is::<StreamError>() {
+                    Some(ExitCode::StreamError)
+                } else if matches!(
+                    cause.downcast_ref(),
+                    Some(cli::ServeCommandError::BindError { .. })
+                ) {
+                    Some(ExitCode::ServerBindError)
+                } else {
+                    None
+                }
+            })
+            .unwrap_or(ExitCode::Failure)
+    }
+}
+
 fn handle_subcommand<I: io::Read, O: io::Write>(
     subcommand: OperatorSubcommand,
+    mounts: &[(Route, PathBuf)],
+    mut config: Config,
     input: &mut I,
     output: &mut O,
 ) -> Result<(), anyhow::Error> {
@@ -144,47 +578,319 @@ fn handle_subcommand<I: io::Read, O: io::Write>(
         OperatorSubcommand::Eval {
             content_directory,
             query,
-        } => cli::eval(
-            get_content_directory(content_directory)?,
+            headers,
+            accept,
+            template_file,
+            data,
+        } => {
+            let mut file;
+            let mut input: &mut dyn io::Read = match template_file {
+                None => input,
+                Some(path) => {
+                    file = fs::File::open(&path).with_context(|| {
+                        format!("Cannot read template file '{}'.", path.display())
+                    })?;
+                    &mut file
+                }
+            };
+            cli::eval(
+                get_content_directory(content_directory, mounts, &config.content)?,
+                query,
+                request_headers(headers),
+                accept,
+                data,
+                config,
+                &mut input,
+                output,
+            )
+            .map_err(anyhow::Error::from)
+        }
+
+        OperatorSubcommand::Get {
+            content_directory,
+            routes,
             query,
-            input,
+            headers,
+            accept,
+            output_dir,
+        } => match (&routes[..], output_dir) {
+            (_, Some(output_dir)) => cli::get_to_directory(
+                get_content_directory(content_directory, mounts, &config.content)?,
+                &routes,
+                query,
+                request_headers(headers),
+                accept,
+                config,
+                &output_dir,
+            )
+            .map_err(anyhow::Error::from),
+            ([route], None) => cli::get(
+                get_content_directory(content_directory, mounts, &config.content)?,
+                route,
+                query,
+                request_headers(headers),
+                accept,
+                config,
+                output,
+            )
+            .map_err(anyhow::Error::from),
+            (_, None) => Err(anyhow::anyhow!(
+                "Getting more than one route requires --output-dir."
+            )),
+        },
+
+        OperatorSubcommand::RenderAll {
+            content_directory,
+            routes,
+            accept,
+            format,
+        } => cli::render_all(
+            get_content_directory(content_directory, mounts, &config.content)?,
+            routes,
+            accept,
+            format,
+            config,
             output,
         )
         .map_err(anyhow::Error::from),
 
-        OperatorSubcommand::Get {
+        OperatorSubcommand::Routes {
+            content_directory,
+            graph,
+        } => cli::routes(
+            get_content_directory(content_directory, mounts, &config.content)?,
+            graph,
+            config,
+            output,
+        )
+        .map_err(anyhow::Error::from),
+
+        OperatorSubcommand::Check {
+            content_directory,
+            probe_executables,
+            render_check,
+        } => cli::check(
+            get_content_directory(content_directory, mounts, &config.content)?,
+            cli::ContentChecks {
+                probe_executables,
+                render: render_check,
+            },
+            config,
+            output,
+        )
+        .map_err(anyhow::Error::from),
+
+        OperatorSubcommand::Bench {
             content_directory,
             route,
-            query,
-            accept,
-        } => cli::get(
-            get_content_directory(content_directory)?,
-            &route,
-            query,
-            accept,
+            requests,
+            concurrency,
+        } => cli::bench(
+            get_content_directory(content_directory, mounts, &config.content)?,
+            route,
+            requests,
+            concurrency,
+            config,
             output,
         )
         .map_err(anyhow::Error::from),
 
         OperatorSubcommand::Serve {
             content_directory,
+            virtual_hosts,
             index_route,
             error_handler_route,
+            prerender,
+            probe_executables,
+            render_check,
+            path_prefix,
+            request_timeout,
+            client_timeout,
+            file_chunk_size,
+            executable_chunk_size,
+            dev,
+            print_config,
             bind_to,
-        } => cli::serve(
-            get_content_directory(content_directory)?,
+        } => {
+            if let Some(file_chunk_size) = file_chunk_size {
+                config.content.file_chunk_size = file_chunk_size;
+            }
+            if let Some(executable_chunk_size) = executable_chunk_size {
+                config.executables.chunk_size =
+                    usize::try_from(executable_chunk_size).unwrap_or(usize::MAX);
+            }
+            let config = Config {
+                path_prefix: path_prefix.or(config.path_prefix),
+                timeouts: TimeoutsConfig {
+                    request: request_timeout.unwrap_or(config.timeouts.request),
+                    client_request: client_timeout.unwrap_or(config.timeouts.client_request),
+                    ..config.timeouts
+                },
+                dev: dev || config.dev,
+                ..config
+            };
+            if print_config {
+                let config = config
+                    .to_toml()
+                    .context("The configuration could not be printed.")?;
+                return output
+                    .write_all(config.as_bytes())
+                    .context("Failed to write output.");
+            }
+            cli::serve(
+                get_content_directory(content_directory, mounts, &config.content)?,
+                virtual_hosts
+                    .into_iter()
+                    .map(|(host_name, path)| {
+                        get_content_directory(path, &[], &config.content)
+                            .map(|content_directory| (host_name, content_directory))
+                    })
+                    .collect::<Result<Vec<_>, _>>()?,
+                index_route,
+                error_handler_route,
+                prerender,
+                cli::ContentChecks {
+                    probe_executables,
+                    render: render_check,
+                },
+                bind_to,
+                config,
+            )
+            .map_err(anyhow::Error::from)
+        }
+
+        #[cfg(feature = "lambda")]
+        OperatorSubcommand::Lambda {
+            content_directory,
             index_route,
-            error_handler_route,
-            bind_to,
+        } => cli::lambda(
+            get_content_directory(content_directory, mounts, &config.content)?,
+            index_route,
+            config,
         )
         .map_err(anyhow::Error::from),
     }
 }
 
-fn get_content_directory<P: AsRef<Path>>(path: P) -> Result<ContentDirectory, anyhow::Error> {
+fn get_content_directory<P: AsRef<Path>>(
+    path: P,
+    mounts: &[(Route, PathBuf)],
+    config: &ContentConfig,
+) -> Result<ContentDirectory, anyhow::Error> {
     let path = path.as_ref();
-    let canonical_path = &fs::canonicalize(path)
+    // Symlinks are not resolved here, so that reloading can follow one that
+    // has been repointed to a new release.
+    let absolute_path = &std::path::absolute(path)
         .with_context(|| format!("Cannot use '{}' as a content directory.", path.display()))?;
-    let content_directory = ContentDirectory::from_root(canonical_path)?;
+    let mut content_directory = ContentDirectory::from_root_with_config(absolute_path, config)?;
+    for (prefix, mounted_path) in mounts {
+        let mounted_directory = get_content_directory(mounted_path, &[], config)?;
+        content_directory.mount(prefix.clone(), mounted_directory)?;
+    }
     Ok(content_directory)
 }
+
+/// Parses a size like "65536", "64k", or "1m" into a number of bytes.
+fn parse_byte_size(input: &str) -> Result<u64, String> {
+    let input = input.trim();
+    let (digits, multiplier) = match input.char_indices().last() {
+        Some((index, 'k' | 'K')) => (&input[..index], 1024),
+        Some((index, 'm' | 'M')) => (&input[..index], 1024 * 1024),
+        Some((index, 'g' | 'G')) => (&input[..index], 1024 * 1024 * 1024),
+        _ => (input, 1),
+    };
+    digits
+        .parse::<u64>()
+        .ok()
+        .and_then(|count| count.checked_mul(multiplier))
+        .ok_or_else(|| String::from("expected a number of bytes (like 65536 or 64k)"))
+}
+
+fn parse_key_value(input: &str) -> Result<(String, String), String> {
+    match input.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((String::from(key), String::from(value))),
+        _ => Err(String::from("expected key=value")),
+    }
+}
+
+/// Parses a header like "Name: value" into its lowercased name and value.
+fn parse_header(input: &str) -> Result<(String, String), String> {
+    match input.split_once(':') {
+        Some((name, value)) if !name.trim().is_empty() => {
+            Ok((name.trim().to_ascii_lowercase(), String::from(value.trim())))
+        }
+        _ => Err(String::from("expected Name: value")),
+    }
+}
+
+/// Combines repeated headers into one comma-separated value, like the server
+/// does.
+fn request_headers(headers: Vec<(String, String)>) -> HashMap<String, String> {
+    let mut request_headers = HashMap::<String, String>::new();
+    for (name, value) in headers {
+        request_headers
+            .entry(name)
+            .and_modify(|combined_value| {
+                combined_value.push(',');
+                combined_value.push_str(&value);
+            })
+            .or_insert(value);
+    }
+    request_headers
+}
+
+fn parse_mount(input: &str) -> Result<(Route, PathBuf), String> {
+    match input.split_once(':') {
+        Some((prefix, path)) if !path.is_empty() => Ok((
+            prefix.parse::<Route>().map_err(|error| error.to_string())?,
+            PathBuf::from(path),
+        )),
+        _ => Err(String::from("expected route:path")),
+    }
+}
+
+fn parse_virtual_host(input: &str) -> Result<(String, PathBuf), String> {
+    match input.split_once(':') {
+        Some((host_name, path)) if !host_name.is_empty() && !path.is_empty() => {
+            Ok((String::from(host_name), PathBuf::from(path)))
+        }
+        _ => Err(String::from("expected host:path")),
+    }
+}
+
+/// Logs to stderr. Routes can be configured to log more or less than the
+/// level chosen here.
+fn init_logger(quiet: bool, verbose: u8) -> Result<(), anyhow::Error> {
+    let level = match (quiet, verbose) {
+        (true, _) => LevelFilter::Off,
+        (false, 0) => LevelFilter::Error,
+        (false, 1) => LevelFilter::Warn,
+        (false, 2) => LevelFilter::Info,
+        (false, 3) => LevelFilter::Debug,
+        (false, _) => LevelFilter::Trace,
+    };
+    let mut stderr_logger = stderrlog::new();
+    stderr_logger
+        .verbosity(LevelFilter::Trace)
+        .timestamp(stderrlog::Timestamp::Millisecond);
+    if !io::stderr().is_terminal() {
+        stderr_logger.color(stderrlog::ColorChoice::Never);
+    }
+    logging::init(logging::RouteAwareLogger::new(stderr_logger, level))?;
+    Ok(())
+}
+
+fn get_config(path: Option<PathBuf>, profile: Option<&str>) -> Result<Config, anyhow::Error> {
+    match path {
+        None => {
+            if let Some(profile) = profile {
+                log::warn!(
+                    "Ignoring profile '{}' because there is no config file",
+                    profile
+                );
+            }
+            Ok(Config::default())
+        }
+        Some(path) => Ok(Config::from_file_with_profile(path, profile)?),
+    }
+}
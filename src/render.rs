@@ -0,0 +1,680 @@
+//! Rendering which is shared by the server and the command line, so that
+//! content at a route behaves the same way no matter how it is requested.
+//! Callers only translate the [`RenderOutcome`] into a response or output.
+
+use crate::config::{Config, RouteConfig};
+use crate::content::*;
+use crate::ServerInfo;
+use bytes::Bytes;
+use futures::{future, stream, StreamExt};
+use mime_guess::MimeGuess;
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime};
+
+/// Everything about a request which affects how content is rendered.
+#[derive(Clone)]
+pub struct RenderRequest {
+    pub route: Route,
+    pub query_parameters: QueryParameters,
+    pub request_headers: HashMap<String, String>,
+
+    /// Media ranges in order of preference.
+    pub acceptable_media_ranges: Vec<MediaRange>,
+
+    /// Details that only exist for HTTP requests. This is empty for content
+    /// rendered from the command line.
+    pub http_request_data: HttpRequestData,
+
+    /// Cancelled when the render is no longer wanted (e.g. because the client
+    /// disconnected).
+    pub cancellation: Cancellation,
+}
+
+impl RenderRequest {
+    pub fn new(route: Route, acceptable_media_ranges: Vec<MediaRange>) -> Self {
+        RenderRequest {
+            route,
+            query_parameters: QueryParameters::default(),
+            request_headers: HashMap::new(),
+            acceptable_media_ranges,
+            http_request_data: HttpRequestData::default(),
+            cancellation: Cancellation::default(),
+        }
+    }
+
+    pub fn with_query_parameters(self, query_parameters: QueryParameters) -> Self {
+        RenderRequest {
+            query_parameters,
+            ..self
+        }
+    }
+
+    pub fn with_request_headers(self, request_headers: HashMap<String, String>) -> Self {
+        RenderRequest {
+            request_headers,
+            ..self
+        }
+    }
+
+    pub fn with_http_request_data(self, http_request_data: HttpRequestData) -> Self {
+        RenderRequest {
+            http_request_data,
+            ..self
+        }
+    }
+
+    pub fn with_cancellation(self, cancellation: Cancellation) -> Self {
+        RenderRequest {
+            cancellation,
+            ..self
+        }
+    }
+}
+
+/// What happened when content at a route was rendered.
+pub enum RenderOutcome<'engine> {
+    /// The route redirects somewhere else.
+    Redirect(&'engine Redirect),
+
+    /// There is no content at the route.
+    NotFound,
+
+    /// The content cannot be rendered as any of the acceptable media types.
+    NotAcceptable(RenderError),
+
+    /// Rendering the content failed (along with any fallbacks that were
+    /// tried).
+    Failed(RenderError),
+
+    /// Rendering started successfully. The content may still fail while it
+    /// is being streamed.
+    Rendered(Rendered<'engine>),
+}
+
+/// Content which has started rendering.
+pub struct Rendered<'engine> {
+    /// The route whose content is being rendered. This is not the requested
+    /// route when a fallback was used.
+    pub route: Route,
+
+    /// The status, caching headers, and media from the route's settings and
+    /// the front matter of the content which was rendered (which takes
+    /// precedence).
+    pub response: Response<Box<dyn ByteStream>>,
+
+    /// The representation of the content that is being rendered, if it is
+    /// registered content (rather than an alternative representation).
+    pub content: Option<&'engine RegisteredContent>,
+}
+
+/// Renders content at `request.route`, trying its configured fallbacks in
+/// order until one of them starts rendering successfully.
+pub fn render_route<'engine, Engine>(
+    content_engine: &'engine Engine,
+    config: &Config,
+    request: &RenderRequest,
+) -> RenderOutcome<'engine>
+where
+    Engine: ContentEngine<ServerInfo>,
+{
+    if let Some(outcome) = outcome_without_rendering(content_engine, &request.route) {
+        return outcome;
+    }
+
+    let mut failure = None;
+    for candidate_route in fallback_chain(content_engine, config, &request.route) {
+        match render_candidate(content_engine, config, request, &candidate_route) {
+            RenderOutcome::NotFound => {
+                log::error!("Fallback route {} does not exist", candidate_route);
+            }
+            RenderOutcome::Failed(error) => {
+                log::warn!("Failed to render {}: {}", candidate_route, error);
+                failure = Some(error);
+            }
+            outcome => {
+                if candidate_route != request.route {
+                    log::warn!(
+                        "Using fallback route {} for {}",
+                        candidate_route,
+                        request.route
+                    );
+                }
+                return outcome;
+            }
+        }
+    }
+    RenderOutcome::Failed(failure.unwrap_or_else(|| {
+        RenderError::Bug(format!("No routes were rendered for {}", request.route))
+    }))
+}
+
+/// The outcome for `route` if it cannot be rendered at all, because it
+/// redirects or does not exist.
+pub fn outcome_without_rendering<'engine, Engine>(
+    content_engine: &'engine Engine,
+    route: &Route,
+) -> Option<RenderOutcome<'engine>>
+where
+    Engine: ContentEngine<ServerInfo>,
+{
+    if let Some(redirect) = content_engine.redirect(route) {
+        Some(RenderOutcome::Redirect(redirect))
+    } else if content_engine.get(route).is_none() {
+        Some(RenderOutcome::NotFound)
+    } else {
+        None
+    }
+}
+
+/// Renders content at `candidate_route` (which is either `request.route` or
+/// one of its fallbacks) as if it were at `request.route`. Only the requested
+/// route can be [`NotAcceptable`](RenderOutcome::NotAcceptable); fallbacks
+/// which cannot be rendered as an acceptable media type have simply
+/// [`Failed`](RenderOutcome::Failed).
+pub fn render_candidate<'engine, Engine>(
+    content_engine: &'engine Engine,
+    config: &Config,
+    request: &RenderRequest,
+    candidate_route: &Route,
+) -> RenderOutcome<'engine>
+where
+    Engine: ContentEngine<ServerInfo>,
+{
+    let content = match content_engine.get(candidate_route) {
+        None => return RenderOutcome::NotFound,
+        Some(content) => content,
+    };
+    let mut render_data = content_engine.render_data(
+        Some(request.route.clone()),
+        request.query_parameters.clone(),
+        request.request_headers.clone(),
+    );
+    render_data.request.http = request.http_request_data.clone();
+    let (render_result, trace) = request.cancellation.scope(|| {
+        trace(candidate_route, || {
+            content_engine.render_content(content, render_data, &request.acceptable_media_ranges)
+        })
+    });
+    if !trace.children.is_empty() {
+        log::debug!("Render trace for {}:\n{}", request.route, trace);
+    }
+    match render_result {
+        Err(error @ RenderError::CannotProvideAcceptableMediaType)
+            if candidate_route == &request.route =>
+        {
+            RenderOutcome::NotAcceptable(error)
+        }
+        Err(error) => RenderOutcome::Failed(error),
+        Ok(mut media) => {
+            if config.render_trace && media.media_type.is_within_media_range(&mime::TEXT_HTML) {
+                let comment = Bytes::from(trace.to_html_comment());
+                media.content = Box::new(media.content.chain(stream::once(future::ok(comment))));
+            }
+            let registered_content = content.get(&media.media_type);
+            let route_config = config.route_config(&request.route);
+            RenderOutcome::Rendered(Rendered {
+                route: candidate_route.clone(),
+                response: Response {
+                    status: status(&route_config, registered_content),
+                    headers: headers(&route_config, registered_content),
+                    media,
+                },
+                content: registered_content,
+            })
+        }
+    }
+}
+
+/// Separates a URL path's extension from the rest of the path, if the
+/// extension maps to a media range.
+pub fn split_extension(path: &str) -> (&str, Option<MediaRange>) {
+    let media_range_from_url = MimeGuess::from_path(path).first();
+    let path_without_extension = if media_range_from_url.is_some() {
+        // Drop the extension from the path.
+        path.rsplit_once('.')
+            .map_or(path, |(path_without_extension, _)| path_without_extension)
+    } else {
+        path
+    };
+    (path_without_extension, media_range_from_url)
+}
+
+/// The media ranges of an `Accept` header value, most preferred first (items
+/// of equal quality keep their order). Ranges with a quality of zero are
+/// left out, since the client does not accept them, and malformed items are
+/// ignored. An empty value accepts anything, and a value without any
+/// well-formed items is `None`.
+pub fn acceptable_media_ranges(accept: &str) -> Option<Vec<MediaRange>> {
+    let items = accept
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .collect::<Vec<_>>();
+    if items.is_empty() {
+        return Some(vec![mime::STAR_STAR]);
+    }
+
+    let mut media_ranges = Vec::new();
+    let mut well_formed_item_count = 0;
+    for item in items {
+        match parse_accept_item(item) {
+            Some((quality, media_range)) => {
+                well_formed_item_count += 1;
+                if quality > 0.0 {
+                    media_ranges.push((quality, media_range));
+                }
+            }
+            None => log::debug!("Ignoring malformed Accept header item `{}`", item),
+        }
+    }
+    if well_formed_item_count == 0 {
+        return None;
+    }
+    // The sort is stable, so items of equal quality keep their order.
+    media_ranges.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+    Some(
+        media_ranges
+            .into_iter()
+            .map(|(_, media_range)| media_range)
+            .collect(),
+    )
+}
+
+/// Parses one item of an `Accept` header, like `text/html;level=1;q=0.5`.
+/// Parameters after `q` (accept extensions) are not part of the media range.
+fn parse_accept_item(item: &str) -> Option<(f32, MediaRange)> {
+    let mut parameters = item.split(';').map(str::trim);
+    let mut media_range = String::from(parameters.next()?);
+    let mut quality = 1.0;
+    for parameter in parameters {
+        let (name, value) = parameter.split_once('=')?;
+        if name.trim().eq_ignore_ascii_case("q") {
+            quality = value
+                .trim()
+                .parse::<f32>()
+                .ok()
+                .filter(|quality| (0.0..=1.0).contains(quality))?;
+            break;
+        }
+        media_range.push(';');
+        media_range.push_str(parameter);
+    }
+    media_range
+        .parse::<MediaRange>()
+        .ok()
+        .map(|media_range| (quality, media_range))
+}
+
+/// The given route followed by its fallbacks (stopping before any route would
+/// repeat). Each route's fallback comes from the front matter of its
+/// templates or else its settings.
+pub fn fallback_chain<Engine>(content_engine: &Engine, config: &Config, route: &Route) -> Vec<Route>
+where
+    Engine: ContentEngine<ServerInfo>,
+{
+    let mut chain = vec![route.clone()];
+    while let Some(fallback) = chain.last().and_then(|route| {
+        content_engine
+            .get(route)
+            .and_then(front_matter_fallback)
+            .or_else(|| config.route_config(route).fallback)
+    }) {
+        if chain.contains(&fallback) {
+            break;
+        }
+        chain.push(fallback);
+    }
+    chain
+}
+
+/// The `fallback` from the front matter of a route's templates. If they
+/// disagree the earliest fallback route wins, since representations have no
+/// order of their own.
+fn front_matter_fallback(representations: &ContentRepresentations) -> Option<Route> {
+    representations
+        .values()
+        .filter_map(|content| match content {
+            RegisteredContent::RegisteredTemplate(template) => {
+                template.front_matter().settings().fallback.clone()
+            }
+            _ => None,
+        })
+        .min()
+}
+
+/// The status code for successfully-rendered content, from the front matter
+/// of the content which was rendered or else the route's settings.
+fn status(route_config: &RouteConfig, content: Option<&RegisteredContent>) -> u16 {
+    let front_matter_status = match content {
+        Some(RegisteredContent::RegisteredTemplate(template)) => {
+            template.front_matter().settings().status
+        }
+        _ => None,
+    };
+    front_matter_status.or(route_config.status).unwrap_or(200)
+}
+
+/// The `Cache-Control`, `Expires`, and `Surrogate-Control` headers for
+/// successfully-rendered content.
+fn headers(
+    route_config: &RouteConfig,
+    content: Option<&RegisteredContent>,
+) -> Vec<(String, String)> {
+    let front_matter_settings = match content {
+        Some(RegisteredContent::RegisteredTemplate(template)) => {
+            Some(template.front_matter().settings())
+        }
+        _ => None,
+    };
+
+    let cache_control = front_matter_settings
+        .and_then(|settings| settings.cache_control.clone())
+        .or_else(|| route_config.cache_control.clone());
+    let expires = front_matter_settings
+        .and_then(|settings| settings.expires)
+        .or(route_config.expires)
+        .map(|seconds| http_date(SystemTime::now() + Duration::from_secs(seconds)));
+    let surrogate_control = front_matter_settings
+        .and_then(|settings| settings.surrogate_control.clone())
+        .or_else(|| route_config.surrogate_control.clone());
+
+    [
+        ("cache-control", cache_control),
+        ("expires", expires),
+        ("surrogate-control", surrogate_control),
+    ]
+    .into_iter()
+    .filter_map(|(name, value)| value.map(|value| (String::from(name), value)))
+    .collect()
+}
+
+/// Formats `time` like `Sun, 06 Nov 1994 08:49:37 GMT` (the IMF-fixdate of
+/// RFC 9110).
+fn http_date(time: SystemTime) -> String {
+    chrono::DateTime::<chrono::Utc>::from(time)
+        .format("%a, %d %b %Y %H:%M:%S GMT")
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_lib::*;
+    use std::fs;
+    use std::time::UNIX_EPOCH;
+    use test_log::test;
+
+    #[test]
+    fn accept_headers_are_ordered_by_quality() {
+        let media_ranges = |accept| {
+            acceptable_media_ranges(accept).map(|media_ranges| {
+                media_ranges
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+            })
+        };
+        assert_eq!(media_ranges(""), Some(vec![String::from("*/*")]));
+        assert_eq!(
+            media_ranges("text/plain;q=0.5, text/html, */*;q=0.1, image/png"),
+            Some(vec![
+                String::from("text/html"),
+                String::from("image/png"),
+                String::from("text/plain"),
+                String::from("*/*"),
+            ])
+        );
+        assert_eq!(
+            media_ranges("text/html;level=1;q=0.9;ext=1, text/plain;Q=1"),
+            Some(vec![
+                String::from("text/plain"),
+                String::from("text/html;level=1"),
+            ])
+        );
+
+        // Ranges with zero quality are not acceptable.
+        assert_eq!(
+            media_ranges("text/html, application/json;q=0"),
+            Some(vec![String::from("text/html")])
+        );
+        assert_eq!(media_ranges("*/*;q=0.0"), Some(Vec::new()));
+
+        // Malformed items are ignored unless that leaves nothing.
+        assert_eq!(
+            media_ranges("nonsense, text/html;q=2, text/plain;q=high, text/css"),
+            Some(vec![String::from("text/css")])
+        );
+        assert_eq!(media_ranges("not a media type"), None);
+    }
+
+    #[test]
+    fn times_can_be_formatted_as_http_dates() {
+        assert_eq!(
+            http_date(UNIX_EPOCH + Duration::from_secs(784111777)),
+            "Sun, 06 Nov 1994 08:49:37 GMT"
+        );
+        assert_eq!(http_date(UNIX_EPOCH), "Thu, 01 Jan 1970 00:00:00 GMT");
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "templating"), ignore = "uses templates")]
+    fn fallbacks_are_rendered_when_content_fails() {
+        let root = tempfile::tempdir().expect("Failed to create temporary directory");
+        fs::write(root.path().join("broken.txt.hbs"), "{{no-such-variable}}").unwrap();
+        fs::write(root.path().join("fallback.txt"), "fallback").unwrap();
+        let config = toml::from_str::<Config>(
+            r#"
+            routes."/broken" = { fallback = "/does-not-exist" }
+            routes."/does-not-exist" = { fallback = "/fallback" }
+            routes."/fallback" = { status = 503 }
+            "#,
+        )
+        .unwrap();
+        let shared_content_engine = FilesystemBasedContentEngine::from_content_directory(
+            ContentDirectory::from_root(&root.path()).unwrap(),
+            ServerInfo::without_socket_address().unwrap(),
+        )
+        .unwrap();
+        let content_engine = shared_content_engine.read().unwrap();
+
+        let request = RenderRequest::new(route("/broken"), vec![mime::TEXT_PLAIN]);
+        match render_route(&*content_engine, &config, &request) {
+            RenderOutcome::Rendered(rendered) => {
+                assert_eq!(rendered.route, route("/fallback"));
+                // Settings come from the requested route.
+                assert_eq!(rendered.response.status, 200);
+            }
+            _ => panic!("Content was not rendered"),
+        }
+
+        let request = RenderRequest::new(route("/fallback"), vec![mime::TEXT_PLAIN]);
+        match render_route(&*content_engine, &config, &request) {
+            RenderOutcome::Rendered(rendered) => assert_eq!(rendered.response.status, 503),
+            _ => panic!("Content was not rendered"),
+        }
+
+        let request = RenderRequest::new(route("/does-not-exist"), vec![mime::TEXT_PLAIN]);
+        assert!(matches!(
+            render_route(&*content_engine, &config, &request),
+            RenderOutcome::NotFound
+        ));
+
+        let request = RenderRequest::new(route("/fallback"), vec![mime::IMAGE_PNG]);
+        assert!(matches!(
+            render_route(&*content_engine, &config, &request),
+            RenderOutcome::NotAcceptable(_)
+        ));
+
+        let config = Config::default();
+        let request = RenderRequest::new(route("/broken"), vec![mime::TEXT_PLAIN]);
+        assert!(matches!(
+            render_route(&*content_engine, &config, &request),
+            RenderOutcome::Failed(_)
+        ));
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "templating"), ignore = "uses templates")]
+    fn responses_include_caching_headers() {
+        let root = tempfile::tempdir().expect("Failed to create temporary directory");
+        fs::write(
+            root.path().join("cached.txt.hbs"),
+            "---\ncache-control: no-store\n---\ncached",
+        )
+        .unwrap();
+        fs::write(root.path().join("plain.txt"), "plain").unwrap();
+        let config = toml::from_str::<Config>(
+            r#"
+            routes."/cached" = { cache-control = "max-age=60", expires = 60, surrogate-control = "max-age=3600" }
+            "#,
+        )
+        .unwrap();
+        let shared_content_engine = FilesystemBasedContentEngine::from_content_directory(
+            ContentDirectory::from_root(&root.path()).unwrap(),
+            ServerInfo::without_socket_address().unwrap(),
+        )
+        .unwrap();
+        let content_engine = shared_content_engine.read().unwrap();
+
+        let request = RenderRequest::new(route("/cached"), vec![mime::TEXT_PLAIN]);
+        match render_route(&*content_engine, &config, &request) {
+            RenderOutcome::Rendered(Rendered { response, .. }) => {
+                assert_eq!(response.status, 200);
+                assert_eq!(response.media.media_type, mime::TEXT_PLAIN);
+                // Front matter takes precedence over the route's settings.
+                assert_eq!(response.header("Cache-Control"), Some("no-store"));
+                assert_eq!(response.header("surrogate-control"), Some("max-age=3600"));
+                let expires = response.header("expires").expect("Expires was missing");
+                assert!(expires.ends_with(" GMT"), "Invalid Expires: {}", expires);
+            }
+            _ => panic!("Content was not rendered"),
+        }
+
+        let request = RenderRequest::new(route("/plain"), vec![mime::TEXT_PLAIN]);
+        match render_route(&*content_engine, &config, &request) {
+            RenderOutcome::Rendered(Rendered { response, .. }) => {
+                assert!(response.headers.is_empty())
+            }
+            _ => panic!("Content was not rendered"),
+        }
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "templating"), ignore = "uses templates")]
+    fn render_traces_can_be_appended_to_html() {
+        let root = tempfile::tempdir().expect("Failed to create temporary directory");
+        fs::write(
+            root.path().join("page.html.hbs"),
+            "page {{get \"/widget\"}}",
+        )
+        .unwrap();
+        fs::write(root.path().join("widget.html.hbs"), "widget").unwrap();
+        let shared_content_engine = FilesystemBasedContentEngine::from_content_directory(
+            ContentDirectory::from_root(&root.path()).unwrap(),
+            ServerInfo::without_socket_address().unwrap(),
+        )
+        .unwrap();
+        let content_engine = shared_content_engine.read().unwrap();
+        let request = RenderRequest::new(route("/page"), vec![mime::TEXT_HTML]);
+
+        for (render_trace, expected_trace) in [(false, None), (true, Some("/page"))] {
+            let config = Config {
+                render_trace,
+                ..Config::default()
+            };
+            let output = match render_route(&*content_engine, &config, &request) {
+                RenderOutcome::Rendered(Rendered { response, .. }) => {
+                    futures::executor::block_on_stream(response.media.content)
+                        .map(|chunk| String::from_utf8(chunk.unwrap().to_vec()).unwrap())
+                        .collect::<String>()
+                }
+                _ => panic!("Content was not rendered"),
+            };
+            let (page, trace) = match output.split_once("<!-- render trace\n") {
+                Some((page, trace)) => (page, Some(trace)),
+                None => (output.as_str(), None),
+            };
+            assert_eq!(page, "page widget");
+            assert_eq!(
+                trace.and_then(|trace| trace.split(' ').next()),
+                expected_trace
+            );
+            if let Some(trace) = trace {
+                assert!(
+                    trace.contains("\n  /widget ("),
+                    "Unexpected trace: {}",
+                    trace
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn fallback_chains_stop_at_cycles() {
+        let root = tempfile::tempdir().expect("Failed to create temporary directory");
+        let config = toml::from_str::<Config>(
+            r#"
+            routes."/a" = { fallback = "/b" }
+            routes."/b" = { fallback = "/c" }
+            routes."/c" = { fallback = "/a" }
+            "#,
+        )
+        .unwrap();
+        let shared_content_engine = FilesystemBasedContentEngine::from_content_directory(
+            ContentDirectory::from_root(&root.path()).unwrap(),
+            ServerInfo::without_socket_address().unwrap(),
+        )
+        .unwrap();
+        let content_engine = shared_content_engine.read().unwrap();
+
+        assert_eq!(
+            fallback_chain(&*content_engine, &config, &route("/a")),
+            vec![route("/a"), route("/b"), route("/c")]
+        );
+        assert_eq!(
+            fallback_chain(&*content_engine, &config, &route("/d")),
+            vec![route("/d")]
+        );
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "templating"), ignore = "uses templates")]
+    fn front_matter_fallbacks_override_settings() {
+        let root = tempfile::tempdir().expect("Failed to create temporary directory");
+        fs::write(
+            root.path().join("broken.txt.hbs"),
+            "---\nfallback: /from-front-matter\n---\n{{no-such-variable}}",
+        )
+        .unwrap();
+        fs::write(root.path().join("from-front-matter.txt"), "front matter").unwrap();
+        fs::write(root.path().join("from-config.txt"), "config").unwrap();
+        let config = toml::from_str::<Config>(
+            r#"
+            routes."/" = { fallback = "/from-config" }
+            "#,
+        )
+        .unwrap();
+        let shared_content_engine = FilesystemBasedContentEngine::from_content_directory(
+            ContentDirectory::from_root(&root.path()).unwrap(),
+            ServerInfo::without_socket_address().unwrap(),
+        )
+        .unwrap();
+        let content_engine = shared_content_engine.read().unwrap();
+
+        assert_eq!(
+            fallback_chain(&*content_engine, &config, &route("/broken")),
+            vec![
+                route("/broken"),
+                route("/from-front-matter"),
+                route("/from-config"),
+            ]
+        );
+        let request = RenderRequest::new(route("/broken"), vec![mime::TEXT_PLAIN]);
+        match render_route(&*content_engine, &config, &request) {
+            RenderOutcome::Rendered(rendered) => {
+                assert_eq!(rendered.route, route("/from-front-matter"))
+            }
+            _ => panic!("Content was not rendered"),
+        }
+    }
+}
@@ -0,0 +1,517 @@
+//! Runs content on a schedule while the server is running (see
+//! [`Config::tasks`](crate::config::Config::tasks) and the `task` setting in
+//! template front matter).
+//!
+//! Schedules are cron expressions with five fields (minute, hour, day of the
+//! month, month, and day of the week) which are evaluated in UTC.
+
+use crate::config::TaskConfig;
+use crate::content::*;
+use crate::ServerInfo;
+use chrono::{DateTime, Timelike, Utc};
+use croner::parser::{CronParser, Seconds, Year};
+use croner::Cron;
+use futures::executor;
+use futures::stream::TryStreamExt;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use thiserror::Error;
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum ScheduleError {
+    #[error("Schedule '{}' is invalid: {}", .schedule, .message)]
+    Invalid { schedule: String, message: String },
+}
+
+#[derive(Error, Debug)]
+pub enum TaskError {
+    #[error("Task route '{}' does not exist.", .route)]
+    RouteMissing { route: Route },
+
+    #[error("Unable to render task '{}'.", .route)]
+    RenderError { route: Route, source: RenderError },
+
+    #[error("Unable to emit rendered content for task '{}'.", .route)]
+    StreamError { route: Route, source: StreamError },
+
+    #[error("Unable to write output of task '{}' to '{}'.", .route, .path.display())]
+    WriteError {
+        route: Route,
+        path: std::path::PathBuf,
+        source: io::Error,
+    },
+}
+
+/// A cron schedule, like `*/15 * * * *` or `@daily`.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Schedule {
+    source: String,
+    cron: Box<Cron>,
+}
+
+impl Schedule {
+    /// Whether the schedule includes the minute containing `time`.
+    pub fn matches(&self, time: SystemTime) -> bool {
+        DateTime::<Utc>::from(time)
+            .with_second(0)
+            .and_then(|minute| minute.with_nanosecond(0))
+            .is_some_and(|minute| self.cron.is_time_matching(&minute).unwrap_or(false))
+    }
+}
+
+impl FromStr for Schedule {
+    type Err = ScheduleError;
+    fn from_str(schedule: &str) -> Result<Self, Self::Err> {
+        let cron = CronParser::builder()
+            .seconds(Seconds::Disallowed)
+            .year(Year::Disallowed)
+            // Allow `5/10` to mean `5-59/10`.
+            .sloppy_ranges(true)
+            .build()
+            .parse(schedule)
+            .map_err(|error| ScheduleError::Invalid {
+                schedule: String::from(schedule),
+                message: error.to_string(),
+            })?;
+        Ok(Schedule {
+            source: String::from(schedule),
+            cron: Box::new(cron),
+        })
+    }
+}
+
+impl TryFrom<String> for Schedule {
+    type Error = ScheduleError;
+    fn try_from(schedule: String) -> Result<Self, Self::Error> {
+        schedule.parse()
+    }
+}
+
+impl From<Schedule> for String {
+    fn from(schedule: Schedule) -> Self {
+        schedule.source
+    }
+}
+
+impl fmt::Display for Schedule {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(formatter, "{}", self.source)
+    }
+}
+
+/// Runs tasks in the background until it is stopped.
+pub struct Scheduler {
+    stopping: Arc<AtomicBool>,
+    thread: thread::JoinHandle<()>,
+}
+
+impl Scheduler {
+    /// Stops running tasks on their schedules, and waits for any which are
+    /// still running to finish.
+    pub fn stop(self) {
+        self.stopping.store(true, Ordering::Release);
+        self.thread.thread().unpark();
+        if self.thread.join().is_err() {
+            log::error!("The task scheduler panicked");
+        }
+    }
+}
+
+/// Starts a background thread which renders each task's route whenever its
+/// schedule says to. A task which is still running when it comes due again
+/// is skipped that time.
+pub fn start<Engine>(
+    shared_content_engine: Arc<RwLock<Engine>>,
+    tasks: BTreeMap<Route, TaskConfig>,
+) -> Option<Scheduler>
+where
+    Engine: 'static + ContentEngine<ServerInfo> + InternalContentEngine + Send + Sync,
+{
+    if tasks.is_empty() {
+        return None;
+    }
+    let stopping = Arc::new(AtomicBool::new(false));
+    let thread = thread::spawn({
+        let stopping = stopping.clone();
+        move || {
+            let mut running = HashMap::<Route, thread::JoinHandle<()>>::new();
+            let mut previous_minute = None;
+            while !stopping.load(Ordering::Acquire) {
+                let now = SystemTime::now();
+                let seconds = now
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or(Duration::ZERO)
+                    .as_secs();
+                let minute = seconds / 60;
+                if previous_minute != Some(minute) {
+                    previous_minute = Some(minute);
+                    for (route, task_config) in &tasks {
+                        if !task_config.schedule.matches(now) {
+                            continue;
+                        }
+                        if running
+                            .get(route)
+                            .is_some_and(|previous_run| !previous_run.is_finished())
+                        {
+                            log::warn!(
+                                "Skipping task '{}' because its previous run has not finished",
+                                route,
+                            );
+                            continue;
+                        }
+                        let shared_content_engine = shared_content_engine.clone();
+                        let task_route = route.clone();
+                        let task_config = task_config.clone();
+                        let task_thread = thread::spawn(move || {
+                            log::info!("Running task '{}'", task_route);
+                            if let Err(error) =
+                                run(&shared_content_engine, &task_route, &task_config)
+                            {
+                                log::error!("{:#}", anyhow::Error::from(error));
+                            }
+                        });
+                        running.insert(route.clone(), task_thread);
+                    }
+                }
+                // Wake up just after the start of the next minute (or when
+                // stopped).
+                thread::park_timeout(
+                    Duration::from_secs(60 - seconds % 60) + Duration::from_millis(10),
+                );
+            }
+            for task_thread in running.into_values() {
+                let _ = task_thread.join();
+            }
+        }
+    });
+    Some(Scheduler { stopping, thread })
+}
+
+/// Renders the task at `route` once. If it is an executable with an output
+/// cache (see `cache-ttl`) then it runs even if it has cached output, and its
+/// new output replaces the cached output for requests without query
+/// parameters. The output is also written to the task's `output` file (if it
+/// has one).
+pub fn run<Engine>(
+    shared_content_engine: &RwLock<Engine>,
+    route: &Route,
+    task_config: &TaskConfig,
+) -> Result<(), TaskError>
+where
+    Engine: ContentEngine<ServerInfo> + InternalContentEngine,
+{
+    let content_engine = shared_content_engine
+        .read()
+        .unwrap_or_else(|error| error.into_inner());
+    let content_item =
+        content_engine
+            .get_internal(route)
+            .ok_or_else(|| TaskError::RouteMissing {
+                route: route.clone(),
+            })?;
+
+    let render_context = content_engine
+        .render_context(
+            Some(route.clone()),
+            QueryParameters::default(),
+            HashMap::new(),
+        )
+        .refreshing_caches();
+    let media = content_item
+        .render(render_context, &[mime::STAR_STAR])
+        .map_err(|source| TaskError::RenderError {
+            route: route.clone(),
+            source,
+        })?;
+    // Executables can take a while to finish writing their output, and
+    // content should still be able to reload in the meantime.
+    drop(content_engine);
+
+    let output = executor::block_on(media.content.try_fold(Vec::new(), |mut output, bytes| {
+        output.extend_from_slice(&bytes);
+        async { Ok(output) }
+    }))
+    .map_err(|source| TaskError::StreamError {
+        route: route.clone(),
+        source,
+    })?;
+
+    match &task_config.output {
+        None => Ok(()),
+        Some(path) => write_atomically(path, &output).map_err(|source| TaskError::WriteError {
+            route: route.clone(),
+            path: path.clone(),
+            source,
+        }),
+    }
+}
+
+/// Writes to a temporary file beside `path` and then moves it into place, so
+/// that readers never see partial output.
+fn write_atomically(path: &Path, contents: &[u8]) -> Result<(), io::Error> {
+    let file_name = path.file_name().ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidInput, "Output path has no file name")
+    })?;
+    let mut temporary_file_name = std::ffi::OsString::from(".");
+    temporary_file_name.push(file_name);
+    temporary_file_name.push(".tmp");
+    let temporary_path = path.with_file_name(temporary_file_name);
+
+    let mut file = fs::File::create(&temporary_path)?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    fs::rename(&temporary_path, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_lib::*;
+    use test_log::test;
+
+    fn time(seconds: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(seconds)
+    }
+
+    #[test]
+    fn schedules_can_be_parsed() {
+        for valid in [
+            "* * * * *",
+            "*/15 * * * *",
+            "0 9-17 * * mon-fri",
+            "5,35 */2 1,15 jan,JUL 0",
+            "0 0 * * 7",
+            "10/20 * * * *",
+            "@daily",
+            "  @hourly ",
+        ] {
+            assert!(
+                valid.parse::<Schedule>().is_ok(),
+                "'{}' should have been valid",
+                valid,
+            );
+        }
+
+        for invalid in [
+            "* * * *",
+            "0 * * * * *",
+            "60 * * * *",
+            "* 24 * * *",
+            "* * 0 * *",
+            "* * * 13 *",
+            "* * * * 8",
+            "*/0 * * * *",
+            "5-1 * * * *",
+            "a * * * *",
+            "* * * * monday",
+            "@sometimes",
+        ] {
+            assert!(
+                invalid.parse::<Schedule>().is_err(),
+                "'{}' should have been invalid",
+                invalid,
+            );
+        }
+    }
+
+    #[test]
+    fn schedules_match_times() {
+        // 2024-02-29T13:45:00Z, a Thursday.
+        let thursday_afternoon = time(1709214300);
+        let matches = |schedule: &str| {
+            schedule
+                .parse::<Schedule>()
+                .unwrap()
+                .matches(thursday_afternoon)
+        };
+
+        assert!(matches("* * * * *"));
+        assert!(matches("*/15 * * * *"));
+        assert!(matches("45 13 29 2 4"));
+        assert!(matches("45 13 * feb thu"));
+        assert!(matches("0-50/5 9-17 * * mon-fri"));
+        assert!(!matches("*/10 * * * *"));
+        assert!(!matches("45 14 * * *"));
+        assert!(!matches("@hourly"));
+        assert!(!matches("* * * * sun,sat"));
+        assert!(!matches("* * 28 * *"));
+
+        // When both day fields are restricted either one can match.
+        assert!(matches("45 13 1 * thu"));
+        assert!(matches("45 13 29 * mon"));
+        assert!(!matches("45 13 1 * mon"));
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "executables"), ignore = "uses executables")]
+    fn tasks_refresh_cached_executable_output() {
+        use std::os::unix::fs::PermissionsExt;
+        let root = tempfile::tempdir().expect("Failed to create temporary directory");
+        let log = tempfile::tempdir().expect("Failed to create temporary directory");
+        let path = root.path().join("count.txt.sh");
+        fs::write(
+            &path,
+            format!(
+                "#!/bin/sh\necho run >> '{0}'\nwc -l < '{0}' | tr -d ' \\n'\n",
+                log.path().join("runs").display(),
+            ),
+        )
+        .unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+        let config =
+            toml::from_str::<crate::config::Config>(r#"executables.cache-ttl."/count" = 600"#)
+                .unwrap();
+        let shared_content_engine =
+            FilesystemBasedContentEngine::from_content_directory_with_config(
+                ContentDirectory::from_root(&root.path()).unwrap(),
+                ServerInfo::without_socket_address().unwrap(),
+                config,
+            )
+            .expect("Content engine could not be created");
+        let request = || {
+            let content_engine = shared_content_engine.read().unwrap();
+            let render_context = content_engine.render_context(
+                Some(route("/count")),
+                QueryParameters::default(),
+                HashMap::new(),
+            );
+            let media = content_engine
+                .get_internal(&route("/count"))
+                .unwrap()
+                .render(render_context, &[mime::STAR_STAR])
+                .expect("Render failed");
+            let output =
+                executor::block_on(media.content.try_fold(Vec::new(), |mut output, bytes| {
+                    output.extend_from_slice(&bytes);
+                    async { Ok(output) }
+                }))
+                .expect("Content stream failed");
+            String::from_utf8(output).unwrap()
+        };
+        let task_config = TaskConfig {
+            schedule: "* * * * *".parse().unwrap(),
+            output: None,
+        };
+
+        assert_eq!(request(), "1");
+        assert_eq!(request(), "1");
+        run(&shared_content_engine, &route("/count"), &task_config).expect("Task failed");
+        assert_eq!(request(), "2");
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "executables"), ignore = "uses executables")]
+    fn content_can_reload_while_tasks_are_running() {
+        use std::os::unix::fs::PermissionsExt;
+        let root = tempfile::tempdir().expect("Failed to create temporary directory");
+        let signals = tempfile::tempdir().expect("Failed to create temporary directory");
+        let started_path = signals.path().join("started");
+        let finish_path = signals.path().join("finish");
+        let path = root.path().join("slow.txt.sh");
+        fs::write(
+            &path,
+            format!(
+                "#!/bin/sh
+echo started
+touch '{}'
+                for _ in $(seq 1000); do [ -e '{1}' ] && break; sleep 0.01; done
+echo done
+",
+                started_path.display(),
+                finish_path.display(),
+            ),
+        )
+        .unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+        let shared_content_engine = FilesystemBasedContentEngine::from_content_directory(
+            ContentDirectory::from_root(&root.path()).unwrap(),
+            ServerInfo::without_socket_address().unwrap(),
+        )
+        .expect("Content engine could not be created");
+        let task_config = TaskConfig {
+            schedule: "* * * * *".parse().unwrap(),
+            output: None,
+        };
+
+        thread::scope(|scope| {
+            let task = scope.spawn(|| run(&shared_content_engine, &route("/slow"), &task_config));
+            let started_at = std::time::Instant::now();
+            while !started_path.exists() {
+                assert!(
+                    started_at.elapsed() < Duration::from_secs(10),
+                    "The task never started"
+                );
+                thread::sleep(Duration::from_millis(10));
+            }
+            // The task is still running, but that does not block writers.
+            while shared_content_engine.try_write().is_err() {
+                assert!(
+                    started_at.elapsed() < Duration::from_secs(10),
+                    "The content engine stayed locked while the task was running"
+                );
+                thread::sleep(Duration::from_millis(10));
+            }
+            assert!(!task.is_finished());
+            fs::write(&finish_path, "").unwrap();
+            task.join().unwrap().expect("Task failed");
+        });
+    }
+
+    #[test]
+    fn schedulers_can_be_stopped() {
+        let shared_content_engine = FilesystemBasedContentEngine::from_content_directory(
+            sample_content_directory("hello-world"),
+            ServerInfo::without_socket_address().unwrap(),
+        )
+        .expect("Content engine could not be created");
+        let tasks = BTreeMap::from([(
+            route("/hello"),
+            TaskConfig {
+                schedule: "* * * * *".parse().unwrap(),
+                output: None,
+            },
+        )]);
+
+        let scheduler = start(shared_content_engine, tasks).expect("Scheduler should have started");
+        let stop_time = std::time::Instant::now();
+        scheduler.stop();
+        assert!(
+            stop_time.elapsed() < Duration::from_secs(10),
+            "Stopping the scheduler took {:?}",
+            stop_time.elapsed(),
+        );
+    }
+
+    #[test]
+    fn tasks_can_write_their_output_to_disk() {
+        let output_directory = tempfile::tempdir().expect("Failed to create temporary directory");
+        let output_path = output_directory.path().join("hello.txt");
+        let shared_content_engine = FilesystemBasedContentEngine::from_content_directory(
+            sample_content_directory("hello-world"),
+            ServerInfo::without_socket_address().unwrap(),
+        )
+        .expect("Content engine could not be created");
+        let task_config = TaskConfig {
+            schedule: "* * * * *".parse().unwrap(),
+            output: Some(output_path.clone()),
+        };
+
+        run(&shared_content_engine, &route("/hello"), &task_config).expect("Task failed");
+        assert_eq!(fs::read_to_string(&output_path).unwrap(), "hello world");
+
+        match run(&shared_content_engine, &route("/nope"), &task_config) {
+            Err(TaskError::RouteMissing { .. }) => {}
+            _ => panic!("Task for a missing route did not fail as expected"),
+        }
+    }
+}
@@ -32,13 +32,16 @@ pub fn sample_content_directories() -> Vec<ContentDirectory> {
         sample_content_directory("executables"),
         sample_content_directory("hello-world"),
         sample_content_directory("hidden-content"),
+        sample_content_directory("layouts"),
         sample_content_directory("media-types"),
+        sample_content_directory("minijinja"),
         sample_content_directory("multimedia"),
         sample_content_directory("ouroboros"),
         sample_content_directory("partials"),
         sample_content_directory("realistic-basic"),
         sample_content_directory("render-context"),
         sample_content_directory("static-content"),
+        sample_content_directory("unicode-names"),
         sample_content_directory("invalid-duplicate-media-type-1"),
         sample_content_directory("invalid-duplicate-media-type-2"),
         sample_content_directory("invalid-duplicate-media-type-3"),
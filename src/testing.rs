@@ -0,0 +1,392 @@
+//! Helpers for testing a content directory, so that sites can have
+//! integration tests of their own.
+//!
+//! Content is rendered the same way the server would render it, without
+//! needing a server. Sites are loaded in [deterministic](Config::deterministic)
+//! mode by default, so output can be compared against snapshots.
+//!
+//! ```
+//! use operator::testing::TestSite;
+//! # fn example() -> Result<(), Box<dyn std::error::Error>> {
+//! # let content_directory = concat!(env!("CARGO_MANIFEST_DIR"), "/samples/hello-world");
+//! let site = TestSite::load(content_directory)?;
+//! let page = site.render("/hello")?;
+//! page.assert_status(200).assert_media_type("text/plain");
+//! assert!(page.text().contains("hello world"));
+//! # Ok(())
+//! # }
+//! # example().unwrap();
+//! ```
+//!
+//! For tests which need real HTTP requests, [`TestSite::serve`] starts a
+//! server on a random local port.
+
+use crate::config::Config;
+use crate::content::*;
+use crate::render::{self, RenderOutcome, RenderRequest, Rendered};
+use crate::{ServerInfo, ServerInfoError};
+use bytes::{Bytes, BytesMut};
+use futures::{executor, TryStreamExt};
+use std::env;
+use std::io;
+use std::path::Path;
+use std::str;
+use std::sync::{Arc, RwLock};
+use thiserror::Error;
+
+#[cfg(feature = "server")]
+pub use server::TestServer;
+
+#[derive(Error, Debug)]
+pub enum TestSiteError {
+    #[error("Unable to find the content directory.")]
+    ContentDirectoryError {
+        #[from]
+        source: ContentDirectoryFromRootError,
+    },
+
+    #[error("Unable to find the content directory.")]
+    IOError {
+        #[from]
+        source: io::Error,
+    },
+
+    #[error("Unable to collect server info.")]
+    ServerInfoError {
+        #[from]
+        source: ServerInfoError,
+    },
+
+    #[error("Unable to load content.")]
+    ContentLoadingError {
+        #[from]
+        source: ContentLoadingErrors,
+    },
+
+    #[error("Route '{}' is invalid: {}", .route, .message)]
+    InvalidRoute { route: String, message: String },
+
+    #[error("Content not found at route '{}'.", .route)]
+    ContentNotFound { route: Route },
+
+    #[error("Route '{}' redirects to '{}'.", .route, .location)]
+    Redirect { route: Route, location: String },
+
+    #[error("Unable to render content.")]
+    RenderError {
+        #[from]
+        source: RenderError,
+    },
+
+    #[error("Unable to read rendered content.")]
+    StreamError {
+        #[from]
+        source: StreamError,
+    },
+
+    #[cfg(feature = "server")]
+    #[error("Unable to start the test server.")]
+    ServerError { source: io::Error },
+}
+
+/// A content directory loaded for testing.
+pub struct TestSite {
+    shared_content_engine: Arc<RwLock<FilesystemBasedContentEngine<'static, ServerInfo>>>,
+    config: Config,
+}
+
+impl TestSite {
+    /// Loads the content directory at `path` (which may be relative to the
+    /// current directory) with default settings, in deterministic mode.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, TestSiteError> {
+        TestSite::load_with_config(
+            path,
+            Config {
+                deterministic: true,
+                ..Config::default()
+            },
+        )
+    }
+
+    /// Loads the content directory at `path` with `config`, exactly as given.
+    pub fn load_with_config<P: AsRef<Path>>(
+        path: P,
+        config: Config,
+    ) -> Result<Self, TestSiteError> {
+        let root = env::current_dir()?.join(path);
+        let content_directory = ContentDirectory::from_root_with_config(&root, &config.content)?;
+        let shared_content_engine =
+            FilesystemBasedContentEngine::from_content_directory_with_config(
+                content_directory,
+                ServerInfo::without_socket_address()?.for_config(&config),
+                config.clone(),
+            )?;
+        Ok(TestSite {
+            shared_content_engine,
+            config,
+        })
+    }
+
+    /// Renders the content at `route` as whatever media type it prefers.
+    pub fn render(&self, route: &str) -> Result<RenderedRoute, TestSiteError> {
+        self.render_as(route, mime::STAR_STAR)
+    }
+
+    /// Renders the content at `route` as if it was requested with `accept`
+    /// as its `Accept` header.
+    pub fn render_as(
+        &self,
+        route: &str,
+        accept: MediaRange,
+    ) -> Result<RenderedRoute, TestSiteError> {
+        let route = route
+            .parse::<Route>()
+            .map_err(|error| TestSiteError::InvalidRoute {
+                route: String::from(route),
+                message: error.to_string(),
+            })?;
+        let content_engine = self
+            .shared_content_engine
+            .read()
+            .expect("RwLock for ContentEngine has been poisoned");
+        let request = RenderRequest::new(route.clone(), vec![accept]);
+
+        match render::render_route(&*content_engine, &self.config, &request) {
+            RenderOutcome::Redirect(redirect) => Err(TestSiteError::Redirect {
+                route,
+                location: String::from(redirect.location()),
+            }),
+            RenderOutcome::NotFound => Err(TestSiteError::ContentNotFound { route }),
+            RenderOutcome::NotAcceptable(error) | RenderOutcome::Failed(error) => Err(error.into()),
+            RenderOutcome::Rendered(Rendered {
+                response:
+                    Response {
+                        status,
+                        headers,
+                        media,
+                    },
+                ..
+            }) => {
+                let body = executor::block_on(media.content.try_fold(
+                    BytesMut::new(),
+                    |mut body, chunk| async move {
+                        body.extend_from_slice(&chunk);
+                        Ok(body)
+                    },
+                ))?;
+                Ok(RenderedRoute {
+                    route,
+                    status,
+                    media_type: media.media_type,
+                    headers,
+                    body: body.freeze(),
+                })
+            }
+        }
+    }
+
+    /// Starts serving this site on a random local port. The server stops
+    /// when the returned [`TestServer`] is dropped.
+    #[cfg(feature = "server")]
+    pub fn serve(&self) -> Result<TestServer, TestSiteError> {
+        TestServer::start(self.shared_content_engine.clone(), self.config.clone())
+            .map_err(|source| TestSiteError::ServerError { source })
+    }
+}
+
+/// Content which was completely rendered.
+#[derive(Clone, Debug)]
+pub struct RenderedRoute {
+    pub route: Route,
+
+    /// The status that the server would respond with.
+    pub status: u16,
+
+    pub media_type: MediaType,
+
+    /// Headers from the route's settings and the content's front matter.
+    pub headers: Vec<(String, String)>,
+
+    pub body: Bytes,
+}
+
+impl RenderedRoute {
+    /// The body as a string.
+    ///
+    /// # Panics
+    ///
+    /// If the body is not UTF-8.
+    pub fn text(&self) -> &str {
+        str::from_utf8(&self.body)
+            .unwrap_or_else(|error| panic!("Body of {} was not UTF-8: {}", self.route, error))
+    }
+
+    /// The first value of the header called `name` (which is compared
+    /// case-insensitively), if there is one.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header_name, _)| header_name.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// # Panics
+    ///
+    /// If the content was not rendered with `status`.
+    pub fn assert_status(&self, status: u16) -> &Self {
+        assert_eq!(
+            self.status, status,
+            "Content at {} was rendered with the wrong status",
+            self.route,
+        );
+        self
+    }
+
+    /// Checks the media type without parameters, so `text/html` matches
+    /// `text/html; charset=utf-8`.
+    ///
+    /// # Panics
+    ///
+    /// If the content was not rendered as `media_type`.
+    pub fn assert_media_type(&self, media_type: &str) -> &Self {
+        assert_eq!(
+            self.media_type.essence_str(),
+            media_type,
+            "Content at {} was rendered as the wrong media type",
+            self.route,
+        );
+        self
+    }
+}
+
+#[cfg(feature = "server")]
+mod server {
+    use super::*;
+    use crate::http::{OperatorServer, OperatorServerHandle, RunServerError};
+    use actix_rt::System;
+    use std::net::{SocketAddr, TcpListener};
+    use std::sync::mpsc;
+    use std::thread::{self, JoinHandle};
+
+    /// A server for a [`TestSite`], which runs on its own thread until it is
+    /// dropped.
+    pub struct TestServer {
+        address: SocketAddr,
+        handle: OperatorServerHandle,
+        thread: Option<JoinHandle<()>>,
+    }
+
+    impl TestServer {
+        pub(super) fn start(
+            shared_content_engine: Arc<RwLock<FilesystemBasedContentEngine<'static, ServerInfo>>>,
+            config: Config,
+        ) -> Result<Self, io::Error> {
+            let listener = TcpListener::bind(("127.0.0.1", 0))?;
+            let address = listener.local_addr()?;
+            let (sender, receiver) = mpsc::channel();
+            let thread = thread::spawn(move || {
+                System::new("operator-test-server").block_on(async move {
+                    let server = match OperatorServer::builder(shared_content_engine)
+                        .with_config(config)
+                        .listen(listener)
+                    {
+                        Ok(server) => server,
+                        Err(error) => {
+                            let _ = sender.send(Err(error));
+                            return;
+                        }
+                    };
+                    let _ = sender.send(Ok(server.handle()));
+                    if let Err(error) = server.await {
+                        log::error!("Test server failed: {}", error);
+                    }
+                })
+            });
+            match receiver.recv() {
+                Ok(Ok(handle)) => Ok(TestServer {
+                    address,
+                    handle,
+                    thread: Some(thread),
+                }),
+                Ok(Err(RunServerError::BindError { source }))
+                | Ok(Err(RunServerError::RunError { source })) => Err(source),
+                Err(_) => Err(io::Error::other(
+                    "The test server stopped before it started",
+                )),
+            }
+        }
+
+        /// Where the server is listening.
+        pub fn address(&self) -> SocketAddr {
+            self.address
+        }
+
+        /// The URL for `path` (which should start with `/`) on this server.
+        pub fn url(&self, path: &str) -> String {
+            format!("http://{}{}", self.address, path)
+        }
+    }
+
+    impl Drop for TestServer {
+        fn drop(&mut self) {
+            executor::block_on(self.handle.stop(false));
+            if let Some(thread) = self.thread.take() {
+                let _ = thread.join();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_lib::*;
+
+    #[test]
+    fn routes_can_be_rendered() {
+        let site = TestSite::load(sample_path("hello-world")).unwrap();
+        let page = site.render("/hello").unwrap();
+        page.assert_status(200).assert_media_type("text/plain");
+        assert_eq!(page.text(), "hello world");
+
+        match site.render("/no-such-route") {
+            Err(TestSiteError::ContentNotFound { route }) => {
+                assert_eq!(route, crate::test_lib::route("/no-such-route"))
+            }
+            result => panic!("Render did not fail as expected: {:?}", result),
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "wrong media type")]
+    fn media_types_can_be_asserted() {
+        let site = TestSite::load(sample_path("hello-world")).unwrap();
+        site.render("/hello")
+            .unwrap()
+            .assert_media_type("text/html");
+    }
+
+    #[test]
+    #[cfg(feature = "server")]
+    fn sites_can_be_served() {
+        use std::io::{Read, Write};
+        use std::net::TcpStream;
+
+        let site = TestSite::load(sample_path("hello-world")).unwrap();
+        let server = site.serve().unwrap();
+        assert_eq!(
+            server.url("/hello"),
+            format!("http://{}/hello", server.address())
+        );
+
+        let mut stream = TcpStream::connect(server.address()).unwrap();
+        stream
+            .write_all(b"GET /hello HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK"), "{}", response);
+        assert!(response.contains("\r\n\r\n") && response.contains("hello world"));
+        drop(server);
+    }
+}
@@ -15,6 +15,7 @@ use std::str;
 use test_log::test;
 
 #[actix_rt::test]
+#[cfg_attr(not(feature = "executables"), ignore = "uses executables")]
 async fn samples_match_snapshots() {
     for content_directory in sample_content_directories() {
         let content_directory_root = &content_directory.root();
@@ -67,7 +68,7 @@ fn missing_subcommand_is_error() {
 
 #[test]
 fn invalid_subcommand_is_error() {
-    let mut command = operator_command(&["invalid-subcommand"]);
+    let mut command = operator_command(["invalid-subcommand"]);
     let output = command.output().expect("Failed to execute process");
 
     assert!(
@@ -77,12 +78,97 @@ fn invalid_subcommand_is_error() {
     );
 }
 
+#[test]
+fn errors_can_be_written_as_json() {
+    let mut command = operator_command([
+        "get",
+        &format!(
+            "--content-directory={}",
+            &sample_path("hello-world").to_str().unwrap()
+        ),
+        "--route=/no/such/route",
+        "--error-format=json",
+    ]);
+    let output = command.output().expect("Failed to execute process");
+
+    assert!(
+        !output.status.success(),
+        "Executing `{:?}` succeeded when it should have failed",
+        command
+    );
+    let stderr = String::from_utf8(output.stderr).expect("Stderr was not valid UTF-8");
+    let reports = stderr
+        .lines()
+        .map(serde_json::from_str::<serde_json::Value>)
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap_or_else(|error| panic!("Stderr was not JSON lines ({}): {}", error, stderr));
+    assert_eq!(
+        reports,
+        vec![serde_json::json!({
+            "kind": "not-found",
+            "route": "/no/such/route",
+            "file": null,
+            "message": "Content not found at route '/no/such/route'.",
+            "sources": [],
+        })],
+    );
+}
+
+#[test]
+#[cfg_attr(not(feature = "executables"), ignore = "uses executables")]
+fn failures_have_distinct_exit_codes() {
+    let hello_world = format!(
+        "--content-directory={}",
+        sample_path("hello-world").to_str().unwrap()
+    );
+    let error_handling = format!(
+        "--content-directory={}",
+        sample_path("error-handling").to_str().unwrap()
+    );
+    let invalid_templates = format!(
+        "--content-directory={}",
+        sample_path("invalid-templates").to_str().unwrap()
+    );
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let bound_address = format!("--bind-to={}", listener.local_addr().unwrap());
+
+    let cases: [(&[&str], i32); 6] = [
+        (&["get", &hello_world, "--route=/no/such/route"], 1),
+        (
+            &[
+                "get",
+                &hello_world,
+                "--route=/hello",
+                "--config=/no/such/config.toml",
+            ],
+            3,
+        ),
+        (&["check", &invalid_templates], 4),
+        (
+            &["get", &hello_world, "--route=/hello", "--accept=image/png"],
+            5,
+        ),
+        (&["get", &error_handling, "--route=/trigger-error"], 6),
+        (&["serve", &hello_world, &bound_address], 7),
+    ];
+    for (arguments, expected_exit_code) in cases {
+        let mut command = operator_command(arguments);
+        let output = command.output().expect("Failed to execute process");
+        assert_eq!(
+            output.status.code(),
+            Some(expected_exit_code),
+            "Executing `{:?}` did not exit with the expected code",
+            command
+        );
+    }
+}
+
 #[test]
 fn eval_subcommand_succeeds() {
     let input = "{{#if true}}hello world{{/if}}";
     let expected_output = "hello world";
 
-    let mut command = operator_command(&["eval", "--content-directory=/dev/null"]);
+    let mut command = operator_command(["eval", "--content-directory=/dev/null"]);
     command
         .stdin(Stdio::piped())
         .stdout(Stdio::piped())
@@ -109,11 +195,67 @@ fn eval_subcommand_succeeds() {
     );
 }
 
+#[test]
+fn eval_subcommand_can_read_template_files() {
+    let template_file = tempfile::NamedTempFile::new().expect("Failed to create temporary file");
+    std::fs::write(template_file.path(), "{{data.greeting}} world")
+        .expect("Failed to write template file");
+
+    let mut command = operator_command([
+        "eval",
+        "--content-directory=/dev/null",
+        &format!("--template-file={}", template_file.path().to_str().unwrap()),
+        "--data=greeting=hello",
+    ]);
+    let output = command.output().expect("Failed to execute process");
+
+    assert!(
+        output.status.success(),
+        "Executing `{:?}` failed when it should have succeeded",
+        command
+    );
+    assert_eq!(
+        String::from_utf8(output.stdout).expect("Output was not valid UTF-8"),
+        "hello world",
+        "Executing `{:?}` did not produce the expected output",
+        command
+    );
+}
+
+#[test]
+#[cfg_attr(not(feature = "executables"), ignore = "uses executables")]
+fn get_subcommand_can_simulate_request_headers() {
+    let mut command = operator_command([
+        "get",
+        &format!(
+            "--content-directory={}",
+            &sample_path("error-handling").to_str().unwrap()
+        ),
+        "--route=/error-code-and-request-info",
+        "--header=X-Example: a",
+        "--header=x-example:b",
+    ]);
+    let output = command.output().expect("Failed to execute process");
+
+    assert!(
+        output.status.success(),
+        "Executing `{:?}` failed when it should have succeeded",
+        command
+    );
+    let stdout = String::from_utf8(output.stdout).expect("Output was not valid UTF-8");
+    assert!(
+        stdout.ends_with("request headers:\n  x-example: a,b"),
+        "Executing `{:?}` did not produce the expected output: {}",
+        command,
+        stdout
+    );
+}
+
 #[test]
 fn get_subcommand_succeeds() {
     let expected_output = "hello world";
 
-    let mut command = operator_command(&[
+    let mut command = operator_command([
         "get",
         &format!(
             "--content-directory={}",
@@ -139,6 +281,67 @@ fn get_subcommand_succeeds() {
     );
 }
 
+#[test]
+fn get_subcommand_can_preload_static_files() {
+    let mut command = operator_command([
+        "get",
+        &format!(
+            "--content-directory={}",
+            &sample_path("hello-world").to_str().unwrap()
+        ),
+        "--route=/hello",
+        "--preload-max-bytes=64k",
+    ]);
+    let output = command.output().expect("Failed to execute process");
+
+    assert!(
+        output.status.success(),
+        "Executing `{:?}` failed when it should have succeeded: {}",
+        command,
+        String::from_utf8_lossy(&output.stderr),
+    );
+    assert_eq!(
+        String::from_utf8(output.stdout).expect("Output was not valid UTF-8"),
+        "hello world",
+    );
+}
+
+#[test]
+fn serve_subcommand_can_print_its_config() {
+    let mut command = operator_command([
+        "serve",
+        &format!(
+            "--content-directory={}",
+            &sample_path("hello-world").to_str().unwrap()
+        ),
+        "--bind-to=127.0.0.1:0",
+        "--base-url=https://example.com",
+        "--request-timeout=30",
+        "--print-config",
+    ]);
+    let output = command.output().expect("Failed to execute process");
+
+    assert!(
+        output.status.success(),
+        "Executing `{:?}` failed when it should have succeeded: {}",
+        command,
+        String::from_utf8_lossy(&output.stderr),
+    );
+    let printed_config = String::from_utf8(output.stdout).expect("Output was not valid UTF-8");
+    let printed_config = printed_config
+        .parse::<toml::Table>()
+        .expect("Printed config was not valid TOML");
+    assert_eq!(
+        printed_config["base-url"].as_str(),
+        Some("https://example.com")
+    );
+    assert_eq!(printed_config["timeouts"]["request"].as_integer(), Some(30));
+    assert_eq!(
+        printed_config["timeouts"]["client-request"].as_integer(),
+        Some(5)
+    );
+}
+
 #[actix_rt::test]
 async fn serve_subcommand_succeeds() {
     let content_directory = ContentDirectory::from_root(&sample_path("hello-world")).unwrap();
@@ -51,7 +51,7 @@ impl RunningServer {
     pub fn start(content_directory: &ContentDirectory) -> Result<Self, String> {
         let address = unused_addr();
 
-        let mut command = operator_command(&[
+        let mut command = operator_command([
             "serve",
             "--quiet",
             &format!(
@@ -149,7 +149,7 @@ pub async fn render_everything_for_snapshots(
                 optional_server.map(RunningServer::address),
                 content_directory,
                 &content_file.route,
-                &target_media_type.to_string(),
+                target_media_type.as_ref(),
             )
             .await;
 
@@ -178,6 +178,7 @@ pub async fn render_everything_for_snapshots(
 /// Render the desired content using a few different methods and verify that
 /// they all produce the same result.
 /// If `server_address` is `None`, no HTTP-based rendering is performed.
+#[allow(clippy::if_same_then_else)]
 async fn render_multiple_ways_for_snapshots(
     server_address: Option<&SocketAddr>,
     content_directory: &ContentDirectory,
@@ -299,7 +300,7 @@ fn render_via_get_command(
     route: &Route,
     accept: &str,
 ) -> Output {
-    let mut command = operator_command(&[
+    let mut command = operator_command([
         "get",
         &format!(
             "--content-directory={}",
@@ -321,7 +322,7 @@ async fn render_via_http_request(
     accept: &str,
 ) -> (StatusCode, Result<Bytes, PayloadError>) {
     let request = HttpClient::new()
-        .get(format!("http://{}{}", server_address, route))
+        .get(format!("http://{}{}", server_address, route.to_url_path()))
         .header("Accept", accept)
         .header("Is-Operator-Snapshot-Test", "true")
         .timeout(time::Duration::from_secs(15));